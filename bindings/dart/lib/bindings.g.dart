@@ -23,6 +23,31 @@ enum AccessMode {
 
 }
 
+enum ConnectionType {
+  direct,
+  holePunched,
+  relayed,
+  ;
+
+  static ConnectionType decode(int n) {
+    switch (n) {
+      case 0: return ConnectionType.direct;
+      case 1: return ConnectionType.holePunched;
+      case 2: return ConnectionType.relayed;
+      default: throw ArgumentError('invalid value: $n');
+    }
+  }
+
+  int encode() {
+    switch (this) {
+      case ConnectionType.direct: return 0;
+      case ConnectionType.holePunched: return 1;
+      case ConnectionType.relayed: return 2;
+    }
+  }
+
+}
+
 enum EntryType {
   file,
   directory,
@@ -17,6 +17,8 @@ class Client {
   var _nextMessageId = 0;
   final _responses = HashMap<int, Completer<Object?>>();
   final _subscriptions = HashMap<int, StreamSink<Object?>>();
+  final _invalidatedHandles = HashSet<int>();
+  Future<void>? _handshake;
 
   Client(this._handle, ReceivePort port) : _stream = port.cast<Uint8List>() {
     unawaited(_receive());
@@ -24,7 +26,25 @@ class Client {
 
   int get handle => _handle;
 
+  /// Negotiates the protocol version with the server. Requests invoked before the handshake
+  /// completes wait for it so that their responses are encoded using the negotiated version.
+  void handshake(int version) {
+    _handshake = _invoke<int>('handshake', {'version': version}).then(
+      (_) {},
+      onError: (e) => print('protocol handshake failed: $e'),
+    );
+  }
+
   Future<T> invoke<T>(String method, [Object? args]) async {
+    final handshake = _handshake;
+    if (handshake != null) {
+      await handshake;
+    }
+
+    return await _invoke<T>(method, args);
+  }
+
+  Future<T> _invoke<T>(String method, Object? args) async {
     final id = _nextMessageId++;
     final completer = Completer();
 
@@ -65,6 +85,10 @@ class Client {
 
   bool get isClosed => _handle == 0;
 
+  /// Whether the handle has been invalidated by the server (e.g., because its repository has been
+  /// force-closed).
+  bool isHandleInvalidated(int handle) => _invalidatedHandles.contains(handle);
+
   void _send(Uint8List data) {
     if (_handle == 0) {
       throw StateError('session has been closed');
@@ -114,6 +138,11 @@ class Client {
           _handleResponseFailure(responseCompleter, message['failure']);
         }
       } else if (isNotification) {
+        if (message['notification'] == 'handle_invalidated') {
+          _invalidatedHandles.add(id);
+          continue;
+        }
+
         final subscription = _subscriptions[id];
         if (subscription == null) {
           print('unsolicited notification');
@@ -144,13 +173,13 @@ class Client {
   }
 
   void _handleResponseFailure(Completer<Object?> completer, Object? payload) {
-    if (payload is! List) {
+    if (payload is! Map) {
       _handleInvalidResponse(completer);
       return;
     }
 
-    final code = payload[0];
-    final message = payload[1];
+    final code = payload['code'];
+    final message = payload['message'];
 
     if (code is! int || message is! String) {
       _handleInvalidResponse(completer);
@@ -28,6 +28,10 @@ part 'local_secret.dart';
 
 const bool debugTrace = false;
 
+/// Version of the client/server protocol this client supports. Since version 1 the server encodes
+/// the structs as maps keyed by the field names, which is what the decoders in this library expect.
+const int protocolVersion = 1;
+
 /// Entry point to the ouisync bindings. A session should be opened at the start of the application
 /// and closed at the end. There can be only one session at the time.
 class Session {
@@ -73,6 +77,10 @@ class Session {
 
     final client = Client(handle, recvPort);
 
+    // Negotiate the protocol version. The client holds back any other requests until the
+    // handshake completes so that all the responses use the negotiated encoding.
+    client.handshake(protocolVersion);
+
     return Session._(client);
   }
 
@@ -147,8 +155,8 @@ class Session {
       _client.invoke<String?>('network_nat_behavior');
 
   Future<TrafficStats> get trafficStats => _client
-      .invoke<List<Object?>>('network_traffic_stats')
-      .then((list) => TrafficStats.decode(list));
+      .invoke<Map<Object?, Object?>>('network_traffic_stats')
+      .then((map) => TrafficStats.decode(map));
 
   /// Gets a stream that yields lists of known peers.
   Stream<List<PeerInfo>> get onPeersChange async* {
@@ -244,20 +252,22 @@ class PeerInfo {
   final PeerSource source;
   final PeerStateKind state;
   final String? runtimeId;
+  final ConnectionType? connectionType;
 
   PeerInfo({
     required this.addr,
     required this.source,
     required this.state,
     this.runtimeId,
+    this.connectionType,
   });
 
   static PeerInfo decode(Object? raw) {
-    final list = raw as List<Object?>;
+    final map = raw as Map<Object?, Object?>;
 
-    final addr = list[0] as String;
-    final source = PeerSource.decode(list[1] as int);
-    final rawState = list[2];
+    final addr = map['addr'] as String;
+    final source = PeerSource.decode(map['source'] as int);
+    final rawState = map['state'];
 
     PeerStateKind state;
     String? runtimeId;
@@ -271,11 +281,17 @@ class PeerInfo {
       throw Exception('invalid peer info state');
     }
 
+    final rawConnectionType = map['connection_type'] as int?;
+    final connectionType = rawConnectionType != null
+        ? ConnectionType.decode(rawConnectionType)
+        : null;
+
     return PeerInfo(
       addr: addr,
       source: source,
       state: state,
       runtimeId: runtimeId,
+      connectionType: connectionType,
     );
   }
 
@@ -293,9 +309,9 @@ class TrafficStats {
 
   const TrafficStats({required this.send, required this.recv});
 
-  static TrafficStats decode(List<Object?> raw) {
-    final send = raw[0] as int;
-    final recv = raw[1] as int;
+  static TrafficStats decode(Map<Object?, Object?> raw) {
+    final send = raw['send'] as int;
+    final recv = raw['recv'] as int;
 
     return TrafficStats(send: send, recv: recv);
   }
@@ -312,7 +328,10 @@ class Repository {
   final Subscription _subscription;
 
   Repository._(this._client, this._handle, this._store)
-      : _subscription = Subscription(_client, "repository", _handle);
+      : _subscription = Subscription(_client, "repository", {
+          'repository': _handle,
+          'since': null,
+        });
 
   /// Creates a new repository and set access to it based on the following table:
   ///
@@ -377,6 +396,14 @@ class Repository {
     await _client.invoke('repository_close', _handle);
   }
 
+  /// Closes the repository immediately, without waiting for the ongoing operations. The open files
+  /// of this repository become invalid (see [File.isValid]) without being flushed. Use when the
+  /// storage is about to disappear (e.g., SD card ejection).
+  Future<void> forceClose() async {
+    await _subscription.close();
+    await _client.invoke('repository_force_close', _handle);
+  }
+
   /// Checks whether syncing with other replicas is enabled.
   Future<bool> get isSyncEnabled =>
       _client.invoke<bool>('repository_is_sync_enabled', _handle);
@@ -512,7 +539,7 @@ class Repository {
   }
 
   Future<Progress> get syncProgress => _client
-      .invoke<List<Object?>>('repository_sync_progress', _handle)
+      .invoke<Map<Object?, Object?>>('repository_sync_progress', _handle)
       .then(Progress.decode);
 
   StateMonitor? get stateMonitor {
@@ -648,9 +675,9 @@ class Progress {
 
   Progress(this.value, this.total);
 
-  static Progress decode(List<Object?> raw) {
-    final value = raw[0] as int;
-    final total = raw[1] as int;
+  static Progress decode(Map<Object?, Object?> raw) {
+    final value = raw['value'] as int;
+    final total = raw['total'] as int;
 
     return Progress(value, total);
   }
@@ -674,9 +701,9 @@ class DirEntry {
   DirEntry(this.name, this.entryType);
 
   static DirEntry decode(Object? raw) {
-    final map = raw as List<Object?>;
-    final name = map[0] as String;
-    final type = map[1] as int;
+    final map = raw as Map<Object?, Object?>;
+    final name = map['name'] as String;
+    final type = map['entry_type'] as int;
 
     return DirEntry(name, EntryType.decode(type));
   }
@@ -761,6 +788,10 @@ class File {
 
   File._(this._client, this._handle);
 
+  /// Whether this file is still valid. A file becomes invalid when its repository is force-closed
+  /// (see [Repository.forceClose]). Any operation on an invalid file throws.
+  bool get isValid => !_client.isHandleInvalidated(_handle);
+
   static const defaultChunkSize = 1024;
 
   /// Opens an existing file from [repo] at [path].
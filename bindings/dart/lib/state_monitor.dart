@@ -57,10 +57,10 @@ class StateMonitorNode {
 
   static StateMonitorNode _decode(
     List<MonitorId> path,
-    List<Object?> raw,
+    Map<Object?, Object?> raw,
   ) {
-    final values = _decodeValues(raw[0]);
-    final children = _decodeChildren(raw[1]);
+    final values = _decodeValues(raw['values']);
+    final children = _decodeChildren(raw['children']);
 
     return StateMonitorNode(
       path,
@@ -110,10 +110,10 @@ class StateMonitor {
 
   Future<StateMonitorNode?> load() async {
     try {
-      final list = await _client.invoke(
+      final map = await _client.invoke(
               "state_monitor_get", _path.map((id) => id.toString()))
-          as List<Object?>;
-      return StateMonitorNode._decode(_path, list);
+          as Map<Object?, Object?>;
+      return StateMonitorNode._decode(_path, map);
     } catch (e) {
       print('failed to load state monitor node at $_path: $e');
       return null;
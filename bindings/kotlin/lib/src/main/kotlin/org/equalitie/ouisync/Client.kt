@@ -61,6 +61,7 @@ internal class Client {
     private var nextMessageId: Long = 0
     private val responses: HashMap<Long, CompletableDeferred<Any?>> = HashMap()
     private val subscriptions: HashMap<Long, SendChannel<Any?>> = HashMap()
+    private val invalidatedHandles: HashSet<Long> = HashSet()
 
     suspend fun invoke(request: Request): Any? {
         val id = nextMessageId++
@@ -138,9 +139,17 @@ internal class Client {
         }
     }
 
+    suspend fun isHandleInvalidated(handle: Long): Boolean = mutex.withLock {
+        invalidatedHandles.contains(handle)
+    }
+
     private suspend fun handleNotification(id: Long, content: Any?) {
         mutex.withLock {
-            subscriptions.get(id)?.send(content)
+            if (content is HandleInvalidated) {
+                invalidatedHandles.add(id)
+            } else {
+                subscriptions.get(id)?.send(content)
+            }
         }
     }
 
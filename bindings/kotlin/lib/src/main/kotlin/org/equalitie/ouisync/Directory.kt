@@ -11,14 +11,23 @@ import org.msgpack.core.MessageUnpacker
 data class DirectoryEntry(val name: String, val entryType: EntryType) {
     companion object {
         internal fun unpack(unpacker: MessageUnpacker): DirectoryEntry {
-            if (unpacker.unpackArrayHeader() != 2) {
-                throw InvalidResponse()
-            }
+            val count = unpacker.unpackMapHeader()
+
+            var name: String? = null
+            var entryType: EntryType? = null
 
-            val name = unpacker.unpackString()
-            val entryType = EntryType.decode(unpacker.unpackByte())
+            for (i in 0 until count) {
+                when (unpacker.unpackString()) {
+                    "name" -> name = unpacker.unpackString()
+                    "entry_type" -> entryType = EntryType.decode(unpacker.unpackByte())
+                    else -> unpacker.skipValue()
+                }
+            }
 
-            return DirectoryEntry(name, entryType)
+            return DirectoryEntry(
+                name ?: throw InvalidResponse(),
+                entryType ?: throw InvalidResponse(),
+            )
         }
     }
 }
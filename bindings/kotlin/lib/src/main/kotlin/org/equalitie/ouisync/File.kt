@@ -34,6 +34,12 @@ class File private constructor(private val handle: Long, private val client: Cli
      */
     suspend fun close() = client.invoke(FileClose(handle))
 
+    /**
+     * Returns whether this file is still valid. A file becomes invalid when its repository is
+     * force-closed (see [Repository.forceClose]). Any operation on an invalid file fails.
+     */
+    suspend fun isValid() = !client.isHandleInvalidated(handle)
+
     /**
      * Flushes any pending writes to the file.
      */
@@ -101,6 +101,13 @@ class Repository private constructor(internal val handle: Long, internal val cli
      */
     suspend fun close() = client.invoke(RepositoryClose(handle))
 
+    /**
+     * Closes the repository immediately, without waiting for the ongoing operations. The open files
+     * of this repository are invalidated (see [File.isValid]) without being flushed. Use when the
+     * storage is about to disappear (e.g., SD card ejection).
+     */
+    suspend fun forceClose() = client.invoke(RepositoryForceClose(handle))
+
     /**
      * Subscribe to repository events.
      *
@@ -108,7 +115,7 @@ class Repository private constructor(internal val handle: Long, internal val cli
      * written to, removed, moved, ...).
      */
     suspend fun subscribe(): EventReceiver<Unit> =
-        client.subscribe(RepositorySubscribe(handle))
+        client.subscribe(RepositorySubscribe(handle, null))
 
     /**
      * Returns whether syncing with other replicas is enabled.
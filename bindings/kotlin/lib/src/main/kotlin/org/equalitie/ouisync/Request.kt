@@ -50,6 +50,12 @@ internal abstract class ValueRequest<T : Any>(val value: T) : Request() {
     }
 }
 
+internal class Handshake(val version: Long) : Request() {
+    override fun packContent(packer: MessagePacker) {
+        packer.packMap(mapOf("version" to version))
+    }
+}
+
 internal class RepositoryCreate(
     val path: String,
     val readSecret: SetLocalSecret?,
@@ -78,10 +84,21 @@ internal class RepositoryClose : ValueRequest<Long> {
     constructor(value: Long) : super(value)
 }
 
-internal class RepositorySubscribe : ValueRequest<Long> {
+internal class RepositoryForceClose : ValueRequest<Long> {
     constructor(value: Long) : super(value)
 }
 
+internal class RepositorySubscribe(val repository: Long, val since: Long?) : Request() {
+    override fun packContent(packer: MessagePacker) {
+        packer.packMap(
+            mapOf(
+                "repository" to repository,
+                "since" to since,
+            ),
+        )
+    }
+}
+
 internal class RepositoryIsSyncEnabled : ValueRequest<Long> {
     constructor(value: Long) : super(value)
 }
@@ -10,48 +10,67 @@ import org.msgpack.value.ValueType
  * @property source    how was the peer discovered.
  * @property state     state of the peer connection.
  * @property runtimeId [runtime id][Session.thisRuntimeId] of the peer if [active][PeerStateKind.ACTIVE], otherwise null.
+ * @property connectionType how was the connection established or null if not connected yet.
  */
 data class PeerInfo(
     val addr: String,
     val source: PeerSource,
     val state: PeerStateKind,
     val runtimeId: String?,
+    val connectionType: ConnectionType?,
 ) {
     companion object {
         fun unpack(unpacker: MessageUnpacker): PeerInfo {
-            val count = unpacker.unpackArrayHeader()
-
-            if (count < 3) {
-                throw InvalidResponse()
-            }
+            val count = unpacker.unpackMapHeader()
 
-            val addr = unpacker.unpackString()
-            val source = PeerSource.decode(unpacker.unpackByte())
-
-            var state: PeerStateKind
+            var addr: String? = null
+            var source: PeerSource? = null
+            var state: PeerStateKind? = null
             var runtimeId: String? = null
-
-            when (unpacker.getNextFormat().getValueType()) {
-                ValueType.INTEGER -> {
-                    state = PeerStateKind.decode(unpacker.unpackByte())
-                }
-                ValueType.ARRAY -> {
-                    if (unpacker.unpackArrayHeader() < 2) {
-                        throw InvalidResponse()
+            var connectionType: ConnectionType? = null
+
+            for (i in 0 until count) {
+                when (unpacker.unpackString()) {
+                    "addr" -> addr = unpacker.unpackString()
+                    "source" -> source = PeerSource.decode(unpacker.unpackByte())
+                    "state" -> when (unpacker.getNextFormat().getValueType()) {
+                        ValueType.INTEGER -> {
+                            state = PeerStateKind.decode(unpacker.unpackByte())
+                        }
+                        ValueType.ARRAY -> {
+                            if (unpacker.unpackArrayHeader() < 2) {
+                                throw InvalidResponse()
+                            }
+
+                            state = PeerStateKind.decode(unpacker.unpackByte())
+
+                            val length = unpacker.unpackBinaryHeader()
+                            val runtimeIdBytes = unpacker.readPayload(length)
+
+                            @OptIn(kotlin.ExperimentalStdlibApi::class)
+                            runtimeId = runtimeIdBytes.toHexString()
+                        }
+                        else -> throw InvalidResponse()
                     }
-
-                    state = PeerStateKind.decode(unpacker.unpackByte())
-
-                    val length = unpacker.unpackBinaryHeader()
-                    val runtimeIdBytes = unpacker.readPayload(length)
-
-                    @OptIn(kotlin.ExperimentalStdlibApi::class)
-                    runtimeId = runtimeIdBytes.toHexString()
+                    "connection_type" -> {
+                        if (unpacker.tryUnpackNil()) {
+                            connectionType = null
+                        } else {
+                            connectionType = ConnectionType.decode(unpacker.unpackByte())
+                        }
+                    }
+                    // Skip any fields this client doesn't know about.
+                    else -> unpacker.skipValue()
                 }
-                else -> throw InvalidResponse()
             }
 
-            return PeerInfo(addr, source, state, runtimeId)
+            return PeerInfo(
+                addr ?: throw InvalidResponse(),
+                source ?: throw InvalidResponse(),
+                state ?: throw InvalidResponse(),
+                runtimeId,
+                connectionType,
+            )
         }
     }
 }
@@ -59,14 +78,20 @@ data class PeerInfo(
 data class Progress(val value: Long, val total: Long) {
     companion object {
         fun unpack(unpacker: MessageUnpacker): Progress {
-            if (unpacker.unpackArrayHeader() != 2) {
-                throw InvalidResponse()
-            }
+            val count = unpacker.unpackMapHeader()
 
-            val value = unpacker.unpackLong()
-            val total = unpacker.unpackLong()
+            var value: Long? = null
+            var total: Long? = null
 
-            return Progress(value, total)
+            for (i in 0 until count) {
+                when (unpacker.unpackString()) {
+                    "value" -> value = unpacker.unpackLong()
+                    "total" -> total = unpacker.unpackLong()
+                    else -> unpacker.skipValue()
+                }
+            }
+
+            return Progress(value ?: throw InvalidResponse(), total ?: throw InvalidResponse())
         }
     }
 }
@@ -127,6 +152,11 @@ internal class Success(val value: Any?) : Response {
                     when (name) {
                         "directory" -> Directory.unpack(unpacker)
                         "peer_infos" -> unpackPeerInfos(unpacker)
+                        else -> throw InvalidResponse()
+                    }
+                }
+                ValueType.MAP -> {
+                    when (name) {
                         "progress" -> Progress.unpack(unpacker)
                         else -> throw InvalidResponse()
                     }
@@ -160,17 +190,27 @@ internal class Failure(val error: Error) : Response {
         private val INVALID_ERROR = Error(ErrorCode.MALFORMED_MESSAGE, "invalid error response")
 
         fun unpack(unpacker: MessageUnpacker): Response {
-            if (unpacker.getNextFormat().getValueType() != ValueType.ARRAY) {
-                return Failure(INVALID_ERROR)
-            }
-
-            if (unpacker.unpackArrayHeader() != 2) {
+            if (unpacker.getNextFormat().getValueType() != ValueType.MAP) {
                 return Failure(INVALID_ERROR)
             }
 
             try {
-                val code = ErrorCode.decode(unpacker.unpackInt().toShort())
-                val message = unpacker.unpackString()
+                val count = unpacker.unpackMapHeader()
+
+                var code: ErrorCode? = null
+                var message: String? = null
+
+                for (i in 0 until count) {
+                    when (unpacker.unpackString()) {
+                        "code" -> code = ErrorCode.decode(unpacker.unpackInt().toShort())
+                        "message" -> message = unpacker.unpackString()
+                        else -> unpacker.skipValue()
+                    }
+                }
+
+                if (code == null || message == null) {
+                    return Failure(INVALID_ERROR)
+                }
 
                 return Failure(Error(code, message))
             } catch (e: Exception) {
@@ -204,12 +244,19 @@ internal class Notification(val content: Any) : ServerMessage {
         private fun unpackValue(name: String, unpacker: MessageUnpacker): Any {
             when (name) {
                 "network" -> return NetworkEvent.decode(unpacker.unpackByte())
+                "handle_invalidated" -> return HandleInvalidated
                 else -> throw InvalidNotification()
             }
         }
     }
 }
 
+/**
+ * The handle identified by the id of the notification is no longer valid (e.g., because its
+ * repository has been force-closed).
+ */
+internal object HandleInvalidated
+
 internal open class InvalidMessage : Exception {
     constructor() : super("invalid message")
     constructor(message: String) : super(message)
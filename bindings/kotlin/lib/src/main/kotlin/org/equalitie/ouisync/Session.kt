@@ -30,6 +30,11 @@ class Session private constructor(
     companion object {
         internal val bindings = Bindings.INSTANCE
 
+        // Version of the client/server protocol this client supports. Since version 1 the server
+        // encodes the structs as maps keyed by the field names, which is what `ServerMessage`
+        // decodes.
+        internal const val PROTOCOL_VERSION: Long = 1
+
         /**
          * Creates a new Ouisync session.
          *
@@ -73,7 +78,14 @@ class Session private constructor(
                 // Keep a reference to the callback in this session to ensure it doesn't get
                 // garbage collected prematurely. More info:
                 // https://github.com/java-native-access/jna/blob/master/www/CallbacksAndClosures.md
-                return Session(result.handle, client, callback)
+                val session = Session(result.handle, client, callback)
+
+                // Negotiate the protocol version before sending any other request.
+                runBlocking {
+                    client.invoke(Handshake(PROTOCOL_VERSION))
+                }
+
+                return session
             } else {
                 val message = result.error_message?.getString(0) ?: "unknown error"
                 bindings.free_string(result.error_message)
@@ -1,25 +1,42 @@
 use crate::dht_contacts;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
     borrow::Borrow,
+    collections::HashMap,
     fmt,
     io::{self, ErrorKind},
     marker::PhantomData,
     path::{Path, PathBuf},
     str,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, SystemTime},
 };
 use thiserror::Error;
 use tokio::{
     fs::{self, OpenOptions},
     io::AsyncWriteExt,
+    sync::{broadcast, Notify},
+    task, time,
 };
 use tracing::instrument;
 
+const EXTENSION: &str = "conf";
+
+// How often the config files are checked for modifications when the OS file change notifications
+// are not available (not supported by the platform or the filesystem, or the watch limit has been
+// reached). Polling the modification times is cheap as the config dir has only a handful of small
+// files.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 #[derive(Clone)]
 pub struct ConfigStore {
     dir: Arc<Path>,
     dht_contacts_store: Arc<dht_contacts::Store>,
+    watcher: Arc<Watcher>,
 }
 
 impl ConfigStore {
@@ -30,6 +47,7 @@ impl ConfigStore {
         Self {
             dir,
             dht_contacts_store,
+            watcher: Arc::new(Watcher::new()),
         }
     }
 
@@ -48,6 +66,181 @@ impl ConfigStore {
     pub fn dht_contacts_store(&self) -> Arc<dht_contacts::Store> {
         self.dht_contacts_store.clone()
     }
+
+    /// Subscribe to the modifications of the config entries. Receives the name of every entry
+    /// that was created, modified or removed, no matter whether by this process, another one
+    /// sharing the same config dir (e.g., the app and the CLI) or by editing the file by hand. The
+    /// modifications are detected using the OS file change notifications. If those are not
+    /// available, the config dir is polled instead and the modifications are detected with a delay
+    /// of a few seconds.
+    ///
+    /// The watching starts with the first subscription and stops when all the clones of this
+    /// store are dropped, which closes the returned receiver.
+    pub async fn subscribe(&self) -> broadcast::Receiver<String> {
+        let rx = self.watcher.tx.subscribe();
+
+        if !self.watcher.started.swap(true, Ordering::Relaxed) {
+            // The dir must exist to be watched.
+            if let Err(error) = fs::create_dir_all(&self.dir).await {
+                tracing::warn!(dir = ?self.dir, ?error, "Failed to create config dir");
+            }
+
+            let entries = scan(&self.dir).await;
+            task::spawn(watch(
+                self.dir.clone(),
+                entries,
+                Arc::downgrade(&self.watcher),
+                self.watcher.wake.clone(),
+            ));
+        }
+
+        rx
+    }
+}
+
+struct Watcher {
+    tx: broadcast::Sender<String>,
+    started: AtomicBool,
+    // Wakes up the watching task when the config dir changes and when this watcher is dropped.
+    wake: Arc<Notify>,
+}
+
+impl Watcher {
+    fn new() -> Self {
+        Self {
+            tx: broadcast::channel(32).0,
+            started: AtomicBool::new(false),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // Let the watching task know it should stop.
+        self.wake.notify_one();
+    }
+}
+
+async fn watch(
+    dir: Arc<Path>,
+    mut old: HashMap<String, (SystemTime, u64)>,
+    watcher: Weak<Watcher>,
+    wake: Arc<Notify>,
+) {
+    // Needs to be kept alive for the notifications to be delivered.
+    let os_watcher = start_os_watcher(&dir, wake.clone());
+
+    if os_watcher.is_some() {
+        // Rescan right away to catch the modifications made before the watcher was started.
+        wake.notify_one();
+    } else {
+        tracing::warn!(
+            ?dir,
+            "Config dir notifications not available, polling instead"
+        );
+    }
+
+    loop {
+        if os_watcher.is_some() {
+            wake.notified().await;
+        } else {
+            time::timeout(POLL_INTERVAL, wake.notified()).await.ok();
+        }
+
+        let Some(watcher) = watcher.upgrade() else {
+            break;
+        };
+
+        let new = scan(&dir).await;
+
+        let modified = new
+            .iter()
+            .filter(|(name, stamp)| old.get(*name) != Some(*stamp))
+            .map(|(name, _)| name);
+        let removed = old.keys().filter(|name| !new.contains_key(*name));
+
+        for name in modified.chain(removed) {
+            tracing::debug!(name, "Config entry changed");
+            watcher.tx.send(name.clone()).ok();
+        }
+
+        old = new;
+    }
+}
+
+// Starts watching `dir` using the OS file change notifications. `wake` is notified on every change
+// of a config entry file. Returns `None` if the notifications are not available.
+fn start_os_watcher(dir: &Path, wake: Arc<Notify>) -> Option<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let changed = match event {
+            Ok(event) => event.paths.iter().any(|path| is_entry_path(path)),
+            // Some notifications might have been lost (e.g., the event queue overflowed). Rescan
+            // to be sure.
+            Err(error) => {
+                tracing::debug!(?error, "Config dir watcher error");
+                true
+            }
+        };
+
+        if changed {
+            wake.notify_one();
+        }
+    })
+    .map_err(|error| tracing::debug!(?error, "Failed to create config dir watcher"))
+    .ok()?;
+
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|error| tracing::debug!(?dir, ?error, "Failed to watch config dir"))
+        .ok()?;
+
+    Some(watcher)
+}
+
+fn is_entry_path(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some(EXTENSION)
+}
+
+// Modification time and length of every config entry file in `dir`. The length is included
+// because the modification time might have too coarse resolution to tell apart two quick writes.
+async fn scan(dir: &Path) -> HashMap<String, (SystemTime, u64)> {
+    let mut entries = HashMap::new();
+
+    let mut read_dir = match fs::read_dir(dir).await {
+        Ok(read_dir) => read_dir,
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                tracing::warn!(?dir, ?error, "Failed to read config dir");
+            }
+
+            return entries;
+        }
+    };
+
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+        let path = entry.path();
+
+        if !is_entry_path(&path) {
+            continue;
+        }
+
+        let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+
+        let Ok(modified) = metadata.modified() else {
+            continue;
+        };
+
+        entries.insert(name.to_owned(), (modified, metadata.len()));
+    }
+
+    entries
 }
 
 #[derive(Clone, Copy)]
@@ -65,6 +258,10 @@ impl<T> ConfigKey<T> {
             _type: PhantomData,
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
 }
 
 #[derive(Clone)]
@@ -126,7 +323,7 @@ impl<T> ConfigEntry<T> {
     }
 
     pub(crate) fn path(&self) -> PathBuf {
-        self.store.dir.join(self.key.name).with_extension("conf")
+        self.store.dir.join(self.key.name).with_extension(EXTENSION)
     }
 }
 
@@ -186,6 +383,7 @@ mod tests {
     use std::net::{Ipv4Addr, Ipv6Addr};
 
     use super::*;
+    use assert_matches::assert_matches;
     use ouisync_lib::PeerAddr;
     use tempfile::TempDir;
 
@@ -256,6 +454,28 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn subscribe() {
+        let dir = TempDir::new().unwrap();
+        let config = ConfigStore::new(dir.path());
+        let mut rx = config.subscribe().await;
+
+        let key: ConfigKey<u16> = ConfigKey::new("u16", "comment");
+        // Another store on the same dir, as in another process.
+        ConfigStore::new(dir.path())
+            .entry(key)
+            .set(&1)
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "u16");
+
+        config.entry(key).remove().await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "u16");
+
+        drop(config);
+        assert_matches!(rx.recv().await, Err(broadcast::error::RecvError::Closed));
+    }
+
     #[tokio::test]
     async fn vec_of_peer_addr_entry() {
         let dir = TempDir::new().unwrap();
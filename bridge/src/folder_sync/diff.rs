@@ -0,0 +1,138 @@
+//! Three-way diff between the last synced state of an entry and its current local and repository
+//! versions.
+
+use ouisync_lib::crypto::Hash;
+
+/// What needs to be done to bring a single entry in sync.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(super) enum Action {
+    /// Both sides are in sync and match the last synced state. Nothing to do.
+    None,
+    /// Both sides are in sync but differ from the last synced state (e.g., the same change was
+    /// made on both sides independently). Only the state needs to be updated.
+    Record,
+    /// Both sides have been removed. Only the state needs to be updated.
+    Forget,
+    /// Copy the local version into the repository.
+    Upload,
+    /// Copy the repository version into the local folder.
+    Download,
+    /// Remove the local version.
+    RemoveLocal,
+    /// Remove the repository version.
+    RemoveRemote,
+    /// Both sides have been modified concurrently.
+    Conflict(ConflictKind),
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(super) enum ConflictKind {
+    /// Both sides have different content.
+    Content,
+    /// The local version has been modified while the repository version has been removed.
+    LocalModifiedRemoteRemoved,
+    /// The local version has been removed while the repository version has been modified.
+    LocalRemovedRemoteModified,
+}
+
+/// Decides what to do with an entry given the hash of its last synced version (`base`) and the
+/// hashes of its current `local` and `remote` versions (`None` means the entry doesn't exist).
+pub(super) fn reconcile(
+    base: Option<&Hash>,
+    local: Option<&Hash>,
+    remote: Option<&Hash>,
+) -> Action {
+    match (base, local, remote) {
+        (Some(base), Some(local), Some(remote)) if local == remote => {
+            if local == base {
+                Action::None
+            } else {
+                Action::Record
+            }
+        }
+        (None, Some(local), Some(remote)) if local == remote => Action::Record,
+        (_, None, None) => Action::Forget,
+        (None, Some(_), None) => Action::Upload,
+        (None, None, Some(_)) => Action::Download,
+        (None, Some(_), Some(_)) => Action::Conflict(ConflictKind::Content),
+        (Some(base), Some(local), None) => {
+            if local == base {
+                Action::RemoveLocal
+            } else {
+                Action::Conflict(ConflictKind::LocalModifiedRemoteRemoved)
+            }
+        }
+        (Some(base), None, Some(remote)) => {
+            if remote == base {
+                Action::RemoveRemote
+            } else {
+                Action::Conflict(ConflictKind::LocalRemovedRemoteModified)
+            }
+        }
+        (Some(base), Some(local), Some(remote)) => match (local == base, remote == base) {
+            (true, _) => Action::Download,
+            (_, true) => Action::Upload,
+            (false, false) => Action::Conflict(ConflictKind::Content),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ouisync_lib::crypto::Hashable;
+
+    #[test]
+    fn unchanged() {
+        let a = b"a".hash();
+
+        assert_eq!(reconcile(Some(&a), Some(&a), Some(&a)), Action::None);
+    }
+
+    #[test]
+    fn created() {
+        let a = b"a".hash();
+        let b = b"b".hash();
+
+        assert_eq!(reconcile(None, Some(&a), None), Action::Upload);
+        assert_eq!(reconcile(None, None, Some(&a)), Action::Download);
+        assert_eq!(reconcile(None, Some(&a), Some(&a)), Action::Record);
+        assert_eq!(
+            reconcile(None, Some(&a), Some(&b)),
+            Action::Conflict(ConflictKind::Content)
+        );
+    }
+
+    #[test]
+    fn modified() {
+        let a = b"a".hash();
+        let b = b"b".hash();
+        let c = b"c".hash();
+
+        assert_eq!(reconcile(Some(&a), Some(&b), Some(&a)), Action::Upload);
+        assert_eq!(reconcile(Some(&a), Some(&a), Some(&b)), Action::Download);
+        assert_eq!(reconcile(Some(&a), Some(&b), Some(&b)), Action::Record);
+        assert_eq!(
+            reconcile(Some(&a), Some(&b), Some(&c)),
+            Action::Conflict(ConflictKind::Content)
+        );
+    }
+
+    #[test]
+    fn removed() {
+        let a = b"a".hash();
+        let b = b"b".hash();
+
+        assert_eq!(reconcile(Some(&a), None, Some(&a)), Action::RemoveRemote);
+        assert_eq!(reconcile(Some(&a), Some(&a), None), Action::RemoveLocal);
+        assert_eq!(reconcile(Some(&a), None, None), Action::Forget);
+        assert_eq!(
+            reconcile(Some(&a), Some(&b), None),
+            Action::Conflict(ConflictKind::LocalModifiedRemoteRemoved)
+        );
+        assert_eq!(
+            reconcile(Some(&a), None, Some(&b)),
+            Action::Conflict(ConflictKind::LocalRemovedRemoteModified)
+        );
+    }
+}
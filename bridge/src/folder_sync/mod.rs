@@ -0,0 +1,823 @@
+//! Two-way synchronization between a regular local folder and a repository.
+//!
+//! This is an alternative to mounting the repository using a kernel filesystem driver. The local
+//! folder and the repository are periodically scanned and reconciled using a three-way diff
+//! against the state recorded after the last successful sync.
+//!
+//! NOTE: Only files are synced. Empty directories are not propagated in either direction.
+
+mod diff;
+mod state;
+
+use self::{
+    diff::{Action, ConflictKind},
+    state::{EntryState, SyncState},
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use ouisync_lib::{
+    crypto::{Hash, Hashable},
+    EntryType, JointEntryRef, Repository, BLOCK_SIZE,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use thiserror::Error;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::broadcast::error::RecvError,
+    time::{self, Duration},
+};
+
+/// How often to rescan the local folder for changes.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+/// How long to wait after a repository change before starting the next sync round.
+const SETTLE_DELAY: Duration = Duration::from_secs(1);
+
+/// How to resolve entries that were modified concurrently both locally and in the repository.
+///
+/// NOTE: This applies only to content conflicts. If one side was modified and the other removed,
+/// the modified version always wins.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Overwrite the repository version with the local one.
+    PreferLocal,
+    /// Overwrite the local version with the repository one.
+    PreferRepository,
+    /// Keep both versions. The local version is renamed and synced as a separate file.
+    KeepBoth,
+}
+
+/// How a particular conflict was resolved.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    KeptLocal,
+    KeptRepository,
+    KeptBoth,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderSyncEvent {
+    /// Number of processed entries out of the total number of entries in the current sync round.
+    Progress { value: u64, total: u64 },
+    /// A conflict has been detected and resolved.
+    Conflict {
+        path: String,
+        resolution: ConflictResolution,
+    },
+    /// Sync round finished.
+    Completed(FolderSyncSummary),
+    /// Sync round failed. It will be retried in the next round.
+    Failed(String),
+}
+
+/// Summary of a single sync round.
+#[derive(Clone, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FolderSyncSummary {
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub removed_local: u64,
+    pub removed_remote: u64,
+    pub conflicts: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum FolderSyncError {
+    #[error("repository error")]
+    Repository(#[from] ouisync_lib::Error),
+    #[error("local filesystem error")]
+    Io(#[from] io::Error),
+}
+
+pub struct FolderSync<'a> {
+    repository: &'a Repository,
+    local_root: PathBuf,
+    state_path: PathBuf,
+    policy: ConflictPolicy,
+}
+
+impl<'a> FolderSync<'a> {
+    /// Creates the sync engine between `repository` and the local folder at `local_root`. The
+    /// sync state is persisted in a file inside `state_dir` whose name is derived from the
+    /// repository id and the local folder path, so multiple folders can be synced with multiple
+    /// repositories without interfering with each other.
+    pub fn new(
+        repository: &'a Repository,
+        local_root: PathBuf,
+        state_dir: &Path,
+        policy: ConflictPolicy,
+    ) -> Self {
+        let key = repository
+            .secrets()
+            .id()
+            .salted_hash(local_root.as_os_str().as_encoded_bytes());
+        let state_path = state_dir.join(format!("{key:x}")).with_extension("json");
+
+        Self {
+            repository,
+            local_root,
+            state_path,
+            policy,
+        }
+    }
+
+    /// Keeps the folder and the repository in sync until the returned future is dropped. A new
+    /// sync round is started whenever the repository changes or after `RESCAN_INTERVAL` elapses
+    /// (to pick up local changes).
+    pub async fn run<F>(&self, mut on_event: impl FnMut(FolderSyncEvent) -> F)
+    where
+        F: Future<Output = ()>,
+    {
+        let mut rx = self.repository.subscribe();
+
+        loop {
+            if let Err(error) = self.sync(&mut on_event).await {
+                tracing::error!(?error, local_root = ?self.local_root, "Folder sync failed");
+                on_event(FolderSyncEvent::Failed(error.to_string())).await;
+            }
+
+            match time::timeout(RESCAN_INTERVAL, rx.recv()).await {
+                Ok(Ok(_) | Err(RecvError::Lagged(_))) | Err(_) => (),
+                Ok(Err(RecvError::Closed)) => break,
+            }
+
+            // Give bursts of repository changes a chance to settle before the next round.
+            time::sleep(SETTLE_DELAY).await;
+            rx = rx.resubscribe();
+        }
+    }
+
+    /// Performs a single sync round, reporting progress and conflicts through `on_event`. The
+    /// returned future is awaited before the round continues, so a slow consumer of the events
+    /// slows the sync down instead of letting the events pile up.
+    pub async fn sync<F>(
+        &self,
+        mut on_event: impl FnMut(FolderSyncEvent) -> F,
+    ) -> Result<FolderSyncSummary, FolderSyncError>
+    where
+        F: Future<Output = ()>,
+    {
+        fs::create_dir_all(&self.local_root).await?;
+
+        let mut state = SyncState::load(&self.state_path).await?;
+        let local = scan_local(&self.local_root).await?;
+        let remote = scan_remote(self.repository).await?;
+
+        let paths: BTreeSet<_> = state
+            .entries
+            .keys()
+            .chain(local.keys())
+            .chain(remote.keys())
+            .cloned()
+            .collect();
+
+        let total = paths.len() as u64;
+        let mut summary = FolderSyncSummary::default();
+
+        for (index, path) in paths.into_iter().enumerate() {
+            on_event(FolderSyncEvent::Progress {
+                value: index as u64,
+                total,
+            })
+            .await;
+
+            let base = state.entries.get(&path);
+            let local_hash = self.local_hash(&path, local.get(&path), base).await?;
+            let remote_hash = match self.remote_hash(&path, remote.get(&path), base).await {
+                Ok(hash) => hash,
+                Err(FolderSyncError::Repository(ouisync_lib::Error::Store(
+                    ouisync_lib::StoreError::BlockNotFound,
+                ))) => {
+                    // The repository version hasn't been fully downloaded yet. Try again in the
+                    // next round.
+                    tracing::debug!(%path, "Skipping incomplete entry");
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+
+            let action = diff::reconcile(
+                base.map(|base| &base.hash),
+                local_hash.as_ref(),
+                remote_hash.as_ref(),
+            );
+
+            match action {
+                Action::None => (),
+                Action::Forget => {
+                    state.entries.remove(&path);
+                }
+                Action::Record => (),
+                Action::Upload => {
+                    self.upload(&path).await?;
+                    summary.uploaded += 1;
+                }
+                Action::Download => {
+                    self.download(&path).await?;
+                    summary.downloaded += 1;
+                }
+                Action::RemoveLocal => {
+                    remove_local(&self.local_path(&path)).await?;
+                    summary.removed_local += 1;
+                }
+                Action::RemoveRemote => {
+                    self.repository.remove_entry(&path).await?;
+                    summary.removed_remote += 1;
+                }
+                Action::Conflict(kind) => {
+                    let resolution = self.resolve_conflict(&mut state, &path, kind).await?;
+                    summary.conflicts += 1;
+                    on_event(FolderSyncEvent::Conflict {
+                        path: path.clone(),
+                        resolution,
+                    })
+                    .await;
+                }
+            }
+
+            if !matches!(action, Action::None | Action::Forget) {
+                self.record(&mut state, &path).await?;
+            }
+        }
+
+        on_event(FolderSyncEvent::Progress {
+            value: total,
+            total,
+        })
+        .await;
+
+        state.save(&self.state_path).await?;
+
+        on_event(FolderSyncEvent::Completed(summary.clone())).await;
+
+        Ok(summary)
+    }
+
+    async fn resolve_conflict(
+        &self,
+        state: &mut SyncState,
+        path: &str,
+        kind: ConflictKind,
+    ) -> Result<ConflictResolution, FolderSyncError> {
+        let resolution = match (kind, self.policy) {
+            (ConflictKind::LocalModifiedRemoteRemoved, _)
+            | (ConflictKind::Content, ConflictPolicy::PreferLocal) => {
+                self.upload(path).await?;
+                ConflictResolution::KeptLocal
+            }
+            (ConflictKind::LocalRemovedRemoteModified, _)
+            | (ConflictKind::Content, ConflictPolicy::PreferRepository) => {
+                self.download(path).await?;
+                ConflictResolution::KeptRepository
+            }
+            (ConflictKind::Content, ConflictPolicy::KeepBoth) => {
+                let conflict_path = self.conflict_path(path).await;
+
+                fs::rename(self.local_path(path), self.local_path(&conflict_path)).await?;
+                self.upload(&conflict_path).await?;
+                self.record(state, &conflict_path).await?;
+                self.download(path).await?;
+
+                ConflictResolution::KeptBoth
+            }
+        };
+
+        Ok(resolution)
+    }
+
+    /// Returns the hash of the local version of the entry or `None` if it doesn't exist.
+    async fn local_hash(
+        &self,
+        path: &str,
+        meta: Option<&LocalMeta>,
+        base: Option<&EntryState>,
+    ) -> Result<Option<Hash>, FolderSyncError> {
+        let Some(meta) = meta else {
+            return Ok(None);
+        };
+
+        if let Some(base) = base {
+            if base.len == meta.len && base.modified == meta.modified {
+                return Ok(Some(base.hash));
+            }
+        }
+
+        Ok(Some(hash_local(&self.local_path(path)).await?))
+    }
+
+    /// Returns the hash of the repository version of the entry or `None` if it doesn't exist.
+    async fn remote_hash(
+        &self,
+        path: &str,
+        version_vector_hash: Option<&Hash>,
+        base: Option<&EntryState>,
+    ) -> Result<Option<Hash>, FolderSyncError> {
+        let Some(version_vector_hash) = version_vector_hash else {
+            return Ok(None);
+        };
+
+        if let Some(base) = base {
+            if &base.version_vector_hash == version_vector_hash {
+                return Ok(Some(base.hash));
+            }
+        }
+
+        Ok(Some(hash_remote(self.repository, path).await?))
+    }
+
+    async fn upload(&self, path: &str) -> Result<(), FolderSyncError> {
+        let mut src = fs::File::open(self.local_path(path)).await?;
+
+        let mut dst = match self.repository.open_file(path).await {
+            Ok(mut file) => {
+                file.fork(self.repository.local_branch()?).await?;
+                file.truncate(0)?;
+                file
+            }
+            Err(ouisync_lib::Error::EntryNotFound) => self.repository.create_file(path).await?,
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut buffer = vec![0; BLOCK_SIZE];
+
+        loop {
+            let len = src.read(&mut buffer).await?;
+            if len == 0 {
+                break;
+            }
+
+            dst.write_all(&buffer[..len]).await?;
+        }
+
+        dst.flush().await?;
+
+        Ok(())
+    }
+
+    async fn download(&self, path: &str) -> Result<(), FolderSyncError> {
+        let dst_path = self.local_path(path);
+
+        if let Some(parent) = dst_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let mut src = self.repository.open_file(path).await?;
+        let mut dst = fs::File::create(&dst_path).await?;
+
+        src.copy_to_writer(&mut dst).await?;
+        dst.flush().await?;
+
+        Ok(())
+    }
+
+    /// Records the current state of the entry (which must be already in sync on both sides).
+    async fn record(&self, state: &mut SyncState, path: &str) -> Result<(), FolderSyncError> {
+        let local_path = self.local_path(path);
+        let metadata = match fs::metadata(&local_path).await {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                state.entries.remove(path);
+                return Ok(());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let version_vector_hash = self
+            .repository
+            .open_file(path)
+            .await?
+            .version_vector()
+            .await?
+            .hash();
+
+        state.entries.insert(
+            path.to_owned(),
+            EntryState {
+                hash: hash_local(&local_path).await?,
+                len: metadata.len(),
+                modified: metadata.modified()?,
+                version_vector_hash,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Finds a path for the local version of a conflicting entry that doesn't exist on either
+    /// side.
+    async fn conflict_path(&self, path: &str) -> String {
+        let path = Utf8Path::new(path);
+        let stem = path.file_stem().unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{ext}"))
+            .unwrap_or_default();
+
+        for n in 1.. {
+            let name = if n == 1 {
+                format!("{stem} (local conflict){extension}")
+            } else {
+                format!("{stem} (local conflict {n}){extension}")
+            };
+
+            let candidate = path.with_file_name(name);
+
+            if fs::metadata(self.local_path(candidate.as_str()))
+                .await
+                .is_err()
+                && matches!(
+                    self.repository.lookup_type(&candidate).await,
+                    Err(ouisync_lib::Error::EntryNotFound)
+                )
+            {
+                return candidate.into_string();
+            }
+        }
+
+        unreachable!()
+    }
+
+    fn local_path(&self, path: &str) -> PathBuf {
+        Utf8Path::new(path)
+            .components()
+            .fold(self.local_root.clone(), |mut local, component| {
+                local.push(component.as_str());
+                local
+            })
+    }
+}
+
+struct LocalMeta {
+    len: u64,
+    modified: SystemTime,
+}
+
+/// Collects all files in the local folder, keyed by their path relative to the folder root.
+async fn scan_local(root: &Path) -> Result<BTreeMap<String, LocalMeta>, FolderSyncError> {
+    let mut files = BTreeMap::new();
+    let mut stack = vec![(root.to_owned(), Utf8PathBuf::new())];
+
+    while let Some((dir, prefix)) = stack.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(name) = entry.file_name().into_string() else {
+                tracing::warn!(path = ?entry.path(), "Skipping entry with non-utf8 name");
+                continue;
+            };
+
+            let path = prefix.join(&name);
+            let metadata = entry.metadata().await?;
+
+            if metadata.is_dir() {
+                stack.push((entry.path(), path));
+            } else if metadata.is_file() {
+                files.insert(
+                    path.into_string(),
+                    LocalMeta {
+                        len: metadata.len(),
+                        modified: metadata.modified()?,
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Collects all files in the repository, keyed by their path, together with the hashes of their
+/// version vectors. Concurrent versions of the same file are disambiguated using their unique
+/// names.
+async fn scan_remote(repository: &Repository) -> Result<BTreeMap<String, Hash>, FolderSyncError> {
+    let mut files = BTreeMap::new();
+    let mut stack = vec![Utf8PathBuf::new()];
+
+    while let Some(path) = stack.pop() {
+        let dir = repository.open_directory(&path).await?;
+
+        for entry in dir.entries() {
+            let entry_path = path.join(entry.unique_name().as_ref());
+
+            match entry.entry_type() {
+                EntryType::Directory => stack.push(entry_path),
+                EntryType::File => {
+                    if let JointEntryRef::File(file) = &entry {
+                        files.insert(entry_path.into_string(), file.version_vector().hash());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+async fn hash_local(path: &Path) -> io::Result<Hash> {
+    let mut file = fs::File::open(path).await?;
+    let mut buffer = vec![0; BLOCK_SIZE];
+    let mut chunks = Vec::new();
+
+    loop {
+        // Fill the whole buffer (unless at the end of the file) so the chunks are the same as
+        // when hashing the repository version.
+        let mut len = 0;
+        while len < buffer.len() {
+            match file.read(&mut buffer[len..]).await? {
+                0 => break,
+                n => len += n,
+            }
+        }
+
+        if len == 0 {
+            break;
+        }
+
+        chunks.push(buffer[..len].hash());
+    }
+
+    Ok(chunks.hash())
+}
+
+async fn hash_remote(repository: &Repository, path: &str) -> Result<Hash, ouisync_lib::Error> {
+    let mut file = repository.open_file(path).await?;
+    let mut buffer = vec![0; BLOCK_SIZE];
+    let mut chunks = Vec::new();
+
+    loop {
+        let len = file.read_all(&mut buffer).await?;
+        if len == 0 {
+            break;
+        }
+
+        chunks.push(buffer[..len].hash());
+    }
+
+    Ok(chunks.hash())
+}
+
+async fn remove_local(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use ouisync_lib::{Access, RepositoryParams, WriteSecrets};
+    use std::future;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn upload() {
+        let (base_dir, repo) = setup().await;
+        let local_root = base_dir.path().join("local");
+
+        write_local(&local_root, "a.txt", b"alpha").await;
+        write_local(&local_root, "dir/b.txt", b"bravo").await;
+
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary.uploaded, 2);
+        assert_eq!(read_remote(&repo, "a.txt").await, b"alpha");
+        assert_eq!(read_remote(&repo, "dir/b.txt").await, b"bravo");
+
+        // Local modification
+        write_local(&local_root, "a.txt", b"alpha 2").await;
+
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary.uploaded, 1);
+        assert_eq!(read_remote(&repo, "a.txt").await, b"alpha 2");
+
+        // Nothing changed
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary, FolderSyncSummary::default());
+    }
+
+    #[tokio::test]
+    async fn download() {
+        let (base_dir, repo) = setup().await;
+        let local_root = base_dir.path().join("local");
+
+        write_remote(&repo, "a.txt", b"alpha").await;
+        repo.create_directory("dir").await.unwrap();
+        write_remote(&repo, "dir/b.txt", b"bravo").await;
+
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary.downloaded, 2);
+        assert_eq!(read_local(&local_root, "a.txt").await, b"alpha");
+        assert_eq!(read_local(&local_root, "dir/b.txt").await, b"bravo");
+
+        // Repository modification
+        write_remote(&repo, "a.txt", b"alpha 2").await;
+
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary.downloaded, 1);
+        assert_eq!(read_local(&local_root, "a.txt").await, b"alpha 2");
+
+        // Nothing changed
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary, FolderSyncSummary::default());
+    }
+
+    #[tokio::test]
+    async fn remove() {
+        let (base_dir, repo) = setup().await;
+        let local_root = base_dir.path().join("local");
+
+        write_local(&local_root, "a.txt", b"alpha").await;
+        write_local(&local_root, "b.txt", b"bravo").await;
+        sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+
+        // Removed locally
+        fs::remove_file(local_root.join("a.txt")).await.unwrap();
+
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary.removed_remote, 1);
+        assert_matches!(
+            repo.lookup_type("a.txt").await,
+            Err(ouisync_lib::Error::EntryNotFound)
+        );
+
+        // Removed in the repository
+        repo.remove_entry("b.txt").await.unwrap();
+
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary.removed_local, 1);
+        assert_matches!(
+            fs::metadata(local_root.join("b.txt")).await,
+            Err(error) if error.kind() == io::ErrorKind::NotFound
+        );
+
+        // Nothing changed
+        let summary = sync(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(summary, FolderSyncSummary::default());
+    }
+
+    #[tokio::test]
+    async fn conflict_prefer_local() {
+        let (base_dir, repo) = setup().await;
+        let local_root = base_dir.path().join("local");
+
+        let resolutions = conflict(&base_dir, &repo, ConflictPolicy::PreferLocal).await;
+        assert_eq!(resolutions, [ConflictResolution::KeptLocal]);
+
+        assert_eq!(read_local(&local_root, "a.txt").await, b"local version");
+        assert_eq!(read_remote(&repo, "a.txt").await, b"local version");
+    }
+
+    #[tokio::test]
+    async fn conflict_prefer_repository() {
+        let (base_dir, repo) = setup().await;
+        let local_root = base_dir.path().join("local");
+
+        let resolutions = conflict(&base_dir, &repo, ConflictPolicy::PreferRepository).await;
+        assert_eq!(resolutions, [ConflictResolution::KeptRepository]);
+
+        assert_eq!(read_local(&local_root, "a.txt").await, b"remote version!");
+        assert_eq!(read_remote(&repo, "a.txt").await, b"remote version!");
+    }
+
+    #[tokio::test]
+    async fn conflict_keep_both() {
+        let (base_dir, repo) = setup().await;
+        let local_root = base_dir.path().join("local");
+
+        let resolutions = conflict(&base_dir, &repo, ConflictPolicy::KeepBoth).await;
+        assert_eq!(resolutions, [ConflictResolution::KeptBoth]);
+
+        assert_eq!(read_local(&local_root, "a.txt").await, b"remote version!");
+        assert_eq!(read_remote(&repo, "a.txt").await, b"remote version!");
+
+        let conflict_path = "a (local conflict).txt";
+        assert_eq!(
+            read_local(&local_root, conflict_path).await,
+            b"local version"
+        );
+        assert_eq!(read_remote(&repo, conflict_path).await, b"local version");
+    }
+
+    async fn setup() -> (TempDir, Repository) {
+        let base_dir = TempDir::new().unwrap();
+        let repo = Repository::create(
+            &RepositoryParams::new(base_dir.path().join("repo.ouisyncdb")),
+            Access::WriteUnlocked {
+                secrets: WriteSecrets::random(),
+            },
+        )
+        .await
+        .unwrap();
+
+        (base_dir, repo)
+    }
+
+    // Runs a single sync round between `repo` and the "local" folder inside `base_dir`.
+    async fn sync(
+        base_dir: &TempDir,
+        repo: &Repository,
+        policy: ConflictPolicy,
+    ) -> FolderSyncSummary {
+        sync_with_events(base_dir, repo, policy).await.0
+    }
+
+    async fn sync_with_events(
+        base_dir: &TempDir,
+        repo: &Repository,
+        policy: ConflictPolicy,
+    ) -> (FolderSyncSummary, Vec<FolderSyncEvent>) {
+        let folder_sync = FolderSync::new(
+            repo,
+            base_dir.path().join("local"),
+            &base_dir.path().join("state"),
+            policy,
+        );
+
+        let mut events = Vec::new();
+        let summary = folder_sync
+            .sync(|event| {
+                events.push(event);
+                future::ready(())
+            })
+            .await
+            .unwrap();
+
+        (summary, events)
+    }
+
+    // Modifies the same file concurrently on both sides, syncs them using `policy` and returns
+    // the resolutions of the reported conflicts. The two versions have different lengths so the
+    // local modification is detected even if the modification time doesn't change.
+    async fn conflict(
+        base_dir: &TempDir,
+        repo: &Repository,
+        policy: ConflictPolicy,
+    ) -> Vec<ConflictResolution> {
+        let local_root = base_dir.path().join("local");
+
+        write_local(&local_root, "a.txt", b"original").await;
+        sync(base_dir, repo, policy).await;
+
+        write_local(&local_root, "a.txt", b"local version").await;
+        write_remote(repo, "a.txt", b"remote version!").await;
+
+        let (summary, events) = sync_with_events(base_dir, repo, policy).await;
+        assert_eq!(summary.conflicts, 1);
+
+        // The conflict is resolved and so the next round has nothing to do.
+        let summary = sync(base_dir, repo, policy).await;
+        assert_eq!(summary, FolderSyncSummary::default());
+
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                FolderSyncEvent::Conflict { path, resolution } => {
+                    assert_eq!(path, "a.txt");
+                    Some(resolution)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    async fn write_local(root: &Path, path: &str, content: &[u8]) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::write(path, content).await.unwrap();
+    }
+
+    async fn read_local(root: &Path, path: &str) -> Vec<u8> {
+        fs::read(root.join(path)).await.unwrap()
+    }
+
+    async fn write_remote(repo: &Repository, path: &str, content: &[u8]) {
+        let mut file = match repo.open_file(path).await {
+            Ok(file) => file,
+            Err(ouisync_lib::Error::EntryNotFound) => repo.create_file(path).await.unwrap(),
+            Err(error) => panic!("unexpected error: {error:?}"),
+        };
+
+        file.truncate(0).unwrap();
+        file.write_all(content).await.unwrap();
+        file.flush().await.unwrap();
+    }
+
+    async fn read_remote(repo: &Repository, path: &str) -> Vec<u8> {
+        repo.open_file(path)
+            .await
+            .unwrap()
+            .read_to_end()
+            .await
+            .unwrap()
+    }
+}
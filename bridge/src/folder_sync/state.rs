@@ -0,0 +1,100 @@
+//! Persistent record of the last synced state of every entry of a synced folder.
+
+use ouisync_lib::crypto::Hash;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::fs;
+
+/// State of a single file at the moment it was last synced.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct EntryState {
+    /// Hash of the content (same on both sides).
+    pub hash: Hash,
+    /// Length of the local file. Together with `modified` used to quickly detect local changes
+    /// without having to re-hash the file.
+    pub len: u64,
+    /// Modification time of the local file.
+    pub modified: SystemTime,
+    /// Hash of the version vector of the repository file. Used to quickly detect repository
+    /// changes without having to re-hash the file.
+    pub version_vector_hash: Hash,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub(super) struct SyncState {
+    pub entries: BTreeMap<String, EntryState>,
+}
+
+impl SyncState {
+    pub async fn load(path: &Path) -> io::Result<Self> {
+        let content = match fs::read(path).await {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => return Err(error),
+        };
+
+        serde_json::from_slice(&content)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Atomically saves the state by first writing it into a temporary file and then renaming it
+    /// over the original one.
+    pub async fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+
+        let content = serde_json::to_vec(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+        let tmp_path = tmp_path(path);
+        fs::write(&tmp_path, content).await?;
+        fs::rename(&tmp_path, path).await?;
+
+        Ok(())
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tmp.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ouisync_lib::crypto::Hashable;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("state.json");
+
+        let state = SyncState::load(&path).await.unwrap();
+        assert!(state.entries.is_empty());
+
+        let mut state = SyncState::default();
+        state.entries.insert(
+            "a/b.txt".to_owned(),
+            EntryState {
+                hash: b"hello".hash(),
+                len: 5,
+                modified: SystemTime::UNIX_EPOCH,
+                version_vector_hash: b"vv".hash(),
+            },
+        );
+        state.save(&path).await.unwrap();
+
+        let state = SyncState::load(&path).await.unwrap();
+        let entry = state.entries.get("a/b.txt").unwrap();
+        assert_eq!(entry.hash, b"hello".hash());
+        assert_eq!(entry.len, 5);
+    }
+}
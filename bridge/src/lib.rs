@@ -1,6 +1,7 @@
 pub mod config;
 pub mod device_id;
 pub mod dht_contacts;
+pub mod folder_sync;
 pub mod logger;
 pub mod network;
 pub mod protocol;
@@ -1,7 +1,10 @@
 use crate::config::{ConfigKey, ConfigStore};
-use ouisync_lib::network::{peer_addr::PeerAddr, Network};
+use ouisync_lib::network::{
+    peer_addr::PeerAddr, ConnectionStats, KeepAliveConfig, Network, PeerSource,
+};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
 
 const BIND_KEY: ConfigKey<Vec<PeerAddr>> =
     ConfigKey::new("bind", "Addresses to bind the network listeners to");
@@ -12,12 +15,46 @@ const PORT_FORWARDING_ENABLED_KEY: ConfigKey<bool> =
 const LOCAL_DISCOVERY_ENABLED_KEY: ConfigKey<bool> =
     ConfigKey::new("local_discovery_enabled", "Enable local discovery");
 
+const LAN_BULK_TRANSFER_ENABLED_KEY: ConfigKey<bool> = ConfigKey::new(
+    "lan_bulk_transfer_enabled",
+    "Request blocks in batches and keep more requests in flight with the peers on the local\n\
+     network. Enable only if the devices on the local network are trusted",
+);
+
+const TAG_PEER_SOURCES_KEY: ConfigKey<HashMap<String, Vec<PeerSource>>> = ConfigKey::new(
+    "tag_peer_sources",
+    "Peer sources (connections and discovery mechanisms) allowed to serve the repositories with\n\
+     the given routing tag. Tags not listed here are served by all sources",
+);
+
+const VERIFY_THREADS_KEY: ConfigKey<u32> = ConfigKey::new(
+    "verify_threads",
+    "Number of threads used to verify the data received from peers",
+);
+
+const KEEP_ALIVE_INTERVAL_KEY: ConfigKey<u64> = ConfigKey::new(
+    "keep_alive_interval",
+    "How often (in milliseconds) to ping a peer when there is no other traffic on the connection",
+);
+
+const KEEP_ALIVE_TIMEOUT_KEY: ConfigKey<u64> = ConfigKey::new(
+    "keep_alive_timeout",
+    "Time (in milliseconds) without receiving anything from a peer after which the peer is\n\
+     considered dead and disconnected",
+);
+
 const PEERS_KEY: ConfigKey<Vec<PeerAddr>> = ConfigKey::new(
     "peers",
     "List of peers to connect to in addition to the ones found by various discovery mechanisms\n\
      (e.g. DHT)",
 );
 
+const CONNECTION_STATS_KEY: ConfigKey<ConnectionStats> = ConfigKey::new(
+    "connection_stats",
+    "Outcomes of the outgoing connection attempts (direct, hole punched, relayed or failed) per\n\
+     the behavior of the NAT we were behind, accumulated over all the runs of the application",
+);
+
 const LAST_USED_TCP_V4_PORT_KEY: ConfigKey<u16> =
     ConfigKey::new("last_used_tcp_v4_port", LAST_USED_TCP_PORT_COMMENT);
 
@@ -51,6 +88,9 @@ pub struct NetworkDefaults {
 
 /// Initialize the network according to the config.
 pub async fn init(network: &Network, config: &ConfigStore, defaults: NetworkDefaults) {
+    // Needs to be applied before binding so the QUIC stacks pick it up.
+    network.set_keep_alive(load_keep_alive(config).await);
+
     let bind_addrs = config.entry(BIND_KEY).get().await.unwrap_or_default();
     bind_with_reuse_ports(network, config, &bind_addrs).await;
 
@@ -68,16 +108,48 @@ pub async fn init(network: &Network, config: &ConfigStore, defaults: NetworkDefa
         .unwrap_or(defaults.local_discovery_enabled);
     network.set_local_discovery_enabled(enabled);
 
+    if let Ok(threads) = config.entry(VERIFY_THREADS_KEY).get().await {
+        network.set_verify_threads(threads as usize);
+    }
+
+    if let Ok(enabled) = config.entry(LAN_BULK_TRANSFER_ENABLED_KEY).get().await {
+        network.set_lan_bulk_transfer_enabled(enabled);
+    }
+
+    let tag_peer_sources = config
+        .entry(TAG_PEER_SOURCES_KEY)
+        .get()
+        .await
+        .unwrap_or_default();
+    for (tag, sources) in tag_peer_sources {
+        network.set_tag_peer_sources(&tag, Some(sources.into_iter().collect()));
+    }
+
     let peers = config.entry(PEERS_KEY).get().await.unwrap_or_default();
     for peer in peers {
         network.add_user_provided_peer(&peer);
     }
+
+    if let Ok(stats) = config.entry(CONNECTION_STATS_KEY).get().await {
+        network.merge_connection_stats(&stats);
+    }
+}
+
+/// Saves the connection stats so they are accumulated across restarts. Should be called before
+/// the network is shut down.
+pub async fn save_connection_stats(network: &Network, config: &ConfigStore) {
+    config
+        .entry(CONNECTION_STATS_KEY)
+        .set(&network.connection_stats())
+        .await
+        .ok();
 }
 
 /// Binds the network to the specified addresses.
 /// Rebinds all stacks (TCP/QUIC, IPv4/IPv6) if any of them needs rebinding (address or port
 /// changes). If any of the addresses are missing, that particular protocol/family combination is
-/// not bound. If all are missing the network is disabled.
+/// not bound. If all are missing the network is disabled. Multiple addresses of the same
+/// protocol/family can be given to listen on several interfaces at once.
 pub async fn bind(network: &Network, config: &ConfigStore, addrs: &[PeerAddr]) {
     config.entry(BIND_KEY).set(addrs).await.ok();
     bind_with_reuse_ports(network, config, addrs).await;
@@ -117,6 +189,86 @@ pub async fn set_local_discovery_enabled(network: &Network, config: &ConfigStore
     network.set_local_discovery_enabled(enabled);
 }
 
+/// Enable/disable bulk transfer with the peers on the local network
+pub async fn set_lan_bulk_transfer_enabled(network: &Network, config: &ConfigStore, enabled: bool) {
+    config
+        .entry(LAN_BULK_TRANSFER_ENABLED_KEY)
+        .set(&enabled)
+        .await
+        .ok();
+    network.set_lan_bulk_transfer_enabled(enabled);
+}
+
+/// Restricts the repositories with the given routing tag to be served only by the given peer
+/// sources. `None` removes the restriction.
+pub async fn set_tag_peer_sources(
+    network: &Network,
+    config: &ConfigStore,
+    tag: &str,
+    sources: Option<Vec<PeerSource>>,
+) {
+    let entry = config.entry(TAG_PEER_SOURCES_KEY);
+    let mut all = entry.get().await.unwrap_or_default();
+
+    match &sources {
+        Some(sources) => {
+            all.insert(tag.to_owned(), sources.clone());
+        }
+        None => {
+            all.remove(tag);
+        }
+    }
+
+    entry.set(&all).await.ok();
+    network.set_tag_peer_sources(tag, sources.map(|sources| sources.into_iter().collect()));
+}
+
+/// Set the number of threads used to verify the received data
+pub async fn set_verify_threads(network: &Network, config: &ConfigStore, threads: u32) {
+    config.entry(VERIFY_THREADS_KEY).set(&threads).await.ok();
+    network.set_verify_threads(threads as usize);
+}
+
+/// Sets the keep-alive (ping) interval and the timeout after which an unresponsive peer is
+/// disconnected.
+pub async fn set_keep_alive(network: &Network, config: &ConfigStore, keep_alive: KeepAliveConfig) {
+    config
+        .entry(KEEP_ALIVE_INTERVAL_KEY)
+        .set(&millis(keep_alive.interval))
+        .await
+        .ok();
+    config
+        .entry(KEEP_ALIVE_TIMEOUT_KEY)
+        .set(&millis(keep_alive.timeout))
+        .await
+        .ok();
+
+    network.set_keep_alive(keep_alive);
+}
+
+async fn load_keep_alive(config: &ConfigStore) -> KeepAliveConfig {
+    let default = KeepAliveConfig::default();
+
+    let interval = config
+        .entry(KEEP_ALIVE_INTERVAL_KEY)
+        .get()
+        .await
+        .map(Duration::from_millis)
+        .unwrap_or(default.interval);
+    let timeout = config
+        .entry(KEEP_ALIVE_TIMEOUT_KEY)
+        .get()
+        .await
+        .map(Duration::from_millis)
+        .unwrap_or(default.timeout);
+
+    KeepAliveConfig { interval, timeout }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
 /// Add peers to connect to
 pub async fn add_user_provided_peers(network: &Network, config: &ConfigStore, peers: &[PeerAddr]) {
     let entry = config.entry(PEERS_KEY);
@@ -162,6 +314,76 @@ pub async fn user_provided_peers(config: &ConfigStore) -> Vec<PeerAddr> {
     config.entry(PEERS_KEY).get().await.unwrap_or_default()
 }
 
+/// Watches the user provided peers stored in the config for modifications made by other processes
+/// sharing the same config dir (e.g., a peer added with the CLI while the app is running), so they
+/// can be applied to the network without a restart.
+pub struct UserProvidedPeersWatcher {
+    config: ConfigStore,
+    rx: broadcast::Receiver<String>,
+    peers: Vec<PeerAddr>,
+}
+
+impl UserProvidedPeersWatcher {
+    pub async fn new(config: ConfigStore) -> Self {
+        let rx = config.subscribe().await;
+        let peers = user_provided_peers(&config).await;
+
+        Self { config, rx, peers }
+    }
+
+    /// Waits until the stored peers change and returns the change. Returns `None` when the config
+    /// store is dropped.
+    pub async fn changed(&mut self) -> Option<UserProvidedPeersChange> {
+        loop {
+            match self.rx.recv().await {
+                Ok(name) if name == PEERS_KEY.name() => (),
+                Ok(_) => continue,
+                // Some notifications were missed, one of them might have been about the peers.
+                Err(RecvError::Lagged(_)) => (),
+                Err(RecvError::Closed) => return None,
+            }
+
+            let peers = user_provided_peers(&self.config).await;
+            let change = UserProvidedPeersChange {
+                added: diff(&peers, &self.peers),
+                removed: diff(&self.peers, &peers),
+            };
+            self.peers = peers;
+
+            // Changes made by this process have been applied already.
+            if change.added.is_empty() && change.removed.is_empty() {
+                continue;
+            }
+
+            return Some(change);
+        }
+    }
+}
+
+/// Peers added to or removed from the config, as detected by `UserProvidedPeersWatcher`.
+#[derive(Eq, PartialEq, Debug)]
+pub struct UserProvidedPeersChange {
+    pub added: Vec<PeerAddr>,
+    pub removed: Vec<PeerAddr>,
+}
+
+impl UserProvidedPeersChange {
+    pub fn apply(&self, network: &Network) {
+        for peer in &self.added {
+            network.add_user_provided_peer(peer);
+        }
+
+        for peer in &self.removed {
+            network.remove_user_provided_peer(peer);
+        }
+    }
+}
+
+// Peers in `a` which are not in `b`.
+fn diff(a: &[PeerAddr], b: &[PeerAddr]) -> Vec<PeerAddr> {
+    a.iter().filter(|peer| !b.contains(peer)).copied().collect()
+}
+
 /// Utility to help reuse bind ports across network restarts.
 struct LastUsedPorts {
     quic_v4: u16,
@@ -277,6 +499,41 @@ mod tests {
     use tempfile::TempDir;
     use tokio::time;
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn user_provided_peers_watcher() {
+        let config_dir = TempDir::new().unwrap();
+        let config = ConfigStore::new(config_dir.path());
+        let network = Network::new(None, StateMonitor::make_root());
+        let mut watcher = UserProvidedPeersWatcher::new(config.clone()).await;
+
+        // Another process sharing the same config dir.
+        let other_config = ConfigStore::new(config_dir.path());
+        let other_network = Network::new(None, StateMonitor::make_root());
+
+        let peer_a = PeerAddr::Quic((Ipv4Addr::LOCALHOST, 45000).into());
+        let peer_b = PeerAddr::Quic((Ipv4Addr::LOCALHOST, 45001).into());
+
+        add_user_provided_peers(&other_network, &other_config, &[peer_a, peer_b]).await;
+        assert_eq!(
+            watcher.changed().await,
+            Some(UserProvidedPeersChange {
+                added: vec![peer_a, peer_b],
+                removed: vec![],
+            })
+        );
+
+        remove_user_provided_peers(&other_network, &other_config, &[peer_a]).await;
+        let change = watcher.changed().await.unwrap();
+        assert_eq!(
+            change,
+            UserProvidedPeersChange {
+                added: vec![],
+                removed: vec![peer_a],
+            }
+        );
+        change.apply(&network);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn network_disable_enable_idle() {
         let config_dir = TempDir::new().unwrap();
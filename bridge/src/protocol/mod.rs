@@ -1,7 +1,53 @@
 pub mod remote;
 
+use crate::folder_sync::FolderSyncEvent;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Latest version of the client/server protocol. Clients negotiate the version they use with a
+/// handshake which they should send as the first request and wait for its response before sending
+/// any other requests. Clients that don't do the handshake use version 0.
+///
+/// The version only affects the encoding of the messages sent by the server. Clients must request
+/// only the versions their decoders support (the Dart and Kotlin bindings request version 1).
+///
+/// Version history:
+///
+/// - 0: structs are encoded as messagepack arrays (fields identified by their position).
+/// - 1: structs are encoded as messagepack maps (fields identified by their names) so clients
+///   can skip unknown fields and tolerate reordering.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// Protocol version negotiated with a particular client. Shared between the transport (which
+/// uses it to select the encoding) and the handler (which performs the handshake).
+#[derive(Clone, Default, Debug)]
+pub struct NegotiatedVersion(Arc<AtomicU64>);
+
+impl NegotiatedVersion {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Negotiates the version given the one requested by the client: uses the lower of the
+    /// requested and the latest supported one. Returns the negotiated version.
+    pub fn negotiate(&self, requested: u64) -> u64 {
+        let version = requested.min(PROTOCOL_VERSION);
+        self.0.store(version, Ordering::Release);
+        version
+    }
+
+    /// Whether structs are encoded with named fields.
+    pub(crate) fn named_fields(&self) -> bool {
+        self.get() >= 1
+    }
+}
 
 pub trait DeserializeVersioned<'de>: Sized {
     fn deserialize_versioned<D>(version: u64, d: D) -> Result<Self, D::Error>
@@ -36,6 +82,13 @@ pub enum Notification {
     Repository,
     Network(NetworkEvent),
     StateMonitor,
+    FolderSync(FolderSyncEvent),
+    SessionRestore(SessionRestoreEvent),
+    Operation(OperationEvent),
+    OverlayState(Vec<OverlayStateChange>),
+    /// The handle identified by the id of the notification is no longer valid (e.g., because its
+    /// repository has been force-closed). Any further request using it fails.
+    HandleInvalidated,
 }
 
 /// Network notification event.
@@ -50,6 +103,60 @@ pub enum NetworkEvent {
     ProtocolVersionMismatch = 0,
     /// The set of known peers has changed (e.g., a new peer has been discovered)
     PeerSetChange = 1,
+    /// A peer has been disconnected because it stopped responding to keep-alive pings.
+    PeerTimeout = 2,
+}
+
+/// Progress of re-opening the repositories from the previous session.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionRestoreEvent {
+    /// The repository at `path` has been re-opened. `repository` is its handle.
+    Restored { path: PathBuf, repository: u64 },
+    /// Failed to re-open the repository at `path`.
+    Failed { path: PathBuf, error: String },
+    /// All the repositories have been processed.
+    Completed { restored: u64, failed: u64 },
+}
+
+/// Progress of a long running operation. The operation is identified by the id of the
+/// notification (which is the handle returned by the request that started it).
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationEvent {
+    /// `value` out of `total` units of work of the current `stage` have been done.
+    Progress {
+        stage: String,
+        value: u64,
+        total: u64,
+    },
+    /// The operation has completed successfully.
+    Completed,
+    /// The operation has failed.
+    Failed { error: String },
+}
+
+/// Sync state of a file or directory as shown by the overlay icons of the file manager shell
+/// extensions (Explorer, Finder, ...).
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayState {
+    /// All the content is present locally.
+    Synced,
+    /// Some of the content is still being downloaded.
+    Syncing,
+    /// There are multiple concurrent versions of the entry (or of some entry inside the directory).
+    Conflict,
+    /// The entry is not being synced (e.g., because syncing of the repository is disabled).
+    Excluded,
+}
+
+/// Change of the overlay state of the entry at `path` (relative to the repository root). `state`
+/// is `None` if the entry has been removed.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct OverlayStateChange {
+    pub path: String,
+    pub state: Option<OverlayState>,
 }
 
 /// Opaque, non-sensitive value unique to a particular client session and accessible to both the
@@ -114,4 +221,17 @@ mod tests {
             assert_eq!(decoded, orig);
         }
     }
+    #[test]
+    fn negotiate_version() {
+        let version = NegotiatedVersion::default();
+        assert_eq!(version.get(), 0);
+        assert!(!version.named_fields());
+
+        assert_eq!(version.negotiate(PROTOCOL_VERSION + 1), PROTOCOL_VERSION);
+        assert_eq!(version.get(), PROTOCOL_VERSION);
+        assert!(version.named_fields());
+
+        assert_eq!(version.negotiate(0), 0);
+        assert!(!version.named_fields());
+    }
 }
@@ -5,9 +5,11 @@ use crate::{
     transport::RemoteClient,
 };
 use ouisync_lib::{
-    crypto::sign::Signature, Access, AccessMode, AccessSecrets, LocalSecret, Repository,
-    RepositoryId, RepositoryParams, SetLocalSecret, ShareToken, StorageSize, WriteSecrets,
+    crypto::sign::Signature, Access, AccessMode, AccessSecrets, DbTuning, LocalSecret,
+    MemoryBudget, Repository, RepositoryId, RepositoryParams, SetLocalSecret, ShareToken,
+    StorageSize, WriteSecrets,
 };
+use rand::{rngs::StdRng, SeedableRng};
 use state_monitor::StateMonitor;
 use std::{io, path::PathBuf, sync::Arc, time::Duration};
 use thiserror::Error;
@@ -19,6 +21,15 @@ const DEFAULT_BLOCK_EXPIRATION_MILLIS: ConfigKey<u64> = ConfigKey::new(
     "default_block_expiration",
     "Default time in seconds when blocks start to expire if not used",
 );
+const DEFAULT_DB_TUNING_KEY: ConfigKey<DbTuning> = ConfigKey::new(
+    "default_db_tuning",
+    "Default tuning of the repository databases",
+);
+
+const TEST_RNG_SEED_KEY: ConfigKey<u64> = ConfigKey::new(
+    "test_rng_seed",
+    "Seed of the generator of the ids of the created repositories (for reproducible tests only)",
+);
 
 #[derive(Debug, Error)]
 pub enum OpenError {
@@ -56,13 +67,21 @@ pub async fn create(
     share_token: Option<ShareToken>,
     config: &ConfigStore,
     repos_monitor: &StateMonitor,
+    memory: &MemoryBudget,
 ) -> Result<Repository, OpenError> {
     let params = RepositoryParams::new(store)
         .with_device_id(device_id::get_or_create(config).await?)
-        .with_parent_monitor(repos_monitor.clone());
+        .with_parent_monitor(repos_monitor.clone())
+        .with_memory_budget(memory.clone())
+        .with_db_tuning(get_default_db_tuning(config).await?);
+
+    let params = match get_test_rng_seed(config).await? {
+        Some(seed) => params.with_rng(StdRng::seed_from_u64(seed)),
+        None => params,
+    };
 
-    let access_secrets = if let Some(share_token) = share_token {
-        share_token.into_secrets()
+    let access_secrets = if let Some(share_token) = &share_token {
+        share_token.secrets().clone()
     } else {
         AccessSecrets::random_write()
     };
@@ -70,6 +89,7 @@ pub async fn create(
     let access = Access::new(local_read_secret, local_write_secret, access_secrets);
 
     let repository = Repository::create(&params, access).await?;
+    repository.record_share_origin(share_token.as_ref()).await?;
 
     let quota = get_default_quota(config).await?;
     repository.set_quota(quota).await?;
@@ -86,10 +106,13 @@ pub async fn open(
     local_secret: Option<LocalSecret>,
     config: &ConfigStore,
     repos_monitor: &StateMonitor,
+    memory: &MemoryBudget,
 ) -> Result<Repository, OpenError> {
     let params = RepositoryParams::new(store)
         .with_device_id(device_id::get_or_create(config).await?)
-        .with_parent_monitor(repos_monitor.clone());
+        .with_parent_monitor(repos_monitor.clone())
+        .with_memory_budget(memory.clone())
+        .with_db_tuning(get_default_db_tuning(config).await?);
 
     let repository = Repository::open(&params, local_secret, AccessMode::Write).await?;
 
@@ -174,6 +197,59 @@ pub async fn get_default_block_expiration(
     }
 }
 
+/// Sets the database tuning of the repositories opened afterwards. `None` resets it to the default
+/// (`DbTuning::mobile()`).
+pub async fn set_default_db_tuning(
+    config: &ConfigStore,
+    value: Option<DbTuning>,
+) -> Result<(), ConfigError> {
+    let entry = config.entry(DEFAULT_DB_TUNING_KEY);
+
+    if let Some(value) = value {
+        entry.set(&value).await?;
+    } else {
+        entry.remove().await?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_default_db_tuning(config: &ConfigStore) -> Result<DbTuning, ConfigError> {
+    match config.entry(DEFAULT_DB_TUNING_KEY).get().await {
+        Ok(value) => Ok(value),
+        Err(ConfigError::NotFound) => Ok(DbTuning::default()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Makes the repositories created afterwards generate their ids (see
+/// `RepositoryParams::with_rng`) from a generator seeded with `value`, so integration tests can be
+/// reproducible. Use a different seed in each process sharing a repository. Opened repositories are
+/// not affected, as reusing the sequence for the same repository would produce colliding ids.
+/// `None` restores the default (random ids).
+pub async fn set_test_rng_seed(
+    config: &ConfigStore,
+    value: Option<u64>,
+) -> Result<(), ConfigError> {
+    let entry = config.entry(TEST_RNG_SEED_KEY);
+
+    if let Some(value) = value {
+        entry.set(&value).await?;
+    } else {
+        entry.remove().await?;
+    }
+
+    Ok(())
+}
+
+async fn get_test_rng_seed(config: &ConfigStore) -> Result<Option<u64>, ConfigError> {
+    match config.entry(TEST_RNG_SEED_KEY).get().await {
+        Ok(value) => Ok(Some(value)),
+        Err(ConfigError::NotFound) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
 /// Create mirrored repository on the cache server
 #[instrument(skip(repository, client_config))]
 pub async fn create_mirror(
@@ -8,7 +8,7 @@ pub use self::{
     socket::{server_connection as socket_server_connection, SocketClient},
 };
 
-use crate::protocol::{Notification, SessionCookie};
+use crate::protocol::{NegotiatedVersion, Notification, SessionCookie};
 use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
@@ -31,6 +31,7 @@ pub trait Handler: Clone + Send + Sync + 'static {
 pub struct SessionContext {
     pub notification_tx: NotificationSender,
     pub session_cookie: SessionCookie,
+    pub protocol_version: NegotiatedVersion,
 }
 
 pub type NotificationSender = mpsc::Sender<(u64, Notification)>;
@@ -2,7 +2,7 @@
 //! clients/servers
 
 use super::{Handler, SessionContext, TransportError};
-use crate::protocol::{ServerMessage, SessionCookie};
+use crate::protocol::{NegotiatedVersion, ServerMessage, SessionCookie};
 use bytes::{Bytes, BytesMut};
 use futures_util::{stream::FuturesUnordered, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
 use serde::{de::DeserializeOwned, Serialize};
@@ -25,6 +25,7 @@ pub mod server_connection {
         let context = SessionContext {
             notification_tx,
             session_cookie,
+            protocol_version: NegotiatedVersion::default(),
         };
 
         let mut request_handlers = FuturesUnordered::new();
@@ -54,11 +55,11 @@ pub mod server_connection {
                     // unwrap is OK because the sender exists at this point.
                     let (id, notification) = notification.unwrap();
                     let message = ServerMessage::<H::Response, H::Error>::notification(notification);
-                    send(&mut socket, id, message).await;
+                    send(&mut socket, id, message, context.protocol_version.named_fields()).await;
                 }
                 Some((id, result)) = request_handlers.next() => {
                     let message = ServerMessage::response(result);
-                    send(&mut socket, id, message).await;
+                    send(&mut socket, id, message, context.protocol_version.named_fields()).await;
                 }
             }
         }
@@ -165,7 +166,7 @@ where
         self.next_message_id = self.next_message_id.wrapping_add(1);
         self.pending_requests.insert(message_id, response_tx);
 
-        if !send(&mut self.socket, message_id, request).await {
+        if !send(&mut self.socket, message_id, request, false).await {
             self.running = false;
         }
     }
@@ -232,7 +233,9 @@ where
     }
 }
 
-async fn send<W, M>(writer: &mut W, id: u64, message: M) -> bool
+// If `named_fields` is true, structs are encoded as maps, otherwise as arrays. The decoder in
+// `receive` accepts both.
+async fn send<W, M>(writer: &mut W, id: u64, message: M, named_fields: bool) -> bool
 where
     W: Sink<Bytes, Error = io::Error> + Unpin,
     M: Serialize,
@@ -242,7 +245,13 @@ where
     let mut buffer = Vec::new();
     buffer.extend(id.to_be_bytes());
 
-    if let Err(error) = rmp_serde::encode::write(&mut buffer, &message) {
+    let result = if named_fields {
+        rmp_serde::encode::write_named(&mut buffer, &message)
+    } else {
+        rmp_serde::encode::write(&mut buffer, &message)
+    };
+
+    if let Err(error) = result {
         tracing::error!(?error, "failed to encode message");
         return false;
     };
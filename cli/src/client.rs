@@ -12,13 +12,17 @@ use std::{
     io,
     path::{Path, PathBuf},
 };
-use tokio::io::{stdin, stdout, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::{
+    io::{stdin, stdout, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    select, signal,
+};
 
 pub(crate) async fn run(
     dirs: Dirs,
     socket: PathBuf,
     log_format: LogFormat,
     log_color: LogColor,
+    json: bool,
     request: Request,
 ) -> Result<()> {
     let _logger = Logger::new(None, None, log_format, log_color)?;
@@ -61,14 +65,74 @@ pub(crate) async fn run(
                 password,
             }
         }
+        Request::Watch { name, interval } => {
+            let result = watch(&client, name, interval, json).await;
+            client.close().await;
+
+            return result;
+        }
         _ => request,
     };
 
-    let response = client.invoke(request).await?;
-    println!("{response}");
-
+    let result = client.invoke(request).await;
     client.close().await;
 
+    print(result, json)
+}
+
+/// Prints the response either in human readable format or as JSON. In the JSON mode the error is
+/// printed to stdout too (as `{"error": "..."}`) so scripts can always parse the output.
+fn print(result: Result<Response, Error>, json: bool) -> Result<()> {
+    match (result, json) {
+        (Ok(response), false) => println!("{response}"),
+        (Ok(response), true) => println!("{}", serde_json::to_string(&response)?),
+        (Err(error), false) => return Err(error.into()),
+        (Err(error), true) => {
+            println!("{}", serde_json::json!({ "error": error.to_string() }));
+            return Err(error.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the sync progress whenever it changes. The server holds each `Watch` request until a
+/// repository changes or `interval` seconds elapse. Runs until interrupted with Ctrl-C. In the JSON
+/// mode, each change is printed as a separate line.
+async fn watch(client: &Client, name: Option<String>, interval: u64, json: bool) -> Result<()> {
+    let mut last = None;
+    let mut request = Request::Progress { name: name.clone() };
+
+    loop {
+        let response = select! {
+            response = client.invoke(request) => response,
+            result = signal::ctrl_c() => {
+                result?;
+                break;
+            }
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => return print(Err(error), json),
+        };
+
+        let output = if json {
+            serde_json::to_string(&response)?
+        } else {
+            response.to_string()
+        };
+
+        if last.as_ref() != Some(&output) {
+            println!("{output}");
+            last = Some(output);
+        }
+
+        request = Request::Watch {
+            name: name.clone(),
+            interval,
+        };
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,195 @@
+//! Support for running the server as a system service (daemon): pidfile, systemd socket
+//! activation and readiness notification.
+
+use anyhow::{format_err, Result};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Pidfile which is removed when dropped.
+pub(crate) struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Creates the pidfile at the given path and writes the pid of the current process into it.
+    /// Fails if the file already exists and the process it refers to is still running.
+    pub fn create(path: PathBuf) -> Result<Self> {
+        if let Some(pid) = read_pid(&path)? {
+            if is_running(pid) {
+                return Err(format_err!(
+                    "server already running (pid: {pid}, pidfile: {})",
+                    path.display()
+                ));
+            }
+
+            tracing::warn!(pid, ?path, "Removing stale pidfile");
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&path, format!("{}\n", process::id()))?;
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            tracing::error!(?error, path = ?self.path, "Failed to remove pidfile");
+        }
+    }
+}
+
+fn read_pid(path: &Path) -> Result<Option<u32>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content.trim().parse().ok()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // A pidfile left behind by a previous instance that happened to have the same pid as us.
+    if pid == process::id() {
+        return false;
+    }
+
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    // SAFETY: Signal 0 performs only the existence and permission checks, nothing is sent.
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+
+    // The process exists but belongs to another user.
+    io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Without a portable way to check it, assume the pidfile is stale. Another running instance would
+// still be detected when binding the local socket.
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+/// Returns the listening socket passed to us by systemd (socket activation), if any.
+///
+/// See `sd_listen_fds(3)`. Only the first passed socket is used, any others are ignored.
+#[cfg(unix)]
+pub(crate) fn activation_listener() -> io::Result<Option<std::os::unix::net::UnixListener>> {
+    use std::{
+        env,
+        os::unix::io::FromRawFd,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    // The first passed file descriptor.
+    const LISTEN_FDS_START: i32 = 3;
+
+    // The descriptor must be taken over at most once. The env variables are not removed because
+    // modifying the environment is not thread-safe. They don't affect child processes anyway
+    // because `LISTEN_PID` wouldn't match their pid.
+    static TAKEN: AtomicBool = AtomicBool::new(false);
+
+    if TAKEN.swap(true, Ordering::AcqRel) {
+        return Ok(None);
+    }
+
+    let pid = env::var("LISTEN_PID").ok();
+    let fds = env::var("LISTEN_FDS").ok();
+
+    if pid.and_then(|pid| pid.parse().ok()) != Some(process::id()) {
+        return Ok(None);
+    }
+
+    match fds.and_then(|fds| fds.parse::<u32>().ok()) {
+        Some(0) | None => return Ok(None),
+        Some(1) => (),
+        Some(n) => tracing::warn!("Received {n} sockets from systemd, using only the first one"),
+    }
+
+    // SAFETY: systemd guarantees the descriptor is open and refers to the listening socket
+    // configured in the `.socket` unit. We take ownership of it here, exactly once (guarded by
+    // `TAKEN` above).
+    let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.set_nonblocking(true)?;
+
+    Ok(Some(listener))
+}
+
+/// Service state notifications to the service manager (see `sd_notify(3)`). No-op when not
+/// running under systemd.
+#[derive(Clone, Copy)]
+pub(crate) enum Notification {
+    Ready,
+    Stopping,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn notify(notification: Notification) {
+    use std::{
+        env,
+        os::{
+            linux::net::SocketAddrExt,
+            unix::net::{SocketAddr, UnixDatagram},
+        },
+    };
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let message = match notification {
+        Notification::Ready => "READY=1",
+        Notification::Stopping => "STOPPING=1",
+    };
+
+    let result = (|| {
+        let socket = UnixDatagram::unbound()?;
+
+        // Names starting with '@' refer to sockets in the abstract namespace.
+        let addr = match path.as_encoded_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+
+        socket.send_to_addr(message.as_bytes(), &addr)
+    })();
+
+    if let Err(error) = result {
+        tracing::error!(?error, ?path, "Failed to notify service manager");
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn notify(_notification: Notification) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn stale_pidfile() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("ouisync.pid");
+
+        // Pid that can't belong to any running process.
+        fs::write(&path, format!("{}\n", u32::MAX)).unwrap();
+
+        let pid_file = PidFile::create(path.clone()).unwrap();
+        assert_eq!(read_pid(&path).unwrap(), Some(process::id()));
+
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
@@ -1,12 +1,17 @@
 use crate::{
-    protocol::{Error, QuotaInfo, Request, Response},
+    protocol::{Error, NetworkStats, QuotaInfo, Request, Response},
     repository::{self, RepositoryHolder, RepositoryName, OPEN_ON_START},
     state::State,
 };
 use async_trait::async_trait;
+use futures_util::future;
 use ouisync_bridge::{network, transport::SessionContext};
-use ouisync_lib::{crypto::Password, LocalSecret, PeerAddr, SetLocalSecret, ShareToken};
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use ouisync_lib::{
+    crypto::Password, network::NatBehavior, LocalSecret, PeerAddr, Progress, SetLocalSecret,
+    ShareToken,
+};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::time;
 
 #[derive(Clone)]
 pub(crate) struct LocalHandler {
@@ -21,6 +26,30 @@ impl LocalHandler {
     pub async fn close(&self) {
         self.state.close().await
     }
+
+    fn find_or_all(&self, name: Option<String>) -> Result<Vec<Arc<RepositoryHolder>>, Error> {
+        if let Some(name) = name {
+            Ok(vec![self.state.repositories.find(&name)?])
+        } else {
+            Ok(self.state.repositories.get_all())
+        }
+    }
+
+    async fn progress(
+        &self,
+        holders: Vec<Arc<RepositoryHolder>>,
+    ) -> Result<BTreeMap<String, Progress>, Error> {
+        let mut progress = BTreeMap::new();
+
+        for holder in holders {
+            progress.insert(
+                holder.name().to_string(),
+                holder.repository.sync_progress().await?,
+            );
+        }
+
+        Ok(progress)
+    }
 }
 
 #[async_trait]
@@ -35,7 +64,13 @@ impl ouisync_bridge::transport::Handler for LocalHandler {
         _context: &SessionContext,
     ) -> Result<Self::Response, Self::Error> {
         match request {
-            Request::Start { .. } => unimplemented!(),
+            Request::Start { .. } => {
+                unimplemented!()
+            }
+            Request::Shutdown => {
+                self.state.request_shutdown();
+                Ok(().into())
+            }
             Request::BindRpc { addrs } => Ok(self
                 .state
                 .rpc_servers
@@ -88,6 +123,7 @@ impl ouisync_bridge::transport::Handler for LocalHandler {
                     share_token,
                     &self.state.config,
                     &self.state.repositories_monitor,
+                    &self.state.memory,
                 )
                 .await?;
 
@@ -131,6 +167,7 @@ impl ouisync_bridge::transport::Handler for LocalHandler {
                     password.map(Password::from).map(LocalSecret::Password),
                     &self.state.config,
                     &self.state.repositories_monitor,
+                    &self.state.memory,
                 )
                 .await?;
 
@@ -313,6 +350,52 @@ impl ouisync_bridge::transport::Handler for LocalHandler {
                 Ok(().into())
             }
             Request::ListPeers => Ok(self.state.network.peer_info_collector().collect().into()),
+            Request::Reconnect => {
+                self.state.network.handle_network_change().await;
+                Ok(().into())
+            }
+            Request::Stats => {
+                let network = &self.state.network;
+
+                Ok(NetworkStats {
+                    traffic: network.traffic_stats(),
+                    connections: network.connection_stats(),
+                    nat_behavior: network.nat_behavior().await.map(nat_behavior_name),
+                    external_addr_v4: network.external_addr_v4().await,
+                    external_addr_v6: network.external_addr_v6().await,
+                    peers: network.peer_info_collector().collect().len(),
+                }
+                .into())
+            }
+            Request::Progress { name } => {
+                let holders = self.find_or_all(name)?;
+                Ok(self.progress(holders).await?.into())
+            }
+            Request::Watch { name, interval } => {
+                if interval == 0 {
+                    return Err(Error::new("interval must be greater than zero"));
+                }
+
+                // Wait until any of the repositories changes (or the interval elapses) and then
+                // return the current progress. The client calls this repeatedly to watch the
+                // progress without busy polling.
+                let holders = self.find_or_all(name)?;
+                let mut rxs: Vec<_> = holders
+                    .iter()
+                    .map(|holder| holder.repository.subscribe())
+                    .collect();
+
+                if !rxs.is_empty() {
+                    time::timeout(
+                        Duration::from_secs(interval),
+                        future::select_all(rxs.iter_mut().map(|rx| Box::pin(rx.recv()))),
+                    )
+                    .await
+                    .ok();
+                }
+
+                Ok(self.progress(holders).await?.into())
+            }
             Request::Dht { name, enabled } => {
                 let holder = self.state.repositories.find(&name)?;
 
@@ -400,6 +483,27 @@ impl ouisync_bridge::transport::Handler for LocalHandler {
                     Ok(Response::BlockExpiration(block_expiration))
                 }
             }
+            Request::DbTuning { remove, preset } => {
+                if remove || preset.is_some() {
+                    ouisync_bridge::repository::set_default_db_tuning(&self.state.config, preset)
+                        .await?;
+                    Ok(().into())
+                } else {
+                    let tuning =
+                        ouisync_bridge::repository::get_default_db_tuning(&self.state.config)
+                            .await?;
+                    Ok(tuning.to_string().into())
+                }
+            }
         }
     }
 }
+
+fn nat_behavior_name(nat: NatBehavior) -> String {
+    match nat {
+        NatBehavior::EndpointIndependent => "endpoint independent",
+        NatBehavior::AddressDependent => "address dependent",
+        NatBehavior::AddressAndPortDependent => "address and port dependent",
+    }
+    .to_owned()
+}
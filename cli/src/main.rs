@@ -1,4 +1,5 @@
 mod client;
+mod daemon;
 mod geo_ip;
 mod handler;
 mod metrics;
@@ -11,9 +12,10 @@ mod transport;
 mod utils;
 
 use anyhow::Result;
-use clap::Parser;
-use options::Options;
+use clap::{CommandFactory, Parser};
+use options::{Command, Options};
 use protocol::Request;
+use std::io;
 
 pub(crate) const APP_NAME: &str = "ouisync";
 pub(crate) const DB_EXTENSION: &str = "ouisyncdb";
@@ -22,22 +24,31 @@ pub(crate) const DB_EXTENSION: &str = "ouisyncdb";
 async fn main() -> Result<()> {
     let options = Options::parse();
 
-    if let Request::Start = &options.request {
-        server::run(
-            options.dirs,
-            options.socket,
-            options.log_format,
-            options.log_color,
-        )
-        .await
-    } else {
-        client::run(
-            options.dirs,
-            options.socket,
-            options.log_format,
-            options.log_color,
-            options.request,
-        )
-        .await
+    match options.command {
+        Command::Request(Request::Start { pid_file }) => {
+            server::run(
+                options.dirs,
+                options.socket,
+                pid_file,
+                options.log_format,
+                options.log_color,
+            )
+            .await
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Options::command(), APP_NAME, &mut io::stdout());
+            Ok(())
+        }
+        Command::Request(request) => {
+            client::run(
+                options.dirs,
+                options.socket,
+                options.log_format,
+                options.log_color,
+                options.json,
+                request,
+            )
+            .await
+        }
     }
 }
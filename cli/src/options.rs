@@ -1,5 +1,6 @@
 use crate::{protocol::Request, APP_NAME};
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
 use ouisync_bridge::logger::{LogColor, LogFormat};
 use std::{env, path::PathBuf};
 
@@ -27,8 +28,25 @@ pub(crate) struct Options {
     #[arg(long, default_value_t)]
     pub log_color: LogColor,
 
+    /// Print the responses (and errors) as JSON instead of in human readable format
+    #[arg(long, global = true)]
+    pub json: bool,
+
     #[command(subcommand)]
-    pub request: Request,
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Print the shell completion script
+    ///
+    /// For example, for bash: `ouisync completions bash > /etc/bash_completion.d/ouisync`
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    #[command(flatten)]
+    Request(Request),
 }
 
 #[derive(Args, Debug)]
@@ -1,7 +1,16 @@
 use clap::{builder::BoolishValueParser, Subcommand};
-use ouisync_lib::{AccessMode, PeerAddr, PeerInfo, StorageSize};
+use ouisync_lib::{
+    network::{ConnectionStats, TrafficStats},
+    AccessMode, DbTuning, PeerAddr, PeerInfo, Progress, StorageSize,
+};
 use serde::{Deserialize, Serialize};
-use std::{fmt, io, net::SocketAddr, path::PathBuf, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    net::{SocketAddr, SocketAddrV4, SocketAddrV6},
+    path::PathBuf,
+    time::Duration,
+};
 
 use crate::repository::{FindError, InvalidRepositoryName};
 
@@ -9,7 +18,17 @@ use crate::repository::{FindError, InvalidRepositoryName};
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum Request {
     /// Start the server
-    Start,
+    ///
+    /// The server runs in the foreground until it receives SIGINT, SIGTERM or the `shutdown`
+    /// command. When started by systemd, it uses the socket passed to it (socket activation)
+    /// instead of binding its own and notifies systemd when it's ready (`Type=notify`).
+    Start {
+        /// Write the pid of the server process to this file (removed on exit)
+        #[arg(long, value_name = "PATH")]
+        pid_file: Option<PathBuf>,
+    },
+    /// Gracefully shut down the server
+    Shutdown,
     /// Bind the remote API to the specified addresses.
     ///
     /// Overwrites any previously specified addresses.
@@ -157,6 +176,31 @@ pub(crate) enum Request {
     },
     /// List all known peers
     ListPeers,
+    /// Rebind the listeners and reconnect to all peers (e.g., after switching networks)
+    Reconnect,
+    /// Print network statistics (traffic, NAT traversal, external addresses, ...)
+    Stats,
+    /// Print sync progress
+    Progress {
+        /// Name of the repository. If omitted, prints the progress of all open repositories.
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+    /// Keep printing the sync progress whenever it changes, until interrupted
+    Watch {
+        /// Name of the repository. If omitted, watches all open repositories.
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Maximum time to wait for a change before checking the progress again (in seconds)
+        #[arg(
+            short,
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u64).range(1..),
+        )]
+        interval: u64,
+    },
     /// Enable or disable DHT
     Dht {
         #[arg(short = 'n', long)]
@@ -220,6 +264,16 @@ pub(crate) enum Request {
         /// Set duration after which blocks are removed if not used (in seconds).
         value: Option<u64>,
     },
+    /// Get or set the database tuning of the repositories. Applies to the repositories opened
+    /// afterwards.
+    DbTuning {
+        /// Reset to the default preset ("mobile")
+        #[arg(short, long, conflicts_with = "preset")]
+        remove: bool,
+
+        /// Preset to set ("mobile", "desktop" or "server"). If omitted, prints the current tuning.
+        preset: Option<DbTuning>,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -233,6 +287,8 @@ pub(crate) enum Response {
     StorageSize(StorageSize),
     QuotaInfo(QuotaInfo),
     BlockExpiration(Option<Duration>),
+    Progress(BTreeMap<String, Progress>),
+    NetworkStats(NetworkStats),
 }
 
 impl From<()> for Response {
@@ -283,6 +339,18 @@ impl From<QuotaInfo> for Response {
     }
 }
 
+impl From<BTreeMap<String, Progress>> for Response {
+    fn from(value: BTreeMap<String, Progress>) -> Self {
+        Self::Progress(value)
+    }
+}
+
+impl From<NetworkStats> for Response {
+    fn from(value: NetworkStats) -> Self {
+        Self::NetworkStats(value)
+    }
+}
+
 impl fmt::Display for Response {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -298,7 +366,21 @@ impl fmt::Display for Response {
             }
             Self::PeerInfo(value) => {
                 for peer in value {
-                    writeln!(f, "{} ({:?}, {:?})", peer.addr, peer.source, peer.state)?;
+                    write!(f, "{} ({:?}, {:?}", peer.addr, peer.source, peer.state)?;
+
+                    if let Some(connection_type) = peer.connection_type {
+                        write!(f, ", {connection_type:?}")?;
+                    }
+
+                    write!(
+                        f,
+                        ", transport encryption: {:?}, secure links: {} ({})",
+                        peer.encryption.transport,
+                        peer.encryption.secure_links,
+                        peer.encryption.link_protocol,
+                    )?;
+
+                    writeln!(f, ")")?;
                 }
 
                 Ok(())
@@ -313,6 +395,14 @@ impl fmt::Display for Response {
             Self::StorageSize(value) => write!(f, "{value}"),
             Self::QuotaInfo(info) => write!(f, "{info}"),
             Self::BlockExpiration(info) => write!(f, "{info:?}"),
+            Self::Progress(value) => {
+                for (name, progress) in value {
+                    writeln!(f, "{name}: {progress} ({:.1})", progress.percent())?;
+                }
+
+                Ok(())
+            }
+            Self::NetworkStats(stats) => write!(f, "{stats}"),
         }
     }
 }
@@ -400,6 +490,67 @@ impl fmt::Display for QuotaInfo {
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub(crate) struct NetworkStats {
+    pub traffic: TrafficStats,
+    pub connections: ConnectionStats,
+    pub nat_behavior: Option<String>,
+    pub external_addr_v4: Option<SocketAddrV4>,
+    pub external_addr_v6: Option<SocketAddrV6>,
+    pub peers: usize,
+}
+
+impl fmt::Display for NetworkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "sent:          {}",
+            StorageSize::from_bytes(self.traffic.send)
+        )?;
+        writeln!(
+            f,
+            "received:      {}",
+            StorageSize::from_bytes(self.traffic.recv)
+        )?;
+        writeln!(f, "peers:         {}", self.peers)?;
+        writeln!(
+            f,
+            "NAT behavior:  {}",
+            OptionDisplay(self.nat_behavior.as_deref())
+        )?;
+        writeln!(f, "external IPv4: {}", OptionDisplay(self.external_addr_v4))?;
+        writeln!(f, "external IPv6: {}", OptionDisplay(self.external_addr_v6))?;
+
+        for (nat, outcomes) in &self.connections.0 {
+            write!(
+                f,
+                "connections ({nat}): direct: {}, hole punched: {}, relayed: {}, failed: {}",
+                outcomes.direct, outcomes.hole_punched, outcomes.relayed, outcomes.failed
+            )?;
+
+            if let Some(rate) = outcomes.success_rate() {
+                write!(f, ", success rate: {:.1}%", rate * 100.0)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Displays `Some(value)` as `value` and `None` as `-`.
+struct OptionDisplay<T>(Option<T>);
+
+impl<T: fmt::Display> fmt::Display for OptionDisplay<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(value) => write!(f, "{value}"),
+            None => write!(f, "-"),
+        }
+    }
+}
+
 fn percent(num: u64, den: u64) -> f64 {
     if den > 0 {
         100.0 * num as f64 / den as f64
@@ -4,7 +4,7 @@ use camino::Utf8Path;
 use ouisync_bridge::{config::ConfigStore, protocol::remote::v1, transport::RemoteClient};
 use ouisync_lib::{
     network::{Network, Registration},
-    Repository,
+    MemoryBudget, Repository,
 };
 use ouisync_vfs::MountGuard;
 use state_monitor::StateMonitor;
@@ -418,6 +418,7 @@ pub(crate) async fn find_all(
     network: &Network,
     config: &ConfigStore,
     monitor: &StateMonitor,
+    memory: &MemoryBudget,
 ) -> RepositoryMap {
     let repositories = RepositoryMap::new();
 
@@ -446,15 +447,21 @@ pub(crate) async fn find_all(
             continue;
         }
 
-        let repository =
-            match ouisync_bridge::repository::open(path.to_path_buf(), None, config, monitor).await
-            {
-                Ok(repository) => repository,
-                Err(error) => {
-                    tracing::error!(?error, ?path, "Failed to open repository");
-                    continue;
-                }
-            };
+        let repository = match ouisync_bridge::repository::open(
+            path.to_path_buf(),
+            None,
+            config,
+            monitor,
+            memory,
+        )
+        .await
+        {
+            Ok(repository) => repository,
+            Err(error) => {
+                tracing::error!(?error, ?path, "Failed to open repository");
+                continue;
+            }
+        };
 
         let metadata = repository.metadata();
 
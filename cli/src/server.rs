@@ -1,4 +1,5 @@
 use crate::{
+    daemon::{self, Notification, PidFile},
     handler::{local::LocalHandler, remote::RemoteHandler},
     options::Dirs,
     state::State,
@@ -18,36 +19,59 @@ use std::{
     path::PathBuf,
     sync::{Arc, Mutex},
 };
-use tokio::task;
+use tokio::{select, task};
 
 pub(crate) async fn run(
     dirs: Dirs,
     socket: PathBuf,
+    pid_file: Option<PathBuf>,
     log_format: LogFormat,
     log_color: LogColor,
 ) -> Result<()> {
     let monitor = StateMonitor::make_root();
     let _logger = Logger::new(None, Some(monitor.clone()), log_format, log_color)?;
 
+    let _pid_file = pid_file.map(PidFile::create).transpose()?;
+
     let state = State::init(&dirs, monitor).await?;
-    let server = LocalServer::bind(socket.as_path())?;
+    let server = bind(socket)?;
     let handle = task::spawn(server.run(LocalHandler::new(state.clone())));
 
-    terminated().await?;
+    daemon::notify(Notification::Ready);
 
-    handle.abort();
+    select! {
+        result = terminated() => result?,
+        _ = state.shutdown_requested() => (),
+    }
+
+    tracing::info!("Shutting down");
+    daemon::notify(Notification::Stopping);
+
+    // Keep accepting requests while closing so the response to `shutdown` gets delivered.
     state.close().await;
+    handle.abort();
 
     Ok(())
 }
 
+#[cfg(unix)]
+fn bind(socket: PathBuf) -> io::Result<LocalServer> {
+    if let Some(listener) = daemon::activation_listener()? {
+        LocalServer::from_std(listener)
+    } else {
+        LocalServer::bind(socket.as_path())
+    }
+}
+
+#[cfg(not(unix))]
+fn bind(socket: PathBuf) -> io::Result<LocalServer> {
+    LocalServer::bind(socket.as_path())
+}
+
 // Wait until the program is terminated.
 #[cfg(unix)]
 async fn terminated() -> io::Result<()> {
-    use tokio::{
-        select,
-        signal::unix::{signal, SignalKind},
-    };
+    use tokio::signal::unix::{signal, SignalKind};
 
     // Wait for SIGINT or SIGTERM
     let mut interrupt = signal(SignalKind::interrupt())?;
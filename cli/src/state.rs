@@ -8,17 +8,21 @@ use anyhow::{format_err, Result};
 use futures_util::future;
 use ouisync_bridge::{
     config::ConfigStore,
-    network::{self, NetworkDefaults},
+    network::{self, NetworkDefaults, UserProvidedPeersWatcher},
     transport,
 };
-use ouisync_lib::network::Network;
+use ouisync_lib::{network::Network, MemoryBudget};
+use scoped_task::ScopedJoinHandle;
 use state_monitor::StateMonitor;
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
-use tokio::{sync::OnceCell, time};
+use tokio::{
+    sync::{Notify, OnceCell},
+    time,
+};
 
 pub(crate) struct State {
     pub config: ConfigStore,
@@ -27,10 +31,13 @@ pub(crate) struct State {
     pub network: Network,
     pub repositories: RepositoryMap,
     pub repositories_monitor: StateMonitor,
+    pub memory: MemoryBudget,
     pub rpc_servers: ServerContainer,
     pub metrics_server: MetricsServer,
     pub server_config: OnceCell<Arc<rustls::ServerConfig>>,
     pub client_config: OnceCell<Arc<rustls::ClientConfig>>,
+    peers_watcher: OnceCell<ScopedJoinHandle<()>>,
+    shutdown: Notify,
 }
 
 impl State {
@@ -53,8 +60,9 @@ impl State {
         .await;
 
         let repositories_monitor = monitor.make_child("Repositories");
+        let memory = MemoryBudget::new(monitor.make_child("Memory"));
         let repositories =
-            repository::find_all(dirs, &network, &config, &repositories_monitor).await;
+            repository::find_all(dirs, &network, &config, &repositories_monitor, &memory).await;
 
         let state = Self {
             config,
@@ -63,19 +71,55 @@ impl State {
             network,
             repositories,
             repositories_monitor,
+            memory,
             rpc_servers: ServerContainer::new(),
             metrics_server: MetricsServer::new(),
             server_config: OnceCell::new(),
             client_config: OnceCell::new(),
+            peers_watcher: OnceCell::new(),
+            shutdown: Notify::new(),
         };
         let state = Arc::new(state);
 
+        state.watch_user_provided_peers().await;
+
         state.rpc_servers.init(state.clone()).await?;
         state.metrics_server.init(&state).await?;
 
         Ok(state)
     }
 
+    /// Starts applying the user provided peers added or removed by other processes sharing the
+    /// same config dir (e.g., the app). See `UserProvidedPeersWatcher`.
+    async fn watch_user_provided_peers(self: &Arc<Self>) {
+        self.peers_watcher
+            .get_or_init(|| async {
+                let mut watcher = UserProvidedPeersWatcher::new(self.config.clone()).await;
+                let state = Arc::downgrade(self);
+
+                scoped_task::spawn(async move {
+                    while let Some(change) = watcher.changed().await {
+                        let Some(state) = state.upgrade() else {
+                            break;
+                        };
+
+                        change.apply(&state.network);
+                    }
+                })
+            })
+            .await;
+    }
+
+    /// Asks the server to shut down (see `shutdown_requested`).
+    pub fn request_shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Completes when `request_shutdown` has been called.
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await
+    }
+
     pub async fn close(&self) {
         // Kill RPC servers
         self.rpc_servers.close();
@@ -97,6 +141,8 @@ impl State {
         ));
 
         let shutdown_network = async move {
+            network::save_connection_stats(&self.network, &self.config).await;
+
             time::timeout(Duration::from_secs(1), self.network.shutdown())
                 .await
                 .ok();
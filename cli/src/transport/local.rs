@@ -4,6 +4,8 @@ use crate::{
     handler::local::LocalHandler,
     protocol::{Error, Request, Response},
 };
+use bytes::{Bytes, BytesMut};
+use futures_util::{Sink, Stream};
 use interprocess::local_socket::{
     tokio::{LocalSocketListener, LocalSocketStream},
     ToLocalSocketName,
@@ -21,10 +23,17 @@ use tokio_util::{
 use tracing::Instrument;
 
 pub(crate) struct LocalServer {
-    listener: LocalSocketListener,
+    listener: Listener,
     path: Option<PathBuf>,
 }
 
+enum Listener {
+    Bound(LocalSocketListener),
+    // Listener passed to us by the service manager (socket activation).
+    #[cfg(unix)]
+    Activated(tokio::net::UnixListener),
+}
+
 impl LocalServer {
     pub fn bind<'a>(name: impl ToLocalSocketName<'a> + Clone) -> io::Result<Self> {
         let orig_name = name.clone();
@@ -51,34 +60,67 @@ impl LocalServer {
             name.inner().to_string_lossy()
         );
 
-        Ok(Self { listener, path })
+        Ok(Self {
+            listener: Listener::Bound(listener),
+            path,
+        })
+    }
+
+    /// Creates the server from an already bound listener (e.g., passed to us by systemd). The
+    /// socket file is not removed when the server is dropped as it's owned by whoever created
+    /// the listener.
+    #[cfg(unix)]
+    pub fn from_std(listener: std::os::unix::net::UnixListener) -> io::Result<Self> {
+        let listener = tokio::net::UnixListener::from_std(listener)?;
+
+        tracing::info!(
+            "Local API server listening on {:?} (socket activation)",
+            listener.local_addr()?
+        );
+
+        Ok(Self {
+            listener: Listener::Activated(listener),
+            path: None,
+        })
     }
 
     pub async fn run(self, handler: LocalHandler) {
         let mut connections = JoinSet::new();
 
         loop {
-            match self.listener.accept().await {
-                Ok(socket) => {
-                    let socket = make_socket(socket);
-                    connections.spawn(
-                        socket_server_connection::run(
-                            socket,
-                            handler.clone(),
-                            SessionCookie::DUMMY,
-                        )
-                        .instrument(tracing::info_span!("local client")),
-                    );
-                }
-                Err(error) => {
-                    tracing::error!(?error, "Failed to accept client");
-                    break;
-                }
+            let result = match &self.listener {
+                Listener::Bound(listener) => listener.accept().await.map(|socket| {
+                    spawn(&mut connections, make_socket(socket), handler.clone());
+                }),
+                #[cfg(unix)]
+                Listener::Activated(listener) => listener.accept().await.map(|(socket, _)| {
+                    let socket = Framed::new(socket, LengthDelimitedCodec::new());
+                    spawn(&mut connections, socket, handler.clone());
+                }),
+            };
+
+            if let Err(error) = result {
+                tracing::error!(?error, "Failed to accept client");
+                break;
             }
         }
     }
 }
 
+fn spawn<S>(connections: &mut JoinSet<()>, socket: S, handler: LocalHandler)
+where
+    S: Stream<Item = io::Result<BytesMut>>
+        + Sink<Bytes, Error = io::Error>
+        + Unpin
+        + Send
+        + 'static,
+{
+    connections.spawn(
+        socket_server_connection::run(socket, handler, SessionCookie::DUMMY)
+            .instrument(tracing::info_span!("local client")),
+    );
+}
+
 impl Drop for LocalServer {
     fn drop(&mut self) {
         if let Some(path) = &self.path {
@@ -23,6 +23,7 @@ impl NativeClient {
         let context = SessionContext {
             notification_tx,
             session_cookie: SessionCookie::DUMMY,
+            protocol_version: Default::default(),
         };
 
         Self { handler, context }
@@ -0,0 +1,80 @@
+//! Cache of the entry type lookups. The UI queries the types of the same paths over and over, so
+//! this avoids hitting the database every time.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use deadlock::BlockingMutex;
+use ouisync_lib::{AccessMode, EntryType, Event, Repository};
+use std::collections::HashMap;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+// Max number of cached paths. When exceeded, the whole cache is cleared.
+const CAPACITY: usize = 1024;
+
+pub(crate) struct EntryTypeCache {
+    inner: BlockingMutex<Inner>,
+}
+
+impl EntryTypeCache {
+    pub fn new(repository: &Repository) -> Self {
+        Self {
+            inner: BlockingMutex::new(Inner {
+                event_rx: repository.subscribe(),
+                access_mode: repository.access_mode(),
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the cached type of the entry at `path` (`Some(None)` means the entry doesn't exist)
+    /// or `None` if not cached.
+    pub fn get(&self, repository: &Repository, path: &Utf8Path) -> Option<Option<EntryType>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.invalidate(repository);
+        inner.entries.get(path).copied()
+    }
+
+    pub fn insert(&self, path: Utf8PathBuf, entry_type: Option<EntryType>) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.entries.len() >= CAPACITY {
+            inner.entries.clear();
+        }
+
+        inner.entries.insert(path, entry_type);
+    }
+}
+
+struct Inner {
+    event_rx: broadcast::Receiver<Event>,
+    access_mode: AccessMode,
+    entries: HashMap<Utf8PathBuf, Option<EntryType>>,
+}
+
+impl Inner {
+    // Clears the cache if the repository changed since the last call. Any event means potential
+    // change and so does change of the access mode (e.g., a locked repository becoming readable).
+    fn invalidate(&mut self, repository: &Repository) {
+        let mut changed = false;
+
+        loop {
+            match self.event_rx.try_recv() {
+                Ok(_) | Err(TryRecvError::Lagged(_)) => changed = true,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => {
+                    changed = true;
+                    break;
+                }
+            }
+        }
+
+        let access_mode = repository.access_mode();
+        if access_mode != self.access_mode {
+            self.access_mode = access_mode;
+            changed = true;
+        }
+
+        if changed {
+            self.entries.clear();
+        }
+    }
+}
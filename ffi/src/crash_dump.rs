@@ -0,0 +1,389 @@
+//! Compact dump of the session state (the open repositories, the requests in flight, the peers
+//! and the recently handled requests) written into `crash_dump.txt` in the config dir when the
+//! process panics or is killed by a fatal signal, so the crash reports from the field come with
+//! some context and not just a stack trace. Disabled unless enabled with
+//! `SessionSetCrashDumpEnabled`.
+//!
+//! Nothing can be locked or allocated in a signal handler, so the dump is rendered ahead of time
+//! (every `REFRESH_INTERVAL`) into a static buffer and the handler only writes that buffer out
+//! using raw syscalls.
+
+use crate::state::State;
+use ouisync_bridge::config::{ConfigError, ConfigKey};
+use scoped_task::ScopedJoinHandle;
+use std::{
+    cell::UnsafeCell,
+    ffi::CString,
+    fmt::Write as _,
+    os::raw::c_char,
+    panic, ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+        Arc, Mutex, Once, PoisonError, Weak,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time;
+
+const ENABLED_KEY: ConfigKey<bool> = ConfigKey::new(
+    "crash_dump_enabled",
+    "Write a dump of the session state into the config dir when the app crashes",
+);
+
+const FILE_NAME: &str = "crash_dump.txt";
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+const CAPACITY: usize = 64 * 1024;
+
+static DUMP: Dump = Dump::new();
+// Nul terminated path of the dump file, null while disabled.
+static PATH: AtomicPtr<c_char> = AtomicPtr::new(ptr::null_mut());
+// Set once a panic has been dumped, so the `SIGABRT` that might follow doesn't overwrite it.
+static PANICKED: AtomicBool = AtomicBool::new(false);
+static REFRESHER: Mutex<Option<ScopedJoinHandle<()>>> = Mutex::new(None);
+static INSTALL: Once = Once::new();
+
+/// Enables or disables the crash dump and persists the setting in the config of `state`.
+pub(crate) async fn set_enabled(state: &Arc<State>, enabled: bool) -> Result<(), ConfigError> {
+    state.config.entry(ENABLED_KEY).set(&enabled).await?;
+    apply(state, enabled);
+
+    Ok(())
+}
+
+/// Applies the persisted setting. Called when the session is created.
+pub(crate) async fn restore(state: Arc<State>) {
+    match state.config.entry(ENABLED_KEY).get().await {
+        Ok(enabled) => apply(&state, enabled),
+        Err(ConfigError::NotFound) => (),
+        Err(error) => tracing::warn!(?error, "Failed to load the crash dump setting"),
+    }
+}
+
+fn apply(state: &Arc<State>, enabled: bool) {
+    let mut refresher = REFRESHER.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if !enabled {
+        PATH.store(ptr::null_mut(), Ordering::Release);
+        *refresher = None;
+        return;
+    }
+
+    let path = state.config.dir().join(FILE_NAME);
+    let Some(path) = path.to_str().and_then(|path| CString::new(path).ok()) else {
+        tracing::warn!(?path, "Crash dump path not supported");
+        return;
+    };
+
+    DUMP.store(render(state).as_bytes());
+
+    // The previous path is leaked because the crash handler might be using it right now. It's
+    // replaced only when the dump is re-enabled so this is negligible.
+    PATH.store(path.into_raw(), Ordering::Release);
+
+    *refresher = Some(scoped_task::spawn(refresh(Arc::downgrade(state))));
+
+    INSTALL.call_once(install);
+}
+
+async fn refresh(state: Weak<State>) {
+    loop {
+        time::sleep(REFRESH_INTERVAL).await;
+
+        let Some(state) = state.upgrade() else {
+            break;
+        };
+
+        DUMP.store(render(&state).as_bytes());
+    }
+}
+
+fn render(state: &State) -> String {
+    let mut out = String::new();
+
+    // Writing into a `String` never fails.
+    let _ = writeln!(out, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        out,
+        "time: {}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0)
+    );
+
+    let _ = writeln!(out, "\nrepositories:");
+    for holder in state.repositories.list() {
+        let _ = writeln!(
+            out,
+            "  {} {:?} events: {}",
+            holder.store_path.display(),
+            holder.repository.access_mode(),
+            holder.event_log.cursor(),
+        );
+    }
+
+    let _ = writeln!(out, "\nrequests in flight:");
+    for (id, name, path, elapsed) in state.request_metrics.in_flight() {
+        let _ = writeln!(
+            out,
+            "  #{id} {name} {} {}ms",
+            path.as_deref().unwrap_or("-"),
+            elapsed.as_millis()
+        );
+    }
+
+    let _ = writeln!(out, "\npeers:");
+    for peer in state.network.peer_info_collector().collect() {
+        let _ = writeln!(out, "  {} {:?} {:?}", peer.addr, peer.source, peer.state);
+    }
+
+    let _ = writeln!(out, "\nrecent requests:");
+    for op in state.request_metrics.recent_ops() {
+        let _ = writeln!(
+            out,
+            "  #{} {} {} {}ms{}",
+            op.id,
+            op.name,
+            op.path.as_deref().unwrap_or("-"),
+            op.duration,
+            if op.success { "" } else { " (failed)" }
+        );
+    }
+
+    out
+}
+
+fn install() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        if !PATH.load(Ordering::Acquire).is_null() {
+            PANICKED.store(true, Ordering::Release);
+            write_out(format!("panic: {info}\n\n").as_bytes(), true);
+        }
+
+        default_hook(info);
+    }));
+
+    #[cfg(unix)]
+    signal::install();
+}
+
+// Writes `header` and (if `with_dump`) the last rendered dump into the dump file, replacing its
+// previous content if `with_dump` or appending otherwise. Async-signal-safe.
+#[cfg(unix)]
+fn write_out(header: &[u8], with_dump: bool) {
+    let path = PATH.load(Ordering::Acquire);
+
+    if path.is_null() {
+        return;
+    }
+
+    let flags = libc::O_WRONLY
+        | libc::O_CREAT
+        | libc::O_CLOEXEC
+        | if with_dump {
+            libc::O_TRUNC
+        } else {
+            libc::O_APPEND
+        };
+
+    // SAFETY: `path` is a valid nul terminated string which is never freed.
+    unsafe {
+        let fd = libc::open(path, flags, 0o600 as libc::c_uint);
+
+        if fd < 0 {
+            return;
+        }
+
+        write_all(fd, header);
+
+        if with_dump {
+            write_all(fd, DUMP.load());
+        }
+
+        libc::close(fd);
+    }
+}
+
+#[cfg(unix)]
+unsafe fn write_all(fd: libc::c_int, mut buffer: &[u8]) {
+    while !buffer.is_empty() {
+        let len = libc::write(fd, buffer.as_ptr().cast(), buffer.len());
+
+        if len <= 0 {
+            break;
+        }
+
+        buffer = &buffer[len as usize..];
+    }
+}
+
+// Only panics are dumped here, so no need to be async-signal-safe.
+#[cfg(not(unix))]
+fn write_out(header: &[u8], with_dump: bool) {
+    use std::{ffi::CStr, fs::OpenOptions, io::Write};
+
+    let path = PATH.load(Ordering::Acquire);
+
+    if path.is_null() {
+        return;
+    }
+
+    // SAFETY: `path` is a valid nul terminated string which is never freed.
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return;
+    };
+
+    let Ok(mut file) = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(with_dump)
+        .append(!with_dump)
+        .open(path)
+    else {
+        return;
+    };
+
+    let _ = file.write_all(header);
+
+    if with_dump {
+        let _ = file.write_all(DUMP.load());
+    }
+}
+
+#[cfg(unix)]
+mod signal {
+    use std::{cell::UnsafeCell, mem::MaybeUninit, ptr, sync::atomic::Ordering};
+
+    const SIGNALS: [(libc::c_int, &[u8]); 5] = [
+        (libc::SIGSEGV, b"signal: SIGSEGV\n\n"),
+        (libc::SIGBUS, b"signal: SIGBUS\n\n"),
+        (libc::SIGILL, b"signal: SIGILL\n\n"),
+        (libc::SIGFPE, b"signal: SIGFPE\n\n"),
+        (libc::SIGABRT, b"signal: SIGABRT\n\n"),
+    ];
+
+    // Actions that were installed before ours, restored when the signal arrives. Written only by
+    // `install` (which runs once, before the handler can be invoked).
+    struct Previous(UnsafeCell<[MaybeUninit<libc::sigaction>; SIGNALS.len()]>);
+
+    unsafe impl Sync for Previous {}
+
+    static PREVIOUS: Previous = Previous(UnsafeCell::new([MaybeUninit::uninit(); SIGNALS.len()]));
+
+    pub(super) fn install() {
+        for (index, (signal, _)) in SIGNALS.iter().enumerate() {
+            // SAFETY: the action is fully initialized and `PREVIOUS` is not accessed concurrently
+            // (see its docs).
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = handle as usize;
+                // `SA_ONSTACK` to be able to handle also stack overflows.
+                action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                let previous = (*PREVIOUS.0.get())[index].as_mut_ptr();
+                libc::sigaction(*signal, &action, previous);
+            }
+        }
+    }
+
+    extern "C" fn handle(
+        signal: libc::c_int,
+        _info: *mut libc::siginfo_t,
+        _context: *mut libc::c_void,
+    ) {
+        let Some(index) = SIGNALS.iter().position(|(s, _)| *s == signal) else {
+            return;
+        };
+
+        let header = SIGNALS[index].1;
+
+        // Keep the dump of the panic that likely caused this signal and only append to it.
+        super::write_out(header, !super::PANICKED.load(Ordering::Acquire));
+
+        // Restore the previous action and raise the signal again. It's blocked until this handler
+        // returns, then it's handled the same way as if we weren't here.
+        //
+        // SAFETY: `PREVIOUS` has been initialized by `install`.
+        unsafe {
+            let previous = (*PREVIOUS.0.get())[index].as_ptr();
+            libc::sigaction(signal, previous, ptr::null_mut());
+            libc::raise(signal);
+        }
+    }
+}
+
+// Double buffer: the dump is rendered into the inactive half which is then made the active one,
+// so the crash handler always sees a complete dump (unless the dump gets replaced twice while the
+// handler is writing it out, which can't happen in practice with `REFRESH_INTERVAL` between the
+// replacements).
+struct Dump {
+    buffers: [UnsafeCell<[u8; CAPACITY]>; 2],
+    lens: [AtomicUsize; 2],
+    active: AtomicUsize,
+    // Serializes the writers.
+    lock: Mutex<()>,
+}
+
+unsafe impl Sync for Dump {}
+
+impl Dump {
+    const fn new() -> Self {
+        Self {
+            buffers: [
+                UnsafeCell::new([0; CAPACITY]),
+                UnsafeCell::new([0; CAPACITY]),
+            ],
+            lens: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            active: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Replaces the dump with `content`, truncated to `CAPACITY`.
+    fn store(&self, content: &[u8]) {
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let index = 1 - self.active.load(Ordering::Acquire);
+        let len = content.len().min(CAPACITY);
+
+        // SAFETY: the inactive buffer is accessed only by the writer holding the lock.
+        unsafe {
+            (*self.buffers[index].get())[..len].copy_from_slice(&content[..len]);
+        }
+
+        self.lens[index].store(len, Ordering::Release);
+        self.active.store(index, Ordering::Release);
+    }
+
+    /// Returns the current dump. Async-signal-safe.
+    fn load(&self) -> &[u8] {
+        let index = self.active.load(Ordering::Acquire);
+        let len = self.lens[index].load(Ordering::Acquire);
+
+        // SAFETY: the active buffer is not written to (see the type docs).
+        unsafe { &(*self.buffers[index].get())[..len] }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_store_and_load() {
+        let dump = Box::new(Dump::new());
+        assert!(dump.load().is_empty());
+
+        dump.store(b"first");
+        assert_eq!(dump.load(), b"first");
+
+        dump.store(b"second");
+        assert_eq!(dump.load(), b"second");
+
+        dump.store(&vec![1; CAPACITY + 1]);
+        assert_eq!(dump.load().len(), CAPACITY);
+    }
+}
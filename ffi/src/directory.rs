@@ -1,6 +1,10 @@
-use crate::{error::Error, repository::RepositoryHandle, state::State};
+use crate::{
+    error::Error, operation::ProgressReporter, repository::RepositoryHandle, state::State,
+};
 use camino::Utf8PathBuf;
+use ouisync_lib::{EntryCursor, EntryType};
 use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // Currently this is only a read-only snapshot of a directory.
 #[derive(Eq, PartialEq, Serialize, Deserialize)]
@@ -11,6 +15,21 @@ pub(crate) struct Directory(Vec<DirEntry>);
 pub(crate) struct DirEntry {
     pub name: String,
     pub entry_type: u8,
+    /// Stable id of the entry (see `ouisync_lib::EntryRef::id`). Zero in the joint view where the
+    /// entries come from different repositories.
+    pub id: u128,
+    /// Creation and modification time of the entry in milliseconds since the Unix epoch. Zero if
+    /// not known, and in the joint view.
+    pub created: u64,
+    pub modified: u64,
+}
+
+/// One page of a directory listing (see `read`).
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DirectoryPage {
+    pub entries: Vec<DirEntry>,
+    /// Cursor to continue the listing with or `None` if this is the last page.
+    pub next: Option<EntryCursor>,
 }
 
 pub(crate) async fn create(
@@ -40,6 +59,64 @@ pub(crate) async fn open(
         .map(|entry| DirEntry {
             name: entry.unique_name().into_owned(),
             entry_type: entry.entry_type().into(),
+            id: entry.id(),
+            created: to_millis(entry.created()),
+            modified: to_millis(entry.modified()),
+        })
+        .collect();
+
+    Ok(Directory(entries))
+}
+
+/// Reads at most `limit` entries of the directory following the `after` cursor. Unlike `open`
+/// this allows listing large directories in chunks. The cursor is keyed by the entry name so the
+/// directory can change between the calls without the listing restarting or skipping entries.
+pub(crate) async fn read(
+    state: &State,
+    repo: RepositoryHandle,
+    path: Utf8PathBuf,
+    after: Option<EntryCursor>,
+    limit: u64,
+) -> Result<DirectoryPage, Error> {
+    let repo = state.repositories.get(repo)?;
+    let dir = repo.repository.open_directory(path).await?;
+
+    let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+    let mut entries = Vec::new();
+    let mut next = None;
+
+    for entry in dir.entries_after(after.as_ref()).take(limit) {
+        next = Some(entry.cursor());
+        entries.push(DirEntry {
+            name: entry.unique_name().into_owned(),
+            entry_type: entry.entry_type().into(),
+            id: entry.id(),
+            created: to_millis(entry.created()),
+            modified: to_millis(entry.modified()),
+        });
+    }
+
+    if entries.len() < limit {
+        next = None;
+    }
+
+    Ok(DirectoryPage { entries, next })
+}
+
+/// Opens a directory in the joint view of all the open repositories (see
+/// `Repositories::joint_view`). The root of the view lists the repositories.
+pub(crate) async fn open_joint(state: &State, path: Utf8PathBuf) -> Result<Directory, Error> {
+    let view = state.repositories.joint_view();
+    let dir = view.open_directory(path).await?;
+    let entries = dir
+        .entries()
+        .into_iter()
+        .map(|(name, entry_type)| DirEntry {
+            name: name.into_owned(),
+            entry_type: entry_type.into(),
+            id: 0,
+            created: 0,
+            modified: 0,
         })
         .collect();
 
@@ -64,3 +141,51 @@ pub(crate) async fn remove(
 
     Ok(())
 }
+
+/// Removes the directory at the given path including its contents, reporting the progress. First
+/// scans the directory tree ("scanning" stage) and then removes the directories, each together
+/// with its files, starting from the deepest ones ("removing" stage).
+pub(crate) async fn remove_with_progress(
+    state: &State,
+    repo: RepositoryHandle,
+    path: Utf8PathBuf,
+    progress: ProgressReporter,
+) -> Result<(), Error> {
+    let holder = state.repositories.get(repo)?;
+    let repo = &holder.repository;
+
+    // Breadth-first, so every directory comes after its parent.
+    let mut dirs = vec![path];
+    let mut index = 0;
+
+    while index < dirs.len() {
+        let dir = repo.open_directory(&dirs[index]).await?;
+        let subdirs: Vec<_> = dir
+            .entries()
+            .filter(|entry| entry.entry_type() == EntryType::Directory)
+            .map(|entry| dirs[index].join(entry.unique_name().as_ref()))
+            .collect();
+
+        dirs.extend(subdirs);
+        index += 1;
+
+        progress.report("scanning", index as u64, dirs.len() as u64);
+    }
+
+    let total = dirs.len() as u64;
+
+    for (index, dir) in dirs.iter().rev().enumerate() {
+        repo.remove_entry_recursively(dir).await?;
+        progress.report("removing", index as u64 + 1, total);
+    }
+
+    Ok(())
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
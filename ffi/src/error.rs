@@ -1,4 +1,5 @@
 use crate::{
+    file::TooManyOpenFiles,
     registry::InvalidHandle,
     repository::{EntryChanged, RegistrationRequired},
     session::SessionError,
@@ -60,6 +61,18 @@ pub enum ErrorCode {
     InvalidHandle = 15,
     /// Entry has been changed and no longer matches the expected value
     EntryChanged = 16,
+    /// The directory has reached the maximum number of entries
+    TooManyEntries = 17,
+    /// The path exceeds the maximum depth of the directory tree
+    PathTooDeep = 18,
+    /// The entry name exceeds the maximum length
+    NameTooLong = 19,
+    /// Write fencing is enabled and the local branch is behind some other branch
+    Outdated = 20,
+    /// The maximum number of concurrently open files has been reached
+    TooManyOpenFiles = 21,
+    /// Not enough free space left on the device
+    StorageFull = 22,
 
     VfsInvalidMountPoint = 2048,
     VfsDriverInstall = 2048 + 1,
@@ -107,6 +120,11 @@ impl ToErrorCode for ouisync_lib::Error {
                 ErrorCode::InvalidArgument
             }
             Self::StorageVersionMismatch => ErrorCode::StorageVersionMismatch,
+            Self::TooManyEntries => ErrorCode::TooManyEntries,
+            Self::PathTooDeep => ErrorCode::PathTooDeep,
+            Self::NameTooLong => ErrorCode::NameTooLong,
+            Self::Outdated => ErrorCode::Outdated,
+            Self::StorageFull => ErrorCode::StorageFull,
             Self::EntryIsFile | Self::EntryIsDirectory | Self::Writer(_) | Self::Locked => {
                 ErrorCode::Other
             }
@@ -185,6 +203,12 @@ impl ToErrorCode for EntryChanged {
     }
 }
 
+impl ToErrorCode for TooManyOpenFiles {
+    fn to_error_code(&self) -> ErrorCode {
+        ErrorCode::TooManyOpenFiles
+    }
+}
+
 impl ToErrorCode for io::Error {
     fn to_error_code(&self) -> ErrorCode {
         ErrorCode::Other
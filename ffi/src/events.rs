@@ -0,0 +1,84 @@
+//! Log of the recent repository events. Allows a client to subscribe to the repository after
+//! triggering some operation without missing the notifications caused by it: the client obtains
+//! the current cursor first, then performs the operation and finally subscribes with the cursor as
+//! `since`. The events that happened in between are then replayed to it.
+//!
+//! The repository notifications carry no payload, so only the sequence numbers of the events need
+//! to be retained.
+
+use deadlock::BlockingMutex;
+use ouisync_lib::{Event, Payload, Repository};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+// Max number of events that can be replayed. If more than this many events happened since the
+// cursor, only this many are replayed. This is fine because the notifications are only hints that
+// something changed, so missing some of them as long as at least one is received is harmless.
+const CAPACITY: u64 = 32;
+
+pub(crate) struct EventLog {
+    inner: BlockingMutex<Inner>,
+}
+
+impl EventLog {
+    pub fn new(repository: &Repository) -> Self {
+        Self {
+            inner: BlockingMutex::new(Inner {
+                event_rx: repository.subscribe(),
+                next: 0,
+            }),
+        }
+    }
+
+    /// Returns the cursor pointing just past the most recent event.
+    pub fn cursor(&self) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.update();
+        inner.next
+    }
+
+    /// Returns the number of events that happened since `cursor` and should be replayed to the
+    /// subscriber.
+    ///
+    /// To not miss any events, the subscriber needs to subscribe to the repository *before*
+    /// calling this. Some events might then be delivered twice, but none are lost.
+    pub fn replay_count(&self, cursor: u64) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.update();
+        inner.next.saturating_sub(cursor).min(CAPACITY)
+    }
+}
+
+struct Inner {
+    event_rx: broadcast::Receiver<Event>,
+    // Sequence number of the next event.
+    next: u64,
+}
+
+impl Inner {
+    fn update(&mut self) {
+        loop {
+            match self.event_rx.try_recv() {
+                Ok(event) if is_notifiable(&event) => self.next += 1,
+                Ok(_) => (),
+                // We don't know what the skipped events were so assume at least one of them was
+                // notifiable.
+                Err(TryRecvError::Lagged(_)) => self.next += 1,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+/// Whether the event should be notified to the repository subscribers.
+pub(crate) fn is_notifiable(event: &Event) -> bool {
+    matches!(
+        event.payload,
+        Payload::BranchChanged(_)
+            | Payload::BlockReceived { .. }
+            | Payload::MetadataChanged
+            | Payload::Wiped
+            | Payload::StorageFull
+            | Payload::AccessNotice
+            | Payload::Resync
+    )
+}
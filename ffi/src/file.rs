@@ -1,48 +1,325 @@
-use crate::{error::Error, registry::Handle, repository::RepositoryHandle, state::State};
-use camino::Utf8PathBuf;
-use deadlock::AsyncMutex;
-use ouisync_lib::{Branch, File};
-use std::{io::SeekFrom, sync::Arc};
+use crate::{
+    error::Error,
+    registry::{Handle, InvalidHandle, Registry},
+    repository::RepositoryHandle,
+    state::State,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use deadlock::{AsyncMutex, BlockingMutex};
+use ouisync_lib::{crypto::sign::PublicKey, BlockPresence, Branch, File};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::SeekFrom,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::time;
+
+/// Default maximum number of concurrently open files per repository.
+const DEFAULT_MAX_OPEN_FILES: usize = 1024;
+
+/// Maximum number of blocks saved by a single step of `flush_within`.
+const FLUSH_CHUNK_BLOCKS: usize = 64;
+
+/// How often to look for idle files.
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct FileHolder {
     pub(crate) file: AsyncMutex<File>,
     pub(crate) local_branch: Option<Branch>,
+    repository: RepositoryHandle,
+    path: Utf8PathBuf,
+    last_used: BlockingMutex<Instant>,
+}
+
+impl FileHolder {
+    fn new(
+        file: File,
+        local_branch: Option<Branch>,
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+    ) -> Self {
+        Self {
+            file: AsyncMutex::new(file),
+            local_branch,
+            repository,
+            path,
+            last_used: BlockingMutex::new(Instant::now()),
+        }
+    }
+
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    fn idle(&self) -> Duration {
+        self.last_used.lock().unwrap().elapsed()
+    }
 }
 
 pub(crate) type FileHandle = Handle<Arc<FileHolder>>;
 
+/// Registry of the open files. Limits the number of files open concurrently per repository and
+/// closes files that haven't been accessed for a while, so that handles leaked by a crashed
+/// frontend don't hold their locks and memory forever.
+pub(crate) struct OpenFiles {
+    inner: BlockingMutex<Inner>,
+}
+
+struct Inner {
+    registry: Registry<Arc<FileHolder>>,
+    max_per_repository: Option<usize>,
+    idle_timeout: Option<Duration>,
+}
+
+impl OpenFiles {
+    pub fn new() -> Self {
+        Self {
+            inner: BlockingMutex::new(Inner {
+                registry: Registry::new(),
+                max_per_repository: Some(DEFAULT_MAX_OPEN_FILES),
+                // Opt-in: a frontend can legitimately keep a file open without touching it for a
+                // long time, so don't close anything unless asked to (see `set_idle_timeout`).
+                idle_timeout: None,
+            }),
+        }
+    }
+
+    /// Sets the maximum number of concurrently open files per repository. `None` means unlimited.
+    /// Doesn't affect the files that are already open.
+    pub fn set_max_per_repository(&self, max: Option<usize>) {
+        self.inner.lock().unwrap().max_per_repository = max;
+    }
+
+    /// Sets the time after which a file that hasn't been accessed is closed automatically. `None`
+    /// (the default) means the files are never closed automatically.
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        self.inner.lock().unwrap().idle_timeout = timeout;
+    }
+
+    /// Checks whether another file can be opened in the given repository.
+    pub fn check_limit(&self, repository: RepositoryHandle) -> Result<(), TooManyOpenFiles> {
+        self.inner.lock().unwrap().check_limit(repository)
+    }
+
+    fn insert(&self, holder: FileHolder) -> Result<FileHandle, TooManyOpenFiles> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.check_limit(holder.repository)?;
+
+        Ok(inner.registry.insert(Arc::new(holder)))
+    }
+
+    /// Returns the file with the given handle and marks it as recently used.
+    pub fn get(&self, handle: FileHandle) -> Result<Arc<FileHolder>, InvalidHandle> {
+        let holder = self.inner.lock().unwrap().registry.get(handle)?.clone();
+        *holder.last_used.lock().unwrap() = Instant::now();
+
+        Ok(holder)
+    }
+
+    pub fn remove(&self, handle: FileHandle) -> Option<Arc<FileHolder>> {
+        self.inner.lock().unwrap().registry.remove(handle)
+    }
+
+    /// Removes all the open files of the given repository and returns them.
+    pub fn remove_all_in(
+        &self,
+        repository: RepositoryHandle,
+    ) -> Vec<(FileHandle, Arc<FileHolder>)> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let handles: Vec<_> = inner
+            .registry
+            .iter()
+            .filter(|(_, holder)| holder.repository == repository)
+            .map(|(handle, _)| handle)
+            .collect();
+
+        handles
+            .into_iter()
+            .filter_map(|handle| Some((handle, inner.registry.remove(handle)?)))
+            .collect()
+    }
+
+    /// Lists the open files, for diagnostics.
+    pub fn list(&self) -> Vec<OpenFileInfo> {
+        let mut files: Vec<_> = self
+            .inner
+            .lock()
+            .unwrap()
+            .registry
+            .iter()
+            .map(|(handle, holder)| OpenFileInfo {
+                handle: handle.id(),
+                repository: holder.repository.id(),
+                path: holder.path.clone(),
+                idle_secs: holder.idle().as_secs(),
+            })
+            .collect();
+
+        files.sort_by_key(|info| info.handle);
+        files
+    }
+
+    /// Periodically closes the files that haven't been accessed for longer than the idle timeout.
+    pub async fn run_reaper(&self) {
+        let mut interval = time::interval(REAP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            for (handle, holder) in self.remove_idle() {
+                tracing::warn!(
+                    ?handle,
+                    repository = ?holder.repository,
+                    path = %holder.path,
+                    "Closing idle file"
+                );
+
+                if let Err(error) = holder.file.lock().await.flush().await {
+                    tracing::error!(?handle, ?error, "Failed to flush idle file");
+                }
+            }
+        }
+    }
+
+    fn remove_idle(&self) -> Vec<(FileHandle, Arc<FileHolder>)> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let Some(timeout) = inner.idle_timeout else {
+            return Vec::new();
+        };
+
+        let handles: Vec<_> = inner
+            .registry
+            .iter()
+            .filter(|(_, holder)| holder.idle() >= timeout)
+            .map(|(handle, _)| handle)
+            .collect();
+
+        handles
+            .into_iter()
+            .filter_map(|handle| Some((handle, inner.registry.remove(handle)?)))
+            .collect()
+    }
+}
+
+impl Inner {
+    fn check_limit(&self, repository: RepositoryHandle) -> Result<(), TooManyOpenFiles> {
+        let Some(max) = self.max_per_repository else {
+            return Ok(());
+        };
+
+        let count = self
+            .registry
+            .iter()
+            .filter(|(_, holder)| holder.repository == repository)
+            .count();
+
+        if count < max {
+            Ok(())
+        } else {
+            Err(TooManyOpenFiles)
+        }
+    }
+}
+
+/// Information about an open file.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct OpenFileInfo {
+    pub handle: u64,
+    pub repository: u64,
+    pub path: Utf8PathBuf,
+    /// Time since the file was last accessed, in seconds.
+    pub idle_secs: u64,
+}
+
+#[derive(Debug, Error)]
+#[error("too many open files")]
+pub(crate) struct TooManyOpenFiles;
+
 pub(crate) async fn open(
     state: &State,
     repo: RepositoryHandle,
     path: Utf8PathBuf,
 ) -> Result<FileHandle, Error> {
-    let repo = state.repositories.get(repo)?;
-    let local_branch = repo.repository.local_branch().ok();
+    let holder = state.repositories.get(repo)?;
+    let local_branch = holder.repository.local_branch().ok();
+
+    state.files.check_limit(repo)?;
+
+    let file = holder.repository.open_file(&path).await?;
+    let holder = FileHolder::new(file, local_branch, repo, path);
+    let handle = state.files.insert(holder)?;
+
+    Ok(handle)
+}
+
+/// Opens the version of the file at `path` in the given branch. Useful to access a specific version
+/// of a conflicted file which `open` refuses to open.
+pub(crate) async fn open_version(
+    state: &State,
+    repo: RepositoryHandle,
+    path: Utf8PathBuf,
+    branch: PublicKey,
+) -> Result<FileHandle, Error> {
+    let holder = state.repositories.get(repo)?;
+    let local_branch = holder.repository.local_branch().ok();
+
+    state.files.check_limit(repo)?;
 
-    let file = repo.repository.open_file(&path).await?;
-    let holder = FileHolder {
-        file: AsyncMutex::new(file),
-        local_branch,
-    };
-    let handle = state.files.insert(Arc::new(holder));
+    let file = holder.repository.open_file_version(&path, &branch).await?;
+    let holder = FileHolder::new(file, local_branch, repo, path);
+    let handle = state.files.insert(holder)?;
 
     Ok(handle)
 }
 
+/// Opens all the concurrent versions of the file at `path`. Either all of them are opened or none.
+pub(crate) async fn open_all_versions(
+    state: &State,
+    repo: RepositoryHandle,
+    path: Utf8PathBuf,
+) -> Result<Vec<FileHandle>, Error> {
+    let holder = state.repositories.get(repo)?;
+    let local_branch = holder.repository.local_branch().ok();
+
+    let files = holder.repository.open_file_versions(&path).await?;
+    let mut handles = Vec::with_capacity(files.len());
+
+    for file in files {
+        let holder = FileHolder::new(file, local_branch.clone(), repo, path.clone());
+
+        match state.files.insert(holder) {
+            Ok(handle) => handles.push(handle),
+            Err(error) => {
+                for handle in handles {
+                    state.files.remove(handle);
+                }
+
+                return Err(error.into());
+            }
+        }
+    }
+
+    Ok(handles)
+}
+
 pub(crate) async fn create(
     state: &State,
     repo: RepositoryHandle,
     path: Utf8PathBuf,
 ) -> Result<FileHandle, Error> {
-    let repo = state.repositories.get(repo)?;
-    let local_branch = repo.repository.local_branch()?;
+    let holder = state.repositories.get(repo)?;
+    let local_branch = holder.repository.local_branch()?;
+
+    // Check the limit before creating the file so it's not created if it can't be opened.
+    state.files.check_limit(repo)?;
 
-    let file = repo.repository.create_file(&path).await?;
-    let holder = FileHolder {
-        file: AsyncMutex::new(file),
-        local_branch: Some(local_branch),
-    };
-    let handle = state.files.insert(Arc::new(holder));
+    let file = holder.repository.create_file(&path).await?;
+    let holder = FileHolder::new(file, Some(local_branch), repo, path);
+    let handle = state.files.insert(holder)?;
 
     Ok(handle)
 }
@@ -62,6 +339,22 @@ pub(crate) async fn remove(
     Ok(())
 }
 
+/// Returns which blocks of the file at the given path are present locally.
+pub(crate) async fn block_presence(
+    state: &State,
+    repo: RepositoryHandle,
+    path: Utf8PathBuf,
+) -> Result<BlockPresence, Error> {
+    let file = state
+        .repositories
+        .get(repo)?
+        .repository
+        .open_file(&path)
+        .await?;
+
+    Ok(file.block_presence().await?)
+}
+
 pub(crate) async fn close(state: &State, handle: FileHandle) -> Result<(), Error> {
     if let Some(holder) = state.files.remove(handle) {
         holder.file.lock().await.flush().await?
@@ -75,6 +368,43 @@ pub(crate) async fn flush(state: &State, handle: FileHandle) -> Result<(), Error
     Ok(())
 }
 
+/// Flushes the file in chunks until it's completely flushed or `timeout` elapses. Returns whether
+/// the file has been completely flushed.
+pub(crate) async fn flush_within(
+    state: &State,
+    handle: FileHandle,
+    timeout: Duration,
+) -> Result<bool, Error> {
+    let deadline = Instant::now() + timeout;
+    let holder = state.files.get(handle)?;
+    let mut file = holder.file.lock().await;
+
+    loop {
+        if file.flush_partial(FLUSH_CHUNK_BLOCKS).await? {
+            return Ok(true);
+        }
+
+        if Instant::now() >= deadline {
+            return Ok(false);
+        }
+    }
+}
+
+pub(crate) async fn set_cache_bypass(
+    state: &State,
+    handle: FileHandle,
+    enabled: bool,
+) -> Result<(), Error> {
+    state
+        .files
+        .get(handle)?
+        .file
+        .lock()
+        .await
+        .set_cache_bypass(enabled);
+    Ok(())
+}
+
 /// Read at most `len` bytes from the file and returns them. The returned buffer can be shorter
 /// than `len` and empty in case of EOF.
 pub(crate) async fn read(
@@ -83,19 +413,12 @@ pub(crate) async fn read(
     offset: u64,
     len: u64,
 ) -> Result<Vec<u8>, Error> {
-    let len = len as usize;
-    let mut buffer = vec![0; len];
-
     let holder = state.files.get(handle)?;
     let mut file = holder.file.lock().await;
 
     file.seek(SeekFrom::Start(offset));
 
-    // TODO: consider using just `read`
-    let len = file.read_all(&mut buffer).await?;
-    buffer.truncate(len);
-
-    Ok(buffer)
+    Ok(file.read_up_to(len).await?)
 }
 
 /// Write `len` bytes from `buffer` into the file.
@@ -146,6 +469,19 @@ pub(crate) async fn len(state: &State, handle: FileHandle) -> Result<u64, Error>
     Ok(state.files.get(handle)?.file.lock().await.len())
 }
 
+/// Id of the branch the open file belongs to, as a hex string.
+pub(crate) async fn branch(state: &State, handle: FileHandle) -> Result<String, Error> {
+    Ok(state
+        .files
+        .get(handle)?
+        .file
+        .lock()
+        .await
+        .branch()
+        .id()
+        .to_string())
+}
+
 /// Retrieve the sync progress of the file.
 pub(crate) async fn progress(state: &State, handle: FileHandle) -> Result<u64, Error> {
     // Don't keep the file locked while progress is being awaited.
@@ -1,42 +1,49 @@
 use crate::{
-    directory,
+    crash_dump, directory,
     error::Error,
-    file, network,
+    file, network, operation, overlay,
+    profile::Profiles,
     protocol::{Request, Response},
-    repository, share_token,
+    repository, restore, share_token,
     state::State,
     state_monitor,
 };
 use async_trait::async_trait;
 use ouisync_bridge::transport::SessionContext;
-use ouisync_lib::{crypto::cipher::SecretKey, PeerAddr};
-use std::{net::SocketAddr, sync::Arc};
+use ouisync_lib::{
+    crypto::cipher::SecretKey, network::KeepAliveConfig, DirectoryLimits, PeerAddr, StorageSize,
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::Instrument;
+
+// Source of the correlation ids of the requests. Unique per process.
+static NEXT_CORRELATION_ID: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Clone)]
 pub(crate) struct Handler {
     state: Arc<State>,
+    profiles: Arc<Profiles>,
 }
 
 impl Handler {
-    pub fn new(state: Arc<State>) -> Self {
-        Self { state }
+    pub fn new(state: Arc<State>, profiles: Arc<Profiles>) -> Self {
+        Self { state, profiles }
     }
-}
-
-#[async_trait]
-impl ouisync_bridge::transport::Handler for Handler {
-    type Request = Request;
-    type Response = Response;
-    type Error = Error;
 
-    async fn handle(
+    async fn dispatch(
         &self,
-        request: Self::Request,
+        request: Request,
         context: &SessionContext,
-    ) -> Result<Self::Response, Self::Error> {
-        tracing::trace!(?request);
-
+    ) -> Result<Response, Error> {
         let response = match request {
+            Request::Handshake { version } => context.protocol_version.negotiate(version).into(),
             Request::RepositoryCreate {
                 path,
                 read_secret,
@@ -59,8 +66,20 @@ impl ouisync_bridge::transport::Handler for Handler {
             Request::RepositoryClose(handle) => {
                 repository::close(&self.state, handle).await?.into()
             }
-            Request::RepositorySubscribe(handle) => {
-                repository::subscribe(&self.state, &context.notification_tx, handle)?.into()
+            Request::RepositoryForceClose(handle) => {
+                repository::force_close(&self.state, &context.notification_tx, handle)
+                    .await?
+                    .into()
+            }
+            Request::RepositorySubscribe { repository, since } => {
+                repository::subscribe(&self.state, &context.notification_tx, repository, since)?
+                    .into()
+            }
+            Request::RepositoryOverlayStateSubscribe(repository) => {
+                overlay::subscribe(&self.state, &context.notification_tx, repository)?.into()
+            }
+            Request::RepositoryEventCursor(handle) => {
+                repository::event_cursor(&self.state, handle)?.into()
             }
             Request::RepositoryIsSyncEnabled(handle) => {
                 repository::is_sync_enabled(&self.state, handle)
@@ -86,6 +105,40 @@ impl ouisync_bridge::transport::Handler for Handler {
                 .set_access(read, write)
                 .await?
                 .into(),
+            Request::RepositorySetLocalSecrets {
+                repository,
+                old,
+                new,
+            } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .change_local_secret(old, new)
+                .await?
+                .into(),
+            Request::RepositoryCreateUnlockToken(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .create_unlock_token()
+                .await?
+                .to_string()
+                .into(),
+            Request::RepositoryRevokeUnlockToken(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .revoke_unlock_token()
+                .await?
+                .into(),
+            Request::RepositoryOpenWithUnlockToken { path, token } => {
+                repository::open_with_unlock_token(&self.state, path.into_std_path_buf(), token)
+                    .await?
+                    .into()
+            }
             Request::RepositoryCredentials(handle) => {
                 repository::credentials(&self.state, handle)?.into()
             }
@@ -125,6 +178,13 @@ impl ouisync_bridge::transport::Handler for Handler {
             Request::RepositoryDatabaseId(handle) => {
                 repository::database_id(&self.state, handle).await?.into()
             }
+            Request::RepositoryDbRecovery(handle) => self
+                .state
+                .repositories
+                .get(handle)?
+                .repository
+                .db_recovery()
+                .into(),
             Request::RepositoryEntryType { repository, path } => {
                 repository::entry_type(&self.state, repository, path)
                     .await?
@@ -161,6 +221,15 @@ impl ouisync_bridge::transport::Handler for Handler {
                 repository::set_pex_enabled(&self.state, repository, enabled).await?;
                 ().into()
             }
+            Request::RepositoryRoutingTags(repository) => {
+                repository::routing_tags(&self.state, repository)
+                    .await?
+                    .into()
+            }
+            Request::RepositorySetRoutingTags { repository, tags } => {
+                repository::set_routing_tags(&self.state, repository, tags).await?;
+                ().into()
+            }
             Request::RepositoryCreateShareToken {
                 repository,
                 secret,
@@ -169,6 +238,17 @@ impl ouisync_bridge::transport::Handler for Handler {
             } => repository::create_share_token(&self.state, repository, secret, access_mode, name)
                 .await?
                 .into(),
+            Request::RepositorySplitSecrets {
+                repository,
+                secret,
+                threshold,
+                count,
+            } => repository::split_secrets(&self.state, repository, secret, threshold, count)
+                .await?
+                .into(),
+            Request::RepositoryRecoverSecrets { shares } => {
+                repository::recover_secrets(shares)?.into()
+            }
             Request::RepositoryCreateMirror { repository, host } => {
                 repository::create_mirror(&self.state, repository, &host)
                     .await?
@@ -196,16 +276,442 @@ impl ouisync_bridge::transport::Handler for Handler {
             Request::RepositoryAccessMode(repository) => {
                 repository::access_mode(&self.state, repository)?.into()
             }
+            Request::RepositoryCreateInvitation {
+                repository,
+                access_mode,
+                name,
+            } => repository::create_invitation(&self.state, repository, access_mode, name)
+                .await?
+                .into(),
+            Request::RepositoryRedeemInvitation {
+                repository,
+                share_token,
+            } => repository::redeem_invitation(&self.state, repository, share_token)
+                .await?
+                .into(),
             Request::RepositorySyncProgress(repository) => {
                 repository::sync_progress(&self.state, repository)
                     .await?
                     .into()
             }
+            Request::RepositoryBarrier(repository) => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .barrier()
+                    .await?;
+                ().into()
+            }
+            Request::RepositoryDirectoryLimits(repository) => {
+                repository::directory_limits(&self.state, repository)?.into()
+            }
+            Request::RepositorySetDirectoryLimits { repository, limits } => {
+                repository::set_directory_limits(&self.state, repository, limits).await?;
+                ().into()
+            }
+            Request::DefaultDirectoryLimits => DirectoryLimits::default().into(),
             Request::RepositoryMountAll(mount_point) => {
-                repository::mount_root(&self.state, mount_point)
+                repository::mount_root(&self.state, mount_point, |_, _| ())
                     .await?
                     .into()
             }
+            Request::RepositoryMountAllWithProgress(mount_point) => {
+                let state = self.state.clone();
+
+                operation::spawn(
+                    &self.state,
+                    &context.notification_tx,
+                    |progress| async move {
+                        repository::mount_root(&state, mount_point, |value, total| {
+                            progress.report("mounting", value, total)
+                        })
+                        .await
+                    },
+                )
+                .into()
+            }
+            Request::RepositoryFolderSync {
+                repository,
+                path,
+                policy,
+            } => repository::folder_sync(
+                &self.state,
+                &context.notification_tx,
+                repository,
+                path,
+                policy,
+            )?
+            .into(),
+            Request::SessionRestore => restore::restore(
+                &self.state,
+                &context.notification_tx,
+                restore::DEFAULT_RESTORE_PARALLELISM,
+            )
+            .into(),
+            Request::SessionRestoreWithParallelism(parallelism) => restore::restore(
+                &self.state,
+                &context.notification_tx,
+                parallelism.try_into().unwrap_or(usize::MAX),
+            )
+            .into(),
+            Request::SessionRestoreLazy => restore::restore_lazy(&self.state)
+                .await
+                .into_iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .into(),
+            Request::SessionListUnopened => self
+                .state
+                .repositories
+                .list_lazy()
+                .into_iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .into(),
+            Request::SessionOpenJointDirectory(path) => {
+                directory::open_joint(&self.state, path).await?.into()
+            }
+            Request::ProfileCreate { configs_path } => self
+                .profiles
+                .create(configs_path.into_std_path_buf())
+                .into(),
+            Request::ProfileClose(profile) => self.profiles.close(profile).await?.into(),
+            Request::ProfileRequest { profile, request } => {
+                let handler = Self::new(self.profiles.get(profile)?, self.profiles.clone());
+                ouisync_bridge::transport::Handler::handle(&handler, *request, context).await?
+            }
+            Request::SessionSlowOps => self.state.request_metrics.slow_ops().into(),
+            Request::SessionSetSlowOpThreshold(threshold) => {
+                self.state
+                    .request_metrics
+                    .set_slow_threshold(threshold.map(Duration::from_millis));
+                ().into()
+            }
+            Request::SessionSetCrashDumpEnabled(enabled) => {
+                crash_dump::set_enabled(&self.state, enabled).await?.into()
+            }
+            Request::RepositoryDisplayName(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .display_name()
+                .await?
+                .into(),
+            Request::RepositorySetDisplayName { repository, name } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .set_display_name(name.as_deref())
+                .await?
+                .into(),
+            Request::RepositoryIcon(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .icon()
+                .await?
+                .into(),
+            Request::RepositorySetIcon { repository, icon } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .set_icon(icon.as_deref())
+                .await?
+                .into(),
+            Request::RepositoryColor(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .color()
+                .await?
+                .into(),
+            Request::RepositorySetColor { repository, color } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .set_color(color)
+                .await?
+                .into(),
+            Request::RepositoryIsWriteFenceEnabled(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .is_write_fence_enabled()
+                .into(),
+            Request::RepositorySetWriteFence {
+                repository,
+                enabled,
+            } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .set_write_fence(enabled)
+                .await?
+                .into(),
+            Request::RepositoryMinFreeSpace(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .min_free_space()
+                .into(),
+            Request::RepositorySetMinFreeSpace { repository, bytes } => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .set_min_free_space(bytes);
+                ().into()
+            }
+            Request::RepositoryIsOutdated(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .is_outdated()
+                .await?
+                .into(),
+            Request::RepositoryFreeze {
+                repository,
+                fail_fast,
+            } => repository::freeze(&self.state, repository, fail_fast)
+                .await?
+                .into(),
+            Request::RepositoryUnfreeze(repository) => {
+                repository::unfreeze(&self.state, repository).await?.into()
+            }
+            Request::RepositoryContributionStats(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .contribution_stats()
+                .await?
+                .into(),
+            Request::RepositoryListSnapshots {
+                repository,
+                limit_per_branch,
+            } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .snapshots(limit_per_branch.try_into().unwrap_or(usize::MAX))
+                .await?
+                .into(),
+            Request::RepositoryMirrorReceipts(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .mirror_receipts()
+                .await?
+                .into(),
+            Request::RepositoryPublishWipeDirective {
+                repository,
+                writer_id,
+            } => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .publish_wipe_directive(writer_id)
+                    .await?;
+                ().into()
+            }
+            Request::RepositoryPruneBranch {
+                repository,
+                writer_id,
+            } => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .prune_branch(writer_id)
+                    .await?;
+                ().into()
+            }
+            Request::RepositoryAtRiskBlockCount(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .at_risk_block_count()
+                .into(),
+            Request::RepositoryIsAtRiskTrackingEnabled(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .is_at_risk_tracking_enabled()
+                .into(),
+            Request::RepositorySetAtRiskTrackingEnabled {
+                repository,
+                enabled,
+            } => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .set_at_risk_tracking_enabled(enabled);
+                ().into()
+            }
+            Request::RepositoryHealth(repository) => {
+                repository::health(&self.state, repository).await?.into()
+            }
+            Request::RepositoryExplainSync { repository, path } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .explain_sync(path)
+                .await?
+                .into(),
+            Request::RepositoryConflicts(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .conflicts()
+                .into(),
+            Request::RepositoryShareOrigin(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .share_origin()
+                .await?
+                .into(),
+            Request::RepositoryUnreplicatedChanges(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .unreplicated_changes()
+                .await?
+                .into(),
+            Request::RepositoryPublishAccessNotice {
+                repository,
+                from,
+                to,
+            } => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .publish_access_notice(from, to)
+                    .await?;
+                ().into()
+            }
+            Request::RepositoryAccessNotice(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .access_notice()
+                .into(),
+            Request::RepositoryIsScrubEnabled(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .is_scrub_enabled()
+                .into(),
+            Request::RepositorySetScrubEnabled {
+                repository,
+                enabled,
+            } => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .set_scrub_enabled(enabled);
+                ().into()
+            }
+            Request::RepositoryEntryVersion { repository, path } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .entry_version_vector(path)
+                .await?
+                .into(),
+            Request::RepositoryWaitForVersion {
+                repository,
+                path,
+                version,
+                timeout,
+            } => repository::wait_for_version(&self.state, repository, path, version, timeout)
+                .await?
+                .into(),
+            Request::RepositoryDerivedData {
+                repository,
+                path,
+                kind,
+            } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .derived_data(path, &kind)
+                .await?
+                .into(),
+            Request::RepositorySetDerivedData {
+                repository,
+                path,
+                kind,
+                version,
+                data,
+            } => {
+                let data: Vec<u8> = data.into();
+
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .set_derived_data(path, &kind, &version, &data)
+                    .await?;
+                ().into()
+            }
+            Request::RepositoryClearDerivedData(repository) => {
+                self.state
+                    .repositories
+                    .get(repository)?
+                    .repository
+                    .clear_derived_data()
+                    .await?;
+                ().into()
+            }
+            Request::RepositoryBranches(repository) => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .branch_ids()
+                .await?
+                .into(),
+            Request::RepositoryDiff {
+                repository,
+                old,
+                new,
+                path,
+            } => self
+                .state
+                .repositories
+                .get(repository)?
+                .repository
+                .diff(&old, &new, path)
+                .await?
+                .into(),
             Request::RepositoryGetMetadata { repository, key } => {
                 repository::metadata_get(&self.state, repository, key)
                     .await?
@@ -224,6 +730,14 @@ impl ouisync_bridge::transport::Handler for Handler {
             Request::DirectoryOpen { repository, path } => {
                 directory::open(&self.state, repository, path).await?.into()
             }
+            Request::DirectoryRead {
+                repository,
+                path,
+                after,
+                limit,
+            } => directory::read(&self.state, repository, path, after, limit)
+                .await?
+                .into(),
             Request::DirectoryRemove {
                 repository,
                 path,
@@ -231,9 +745,33 @@ impl ouisync_bridge::transport::Handler for Handler {
             } => directory::remove(&self.state, repository, path, recursive)
                 .await?
                 .into(),
+            Request::DirectoryRemoveWithProgress { repository, path } => {
+                let state = self.state.clone();
+
+                operation::spawn(
+                    &self.state,
+                    &context.notification_tx,
+                    |progress| async move {
+                        directory::remove_with_progress(&state, repository, path, progress).await
+                    },
+                )
+                .into()
+            }
             Request::FileOpen { repository, path } => {
                 file::open(&self.state, repository, path).await?.into()
             }
+            Request::FileOpenVersion {
+                repository,
+                path,
+                branch,
+            } => file::open_version(&self.state, repository, path, branch)
+                .await?
+                .into(),
+            Request::FileOpenAllVersions { repository, path } => {
+                file::open_all_versions(&self.state, repository, path)
+                    .await?
+                    .into()
+            }
             Request::FileCreate { repository, path } => {
                 file::create(&self.state, repository, path).await?.into()
             }
@@ -252,12 +790,46 @@ impl ouisync_bridge::transport::Handler for Handler {
                 file::truncate(&self.state, file, len).await?.into()
             }
             Request::FileLen(file) => file::len(&self.state, file).await?.into(),
+            Request::FileBranch(file) => file::branch(&self.state, file).await?.into(),
             Request::FileProgress(file) => file::progress(&self.state, file).await?.into(),
+            Request::FileBlockPresence { repository, path } => {
+                file::block_presence(&self.state, repository, path)
+                    .await?
+                    .into()
+            }
             Request::FileFlush(file) => file::flush(&self.state, file).await?.into(),
+            Request::FileFlushWithin { file, timeout } => {
+                file::flush_within(&self.state, file, Duration::from_millis(timeout))
+                    .await?
+                    .into()
+            }
+            Request::FileSetCacheBypass { file, enabled } => {
+                file::set_cache_bypass(&self.state, file, enabled)
+                    .await?
+                    .into()
+            }
             Request::FileClose(file) => file::close(&self.state, file).await?.into(),
+            Request::ListOpenFiles => self.state.files.list().into(),
+            Request::SetMaxOpenFiles(max) => {
+                self.state
+                    .files
+                    .set_max_per_repository(max.map(|max| max as usize));
+                ().into()
+            }
+            Request::SetFileIdleTimeout(timeout) => {
+                self.state
+                    .files
+                    .set_idle_timeout(timeout.map(Duration::from_secs));
+                ().into()
+            }
+            Request::SetTestRngSeed(seed) => {
+                ouisync_bridge::repository::set_test_rng_seed(&self.state.config, seed).await?;
+                ().into()
+            }
             Request::NetworkInit(defaults) => {
                 ouisync_bridge::network::init(&self.state.network, &self.state.config, defaults)
                     .await;
+                self.state.watch_user_provided_peers().await;
                 ().into()
             }
             Request::NetworkSubscribe => {
@@ -272,15 +844,19 @@ impl ouisync_bridge::transport::Handler for Handler {
                 ouisync_bridge::network::bind(
                     &self.state.network,
                     &self.state.config,
-                    &[
-                        quic_v4.map(SocketAddr::from).map(PeerAddr::Quic),
-                        quic_v6.map(SocketAddr::from).map(PeerAddr::Quic),
-                        tcp_v4.map(SocketAddr::from).map(PeerAddr::Tcp),
-                        tcp_v6.map(SocketAddr::from).map(PeerAddr::Tcp),
-                    ]
-                    .into_iter()
-                    .flatten()
-                    .collect::<Vec<_>>(),
+                    &quic_v4
+                        .into_iter()
+                        .map(SocketAddr::from)
+                        .map(PeerAddr::Quic)
+                        .chain(
+                            quic_v6
+                                .into_iter()
+                                .map(SocketAddr::from)
+                                .map(PeerAddr::Quic),
+                        )
+                        .chain(tcp_v4.into_iter().map(SocketAddr::from).map(PeerAddr::Tcp))
+                        .chain(tcp_v6.into_iter().map(SocketAddr::from).map(PeerAddr::Tcp))
+                        .collect::<Vec<_>>(),
                 )
                 .await;
                 ().into()
@@ -363,6 +939,35 @@ impl ouisync_bridge::transport::Handler for Handler {
             Request::NetworkIsLocalDiscoveryEnabled => {
                 self.state.network.is_local_discovery_enabled().into()
             }
+            Request::NetworkVerifyThreads => (self.state.network.verify_threads() as u32).into(),
+            Request::NetworkSetVerifyThreads(threads) => {
+                ouisync_bridge::network::set_verify_threads(
+                    &self.state.network,
+                    &self.state.config,
+                    threads,
+                )
+                .await;
+                ().into()
+            }
+            Request::NetworkBindStatus => self.state.network.bind_status().into(),
+            Request::NetworkKeepAliveInterval => {
+                (self.state.network.keep_alive().interval.as_millis() as u64).into()
+            }
+            Request::NetworkKeepAliveTimeout => {
+                (self.state.network.keep_alive().timeout.as_millis() as u64).into()
+            }
+            Request::NetworkSetKeepAlive { interval, timeout } => {
+                ouisync_bridge::network::set_keep_alive(
+                    &self.state.network,
+                    &self.state.config,
+                    KeepAliveConfig {
+                        interval: Duration::from_millis(interval),
+                        timeout: Duration::from_millis(timeout),
+                    },
+                )
+                .await;
+                ().into()
+            }
             Request::NetworkSetLocalDiscoveryEnabled(enabled) => {
                 ouisync_bridge::network::set_local_discovery_enabled(
                     &self.state.network,
@@ -372,11 +977,50 @@ impl ouisync_bridge::transport::Handler for Handler {
                 .await;
                 ().into()
             }
+            Request::NetworkIsLanBulkTransferEnabled => {
+                self.state.network.is_lan_bulk_transfer_enabled().into()
+            }
+            Request::NetworkSetLanBulkTransferEnabled(enabled) => {
+                ouisync_bridge::network::set_lan_bulk_transfer_enabled(
+                    &self.state.network,
+                    &self.state.config,
+                    enabled,
+                )
+                .await;
+                ().into()
+            }
+            Request::NetworkSetTagPeerSources { tag, sources } => {
+                ouisync_bridge::network::set_tag_peer_sources(
+                    &self.state.network,
+                    &self.state.config,
+                    &tag,
+                    sources,
+                )
+                .await;
+                ().into()
+            }
+            Request::NetworkReconnect => {
+                self.state.network.handle_network_change().await;
+                ().into()
+            }
             Request::NetworkExternalAddrV4 => self.state.network.external_addr_v4().await.into(),
             Request::NetworkExternalAddrV6 => self.state.network.external_addr_v6().await.into(),
             Request::NetworkNatBehavior => self.state.network.nat_behavior().await.into(),
+            Request::NetworkConnectionStats => self.state.network.connection_stats().into(),
             Request::NetworkTrafficStats => self.state.network.traffic_stats().into(),
+            Request::NetworkSetProtocolTraceCapacity(capacity) => {
+                self.state
+                    .network
+                    .set_protocol_trace_capacity(capacity as usize);
+                ().into()
+            }
+            Request::NetworkDumpProtocolTrace => self.state.network.protocol_trace().into(),
             Request::NetworkShutdown => {
+                ouisync_bridge::network::save_connection_stats(
+                    &self.state.network,
+                    &self.state.config,
+                )
+                .await;
                 self.state.network.shutdown().await;
                 ().into()
             }
@@ -384,6 +1028,13 @@ impl ouisync_bridge::transport::Handler for Handler {
             Request::StateMonitorSubscribe(path) => {
                 state_monitor::subscribe(&self.state, &context.notification_tx, path)?.into()
             }
+            Request::SetMemoryLimit(limit) => {
+                self.state
+                    .memory
+                    .set_limit(limit.map(StorageSize::from_bytes));
+                ().into()
+            }
+            Request::MemoryUsage => self.state.memory.usage().to_bytes().into(),
             Request::Unsubscribe(handle) => {
                 self.state.remove_task(handle);
                 ().into()
@@ -421,3 +1072,54 @@ impl ouisync_bridge::transport::Handler for Handler {
         Ok(response)
     }
 }
+
+#[async_trait]
+impl ouisync_bridge::transport::Handler for Handler {
+    type Request = Request;
+    type Response = Response;
+    type Error = Error;
+
+    async fn handle(
+        &self,
+        request: Self::Request,
+        context: &SessionContext,
+    ) -> Result<Self::Response, Self::Error> {
+        // Every request gets a correlation id which is attached to all the log events emitted
+        // while handling it (including those from the repository and the store) so they can be
+        // grouped together.
+        let id = NEXT_CORRELATION_ID.fetch_add(1, Ordering::Relaxed);
+        let name = request.name();
+        let span = tracing::info_span!("request", id, name = %name);
+
+        // Path of the entry the request operates on, reported if the request turns out to be slow.
+        let path = request.path().map(ToString::to_string).or_else(|| {
+            request
+                .file()
+                .and_then(|file| self.state.files.get(file).ok())
+                .map(|holder| holder.path().to_string())
+        });
+
+        self.state.request_metrics.start(id, &name, path.as_deref());
+
+        let start = Instant::now();
+        let result = async {
+            tracing::trace!(?request);
+
+            let result = self.dispatch(request, context).await;
+
+            if let Err(error) = &result {
+                tracing::debug!(?error);
+            }
+
+            result
+        }
+        .instrument(span)
+        .await;
+
+        self.state
+            .request_metrics
+            .record(id, name, path, start.elapsed(), result.is_ok());
+
+        result
+    }
+}
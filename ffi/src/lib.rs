@@ -3,17 +3,25 @@
 #[macro_use]
 mod utils;
 mod c;
+mod cache;
+mod crash_dump;
 mod dart;
 mod directory;
 mod error;
+mod events;
 mod file;
 mod handler;
 mod log;
+mod metrics;
 mod mounter;
 mod network;
+mod operation;
+mod overlay;
+mod profile;
 mod protocol;
 mod registry;
 mod repository;
+mod restore;
 mod sender;
 mod session;
 mod share_token;
@@ -0,0 +1,302 @@
+//! Per request type latency and throughput metrics reported to the state monitor.
+
+use deadlock::BlockingMutex;
+use serde::{Deserialize, Serialize};
+use state_monitor::{MonitoredValue, StateMonitor};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+// Length of the window over which the request rate is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Requests taking at least this long are considered slow, unless configured otherwise.
+const DEFAULT_SLOW_THRESHOLD: Duration = Duration::from_millis(500);
+
+// How many of the most recent slow requests to keep.
+const SLOW_CAPACITY: usize = 32;
+
+// How many of the most recently handled requests (slow or not) to keep.
+const RECENT_CAPACITY: usize = 16;
+
+pub(crate) struct RequestMetrics {
+    monitor: StateMonitor,
+    entries: BlockingMutex<HashMap<String, Entry>>,
+    slow: SlowRequests,
+    slow_threshold: BlockingMutex<Duration>,
+    in_flight: BlockingMutex<HashMap<u64, InFlight>>,
+    recent: BlockingMutex<VecDeque<SlowOp>>,
+}
+
+impl RequestMetrics {
+    pub fn new(monitor: StateMonitor) -> Self {
+        let slow = SlowRequests::new(monitor.make_child("Slow requests"));
+
+        Self {
+            monitor,
+            entries: BlockingMutex::new(HashMap::new()),
+            slow,
+            slow_threshold: BlockingMutex::new(DEFAULT_SLOW_THRESHOLD),
+            in_flight: BlockingMutex::new(HashMap::new()),
+            recent: BlockingMutex::new(VecDeque::with_capacity(RECENT_CAPACITY)),
+        }
+    }
+
+    /// Sets the duration after which a request is considered slow. `None` restores the default.
+    pub fn set_slow_threshold(&self, threshold: Option<Duration>) {
+        *self.slow_threshold.lock().unwrap() = threshold.unwrap_or(DEFAULT_SLOW_THRESHOLD);
+    }
+
+    pub fn slow_threshold(&self) -> Duration {
+        *self.slow_threshold.lock().unwrap()
+    }
+
+    /// The most recent slow requests, oldest first.
+    pub fn slow_ops(&self) -> Vec<SlowOp> {
+        self.slow.list()
+    }
+
+    /// The requests currently being handled (started but not yet recorded), as
+    /// `(id, name, path, elapsed)`.
+    pub fn in_flight(&self) -> Vec<(u64, String, Option<String>, Duration)> {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, op)| (*id, op.name.clone(), op.path.clone(), op.start.elapsed()))
+            .collect()
+    }
+
+    /// The most recently handled requests, oldest first.
+    pub fn recent_ops(&self) -> Vec<SlowOp> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Records that a request of the given type and correlation id started being handled. It's
+    /// reported as in flight until the corresponding `record` call.
+    pub fn start(&self, id: u64, name: &str, path: Option<&str>) {
+        self.in_flight.lock().unwrap().insert(
+            id,
+            InFlight {
+                name: name.to_owned(),
+                path: path.map(ToOwned::to_owned),
+                start: Instant::now(),
+            },
+        );
+    }
+
+    /// Records that a request of the given type and correlation id was handled in `latency`.
+    /// `path` is the path of the entry (or repository) the request operated on, if any. Slow
+    /// requests are also logged.
+    pub fn record(
+        &self,
+        id: u64,
+        name: String,
+        path: Option<String>,
+        latency: Duration,
+        success: bool,
+    ) {
+        self.in_flight.lock().unwrap().remove(&id);
+
+        let op = SlowOp {
+            id,
+            name: name.clone(),
+            path,
+            duration: latency.as_millis().try_into().unwrap_or(u64::MAX),
+            success,
+        };
+
+        {
+            let mut recent = self.recent.lock().unwrap();
+
+            if recent.len() >= RECENT_CAPACITY {
+                recent.pop_front();
+            }
+
+            recent.push_back(op.clone());
+        }
+
+        if latency >= self.slow_threshold() {
+            tracing::warn!(id, %name, path = ?op.path, ?latency, success, "Slow request");
+            self.slow.record(op);
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .entry(name)
+            .or_insert_with_key(|name| Entry::new(&self.monitor.make_child(name.as_str())));
+
+        entry.record(latency, success);
+    }
+}
+
+/// Handled request, as returned from `slow_ops` (the requests that took at least the slow
+/// threshold to handle) and `recent_ops`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub(crate) struct SlowOp {
+    /// Correlation id of the request which can be used to find the corresponding log events.
+    pub id: u64,
+    pub name: String,
+    pub path: Option<String>,
+    /// Duration in milliseconds.
+    pub duration: u64,
+    pub success: bool,
+}
+
+struct InFlight {
+    name: String,
+    path: Option<String>,
+    start: Instant,
+}
+
+/// The last `SLOW_CAPACITY` slow requests, keyed by their correlation ids.
+struct SlowRequests {
+    monitor: StateMonitor,
+    values: BlockingMutex<VecDeque<(SlowOp, MonitoredValue<SlowRequest>)>>,
+}
+
+impl SlowRequests {
+    fn new(monitor: StateMonitor) -> Self {
+        Self {
+            monitor,
+            values: BlockingMutex::new(VecDeque::with_capacity(SLOW_CAPACITY)),
+        }
+    }
+
+    fn record(&self, op: SlowOp) {
+        let value = self.monitor.make_value(
+            format!("{}", op.id),
+            SlowRequest {
+                name: op.name.clone(),
+                path: op.path.clone(),
+                latency: Latency(Duration::from_millis(op.duration)),
+                success: op.success,
+            },
+        );
+
+        let mut values = self.values.lock().unwrap();
+
+        // Dropping the value removes it from the monitor.
+        if values.len() >= SLOW_CAPACITY {
+            values.pop_front();
+        }
+
+        values.push_back((op, value));
+    }
+
+    fn list(&self) -> Vec<SlowOp> {
+        self.values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(op, _)| op.clone())
+            .collect()
+    }
+}
+
+struct Entry {
+    count: u64,
+    total_latency: Duration,
+    window_start: Instant,
+    window_count: u64,
+    monitors: Monitors,
+}
+
+impl Entry {
+    fn new(monitor: &StateMonitor) -> Self {
+        Self {
+            count: 0,
+            total_latency: Duration::ZERO,
+            window_start: Instant::now(),
+            window_count: 0,
+            monitors: Monitors {
+                count: monitor.make_value("count", 0),
+                errors: monitor.make_value("errors", 0),
+                mean_latency: monitor.make_value("mean latency", Latency(Duration::ZERO)),
+                max_latency: monitor.make_value("max latency", Latency(Duration::ZERO)),
+                rate: monitor.make_value("rate", Rate(0.0)),
+            },
+        }
+    }
+
+    fn record(&mut self, latency: Duration, success: bool) {
+        self.count += 1;
+        self.total_latency += latency;
+
+        *self.monitors.count.get() = self.count;
+
+        if !success {
+            *self.monitors.errors.get() += 1;
+        }
+
+        *self.monitors.mean_latency.get() = Latency(self.total_latency.div_f64(self.count as f64));
+
+        {
+            let mut max_latency = self.monitors.max_latency.get();
+            if latency > max_latency.0 {
+                *max_latency = Latency(latency);
+            }
+        }
+
+        self.window_count += 1;
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= RATE_WINDOW {
+            *self.monitors.rate.get() = Rate(self.window_count as f64 / elapsed.as_secs_f64());
+            self.window_start = Instant::now();
+            self.window_count = 0;
+        }
+    }
+}
+
+struct Monitors {
+    count: MonitoredValue<u64>,
+    errors: MonitoredValue<u64>,
+    mean_latency: MonitoredValue<Latency>,
+    max_latency: MonitoredValue<Latency>,
+    rate: MonitoredValue<Rate>,
+}
+
+// Wrappers to display the values in human readable format in the state monitor.
+struct Latency(Duration);
+
+impl fmt::Debug for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.3}ms", self.0.as_secs_f64() * 1000.0)
+    }
+}
+
+struct Rate(f64);
+
+impl fmt::Debug for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1}/s", self.0)
+    }
+}
+
+struct SlowRequest {
+    name: String,
+    path: Option<String>,
+    latency: Latency,
+    success: bool,
+}
+
+impl fmt::Debug for SlowRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if let Some(path) = &self.path {
+            write!(f, " {path}")?;
+        }
+
+        write!(f, " {:?}", self.latency)?;
+
+        if !self.success {
+            write!(f, " (failed)")?;
+        }
+
+        Ok(())
+    }
+}
@@ -82,7 +82,13 @@ impl Mounter {
         result
     }
 
-    pub async fn mount_root(&self, mount_point: PathBuf) -> Result<(), Error> {
+    /// Mounts the root and all the repositories mounted so far under it. `on_progress` is called
+    /// with the number of repositories processed so far and the total number of repositories.
+    pub async fn mount_root(
+        &self,
+        mount_point: PathBuf,
+        mut on_progress: impl FnMut(u64, u64),
+    ) -> Result<(), Error> {
         let vfs = MultiRepoVFS::create(mount_point).await.map_err(|error| {
             tracing::error!("Failed to create mounter: {error:?}");
             error
@@ -90,14 +96,24 @@ impl Mounter {
 
         let mut inner = self.inner.lock().unwrap();
 
-        for (store_path, repo) in &inner.repos {
+        let total = inner.repos.len() as u64;
+        on_progress(0, total);
+
+        for (index, (store_path, repo)) in inner.repos.iter().enumerate() {
             if let Err(error) = vfs.insert(store_path.to_owned(), repo.clone()) {
                 tracing::error!("Failed to mount repository {:?}: {error:?}", store_path);
             }
+
+            on_progress(index as u64 + 1, total);
         }
 
         inner.multi_repo_vfs = Some(vfs);
 
         Ok(())
     }
+
+    /// Whether `mount_root` has been successfully called.
+    pub fn is_root_mounted(&self) -> bool {
+        self.inner.lock().unwrap().multi_repo_vfs.is_some()
+    }
 }
@@ -9,6 +9,7 @@ use tokio::select;
 pub(crate) fn subscribe(state: &State, notification_tx: &NotificationSender) -> TaskHandle {
     let mut on_protocol_mismatch = state.network.on_protocol_mismatch();
     let mut on_peer_set_change = state.network.on_peer_set_change();
+    let mut on_peer_timeout = state.network.on_peer_timeout();
     let notification_tx = notification_tx.clone();
 
     state.spawn_task(|id| async move {
@@ -28,6 +29,12 @@ pub(crate) fn subscribe(state: &State, notification_tx: &NotificationSender) ->
                         Err(_) => return,
                     }
                 }
+                e = on_peer_timeout.changed() => {
+                    match e {
+                        Ok(()) => NetworkEvent::PeerTimeout,
+                        Err(_) => return,
+                    }
+                }
             };
 
             notification_tx
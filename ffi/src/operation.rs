@@ -0,0 +1,73 @@
+//! Long running operations which report their progress as notifications.
+
+use crate::{
+    error::Error,
+    state::{State, TaskHandle},
+};
+use ouisync_bridge::{
+    protocol::{Notification, OperationEvent},
+    transport::NotificationSender,
+};
+use std::future::Future;
+
+/// Spawns the operation as a task and returns its handle. The progress reported by the operation
+/// is sent as notifications with the id of the handle, followed by either `Completed` or `Failed`
+/// once the operation finishes. Unsubscribing the handle cancels the operation.
+pub(crate) fn spawn<M, F>(
+    state: &State,
+    notification_tx: &NotificationSender,
+    make_operation: M,
+) -> TaskHandle
+where
+    M: FnOnce(ProgressReporter) -> F + Send + 'static,
+    F: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    let notification_tx = notification_tx.clone();
+
+    state.spawn_task(move |id| async move {
+        let reporter = ProgressReporter {
+            id,
+            notification_tx: notification_tx.clone(),
+        };
+
+        let event = match make_operation(reporter).await {
+            Ok(()) => OperationEvent::Completed,
+            Err(error) => {
+                tracing::error!(?error, "Operation failed");
+                OperationEvent::Failed {
+                    error: error.message,
+                }
+            }
+        };
+
+        notification_tx
+            .send((id, Notification::Operation(event)))
+            .await
+            .ok();
+    })
+}
+
+/// Reports the progress of an operation started with [`spawn`].
+#[derive(Clone)]
+pub(crate) struct ProgressReporter {
+    id: u64,
+    notification_tx: NotificationSender,
+}
+
+impl ProgressReporter {
+    /// Reports that `value` out of `total` units of work of the given stage have been done. This
+    /// is best effort: if the client isn't keeping up with the notifications, the report is
+    /// dropped. Can be called from sync code.
+    pub fn report(&self, stage: &str, value: u64, total: u64) {
+        self.notification_tx
+            .try_send((
+                self.id,
+                Notification::Operation(OperationEvent::Progress {
+                    stage: stage.to_owned(),
+                    value,
+                    total,
+                }),
+            ))
+            .ok();
+    }
+}
@@ -0,0 +1,401 @@
+//! Per-path sync states for the overlay icons of the file manager shell extensions.
+
+use crate::{
+    error::Error,
+    events,
+    repository::RepositoryHandle,
+    state::{State, TaskHandle},
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use ouisync_bridge::{
+    protocol::{Notification, OverlayState, OverlayStateChange},
+    transport::NotificationSender,
+};
+use ouisync_lib::{
+    crypto::{Hash, Hashable},
+    Event, JointEntryRef, Repository,
+};
+use std::collections::{BTreeMap, HashMap};
+use tokio::{
+    sync::broadcast::{
+        error::{RecvError, TryRecvError},
+        Receiver,
+    },
+    time::{self, Duration},
+};
+
+/// Events arriving within this interval after the first one are coalesced into a single update.
+const THROTTLE: Duration = Duration::from_secs(1);
+
+/// Subscribes to the overlay states of all the entries in the repository. The first notification
+/// contains the states of all the entries, the subsequent ones only the entries whose state
+/// changed.
+pub(crate) fn subscribe(
+    state: &State,
+    notification_tx: &NotificationSender,
+    repository_handle: RepositoryHandle,
+) -> Result<TaskHandle, Error> {
+    let holder = state.repositories.get(repository_handle)?;
+
+    // Subscribe before computing the initial states so no event is missed.
+    let mut event_rx = holder.repository.subscribe();
+    let notification_tx = notification_tx.clone();
+
+    let handle = state.spawn_task(move |id| async move {
+        let mut tracker = OverlayTracker::default();
+
+        loop {
+            let excluded = holder.registration.read().await.is_none();
+
+            match tracker.update(&holder.repository, excluded).await {
+                Ok(changes) if changes.is_empty() => (),
+                Ok(changes) => {
+                    notification_tx
+                        .send((id, Notification::OverlayState(changes)))
+                        .await
+                        .ok();
+                }
+                Err(error) => {
+                    tracing::error!(?error, "Failed to compute overlay states");
+                }
+            }
+
+            if !wait(&mut event_rx).await {
+                break;
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Waits for the next notifiable event and then for the throttle interval to let the related
+/// events arrive. Returns `false` if the repository has been closed.
+async fn wait(event_rx: &mut Receiver<Event>) -> bool {
+    loop {
+        match event_rx.recv().await {
+            Ok(event) if events::is_notifiable(&event) => break,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(_)) => break,
+            Err(RecvError::Closed) => return false,
+        }
+    }
+
+    time::sleep(THROTTLE).await;
+
+    loop {
+        match event_rx.try_recv() {
+            Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+            Err(TryRecvError::Empty) => return true,
+            Err(TryRecvError::Closed) => return false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct OverlayTracker {
+    // Last reported states.
+    states: BTreeMap<String, OverlayState>,
+    // Last computed states, before marking them as excluded.
+    computed: BTreeMap<String, OverlayState>,
+    // Version vector hashes of the directories as of the last computation. A directory whose
+    // version vector hasn't changed has the same contents, so its subtree needn't be walked again
+    // unless some of its files were still syncing.
+    dirs: BTreeMap<String, Hash>,
+    // Version vector hashes of the files known to be completely present locally. As the content of
+    // a given file version never changes, these don't need to be opened again to check their
+    // progress.
+    synced_files: HashMap<String, Hash>,
+}
+
+enum EntryKind {
+    File(Hash),
+    Directory(Hash),
+}
+
+impl OverlayTracker {
+    /// Computes the current states and returns the ones that changed since the last call.
+    async fn update(
+        &mut self,
+        repository: &Repository,
+        excluded: bool,
+    ) -> Result<Vec<OverlayStateChange>, Error> {
+        self.compute(repository).await?;
+
+        let mut states = self.computed.clone();
+
+        if excluded {
+            for state in states.values_mut() {
+                *state = OverlayState::Excluded;
+            }
+        }
+
+        let mut changes = Vec::new();
+
+        for (path, state) in &states {
+            if self.states.get(path) != Some(state) {
+                changes.push(OverlayStateChange {
+                    path: path.clone(),
+                    state: Some(*state),
+                });
+            }
+        }
+
+        for path in self.states.keys() {
+            if !states.contains_key(path) {
+                changes.push(OverlayStateChange {
+                    path: path.clone(),
+                    state: None,
+                });
+            }
+        }
+
+        self.synced_files
+            .retain(|path, _| states.contains_key(path));
+        self.states = states;
+
+        Ok(changes)
+    }
+
+    async fn compute(&mut self, repository: &Repository) -> Result<(), Error> {
+        let mut states = BTreeMap::new();
+        let mut dir_hashes = BTreeMap::new();
+
+        // Breadth-first, so every directory comes after its parent.
+        let mut dirs = vec![Utf8PathBuf::new()];
+        let mut index = 0;
+
+        while index < dirs.len() {
+            let path = dirs[index].clone();
+            let dir = repository.open_directory(&path).await?;
+
+            // Collect first so the directory isn't borrowed across the awaits below.
+            let entries: Vec<_> = dir
+                .entries()
+                .map(|entry| {
+                    let conflict = dir.lookup(entry.name()).count() > 1;
+                    let kind = match &entry {
+                        JointEntryRef::File(file) => EntryKind::File(file.version_vector().hash()),
+                        JointEntryRef::Directory(dir) => {
+                            EntryKind::Directory(dir.version_vector().hash())
+                        }
+                    };
+
+                    (path.join(entry.unique_name().as_ref()), conflict, kind)
+                })
+                .collect();
+            drop(dir);
+
+            let mut dir_state = OverlayState::Synced;
+
+            for (entry_path, conflict, kind) in entries {
+                let state = match (conflict, &kind) {
+                    (true, _) => OverlayState::Conflict,
+                    (false, EntryKind::File(hash)) => {
+                        self.file_state(repository, &entry_path, *hash).await
+                    }
+                    (false, EntryKind::Directory(hash)) => {
+                        dir_hashes.insert(entry_path.to_string(), *hash);
+
+                        // Unchanged subtree - reuse its states instead of walking it.
+                        if let Some(state) =
+                            self.reuse(&entry_path, *hash, &mut states, &mut dir_hashes)
+                        {
+                            dir_state = combine(dir_state, state);
+                        } else {
+                            dirs.push(entry_path);
+                        }
+
+                        continue;
+                    }
+                };
+
+                // A conflicting directory gets its state from its contents but is marked as
+                // conflicting as well.
+                if let EntryKind::Directory(hash) = kind {
+                    dir_hashes.insert(entry_path.to_string(), hash);
+                    dirs.push(entry_path.clone());
+                }
+
+                dir_state = combine(dir_state, state);
+                states.insert(entry_path.into_string(), state);
+            }
+
+            states
+                .entry(path.into_string())
+                .and_modify(|state| *state = combine(*state, dir_state))
+                .or_insert(dir_state);
+
+            index += 1;
+        }
+
+        // Propagate the states of the walked directories to their parents, deepest first.
+        for path in dirs.iter().skip(1).rev() {
+            let state = states[path.as_str()];
+            let parent = path.parent().unwrap_or(path).as_str();
+
+            if let Some(parent_state) = states.get_mut(parent) {
+                *parent_state = combine(*parent_state, state);
+            }
+        }
+
+        self.computed = states;
+        self.dirs = dir_hashes;
+
+        Ok(())
+    }
+
+    /// If the directory at `path` hasn't changed since the last computation and nothing in it was
+    /// syncing, copies the previously computed states of it and its subtree into `states` and
+    /// returns its state.
+    fn reuse(
+        &self,
+        path: &Utf8Path,
+        hash: Hash,
+        states: &mut BTreeMap<String, OverlayState>,
+        dir_hashes: &mut BTreeMap<String, Hash>,
+    ) -> Option<OverlayState> {
+        if self.dirs.get(path.as_str()) != Some(&hash) {
+            return None;
+        }
+
+        let state = *self.computed.get(path.as_str())?;
+        let prefix = format!("{path}/");
+        let subtree: Vec<_> = self
+            .computed
+            .range(prefix.clone()..)
+            .take_while(|(entry_path, _)| entry_path.starts_with(&prefix))
+            .collect();
+
+        // Files still syncing can change without the version vector changing.
+        if state == OverlayState::Syncing
+            || subtree
+                .iter()
+                .any(|(_, state)| **state == OverlayState::Syncing)
+        {
+            return None;
+        }
+
+        states.insert(path.to_string(), state);
+        states.extend(
+            subtree
+                .into_iter()
+                .map(|(entry_path, state)| (entry_path.clone(), *state)),
+        );
+        dir_hashes.extend(
+            self.dirs
+                .range(prefix.clone()..)
+                .take_while(|(dir_path, _)| dir_path.starts_with(&prefix))
+                .map(|(dir_path, hash)| (dir_path.clone(), *hash)),
+        );
+
+        Some(state)
+    }
+
+    async fn file_state(
+        &mut self,
+        repository: &Repository,
+        path: &Utf8Path,
+        hash: Hash,
+    ) -> OverlayState {
+        let path = path.as_str();
+
+        if self.synced_files.get(path) == Some(&hash) {
+            return OverlayState::Synced;
+        }
+
+        // Failing to open the file most likely means its first block hasn't been downloaded yet.
+        let synced = match repository.open_file(path).await {
+            Ok(file) => matches!(file.progress().await, Ok(progress) if progress >= file.len()),
+            Err(_) => false,
+        };
+
+        if synced {
+            self.synced_files.insert(path.to_owned(), hash);
+            OverlayState::Synced
+        } else {
+            self.synced_files.remove(path);
+            OverlayState::Syncing
+        }
+    }
+}
+
+/// State of a directory containing entries in the given states.
+fn combine(a: OverlayState, b: OverlayState) -> OverlayState {
+    fn rank(state: OverlayState) -> u8 {
+        match state {
+            OverlayState::Synced => 0,
+            OverlayState::Excluded => 1,
+            OverlayState::Syncing => 2,
+            OverlayState::Conflict => 3,
+        }
+    }
+
+    if rank(a) >= rank(b) {
+        a
+    } else {
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combine_prefers_conflict_over_syncing_over_synced() {
+        use OverlayState::*;
+
+        assert_eq!(combine(Synced, Synced), Synced);
+        assert_eq!(combine(Synced, Syncing), Syncing);
+        assert_eq!(combine(Conflict, Syncing), Conflict);
+        assert_eq!(combine(Syncing, Conflict), Conflict);
+    }
+
+    #[test]
+    fn reuse_unchanged_subtree_unless_syncing() {
+        use OverlayState::*;
+
+        let hash = 0u64.hash();
+        let mut tracker = OverlayTracker::default();
+        tracker.computed = [
+            ("a".to_owned(), Synced),
+            ("a/b".to_owned(), Synced),
+            ("a/b/c".to_owned(), Conflict),
+            ("ab".to_owned(), Syncing),
+        ]
+        .into();
+        tracker.dirs = [("a".to_owned(), hash), ("a/b".to_owned(), hash)].into();
+
+        let mut states = BTreeMap::new();
+        let mut dir_hashes = BTreeMap::new();
+
+        // Changed directory
+        assert_eq!(
+            tracker.reuse(
+                Utf8Path::new("a"),
+                1u64.hash(),
+                &mut states,
+                &mut dir_hashes
+            ),
+            None
+        );
+        assert!(states.is_empty());
+
+        // Unchanged directory. The sibling with a common prefix is not part of the subtree.
+        assert_eq!(
+            tracker.reuse(Utf8Path::new("a"), hash, &mut states, &mut dir_hashes),
+            Some(Synced)
+        );
+        assert_eq!(states.len(), 3);
+        assert_eq!(states.get("a/b/c"), Some(&Conflict));
+        assert_eq!(dir_hashes.len(), 1);
+
+        // Something in the subtree still syncing
+        tracker.computed.insert("a/b/d".to_owned(), Syncing);
+        assert_eq!(
+            tracker.reuse(Utf8Path::new("a"), hash, &mut states, &mut dir_hashes),
+            None
+        );
+    }
+}
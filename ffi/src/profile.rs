@@ -0,0 +1,65 @@
+//! Profiles: additional isolated `State`s (each with its own config dir, network and
+//! repositories) hosted by the same session. Requests are routed to a profile by wrapping them in
+//! `Request::ProfileRequest`, all the other requests go to the session's own (default) state. Useful
+//! for multi-account apps and for testing tools which would otherwise need a separate process per
+//! replica.
+
+use crate::{
+    registry::{Handle, InvalidHandle, SharedRegistry},
+    repository,
+    state::State,
+};
+use state_monitor::StateMonitor;
+use std::{path::PathBuf, sync::Arc};
+
+pub(crate) type ProfileHandle = Handle<Arc<State>>;
+
+pub(crate) struct Profiles {
+    registry: SharedRegistry<Arc<State>>,
+    root_monitor: StateMonitor,
+}
+
+impl Profiles {
+    pub fn new(root_monitor: StateMonitor) -> Self {
+        Self {
+            registry: SharedRegistry::new(),
+            root_monitor,
+        }
+    }
+
+    /// Creates a new profile using the config dir at `configs_path`. Must be called from within
+    /// the runtime.
+    pub fn create(&self, configs_path: PathBuf) -> ProfileHandle {
+        let monitor = self
+            .root_monitor
+            .make_child(format!("Profile {}", configs_path.display()));
+
+        self.registry
+            .insert(Arc::new(State::new(configs_path, monitor)))
+    }
+
+    pub fn get(&self, handle: ProfileHandle) -> Result<Arc<State>, InvalidHandle> {
+        self.registry.get(handle)
+    }
+
+    /// Removes the profile and closes its repositories and network.
+    pub async fn close(&self, handle: ProfileHandle) -> Result<(), InvalidHandle> {
+        let state = self.registry.remove(handle).ok_or(InvalidHandle)?;
+        close(&state).await;
+
+        Ok(())
+    }
+
+    /// Removes and closes all the profiles.
+    pub async fn close_all(&self) {
+        for state in self.registry.remove_all() {
+            close(&state).await;
+        }
+    }
+}
+
+async fn close(state: &State) {
+    ouisync_bridge::network::save_connection_stats(&state.network, &state.config).await;
+    state.network.shutdown().await;
+    repository::close_all_repositories(state).await;
+}
@@ -1,17 +1,24 @@
 use crate::{
-    directory::Directory,
-    file::FileHandle,
+    directory::{Directory, DirectoryPage},
+    file::{FileHandle, OpenFileInfo},
+    metrics::SlowOp,
+    profile::ProfileHandle,
     registry::Handle,
     repository::{MetadataEdit, RepositoryHandle},
     state::TaskHandle,
 };
-use camino::Utf8PathBuf;
-use ouisync_bridge::network::NetworkDefaults;
+use camino::{Utf8Path, Utf8PathBuf};
+use ouisync_bridge::{folder_sync::ConflictPolicy, network::NetworkDefaults};
 use ouisync_lib::{
-    crypto::PasswordSalt,
-    network::{NatBehavior, TrafficStats},
-    AccessChange, AccessMode, LocalSecret, PeerAddr, PeerInfo, Progress, SetLocalSecret,
-    ShareToken,
+    crypto::{sign::PublicKey, PasswordSalt},
+    network::{
+        BindStatus, ConnectionStats, MirrorReceipt, NatBehavior, PeerProtocolTrace, PeerSource,
+        TrafficStats,
+    },
+    AccessChange, AccessMode, AccessNotice, BlockPresence, Conflicts, ContributionStats,
+    DbRecovery, DirectoryLimits, EntryCursor, EntryDiff, LocalSecret, PeerAddr, PeerInfo, Progress,
+    RepositoryHealth, SetLocalSecret, ShareOrigin, ShareToken, SnapshotInfo, SyncExplanation,
+    UnreplicatedChanges, VersionVector,
 };
 use serde::{Deserialize, Serialize};
 use state_monitor::{MonitorId, StateMonitor};
@@ -26,6 +33,17 @@ use thiserror::Error;
 #[serde(rename_all = "snake_case")]
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum Request {
+    /// Negotiates the protocol version (see `ouisync_bridge::protocol::PROTOCOL_VERSION`). Should
+    /// be the first request sent by the client. Returns the negotiated version, which is the lower
+    /// of `version` and the latest version supported by this library. Clients that don't send it
+    /// use version 0.
+    ///
+    /// Regardless of the version, unknown fields in the requests are ignored and missing optional
+    /// fields default to `None`. New fields should therefore be added as optional (or with
+    /// `#[serde(default)]`) so older clients keep working.
+    Handshake {
+        version: u64,
+    },
     RepositoryCreate {
         path: Utf8PathBuf,
         read_secret: Option<SetLocalSecret>,
@@ -37,7 +55,25 @@ pub(crate) enum Request {
         secret: Option<LocalSecret>,
     },
     RepositoryClose(RepositoryHandle),
-    RepositorySubscribe(RepositoryHandle),
+    /// Close the repository immediately without waiting for the ongoing operations: the open
+    /// files of the repository are dropped without flushing (a `HandleInvalidated` notification is
+    /// sent for each of them), the in-flight store operations are aborted and the store is closed.
+    /// Use when the storage is about to disappear (e.g., SD card ejection).
+    RepositoryForceClose(RepositoryHandle),
+    /// Subscribe to the repository change notifications. If `since` is set, the notifications of
+    /// the changes that happened since that cursor (obtained with `RepositoryEventCursor`) are
+    /// replayed first. This allows to subscribe after triggering an operation without missing the
+    /// notifications caused by it.
+    RepositorySubscribe {
+        repository: RepositoryHandle,
+        since: Option<u64>,
+    },
+    /// Subscribe to the overlay icon states (synced, syncing, conflict, excluded) of the entries in
+    /// the repository. The first notification contains the states of all the entries, the
+    /// subsequent ones only the changed ones (see `overlay::subscribe`).
+    RepositoryOverlayStateSubscribe(RepositoryHandle),
+    /// Current cursor of the repository events, to be used as `since` in `RepositorySubscribe`.
+    RepositoryEventCursor(RepositoryHandle),
     RepositoryIsSyncEnabled(RepositoryHandle),
     RepositorySetSyncEnabled {
         repository: RepositoryHandle,
@@ -50,6 +86,24 @@ pub(crate) enum Request {
         read: Option<AccessChange>,
         write: Option<AccessChange>,
     },
+    /// Change the local secret protecting the stored read and/or write keys from `old` to `new`
+    /// without re-encrypting the content (see `ouisync_lib::Repository::change_local_secret`).
+    RepositorySetLocalSecrets {
+        repository: RepositoryHandle,
+        old: LocalSecret,
+        new: SetLocalSecret,
+    },
+    /// Create a token which unlocks the repository in its current access mode without the local
+    /// secret, to be stored behind the platform biometric APIs. Creating a new token revokes the
+    /// previous one. See `RepositoryOpenWithUnlockToken`.
+    RepositoryCreateUnlockToken(RepositoryHandle),
+    RepositoryRevokeUnlockToken(RepositoryHandle),
+    /// Open the repository (if not open yet) and unlock it with the token created by
+    /// `RepositoryCreateUnlockToken`.
+    RepositoryOpenWithUnlockToken {
+        path: Utf8PathBuf,
+        token: String,
+    },
     RepositoryCredentials(RepositoryHandle),
     RepositorySetCredentials {
         repository: RepositoryHandle,
@@ -63,6 +117,9 @@ pub(crate) enum Request {
     },
     RepositoryInfoHash(RepositoryHandle),
     RepositoryDatabaseId(RepositoryHandle),
+    /// What was done to recover the repository database when it was opened, in case it hadn't been
+    /// closed cleanly before (e.g., the app crashed).
+    RepositoryDbRecovery(RepositoryHandle),
     RepositoryEntryType {
         repository: RepositoryHandle,
         path: Utf8PathBuf,
@@ -82,13 +139,58 @@ pub(crate) enum Request {
         repository: RepositoryHandle,
         enabled: bool,
     },
+    /// Routing tags of the repository (e.g., "work", "home"). See `NetworkSetTagPeerSources`.
+    RepositoryRoutingTags(RepositoryHandle),
+    RepositorySetRoutingTags {
+        repository: RepositoryHandle,
+        tags: Vec<String>,
+    },
     RepositoryCreateShareToken {
         repository: RepositoryHandle,
         secret: Option<LocalSecret>,
         access_mode: AccessMode,
         name: Option<String>,
     },
+    /// Split the write secrets of the repository into `count` shares any `threshold` of which are
+    /// needed to recover them, for printing or storing at different places. `secret` is needed if
+    /// the write access is locked. Returns the shares as strings.
+    RepositorySplitSecrets {
+        repository: RepositoryHandle,
+        secret: Option<LocalSecret>,
+        threshold: u8,
+        count: u8,
+    },
+    /// Recover the write secrets from the shares produced by `RepositorySplitSecrets`. Returns
+    /// them as a write share token which can be used to re-create the repository.
+    RepositoryRecoverSecrets {
+        shares: Vec<String>,
+    },
+    /// Create a one-time invitation (a blind share token) granting the given access mode. See
+    /// `RepositoryRedeemInvitation`.
+    RepositoryCreateInvitation {
+        repository: RepositoryHandle,
+        access_mode: AccessMode,
+        name: Option<String>,
+    },
+    /// Redeem a one-time invitation with the replica that created it. Waits until that replica is
+    /// connected. Returns the granted access mode. The granted access is not persisted, use
+    /// `RepositorySetAccess` for that.
+    RepositoryRedeemInvitation {
+        repository: RepositoryHandle,
+        share_token: ShareToken,
+    },
     RepositorySyncProgress(RepositoryHandle),
+    /// Waits until all the writes to the repository submitted before this request are committed
+    /// and published (see `ouisync_lib::Repository::barrier`).
+    RepositoryBarrier(RepositoryHandle),
+    RepositoryDirectoryLimits(RepositoryHandle),
+    RepositorySetDirectoryLimits {
+        repository: RepositoryHandle,
+        limits: DirectoryLimits,
+    },
+    /// Returns the default directory limits (`ouisync_lib::DEFAULT_MAX_DIRECTORY_ENTRIES`,
+    /// `DEFAULT_MAX_PATH_DEPTH` and `DEFAULT_MAX_NAME_LENGTH`).
+    DefaultDirectoryLimits,
     RepositoryCreateMirror {
         repository: RepositoryHandle,
         host: String,
@@ -102,6 +204,211 @@ pub(crate) enum Request {
         host: String,
     },
     RepositoryMountAll(PathBuf),
+    /// Like `RepositoryMountAll` but runs in the background and returns a handle whose
+    /// notifications report the progress (see `operation::spawn`).
+    RepositoryMountAllWithProgress(PathBuf),
+    /// Start two-way syncing of the repository with a regular local folder. Returns a handle which
+    /// can be passed to `Unsubscribe` to stop the sync.
+    RepositoryFolderSync {
+        repository: RepositoryHandle,
+        path: PathBuf,
+        policy: ConflictPolicy,
+    },
+    /// Re-open the repositories that were open at the end of the previous session (see
+    /// `restore::restore`). Returns a handle whose notifications report the progress.
+    SessionRestore,
+    /// Like `SessionRestore` but re-opens up to the given number of repositories concurrently.
+    SessionRestoreWithParallelism(u32),
+    /// Like `SessionRestore` but only registers the repositories without opening them. Each one
+    /// gets opened on first `RepositoryOpen`. Returns their store paths.
+    SessionRestoreLazy,
+    /// Store paths of the repositories registered by `SessionRestoreLazy` that are not opened yet.
+    SessionListUnopened,
+    /// Open a directory in the read-only joint view of all the open repositories. The root of the
+    /// view contains one directory per repository, named after its store file.
+    SessionOpenJointDirectory(Utf8PathBuf),
+    /// Creates a profile: a separate state (network, repositories, ...) using the config dir at
+    /// `configs_path`, isolated from the session's own one. Returns its handle.
+    ProfileCreate {
+        configs_path: Utf8PathBuf,
+    },
+    /// Closes the profile's repositories and network and removes the profile.
+    ProfileClose(ProfileHandle),
+    /// Handles `request` by the given profile instead of the session's own state. Handles returned
+    /// by it (repositories, files, subscriptions, ...) are valid only within the same profile.
+    ProfileRequest {
+        profile: ProfileHandle,
+        request: Box<Request>,
+    },
+    /// The most recent requests that took at least the slow threshold to handle, oldest first.
+    SessionSlowOps,
+    /// Sets the duration (in milliseconds) after which a request is considered slow and logged.
+    /// `None` restores the default (500 ms).
+    SessionSetSlowOpThreshold(Option<u64>),
+    /// Enables or disables writing a dump of the session state (open repositories, requests in
+    /// flight, peers, recently handled requests) into `crash_dump.txt` in the config dir when the
+    /// app panics or crashes. The setting is persisted. Disabled by default.
+    SessionSetCrashDumpEnabled(bool),
+    /// Labels shown by the frontends. Changes to them are reported by `RepositorySubscribe`.
+    RepositoryDisplayName(RepositoryHandle),
+    RepositorySetDisplayName {
+        repository: RepositoryHandle,
+        name: Option<String>,
+    },
+    RepositoryIcon(RepositoryHandle),
+    RepositorySetIcon {
+        repository: RepositoryHandle,
+        icon: Option<String>,
+    },
+    /// The color is `0xAARRGGBB`.
+    RepositoryColor(RepositoryHandle),
+    RepositorySetColor {
+        repository: RepositoryHandle,
+        color: Option<u32>,
+    },
+    /// Write fencing (see `Repository::set_write_fence`). When enabled, local modifications fail
+    /// with `ErrorCode::Outdated` while `RepositoryIsOutdated` returns `true`.
+    RepositoryIsWriteFenceEnabled(RepositoryHandle),
+    RepositorySetWriteFence {
+        repository: RepositoryHandle,
+        enabled: bool,
+    },
+    RepositoryIsOutdated(RepositoryHandle),
+    /// Minimum free space in bytes on the device holding the repository (see
+    /// `Repository::set_min_free_space`). Below it, local modifications and syncing fail with
+    /// `ErrorCode::StorageFull`.
+    RepositoryMinFreeSpace(RepositoryHandle),
+    RepositorySetMinFreeSpace {
+        repository: RepositoryHandle,
+        bytes: u64,
+    },
+    /// Freeze the repository so an external backup tool can copy its store consistently (see
+    /// `Repository::freeze`). While frozen, the writes wait or, if `fail_fast`, fail with
+    /// `ErrorCode::Store`. Returns whether copying the main database file is enough (otherwise
+    /// the `-wal` file needs to be copied too). Lasts until `RepositoryUnfreeze` or until the
+    /// repository is closed.
+    RepositoryFreeze {
+        repository: RepositoryHandle,
+        fail_fast: bool,
+    },
+    RepositoryUnfreeze(RepositoryHandle),
+    /// Total amount of data this replica served to and downloaded from the peers.
+    RepositoryContributionStats(RepositoryHandle),
+    /// Lists the snapshots (root nodes) of all the branches of the repository, at most
+    /// `limit_per_branch` most recent ones per branch.
+    RepositoryListSnapshots {
+        repository: RepositoryHandle,
+        limit_per_branch: u64,
+    },
+    /// Number of the locally present blocks that no connected peer is known to hold, that is, the
+    /// blocks that would be lost if this device was lost.
+    RepositoryAtRiskBlockCount(RepositoryHandle),
+    /// Tracking of the at risk blocks records which blocks the peers hold, periodically scans the
+    /// local blocks to count the at risk ones and offers those to the peers. Disabled by default.
+    RepositoryIsAtRiskTrackingEnabled(RepositoryHandle),
+    RepositorySetAtRiskTrackingEnabled {
+        repository: RepositoryHandle,
+        enabled: bool,
+    },
+    /// Signals of how well the repository syncs (connected peers, NAT, at risk blocks, ...) with
+    /// the detected issues as machine-readable codes, to show the user why it isn't syncing.
+    RepositoryHealth(RepositoryHandle),
+    /// Explains why the file at `path` is or isn't synced: the versions of it in the branches,
+    /// their missing blocks, how many peers offer or hold them and whether they've been requested.
+    /// For diagnostics only.
+    RepositoryExplainSync {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+    },
+    /// Number of the entries with concurrent versions that couldn't be merged automatically,
+    /// together with some of their paths.
+    RepositoryConflicts(RepositoryHandle),
+    /// How the repository came to this device (created new or from a share token, the original
+    /// access mode, ...), recorded when it was created. `None` for repositories created by older
+    /// versions.
+    RepositoryShareOrigin(RepositoryHandle),
+    /// Local changes not yet observed by any peer, to tell the user whether anything would be lost
+    /// if this device was lost (see `ouisync_lib::Repository::unreplicated_changes`).
+    RepositoryUnreplicatedChanges(RepositoryHandle),
+    /// Publish a notice to the peers that the replicas with the `from` access mode are now limited
+    /// to the `to` one (which must be lower). Requires write access.
+    RepositoryPublishAccessNotice {
+        repository: RepositoryHandle,
+        from: AccessMode,
+        to: AccessMode,
+    },
+    /// The latest access notice published by a writer of the repository, if any.
+    RepositoryAccessNotice(RepositoryHandle),
+    /// Signed receipts from the peers acknowledging they hold complete snapshots of the
+    /// repository. Can be used to prove a mirror actually holds the data.
+    RepositoryMirrorReceipts(RepositoryHandle),
+    /// Publish a directive for the device with the given writer id (branch id) to wipe its local
+    /// copy of the repository when it syncs it. Requires write access.
+    RepositoryPublishWipeDirective {
+        repository: RepositoryHandle,
+        #[serde(with = "as_str")]
+        writer_id: PublicKey,
+    },
+    /// Remove the branch of the given writer (e.g., of a device that will never sync again) and
+    /// reclaim the blocks referenced only by it. Requires write access. Fails with
+    /// `ErrorCode::Outdated` unless the branch has been fully merged into the local one.
+    RepositoryPruneBranch {
+        repository: RepositoryHandle,
+        #[serde(with = "as_str")]
+        writer_id: PublicKey,
+    },
+    /// Scrubbing periodically verifies the locally stored blocks to detect data corruption.
+    /// Corrupted blocks are logged and counted in the repository state monitor.
+    RepositoryIsScrubEnabled(RepositoryHandle),
+    RepositorySetScrubEnabled {
+        repository: RepositoryHandle,
+        enabled: bool,
+    },
+    /// Version of the entry at `path`, to be passed to `RepositoryWaitForVersion` (possibly on
+    /// another device). Should be treated as opaque.
+    RepositoryEntryVersion {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+    },
+    /// Waits until the entry at `path` is at the given version or newer. Returns `true` when it
+    /// is or `false` if the `timeout` (in milliseconds) elapsed first.
+    RepositoryWaitForVersion {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+        version: VersionVector,
+        timeout: Option<u64>,
+    },
+    /// Data of the given kind (e.g., a thumbnail) derived by the app from the current version of
+    /// the file at `path`, or `None` if it needs to be derived (again). See
+    /// `RepositorySetDerivedData`.
+    RepositoryDerivedData {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+        kind: String,
+    },
+    /// Stores data derived from the file at `path`. `version` is the version of the file the data
+    /// was derived from, as returned by `RepositoryEntryVersion` before reading the file.
+    RepositorySetDerivedData {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+        kind: String,
+        version: VersionVector,
+        data: Bytes,
+    },
+    /// Removes all the derived data of the repository.
+    RepositoryClearDerivedData(RepositoryHandle),
+    /// Ids of all the branches of the repository, as hex strings.
+    RepositoryBranches(RepositoryHandle),
+    /// Entries of the directory at `path` that were added, removed or modified in the branch `new`
+    /// compared to the branch `old`. Not recursive.
+    RepositoryDiff {
+        repository: RepositoryHandle,
+        #[serde(with = "as_str")]
+        old: PublicKey,
+        #[serde(with = "as_str")]
+        new: PublicKey,
+        path: Utf8PathBuf,
+    },
     RepositoryGetMetadata {
         repository: RepositoryHandle,
         key: String,
@@ -127,15 +434,45 @@ pub(crate) enum Request {
         repository: RepositoryHandle,
         path: Utf8PathBuf,
     },
+    /// Lists the directory in pages of at most `limit` entries, starting after the `after` cursor
+    /// (or from the beginning if `None`). Pass the `next` cursor of the returned page to get the
+    /// following one. The directory can change between the requests, the listing continues where
+    /// it left off.
+    DirectoryRead {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+        after: Option<EntryCursor>,
+        limit: u64,
+    },
     DirectoryRemove {
         repository: RepositoryHandle,
         path: Utf8PathBuf,
         recursive: bool,
     },
+    /// Like `DirectoryRemove` with `recursive` set but runs in the background and returns a
+    /// handle whose notifications report the progress (see `operation::spawn`).
+    DirectoryRemoveWithProgress {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+    },
     FileOpen {
         repository: RepositoryHandle,
         path: Utf8PathBuf,
     },
+    /// Opens the version of the file at `path` in the given branch, e.g. one of the concurrent
+    /// versions of a conflicted file which `FileOpen` refuses to open.
+    FileOpenVersion {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+        #[serde(with = "as_str")]
+        branch: PublicKey,
+    },
+    /// Opens all the concurrent versions of the file at `path` (e.g., to show a diff of a
+    /// conflicted file) and returns their handles. Use `FileBranch` to tell them apart.
+    FileOpenAllVersions {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+    },
     FileCreate {
         repository: RepositoryHandle,
         path: Utf8PathBuf,
@@ -159,20 +496,57 @@ pub(crate) enum Request {
         len: u64,
     },
     FileLen(FileHandle),
+    /// Id of the branch the open file belongs to, as a hex string.
+    FileBranch(FileHandle),
     FileProgress(FileHandle),
+    /// Which blocks of the file at `path` are present locally, as a bitmap. Doesn't need the file
+    /// to be opened.
+    FileBlockPresence {
+        repository: RepositoryHandle,
+        path: Utf8PathBuf,
+    },
     FileFlush(FileHandle),
+    /// Flush the file in chunks until it's completely flushed or the `timeout` (in milliseconds)
+    /// elapses. Returns whether the file has been completely flushed. If not, the progress made so
+    /// far is persisted and the flush can be resumed by calling this again. Useful in environments
+    /// with strict time limits (e.g., iOS file provider extensions).
+    FileFlushWithin {
+        file: FileHandle,
+        timeout: u64,
+    },
+    /// Bypass the block cache when reading the file. Use for huge one-pass reads (e.g., backups) to
+    /// avoid evicting the cached data of the other open files.
+    FileSetCacheBypass {
+        file: FileHandle,
+        enabled: bool,
+    },
     FileClose(FileHandle),
+    /// Lists the currently open files with the time since they were last accessed. Useful to
+    /// diagnose leaked file handles.
+    ListOpenFiles,
+    /// Sets the maximum number of concurrently open files per repository. `None` means unlimited.
+    /// Opening more files fails with `TooManyOpenFiles`.
+    SetMaxOpenFiles(Option<u32>),
+    /// Sets the time (in seconds) after which a file that hasn't been accessed is closed
+    /// automatically. `None` (the default) means never.
+    SetFileIdleTimeout(Option<u64>),
+    /// Test hook: seeds the generator of the ids of the repositories created afterwards (see
+    /// `ouisync_bridge::repository::set_test_rng_seed`) so integration tests are reproducible.
+    /// `None` restores random ids.
+    SetTestRngSeed(Option<u64>),
     NetworkInit(NetworkDefaults),
     NetworkSubscribe,
+    /// Binds the network listeners. Each field can be a single address or a list of addresses
+    /// (to listen on multiple interfaces).
     NetworkBind {
-        #[serde(with = "as_option_str", default)]
-        quic_v4: Option<SocketAddrV4>,
-        #[serde(with = "as_option_str", default)]
-        quic_v6: Option<SocketAddrV6>,
-        #[serde(with = "as_option_str", default)]
-        tcp_v4: Option<SocketAddrV4>,
-        #[serde(with = "as_option_str", default)]
-        tcp_v6: Option<SocketAddrV6>,
+        #[serde(with = "as_one_or_many_str", default)]
+        quic_v4: Vec<SocketAddrV4>,
+        #[serde(with = "as_one_or_many_str", default)]
+        quic_v6: Vec<SocketAddrV6>,
+        #[serde(with = "as_one_or_many_str", default)]
+        tcp_v4: Vec<SocketAddrV4>,
+        #[serde(with = "as_one_or_many_str", default)]
+        tcp_v6: Vec<SocketAddrV6>,
     },
     NetworkTcpListenerLocalAddrV4,
     NetworkTcpListenerLocalAddrV6,
@@ -189,13 +563,52 @@ pub(crate) enum Request {
     NetworkSetPortForwardingEnabled(bool),
     NetworkIsLocalDiscoveryEnabled,
     NetworkSetLocalDiscoveryEnabled(bool),
+    NetworkVerifyThreads,
+    NetworkSetVerifyThreads(u32),
+    /// Returns the outcome of the last bind for each requested listener.
+    NetworkBindStatus,
+    /// Returns the keep-alive (ping) interval in milliseconds.
+    NetworkKeepAliveInterval,
+    /// Returns the time in milliseconds after which an unresponsive peer is disconnected.
+    NetworkKeepAliveTimeout,
+    /// Sets the keep-alive interval and timeout (both in milliseconds).
+    NetworkSetKeepAlive {
+        interval: u64,
+        timeout: u64,
+    },
+    NetworkIsLanBulkTransferEnabled,
+    /// Enables/disables requesting the blocks in batches from the peers on the local network.
+    /// Applies to the connections established afterwards.
+    NetworkSetLanBulkTransferEnabled(bool),
+    /// Restricts the repositories with the given routing tag to be served only by the peers from
+    /// the given sources (connections and discovery mechanisms). `None` removes the restriction.
+    /// Applies to the connections established afterwards.
+    NetworkSetTagPeerSources {
+        tag: String,
+        sources: Option<Vec<PeerSource>>,
+    },
+    /// Tells the network that the device switched networks (e.g., from WiFi to cellular) so it
+    /// rebinds its sockets and reconnects to the peers.
+    NetworkReconnect,
     NetworkExternalAddrV4,
     NetworkExternalAddrV6,
     NetworkNatBehavior,
+    /// Returns the outcomes of the outgoing connection attempts per NAT behavior.
+    NetworkConnectionStats,
     NetworkTrafficStats,
+    /// Sets how many of the last protocol messages exchanged with each peer are recorded (only
+    /// their kinds and sizes, not the content). Zero (the default) disables the recording and
+    /// discards the recorded messages.
+    NetworkSetProtocolTraceCapacity(u32),
+    /// Returns the protocol messages recorded per peer, see `NetworkSetProtocolTraceCapacity`.
+    NetworkDumpProtocolTrace,
     NetworkShutdown,
     StateMonitorGet(Vec<MonitorId>),
     StateMonitorSubscribe(Vec<MonitorId>),
+    /// Sets the memory ceiling (in bytes) of the session. `None` means unlimited.
+    SetMemoryLimit(Option<u64>),
+    /// Returns the current (estimated) memory usage of the session in bytes.
+    MemoryUsage,
     Unsubscribe(TaskHandle),
     GenerateSaltForSecretKey,
     DeriveSecretKey {
@@ -206,6 +619,79 @@ pub(crate) enum Request {
     GetWritePasswordSalt(RepositoryHandle),
 }
 
+impl Request {
+    /// Name of the request type (e.g., "RepositoryOpen").
+    pub fn name(&self) -> String {
+        // The derived `Debug` writes the variant name first. Stop the formatting as soon as the
+        // fields start to avoid formatting potentially big payloads.
+        struct NameWriter(String);
+
+        impl fmt::Write for NameWriter {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                match s.find(|c: char| !c.is_alphanumeric()) {
+                    Some(end) => {
+                        self.0.push_str(&s[..end]);
+                        Err(fmt::Error)
+                    }
+                    None => {
+                        self.0.push_str(s);
+                        Ok(())
+                    }
+                }
+            }
+        }
+
+        let mut writer = NameWriter(String::new());
+        fmt::write(&mut writer, format_args!("{self:?}")).ok();
+        writer.0
+    }
+
+    /// Path of the entry (or the repository) the request operates on, if any.
+    pub(crate) fn path(&self) -> Option<&Utf8Path> {
+        match self {
+            Self::RepositoryCreate { path, .. }
+            | Self::RepositoryOpen { path, .. }
+            | Self::RepositoryOpenWithUnlockToken { path, .. }
+            | Self::RepositoryEntryType { path, .. }
+            | Self::RepositoryMoveEntry { src: path, .. }
+            | Self::RepositoryEntryVersion { path, .. }
+            | Self::RepositoryWaitForVersion { path, .. }
+            | Self::RepositoryDerivedData { path, .. }
+            | Self::RepositorySetDerivedData { path, .. }
+            | Self::RepositoryDiff { path, .. }
+            | Self::DirectoryCreate { path, .. }
+            | Self::DirectoryOpen { path, .. }
+            | Self::DirectoryRead { path, .. }
+            | Self::DirectoryRemove { path, .. }
+            | Self::DirectoryRemoveWithProgress { path, .. }
+            | Self::FileOpen { path, .. }
+            | Self::FileOpenVersion { path, .. }
+            | Self::FileOpenAllVersions { path, .. }
+            | Self::FileCreate { path, .. }
+            | Self::FileRemove { path, .. }
+            | Self::FileBlockPresence { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Open file the request operates on, if any.
+    pub(crate) fn file(&self) -> Option<FileHandle> {
+        match self {
+            Self::FileRead { file, .. }
+            | Self::FileWrite { file, .. }
+            | Self::FileTruncate { file, .. }
+            | Self::FileFlushWithin { file, .. }
+            | Self::FileSetCacheBypass { file, .. }
+            | Self::FileLen(file)
+            | Self::FileBranch(file)
+            | Self::FileProgress(file)
+            | Self::FileFlush(file)
+            | Self::FileClose(file) => Some(*file),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum Response {
@@ -216,14 +702,36 @@ pub(crate) enum Response {
     U64(u64),
     Bytes(Bytes),
     String(String),
+    Strings(Vec<String>),
     Handle(u64),
     Handles(Vec<u64>),
     Directory(Directory),
+    DirectoryPage(DirectoryPage),
     StateMonitor(StateMonitor),
     Progress(Progress),
+    BlockPresence(BlockPresence),
+    ContributionStats(ContributionStats),
+    MirrorReceipts(Vec<MirrorReceipt>),
     PeerInfos(Vec<PeerInfo>),
     PeerAddrs(#[serde(with = "as_vec_str")] Vec<PeerAddr>),
+    Branches(#[serde(with = "as_vec_str")] Vec<PublicKey>),
+    EntryDiffs(Vec<EntryDiff>),
+    VersionVector(VersionVector),
     TrafficStats(TrafficStats),
+    BindStatus(Vec<BindStatus>),
+    ConnectionStats(ConnectionStats),
+    DirectoryLimits(DirectoryLimits),
+    OpenFiles(Vec<OpenFileInfo>),
+    Snapshots(Vec<SnapshotInfo>),
+    RepositoryHealth(RepositoryHealth),
+    SyncExplanation(SyncExplanation),
+    Conflicts(Conflicts),
+    ShareOrigin(ShareOrigin),
+    UnreplicatedChanges(UnreplicatedChanges),
+    AccessNotice(AccessNotice),
+    DbRecovery(DbRecovery),
+    SlowOps(Vec<SlowOp>),
+    ProtocolTrace(Vec<PeerProtocolTrace>),
 }
 
 impl<T> From<Option<T>> for Response
@@ -303,6 +811,12 @@ impl TryFrom<Response> for String {
     }
 }
 
+impl From<Vec<String>> for Response {
+    fn from(value: Vec<String>) -> Self {
+        Self::Strings(value)
+    }
+}
+
 impl From<StateMonitor> for Response {
     fn from(value: StateMonitor) -> Self {
         Self::StateMonitor(value)
@@ -315,6 +829,12 @@ impl From<Directory> for Response {
     }
 }
 
+impl From<DirectoryPage> for Response {
+    fn from(value: DirectoryPage) -> Self {
+        Self::DirectoryPage(value)
+    }
+}
+
 impl<T> From<Handle<T>> for Response {
     fn from(value: Handle<T>) -> Self {
         Self::Handle(value.id())
@@ -373,6 +893,84 @@ impl From<Progress> for Response {
     }
 }
 
+impl From<ContributionStats> for Response {
+    fn from(value: ContributionStats) -> Self {
+        Self::ContributionStats(value)
+    }
+}
+
+impl From<Vec<MirrorReceipt>> for Response {
+    fn from(value: Vec<MirrorReceipt>) -> Self {
+        Self::MirrorReceipts(value)
+    }
+}
+
+impl From<Vec<SnapshotInfo>> for Response {
+    fn from(value: Vec<SnapshotInfo>) -> Self {
+        Self::Snapshots(value)
+    }
+}
+
+impl From<Conflicts> for Response {
+    fn from(value: Conflicts) -> Self {
+        Self::Conflicts(value)
+    }
+}
+
+impl From<ShareOrigin> for Response {
+    fn from(value: ShareOrigin) -> Self {
+        Self::ShareOrigin(value)
+    }
+}
+
+impl From<AccessNotice> for Response {
+    fn from(value: AccessNotice) -> Self {
+        Self::AccessNotice(value)
+    }
+}
+
+impl From<UnreplicatedChanges> for Response {
+    fn from(value: UnreplicatedChanges) -> Self {
+        Self::UnreplicatedChanges(value)
+    }
+}
+
+impl From<RepositoryHealth> for Response {
+    fn from(value: RepositoryHealth) -> Self {
+        Self::RepositoryHealth(value)
+    }
+}
+
+impl From<SyncExplanation> for Response {
+    fn from(value: SyncExplanation) -> Self {
+        Self::SyncExplanation(value)
+    }
+}
+
+impl From<Vec<SlowOp>> for Response {
+    fn from(value: Vec<SlowOp>) -> Self {
+        Self::SlowOps(value)
+    }
+}
+
+impl From<Vec<PeerProtocolTrace>> for Response {
+    fn from(value: Vec<PeerProtocolTrace>) -> Self {
+        Self::ProtocolTrace(value)
+    }
+}
+
+impl From<DbRecovery> for Response {
+    fn from(value: DbRecovery) -> Self {
+        Self::DbRecovery(value)
+    }
+}
+
+impl From<BlockPresence> for Response {
+    fn from(value: BlockPresence) -> Self {
+        Self::BlockPresence(value)
+    }
+}
+
 impl From<Vec<PeerInfo>> for Response {
     fn from(value: Vec<PeerInfo>) -> Self {
         Self::PeerInfos(value)
@@ -396,6 +994,24 @@ impl From<Vec<PeerAddr>> for Response {
     }
 }
 
+impl From<Vec<PublicKey>> for Response {
+    fn from(value: Vec<PublicKey>) -> Self {
+        Self::Branches(value)
+    }
+}
+
+impl From<VersionVector> for Response {
+    fn from(value: VersionVector) -> Self {
+        Self::VersionVector(value)
+    }
+}
+
+impl From<Vec<EntryDiff>> for Response {
+    fn from(value: Vec<EntryDiff>) -> Self {
+        Self::EntryDiffs(value)
+    }
+}
+
 impl TryFrom<Response> for Vec<PeerAddr> {
     type Error = UnexpectedResponse;
 
@@ -426,6 +1042,30 @@ impl From<TrafficStats> for Response {
     }
 }
 
+impl From<Vec<BindStatus>> for Response {
+    fn from(value: Vec<BindStatus>) -> Self {
+        Self::BindStatus(value)
+    }
+}
+
+impl From<ConnectionStats> for Response {
+    fn from(value: ConnectionStats) -> Self {
+        Self::ConnectionStats(value)
+    }
+}
+
+impl From<DirectoryLimits> for Response {
+    fn from(value: DirectoryLimits) -> Self {
+        Self::DirectoryLimits(value)
+    }
+}
+
+impl From<Vec<OpenFileInfo>> for Response {
+    fn from(value: Vec<OpenFileInfo>) -> Self {
+        Self::OpenFiles(value)
+    }
+}
+
 impl fmt::Debug for Response {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -436,17 +1076,63 @@ impl fmt::Debug for Response {
             Self::U64(value) => f.debug_tuple("U64").field(value).finish(),
             Self::Bytes(_) => write!(f, "Bytes(_)"),
             Self::String(value) => f.debug_tuple("String").field(value).finish(),
+            Self::Strings(value) => f.debug_tuple("Strings").field(value).finish(),
             Self::Handle(value) => f.debug_tuple("Handle").field(value).finish(),
             Self::Handles(value) => f.debug_tuple("Handles").field(value).finish(),
             Self::Directory(_) => write!(f, "Directory(_)"),
+            Self::DirectoryPage(_) => write!(f, "DirectoryPage(_)"),
             Self::StateMonitor(_) => write!(f, "StateMonitor(_)"),
             Self::Progress(value) => f.debug_tuple("Progress").field(value).finish(),
+            Self::BlockPresence(value) => f.debug_tuple("BlockPresence").field(value).finish(),
+            Self::ContributionStats(value) => {
+                f.debug_tuple("ContributionStats").field(value).finish()
+            }
+            Self::MirrorReceipts(value) => f
+                .debug_struct("MirrorReceipts")
+                .field("len", &value.len())
+                .finish(),
             Self::PeerInfos(value) => f
                 .debug_struct("PeerInfos")
                 .field("len", &value.len())
                 .finish(),
             Self::PeerAddrs(value) => f.debug_tuple("PeerAddrs").field(value).finish(),
+            Self::Branches(value) => f.debug_tuple("Branches").field(value).finish(),
+            Self::EntryDiffs(value) => f.debug_tuple("EntryDiffs").field(value).finish(),
+            Self::VersionVector(value) => f.debug_tuple("VersionVector").field(value).finish(),
             Self::TrafficStats(value) => f.debug_tuple("TrafficStats").field(value).finish(),
+            Self::BindStatus(value) => f.debug_tuple("BindStatus").field(value).finish(),
+            Self::ConnectionStats(value) => f.debug_tuple("ConnectionStats").field(value).finish(),
+            Self::DirectoryLimits(value) => f.debug_tuple("DirectoryLimits").field(value).finish(),
+            Self::OpenFiles(value) => f
+                .debug_struct("OpenFiles")
+                .field("len", &value.len())
+                .finish(),
+            Self::Snapshots(value) => f
+                .debug_struct("Snapshots")
+                .field("len", &value.len())
+                .finish(),
+            Self::RepositoryHealth(value) => {
+                f.debug_tuple("RepositoryHealth").field(value).finish()
+            }
+            Self::SyncExplanation(value) => f
+                .debug_struct("SyncExplanation")
+                .field("versions", &value.versions.len())
+                .finish(),
+            Self::Conflicts(value) => f.debug_tuple("Conflicts").field(value).finish(),
+            Self::ShareOrigin(value) => f.debug_tuple("ShareOrigin").field(value).finish(),
+            Self::UnreplicatedChanges(value) => {
+                f.debug_tuple("UnreplicatedChanges").field(value).finish()
+            }
+            Self::AccessNotice(value) => f.debug_tuple("AccessNotice").field(value).finish(),
+            Self::DbRecovery(value) => f.debug_tuple("DbRecovery").field(value).finish(),
+            Self::SlowOps(value) => f
+                .debug_struct("SlowOps")
+                .field("len", &value.len())
+                .finish(),
+            Self::ProtocolTrace(value) => f
+                .debug_struct("ProtocolTrace")
+                .field("peers", &value.len())
+                .finish(),
         }
     }
 }
@@ -479,33 +1165,6 @@ pub mod as_str {
     }
 }
 
-pub mod as_option_str {
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
-    use std::{fmt, str::FromStr};
-
-    pub fn deserialize<'de, D, T>(d: D) -> Result<Option<T>, D::Error>
-    where
-        D: Deserializer<'de>,
-        T: FromStr,
-        T::Err: fmt::Display,
-    {
-        let s = Option::<&str>::deserialize(d)?;
-        if let Some(s) = s {
-            Ok(Some(s.parse().map_err(serde::de::Error::custom)?))
-        } else {
-            Ok(None)
-        }
-    }
-
-    pub fn serialize<T, S>(value: &Option<T>, s: S) -> Result<S::Ok, S::Error>
-    where
-        T: fmt::Display,
-        S: Serializer,
-    {
-        value.as_ref().map(|value| value.to_string()).serialize(s)
-    }
-}
-
 pub mod as_vec_str {
     use serde::{de, ser::SerializeSeq, Deserializer, Serializer};
     use std::{fmt, marker::PhantomData, str::FromStr};
@@ -559,6 +1218,73 @@ pub mod as_vec_str {
     }
 }
 
+/// Like `as_vec_str` but also accepts a single string or nil (for compatibility with fields that
+/// used to be `Option`).
+pub mod as_one_or_many_str {
+    use serde::{de, Deserializer};
+    use std::{fmt, marker::PhantomData, str::FromStr};
+
+    pub use super::as_vec_str::serialize;
+
+    pub fn deserialize<'de, D, T>(d: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        struct Visitor<T>(PhantomData<T>);
+
+        impl<'de, T> de::Visitor<'de> for Visitor<T>
+        where
+            T: FromStr,
+            T::Err: fmt::Display,
+        {
+            type Value = Vec<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "string, sequence of strings or nil")
+            }
+
+            fn visit_unit<E>(self) -> Result<Self::Value, E> {
+                Ok(Vec::new())
+            }
+
+            fn visit_none<E>(self) -> Result<Self::Value, E> {
+                Ok(Vec::new())
+            }
+
+            fn visit_some<D>(self, d: D) -> Result<Self::Value, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                d.deserialize_any(self)
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(vec![v.parse().map_err(E::custom)?])
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut out = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+
+                while let Some(item) = seq.next_element::<&str>()? {
+                    out.push(item.parse().map_err(<A::Error as de::Error>::custom)?);
+                }
+
+                Ok(out)
+            }
+        }
+
+        d.deserialize_any(Visitor(PhantomData))
+    }
+}
+
 /// Simple wrapper for `Vec<u8>` with a custom `Debug` impl that doesn't print the whole content to
 /// prevent spamming logs.
 #[derive(Eq, PartialEq, Serialize, Deserialize)]
@@ -587,7 +1313,7 @@ impl fmt::Debug for Bytes {
 mod tests {
     use super::*;
     use ouisync_lib::{
-        network::{PeerSource, PeerState},
+        network::{ConnectionType, EncryptionInfo, PeerSource, PeerState, TransportEncryption},
         AccessSecrets, Credentials, PeerInfo, SecretRuntimeId,
     };
 
@@ -607,6 +1333,16 @@ mod tests {
                 repository: Handle::from_id(1),
                 credentials: credentials.encode().into(),
             },
+            Request::DirectoryRemoveWithProgress {
+                repository: Handle::from_id(1),
+                path: Utf8PathBuf::from("/foo"),
+            },
+            Request::RepositoryOverlayStateSubscribe(Handle::from_id(1)),
+            Request::SessionOpenJointDirectory(Utf8PathBuf::from("/foo/bar")),
+            Request::ProfileRequest {
+                profile: Handle::from_id(2),
+                request: Box::new(Request::RepositoryClose(Handle::from_id(1))),
+            },
         ];
 
         for orig in origs {
@@ -616,6 +1352,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn request_deserialize_unknown_and_missing_fields() {
+        #[derive(Serialize)]
+        #[serde(rename_all = "snake_case")]
+        enum NewerRequest {
+            RepositoryOpen {
+                path: Utf8PathBuf,
+                // Field unknown to this version.
+                read_only: bool,
+            },
+        }
+
+        let encoded = rmp_serde::to_vec_named(&NewerRequest::RepositoryOpen {
+            path: Utf8PathBuf::from("/tmp/repo.db"),
+            read_only: true,
+        })
+        .unwrap();
+        let decoded: Request = rmp_serde::from_slice(&encoded).unwrap();
+
+        assert_eq!(
+            decoded,
+            Request::RepositoryOpen {
+                path: Utf8PathBuf::from("/tmp/repo.db"),
+                secret: None,
+            }
+        );
+    }
+
+    #[test]
+    fn request_name() {
+        assert_eq!(Request::NetworkSubscribe.name(), "NetworkSubscribe");
+        assert_eq!(
+            Request::RepositoryClose(Handle::from_id(1)).name(),
+            "RepositoryClose"
+        );
+        assert_eq!(
+            Request::RepositoryForceClose(Handle::from_id(1)).name(),
+            "RepositoryForceClose"
+        );
+        assert_eq!(
+            Request::FileWrite {
+                file: Handle::from_id(1),
+                offset: 0,
+                data: vec![0; 1024].into(),
+            }
+            .name(),
+            "FileWrite"
+        );
+    }
+
     #[test]
     fn response_serialize_deserialize() {
         let origs = [
@@ -641,6 +1427,12 @@ mod tests {
                     addr: PeerAddr::Quic(([192, 168, 1, 204], 65535).into()),
                     source: PeerSource::LocalDiscovery,
                     state: PeerState::Connecting,
+                    connection_type: None,
+                    encryption: EncryptionInfo {
+                        transport: TransportEncryption::Tls13,
+                        link_protocol: "Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s".to_owned(),
+                        secure_links: 0,
+                    },
                 },
                 PeerInfo {
                     addr: PeerAddr::Quic(
@@ -648,15 +1440,32 @@ mod tests {
                     ),
                     source: PeerSource::Dht,
                     state: PeerState::Active(SecretRuntimeId::random().public()),
+                    connection_type: Some(ConnectionType::HolePunched),
+                    encryption: EncryptionInfo {
+                        transport: TransportEncryption::Tls13,
+                        link_protocol: "Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s".to_owned(),
+                        secure_links: 2,
+                    },
                 },
             ]),
             Response::PeerAddrs(vec![PeerAddr::Tcp(([192, 168, 1, 234], 45678).into())]),
+            Response::OpenFiles(vec![OpenFileInfo {
+                handle: 2,
+                repository: 1,
+                path: Utf8PathBuf::from("/foo/bar.txt"),
+                idle_secs: 30,
+            }]),
         ];
 
         for orig in origs {
             let encoded = rmp_serde::to_vec(&orig).unwrap();
             let decoded: Response = rmp_serde::from_slice(&encoded).unwrap();
             assert_eq!(decoded, orig);
+
+            // Encoding used since protocol version 1.
+            let encoded = rmp_serde::to_vec_named(&orig).unwrap();
+            let decoded: Response = rmp_serde::from_slice(&encoded).unwrap();
+            assert_eq!(decoded, orig);
         }
     }
 }
@@ -35,6 +35,10 @@ impl<T: 'static> Registry<T> {
     pub fn get(&self, handle: Handle<T>) -> Result<&T, InvalidHandle> {
         self.0.get(&handle).ok_or(InvalidHandle)
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.0.iter().map(|(handle, value)| (*handle, value))
+    }
 }
 
 impl<T> Default for Registry<T> {
@@ -57,6 +61,10 @@ impl<T: 'static> SharedRegistry<T> {
     pub fn remove(&self, handle: Handle<T>) -> Option<T> {
         self.0.write().unwrap().remove(handle)
     }
+
+    pub fn remove_all(&self) -> Vec<T> {
+        self.0.write().unwrap().remove_all()
+    }
 }
 
 impl<T> SharedRegistry<T>
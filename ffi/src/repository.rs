@@ -1,29 +1,64 @@
 use crate::{
+    cache::EntryTypeCache,
     error::Error,
+    events::{self, EventLog},
     registry::{Handle, InvalidHandle, Registry},
     state::{State, TaskHandle},
 };
 use camino::Utf8PathBuf;
-use ouisync_bridge::{protocol::Notification, repository, transport::NotificationSender};
+use deadlock::AsyncMutex;
+use futures_util::{future, StreamExt};
+use ouisync_bridge::{
+    folder_sync::{ConflictPolicy, FolderSync, FolderSyncEvent},
+    protocol::Notification,
+    repository,
+    transport::NotificationSender,
+};
 use ouisync_lib::{
     network::{self, Registration},
-    path, AccessMode, Credentials, Event, LocalSecret, Payload, Progress, Repository,
-    SetLocalSecret, ShareToken,
+    path, AccessMode, AccessSecrets, Credentials, DirectoryLimits, Event, FreezeGuard, FreezeMode,
+    JointRepositoryView, LocalSecret, Payload, Progress, Repository, RepositoryHealth, SecretShare,
+    SetLocalSecret, ShareToken, UnlockToken, VersionVector,
 };
+use scoped_task::ScopedJoinHandle;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{hash_map::Entry, HashMap},
     mem,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    pin::pin,
     sync::{Arc, RwLock as BlockingRwLock},
+    time::Duration,
 };
 use thiserror::Error;
-use tokio::sync::{broadcast::error::RecvError, Notify, RwLock as AsyncRwLock};
+use tokio::{
+    sync::{broadcast::error::RecvError, Notify, RwLock as AsyncRwLock},
+    time,
+};
 
 pub(crate) struct RepositoryHolder {
     pub store_path: PathBuf,
     pub repository: Arc<Repository>,
     pub registration: AsyncRwLock<Option<Registration>>,
+    pub entry_type_cache: EntryTypeCache,
+    pub event_log: EventLog,
+    // Set while frozen with `RepositoryFreeze`.
+    pub freeze: AsyncMutex<Option<FreezeGuard>>,
+    _wipe_watcher: ScopedJoinHandle<()>,
+}
+
+impl RepositoryHolder {
+    /// Closes the repository, ending its freeze (if any) first as closing would wait for it.
+    pub async fn close(&self) -> Result<(), ouisync_lib::Error> {
+        self.freeze.lock().await.take();
+        self.repository.close().await
+    }
+
+    /// Like `close` but doesn't wait for the ongoing operations (see `Repository::force_close`).
+    pub async fn force_close(&self) {
+        self.freeze.lock().await.take();
+        self.repository.force_close();
+    }
 }
 
 pub(crate) type RepositoryHandle = Handle<Arc<RepositoryHolder>>;
@@ -37,7 +72,7 @@ pub(crate) struct RegistrationRequired;
 pub(crate) struct EntryChanged;
 
 pub(crate) async fn create(
-    state: &State,
+    state: &Arc<State>,
     store_path: PathBuf,
     local_read_secret: Option<SetLocalSecret>,
     local_write_secret: Option<SetLocalSecret>,
@@ -52,19 +87,29 @@ pub(crate) async fn create(
         share_token,
         &state.config,
         &state.repos_monitor,
+        &state.memory,
     )
     .await?;
 
     let holder = RepositoryHolder {
+        _wipe_watcher: watch_wiped(state, store_path.clone(), &repository),
         store_path,
+        entry_type_cache: EntryTypeCache::new(&repository),
+        event_log: EventLog::new(&repository),
         repository: Arc::new(repository),
         registration: AsyncRwLock::new(None),
+        freeze: AsyncMutex::new(None),
     };
 
     state
         .mounter
         .mount(&holder.store_path, &holder.repository)?;
 
+    state
+        .session_store
+        .repository_opened(&holder.store_path)
+        .await;
+
     let handle = entry.insert(holder);
 
     Ok(handle)
@@ -72,7 +117,7 @@ pub(crate) async fn create(
 
 /// Opens an existing repository.
 pub(crate) async fn open(
-    state: &State,
+    state: &Arc<State>,
     store_path: PathBuf,
     local_secret: Option<LocalSecret>,
 ) -> Result<RepositoryHandle, Error> {
@@ -92,26 +137,63 @@ pub(crate) async fn open(
         RepositoryEntry::Vacant(entry) => entry,
     };
 
+    let lazy = entry.lazy();
+
     let repository = repository::open(
         store_path.clone(),
         local_secret,
         &state.config,
         &state.repos_monitor,
+        &state.memory,
     )
     .await?;
 
     let holder = RepositoryHolder {
+        _wipe_watcher: watch_wiped(state, store_path.clone(), &repository),
         store_path,
+        entry_type_cache: EntryTypeCache::new(&repository),
+        event_log: EventLog::new(&repository),
         repository: Arc::new(repository),
         registration: AsyncRwLock::new(None),
+        freeze: AsyncMutex::new(None),
     };
 
     state
         .mounter
         .mount(&holder.store_path, &holder.repository)?;
 
+    state
+        .session_store
+        .repository_opened(&holder.store_path)
+        .await;
+
     let handle = entry.insert(holder);
 
+    // First access of a lazily restored repository - finish restoring it.
+    if lazy.is_some_and(|lazy| lazy.sync_enabled) {
+        set_sync_enabled(state, handle, true).await?;
+    }
+
+    Ok(handle)
+}
+
+/// Opens the repository (if not open yet) and unlocks it with the unlock token (see
+/// `Repository::create_unlock_token`).
+pub(crate) async fn open_with_unlock_token(
+    state: &Arc<State>,
+    store_path: PathBuf,
+    token: String,
+) -> Result<RepositoryHandle, Error> {
+    let token: UnlockToken = token.parse()?;
+    let handle = open(state, store_path, None).await?;
+
+    state
+        .repositories
+        .get(handle)?
+        .repository
+        .unlock_with_token(&token)
+        .await?;
+
     Ok(handle)
 }
 
@@ -123,7 +205,7 @@ async fn ensure_vacant_entry(
         match state.repositories.entry(store_path.clone()).await {
             RepositoryEntry::Occupied(handle) => {
                 if let Some(holder) = state.repositories.remove(handle) {
-                    holder.repository.close().await?;
+                    holder.close().await?;
                 }
             }
             RepositoryEntry::Vacant(entry) => return Ok(entry),
@@ -131,22 +213,99 @@ async fn ensure_vacant_entry(
     }
 }
 
+// Closes and deregisters the repository once it's been wiped by a wipe directive (see
+// `Repository::publish_wipe_directive`). Its store files are already deleted by then.
+fn watch_wiped(
+    state: &Arc<State>,
+    store_path: PathBuf,
+    repository: &Repository,
+) -> ScopedJoinHandle<()> {
+    let state = Arc::downgrade(state);
+    let mut rx = repository.subscribe();
+
+    scoped_task::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(Event {
+                    payload: Payload::Wiped,
+                    ..
+                }) => break,
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            }
+        }
+
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+
+        let Some(handle) = state.repositories.find(&store_path) else {
+            return;
+        };
+
+        if let Err(error) = close(&state, handle).await {
+            tracing::warn!(?error, ?store_path, "Failed to close wiped repository");
+        }
+    })
+}
+
 /// Closes a repository.
 pub(crate) async fn close(state: &State, handle: RepositoryHandle) -> Result<(), Error> {
     if let Some(holder) = state.repositories.remove(handle) {
-        holder.repository.close().await?;
+        holder.close().await?;
         state.mounter.unmount(&holder.store_path)?;
+        state
+            .session_store
+            .repository_closed(&holder.store_path)
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Closes the repository without waiting for its open files and ongoing operations. The open files
+/// are dropped without being flushed and a `HandleInvalidated` notification is sent for each of
+/// them. The in-flight operations are interrupted and the store is closed in the background.
+pub(crate) async fn force_close(
+    state: &State,
+    notification_tx: &NotificationSender,
+    handle: RepositoryHandle,
+) -> Result<(), Error> {
+    let Some(holder) = state.repositories.remove(handle) else {
+        return Ok(());
+    };
+
+    for (file, _) in state.files.remove_all_in(handle) {
+        notification_tx
+            .send((file.id(), Notification::HandleInvalidated))
+            .await
+            .ok();
+    }
+
+    // Unmount first so the filesystem stops issuing new operations.
+    if let Err(error) = state.mounter.unmount(&holder.store_path) {
+        tracing::warn!(
+            "Failed to unmount repository \"{:?}\": {error:?}",
+            holder.store_path
+        );
     }
 
+    holder.force_close().await;
+    state
+        .session_store
+        .repository_closed(&holder.store_path)
+        .await;
+
     Ok(())
 }
 
 /// Called when the session is closed and the user has not closed some or all the open
-/// repositories.
+/// repositories. The repositories are kept in the persisted session state so they can be restored
+/// in the next session.
 pub async fn close_all_repositories(state: &State) {
     // Best effort: if some operation fails, continue with the rest.
     for holder in state.repositories.remove_all() {
-        if let Err(error) = holder.repository.close().await {
+        if let Err(error) = holder.close().await {
             tracing::warn!(
                 "Failed to close repository \"{:?}\": {error:?}",
                 holder.store_path
@@ -161,6 +320,17 @@ pub async fn close_all_repositories(state: &State) {
     }
 }
 
+pub(crate) async fn health(
+    state: &State,
+    handle: RepositoryHandle,
+) -> Result<RepositoryHealth, Error> {
+    let holder = state.repositories.get(handle)?;
+    let sync_enabled = holder.registration.read().await.is_some();
+    let nat_behavior = state.network.nat_behavior().await;
+
+    Ok(holder.repository.health(sync_enabled, nat_behavior).await?)
+}
+
 pub(crate) async fn is_sync_enabled(
     state: &State,
     handle: RepositoryHandle,
@@ -190,6 +360,44 @@ pub(crate) async fn set_sync_enabled(
         holder.registration.write().await.take();
     }
 
+    state
+        .session_store
+        .sync_enabled_changed(&holder.store_path, enabled)
+        .await;
+
+    Ok(())
+}
+
+/// Freezes the repository (see `Repository::freeze`) until `unfreeze` is called or the repository
+/// is closed. Returns whether copying the main database file is enough (see
+/// `FreezeGuard::checkpointed`). Does nothing but return that if already frozen.
+pub(crate) async fn freeze(
+    state: &State,
+    handle: RepositoryHandle,
+    fail_fast: bool,
+) -> Result<bool, Error> {
+    let holder = state.repositories.get(handle)?;
+    let mut freeze = holder.freeze.lock().await;
+
+    if let Some(guard) = &*freeze {
+        return Ok(guard.checkpointed());
+    }
+
+    let mode = if fail_fast {
+        FreezeMode::FailFast
+    } else {
+        FreezeMode::Queue
+    };
+
+    let guard = holder.repository.freeze(mode).await?;
+    let checkpointed = guard.checkpointed();
+    *freeze = Some(guard);
+
+    Ok(checkpointed)
+}
+
+pub(crate) async fn unfreeze(state: &State, handle: RepositoryHandle) -> Result<(), Error> {
+    state.repositories.get(handle)?.freeze.lock().await.take();
     Ok(())
 }
 
@@ -268,11 +476,43 @@ pub(crate) async fn entry_type(
 ) -> Result<Option<u8>, Error> {
     let holder = state.repositories.get(handle)?;
 
-    match holder.repository.lookup_type(path).await {
-        Ok(entry_type) => Ok(Some(entry_type.into())),
-        Err(ouisync_lib::Error::EntryNotFound) => Ok(None),
-        Err(error) => Err(error.into()),
+    if let Some(entry_type) = holder.entry_type_cache.get(&holder.repository, &path) {
+        return Ok(entry_type.map(Into::into));
+    }
+
+    let entry_type = match holder.repository.lookup_type(&path).await {
+        Ok(entry_type) => Some(entry_type),
+        Err(ouisync_lib::Error::EntryNotFound) => None,
+        Err(error) => return Err(error.into()),
+    };
+
+    holder.entry_type_cache.insert(path, entry_type);
+
+    Ok(entry_type.map(Into::into))
+}
+
+/// Waits until the entry at `path` is at `version` or newer. Returns `false` if `timeout_ms` elapses
+/// first.
+pub(crate) async fn wait_for_version(
+    state: &State,
+    handle: RepositoryHandle,
+    path: Utf8PathBuf,
+    version: VersionVector,
+    timeout_ms: Option<u64>,
+) -> Result<bool, Error> {
+    let holder = state.repositories.get(handle)?;
+    let wait = holder.repository.wait_for_entry_version(&path, &version);
+
+    if let Some(timeout_ms) = timeout_ms {
+        match time::timeout(Duration::from_millis(timeout_ms), wait).await {
+            Ok(result) => result?,
+            Err(_) => return Ok(false),
+        }
+    } else {
+        wait.await?
     }
+
+    Ok(true)
 }
 
 /// Move/rename entry from src to dst.
@@ -294,29 +534,41 @@ pub(crate) async fn move_entry(
     Ok(())
 }
 
-/// Subscribe to change notifications from the repository.
+/// Returns the cursor to pass to `subscribe` to receive also the notifications of the events that
+/// happen between now and the subscription.
+pub(crate) fn event_cursor(state: &State, handle: RepositoryHandle) -> Result<u64, Error> {
+    Ok(state.repositories.get(handle)?.event_log.cursor())
+}
+
+/// Subscribe to change notifications from the repository. If `since` is given, the notifications
+/// of the events that happened since that cursor (see `event_cursor`) are replayed first.
 pub(crate) fn subscribe(
     state: &State,
     notification_tx: &NotificationSender,
     repository_handle: RepositoryHandle,
+    since: Option<u64>,
 ) -> Result<TaskHandle, Error> {
     let holder = state.repositories.get(repository_handle)?;
 
-    let mut notification_rx = holder.repository.subscribe();
+    // Subscribe before computing the replay so no event is missed.
+    let notifications = holder
+        .repository
+        .events()
+        .filter(|event| future::ready(events::is_notifiable(event)));
+    let replay_count = since.map_or(0, |since| holder.event_log.replay_count(since));
     let notification_tx = notification_tx.clone();
 
-    let handle = state.spawn_task(|id| async move {
-        loop {
-            match notification_rx.recv().await {
-                Ok(Event {
-                    payload: Payload::BranchChanged(_) | Payload::BlockReceived { .. },
-                    ..
-                }) => (),
-                Ok(Event { .. }) => continue,
-                Err(RecvError::Lagged(_)) => (),
-                Err(RecvError::Closed) => break,
-            }
+    let handle = state.spawn_task(move |id| async move {
+        for _ in 0..replay_count {
+            notification_tx
+                .send((id, Notification::Repository))
+                .await
+                .ok();
+        }
+
+        let mut notifications = pin!(notifications);
 
+        while notifications.next().await.is_some() {
             notification_tx
                 .send((id, Notification::Repository))
                 .await
@@ -387,6 +639,41 @@ pub(crate) async fn set_pex_enabled(
     Ok(())
 }
 
+pub(crate) async fn routing_tags(
+    state: &State,
+    handle: RepositoryHandle,
+) -> Result<Vec<String>, Error> {
+    Ok(state
+        .repositories
+        .get(handle)?
+        .registration
+        .read()
+        .await
+        .as_ref()
+        .ok_or(RegistrationRequired)?
+        .tags()
+        .into_iter()
+        .collect())
+}
+
+pub(crate) async fn set_routing_tags(
+    state: &State,
+    handle: RepositoryHandle,
+    tags: Vec<String>,
+) -> Result<(), Error> {
+    state
+        .repositories
+        .get(handle)?
+        .registration
+        .read()
+        .await
+        .as_ref()
+        .ok_or(RegistrationRequired)?
+        .set_tags(tags.into_iter().collect())
+        .await;
+    Ok(())
+}
+
 /// The `local_secret` parameter is optional, if `None` the current access level of the opened
 /// repository is used. If provided, the highest access level that the local_secret can unlock is
 /// used.
@@ -403,6 +690,77 @@ pub(crate) async fn create_share_token(
     Ok(token)
 }
 
+/// Splits the write secrets of the repository into `count` shares (as strings for printing) any
+/// `threshold` of which recover them (see `recover_secrets`). The `local_secret` is needed if the
+/// write access is locked.
+pub(crate) async fn split_secrets(
+    state: &State,
+    repository: RepositoryHandle,
+    local_secret: Option<LocalSecret>,
+    threshold: u8,
+    count: u8,
+) -> Result<Vec<String>, Error> {
+    let holder = state.repositories.get(repository)?;
+    let secrets = if let Some(local_secret) = local_secret {
+        holder.repository.unlock_secrets(local_secret).await?
+    } else {
+        holder.repository.secrets()
+    };
+    let secrets = secrets
+        .into_write_secrets()
+        .ok_or(ouisync_lib::Error::PermissionDenied)?;
+
+    let shares = ouisync_lib::split_write_secrets(&secrets, threshold, count)?;
+
+    Ok(shares.iter().map(ToString::to_string).collect())
+}
+
+/// Recovers the write secrets from the shares produced by `split_secrets` and returns them as a
+/// write share token, which can then be used to re-create the repository.
+pub(crate) fn recover_secrets(shares: Vec<String>) -> Result<String, Error> {
+    let shares = shares
+        .iter()
+        .map(|share| share.parse())
+        .collect::<Result<Vec<SecretShare>, _>>()?;
+    let secrets = ouisync_lib::combine_secret_shares(&shares)?;
+
+    Ok(ShareToken::from(AccessSecrets::Write(secrets)).to_string())
+}
+
+pub(crate) async fn create_invitation(
+    state: &State,
+    handle: RepositoryHandle,
+    access_mode: AccessMode,
+    name: Option<String>,
+) -> Result<String, Error> {
+    let token = state
+        .repositories
+        .get(handle)?
+        .repository
+        .create_invitation(access_mode)
+        .await?;
+    let token = if let Some(name) = name {
+        token.with_name(name)
+    } else {
+        token
+    };
+
+    Ok(token.to_string())
+}
+
+pub(crate) async fn redeem_invitation(
+    state: &State,
+    handle: RepositoryHandle,
+    share_token: ShareToken,
+) -> Result<u8, Error> {
+    let holder = state.repositories.get(handle)?;
+    Ok(holder
+        .repository
+        .redeem_invitation(&share_token)
+        .await?
+        .into())
+}
+
 /// Returns the syncing progress.
 pub(crate) async fn sync_progress(
     state: &State,
@@ -416,6 +774,32 @@ pub(crate) async fn sync_progress(
         .await?)
 }
 
+pub(crate) fn directory_limits(
+    state: &State,
+    handle: RepositoryHandle,
+) -> Result<DirectoryLimits, Error> {
+    Ok(state
+        .repositories
+        .get(handle)?
+        .repository
+        .directory_limits())
+}
+
+pub(crate) async fn set_directory_limits(
+    state: &State,
+    handle: RepositoryHandle,
+    limits: DirectoryLimits,
+) -> Result<(), Error> {
+    state
+        .repositories
+        .get(handle)?
+        .repository
+        .set_directory_limits(limits)
+        .await?;
+
+    Ok(())
+}
+
 /// Create mirrored repository on the given server
 pub(crate) async fn create_mirror(
     state: &State,
@@ -459,13 +843,58 @@ pub(crate) async fn mirror_exists(
     )
 }
 
-/// Mount all opened repositories
-pub(crate) async fn mount_root(state: &State, mount_point: PathBuf) -> Result<(), Error> {
-    state.mounter.mount_root(mount_point).await?;
+/// Mount all opened repositories. `on_progress` is called with the number of repositories mounted
+/// so far and the total number of repositories.
+pub(crate) async fn mount_root(
+    state: &State,
+    mount_point: PathBuf,
+    on_progress: impl FnMut(u64, u64),
+) -> Result<(), Error> {
+    state
+        .mounter
+        .mount_root(mount_point.clone(), on_progress)
+        .await?;
+    state.session_store.mount_root_changed(&mount_point).await;
 
     Ok(())
 }
 
+/// Start syncing the repository with the local folder at `path`. The sync keeps running until the
+/// returned task is unsubscribed. Progress and conflicts are reported as notifications.
+pub(crate) fn folder_sync(
+    state: &State,
+    notification_tx: &NotificationSender,
+    handle: RepositoryHandle,
+    path: PathBuf,
+    policy: ConflictPolicy,
+) -> Result<TaskHandle, Error> {
+    let holder = state.repositories.get(handle)?;
+    let state_dir = state.config.dir().join("folder_sync");
+    let notification_tx = notification_tx.clone();
+
+    let handle = state.spawn_task(move |id| async move {
+        let notification_tx = &notification_tx;
+
+        FolderSync::new(&holder.repository, path, &state_dir, policy)
+            .run(move |event| async move {
+                // Progress is best effort (the same as in `ProgressReporter::report`) as it's
+                // superseded by the next report anyway. The other events are awaited, which pauses
+                // the sync until the client catches up.
+                let best_effort = matches!(event, FolderSyncEvent::Progress { .. });
+                let notification = (id, Notification::FolderSync(event));
+
+                if best_effort {
+                    notification_tx.try_send(notification).ok();
+                } else {
+                    notification_tx.send(notification).await.ok();
+                }
+            })
+            .await
+    });
+
+    Ok(handle)
+}
+
 /// Reads a metadata entry
 pub(crate) async fn metadata_get(
     state: &State,
@@ -558,6 +987,19 @@ impl Repositories {
                             // The repo already exists.
                             return RepositoryEntry::Occupied(*handle);
                         }
+                        IndexEntry::Lazy(lazy) => {
+                            // The repo is known but not opened yet. Whoever accesses it first
+                            // opens it.
+                            let lazy = *lazy;
+                            *entry.into_mut() = IndexEntry::Reserved(Arc::new(Notify::new()));
+
+                            return RepositoryEntry::Vacant(RepositoryVacantEntry {
+                                inner: &self.inner,
+                                store_path,
+                                lazy: Some(lazy),
+                                inserted: false,
+                            });
+                        }
                     },
                     Entry::Vacant(entry) => {
                         entry.insert(IndexEntry::Reserved(Arc::new(Notify::new())));
@@ -566,6 +1008,7 @@ impl Repositories {
                         return RepositoryEntry::Vacant(RepositoryVacantEntry {
                             inner: &self.inner,
                             store_path,
+                            lazy: None,
                             inserted: false,
                         });
                     }
@@ -576,6 +1019,37 @@ impl Repositories {
         }
     }
 
+    /// Registers a repository whose store hasn't been opened yet. It gets opened on first access
+    /// (see `entry`). Does nothing if the repository is already known.
+    pub fn insert_lazy(&self, store_path: PathBuf, lazy: LazyRepository) {
+        self.inner
+            .write()
+            .unwrap()
+            .index
+            .entry(store_path)
+            .or_insert(IndexEntry::Lazy(lazy));
+    }
+
+    /// Handle of the opened repository with the given store path, if any.
+    pub fn find(&self, store_path: &Path) -> Option<RepositoryHandle> {
+        match self.inner.read().unwrap().index.get(store_path)? {
+            IndexEntry::Existing(handle) => Some(*handle),
+            IndexEntry::Reserved(_) | IndexEntry::Lazy(_) => None,
+        }
+    }
+
+    /// Store paths of the repositories that are known but not opened yet.
+    pub fn list_lazy(&self) -> Vec<PathBuf> {
+        self.inner
+            .read()
+            .unwrap()
+            .index
+            .iter()
+            .filter(|(_, entry)| matches!(entry, IndexEntry::Lazy(_)))
+            .map(|(store_path, _)| store_path.clone())
+            .collect()
+    }
+
     /// Removes the repository regardless of how many handles it has. All outstanding handles
     /// become invalid.
     pub fn remove(&self, handle: RepositoryHandle) -> Option<Arc<RepositoryHolder>> {
@@ -594,6 +1068,36 @@ impl Repositories {
     pub fn get(&self, handle: RepositoryHandle) -> Result<Arc<RepositoryHolder>, InvalidHandle> {
         self.inner.read().unwrap().registry.get(handle).cloned()
     }
+
+    /// All the opened repositories.
+    pub fn list(&self) -> Vec<Arc<RepositoryHolder>> {
+        self.inner
+            .read()
+            .unwrap()
+            .registry
+            .iter()
+            .map(|(_, holder)| holder.clone())
+            .collect()
+    }
+
+    /// Joint view of all the open repositories, each named by the file stem of its store path (the
+    /// same name it's mounted under by `RepositoryMountAll`).
+    pub fn joint_view(&self) -> JointRepositoryView {
+        let inner = self.inner.read().unwrap();
+        let mut view = JointRepositoryView::new();
+
+        for (_, holder) in inner.registry.iter() {
+            let Some(name) = holder.store_path.file_stem().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            if let Err(error) = view.insert(name, holder.repository.clone()) {
+                tracing::warn!(?error, ?holder.store_path, "Repository not added to joint view");
+            }
+        }
+
+        view
+    }
 }
 
 pub(crate) enum RepositoryEntry<'a> {
@@ -604,10 +1108,16 @@ pub(crate) enum RepositoryEntry<'a> {
 pub(crate) struct RepositoryVacantEntry<'a> {
     inner: &'a BlockingRwLock<Inner>,
     store_path: PathBuf,
+    lazy: Option<LazyRepository>,
     inserted: bool,
 }
 
 impl RepositoryVacantEntry<'_> {
+    /// If the repository was registered lazily, returns its deferred state.
+    pub fn lazy(&self) -> Option<LazyRepository> {
+        self.lazy
+    }
+
     pub fn insert(mut self, holder: RepositoryHolder) -> RepositoryHandle {
         let mut inner = self.inner.write().unwrap();
 
@@ -641,6 +1151,13 @@ impl Drop for RepositoryVacantEntry<'_> {
             unreachable!()
         };
 
+        // Failed to open a lazy repo - keep it known so it can be retried.
+        if let Some(lazy) = self.lazy {
+            inner
+                .index
+                .insert(self.store_path.clone(), IndexEntry::Lazy(lazy));
+        }
+
         notify.notify_waiters();
     }
 }
@@ -654,5 +1171,13 @@ struct Inner {
 
 enum IndexEntry {
     Reserved(Arc<Notify>),
+    Lazy(LazyRepository),
     Existing(RepositoryHandle),
 }
+
+/// State of a repository that's known (e.g., from the previous session) but whose store hasn't
+/// been opened yet. Applied when it's opened.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LazyRepository {
+    pub sync_enabled: bool,
+}
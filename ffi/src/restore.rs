@@ -0,0 +1,248 @@
+//! Persisting the set of opened repositories so they can be re-opened in the next session with
+//! `SessionRestore`.
+//!
+//! Only the state not already persisted elsewhere is stored here. In particular the DHT and PEX
+//! flags are stored in the repository metadata and get restored automatically when the
+//! repository is registered with the network (i.e., when its sync is enabled).
+
+use crate::{
+    error::Error,
+    repository::{self, LazyRepository, RepositoryHandle},
+    state::{State, TaskHandle},
+};
+use futures_util::{stream, StreamExt};
+use ouisync_bridge::{
+    config::{ConfigError, ConfigKey, ConfigStore},
+    protocol::{Notification, SessionRestoreEvent},
+    transport::NotificationSender,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::Mutex as AsyncMutex;
+
+/// How many repositories are re-opened concurrently by default.
+pub(crate) const DEFAULT_RESTORE_PARALLELISM: usize = 4;
+
+const SESSION_KEY: ConfigKey<SessionState> = ConfigKey::new(
+    "session",
+    "Repositories opened in the last session. Used to re-open them in the next one.",
+);
+
+#[derive(Default, Serialize, Deserialize, Debug)]
+struct SessionState {
+    /// Mount point of the repositories or `None` if not mounted.
+    mount_root: Option<PathBuf>,
+    repositories: Vec<RepositoryState>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RepositoryState {
+    path: PathBuf,
+    sync_enabled: bool,
+}
+
+impl SessionState {
+    fn find_mut(&mut self, path: &Path) -> Option<&mut RepositoryState> {
+        self.repositories.iter_mut().find(|repo| repo.path == path)
+    }
+}
+
+/// Keeps the persisted session state up to date. All the updates are best effort - failure to
+/// persist them is logged but otherwise ignored.
+pub(crate) struct SessionStore {
+    config: ConfigStore,
+    // Serializes the read-modify-write cycles of the updates.
+    lock: AsyncMutex<()>,
+}
+
+impl SessionStore {
+    pub fn new(config: ConfigStore) -> Self {
+        Self {
+            config,
+            lock: AsyncMutex::new(()),
+        }
+    }
+
+    pub async fn repository_opened(&self, path: &Path) {
+        self.update(|session| {
+            if session.find_mut(path).is_none() {
+                session.repositories.push(RepositoryState {
+                    path: path.to_owned(),
+                    sync_enabled: false,
+                });
+            }
+        })
+        .await
+    }
+
+    pub async fn repository_closed(&self, path: &Path) {
+        self.update(|session| session.repositories.retain(|repo| repo.path != path))
+            .await
+    }
+
+    pub async fn sync_enabled_changed(&self, path: &Path, enabled: bool) {
+        self.update(|session| {
+            if let Some(repo) = session.find_mut(path) {
+                repo.sync_enabled = enabled;
+            }
+        })
+        .await
+    }
+
+    pub async fn mount_root_changed(&self, mount_root: &Path) {
+        self.update(|session| session.mount_root = Some(mount_root.to_owned()))
+            .await
+    }
+
+    async fn load(&self) -> SessionState {
+        match self.config.entry(SESSION_KEY).get().await {
+            Ok(session) => session,
+            Err(ConfigError::NotFound) => SessionState::default(),
+            Err(error) => {
+                tracing::error!(?error, "Failed to load session state");
+                SessionState::default()
+            }
+        }
+    }
+
+    async fn update(&self, f: impl FnOnce(&mut SessionState)) {
+        let _guard = self.lock.lock().await;
+
+        let mut session = self.load().await;
+        f(&mut session);
+
+        if let Err(error) = self.config.entry(SESSION_KEY).set(&session).await {
+            tracing::error!(?error, "Failed to save session state");
+        }
+    }
+}
+
+/// Re-opens the repositories from the previous session, re-enables their sync and re-mounts them.
+/// The repositories are opened without local secret, so they get the access mode that doesn't
+/// require one (use `RepositoryOpen` or `RepositorySetAccessMode` to raise it). Up to
+/// `parallelism` repositories are re-opened concurrently. The progress is reported as
+/// notifications on the returned task, one per repository as soon as it's ready (so not
+/// necessarily in the order they were stored).
+pub(crate) fn restore(
+    state: &Arc<State>,
+    notification_tx: &NotificationSender,
+    parallelism: usize,
+) -> TaskHandle {
+    let notification_tx = notification_tx.clone();
+    let parallelism = parallelism.max(1);
+
+    state.spawn_task({
+        let state = state.clone();
+
+        move |id| async move {
+            let session = state.session_store.load().await;
+
+            // Don't remount if the frontend already mounted the root itself.
+            if let Some(mount_root) = session
+                .mount_root
+                .filter(|_| !state.mounter.is_root_mounted())
+            {
+                if let Err(error) = repository::mount_root(&state, mount_root, |_, _| ()).await {
+                    tracing::error!(?error, "Failed to restore mount root");
+                }
+            }
+
+            let mut restored = 0;
+            let mut failed = 0;
+
+            let mut results = stream::iter(session.repositories)
+                .map(|repo| {
+                    let state = &state;
+                    async move {
+                        let result = restore_repository(state, &repo).await;
+                        (repo, result)
+                    }
+                })
+                .buffer_unordered(parallelism);
+
+            while let Some((repo, result)) = results.next().await {
+                let event = match result {
+                    Ok(handle) => {
+                        restored += 1;
+
+                        SessionRestoreEvent::Restored {
+                            path: repo.path,
+                            repository: handle.id(),
+                        }
+                    }
+                    Err(error) => {
+                        tracing::error!(path = ?repo.path, ?error, "Failed to restore repository");
+                        failed += 1;
+
+                        SessionRestoreEvent::Failed {
+                            path: repo.path,
+                            error: error.message,
+                        }
+                    }
+                };
+
+                notification_tx
+                    .send((id, Notification::SessionRestore(event)))
+                    .await
+                    .ok();
+            }
+
+            notification_tx
+                .send((
+                    id,
+                    Notification::SessionRestore(SessionRestoreEvent::Completed {
+                        restored,
+                        failed,
+                    }),
+                ))
+                .await
+                .ok();
+        }
+    })
+}
+
+/// Like `restore` but doesn't open the repositories, only registers them as known. Each one gets
+/// opened on its first access (`RepositoryOpen`) and its sync re-enabled then if it was enabled in
+/// the previous session. Returns the store paths of the registered repositories.
+pub(crate) async fn restore_lazy(state: &State) -> Vec<PathBuf> {
+    let session = state.session_store.load().await;
+
+    if let Some(mount_root) = session
+        .mount_root
+        .filter(|_| !state.mounter.is_root_mounted())
+    {
+        if let Err(error) = repository::mount_root(state, mount_root, |_, _| ()).await {
+            tracing::error!(?error, "Failed to restore mount root");
+        }
+    }
+
+    session
+        .repositories
+        .into_iter()
+        .map(|repo| {
+            state.repositories.insert_lazy(
+                repo.path.clone(),
+                LazyRepository {
+                    sync_enabled: repo.sync_enabled,
+                },
+            );
+            repo.path
+        })
+        .collect()
+}
+
+async fn restore_repository(
+    state: &Arc<State>,
+    repo: &RepositoryState,
+) -> Result<RepositoryHandle, Error> {
+    let handle = repository::open(state, repo.path.clone(), None).await?;
+
+    if repo.sync_enabled {
+        repository::set_sync_enabled(state, handle, true).await?;
+    }
+
+    Ok(handle)
+}
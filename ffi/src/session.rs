@@ -1,6 +1,8 @@
 use crate::{
+    crash_dump,
     error::{ErrorCode, ToErrorCode},
     handler::Handler,
+    profile::Profiles,
     repository,
     sender::Sender,
     state::State,
@@ -33,6 +35,7 @@ pub struct Session {
 pub(crate) struct Shared {
     pub(crate) runtime: runtime::Runtime,
     pub(crate) state: Arc<State>,
+    pub(crate) profiles: Arc<Profiles>,
     _logger: Logger,
 }
 
@@ -56,11 +59,15 @@ impl Shared {
             .map_err(SessionError::InitializeRuntime)?;
         let _enter = runtime.enter(); // runtime context is needed for some of the following calls
 
+        let profiles = Arc::new(Profiles::new(root_monitor.clone()));
         let state = Arc::new(State::new(configs_path.to_owned(), root_monitor));
 
+        runtime.spawn(crash_dump::restore(state.clone()));
+
         Ok(Arc::new(Self {
             runtime,
             state,
+            profiles,
             _logger: logger,
         }))
     }
@@ -166,7 +173,7 @@ pub(crate) unsafe fn create(
 
     shared
         .runtime
-        .spawn(server.run(Handler::new(shared.state.clone())));
+        .spawn(server.run(Handler::new(shared.state.clone(), shared.profiles.clone())));
 
     Ok(Session { shared, client_tx })
 }
@@ -185,10 +192,14 @@ pub(crate) fn close(session: Session, sender: impl Sender) {
     // Can't drop Runtime from inside a task spawned on it. Spawn a thread and do it there instead.
     thread::spawn(move || {
         let state = shared.state;
-        shared.runtime.block_on(state.network.shutdown());
+        shared.runtime.block_on(async {
+            ouisync_bridge::network::save_connection_stats(&state.network, &state.config).await;
+            state.network.shutdown().await;
+        });
         shared
             .runtime
             .block_on(repository::close_all_repositories(&state));
+        shared.runtime.block_on(shared.profiles.close_all());
         sender.send(Bytes::new());
     });
 }
@@ -209,9 +220,10 @@ pub(crate) fn close_blocking(session: Session) {
 
     shared
         .runtime
-        .block_on(time::timeout(
-            Duration::from_millis(500),
-            state.network.shutdown(),
-        ))
+        .block_on(time::timeout(Duration::from_millis(500), async {
+            ouisync_bridge::network::save_connection_stats(&state.network, &state.config).await;
+            state.network.shutdown().await;
+            shared.profiles.close_all().await;
+        }))
         .ok();
 }
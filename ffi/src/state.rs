@@ -1,11 +1,13 @@
 use crate::{
-    file::FileHolder,
+    file::OpenFiles,
+    metrics::RequestMetrics,
     mounter::Mounter,
     registry::{Handle, SharedRegistry},
     repository::Repositories,
+    restore::SessionStore,
 };
-use ouisync_bridge::{config::ConfigStore, transport};
-use ouisync_lib::network::Network;
+use ouisync_bridge::{config::ConfigStore, network::UserProvidedPeersWatcher, transport};
+use ouisync_lib::{crypto::backend::BackendMonitor, network::Network, MemoryBudget};
 use scoped_task::ScopedJoinHandle;
 use state_monitor::StateMonitor;
 use std::{
@@ -18,14 +20,20 @@ use tokio::sync::{oneshot, OnceCell};
 
 pub(crate) struct State {
     pub config: ConfigStore,
-    pub files: SharedRegistry<Arc<FileHolder>>,
+    pub files: Arc<OpenFiles>,
     pub mounter: Mounter,
     pub network: Network,
     pub remote_client_config: OnceCell<Arc<rustls::ClientConfig>>,
     pub repositories: Repositories,
     pub repos_monitor: StateMonitor,
+    pub memory: MemoryBudget,
     pub root_monitor: StateMonitor,
+    pub request_metrics: RequestMetrics,
+    pub session_store: SessionStore,
     tasks: SharedRegistry<ScopedJoinHandle<()>>,
+    peers_watcher: OnceCell<ScopedJoinHandle<()>>,
+    _crypto_backend: BackendMonitor,
+    _file_reaper: ScopedJoinHandle<()>,
 }
 
 impl State {
@@ -38,17 +46,33 @@ impl State {
         );
 
         let repos_monitor = root_monitor.make_child("Repositories");
+        let memory = MemoryBudget::new(root_monitor.make_child("Memory"));
+        let request_metrics = RequestMetrics::new(root_monitor.make_child("Requests"));
+        let crypto_backend = BackendMonitor::new(root_monitor.make_child("Crypto"));
+        let session_store = SessionStore::new(config.clone());
+
+        let files = Arc::new(OpenFiles::new());
+        let file_reaper = scoped_task::spawn({
+            let files = files.clone();
+            async move { files.run_reaper().await }
+        });
 
         Self {
             config,
-            files: SharedRegistry::new(),
+            files,
             mounter: Mounter::new(),
             network,
             remote_client_config: OnceCell::new(),
             repositories: Repositories::new(),
             repos_monitor,
+            memory,
             root_monitor,
+            request_metrics,
+            session_store,
             tasks: SharedRegistry::new(),
+            peers_watcher: OnceCell::new(),
+            _crypto_backend: crypto_backend,
+            _file_reaper: file_reaper,
         }
     }
 
@@ -59,6 +83,27 @@ impl State {
             .cloned()
     }
 
+    /// Starts applying the user provided peers added or removed by other processes sharing the
+    /// same config dir (see `UserProvidedPeersWatcher`). Does nothing if already started.
+    pub async fn watch_user_provided_peers(self: &Arc<Self>) {
+        self.peers_watcher
+            .get_or_init(|| async {
+                let mut watcher = UserProvidedPeersWatcher::new(self.config.clone()).await;
+                let state = Arc::downgrade(self);
+
+                scoped_task::spawn(async move {
+                    while let Some(change) = watcher.changed().await {
+                        let Some(state) = state.upgrade() else {
+                            break;
+                        };
+
+                        change.apply(&state.network);
+                    }
+                })
+            })
+            .await;
+    }
+
     /// Spawns a task and inserts it into the `tasks` registry. Returns its Registry handle.
     pub fn spawn_task<M, F>(&self, make_task: M) -> TaskHandle
     where
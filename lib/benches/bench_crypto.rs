@@ -0,0 +1,52 @@
+use chacha20::{
+    cipher::{KeyIvInit, StreamCipher},
+    ChaCha20,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ouisync::crypto::backend::Backend;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+criterion_group!(default, hash, cipher);
+criterion_main!(default);
+
+const SIZES: [usize; 2] = [32 * 1024, 1024 * 1024];
+
+fn hash(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("crypto/hash/{}", Backend::get().hash));
+
+    for size in SIZES {
+        let data = random_data(size);
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| blake3::hash(&data))
+        });
+    }
+
+    group.finish();
+}
+
+fn cipher(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("crypto/cipher/{}", Backend::get().cipher));
+
+    let key = [0; 32];
+    let nonce = [0; 12];
+
+    for size in SIZES {
+        let mut data = random_data(size);
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| ChaCha20::new(&key.into(), &nonce.into()).apply_keystream(&mut data))
+        });
+    }
+
+    group.finish();
+}
+
+fn random_data(size: usize) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut data = vec![0; size];
+    rng.fill(&mut data[..]);
+    data
+}
@@ -6,9 +6,18 @@ use rand::{rngs::StdRng, SeedableRng};
 use state_monitor::StateMonitor;
 use tempfile::TempDir;
 use tokio::runtime::Runtime;
-use utils::Actor;
+use utils::{Actor, RepositoryGuard};
 
-criterion_group!(default, write_file, read_file, sync);
+criterion_group!(
+    default,
+    write_file,
+    read_file,
+    directory_commit,
+    sync,
+    index_receive,
+    index_receive_deep_tree,
+    merge
+);
 criterion_main!(default);
 
 fn write_file(c: &mut Criterion) {
@@ -154,3 +163,190 @@ fn sync(c: &mut Criterion) {
     }
     group.finish();
 }
+
+fn directory_commit(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("lib/directory_commit");
+    group.sample_size(10);
+
+    for n in [10, 100, 1000] {
+        group.throughput(Throughput::Elements(n));
+        group.bench_function(BenchmarkId::from_parameter(format!("{n} entries")), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut rng = StdRng::from_entropy();
+                    let base_dir = TempDir::new_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+                    let repo = runtime.block_on(utils::create_repo(
+                        &mut rng,
+                        &base_dir.path().join("repo.db"),
+                        0,
+                        StateMonitor::make_root(),
+                    ));
+                    (base_dir, repo)
+                },
+                |(_base_dir, repo)| {
+                    runtime.block_on(create_empty_files(repo, n));
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+// Sync of many empty files, which is dominated by receiving the index rather than the blocks.
+fn index_receive(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("lib/index_receive");
+    group.sample_size(10);
+
+    for n in [100, 1000] {
+        group.throughput(Throughput::Elements(n));
+        group.bench_function(BenchmarkId::from_parameter(format!("{n} files")), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut rng = StdRng::from_entropy();
+                    let base_dir = TempDir::new_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+
+                    let (reader, writer) = runtime.block_on(async {
+                        let reader = Actor::new(&mut rng, &base_dir.path().join("reader")).await;
+                        let writer = Actor::new(&mut rng, &base_dir.path().join("writer")).await;
+
+                        create_empty_files(&writer.repo, n).await;
+                        reader.connect_to(&writer);
+
+                        (reader, writer)
+                    });
+
+                    (base_dir, reader, writer)
+                },
+                |(_base_dir, reader, writer)| {
+                    runtime.block_on(utils::wait_for_sync(&reader.repo, &writer.repo));
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+// Sync of a deep tree of many small directories. Measures how well the index traversal is pipelined.
+fn index_receive_deep_tree(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("lib/index_receive_deep_tree");
+    group.sample_size(10);
+
+    for depth in [2, 4] {
+        let width = 4;
+
+        group.throughput(Throughput::Elements((width as u64).pow(depth + 1)));
+        group.bench_function(BenchmarkId::from_parameter(format!("depth {depth}")), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut rng = StdRng::from_entropy();
+                    let base_dir = TempDir::new_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+
+                    let (reader, writer) = runtime.block_on(async {
+                        let reader = Actor::new(&mut rng, &base_dir.path().join("reader")).await;
+                        let writer = Actor::new(&mut rng, &base_dir.path().join("writer")).await;
+
+                        create_deep_tree(&writer.repo, Utf8Path::new(""), depth, width).await;
+                        reader.connect_to(&writer);
+
+                        (reader, writer)
+                    });
+
+                    (base_dir, reader, writer)
+                },
+                |(_base_dir, reader, writer)| {
+                    runtime.block_on(utils::wait_for_sync(&reader.repo, &writer.repo));
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+// Both replicas write a file concurrently and then sync, so each of them has to merge the branch of
+// the other one.
+fn merge(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("lib/merge");
+    group.sample_size(10);
+
+    for m in [1, 8] {
+        let file_size = m * 1024 * 1024;
+
+        group.throughput(Throughput::Bytes(2 * file_size));
+        group.bench_function(BenchmarkId::from_parameter(format!("{m} MiB")), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut rng = StdRng::from_entropy();
+                    let base_dir = TempDir::new_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+
+                    let (a, b) = runtime.block_on(async {
+                        let a = Actor::new(&mut rng, &base_dir.path().join("a")).await;
+                        let b = Actor::new(&mut rng, &base_dir.path().join("b")).await;
+
+                        for (actor, name) in [(&a, "a.dat"), (&b, "b.dat")] {
+                            utils::write_file(
+                                &mut rng,
+                                &actor.repo,
+                                Utf8Path::new(name),
+                                file_size as usize,
+                                4096,
+                                false,
+                            )
+                            .await;
+                        }
+
+                        a.connect_to(&b);
+
+                        (a, b)
+                    });
+
+                    (base_dir, a, b)
+                },
+                |(_base_dir, a, b)| {
+                    runtime.block_on(async {
+                        utils::wait_for_sync(&a.repo, &b.repo).await;
+                        utils::wait_for_sync(&b.repo, &a.repo).await;
+                    });
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+async fn create_empty_files(repo: &RepositoryGuard, count: u64) {
+    for i in 0..count {
+        let mut file = repo.create_file(format!("file-{i}.dat")).await.unwrap();
+        file.flush().await.unwrap();
+    }
+}
+
+// Creates a tree of `depth` levels of `width` directories each, with `width` empty files in every
+// leaf directory.
+#[async_recursion::async_recursion]
+async fn create_deep_tree(repo: &RepositoryGuard, parent: &Utf8Path, depth: u32, width: usize) {
+    for i in 0..width {
+        if depth == 0 {
+            let mut file = repo
+                .create_file(parent.join(format!("file-{i}.dat")))
+                .await
+                .unwrap();
+            file.flush().await.unwrap();
+        } else {
+            let path = parent.join(format!("dir-{i}"));
+            repo.create_directory(&path).await.unwrap();
+            create_deep_tree(repo, &path, depth - 1, width).await;
+        }
+    }
+}
@@ -25,6 +25,8 @@ fn main() -> ExitCode {
         .chain((0..options.num_readers).map(|i| ActorId(AccessMode::Read, i)))
         .collect();
     let proto = options.protocol;
+    let num_writers = options.num_writers;
+    let num_readers = options.num_readers;
 
     let mut env = Env::new();
 
@@ -92,10 +94,22 @@ fn main() -> ExitCode {
     let start = Instant::now();
     info!("simulation started");
     drop(env);
-    info!(
-        "simulation completed in {:.3}s",
-        start.elapsed().as_secs_f64()
-    );
+    let elapsed = start.elapsed().as_secs_f64();
+    info!("simulation completed in {:.3}s", elapsed);
+
+    if options.json {
+        let output = serde_json::json!({
+            "protocol": proto.to_string(),
+            "file_size": file_size,
+            "num_writers": num_writers,
+            "num_readers": num_readers,
+            "duration": elapsed,
+            // Total bytes received by all the replicas that didn't write the file.
+            "throughput": (file_size * (num_writers + num_readers - 1) as u64) as f64 / elapsed,
+        });
+
+        println!("{output}");
+    }
 
     ExitCode::SUCCESS
 }
@@ -119,6 +133,11 @@ struct Options {
     #[arg(short, long, value_parser, default_value_t = Proto::Quic)]
     pub protocol: Proto,
 
+    /// Print the result as a single line of JSON (duration in seconds, throughput in bytes per
+    /// second) to stdout, for comparing runs by scripts.
+    #[arg(long)]
+    pub json: bool,
+
     // `cargo bench` passes the `--bench` flag down to the bench binary so we need to accept it even
     // if we don't use it.
     #[arg(
@@ -0,0 +1,344 @@
+//! Escrow of the repository write secrets using Shamir's secret sharing. The secrets are split
+//! into `count` shares (to be printed or stored at different places) any `threshold` of which
+//! reconstruct them while fewer reveal nothing. This gives the users a way to recover the write
+//! access after losing all their devices without having to keep a complete copy of the secrets
+//! anywhere.
+
+use super::WriteSecrets;
+use crate::{
+    crypto::sign::Keypair,
+    error::{Error, Result},
+    repository::RepositoryId,
+};
+use rand::{rngs::OsRng, Rng};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use zeroize::Zeroizing;
+
+const PREFIX: &str = "ouisync-share:";
+const VERSION: u8 = 1;
+const SECRET_SIZE: usize = Keypair::SECRET_KEY_SIZE;
+const ENCODED_SIZE: usize = 1 + RepositoryId::SIZE + 1 + 1 + SECRET_SIZE;
+
+/// One share of the split write secrets of a repository. Converts to and from a string suitable
+/// for printing.
+#[derive(Clone, Eq, PartialEq)]
+pub struct SecretShare {
+    repository_id: RepositoryId,
+    threshold: u8,
+    // Point at which the polynomials are evaluated, never zero.
+    index: u8,
+    value: Zeroizing<[u8; SECRET_SIZE]>,
+}
+
+impl SecretShare {
+    /// Id of the repository whose secrets this is a share of.
+    pub fn repository_id(&self) -> &RepositoryId {
+        &self.repository_id
+    }
+
+    /// Number of shares needed to recover the secrets.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Index of this share, from 1 to the number of shares.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+}
+
+impl fmt::Display for SecretShare {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(ENCODED_SIZE));
+        bytes.push(VERSION);
+        bytes.extend_from_slice(self.repository_id.as_ref());
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.value[..]);
+
+        write!(
+            f,
+            "{}{}",
+            PREFIX,
+            Zeroizing::new(hex::encode(&*bytes)).as_str()
+        )
+    }
+}
+
+impl FromStr for SecretShare {
+    type Err = Error;
+
+    /// Parses the share, ignoring any whitespace and dashes so it can be typed in from a printout
+    /// in any grouping.
+    fn from_str(input: &str) -> Result<Self> {
+        let input: Zeroizing<String> = Zeroizing::new(
+            input
+                .trim()
+                .strip_prefix(PREFIX)
+                .ok_or(Error::MalformedData)?
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '-')
+                .collect(),
+        );
+
+        let bytes = Zeroizing::new(hex::decode(input.as_str()).map_err(|_| Error::MalformedData)?);
+
+        if bytes.len() != ENCODED_SIZE || bytes[0] != VERSION {
+            return Err(Error::MalformedData);
+        }
+
+        let (repository_id, rest) = bytes[1..].split_at(RepositoryId::SIZE);
+        let repository_id =
+            RepositoryId::try_from(repository_id).map_err(|_| Error::MalformedData)?;
+        let threshold = rest[0];
+        let index = rest[1];
+
+        if threshold == 0 || index == 0 {
+            return Err(Error::MalformedData);
+        }
+
+        let mut value = Zeroizing::new([0; SECRET_SIZE]);
+        value.copy_from_slice(&rest[2..]);
+
+        Ok(Self {
+            repository_id,
+            threshold,
+            index,
+            value,
+        })
+    }
+}
+
+impl fmt::Debug for SecretShare {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SecretShare")
+            .field("repository_id", &self.repository_id)
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Splits the write secrets into `count` shares any `threshold` of which are needed to recover
+/// them (see `combine_secret_shares`). Fails with `InvalidArgument` unless
+/// `1 <= threshold <= count`.
+pub fn split_write_secrets(
+    secrets: &WriteSecrets,
+    threshold: u8,
+    count: u8,
+) -> Result<Vec<SecretShare>> {
+    if threshold == 0 || threshold > count {
+        return Err(Error::InvalidArgument);
+    }
+
+    let secret = Zeroizing::new(secrets.write_keys.to_bytes());
+
+    // One random polynomial of degree `threshold - 1` per secret byte, with the byte as the
+    // constant term. Stored by degree, one array of all the polynomials per degree.
+    let mut coefficients = Zeroizing::new(vec![[0; SECRET_SIZE]; threshold as usize]);
+    coefficients[0] = *secret;
+
+    for coefficient in &mut coefficients[1..] {
+        OsRng.fill(&mut coefficient[..]);
+    }
+
+    let shares = (1..=count)
+        .map(|index| {
+            let mut value = Zeroizing::new([0; SECRET_SIZE]);
+
+            // Horner's method, from the highest degree.
+            for (byte, output) in value.iter_mut().enumerate() {
+                *output = coefficients
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, coefficient| gf_mul(acc, index) ^ coefficient[byte]);
+            }
+
+            SecretShare {
+                repository_id: secrets.id,
+                threshold,
+                index,
+                value,
+            }
+        })
+        .collect();
+
+    Ok(shares)
+}
+
+/// Recovers the write secrets from the shares produced by `split_write_secrets`. Fails with
+/// `InvalidArgument` if there are fewer distinct shares than needed or they belong to different
+/// repositories and with `MalformedData` if some of them are corrupted.
+pub fn combine_secret_shares(shares: &[SecretShare]) -> Result<WriteSecrets> {
+    let first = shares.first().ok_or(Error::InvalidArgument)?;
+
+    let mut unique = BTreeMap::new();
+
+    for share in shares {
+        if share.repository_id != first.repository_id || share.threshold != first.threshold {
+            return Err(Error::InvalidArgument);
+        }
+
+        if let Some(existing) = unique.insert(share.index, share) {
+            if existing != share {
+                return Err(Error::MalformedData);
+            }
+        }
+    }
+
+    if unique.len() < first.threshold as usize {
+        return Err(Error::InvalidArgument);
+    }
+
+    let shares: Vec<_> = unique
+        .into_values()
+        .take(first.threshold as usize)
+        .collect();
+
+    // Lagrange interpolation at zero.
+    let mut secret = Zeroizing::new([0; SECRET_SIZE]);
+
+    for share in &shares {
+        let mut numerator = 1;
+        let mut denominator = 1;
+
+        for other in &shares {
+            if other.index != share.index {
+                numerator = gf_mul(numerator, other.index);
+                denominator = gf_mul(denominator, other.index ^ share.index);
+            }
+        }
+
+        let basis = gf_mul(numerator, gf_inv(denominator));
+
+        for (secret, value) in secret.iter_mut().zip(share.value.iter()) {
+            *secret ^= gf_mul(*value, basis);
+        }
+    }
+
+    let secrets = WriteSecrets::from(Keypair::from(&*secret));
+
+    // A corrupted share yields different keys.
+    if secrets.id != first.repository_id {
+        return Err(Error::MalformedData);
+    }
+
+    Ok(secrets)
+}
+
+// Multiplication in GF(2^8) with the AES polynomial.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+
+        if carry {
+            a ^= 0x1b;
+        }
+
+        b >>= 1;
+    }
+
+    product
+}
+
+// Multiplicative inverse in GF(2^8) (`a^254`). `a` must not be zero.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254u8;
+
+    while exp > 0 {
+        if exp & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn split_and_combine() {
+        let secrets = WriteSecrets::random();
+        let shares = split_write_secrets(&secrets, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        // Any three shares recover the secrets.
+        for combination in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let subset: Vec<_> = combination.iter().map(|i| shares[*i].clone()).collect();
+            let recovered = combine_secret_shares(&subset).unwrap();
+
+            assert_eq!(recovered.id, secrets.id);
+            assert_eq!(
+                recovered.write_keys.to_bytes(),
+                secrets.write_keys.to_bytes()
+            );
+        }
+
+        // Two are not enough, not even when one of them is repeated.
+        assert_matches!(
+            combine_secret_shares(&shares[..2]),
+            Err(Error::InvalidArgument)
+        );
+        assert_matches!(
+            combine_secret_shares(&[shares[0].clone(), shares[1].clone(), shares[0].clone()]),
+            Err(Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        let secrets = WriteSecrets::random();
+        let shares = split_write_secrets(&secrets, 2, 3).unwrap();
+
+        let encoded = shares[1].to_string();
+        let decoded: SecretShare = encoded.parse().unwrap();
+        assert_eq!(decoded, shares[1]);
+
+        // Whitespace and dashes are ignored.
+        let (prefix, body) = encoded.split_at(PREFIX.len());
+        let grouped: Vec<_> = body
+            .as_bytes()
+            .chunks(8)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap())
+            .collect();
+        let grouped = format!("{prefix} {}\n", grouped.join("-"));
+        assert_eq!(grouped.parse::<SecretShare>().unwrap(), shares[1]);
+
+        // Corrupted share is detected on combining.
+        let mut corrupted = shares[0].clone();
+        corrupted.value[0] ^= 1;
+        assert_matches!(
+            combine_secret_shares(&[corrupted, shares[2].clone()]),
+            Err(Error::MalformedData)
+        );
+    }
+
+    #[test]
+    fn invalid_threshold() {
+        let secrets = WriteSecrets::random();
+
+        assert_matches!(
+            split_write_secrets(&secrets, 0, 3),
+            Err(Error::InvalidArgument)
+        );
+        assert_matches!(
+            split_write_secrets(&secrets, 4, 3),
+            Err(Error::InvalidArgument)
+        );
+    }
+}
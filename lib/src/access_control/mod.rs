@@ -1,280 +1,20 @@
-mod access_mode;
+mod escrow;
 mod local_secret;
-mod share_token;
+mod unlock_token;
 
 pub use self::{
-    access_mode::AccessMode,
+    escrow::{combine_secret_shares, split_write_secrets, SecretShare},
     local_secret::{KeyAndSalt, LocalSecret, SetLocalSecret},
-    share_token::ShareToken,
+    unlock_token::UnlockToken,
 };
-
-use crate::{
-    crypto::{cipher, sign},
-    error::Error,
-    repository::RepositoryId,
-    Result,
+pub use ouisync_protocol::access_control::{
+    AccessMode, AccessSecrets, DecodeError, ShareToken, WriteSecrets,
 };
-use rand::{rngs::OsRng, CryptoRng, Rng};
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::{fmt, str::Utf8Error, string::FromUtf8Error, sync::Arc};
-use thiserror::Error;
-
-/// Secrets for access to a repository.
-#[derive(Clone, Serialize, Deserialize)]
-pub enum AccessSecrets {
-    Blind {
-        id: RepositoryId,
-    },
-    Read {
-        id: RepositoryId,
-        read_key: cipher::SecretKey,
-    },
-    Write(WriteSecrets),
-}
-
-impl AccessSecrets {
-    /// Generates random access secrets with write access using the provided RNG.
-    pub fn generate_write<R: Rng + CryptoRng>(rng: &mut R) -> Self {
-        Self::Write(WriteSecrets::generate(rng))
-    }
-
-    /// Generates random access secrets with write access using OsRng.
-    pub fn random_write() -> Self {
-        Self::Write(WriteSecrets::random())
-    }
-
-    /// Change the access mode of this secrets to the given mode. If the given mode is higher than
-    /// self, returns self unchanged.
-    pub fn with_mode(&self, mode: AccessMode) -> Self {
-        match (self, mode) {
-            (Self::Blind { .. }, AccessMode::Blind | AccessMode::Read | AccessMode::Write)
-            | (Self::Read { .. }, AccessMode::Read | AccessMode::Write)
-            | (Self::Write { .. }, AccessMode::Write) => self.clone(),
-            (Self::Read { id, .. } | Self::Write(WriteSecrets { id, .. }), AccessMode::Blind) => {
-                Self::Blind { id: *id }
-            }
-            (Self::Write(WriteSecrets { id, read_key, .. }), AccessMode::Read) => Self::Read {
-                id: *id,
-                read_key: read_key.clone(),
-            },
-        }
-    }
-
-    pub fn access_mode(&self) -> AccessMode {
-        match self {
-            Self::Blind { .. } => AccessMode::Blind,
-            Self::Read { .. } => AccessMode::Read,
-            Self::Write(_) => AccessMode::Write,
-        }
-    }
-
-    pub fn id(&self) -> &RepositoryId {
-        match self {
-            Self::Blind { id } | Self::Read { id, .. } | Self::Write(WriteSecrets { id, .. }) => id,
-        }
-    }
-
-    pub fn can_write(&self) -> bool {
-        matches!(self, Self::Write(_))
-    }
-
-    pub fn can_read(&self) -> bool {
-        matches!(self, Self::Read { .. } | Self::Write(_))
-    }
-
-    pub fn read_key(&self) -> Option<&cipher::SecretKey> {
-        match self {
-            Self::Blind { .. } => None,
-            Self::Read { read_key, .. } => Some(read_key),
-            Self::Write(secrets) => Some(&secrets.read_key),
-        }
-    }
-
-    pub fn write_secrets(&self) -> Option<&WriteSecrets> {
-        match self {
-            Self::Blind { .. } => None,
-            Self::Read { .. } => None,
-            Self::Write(secrets) => Some(secrets),
-        }
-    }
-
-    pub fn into_write_secrets(self) -> Option<WriteSecrets> {
-        match self {
-            Self::Blind { .. } => None,
-            Self::Read { .. } => None,
-            Self::Write(secrets) => Some(secrets),
-        }
-    }
-
-    pub(crate) fn keys(&self) -> Option<AccessKeys> {
-        match self {
-            Self::Blind { .. } => None,
-            Self::Read { read_key, .. } => Some(AccessKeys {
-                read: read_key.clone(),
-                write: None,
-            }),
-            Self::Write(secrets) => Some(AccessKeys {
-                read: secrets.read_key.clone(),
-                write: Some(secrets.write_keys.clone()),
-            }),
-        }
-    }
-}
-
-impl fmt::Debug for AccessSecrets {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::Blind { .. } => f.debug_struct("Blind").finish_non_exhaustive(),
-            Self::Read { .. } => f.debug_struct("Read").finish_non_exhaustive(),
-            Self::Write { .. } => f.debug_struct("Write").finish_non_exhaustive(),
-        }
-    }
-}
-
-impl PartialEq for AccessSecrets {
-    fn eq(&self, other: &Self) -> bool {
-        self.access_mode() == other.access_mode() && self.id() == other.id()
-    }
-}
-
-impl Eq for AccessSecrets {}
-
-/// Secrets for write access.
-#[derive(Clone)]
-pub struct WriteSecrets {
-    pub id: RepositoryId,
-    pub read_key: cipher::SecretKey,
-    pub write_keys: Arc<sign::Keypair>,
-}
-
-impl WriteSecrets {
-    /// Generates random write secrets using the provided RNG.
-    pub fn generate<R: Rng + CryptoRng>(rng: &mut R) -> Self {
-        Self::from(sign::Keypair::generate(rng))
-    }
-
-    /// Generates random write secrets using OsRng.
-    pub fn random() -> Self {
-        Self::generate(&mut OsRng)
-    }
-}
-
-impl PartialEq for WriteSecrets {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-    }
-}
-
-impl Eq for WriteSecrets {}
-
-impl From<sign::Keypair> for WriteSecrets {
-    fn from(keys: sign::Keypair) -> Self {
-        let id = keys.public_key().into();
-        let read_key = derive_read_key_from_write_keys(&keys);
-
-        Self {
-            id,
-            read_key,
-            write_keys: Arc::new(keys),
-        }
-    }
-}
-
-impl Serialize for WriteSecrets {
-    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        // Serialize only the write keys because all the other fields can be derived from it
-        self.write_keys.serialize(s)
-    }
-}
-
-impl<'de> Deserialize<'de> for WriteSecrets {
-    fn deserialize<D>(d: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Ok(Self::from(sign::Keypair::deserialize(d)?))
-    }
-}
-
-/// Secret keys for read and optionaly write access.
-#[derive(Clone)]
-pub(crate) struct AccessKeys {
-    read: cipher::SecretKey,
-    write: Option<Arc<sign::Keypair>>,
-}
-
-impl AccessKeys {
-    pub fn read(&self) -> &cipher::SecretKey {
-        &self.read
-    }
 
-    pub fn write(&self) -> Option<&sign::Keypair> {
-        self.write.as_deref()
-    }
-
-    pub fn read_only(self) -> Self {
-        Self {
-            read: self.read,
-            write: None,
-        }
-    }
-}
-
-impl From<WriteSecrets> for AccessKeys {
-    fn from(secrets: WriteSecrets) -> Self {
-        Self {
-            read: secrets.read_key,
-            write: Some(secrets.write_keys),
-        }
-    }
-}
-
-fn derive_read_key_from_write_keys(write_keys: &sign::Keypair) -> cipher::SecretKey {
-    cipher::SecretKey::derive_from_key(&write_keys.to_bytes(), b"ouisync repository read key")
-}
-
-#[derive(Debug, Error)]
-#[error("decode error")]
-pub struct DecodeError;
-
-impl From<base64::DecodeError> for DecodeError {
-    fn from(_: base64::DecodeError) -> Self {
-        Self
-    }
-}
+pub(crate) use ouisync_protocol::access_control::AccessKeys;
 
-impl From<bincode::Error> for DecodeError {
-    fn from(_: bincode::Error) -> Self {
-        Self
-    }
-}
-
-impl From<FromUtf8Error> for DecodeError {
-    fn from(_: FromUtf8Error) -> Self {
-        Self
-    }
-}
-
-impl From<Utf8Error> for DecodeError {
-    fn from(_: Utf8Error) -> Self {
-        Self
-    }
-}
-
-impl From<sign::SignatureError> for DecodeError {
-    fn from(_: sign::SignatureError) -> Self {
-        Self
-    }
-}
-
-impl From<cipher::SecretKeyLengthError> for DecodeError {
-    fn from(_: cipher::SecretKeyLengthError) -> Self {
-        Self
-    }
-}
+use crate::{crypto::cipher, error::Error, repository::RepositoryId};
+use serde::{Deserialize, Serialize};
 
 impl From<DecodeError> for Error {
     fn from(_: DecodeError) -> Self {
@@ -391,7 +131,7 @@ impl Access {
     }
 
     #[cfg(test)]
-    pub fn highest_local_secret(&self) -> Option<&SetLocalSecret> {
+    pub(crate) fn highest_local_secret(&self) -> Option<&SetLocalSecret> {
         match self {
             Self::Blind { .. } => None,
             Self::ReadUnlocked { .. } => None,
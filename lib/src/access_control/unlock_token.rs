@@ -0,0 +1,51 @@
+use crate::{crypto::cipher, error::Error};
+use std::{fmt, str::FromStr};
+use zeroize::Zeroizing;
+
+/// Opaque high-entropy token that unlocks a repository without the user's local secret (see
+/// `Repository::create_unlock_token`). Meant to be stored behind the platform biometric APIs
+/// instead of caching the user's password. Converts to and from a string.
+#[derive(Clone, Eq, PartialEq)]
+pub struct UnlockToken(cipher::SecretKey);
+
+impl UnlockToken {
+    pub(crate) fn random() -> Self {
+        Self(cipher::SecretKey::random())
+    }
+
+    pub(crate) fn key(&self) -> &cipher::SecretKey {
+        &self.0
+    }
+}
+
+impl fmt::Debug for UnlockToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnlockToken").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for UnlockToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            Zeroizing::new(hex::encode(self.0.as_ref())).as_str()
+        )
+    }
+}
+
+impl FromStr for UnlockToken {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let bytes = Zeroizing::new(hex::decode(input.trim()).map_err(|_| Error::MalformedData)?);
+
+        if bytes.len() != cipher::SecretKey::SIZE {
+            return Err(Error::MalformedData);
+        }
+
+        cipher::SecretKey::try_from(&bytes[..])
+            .map(Self)
+            .map_err(|_| Error::MalformedData)
+    }
+}
@@ -1,4 +1,5 @@
 use crate::crypto::{Digest, Hashable};
+use ouisync_protocol::define_byte_array_wrapper;
 use rand::{
     distributions::{Distribution, Standard},
     Rng,
@@ -13,6 +14,19 @@ define_byte_array_wrapper! {
 
 impl BlobId {
     pub(crate) const ROOT: Self = Self([0; Self::SIZE]);
+
+    /// Id of the entry pointing to this blob (see `EntryRef::id`). Zero for the root.
+    pub(crate) fn entry_id(&self) -> u128 {
+        entry_id(&self.0)
+    }
+}
+
+/// Truncates the given bytes (a blob id or a hash) to an entry id. The ids keep 128 bits so they
+/// are, for all practical purposes, collision free even in the largest repositories.
+pub(crate) fn entry_id(bytes: &[u8]) -> u128 {
+    let mut id = [0; 16];
+    id.copy_from_slice(&bytes[..16]);
+    u128::from_le_bytes(id)
 }
 
 // Never generates `ROOT`
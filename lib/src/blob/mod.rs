@@ -7,7 +7,10 @@ mod position;
 #[cfg(test)]
 mod tests;
 
-pub(crate) use self::{block_ids::BlockIds, id::BlobId};
+pub(crate) use self::{
+    block_ids::BlockIds,
+    id::{entry_id, BlobId},
+};
 
 use self::position::Position;
 use crate::{
@@ -19,13 +22,21 @@ use crate::{
         Hashable,
     },
     error::{Error, Result},
+    memory::{MemoryCategory, MemoryReservation},
     protocol::{
         Block, BlockContent, BlockId, BlockNonce, Locator, RootNode, RootNodeFilter,
         SingleBlockPresence, BLOCK_SIZE,
     },
     store::{self, Changeset, ReadTransaction},
 };
-use std::{io::SeekFrom, iter, mem};
+use std::{
+    io::SeekFrom,
+    iter, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use thiserror::Error;
 
 /// Size of the blob header in bytes.
@@ -49,9 +60,13 @@ pub(crate) struct Blob {
     branch: Branch,
     id: BlobId,
     cache: HashMap<u32, CachedBlock>,
+    memory: MemoryReservation,
     len_original: u64,
     len_modified: u64,
     position: Position,
+    cache_bypass: bool,
+    // Set when the blob has been flushed with `flush_pending`. See `settle`.
+    pending_commit: Option<Arc<AtomicBool>>,
 }
 
 impl Blob {
@@ -75,15 +90,19 @@ impl Blob {
         let len = buffer.read_u64(0);
         let cached_block = CachedBlock::from(buffer);
         let cache = iter::once((0, cached_block)).collect();
+        let memory = reserve_memory(&branch, &cache);
         let position = Position::ZERO;
 
         Ok(Self {
             branch,
             id,
             cache,
+            memory,
             len_original: len,
             len_modified: len,
             position,
+            cache_bypass: false,
+            pending_commit: None,
         })
     }
 
@@ -91,14 +110,18 @@ impl Blob {
     pub fn create(branch: Branch, id: BlobId) -> Self {
         let cached_block = CachedBlock::new().with_dirty(true);
         let cache = iter::once((0, cached_block)).collect();
+        let memory = reserve_memory(&branch, &cache);
 
         Self {
             branch,
             id,
             cache,
+            memory,
             len_original: 0,
             len_modified: 0,
             position: Position::ZERO,
+            cache_bypass: false,
+            pending_commit: None,
         }
     }
 
@@ -116,6 +139,17 @@ impl Blob {
         self.len_modified
     }
 
+    /// When enabled, at most one clean (not modified) block is kept in the cache. This is for
+    /// huge one-pass reads which would otherwise fill the cache (and the memory budget) with blocks
+    /// that are never read again, evicting the blocks of the other open blobs.
+    pub fn set_cache_bypass(&mut self, bypass: bool) {
+        self.cache_bypass = bypass;
+    }
+
+    pub fn cache_bypass(&self) -> bool {
+        self.cache_bypass
+    }
+
     // Returns the current seek position from the start of the blob.
     pub fn seek_position(&self) -> u64 {
         self.position.get()
@@ -127,6 +161,10 @@ impl Blob {
 
     /// Was this blob modified and not flushed yet?
     pub fn is_dirty(&self) -> bool {
+        if self.is_pending_committed() {
+            return false;
+        }
+
         self.cache.values().any(|block| block.dirty) || self.len_modified != self.len_original
     }
 
@@ -271,11 +309,17 @@ impl Blob {
         Ok(buffer)
     }
 
+    /// Writes into the cached current block. Consecutive writes (even small ones and with seeks in
+    /// between) only modify the cache and get written to the store together on the next flush.
+    /// Fails with `CacheMiss` if the current block needs to be loaded first and with `CacheFull`
+    /// if the cache needs to be flushed first.
     pub fn write(&mut self, buffer: &[u8]) -> Result<usize, ReadWriteError> {
         if buffer.is_empty() {
             return Ok(0);
         }
 
+        self.settle();
+
         let block = match self.cache.get_mut(&self.position.block) {
             Some(block) => block,
             None => {
@@ -283,9 +327,14 @@ impl Blob {
                     return Err(ReadWriteError::CacheFull);
                 }
 
-                if self.position.get() >= self.len_modified
-                    || self.position.offset == 0 && buffer.len() >= BLOCK_SIZE
+                // A block can be started from scratch only if none of its existing content needs
+                // to be preserved, that is, if we are at its beginning and either past the end of
+                // the blob or about to overwrite it whole. Otherwise (e.g., when appending to a
+                // partially filled block) it needs to be loaded first.
+                if self.position.offset == 0
+                    && (self.position.get() >= self.len_modified || buffer.len() >= BLOCK_SIZE)
                 {
+                    self.memory.set((self.cache.len() + 1) * BLOCK_SIZE);
                     self.cache.entry(self.position.block).or_default()
                 } else {
                     return Err(ReadWriteError::CacheMiss);
@@ -355,6 +404,7 @@ impl Blob {
                 let (_, buffer) =
                     read_block(tx, root_node, &locator, self.branch.keys().read()).await?;
                 entry.insert(CachedBlock::from(buffer));
+                self.memory.set(self.cache.len() * BLOCK_SIZE);
             }
         }
 
@@ -372,6 +422,8 @@ impl Blob {
             return Err(Error::OperationNotSupported);
         }
 
+        self.settle();
+
         if self.seek_position() > len {
             self.seek(SeekFrom::Start(len));
         }
@@ -388,12 +440,93 @@ impl Blob {
         tx: &mut ReadTransaction,
         changeset: &mut Changeset,
     ) -> Result<()> {
+        self.settle();
         self.write_len(tx, changeset).await?;
         self.write_blocks(changeset);
 
         Ok(())
     }
 
+    /// Like `flush` but the blob stays dirty until `committed` is set, which the caller should do
+    /// once the transaction the changeset is applied in gets committed. If it's not committed
+    /// (e.g., it's dropped), the modifications are preserved and written by the next flush.
+    pub(crate) async fn flush_pending(
+        &mut self,
+        tx: &mut ReadTransaction,
+        changeset: &mut Changeset,
+        committed: Arc<AtomicBool>,
+    ) -> Result<()> {
+        self.settle();
+
+        let len_original = self.len_original;
+        self.write_len(tx, changeset).await?;
+        self.len_original = len_original;
+
+        for (number, block) in self.cache.iter().filter(|(_, block)| block.dirty) {
+            let locator = Locator::head(self.id).nth(*number);
+            write_block(
+                changeset,
+                &locator,
+                block.content.clone(),
+                self.branch.keys().read(),
+            );
+        }
+
+        self.pending_commit = Some(committed);
+
+        Ok(())
+    }
+
+    /// Like `flush` but writes at most `max_blocks` blocks (at least one). The blocks are written
+    /// in ascending order and the head block (which contains the length) last, so the blob stays
+    /// readable (with its original length) between the partial flushes. Returns whether the blob
+    /// has been flushed completely.
+    pub(crate) async fn flush_partial(
+        &mut self,
+        tx: &mut ReadTransaction,
+        changeset: &mut Changeset,
+        max_blocks: usize,
+    ) -> Result<bool> {
+        self.settle();
+
+        let max_blocks = max_blocks.max(1);
+
+        let mut numbers: Vec<_> = self
+            .cache
+            .iter()
+            .filter(|(number, block)| **number != 0 && block.dirty)
+            .map(|(number, _)| *number)
+            .collect();
+
+        let head_dirty = self.cache.get(&0).map(|block| block.dirty).unwrap_or(false)
+            || self.len_modified != self.len_original;
+
+        if numbers.len() + usize::from(head_dirty) <= max_blocks {
+            self.flush(tx, changeset).await?;
+            return Ok(true);
+        }
+
+        numbers.sort_unstable();
+
+        for number in numbers.into_iter().take(max_blocks) {
+            let Some(block) = self.cache.get_mut(&number) else {
+                continue;
+            };
+
+            block.dirty = false;
+
+            let locator = Locator::head(self.id).nth(number);
+            write_block(
+                changeset,
+                &locator,
+                block.content.clone(),
+                self.branch.keys().read(),
+            );
+        }
+
+        Ok(false)
+    }
+
     /// Remove this blob from the store.
     pub(crate) fn remove(self, changeset: &mut Changeset) {
         let locators = Locator::head(self.id)
@@ -406,8 +539,40 @@ impl Blob {
         }
     }
 
+    fn is_pending_committed(&self) -> bool {
+        self.pending_commit
+            .as_ref()
+            .is_some_and(|committed| committed.load(Ordering::Acquire))
+    }
+
+    // If the blob has been flushed with `flush_pending` and the transaction has been committed
+    // since, marks it clean. Otherwise it stays dirty and so gets flushed again.
+    fn settle(&mut self) {
+        let Some(committed) = self.pending_commit.take() else {
+            return;
+        };
+
+        if committed.load(Ordering::Acquire) {
+            for block in self.cache.values_mut() {
+                block.dirty = false;
+            }
+
+            self.len_original = self.len_modified;
+        }
+    }
+
     fn check_cache_capacity(&mut self) -> bool {
-        if self.cache.len() < CACHE_CAPACITY {
+        if self.cache_bypass {
+            // Called only before loading a new block, so drop all the clean ones.
+            self.cache.retain(|_, block| block.dirty);
+            self.memory.set(self.cache.len() * BLOCK_SIZE);
+
+            return self.cache.len() < CACHE_CAPACITY;
+        }
+
+        let budget_exceeded = self.memory.is_budget_exceeded();
+
+        if self.cache.len() < CACHE_CAPACITY && !budget_exceeded {
             return true;
         }
 
@@ -419,9 +584,12 @@ impl Blob {
 
         if let Some(number) = number {
             self.cache.remove(&number);
+            self.memory.set(self.cache.len() * BLOCK_SIZE);
             true
         } else {
-            false
+            // When over the memory budget but all the cached blocks are dirty, allow the cache to
+            // still grow up to its capacity, otherwise reads would fail until the blob is flushed.
+            self.cache.len() < CACHE_CAPACITY
         }
     }
 
@@ -480,9 +648,12 @@ impl Clone for Blob {
             branch: self.branch.clone(),
             id: self.id,
             cache: HashMap::default(),
+            memory: self.memory.new_empty(),
             len_original: self.len_original,
             len_modified: self.len_original,
             position: self.position,
+            cache_bypass: self.cache_bypass,
+            pending_commit: None,
         }
     }
 }
@@ -659,11 +830,22 @@ async fn read_block(
     let mut content = BlockContent::new();
     let nonce = tx.read_block(&id, &mut content).await?;
 
+    let memory = tx.memory_budget().clone();
+    let _permit = memory.acquire_decrypt_permit().await;
     decrypt_block(read_key, &nonce, &mut content);
 
     Ok((id, content))
 }
 
+fn reserve_memory(branch: &Branch, cache: &HashMap<u32, CachedBlock>) -> MemoryReservation {
+    let mut memory = branch
+        .store()
+        .memory_budget()
+        .reserve(MemoryCategory::BlockCache);
+    memory.set(cache.len() * BLOCK_SIZE);
+    memory
+}
+
 fn write_block(
     changeset: &mut Changeset,
     locator: &Locator,
@@ -688,12 +870,20 @@ fn write_block(
     block_id
 }
 
-fn decrypt_block(blob_key: &cipher::SecretKey, block_nonce: &BlockNonce, content: &mut [u8]) {
+pub(crate) fn decrypt_block(
+    blob_key: &cipher::SecretKey,
+    block_nonce: &BlockNonce,
+    content: &mut [u8],
+) {
     let block_key = SecretKey::derive_from_key(blob_key.as_array(), block_nonce);
     block_key.decrypt_no_aead(&Nonce::default(), content);
 }
 
-fn encrypt_block(blob_key: &cipher::SecretKey, block_nonce: &BlockNonce, content: &mut [u8]) {
+pub(crate) fn encrypt_block(
+    blob_key: &cipher::SecretKey,
+    block_nonce: &BlockNonce,
+    content: &mut [u8],
+) {
     let block_key = SecretKey::derive_from_key(blob_key.as_array(), block_nonce);
     block_key.encrypt_no_aead(&Nonce::default(), content);
 }
@@ -713,7 +903,7 @@ fn encrypt_block(blob_key: &cipher::SecretKey, block_nonce: &BlockNonce, content
 ///
 /// Note: `read_key` is used as an additional secret hashing material to prevent known plaintext
 /// attacks.
-fn make_block_nonce(
+pub(crate) fn make_block_nonce(
     locator: &Locator,
     plaintext_content: &[u8],
     read_key: &cipher::SecretKey,
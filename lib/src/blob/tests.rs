@@ -267,6 +267,45 @@ async fn seek_after_end() {
     store.close().await.unwrap();
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn cache_bypass() {
+    let (mut rng, _base_dir, store, [branch]) = setup(0).await;
+    let mut tx = store.begin_write().await.unwrap();
+    let mut changeset = Changeset::new();
+
+    let content: Vec<u8> = (&mut rng)
+        .sample_iter(Standard)
+        .take(4 * BLOCK_SIZE)
+        .collect();
+
+    let mut blob = Blob::create(branch.clone(), BlobId::ROOT);
+    blob.write_all(&mut tx, &mut changeset, &content)
+        .await
+        .unwrap();
+    blob.flush(&mut tx, &mut changeset).await.unwrap();
+    changeset
+        .apply(&mut tx, branch.id(), branch.keys().write().unwrap())
+        .await
+        .unwrap();
+
+    let mut blob = Blob::open(&mut tx, branch.clone(), BlobId::ROOT)
+        .await
+        .unwrap();
+    blob.set_cache_bypass(true);
+
+    let read_content = blob.read_to_end(&mut tx).await.unwrap();
+    assert_eq!(read_content, content);
+    assert_eq!(blob.cache.len(), 1);
+
+    // Without the bypass all the read blocks stay cached.
+    let mut blob = Blob::open(&mut tx, branch, BlobId::ROOT).await.unwrap();
+    blob.read_to_end(&mut tx).await.unwrap();
+    assert_eq!(blob.cache.len(), 5);
+
+    drop(tx);
+    store.close().await.unwrap();
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn seek_before_start() {
     let (_, _base_dir, store, [branch]) = setup(0).await;
@@ -497,6 +536,107 @@ async fn append() {
     store.close().await.unwrap();
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn append_to_partially_filled_block() {
+    let (mut rng, _base_dir, store, [branch]) = setup(0).await;
+
+    let mut tx = store.begin_write().await.unwrap();
+
+    let id = rng.gen();
+    let mut content = random_bytes(&mut rng, 3 * BLOCK_SIZE / 2);
+    let mut changeset = Changeset::new();
+    let mut blob = Blob::create(branch.clone(), id);
+    blob.write_all(&mut tx, &mut changeset, &content)
+        .await
+        .unwrap();
+    blob.flush(&mut tx, &mut changeset).await.unwrap();
+    changeset
+        .apply(&mut tx, branch.id(), branch.keys().write().unwrap())
+        .await
+        .unwrap();
+
+    // Reopen so the last block is not cached.
+    let suffix = random_bytes(&mut rng, 4096);
+    let mut changeset = Changeset::new();
+    let mut blob = Blob::open(&mut tx, branch.clone(), id).await.unwrap();
+    blob.seek(SeekFrom::End(0));
+    blob.write_all(&mut tx, &mut changeset, &suffix)
+        .await
+        .unwrap();
+    blob.flush(&mut tx, &mut changeset).await.unwrap();
+    changeset
+        .apply(&mut tx, branch.id(), branch.keys().write().unwrap())
+        .await
+        .unwrap();
+
+    content.extend_from_slice(&suffix);
+
+    let mut blob = Blob::open(&mut tx, branch, id).await.unwrap();
+    assert_eq!(blob.read_to_end(&mut tx).await.unwrap(), content);
+
+    drop(tx);
+    store.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn small_writes_are_coalesced() {
+    let (mut rng, _base_dir, store, [branch]) = setup(0).await;
+
+    let id = rng.gen();
+    let mut blob = Blob::create(branch.clone(), id);
+    let mut expected = Vec::new();
+
+    // Sequential 4 KiB writes spanning several blocks...
+    for _ in 0..(3 * BLOCK_SIZE / 4096) {
+        let chunk = random_bytes(&mut rng, 4096);
+        write_cached(&mut blob, &chunk);
+        expected.extend_from_slice(&chunk);
+    }
+
+    // ...and some overwrites after seeks back and forth.
+    for _ in 0..32 {
+        let offset = rng.gen_range(0..expected.len() - 4096);
+        let len = rng.gen_range(1..=4096);
+        let chunk = random_bytes(&mut rng, len);
+
+        if rng.gen() {
+            blob.seek(SeekFrom::Start(offset as u64));
+        } else {
+            let current = blob.seek_position() as i64;
+            blob.seek(SeekFrom::Current(offset as i64 - current));
+        }
+
+        write_cached(&mut blob, &chunk);
+        expected[offset..offset + chunk.len()].copy_from_slice(&chunk);
+        assert_eq!(blob.seek_position(), (offset + chunk.len()) as u64);
+    }
+
+    // Everything is committed in a single transaction.
+    let mut tx = store.begin_write().await.unwrap();
+    let mut changeset = Changeset::new();
+    blob.flush(&mut tx, &mut changeset).await.unwrap();
+    changeset
+        .apply(&mut tx, branch.id(), branch.keys().write().unwrap())
+        .await
+        .unwrap();
+
+    let mut blob = Blob::open(&mut tx, branch, id).await.unwrap();
+    assert_eq!(blob.len(), expected.len() as u64);
+    assert_eq!(blob.read_to_end(&mut tx).await.unwrap(), expected);
+
+    drop(tx);
+    store.close().await.unwrap();
+}
+
+// Writes the whole buffer without touching the store. Panics if the blob needs to load or flush
+// anything.
+fn write_cached(blob: &mut Blob, mut buffer: &[u8]) {
+    while !buffer.is_empty() {
+        let len = blob.write(buffer).unwrap();
+        buffer = &buffer[len..];
+    }
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn write_reopen_and_read() {
     let (mut rng, _base_dir, store, [branch]) = setup(0).await;
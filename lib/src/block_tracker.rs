@@ -3,27 +3,56 @@ use crate::{
     protocol::BlockId,
 };
 use deadlock::BlockingMutex;
-use slab::Slab;
+use metrics::{Counter, Gauge};
 use std::{collections::hash_map::Entry, sync::Arc};
 use tokio::sync::watch;
 
+/// Maximum number of offers a single client can have proposed or accepted at the same time. When a
+/// peer sends us a large index, this keeps the block requests flowing at the rate the blocks are
+/// being received instead of enqueueing all of them at once. More offers are handed out as the
+/// outstanding ones complete or get dropped.
+pub(crate) const MAX_OUTSTANDING_OFFERS_PER_CLIENT: usize = 512;
+
+/// Maximum number of blocks a single client can have offered at the same time. Bounds the memory
+/// used by the offers of a peer with a large index: the offered blocks not required yet are kept in
+/// `missing_blocks` and `block_ids` until the client is dropped. The offers over the limit are
+/// discarded and the client is told to get them again once the offers it holds drop below half of
+/// the limit (see `TrackerClient::take_overflowed`).
+pub(crate) const MAX_OFFERS_PER_CLIENT: usize = 64 * 1024;
+
 /// Helper for tracking required missing blocks.
+///
+/// The state is kept only in memory and is never persisted, so registering and accepting offers
+/// costs no database round trips. After a restart it's rebuilt from the index: the worker requires
+/// the missing blocks of every branch it traverses (see `worker::require_missing_blocks`) and the
+/// peers offer them again as they send us their leaf nodes.
 #[derive(Clone)]
 pub(crate) struct BlockTracker {
     shared: Arc<Shared>,
 }
 
 impl BlockTracker {
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_metrics(Gauge::noop(), Counter::noop())
+    }
+
+    /// Creates the tracker which reports the total number of outstanding offers (proposed or
+    /// accepted, over all clients) into `outstanding` and the number of offer lookups turned down
+    /// because the client reached `MAX_OUTSTANDING_OFFERS_PER_CLIENT` into `throttled`.
+    pub fn with_metrics(outstanding: Gauge, throttled: Counter) -> Self {
         let (notify_tx, _) = watch::channel(());
 
         Self {
             shared: Arc::new(Shared {
                 inner: BlockingMutex::new(Inner {
                     missing_blocks: HashMap::default(),
-                    offering_clients: Slab::new(),
+                    offering_clients: HashMap::default(),
+                    next_client_id: 0,
                 }),
                 notify_tx,
+                outstanding,
+                throttled,
             }),
         }
     }
@@ -78,14 +107,35 @@ impl BlockTracker {
         }
     }
 
+    /// Snapshot of what the tracker knows about the given block, for diagnostics. `None` if the
+    /// block is neither required nor offered.
+    pub fn inspect(&self, block_id: &BlockId) -> Option<BlockInspection> {
+        let inner = self.shared.inner.lock().unwrap();
+        let missing_block = inner.missing_blocks.get(block_id)?;
+
+        let (required, approved, requested) = match missing_block.state {
+            State::Idle { required, approved } => (required, approved, false),
+            State::Accepted(_) => (true, true, true),
+        };
+
+        Some(BlockInspection {
+            offers: missing_block.offers.len(),
+            required,
+            approved,
+            requested,
+        })
+    }
+
     pub fn client(&self) -> TrackerClient {
-        let client_id = self
-            .shared
-            .inner
-            .lock()
-            .unwrap()
-            .offering_clients
-            .insert(HashSet::default());
+        let client_id = {
+            let mut inner = self.shared.inner.lock().unwrap();
+            let client_id = inner.next_client_id;
+            inner.next_client_id += 1;
+            inner
+                .offering_clients
+                .insert(client_id, OfferingClient::default());
+            client_id
+        };
 
         let notify_rx = self.shared.notify_tx.subscribe();
 
@@ -97,6 +147,17 @@ impl BlockTracker {
     }
 }
 
+/// See `BlockTracker::inspect`.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) struct BlockInspection {
+    /// Number of clients currently offering the block.
+    pub offers: usize,
+    pub required: bool,
+    pub approved: bool,
+    /// Whether the block has been requested from one of the offering clients.
+    pub requested: bool,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub(crate) enum OfferState {
     Pending,
@@ -121,15 +182,26 @@ impl TrackerClient {
 
     /// Registers an offer for a block with the given id.
     /// Returns `true` if this block was offered for the first time (by any client) or `false` if
-    /// it's already been offered but not yet accepted or cancelled.
+    /// it's already been offered but not yet accepted or cancelled or if this client already
+    /// offers `MAX_OFFERS_PER_CLIENT` blocks.
     pub fn register(&self, block_id: BlockId, state: OfferState) -> bool {
         let mut inner = self.shared.inner.lock().unwrap();
 
-        if !inner.offering_clients[self.client_id].insert(block_id) {
+        // unwrap is ok because the client is removed only when `self` is dropped.
+        let client = inner.offering_clients.get_mut(&self.client_id).unwrap();
+
+        if client.block_ids.contains(&block_id) {
             // Already offered
             return false;
         }
 
+        if client.block_ids.len() >= MAX_OFFERS_PER_CLIENT {
+            client.overflowed = true;
+            return false;
+        }
+
+        client.block_ids.insert(block_id);
+
         let missing_block = inner
             .missing_blocks
             .entry(block_id)
@@ -163,15 +235,39 @@ impl TrackerClient {
 
         true
     }
+
+    /// Returns `true` if some offers were discarded because of `MAX_OFFERS_PER_CLIENT` and this
+    /// client now has room for them again. The caller should then have the peer offer them again.
+    /// Returns `true` only once per overflow.
+    pub fn take_overflowed(&self) -> bool {
+        let mut inner = self.shared.inner.lock().unwrap();
+
+        let Some(client) = inner.offering_clients.get_mut(&self.client_id) else {
+            return false;
+        };
+
+        if client.overflowed && client.block_ids.len() < MAX_OFFERS_PER_CLIENT / 2 {
+            client.overflowed = false;
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl Drop for TrackerClient {
     fn drop(&mut self) {
         let mut inner = self.shared.inner.lock().unwrap();
-        let block_ids = inner.offering_clients.remove(self.client_id);
+        let client = inner
+            .offering_clients
+            .remove(&self.client_id)
+            .unwrap_or_default();
         let mut notify = false;
 
-        for block_id in block_ids {
+        // The offers still held by this client don't count anymore.
+        self.shared.outstanding.decrement(client.outstanding as f64);
+
+        for block_id in client.block_ids {
             // unwrap is ok because of the invariant in `Inner`
             let missing_block = inner.missing_blocks.get_mut(&block_id).unwrap();
 
@@ -210,13 +306,22 @@ impl BlockOffers {
         }
     }
 
-    /// Returns the next offer or `None` if none exists currently.
+    /// Returns the next offer or `None` if none exists currently or if this client already has
+    /// `MAX_OUTSTANDING_OFFERS_PER_CLIENT` offers outstanding.
     pub fn try_next(&self) -> Option<BlockOffer> {
         let mut inner = self.shared.inner.lock().unwrap();
         let inner = &mut *inner;
 
+        // The client has been dropped (its peer disconnected).
+        let client = inner.offering_clients.get_mut(&self.client_id)?;
+
+        if client.outstanding >= MAX_OUTSTANDING_OFFERS_PER_CLIENT {
+            self.shared.throttled.increment(1);
+            return None;
+        }
+
         // TODO: OPTIMIZE (but profile first) this linear lookup
-        for block_id in &inner.offering_clients[self.client_id] {
+        for block_id in &client.block_ids {
             // unwrap is ok because of the invariant in `Inner`
             let missing_block = inner.missing_blocks.get_mut(block_id).unwrap();
 
@@ -237,6 +342,9 @@ impl BlockOffers {
                 Offer::Proposed | Offer::Accepted => continue,
             }
 
+            client.outstanding += 1;
+            self.shared.outstanding.increment(1.0);
+
             return Some(BlockOffer {
                 shared: self.shared.clone(),
                 client_id: self.client_id,
@@ -277,6 +385,13 @@ impl BlockOffer {
             State::Idle { .. } | State::Accepted(_) => return None,
         }
 
+        // The offer has been expired because the client was dropped in the meantime. Accepting
+        // it would leave the block accepted by a peer that's no longer connected.
+        match missing_block.offers.get(&self.client_id) {
+            Some(Offer::Proposed) => (),
+            Some(Offer::Available | Offer::Accepted) | None => return None,
+        }
+
         missing_block.state = State::Accepted(self.client_id);
         missing_block.offers.insert(self.client_id, Offer::Accepted);
 
@@ -291,6 +406,18 @@ impl Drop for BlockOffer {
         let mut inner = self.shared.inner.lock().unwrap();
         let inner = &mut *inner;
 
+        // Release the slot regardless of how the offer ended (declined, failed or completed) so the
+        // client can be given the next one. If the client's been dropped, its slots were released
+        // already.
+        if let Some(client) = inner.offering_clients.get_mut(&self.client_id) {
+            if client.outstanding == MAX_OUTSTANDING_OFFERS_PER_CLIENT {
+                self.shared.notify();
+            }
+
+            client.outstanding -= 1;
+            self.shared.outstanding.decrement(1.0);
+        }
+
         let Some(missing_block) = inner.missing_blocks.get_mut(&self.block_id) else {
             return;
         };
@@ -308,7 +435,10 @@ impl Drop for BlockOffer {
                 // safe to remove it. If the peer sends us another leaf node response with the same
                 // block id, we register the offer again.
                 entry.remove();
-                inner.offering_clients[self.client_id].remove(&self.block_id);
+
+                if let Some(client) = inner.offering_clients.get_mut(&self.client_id) {
+                    client.block_ids.remove(&self.block_id);
+                }
             }
             Offer::Available => unreachable!(),
         }
@@ -336,8 +466,8 @@ impl BlockPromise {
         };
 
         for (client_id, _) in missing_block.offers {
-            if let Some(block_ids) = inner.offering_clients.get_mut(client_id) {
-                block_ids.remove(&self.0.block_id);
+            if let Some(client) = inner.offering_clients.get_mut(&client_id) {
+                client.block_ids.remove(&self.0.block_id);
             }
         }
     }
@@ -346,6 +476,8 @@ impl BlockPromise {
 struct Shared {
     inner: BlockingMutex<Inner>,
     notify_tx: watch::Sender<()>,
+    outstanding: Gauge,
+    throttled: Counter,
 }
 
 impl Shared {
@@ -360,12 +492,28 @@ impl Shared {
 //
 // it must hold that
 //
-//     offering_clients[client_id].contains(block_id)
+//     offering_clients[client_id].block_ids.contains(block_id)
 //
 // and vice-versa.
+//
+// Client ids are never reused so that the offers and promises outliving their client (e.g.,
+// requests still in flight when the peer disconnects) can't affect a client created later (e.g.,
+// when the same peer reconnects).
 struct Inner {
     missing_blocks: HashMap<BlockId, MissingBlock>,
-    offering_clients: Slab<HashSet<BlockId>>,
+    offering_clients: HashMap<ClientId, OfferingClient>,
+    next_client_id: ClientId,
+}
+
+#[derive(Default)]
+struct OfferingClient {
+    // Blocks offered by this client.
+    block_ids: HashSet<BlockId>,
+    // Number of `BlockOffer`s (including the ones wrapped in `BlockPromise`s) of this client that
+    // haven't been dropped yet.
+    outstanding: usize,
+    // Whether some offers were discarded because of `MAX_OFFERS_PER_CLIENT`.
+    overflowed: bool,
 }
 
 #[derive(Debug)]
@@ -403,7 +551,7 @@ enum Offer {
     Accepted,
 }
 
-type ClientId = usize;
+type ClientId = u64;
 
 #[cfg(test)]
 mod tests {
@@ -450,6 +598,49 @@ mod tests {
         assert!(client.offers().try_next().is_none());
     }
 
+    #[test]
+    fn inspect() {
+        let tracker = BlockTracker::new();
+        let client = tracker.client();
+        let block: Block = rand::random();
+
+        assert_eq!(tracker.inspect(&block.id), None);
+
+        tracker.require(block.id);
+        assert_eq!(
+            tracker.inspect(&block.id),
+            Some(BlockInspection {
+                offers: 0,
+                required: true,
+                approved: false,
+                requested: false,
+            })
+        );
+
+        client.register(block.id, OfferState::Approved);
+        assert_eq!(
+            tracker.inspect(&block.id),
+            Some(BlockInspection {
+                offers: 1,
+                required: true,
+                approved: true,
+                requested: false,
+            })
+        );
+
+        let promise = client.offers().try_next().and_then(BlockOffer::accept);
+        assert!(promise.is_some());
+        assert_eq!(
+            tracker
+                .inspect(&block.id)
+                .map(|inspection| inspection.requested),
+            Some(true)
+        );
+
+        promise.unwrap().complete();
+        assert_eq!(tracker.inspect(&block.id), None);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn simple_async() {
         let tracker = BlockTracker::new();
@@ -584,6 +775,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn offer_accepted_after_client_drop_is_rejected() {
+        let tracker = BlockTracker::new();
+
+        let client0 = tracker.client();
+        let block: Block = rand::random();
+
+        tracker.require(block.id);
+        client0.register(block.id, OfferState::Approved);
+
+        let offer = client0.offers().try_next().unwrap();
+
+        // The peer disconnects while the offer is being processed...
+        drop(client0);
+
+        // ...so it can't be accepted anymore.
+        assert!(offer.accept().is_none());
+
+        // The peer reconnects and offers the block again.
+        let client1 = tracker.client();
+        client1.register(block.id, OfferState::Approved);
+
+        assert_eq!(
+            client1
+                .offers()
+                .try_next()
+                .and_then(BlockOffer::accept)
+                .as_ref()
+                .map(BlockPromise::block_id),
+            Some(&block.id)
+        );
+    }
+
+    #[test]
+    fn stale_promise_does_not_affect_reconnected_client() {
+        let tracker = BlockTracker::new();
+
+        let client0 = tracker.client();
+        let block: Block = rand::random();
+
+        tracker.require(block.id);
+        client0.register(block.id, OfferState::Approved);
+
+        let promise0 = client0.offers().try_next().and_then(BlockOffer::accept);
+        assert!(promise0.is_some());
+
+        // The peer disconnects while the request is in flight which expires the accepted offer
+        // immediately...
+        drop(client0);
+
+        // ...so the reconnected peer can be asked for the block right away.
+        let client1 = tracker.client();
+        client1.register(block.id, OfferState::Approved);
+
+        let promise1 = client1.offers().try_next().and_then(BlockOffer::accept);
+        assert_eq!(
+            promise1.as_ref().map(BlockPromise::block_id),
+            Some(&block.id)
+        );
+
+        // The stale request fails eventually. This must not release the new one.
+        drop(promise0);
+
+        let client2 = tracker.client();
+        client2.register(block.id, OfferState::Approved);
+        assert!(client2.offers().try_next().is_none());
+
+        // And the offers of the dropped client are gone.
+        assert!(client1.offers().try_next().is_none());
+    }
+
     #[test]
     fn fallback_on_client_drop_before_require() {
         let tracker = BlockTracker::new();
@@ -679,6 +941,80 @@ mod tests {
         assert!(offer2.is_none());
     }
 
+    #[test]
+    fn offers_limit() {
+        let tracker = BlockTracker::new();
+        let client = tracker.client();
+
+        let block_ids: Vec<BlockId> = (0..MAX_OFFERS_PER_CLIENT).map(|_| rand::random()).collect();
+
+        for block_id in &block_ids {
+            assert!(client.register(*block_id, OfferState::Approved));
+        }
+
+        // Offers over the limit are discarded.
+        let block_id: BlockId = rand::random();
+        assert!(!client.register(block_id, OfferState::Approved));
+        assert!(tracker.inspect(&block_id).is_none());
+        assert!(!client.take_overflowed());
+
+        // Once enough offers are done, the client is told to get the discarded ones again, but
+        // only once.
+        for block_id in &block_ids[..MAX_OFFERS_PER_CLIENT / 2 + 1] {
+            tracker.require(*block_id);
+            client
+                .offers()
+                .try_next()
+                .and_then(BlockOffer::accept)
+                .unwrap()
+                .complete();
+        }
+
+        assert!(client.take_overflowed());
+        assert!(!client.take_overflowed());
+        assert!(client.register(block_id, OfferState::Approved));
+    }
+
+    #[test]
+    fn outstanding_offers_limit() {
+        let tracker = BlockTracker::new();
+        let client = tracker.client();
+
+        for _ in 0..MAX_OUTSTANDING_OFFERS_PER_CLIENT + 2 {
+            let block: Block = rand::random();
+            tracker.require(block.id);
+            client.register(block.id, OfferState::Approved);
+        }
+
+        let mut promises: Vec<_> = (0..MAX_OUTSTANDING_OFFERS_PER_CLIENT)
+            .map(|_| {
+                client
+                    .offers()
+                    .try_next()
+                    .and_then(BlockOffer::accept)
+                    .unwrap()
+            })
+            .collect();
+
+        // The limit is reached, no more offers until some of the outstanding ones are done.
+        assert!(client.offers().try_next().is_none());
+
+        // Completing a request frees up a slot...
+        promises.pop().unwrap().complete();
+        let offer = client.offers().try_next().unwrap();
+        assert!(client.offers().try_next().is_none());
+
+        // ...and so does dropping an offer without accepting it.
+        drop(offer);
+        let promise = client.offers().try_next().and_then(BlockOffer::accept);
+        assert!(promise.is_some());
+        assert!(client.offers().try_next().is_none());
+
+        // Failed requests too.
+        drop(promises.pop());
+        assert!(client.offers().try_next().is_some());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn race() {
         let num_clients = 10;
@@ -0,0 +1,365 @@
+//! Synchronous (blocking) API for embedders that don't want to use async (e.g., simple CLI tools
+//! or tests).
+//!
+//! The wrappers run the async operations on a [`Runtime`] owned by this module. Their methods
+//! block the calling thread and so must not be called from within an async context.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use ouisync::{blocking, Access, RepositoryParams, WriteSecrets};
+//! use std::io::Write;
+//!
+//! let runtime = blocking::Runtime::new().unwrap();
+//! let params = RepositoryParams::new("/tmp/repo.ouisyncdb");
+//! let repo = blocking::Repository::create(
+//!     &runtime,
+//!     &params,
+//!     Access::WriteUnlocked {
+//!         secrets: WriteSecrets::random(),
+//!     },
+//! )
+//! .unwrap();
+//!
+//! let mut file = repo.create_file("hello.txt").unwrap();
+//! file.write_all(b"hello world").unwrap();
+//! file.flush().unwrap();
+//! ```
+
+use crate::{
+    access_control::{AccessMode, LocalSecret},
+    directory::EntryType,
+    error::{Error, Result},
+    joint_directory::{JointDirectory, JointEntryRef},
+    progress::Progress,
+    repository::RepositoryParams,
+    Access,
+};
+use camino::Utf8Path;
+use metrics::Recorder;
+use std::{
+    future::Future,
+    io::{self, SeekFrom},
+    sync::Arc,
+};
+
+/// Runtime on which the blocking wrappers run. Cloning it returns a handle to the same runtime.
+/// The runtime is shut down when the last handle to it (including the ones held by the wrappers)
+/// is dropped.
+#[derive(Clone)]
+pub struct Runtime {
+    inner: Arc<tokio::runtime::Runtime>,
+}
+
+impl Runtime {
+    pub fn new() -> io::Result<Self> {
+        let inner = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("ouisync-blocking")
+            .enable_all()
+            .build()?;
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Handle to the underlying tokio runtime. Can be used to run the parts of the async API that
+    /// have no blocking wrapper (e.g., the `Network`).
+    pub fn handle(&self) -> &tokio::runtime::Handle {
+        self.inner.handle()
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.inner.block_on(future)
+    }
+}
+
+/// Blocking wrapper for [`crate::Repository`].
+pub struct Repository {
+    inner: Arc<crate::Repository>,
+    runtime: Runtime,
+}
+
+impl Repository {
+    /// Creates a new repository.
+    pub fn create(
+        runtime: &Runtime,
+        params: &RepositoryParams<impl Recorder>,
+        access: Access,
+    ) -> Result<Self> {
+        let inner = runtime.block_on(crate::Repository::create(params, access))?;
+        Ok(Self::new(runtime, inner))
+    }
+
+    /// Opens an existing repository.
+    pub fn open(
+        runtime: &Runtime,
+        params: &RepositoryParams<impl Recorder>,
+        local_secret: Option<LocalSecret>,
+        access_mode: AccessMode,
+    ) -> Result<Self> {
+        let inner = runtime.block_on(crate::Repository::open(params, local_secret, access_mode))?;
+        Ok(Self::new(runtime, inner))
+    }
+
+    fn new(runtime: &Runtime, inner: crate::Repository) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            runtime: runtime.clone(),
+        }
+    }
+
+    /// Returns the underlying async repository (e.g., to register it with the `Network`).
+    pub fn as_async(&self) -> &Arc<crate::Repository> {
+        &self.inner
+    }
+
+    pub fn access_mode(&self) -> AccessMode {
+        self.inner.access_mode()
+    }
+
+    pub fn lookup_type<P: AsRef<Utf8Path>>(&self, path: P) -> Result<EntryType> {
+        self.runtime.block_on(self.inner.lookup_type(path))
+    }
+
+    pub fn open_file<P: AsRef<Utf8Path>>(&self, path: P) -> Result<File> {
+        let inner = self.runtime.block_on(self.inner.open_file(path))?;
+        Ok(File::new(&self.runtime, inner))
+    }
+
+    pub fn create_file<P: AsRef<Utf8Path>>(&self, path: P) -> Result<File> {
+        let inner = self.runtime.block_on(self.inner.create_file(path))?;
+        Ok(File::new(&self.runtime, inner))
+    }
+
+    pub fn open_directory<P: AsRef<Utf8Path>>(&self, path: P) -> Result<Directory> {
+        let inner = self.runtime.block_on(self.inner.open_directory(path))?;
+        Ok(Directory::new(&self.runtime, inner))
+    }
+
+    /// Creates a new directory (if it doesn't exist yet) and opens it.
+    pub fn create_directory<P: AsRef<Utf8Path>>(&self, path: P) -> Result<Directory> {
+        let path = path.as_ref();
+        let inner = self.runtime.block_on(async {
+            self.inner.create_directory(path).await?;
+            self.inner.open_directory(path).await
+        })?;
+
+        Ok(Directory::new(&self.runtime, inner))
+    }
+
+    pub fn remove_entry<P: AsRef<Utf8Path>>(&self, path: P) -> Result<()> {
+        self.runtime.block_on(self.inner.remove_entry(path))
+    }
+
+    pub fn remove_entry_recursively<P: AsRef<Utf8Path>>(&self, path: P) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.remove_entry_recursively(path))
+    }
+
+    pub fn move_entry<S: AsRef<Utf8Path>, D: AsRef<Utf8Path>>(
+        &self,
+        src_dir_path: S,
+        src_name: &str,
+        dst_dir_path: D,
+        dst_name: &str,
+    ) -> Result<()> {
+        self.runtime.block_on(
+            self.inner
+                .move_entry(src_dir_path, src_name, dst_dir_path, dst_name),
+        )
+    }
+
+    pub fn sync_progress(&self) -> Result<Progress> {
+        self.runtime.block_on(self.inner.sync_progress())
+    }
+
+    /// Closes the repository.
+    pub fn close(self) -> Result<()> {
+        self.runtime.block_on(self.inner.close())
+    }
+}
+
+/// Blocking wrapper for [`crate::File`]. Implements the standard `Read`, `Write` and `Seek`
+/// traits. Note that the writes are not persisted until [`File::flush`] is called.
+pub struct File {
+    inner: crate::File,
+    runtime: Runtime,
+}
+
+impl File {
+    fn new(runtime: &Runtime, inner: crate::File) -> Self {
+        Self {
+            inner,
+            runtime: runtime.clone(),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads all data from the current seek position until the end.
+    pub fn read_to_end(&mut self) -> Result<Vec<u8>> {
+        self.runtime.block_on(self.inner.read_to_end())
+    }
+
+    /// Truncates the file to the given length.
+    pub fn truncate(&mut self, len: u64) -> Result<()> {
+        self.inner.truncate(len)
+    }
+
+    /// Saves any pending modifications.
+    pub fn flush(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.flush())
+    }
+
+    /// Returns the underlying async file.
+    pub fn into_async(self) -> crate::File {
+        self.inner
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.runtime
+            .block_on(self.inner.read(buf))
+            .map_err(into_io_error)
+    }
+}
+
+impl io::Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.runtime
+            .block_on(self.inner.write(buf))
+            .map_err(into_io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        File::flush(self).map_err(into_io_error)
+    }
+}
+
+impl io::Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        Ok(self.inner.seek(pos))
+    }
+}
+
+/// Blocking wrapper for [`crate::JointDirectory`].
+pub struct Directory {
+    inner: JointDirectory,
+    runtime: Runtime,
+}
+
+impl Directory {
+    fn new(runtime: &Runtime, inner: JointDirectory) -> Self {
+        Self {
+            inner,
+            runtime: runtime.clone(),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = JointEntryRef> {
+        self.inner.entries()
+    }
+
+    pub fn lookup_unique<'a>(&'a self, name: &'a str) -> Result<JointEntryRef<'a>> {
+        self.inner.lookup_unique(name)
+    }
+
+    /// Opens the subdirectory at the given path (relative to this directory).
+    pub fn cd(&self, path: impl AsRef<Utf8Path>) -> Result<Self> {
+        let inner = self.runtime.block_on(self.inner.cd(path))?;
+        Ok(Self::new(&self.runtime, inner))
+    }
+
+    /// Opens the file with the given name in this directory.
+    pub fn open_file(&self, name: &str) -> Result<File> {
+        let inner = self
+            .runtime
+            .block_on(self.inner.lookup_unique(name)?.file()?.open())?;
+        Ok(File::new(&self.runtime, inner))
+    }
+
+    pub fn remove_entry(&mut self, name: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.remove_entry(name))
+    }
+
+    pub fn remove_entry_recursively(&mut self, name: &str) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.remove_entry_recursively(name))
+    }
+
+    /// Returns the underlying async directory.
+    pub fn into_async(self) -> JointDirectory {
+        self.inner
+    }
+}
+
+fn into_io_error(error: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_control::WriteSecrets, db};
+    use assert_matches::assert_matches;
+    use std::io::{Read, Seek, Write};
+
+    #[test]
+    fn create_write_read() {
+        let runtime = Runtime::new().unwrap();
+        let (_base_dir, pool) = runtime.block_on(db::create_temp()).unwrap();
+        let params = RepositoryParams::with_pool(pool, "test");
+        let repo = Repository::create(
+            &runtime,
+            &params,
+            Access::WriteUnlocked {
+                secrets: WriteSecrets::random(),
+            },
+        )
+        .unwrap();
+
+        repo.create_directory("dir").unwrap();
+
+        let mut file = repo.create_file("dir/test.txt").unwrap();
+        file.write_all(b"hello world").unwrap();
+        Write::flush(&mut file).unwrap();
+        drop(file);
+
+        assert_matches!(repo.lookup_type("dir"), Ok(EntryType::Directory));
+
+        let dir = repo.open_directory("dir").unwrap();
+        assert_eq!(
+            dir.entries().map(|entry| entry.name()).collect::<Vec<_>>(),
+            ["test.txt"]
+        );
+
+        let mut file = dir.open_file("test.txt").unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hello world");
+
+        file.seek(SeekFrom::Start(6)).unwrap();
+        assert_eq!(file.read_to_end().unwrap(), b"world");
+
+        drop(file);
+        drop(dir);
+
+        repo.close().unwrap();
+    }
+}
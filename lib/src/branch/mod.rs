@@ -3,16 +3,24 @@ use crate::{
     blob::lock::{BranchLocker, Locker},
     crypto::sign::PublicKey,
     debug::DebugPrinter,
-    directory::{Directory, DirectoryFallback, DirectoryLocking, EntryRef},
+    directory::{Directory, DirectoryFallback, DirectoryLimits, DirectoryLocking, EntryRef},
     error::{Error, Result},
     event::{EventScope, EventSender, Payload},
     file::{File, FileProgressCache},
     path,
     protocol::{BlockId, Locator, Proof, RootNodeFilter},
-    store::{self, Store},
+    rng::SharedRng,
+    store::{self, ReadTransaction, Store},
     version_vector::VersionVector,
 };
 use camino::{Utf8Component, Utf8Path};
+use deadlock::BlockingRwLock;
+use futures_util::{future, TryStreamExt};
+use rand::Rng;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 #[derive(Clone)]
 pub struct Branch {
@@ -115,7 +123,7 @@ impl Branch {
                     } else {
                         curr.create_directory(
                             name.to_string(),
-                            rand::random(),
+                            self.rng().gen(),
                             &VersionVector::new(),
                         )
                         .await?
@@ -157,6 +165,52 @@ impl Branch {
         &self.shared.file_progress_cache
     }
 
+    /// Whether large directories may be saved compressed (see
+    /// `Repository::set_directory_compression`).
+    pub(crate) fn is_directory_compression_enabled(&self) -> bool {
+        self.shared.directory_compression.load(Ordering::Acquire)
+    }
+
+    /// If write fencing is enabled, checks that this branch is not strictly behind any other
+    /// branch and fails with `Error::Outdated` if it is. Should be called before every local
+    /// modification of this branch (but not before merges which are what brings it up to date).
+    pub(crate) async fn check_write_fence(&self, tx: &mut ReadTransaction) -> Result<()> {
+        if !self.shared.write_fence.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        if is_outdated(tx, &self.id).await? {
+            Err(Error::Outdated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that there is enough free space left on the device holding the store and fails with
+    /// `Error::StorageFull` if not (see `Repository::set_min_free_space`). Should be called before
+    /// every local modification of this branch which grows the store.
+    pub(crate) fn check_disk_space(&self) -> Result<()> {
+        match self.store.db().disk_space().check() {
+            Ok(()) => Ok(()),
+            Err(crossed) => {
+                if crossed {
+                    self.event_tx.send(Payload::StorageFull);
+                }
+
+                Err(Error::StorageFull)
+            }
+        }
+    }
+
+    pub(crate) fn directory_limits(&self) -> DirectoryLimits {
+        *self.shared.directory_limits.read().unwrap()
+    }
+
+    /// Generator of the ids of the new blobs (see `RepositoryParams::with_rng`).
+    pub(crate) fn rng(&self) -> SharedRng {
+        self.shared.rng.clone()
+    }
+
     pub(crate) fn notify(&self) -> BranchEventSender {
         BranchEventSender {
             event_tx: self.event_tx.clone(),
@@ -213,17 +267,47 @@ impl Branch {
 pub(crate) struct BranchShared {
     pub locker: Locker,
     pub file_progress_cache: FileProgressCache,
+    pub directory_limits: Arc<BlockingRwLock<DirectoryLimits>>,
+    pub write_fence: Arc<AtomicBool>,
+    pub directory_compression: Arc<AtomicBool>,
+    pub rng: SharedRng,
 }
 
 impl BranchShared {
     pub fn new() -> Self {
+        Self::with_rng(SharedRng::default())
+    }
+
+    pub fn with_rng(rng: SharedRng) -> Self {
         Self {
             locker: Locker::new(),
             file_progress_cache: FileProgressCache::new(),
+            directory_limits: Arc::new(BlockingRwLock::new(DirectoryLimits::default())),
+            write_fence: Arc::new(AtomicBool::new(false)),
+            directory_compression: Arc::new(AtomicBool::new(false)),
+            rng,
         }
     }
 }
 
+/// Is the given branch strictly behind (happens-before) any other branch?
+pub(crate) async fn is_outdated(tx: &mut ReadTransaction, branch_id: &PublicKey) -> Result<bool> {
+    let local_vv = match tx.load_root_node(branch_id, RootNodeFilter::Any).await {
+        Ok(node) => node.proof.into_version_vector(),
+        Err(store::Error::BranchNotFound) => VersionVector::new(),
+        Err(error) => return Err(error.into()),
+    };
+
+    let other_vvs: Vec<_> = tx
+        .load_root_nodes()
+        .try_filter(|node| future::ready(node.proof.writer_id != *branch_id))
+        .map_ok(|node| node.proof.into_version_vector())
+        .try_collect()
+        .await?;
+
+    Ok(other_vvs.iter().any(|other_vv| local_vv < *other_vv))
+}
+
 /// Sender to send event notification for the given branch.
 #[derive(Clone)]
 pub(crate) struct BranchEventSender {
@@ -303,6 +387,38 @@ mod tests {
         assert_matches!(file.flush().await, Err(Error::PermissionDenied));
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_fence() {
+        let (_base_dir, branch_a) = setup().await;
+        branch_a.shared.write_fence.store(true, Ordering::Release);
+
+        let mut root_a = branch_a.open_or_create_root().await.unwrap();
+        let mut file = root_a.create_file("a.txt".into()).await.unwrap();
+        file.write_all(b"a").await.unwrap();
+        file.flush().await.unwrap();
+
+        // Branch b is a copy of branch a plus one more modification, so a is now strictly behind.
+        let branch_b = branch_a.clone_into(PublicKey::random()).await.unwrap();
+        branch_b
+            .open_root(DirectoryLocking::Enabled, DirectoryFallback::Disabled)
+            .await
+            .unwrap()
+            .create_file("b.txt".into())
+            .await
+            .unwrap();
+
+        assert_matches!(
+            root_a.create_file("c.txt".into()).await,
+            Err(Error::Outdated)
+        );
+
+        file.write_all(b"b").await.unwrap();
+        assert_matches!(file.flush().await, Err(Error::Outdated));
+
+        branch_a.shared.write_fence.store(false, Ordering::Release);
+        root_a.create_file("c.txt".into()).await.unwrap();
+    }
+
     async fn setup() -> (TempDir, Branch) {
         let (base_dir, pool) = db::create_temp().await.unwrap();
 
@@ -0,0 +1,133 @@
+//! Selection of the platform specific implementations of the hash and cipher primitives.
+//!
+//! Both `blake3` and `chacha20` detect the CPU features at runtime and dispatch to the fastest
+//! implementation the CPU supports, falling back to the portable one. This module mirrors that
+//! detection so the selected implementations can be reported (e.g., to tell why crypto is slow on
+//! a particular device). On aarch64 the NEON implementations are enabled at compile time (see the
+//! `neon` feature of `blake3` in `Cargo.toml` and the `chacha20_force_neon` cfg in
+//! `.cargo/config.toml`) because NEON is mandatory there.
+
+use once_cell::sync::Lazy;
+use state_monitor::{MonitoredValue, StateMonitor};
+
+/// Implementations of the crypto primitives selected for the current CPU.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Backend {
+    /// Implementation of the BLAKE3 hash.
+    pub hash: &'static str,
+    /// Implementation of the ChaCha20 cipher.
+    pub cipher: &'static str,
+}
+
+impl Backend {
+    /// Returns the implementations selected for the current CPU. The detection runs only once.
+    pub fn get() -> Self {
+        static BACKEND: Lazy<Backend> = Lazy::new(Backend::detect);
+        *BACKEND
+    }
+
+    fn detect() -> Self {
+        Self {
+            hash: detect_hash(),
+            cipher: detect_cipher(),
+        }
+    }
+}
+
+/// Publishes the selected crypto implementations in the state monitor for as long as it's alive.
+pub struct BackendMonitor {
+    _hash: MonitoredValue<&'static str>,
+    _cipher: MonitoredValue<&'static str>,
+}
+
+impl BackendMonitor {
+    pub fn new(monitor: StateMonitor) -> Self {
+        let backend = Backend::get();
+
+        Self {
+            _hash: monitor.make_value("hash", backend.hash),
+            _cipher: monitor.make_value("cipher", backend.cipher),
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn detect_hash() -> &'static str {
+    // Same order of preference as in `blake3`, which uses AVX-512 only on x86_64.
+    if cfg!(target_arch = "x86_64")
+        && is_x86_feature_detected!("avx512f")
+        && is_x86_feature_detected!("avx512vl")
+    {
+        "avx512"
+    } else if is_x86_feature_detected!("avx2") {
+        "avx2"
+    } else if is_x86_feature_detected!("sse4.1") {
+        "sse41"
+    } else if is_x86_feature_detected!("sse2") {
+        "sse2"
+    } else {
+        "portable"
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn detect_hash() -> &'static str {
+    "neon"
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn detect_hash() -> &'static str {
+    "portable"
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn detect_cipher() -> &'static str {
+    // Same order of preference as in `chacha20`.
+    if is_x86_feature_detected!("avx2") {
+        "avx2"
+    } else if is_x86_feature_detected!("sse2") {
+        "sse2"
+    } else {
+        "soft"
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[allow(unexpected_cfgs)] // `chacha20_force_neon` is set by `.cargo/config.toml`, not by cargo.
+fn detect_cipher() -> &'static str {
+    if cfg!(chacha20_force_neon) {
+        "neon"
+    } else {
+        "soft"
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn detect_cipher() -> &'static str {
+    "soft"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monitor() {
+        let root = StateMonitor::make_root();
+        let monitor = root.make_child("Crypto");
+        let backend = Backend::get();
+        let backend_monitor = BackendMonitor::new(monitor.clone());
+
+        assert_eq!(
+            monitor.get_value::<&'static str>("hash").ok(),
+            Some(backend.hash)
+        );
+        assert_eq!(
+            monitor.get_value::<&'static str>("cipher").ok(),
+            Some(backend.cipher)
+        );
+
+        drop(backend_monitor);
+        assert!(monitor.get_value::<&'static str>("hash").is_err());
+    }
+}
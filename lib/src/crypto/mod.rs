@@ -1,10 +1,6 @@
-pub mod cipher;
-mod hash;
-mod password;
-pub mod sign;
+//! Cryptographic primitives. Everything except `backend` is re-exported from `ouisync_protocol`.
 
-pub(crate) use self::hash::CacheHash;
-pub use self::{
-    hash::{Digest, Hash, Hashable},
-    password::{Password, PasswordSalt},
-};
+pub mod backend;
+
+pub(crate) use ouisync_protocol::crypto::CacheHash;
+pub use ouisync_protocol::crypto::{cipher, sign, Digest, Hash, Hashable, Password, PasswordSalt};
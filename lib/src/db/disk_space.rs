@@ -0,0 +1,168 @@
+//! Guard against running out of disk space. SQLite fails with `SQLITE_FULL` in the middle of a
+//! transaction when the device fills up and leaves the other applications on the device starved
+//! of space too, so the writes are refused already when the free space drops below a threshold.
+//!
+//! The free space is queried only on unix platforms and on Windows. Elsewhere it's unknown and so
+//! the guard never refuses anything.
+
+use deadlock::BlockingMutex;
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Default minimum free space on the device holding the database.
+pub const DEFAULT_MIN_FREE_SPACE: u64 = 64 * 1024 * 1024;
+
+// Querying the free space takes a syscall so the result is reused for this long.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) struct DiskSpace {
+    path: Option<PathBuf>,
+    min_free: AtomicU64,
+    low: AtomicBool,
+    last_check: BlockingMutex<Option<(Instant, Option<u64>)>>,
+}
+
+impl DiskSpace {
+    pub fn new(path: &Path) -> Self {
+        Self::with_path(Some(path.to_owned()))
+    }
+
+    /// Guard whose free space is never known and which therefore never refuses anything.
+    pub fn unknown() -> Self {
+        Self::with_path(None)
+    }
+
+    fn with_path(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            min_free: AtomicU64::new(DEFAULT_MIN_FREE_SPACE),
+            low: AtomicBool::new(false),
+            last_check: BlockingMutex::new(None),
+        }
+    }
+
+    /// Sets the minimum free space in bytes. Zero disables the guard.
+    pub fn set_min_free(&self, bytes: u64) {
+        self.min_free.store(bytes, Ordering::Relaxed);
+        // Re-check right away with the new threshold.
+        *self.last_check.lock().unwrap() = None;
+    }
+
+    pub fn min_free(&self) -> u64 {
+        self.min_free.load(Ordering::Relaxed)
+    }
+
+    /// Checks whether there is still enough free space on the device. On `Err` returns whether the
+    /// space dropped below the threshold just now (as opposed to already at the previous check).
+    /// Succeeds if the free space can't be determined.
+    pub fn check(&self) -> Result<(), bool> {
+        let min_free = self.min_free();
+        let low = min_free > 0
+            && self
+                .available()
+                .map(|available| available < min_free)
+                .unwrap_or(false);
+
+        let was_low = self.low.swap(low, Ordering::Relaxed);
+
+        if low {
+            Err(!was_low)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn available(&self) -> Option<u64> {
+        let path = self.path.as_deref()?;
+        let mut last_check = self.last_check.lock().unwrap();
+
+        match *last_check {
+            Some((timestamp, available)) if timestamp.elapsed() < CHECK_INTERVAL => available,
+            _ => {
+                let available = available_space(path);
+                *last_check = Some((Instant::now(), available));
+                available
+            }
+        }
+    }
+}
+
+/// Free space in bytes available to unprivileged users on the device holding `path`.
+#[cfg(unix)]
+fn available_space(path: &Path) -> Option<u64> {
+    use nix::sys::statvfs::statvfs;
+
+    match statvfs(path) {
+        Ok(stat) => Some(stat.blocks_available() as u64 * stat.fragment_size() as u64),
+        Err(error) => {
+            tracing::warn!(?path, ?error, "Failed to query free disk space");
+            None
+        }
+    }
+}
+
+/// Free space in bytes available to the current user on the device holding `path`.
+#[cfg(windows)]
+fn available_space(path: &Path) -> Option<u64> {
+    use std::{io, mem, os::windows::ffi::OsStrExt, ptr};
+    use winapi::{shared::ntdef::ULARGE_INTEGER, um::fileapi::GetDiskFreeSpaceExW};
+
+    // `GetDiskFreeSpaceExW` accepts only directories, but `path` is usually the database file.
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let dir_wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+
+    // SAFETY: `ULARGE_INTEGER` is plain integer data so all zeros is a valid value.
+    let mut available: ULARGE_INTEGER = unsafe { mem::zeroed() };
+
+    // SAFETY: `dir_wide` is a null terminated wide string and `available` is a valid pointer. The
+    // total size and the total free space outputs are optional so they can be null.
+    let result = unsafe {
+        GetDiskFreeSpaceExW(
+            dir_wide.as_ptr(),
+            &mut available,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+
+    if result != 0 {
+        // SAFETY: `ULARGE_INTEGER` is a union whose every variant is valid for any bit pattern.
+        Some(unsafe { *available.QuadPart() })
+    } else {
+        let error = io::Error::last_os_error();
+        tracing::warn!(?path, ?error, "Failed to query free disk space");
+        None
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(all(test, any(unix, windows)))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn check() {
+        let dir = TempDir::new().unwrap();
+        let guard = DiskSpace::new(dir.path());
+
+        guard.set_min_free(1);
+        assert_eq!(guard.check(), Ok(()));
+
+        guard.set_min_free(u64::MAX);
+        assert_eq!(guard.check(), Err(true));
+        assert_eq!(guard.check(), Err(false));
+
+        guard.set_min_free(0);
+        assert_eq!(guard.check(), Ok(()));
+
+        assert_eq!(DiskSpace::unknown().check(), Ok(()));
+    }
+}
@@ -0,0 +1,70 @@
+//! Freezing the database so it can be copied by external backup tools. While frozen, nothing is
+//! written into the database files so a copy of them taken at any point is consistent.
+
+use sqlx::{Row, SqliteConnection};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::sync::OwnedMutexGuard;
+
+/// What happens to the writes while the database is frozen.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FreezeMode {
+    /// The writes wait until the freeze ends.
+    Queue,
+    /// The writes fail right away with a busy error (see `Error::is_retryable`). This includes
+    /// storing the data received from the peers, which is then synced again after the freeze.
+    FailFast,
+}
+
+/// Keeps the database frozen until dropped. Obtained with `Repository::freeze`.
+pub struct FreezeGuard {
+    _conn: OwnedMutexGuard<Option<SqliteConnection>>,
+    fail_writes: Option<Arc<AtomicBool>>,
+    checkpointed: bool,
+}
+
+impl FreezeGuard {
+    pub(super) async fn new(
+        mut conn: OwnedMutexGuard<Option<SqliteConnection>>,
+        mode: FreezeMode,
+        fail_writes: &Arc<AtomicBool>,
+    ) -> Result<Self, sqlx::Error> {
+        // Move everything from the write-ahead log into the main file so it's usually enough to
+        // copy just that. Can't complete while some reader still uses the log.
+        let busy: i64 = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .fetch_one(conn.as_mut().ok_or(sqlx::Error::PoolClosed)?)
+            .await?
+            .get(0);
+
+        let fail_writes = match mode {
+            FreezeMode::Queue => None,
+            FreezeMode::FailFast => {
+                fail_writes.store(true, Ordering::Release);
+                Some(fail_writes.clone())
+            }
+        };
+
+        Ok(Self {
+            _conn: conn,
+            fail_writes,
+            checkpointed: busy == 0,
+        })
+    }
+
+    /// Whether the whole write-ahead log was moved into the main database file when freezing. If
+    /// so, copying the main file is enough. Otherwise the `-wal` file needs to be copied along
+    /// with it.
+    pub fn checkpointed(&self) -> bool {
+        self.checkpointed
+    }
+}
+
+impl Drop for FreezeGuard {
+    fn drop(&mut self) {
+        if let Some(fail_writes) = &self.fail_writes {
+            fail_writes.store(false, Ordering::Release);
+        }
+    }
+}
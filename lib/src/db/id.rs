@@ -1,3 +1,4 @@
+use ouisync_protocol::define_byte_array_wrapper;
 use rand::{
     distributions::{Distribution, Standard},
     Rng,
@@ -24,6 +24,17 @@ pub(super) async fn run(pool: &Pool) -> Result<(), Error> {
     Ok(())
 }
 
+/// Checks that the database is at the latest schema version, without migrating it.
+pub(super) async fn check(pool: &Pool) -> Result<(), Error> {
+    let mut conn = pool.acquire().await?;
+
+    if get_version(&mut conn).await? == *SCHEMA_VERSION {
+        Ok(())
+    } else {
+        Err(Error::SchemaVersion)
+    }
+}
+
 static MIGRATIONS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/src/db/migrations");
 
 fn get_migration<'a>(file: &'a File<'_>) -> Option<(u32, &'a str)> {
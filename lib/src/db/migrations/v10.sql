@@ -0,0 +1,11 @@
+-- Bound the size of the receive filter by evicting the least recently used entries. Also allow
+-- the filter to persist across restarts.
+
+ALTER TABLE received_nodes ADD COLUMN last_used INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX index_received_nodes_on_client_id_and_last_used
+    ON received_nodes (client_id, last_used);
+
+-- Client id 0 is now reserved for the persistent filter. Remove any leftovers from previous runs
+-- so they are not mistaken for it.
+DELETE FROM received_nodes;
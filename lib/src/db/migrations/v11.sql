@@ -0,0 +1,8 @@
+-- Final versions of the retired writers (writers whose branches have been fully merged into all
+-- the other branches and then removed). Snapshots of these writers that contain nothing newer
+-- than the final version are discarded so the removed branches are not restored.
+CREATE TABLE version_vector_tombstones (
+    writer_id BLOB NOT NULL PRIMARY KEY,
+    version   INTEGER NOT NULL,
+    epoch     INTEGER NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Data derived from the file contents by the app (e.g., thumbnails). Only the latest result per
+-- file (blob) and kind is kept, together with the hash of the file version it was derived from.
+CREATE TABLE derived_data (
+    blob_id      BLOB NOT NULL,
+    kind         TEXT NOT NULL,
+    version_hash BLOB NOT NULL,
+    data         BLOB NOT NULL,
+
+    PRIMARY KEY (blob_id, kind)
+);
@@ -1,14 +1,39 @@
+//! Access to the SQLite database of a repository.
+//!
+//! # Concurrency
+//!
+//! The database runs in WAL mode with a single writable connection and a pool of read-only ones:
+//!
+//! - Write transactions ([`WriteTransaction`]) are serialized: a long one (e.g., flushing a big
+//!   file) delays the other writers until it commits.
+//! - Read transactions ([`ReadTransaction`]) never wait for the writer. Each one reads a consistent
+//!   snapshot of the database as of its start and doesn't see the changes committed afterwards.
+//!   So directory listings, file reads, etc. proceed concurrently with a long flush and see the
+//!   state from before it.
+//!
+//! Consequently, operations that only read should use `begin_read` and not `begin_write`, and
+//! read-modify-write operations need to re-read the data inside the write transaction (see e.g.
+//! `Directory::refresh_in`) since the snapshot they read before might be stale by then.
+
 #[macro_use]
 mod macros;
 
 mod connection;
+mod disk_space;
+mod freeze;
 mod id;
 mod migrations;
 mod mutex;
+mod recovery;
 mod transaction;
+mod tuning;
 
+pub use disk_space::DEFAULT_MIN_FREE_SPACE;
+pub use freeze::{FreezeGuard, FreezeMode};
 pub use id::DatabaseId;
 pub use migrations::SCHEMA_VERSION;
+pub use recovery::DbRecovery;
+pub use tuning::{DbSynchronous, DbTuning, UnknownDbTuningPreset};
 
 use tracing::Span;
 
@@ -19,9 +44,7 @@ use self::{
 use deadlock::ExpectShortLifetime;
 use ref_cast::RefCast;
 use sqlx::{
-    sqlite::{
-        Sqlite, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous,
-    },
+    sqlite::{Sqlite, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
     Row, SqlitePool,
 };
 use std::{
@@ -31,6 +54,10 @@ use std::{
     ops::{Deref, DerefMut},
     panic::Location,
     path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 #[cfg(test)]
@@ -40,26 +67,45 @@ use tokio::{fs, task};
 
 const WARN_AFTER_TRANSACTION_LIFETIME: Duration = Duration::from_secs(3);
 
-pub(crate) use self::connection::Connection;
+pub(crate) use self::{connection::Connection, disk_space::DiskSpace};
 
 /// Database connection pool.
 #[derive(Clone)]
 pub(crate) struct Pool {
+    // Path to the main database file.
+    path: Arc<Path>,
     // Pool with multiple read-only connections
     reads: SqlitePool,
     // Single writable connection.
     write: ConnectionMutex,
+    recovery: DbRecovery,
+    disk_space: Arc<DiskSpace>,
+    // Set while frozen with `FreezeMode::FailFast`.
+    fail_writes: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum OpenMode {
+    Create,
+    Open,
+    // Both the read and the "write" connections are read-only. Used to open databases we don't
+    // own (e.g., a copy of a store on an external disk).
+    ReadOnly,
 }
 
 impl Pool {
-    async fn create(connect_options: SqliteConnectOptions) -> Result<Self, sqlx::Error> {
-        let common_options = connect_options
+    async fn create(path: &Path, tuning: &DbTuning, mode: OpenMode) -> Result<Self, sqlx::Error> {
+        let connect_options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(mode == OpenMode::Create);
+
+        let common_options = tuning
+            .apply(connect_options)
             .journal_mode(SqliteJournalMode::Wal)
-            .synchronous(SqliteSynchronous::Normal)
             .pragma("recursive_triggers", "ON")
-            .optimize_on_close(true, Some(1000));
+            .optimize_on_close(mode != OpenMode::ReadOnly, Some(1000));
 
-        let write_options = common_options.clone();
+        let write_options = common_options.clone().read_only(mode == OpenMode::ReadOnly);
         let write = ConnectionMutex::connect(write_options).await?;
 
         let read_options = common_options.read_only(true);
@@ -69,7 +115,30 @@ impl Pool {
             .connect_with(read_options)
             .await?;
 
-        Ok(Self { reads, write })
+        Ok(Self {
+            path: path.into(),
+            reads,
+            write,
+            recovery: DbRecovery::default(),
+            disk_space: Arc::new(DiskSpace::unknown()),
+            fail_writes: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Path to the main database file. The auxiliary files (*-wal and *-shm) are next to it.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What was done to recover the database files when this pool was created. See
+    /// [`DbRecovery`].
+    pub fn recovery(&self) -> DbRecovery {
+        self.recovery
+    }
+
+    /// Guard of the free space on the device holding the database. See [`DiskSpace`].
+    pub fn disk_space(&self) -> &DiskSpace {
+        &self.disk_space
     }
 
     /// Acquire a read-only database connection.
@@ -114,6 +183,10 @@ impl Pool {
         let location = Location::caller();
 
         async move {
+            if self.fail_writes.load(Ordering::Acquire) {
+                return Err(sqlx::Error::PoolTimedOut);
+            }
+
             let tx = self.write.begin().await?;
 
             let track_lifetime =
@@ -128,6 +201,13 @@ impl Pool {
         }
     }
 
+    /// Freezes the database: waits for the current write transaction (if any) to finish and then
+    /// blocks the subsequent ones, as specified by `mode`, until the returned guard is dropped. The
+    /// reads are not affected. Closing the pool waits for the guard to be dropped.
+    pub async fn freeze(&self, mode: FreezeMode) -> Result<FreezeGuard, sqlx::Error> {
+        FreezeGuard::new(self.write.lock().await, mode, &self.fail_writes).await
+    }
+
     pub(crate) async fn close(&self) -> Result<(), sqlx::Error> {
         // Make sure to first close `reads` and only then `write`. That way when closing the write
         // connection it is the last remaining connection and so it performs a WAL checkpoint and
@@ -305,7 +385,7 @@ impl std::fmt::Debug for WriteTransaction {
 impl_executor_by_deref!(WriteTransaction);
 
 /// Creates a new database and opens a connection to it.
-pub(crate) async fn create(path: impl AsRef<Path>) -> Result<Pool, Error> {
+pub(crate) async fn create(path: impl AsRef<Path>, tuning: &DbTuning) -> Result<Pool, Error> {
     let path = path.as_ref();
 
     if fs::metadata(path).await.is_ok() {
@@ -313,12 +393,13 @@ pub(crate) async fn create(path: impl AsRef<Path>) -> Result<Pool, Error> {
     }
 
     create_directory(path).await?;
+    let recovery = recovery::before_create(path).await?;
 
-    let connect_options = SqliteConnectOptions::new()
-        .filename(path)
-        .create_if_missing(true);
-
-    let pool = Pool::create(connect_options).await.map_err(Error::Open)?;
+    let mut pool = Pool::create(path, tuning, OpenMode::Create)
+        .await
+        .map_err(Error::Open)?;
+    pool.recovery = recovery;
+    pool.disk_space = Arc::new(DiskSpace::new(path));
 
     migrations::run(&pool).await?;
 
@@ -329,21 +410,47 @@ pub(crate) async fn create(path: impl AsRef<Path>) -> Result<Pool, Error> {
 #[cfg(test)]
 pub(crate) async fn create_temp() -> Result<(TempDir, Pool), Error> {
     let temp_dir = TempDir::new().map_err(Error::CreateDirectory)?;
-    let pool = create(temp_dir.path().join("temp.db")).await?;
+    let pool = create(temp_dir.path().join("temp.db"), &DbTuning::default()).await?;
 
     Ok((temp_dir, pool))
 }
 
-/// Opens a connection to the specified database. Fails if the db doesn't exist.
-pub(crate) async fn open(path: impl AsRef<Path>) -> Result<Pool, Error> {
-    let connect_options = SqliteConnectOptions::new().filename(path);
-    let pool = Pool::create(connect_options).await.map_err(Error::Open)?;
+/// Opens a connection to the specified database. Fails if the db doesn't exist. Recovers the
+/// database files left behind if it wasn't closed cleanly (see [`DbRecovery`]).
+pub(crate) async fn open(path: impl AsRef<Path>, tuning: &DbTuning) -> Result<Pool, Error> {
+    let path = path.as_ref();
+    let recovery = recovery::before_open(path).await?;
+
+    let mut pool = Pool::create(path, tuning, OpenMode::Open)
+        .await
+        .map_err(Error::Open)?;
+    pool.recovery = recovery;
+    pool.disk_space = Arc::new(DiskSpace::new(path));
 
     migrations::run(&pool).await?;
 
     Ok(pool)
 }
 
+/// Opens the specified database read-only. Unlike [`open`], doesn't recover the database files
+/// and doesn't run the migrations, so the files are left exactly as they are. Fails with
+/// [`Error::SchemaVersion`] if the database is not at the current schema version.
+pub(crate) async fn open_read_only(
+    path: impl AsRef<Path>,
+    tuning: &DbTuning,
+) -> Result<Pool, Error> {
+    let pool = Pool::create(path.as_ref(), tuning, OpenMode::ReadOnly)
+        .await
+        .map_err(Error::Open)?;
+
+    if let Err(error) = migrations::check(&pool).await {
+        pool.close().await?;
+        return Err(error);
+    }
+
+    Ok(pool)
+}
+
 async fn create_directory(path: &Path) -> Result<(), Error> {
     if let Some(dir) = path.parent() {
         fs::create_dir_all(dir)
@@ -376,6 +483,8 @@ pub enum Error {
     Open(#[source] sqlx::Error),
     #[error("failed to execute database query")]
     Query(#[from] sqlx::Error),
+    #[error("database schema version mismatch")]
+    SchemaVersion,
 }
 
 async fn get_pragma(conn: &mut Connection, name: &str) -> Result<u32, Error> {
@@ -397,6 +506,7 @@ async fn set_pragma(conn: &mut Connection, name: &str, value: u32) -> Result<(),
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::time;
 
     // Check the casts are lossless
 
@@ -420,4 +530,204 @@ mod tests {
         assert_eq!(encode_u64(u64::MAX / 2 + 1), i64::MIN);
         assert_eq!(encode_u64(u64::MAX), -1);
     }
+
+    #[tokio::test]
+    async fn read_during_write() {
+        let (_temp_dir, pool) = create_temp().await.unwrap();
+
+        let mut tx = pool.begin_write().await.unwrap();
+        sqlx::query("CREATE TABLE test (value INTEGER)")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // Write transaction left open (as during a long flush).
+        let mut write_tx = pool.begin_write().await.unwrap();
+        sqlx::query("INSERT INTO test (value) VALUES (1)")
+            .execute(&mut write_tx)
+            .await
+            .unwrap();
+
+        // Reading doesn't block and sees the snapshot from before the write.
+        let mut read_tx = time::timeout(Duration::from_secs(5), pool.begin_read())
+            .await
+            .unwrap()
+            .unwrap();
+        let count: u32 = sqlx::query("SELECT COUNT(*) FROM test")
+            .fetch_one(&mut read_tx)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 0);
+        drop(read_tx);
+
+        write_tx.commit().await.unwrap();
+
+        let mut read_tx = pool.begin_read().await.unwrap();
+        let count: u32 = sqlx::query("SELECT COUNT(*) FROM test")
+            .fetch_one(&mut read_tx)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn freeze() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("temp.db");
+        let pool = create(&path, &DbTuning::default()).await.unwrap();
+
+        let mut tx = pool.begin_write().await.unwrap();
+        sqlx::query("CREATE TABLE test (value INTEGER)")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // Writes wait for the freeze to end but reads proceed.
+        let guard = pool.freeze(FreezeMode::Queue).await.unwrap();
+        assert!(guard.checkpointed());
+        assert_eq!(
+            fs::metadata(temp_dir.path().join("temp.db-wal"))
+                .await
+                .unwrap()
+                .len(),
+            0
+        );
+
+        assert!(
+            time::timeout(Duration::from_millis(100), pool.begin_write())
+                .await
+                .is_err()
+        );
+
+        let mut read_tx = pool.begin_read().await.unwrap();
+        sqlx::query("SELECT COUNT(*) FROM test")
+            .fetch_one(&mut read_tx)
+            .await
+            .unwrap();
+        drop(read_tx);
+
+        drop(guard);
+        pool.begin_write().await.unwrap();
+
+        // Writes fail right away.
+        let guard = pool.freeze(FreezeMode::FailFast).await.unwrap();
+        assert!(matches!(
+            pool.begin_write().await,
+            Err(sqlx::Error::PoolTimedOut)
+        ));
+
+        drop(guard);
+        pool.begin_write().await.unwrap();
+
+        pool.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn recover_leftover_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("temp.db");
+
+        let pool = create(&path, &DbTuning::default()).await.unwrap();
+        assert_eq!(pool.recovery(), DbRecovery::default());
+
+        let mut tx = pool.begin_write().await.unwrap();
+        sqlx::query("CREATE TABLE test (value INTEGER)")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO test (value) VALUES (1)")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // Simulate a crash by copying the files while the database is still open.
+        let crashed_path = temp_dir.path().join("crashed.db");
+        fs::copy(&path, &crashed_path).await.unwrap();
+        fs::copy(
+            temp_dir.path().join("temp.db-wal"),
+            temp_dir.path().join("crashed.db-wal"),
+        )
+        .await
+        .unwrap();
+        pool.close().await.unwrap();
+
+        let pool = open(&crashed_path, &DbTuning::default()).await.unwrap();
+        assert!(pool.recovery().wal_checkpointed);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let count: u32 = sqlx::query("SELECT COUNT(*) FROM test")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 1);
+        drop(conn);
+        pool.close().await.unwrap();
+
+        // Closed cleanly, nothing to recover.
+        let pool = open(&crashed_path, &DbTuning::default()).await.unwrap();
+        assert_eq!(pool.recovery(), DbRecovery::default());
+        pool.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove_stale_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("temp.db");
+
+        // Leftovers of a deleted database.
+        fs::write(temp_dir.path().join("temp.db-wal"), b"stale")
+            .await
+            .unwrap();
+        fs::write(temp_dir.path().join("temp.db-shm"), b"stale")
+            .await
+            .unwrap();
+
+        let pool = create(&path, &DbTuning::default()).await.unwrap();
+        assert!(pool.recovery().orphans_removed);
+        pool.close().await.unwrap();
+
+        fs::write(temp_dir.path().join("temp.db-shm"), b"stale")
+            .await
+            .unwrap();
+
+        let pool = open(&path, &DbTuning::default()).await.unwrap();
+        assert!(pool.recovery().stale_shm_removed);
+        assert!(!pool.recovery().wal_checkpointed);
+        pool.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn tuning() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("temp.db");
+        let tuning = DbTuning::server();
+
+        let pool = create(&path, &tuning).await.unwrap();
+        let mut tx = pool.begin_write().await.unwrap();
+
+        assert_eq!(get_pragma(&mut tx, "page_size").await.unwrap(), 8192);
+        assert_eq!(
+            get_pragma(&mut tx, "wal_autocheckpoint").await.unwrap(),
+            10000
+        );
+        // FULL
+        assert_eq!(get_pragma(&mut tx, "synchronous").await.unwrap(), 2);
+
+        drop(tx);
+        pool.close().await.unwrap();
+
+        // Page size can't be changed once the database is created.
+        let pool = open(&path, &DbTuning::mobile()).await.unwrap();
+        let mut tx = pool.begin_write().await.unwrap();
+
+        assert_eq!(get_pragma(&mut tx, "page_size").await.unwrap(), 8192);
+        // NORMAL
+        assert_eq!(get_pragma(&mut tx, "synchronous").await.unwrap(), 1);
+    }
 }
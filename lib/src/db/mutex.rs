@@ -29,6 +29,12 @@ impl ConnectionMutex {
         MutexTransaction::begin(conn).await
     }
 
+    /// Locks the connection without beginning a transaction, waiting for the current one (if any)
+    /// to finish.
+    pub async fn lock(&self) -> OwnedMutexGuard<Option<SqliteConnection>> {
+        self.0.clone().lock_owned().await
+    }
+
     /// Waits for the connection to be released (if checked out) and then closes it. Any subsequent
     /// attempts to check the connection out return an error.
     pub async fn close(&self) {
@@ -0,0 +1,115 @@
+//! Recovery of the auxiliary database files (`-wal` and `-shm`) left behind by a process which
+//! didn't close the database cleanly (e.g., it crashed or was killed). A clean close checkpoints
+//! the write-ahead log and removes both files.
+
+use super::Error;
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    ConnectOptions, Connection, Row,
+};
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+/// What was done to recover the database when it was opened or created. All `false` means the
+/// database had been closed cleanly.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DbRecovery {
+    /// Leftover `-wal` / `-shm` files of a previously deleted database were removed before creating
+    /// a new one in its place. SQLite would otherwise replay the stale log into the new database.
+    pub orphans_removed: bool,
+    /// Stale `-shm` file (the WAL index, which also holds the locks) without any write-ahead log
+    /// was removed.
+    pub stale_shm_removed: bool,
+    /// Write-ahead log left over by a process which didn't close the database was checkpointed
+    /// into the database.
+    pub wal_checkpointed: bool,
+}
+
+/// Prepares creating a new database at `path`, which must not exist yet.
+pub(super) async fn before_create(path: &Path) -> Result<DbRecovery, Error> {
+    let mut recovery = DbRecovery::default();
+
+    for suffix in ["-wal", "-shm"] {
+        if remove(&aux_path(path, suffix)).await? {
+            tracing::warn!(?path, suffix, "Removed orphaned database file");
+            recovery.orphans_removed = true;
+        }
+    }
+
+    Ok(recovery)
+}
+
+/// Recovers the existing database at `path` before opening it.
+pub(super) async fn before_open(path: &Path) -> Result<DbRecovery, Error> {
+    let mut recovery = DbRecovery::default();
+
+    // Don't touch anything if the database itself is missing. Opening fails in that case anyway.
+    if fs::metadata(path).await.is_err() {
+        return Ok(recovery);
+    }
+
+    let wal_len = match fs::metadata(aux_path(path, "-wal")).await {
+        Ok(metadata) => Some(metadata.len()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(Error::Open(error.into())),
+    };
+
+    match wal_len {
+        // The WAL exists for as long as any connection to the database is open so a `-shm`
+        // without it can't be in use.
+        None => {
+            if remove(&aux_path(path, "-shm")).await? {
+                tracing::warn!(?path, "Removed stale database WAL index");
+                recovery.stale_shm_removed = true;
+            }
+        }
+        Some(0) => (),
+        Some(_) => {
+            if checkpoint(path).await.map_err(Error::Open)? {
+                tracing::warn!(?path, "Checkpointed leftover database WAL");
+                recovery.wal_checkpointed = true;
+            }
+        }
+    }
+
+    Ok(recovery)
+}
+
+// Replays the write-ahead log into the database and truncates it. Returns `false` if the
+// checkpoint couldn't complete because another connection is using the database.
+async fn checkpoint(path: &Path) -> Result<bool, sqlx::Error> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .journal_mode(SqliteJournalMode::Wal)
+        .connect()
+        .await?;
+
+    let busy: i64 = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .fetch_one(&mut conn)
+        .await?
+        .get(0);
+
+    conn.close().await?;
+
+    Ok(busy == 0)
+}
+
+fn aux_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path.as_os_str());
+    path.push(suffix);
+    path.into()
+}
+
+// Returns whether the file existed.
+async fn remove(path: &Path) -> Result<bool, Error> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(Error::Open(error.into())),
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteSynchronous};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// Tuning of the database of a repository. Use one of the presets (`mobile`, `desktop`,
+/// `server`) unless there is a good reason not to. `None` keeps the SQLite default.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DbTuning {
+    /// Page size in bytes. Takes effect only when the database is created.
+    pub page_size: Option<u32>,
+    /// Number of pages in the write-ahead log after which it's checkpointed.
+    pub wal_autocheckpoint: Option<u32>,
+    /// Maximum number of bytes of the database file to access using memory-mapped I/O.
+    pub mmap_size: Option<u64>,
+    pub synchronous: DbSynchronous,
+}
+
+impl DbTuning {
+    /// Small memory footprint. These are the SQLite defaults.
+    pub fn mobile() -> Self {
+        Self {
+            page_size: None,
+            wal_autocheckpoint: None,
+            mmap_size: None,
+            synchronous: DbSynchronous::Normal,
+        }
+    }
+
+    pub fn desktop() -> Self {
+        Self {
+            page_size: Some(4096),
+            wal_autocheckpoint: Some(2000),
+            mmap_size: Some(64 * 1024 * 1024),
+            synchronous: DbSynchronous::Normal,
+        }
+    }
+
+    /// Large repositories under sustained load, durable even on power loss.
+    pub fn server() -> Self {
+        Self {
+            page_size: Some(8192),
+            wal_autocheckpoint: Some(10000),
+            mmap_size: Some(256 * 1024 * 1024),
+            synchronous: DbSynchronous::Full,
+        }
+    }
+
+    pub(super) fn apply(&self, mut options: SqliteConnectOptions) -> SqliteConnectOptions {
+        if let Some(page_size) = self.page_size {
+            options = options.page_size(page_size);
+        }
+
+        if let Some(pages) = self.wal_autocheckpoint {
+            options = options.pragma("wal_autocheckpoint", pages.to_string());
+        }
+
+        if let Some(size) = self.mmap_size {
+            options = options.pragma("mmap_size", size.to_string());
+        }
+
+        options.synchronous(self.synchronous.into())
+    }
+}
+
+impl Default for DbTuning {
+    fn default() -> Self {
+        Self::mobile()
+    }
+}
+
+impl FromStr for DbTuning {
+    type Err = UnknownDbTuningPreset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mobile" => Ok(Self::mobile()),
+            "desktop" => Ok(Self::desktop()),
+            "server" => Ok(Self::server()),
+            _ => Err(UnknownDbTuningPreset),
+        }
+    }
+}
+
+impl fmt::Display for DbTuning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<T: fmt::Display>(value: Option<T>) -> String {
+            value.map_or_else(|| "default".to_owned(), |value| value.to_string())
+        }
+
+        write!(
+            f,
+            "page_size={} wal_autocheckpoint={} mmap_size={} synchronous={}",
+            opt(self.page_size),
+            opt(self.wal_autocheckpoint),
+            opt(self.mmap_size),
+            self.synchronous,
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown db tuning preset (expected one of: mobile, desktop, server)")]
+pub struct UnknownDbTuningPreset;
+
+/// How often the database waits for the data to be safely written to disk. `Normal` never
+/// corrupts the database, but the last commits might be lost on power loss (not on app crash).
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbSynchronous {
+    Normal,
+    Full,
+    Extra,
+}
+
+impl From<DbSynchronous> for SqliteSynchronous {
+    fn from(value: DbSynchronous) -> Self {
+        match value {
+            DbSynchronous::Normal => Self::Normal,
+            DbSynchronous::Full => Self::Full,
+            DbSynchronous::Extra => Self::Extra,
+        }
+    }
+}
+
+impl fmt::Display for DbSynchronous {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => write!(f, "normal"),
+            Self::Full => write!(f, "full"),
+            Self::Extra => write!(f, "extra"),
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use hex::FromHexError;
+use ouisync_protocol::{
+    define_byte_array_wrapper, derive_rand_for_wrapper, derive_sqlx_traits_for_byte_array_wrapper,
+};
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 use std::str::FromStr;
 
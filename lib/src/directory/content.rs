@@ -1,10 +1,11 @@
 //! Directory content
 
-use super::entry_data::EntryData;
+use super::entry_data::{EntryData, EntryTimes};
 use crate::{
     blob::BlobId,
     error::{Error, Result},
     protocol::Bump,
+    time,
     version_vector::VersionVector,
 };
 use serde::Deserialize;
@@ -14,13 +15,30 @@ use std::{
         btree_map::{self, Entry},
         BTreeMap,
     },
+    io::Read,
+    ops::Bound,
 };
 
 /// Version of the Directory serialization format.
 pub const VERSION: u64 = 2;
 
+// Same as `VERSION` but with the serialized entries compressed using zstd. Used only when enabled
+// for the repository (older replicas can't read it) and only for the directories whose serialized
+// entries are larger than `COMPRESSION_THRESHOLD`.
+const COMPRESSED_VERSION: u64 = 3;
+
+// Directories smaller than this are not compressed because they fit into a single block anyway.
+const COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+// zstd compression level. Low level is used because directories are re-serialized on every
+// modification.
+const COMPRESSION_LEVEL: i32 = 3;
+
+// Upper bound on the size of decompressed directory, to protect against decompression bombs.
+const MAX_DECOMPRESSED_SIZE: u64 = 256 * 1024 * 1024;
+
 #[derive(Clone, Debug)]
-pub(super) struct Content {
+pub(crate) struct Content {
     entries: v2::Entries,
 }
 
@@ -34,7 +52,8 @@ impl Content {
     pub fn deserialize(mut input: &[u8]) -> Result<Self> {
         let version = vint64::decode(&mut input).map_err(|_| Error::MalformedDirectory)?;
         let entries = match version {
-            VERSION => deserialize_entries(input),
+            COMPRESSED_VERSION => deserialize_entries_with_times(&decompress(input)?),
+            VERSION => deserialize_entries_with_times(input),
             1 => Ok(v2::from_v1(deserialize_entries(input)?)),
             0 => Ok(v2::from_v1(v1::from_v0(deserialize_entries(input)?))),
             _ => Err(Error::StorageVersionMismatch),
@@ -43,18 +62,51 @@ impl Content {
         Ok(Self { entries: entries? })
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut output = Vec::new();
-        output.extend_from_slice(vint64::encode(VERSION).as_ref());
-        bincode::serialize_into(&mut output, &self.entries)
-            .expect("failed to serialize directory content");
-        output
+    /// Serializes the content. If `compress` is true, large content is compressed.
+    ///
+    /// The timestamps of the entries (if any are known) are appended after the entries. This keeps
+    /// the format readable by the replicas that don't know about them because they ignore the
+    /// trailing bytes.
+    pub fn serialize(&self, compress: bool) -> Vec<u8> {
+        let mut entries =
+            bincode::serialize(&self.entries).expect("failed to serialize directory content");
+
+        if self.entries.values().any(|data| {
+            data.times()
+                .is_some_and(|times| *times != EntryTimes::default())
+        }) {
+            let times: Vec<_> = self
+                .entries
+                .values()
+                .map(|data| data.times().copied().unwrap_or_default())
+                .collect();
+
+            bincode::serialize_into(&mut entries, &times)
+                .expect("failed to serialize directory content");
+        }
+
+        if compress && entries.len() > COMPRESSION_THRESHOLD {
+            let compressed = zstd::bulk::compress(&entries, COMPRESSION_LEVEL)
+                .expect("failed to compress directory content");
+
+            if compressed.len() < entries.len() {
+                return with_version(COMPRESSED_VERSION, &compressed);
+            }
+        }
+
+        with_version(VERSION, &entries)
     }
 
     pub fn iter(&self) -> btree_map::Iter<String, EntryData> {
         self.entries.iter()
     }
 
+    /// Iterates the entries whose names are greater than or equal to `name`.
+    pub fn iter_from(&self, name: &str) -> btree_map::Range<String, EntryData> {
+        self.entries
+            .range::<str, _>((Bound::Included(name), Bound::Unbounded))
+    }
+
     pub fn get_key_value(&self, name: &str) -> Option<(&String, &EntryData)> {
         self.entries.get_key_value(name)
     }
@@ -146,10 +198,58 @@ impl<'a> IntoIterator for &'a Content {
     }
 }
 
+fn with_version(version: u64, entries: &[u8]) -> Vec<u8> {
+    let version = vint64::encode(version);
+    let mut output = Vec::with_capacity(version.len() + entries.len());
+    output.extend_from_slice(version.as_ref());
+    output.extend_from_slice(entries);
+    output
+}
+
+fn decompress(input: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+
+    zstd::stream::read::Decoder::new(input)
+        .map_err(|_| Error::MalformedDirectory)?
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut output)
+        .map_err(|_| Error::MalformedDirectory)?;
+
+    if output.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(Error::MalformedDirectory);
+    }
+
+    Ok(output)
+}
+
 fn deserialize_entries<'a, T: Deserialize<'a>>(input: &'a [u8]) -> Result<T, Error> {
     bincode::deserialize(input).map_err(|_| Error::MalformedDirectory)
 }
 
+fn deserialize_entries_with_times(mut input: &[u8]) -> Result<v2::Entries> {
+    let mut entries: v2::Entries =
+        bincode::deserialize_from(&mut input).map_err(|_| Error::MalformedDirectory)?;
+
+    if input.is_empty() {
+        return Ok(entries);
+    }
+
+    let times: Vec<EntryTimes> = deserialize_entries(input)?;
+
+    if times.len() != entries.len() {
+        return Err(Error::MalformedDirectory);
+    }
+
+    for (data, times) in entries.values_mut().zip(times) {
+        if let Some(old) = data.times_mut() {
+            time::observe(times.modified);
+            *old = times;
+        }
+    }
+
+    Ok(entries)
+}
+
 fn check_replace(old: &EntryData, new: &EntryData) -> Result<Option<BlobId>, EntryExists> {
     // Replace entries only if the new version is more up to date than the old version.
 
@@ -263,3 +363,98 @@ mod v0 {
         pub version_vector: VersionVector,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign::PublicKey;
+
+    #[test]
+    fn serialize_small_uncompressed() {
+        let content = content_with_entries(10);
+        let serialized = content.serialize(true);
+
+        assert_eq!(vint64::decode(&mut &serialized[..]).unwrap(), VERSION,);
+
+        let deserialized = Content::deserialize(&serialized).unwrap();
+        assert!(deserialized.iter().eq(content.iter()));
+    }
+
+    #[test]
+    fn serialize_large_compressed() {
+        let content = content_with_entries(1000);
+        let serialized = content.serialize(true);
+
+        assert_eq!(
+            vint64::decode(&mut &serialized[..]).unwrap(),
+            COMPRESSED_VERSION,
+        );
+        assert!(serialized.len() < bincode::serialize(&content.entries).unwrap().len());
+
+        let deserialized = Content::deserialize(&serialized).unwrap();
+        assert!(deserialized.iter().eq(content.iter()));
+    }
+
+    #[test]
+    fn serialize_large_compression_disabled() {
+        let content = content_with_entries(1000);
+        let serialized = content.serialize(false);
+
+        assert_eq!(vint64::decode(&mut &serialized[..]).unwrap(), VERSION);
+
+        let deserialized = Content::deserialize(&serialized).unwrap();
+        assert!(deserialized.iter().eq(content.iter()));
+    }
+
+    #[test]
+    fn serialize_times() {
+        let mut content = content_with_entries(2);
+
+        // Without any known timestamps the format is the same as before they were introduced.
+        let serialized = content.serialize(true);
+        let mut input = &serialized[..];
+        vint64::decode(&mut input).unwrap();
+        assert_eq!(input, bincode::serialize(&content.entries).unwrap());
+
+        let times = EntryTimes::now();
+        *content
+            .get_mut("file-00001.txt")
+            .unwrap()
+            .times_mut()
+            .unwrap() = times;
+
+        let deserialized = Content::deserialize(&content.serialize(true)).unwrap();
+        assert!(deserialized.iter().eq(content.iter()));
+        assert_eq!(
+            deserialized
+                .get_key_value("file-00001.txt")
+                .unwrap()
+                .1
+                .times(),
+            Some(&times)
+        );
+
+        // The entries can still be read without the timestamps.
+        let serialized = content.serialize(true);
+        let mut input = &serialized[1..];
+        let entries: v2::Entries = bincode::deserialize_from(&mut input).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!input.is_empty());
+    }
+
+    fn content_with_entries(count: usize) -> Content {
+        let mut content = Content::empty();
+        let writer_id = PublicKey::random();
+
+        for index in 0..count {
+            content
+                .insert(
+                    format!("file-{index:05}.txt"),
+                    EntryData::file(rand::random(), VersionVector::first(writer_id)),
+                )
+                .unwrap();
+        }
+
+        content
+    }
+}
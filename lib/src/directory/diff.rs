@@ -0,0 +1,119 @@
+use super::{Directory, DirectoryFallback, DirectoryLocking, EntryRef, EntryType};
+use crate::{
+    branch::Branch,
+    error::{Error, Result},
+    store,
+};
+use camino::{Utf8Component, Utf8Path};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Difference of a single entry between two versions of a directory.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EntryDiff {
+    pub name: String,
+    pub kind: DiffKind,
+    /// Type of the entry in the new version, or in the old one if removed.
+    pub entry_type: EntryType,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    /// The entry exists only in the new version.
+    Added,
+    /// The entry exists only in the old version.
+    Removed,
+    /// The entry exists in both versions but its content (or type) differs.
+    Modified,
+}
+
+/// Opens the directory at `path` in the given branch. Returns `None` if the branch or the directory
+/// doesn't exist (or has been removed).
+pub(crate) async fn open_in_branch(branch: &Branch, path: &Utf8Path) -> Result<Option<Directory>> {
+    let mut dir = match branch
+        .open_root(DirectoryLocking::Disabled, DirectoryFallback::Disabled)
+        .await
+    {
+        Ok(dir) => dir,
+        Err(Error::Store(store::Error::BranchNotFound)) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    for component in path.components() {
+        match component {
+            Utf8Component::RootDir | Utf8Component::CurDir => (),
+            Utf8Component::Normal(name) => {
+                dir = match dir.lookup(name) {
+                    Ok(EntryRef::Directory(entry)) => {
+                        entry.open(DirectoryFallback::Disabled).await?
+                    }
+                    Ok(EntryRef::File(_)) => return Err(Error::EntryIsFile),
+                    Ok(EntryRef::Tombstone(_)) | Err(Error::EntryNotFound) => return Ok(None),
+                    Err(error) => return Err(error),
+                };
+            }
+            Utf8Component::ParentDir | Utf8Component::Prefix(_) => {
+                return Err(Error::OperationNotSupported)
+            }
+        }
+    }
+
+    Ok(Some(dir))
+}
+
+/// Compares the entries of the two versions of a directory (`None` means the directory doesn't
+/// exist in that version). Only the directory metadata is compared (entry types and version
+/// vectors), the content of the entries is not read. The result is sorted by name.
+pub(crate) fn diff(old: Option<&Directory>, new: Option<&Directory>) -> Vec<EntryDiff> {
+    let old = live_entries(old);
+    let mut new = live_entries(new);
+    let mut diffs = Vec::new();
+
+    for (name, old_entry) in old {
+        let (kind, entry_type) = match new.remove(name) {
+            Some(new_entry) => {
+                if entry_type(&new_entry) == entry_type(&old_entry)
+                    && new_entry.version_vector() == old_entry.version_vector()
+                {
+                    continue;
+                }
+
+                (DiffKind::Modified, entry_type(&new_entry))
+            }
+            None => (DiffKind::Removed, entry_type(&old_entry)),
+        };
+
+        diffs.push(EntryDiff {
+            name: name.to_owned(),
+            kind,
+            entry_type,
+        });
+    }
+
+    diffs.extend(new.into_iter().map(|(name, entry)| EntryDiff {
+        name: name.to_owned(),
+        kind: DiffKind::Added,
+        entry_type: entry_type(&entry),
+    }));
+
+    diffs.sort_by(|a, b| a.name.cmp(&b.name));
+    diffs
+}
+
+// Entries of the directory excluding tombstones.
+fn live_entries(dir: Option<&Directory>) -> BTreeMap<&str, EntryRef> {
+    dir.into_iter()
+        .flat_map(|dir| dir.entries())
+        .filter(|entry| !entry.is_tombstone())
+        .map(|entry| (entry.name(), entry))
+        .collect()
+}
+
+fn entry_type(entry: &EntryRef) -> EntryType {
+    if entry.is_directory() {
+        EntryType::Directory
+    } else {
+        EntryType::File
+    }
+}
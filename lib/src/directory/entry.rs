@@ -1,6 +1,6 @@
 use super::{
     content::Content,
-    entry_data::{EntryData, EntryDirectoryData, EntryFileData, EntryTombstoneData},
+    entry_data::{EntryData, EntryDirectoryData, EntryFileData, EntryTimes, EntryTombstoneData},
     parent_context::ParentContext,
     Directory, DirectoryFallback, DirectoryLocking,
 };
@@ -15,7 +15,7 @@ use crate::{
     version_vector::VersionVector,
     versioned::{BranchItem, Versioned},
 };
-use std::fmt;
+use std::{fmt, time::SystemTime};
 
 /// Info about a directory entry.
 #[derive(Copy, Clone, Debug)]
@@ -44,6 +44,36 @@ impl<'a> EntryRef<'a> {
         }
     }
 
+    /// Stable id of this entry, the first 128 bits of its blob id. Unique within the repository
+    /// and preserved when the entry is renamed or moved. `None` for tombstones.
+    pub fn id(&self) -> Option<u128> {
+        match self {
+            Self::File(r) => Some(r.id()),
+            Self::Directory(r) => Some(r.id()),
+            Self::Tombstone(_) => None,
+        }
+    }
+
+    /// Time this entry was created at. `None` for tombstones. `UNIX_EPOCH` if not known (the entry
+    /// was created by a version that didn't record the timestamps).
+    pub fn created(&self) -> Option<SystemTime> {
+        self.times().map(EntryTimes::created_time)
+    }
+
+    /// Time this entry was last modified at (for a directory, when its entries were last added,
+    /// removed or renamed). `None` for tombstones. `UNIX_EPOCH` if not known.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.times().map(EntryTimes::modified_time)
+    }
+
+    pub(crate) fn times(&self) -> Option<&'a EntryTimes> {
+        match self {
+            Self::File(f) => Some(&f.entry_data.times),
+            Self::Directory(d) => Some(&d.entry_data.times),
+            Self::Tombstone(_) => None,
+        }
+    }
+
     pub fn version_vector(&self) -> &'a VersionVector {
         match self {
             Self::File(f) => f.version_vector(),
@@ -136,10 +166,25 @@ impl<'a> FileRef<'a> {
         &self.entry_data.blob_id
     }
 
+    /// See `EntryRef::id`.
+    pub fn id(&self) -> u128 {
+        self.blob_id().entry_id()
+    }
+
     pub fn version_vector(&self) -> &'a VersionVector {
         &self.entry_data.version_vector
     }
 
+    /// See `EntryRef::created`.
+    pub fn created(&self) -> SystemTime {
+        self.entry_data.times.created_time()
+    }
+
+    /// See `EntryRef::modified`.
+    pub fn modified(&self) -> SystemTime {
+        self.entry_data.times.modified_time()
+    }
+
     pub async fn open(&self) -> Result<File> {
         let parent_context = self.inner.parent_context();
         let branch = self.branch().clone();
@@ -205,6 +250,11 @@ impl<'a> DirectoryRef<'a> {
         &self.entry_data.blob_id
     }
 
+    /// See `EntryRef::id`.
+    pub fn id(&self) -> u128 {
+        self.blob_id().entry_id()
+    }
+
     pub(crate) async fn open(&self, fallback: DirectoryFallback) -> Result<Directory> {
         Directory::open(
             self.branch().clone(),
@@ -220,6 +270,29 @@ impl<'a> DirectoryRef<'a> {
         .await
     }
 
+    /// Like `open` but within an existing transaction.
+    pub(super) async fn open_in(
+        &self,
+        tx: &mut ReadTransaction,
+        fallback: DirectoryFallback,
+    ) -> Result<Directory> {
+        let lock = if self.inner.parent.lock.is_some() {
+            Some(self.branch().locker().read(*self.blob_id()).await)
+        } else {
+            None
+        };
+
+        Directory::open_in(
+            lock,
+            tx,
+            self.branch().clone(),
+            *self.blob_id(),
+            Some(self.inner.parent_context()),
+            fallback,
+        )
+        .await
+    }
+
     pub(super) async fn open_snapshot(
         &self,
         tx: &mut ReadTransaction,
@@ -239,6 +312,16 @@ impl<'a> DirectoryRef<'a> {
     pub fn version_vector(&self) -> &'a VersionVector {
         &self.entry_data.version_vector
     }
+
+    /// See `EntryRef::created`.
+    pub fn created(&self) -> SystemTime {
+        self.entry_data.times.created_time()
+    }
+
+    /// See `EntryRef::modified`.
+    pub fn modified(&self) -> SystemTime {
+        self.entry_data.times.modified_time()
+    }
 }
 
 impl fmt::Debug for DirectoryRef<'_> {
@@ -1,5 +1,6 @@
-use crate::{blob::BlobId, version_vector::VersionVector};
+use crate::{blob::BlobId, time::Timestamp, version_vector::VersionVector};
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 
 //--------------------------------------------------------------------
 
@@ -15,6 +16,7 @@ impl EntryData {
         Self::File(EntryFileData {
             blob_id,
             version_vector,
+            times: EntryTimes::default(),
         })
     }
 
@@ -22,9 +24,18 @@ impl EntryData {
         Self::Directory(EntryDirectoryData {
             blob_id,
             version_vector,
+            times: EntryTimes::default(),
         })
     }
 
+    pub fn with_times(mut self, times: EntryTimes) -> Self {
+        if let Some(old) = self.times_mut() {
+            *old = times;
+        }
+
+        self
+    }
+
     pub fn version_vector(&self) -> &VersionVector {
         match self {
             Self::File(f) => &f.version_vector,
@@ -48,6 +59,22 @@ impl EntryData {
             Self::Tombstone(_) => None,
         }
     }
+
+    pub fn times(&self) -> Option<&EntryTimes> {
+        match self {
+            Self::File(f) => Some(&f.times),
+            Self::Directory(d) => Some(&d.times),
+            Self::Tombstone(_) => None,
+        }
+    }
+
+    pub fn times_mut(&mut self) -> Option<&mut EntryTimes> {
+        match self {
+            Self::File(f) => Some(&mut f.times),
+            Self::Directory(d) => Some(&mut d.times),
+            Self::Tombstone(_) => None,
+        }
+    }
 }
 
 //--------------------------------------------------------------------
@@ -56,6 +83,9 @@ impl EntryData {
 pub(crate) struct EntryFileData {
     pub blob_id: BlobId,
     pub version_vector: VersionVector,
+    // Stored separately (see `Content::serialize`).
+    #[serde(skip)]
+    pub times: EntryTimes,
 }
 
 impl Clone for EntryFileData {
@@ -63,6 +93,7 @@ impl Clone for EntryFileData {
         Self {
             blob_id: self.blob_id,
             version_vector: self.version_vector.clone(),
+            times: self.times,
         }
     }
 }
@@ -79,6 +110,50 @@ impl Eq for EntryFileData {}
 pub(crate) struct EntryDirectoryData {
     pub blob_id: BlobId,
     pub version_vector: VersionVector,
+    // Stored separately (see `Content::serialize`).
+    #[serde(skip)]
+    pub times: EntryTimes,
+}
+
+/// Creation and modification timestamps of an entry, taken from the hybrid logical clock (see
+/// `time`). Zero if not known (e.g., the entry was created before the timestamps were recorded).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub(crate) struct EntryTimes {
+    pub created: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl EntryTimes {
+    pub fn now() -> Self {
+        let now = Timestamp::now();
+
+        Self {
+            created: now,
+            modified: now,
+        }
+    }
+
+    /// Merges the timestamps of two versions of the same entry: the earliest known creation and
+    /// the latest modification. Commutative and idempotent so all the replicas end up with the same
+    /// timestamps regardless of the order of the merges.
+    pub fn merge(&mut self, other: &Self) {
+        self.created = if self.created.is_zero() {
+            other.created
+        } else if other.created.is_zero() {
+            self.created
+        } else {
+            self.created.min(other.created)
+        };
+        self.modified = self.modified.max(other.modified);
+    }
+
+    pub fn created_time(&self) -> SystemTime {
+        self.created.to_system_time()
+    }
+
+    pub fn modified_time(&self) -> SystemTime {
+        self.modified.to_system_time()
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
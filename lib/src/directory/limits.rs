@@ -0,0 +1,53 @@
+use crate::{
+    error::{Error, Result},
+    protocol::{DEFAULT_MAX_DIRECTORY_ENTRIES, DEFAULT_MAX_NAME_LENGTH, DEFAULT_MAX_PATH_DEPTH},
+};
+use serde::{Deserialize, Serialize};
+
+/// Limits on the shape of the directory tree which are checked when creating new files and
+/// directories and when moving entries. Entries received from other replicas are never rejected
+/// because of them.
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct DirectoryLimits {
+    /// Maximum number of entries (not counting tombstones) in a single directory.
+    pub max_entries: u32,
+    /// Maximum depth of the directory tree (the entries of the root directory have depth 1).
+    pub max_depth: u32,
+    /// Maximum length of an entry name in bytes.
+    pub max_name_length: u32,
+}
+
+impl DirectoryLimits {
+    /// Checks whether a new entry can be created.
+    ///
+    /// - `name` is the name of the entry,
+    /// - `depth` is the depth the entry would have,
+    /// - `entries` is the number of entries (not counting tombstones) in the parent directory or
+    ///   `None` if the new entry replaces an existing one (and so the number of entries doesn't
+    ///   change).
+    pub(super) fn check(&self, name: &str, depth: u32, entries: Option<usize>) -> Result<()> {
+        if name.len() > self.max_name_length as usize {
+            return Err(Error::NameTooLong);
+        }
+
+        if depth > self.max_depth {
+            return Err(Error::PathTooDeep);
+        }
+
+        if entries.is_some_and(|entries| entries >= self.max_entries as usize) {
+            return Err(Error::TooManyEntries);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for DirectoryLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_DIRECTORY_ENTRIES,
+            max_depth: DEFAULT_MAX_PATH_DEPTH,
+            max_name_length: DEFAULT_MAX_NAME_LENGTH,
+        }
+    }
+}
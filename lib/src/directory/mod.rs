@@ -1,22 +1,27 @@
 mod content;
+mod diff;
 mod entry;
 mod entry_data;
 mod entry_type;
+mod limits;
 mod parent_context;
 #[cfg(test)]
 mod tests;
 
+pub(crate) use self::{
+    content::Content,
+    diff::{diff, open_in_branch},
+    entry_data::{EntryData, EntryTimes, EntryTombstoneData, TombstoneCause},
+    parent_context::ParentContext,
+};
 pub use self::{
     content::VERSION as DIRECTORY_VERSION,
+    diff::{DiffKind, EntryDiff},
     entry::{DirectoryRef, EntryRef, FileRef},
     entry_type::EntryType,
-};
-pub(crate) use self::{
-    entry_data::{EntryData, EntryTombstoneData, TombstoneCause},
-    parent_context::ParentContext,
+    limits::DirectoryLimits,
 };
 
-use self::content::Content;
 use crate::{
     blob::{lock::ReadLock, Blob, BlobId},
     branch::Branch,
@@ -29,6 +34,7 @@ use crate::{
     version_vector::VersionVector,
 };
 use async_recursion::async_recursion;
+use rand::Rng;
 use std::{cmp::Ordering, fmt, mem};
 use tracing::instrument;
 
@@ -124,19 +130,29 @@ impl Directory {
             .map(move |(name, data)| EntryRef::new(self, name, data))
     }
 
+    /// Returns iterator over the entries of this directory starting at the given name (inclusive).
+    pub(crate) fn entries_from<'a>(&'a self, name: &str) -> impl Iterator<Item = EntryRef<'a>> {
+        self.content
+            .iter_from(name)
+            .map(move |(name, data)| EntryRef::new(self, name, data))
+    }
+
     /// Creates a new file inside this directory.
     pub async fn create_file(&mut self, name: String) -> Result<File> {
         let mut tx = self.branch().store().begin_write().await?;
         let mut changeset = Changeset::new();
 
+        self.branch().check_write_fence(&mut tx).await?;
+        self.branch().check_disk_space()?;
         self.refresh_in(&mut tx).await?;
+        self.check_limits(&name, None)?;
 
-        let blob_id = rand::random();
+        let blob_id = self.branch().rng().gen();
         let version_vector = self
             .content
             .initial_version_vector(&name)
             .incremented(*self.branch().id());
-        let data = EntryData::file(blob_id, version_vector);
+        let data = EntryData::file(blob_id, version_vector).with_times(EntryTimes::now());
         let parent = self.create_parent_context(name.clone());
 
         let mut file = File::create(self.branch().clone(), Locator::head(blob_id), parent);
@@ -146,7 +162,8 @@ impl Directory {
 
         file.save(&mut tx, &mut changeset).await?;
         self.save(&mut tx, &mut changeset, &content).await?;
-        self.bump(&mut tx, &mut changeset, Bump::Add(diff)).await?;
+        self.bump_modified(&mut tx, &mut changeset, Bump::Add(diff))
+            .await?;
         self.commit(tx, changeset).await?;
         self.finalize(content);
 
@@ -182,10 +199,24 @@ impl Directory {
         let mut tx = self.branch().store().begin_write().await?;
         let mut changeset = Changeset::new();
 
+        // Non-empty `merge` means this is part of a merge which must be allowed even when fenced.
+        if merge.is_empty() {
+            self.branch().check_write_fence(&mut tx).await?;
+            self.branch().check_disk_space()?;
+        }
+
         self.refresh_in(&mut tx).await?;
+        self.check_limits(&name, None)?;
+
+        // A directory created by a merge has no local creation time.
+        let times = if merge.is_empty() {
+            EntryTimes::now()
+        } else {
+            EntryTimes::default()
+        };
 
         let (dir, content) = self
-            .create_directory_in(lock, &mut tx, &mut changeset, name, blob_id, merge)
+            .create_directory_in(lock, &mut tx, &mut changeset, name, blob_id, merge, times)
             .await?;
 
         self.commit(tx, changeset).await?;
@@ -194,6 +225,93 @@ impl Directory {
         Ok(dir)
     }
 
+    /// Like `create_file` but within an existing transaction. The caller is responsible for
+    /// committing it and for notifying the branch.
+    pub(crate) async fn create_file_in(
+        &mut self,
+        tx: &mut WriteTransaction,
+        name: String,
+    ) -> Result<File> {
+        let mut changeset = Changeset::new();
+
+        self.branch().check_write_fence(tx).await?;
+        self.branch().check_disk_space()?;
+        self.refresh_in(tx).await?;
+        self.check_limits(&name, None)?;
+
+        let blob_id = self.branch().rng().gen();
+        let version_vector = self
+            .content
+            .initial_version_vector(&name)
+            .incremented(*self.branch().id());
+        let data = EntryData::file(blob_id, version_vector).with_times(EntryTimes::now());
+        let parent = self.create_parent_context(name.clone());
+
+        let mut file = File::create(self.branch().clone(), Locator::head(blob_id), parent);
+        let mut content = self.content.clone();
+
+        let diff = content.insert(name, data)?;
+
+        file.save(tx, &mut changeset).await?;
+        self.save(tx, &mut changeset, &content).await?;
+        self.bump_modified(tx, &mut changeset, Bump::Add(diff))
+            .await?;
+        self.apply(tx, changeset).await?;
+        self.finalize(content);
+
+        Ok(file)
+    }
+
+    /// Opens the subdirectory with the given name within an existing transaction, creating it
+    /// first if it doesn't exist. The caller is responsible for committing the transaction and for
+    /// notifying the branch.
+    pub(crate) async fn open_or_create_directory_in(
+        &mut self,
+        tx: &mut WriteTransaction,
+        name: &str,
+    ) -> Result<Self> {
+        self.refresh_in(tx).await?;
+
+        match self.lookup(name) {
+            Ok(EntryRef::Directory(entry)) => {
+                return entry.open_in(tx, DirectoryFallback::Disabled).await
+            }
+            Ok(EntryRef::File(_)) => return Err(Error::EntryIsFile),
+            Ok(EntryRef::Tombstone(_)) | Err(Error::EntryNotFound) => (),
+            Err(error) => return Err(error),
+        }
+
+        let blob_id = self.branch().rng().gen();
+        let lock = self
+            .branch()
+            .locker()
+            .try_read(blob_id)
+            .map_err(|_| Error::EntryExists)?;
+        let mut changeset = Changeset::new();
+
+        self.branch().check_write_fence(tx).await?;
+        self.branch().check_disk_space()?;
+        self.check_limits(name, None)?;
+
+        let (dir, content) = self
+            .create_directory_in(
+                lock,
+                tx,
+                &mut changeset,
+                name.to_owned(),
+                blob_id,
+                &VersionVector::new(),
+                EntryTimes::now(),
+            )
+            .await?;
+
+        self.apply(tx, changeset).await?;
+        self.finalize(content);
+
+        Ok(dir)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn create_directory_in(
         &mut self,
         lock: ReadLock,
@@ -202,6 +320,7 @@ impl Directory {
         name: String,
         blob_id: BlobId,
         merge: &VersionVector,
+        times: EntryTimes,
     ) -> Result<(Self, Content)> {
         let mut version_vector = self.content.initial_version_vector(&name);
 
@@ -211,7 +330,7 @@ impl Directory {
             version_vector.merge(merge)
         }
 
-        let data = EntryData::directory(blob_id, version_vector);
+        let data = EntryData::directory(blob_id, version_vector).with_times(times);
         let parent = self.create_parent_context(name.clone());
 
         let mut dir = Directory::create(lock, self.branch().clone(), blob_id, Some(parent));
@@ -221,11 +340,43 @@ impl Directory {
 
         dir.save(tx, changeset, &Content::empty()).await?;
         self.save(tx, changeset, &content).await?;
-        self.bump(tx, changeset, Bump::Add(diff)).await?;
+
+        if merge.is_empty() {
+            self.bump_modified(tx, changeset, Bump::Add(diff)).await?;
+        } else {
+            self.bump(tx, changeset, Bump::Add(diff)).await?;
+        }
 
         Ok((dir, content))
     }
 
+    /// Checks whether a new entry with the given name can be created in this directory without
+    /// exceeding the `DirectoryLimits` of the branch. `moved_from` is the name of the entry of this
+    /// directory that is being renamed to `name` (if any) so it doesn't count as a new one.
+    fn check_limits(&self, name: &str, moved_from: Option<&str>) -> Result<()> {
+        let depth = self.parent.as_ref().map(ParentContext::depth).unwrap_or(0) + 1;
+        let is_live = |name: &str| {
+            self.content
+                .get_key_value(name)
+                .is_some_and(|(_, data)| !matches!(data, EntryData::Tombstone(_)))
+        };
+
+        // Tombstones don't count, otherwise a directory whose entries keep being replaced by new
+        // ones would eventually refuse any new entry.
+        let entries = if is_live(name) || moved_from.is_some_and(is_live) {
+            None
+        } else {
+            Some(
+                self.content
+                    .iter()
+                    .filter(|(_, data)| !matches!(data, EntryData::Tombstone(_)))
+                    .count(),
+            )
+        };
+
+        self.branch().directory_limits().check(name, depth, entries)
+    }
+
     fn create_parent_context(&self, entry_name: String) -> ParentContext {
         ParentContext::new(
             *self.blob_id(),
@@ -255,6 +406,8 @@ impl Directory {
         let mut tx = self.branch().store().begin_write().await?;
         let mut changeset = Changeset::new();
 
+        self.branch().check_write_fence(&mut tx).await?;
+
         // If we are removing a directory, ensure it's empty (recursive removal can still be
         // implemented at the upper layers).
         self.check_directory_empty(&mut tx, name).await?;
@@ -307,6 +460,7 @@ impl Directory {
                 &mut changeset,
                 name.to_owned(),
                 EntryData::Tombstone(tombstone),
+                false,
             )
             .await?;
 
@@ -350,9 +504,17 @@ impl Directory {
 
         let mut tx = self.branch().store().begin_write().await?;
 
+        self.branch().check_write_fence(&mut tx).await?;
+
+        dst_dir.refresh_in(&mut tx).await?;
+        dst_dir.check_limits(
+            dst_name,
+            (dst_dir.blob_id() == self.blob_id()).then_some(src_name),
+        )?;
+
         let mut changeset = Changeset::new();
         let dst_content = dst_dir
-            .begin_insert_entry(&mut tx, &mut changeset, dst_name.to_owned(), dst_data)
+            .begin_insert_entry(&mut tx, &mut changeset, dst_name.to_owned(), dst_data, true)
             .await?;
 
         // TODO: Handle the case when `self` == `dst_dir` separately (call `refresh` and `save`
@@ -405,12 +567,12 @@ impl Directory {
         // at the time it was initially created.
         let (parent, current_vv, initial_vv) = self.prepare_fork().await?;
 
-        if let Some((parent_dir, entry_name)) = parent {
+        if let Some((parent_dir, entry_name, times)) = parent {
             let mut parent_dir = parent_dir.fork(dst_branch).await?;
             let blob_id = *self.blob_id();
 
             parent_dir
-                .fork_into(entry_name, blob_id, current_vv, initial_vv)
+                .fork_into(entry_name, blob_id, current_vv, initial_vv, times)
                 .await
         } else {
             Self::open_or_create_root(dst_branch.clone(), initial_vv).await
@@ -419,10 +581,17 @@ impl Directory {
 
     /// Prepares information needed to fork this directory.
     ///
-    /// Returns the parent directory and entry name (unless root) and the current and initial
-    /// version vectors of this directory (initial version vector is the version vector this
-    /// directory had when it was initially created).
-    async fn prepare_fork(&self) -> Result<(Option<(Self, &str)>, VersionVector, VersionVector)> {
+    /// Returns the parent directory, entry name and timestamps (unless root) and the current and
+    /// initial version vectors of this directory (initial version vector is the version vector
+    /// this directory had when it was initially created).
+    #[allow(clippy::type_complexity)]
+    async fn prepare_fork(
+        &self,
+    ) -> Result<(
+        Option<(Self, &str, EntryTimes)>,
+        VersionVector,
+        VersionVector,
+    )> {
         // Running this in a read transaction to make sure the version vector of this directory
         // and the version vectors of its entries are in sync.
         let mut tx = self.branch().store().begin_read().await?;
@@ -430,9 +599,11 @@ impl Directory {
         let (parent, current_vv) = if let Some(parent) = &self.parent {
             let parent_dir = parent.open_in(&mut tx, self.branch().clone()).await?;
             let entry_name = parent.entry_name();
-            let current_vv = parent_dir.lookup(entry_name)?.version_vector().clone();
+            let entry = parent_dir.lookup(entry_name)?;
+            let current_vv = entry.version_vector().clone();
+            let times = entry.times().copied().unwrap_or_default();
 
-            (Some((parent_dir, entry_name)), current_vv)
+            (Some((parent_dir, entry_name, times)), current_vv)
         } else {
             let current_vv = tx
                 .load_root_node(self.branch().id(), RootNodeFilter::Any)
@@ -461,6 +632,7 @@ impl Directory {
         src_blob_id: BlobId,
         src_current_vv: VersionVector,
         src_initial_vv: VersionVector,
+        src_times: EntryTimes,
     ) -> Result<Self> {
         let new_lock = self.branch().locker().read(src_blob_id).await;
         let (mut tx, old_lock, old_vv) = self.begin_fork(name).await?;
@@ -489,6 +661,7 @@ impl Directory {
                 old_lock,
                 new_lock,
                 src_initial_vv,
+                src_times,
             )
             .await?
         } else {
@@ -499,6 +672,7 @@ impl Directory {
                 name.to_owned(),
                 src_blob_id,
                 &src_initial_vv,
+                src_times,
             )
             .await?
         };
@@ -561,6 +735,7 @@ impl Directory {
     /// # Panics
     ///
     /// Panics if the entry at `name` doesn't exist or is not a directory.
+    #[allow(clippy::too_many_arguments)]
     async fn fork_update(
         &mut self,
         tx: &mut WriteTransaction,
@@ -569,6 +744,7 @@ impl Directory {
         old_lock: ReadLock,
         new_lock: ReadLock,
         initial_vv: VersionVector,
+        times: EntryTimes,
     ) -> Result<(Self, Content)> {
         let old_blob_id = *old_lock.blob_id();
         let new_blob_id = *new_lock.blob_id();
@@ -594,6 +770,7 @@ impl Directory {
 
         let bump = Bump::Merge(initial_vv);
         let diff = bump.apply(&mut entry.version_vector);
+        entry.times.merge(&times);
 
         // Change the blob id
         if new_blob_id != entry.blob_id {
@@ -777,6 +954,11 @@ impl Directory {
         self.blob.id()
     }
 
+    /// Stable id of this directory (see `EntryRef::id`).
+    pub fn id(&self) -> u128 {
+        self.blob_id().entry_id()
+    }
+
     /// Length of this directory in bytes. Does not include the content, only the size of directory
     /// itself.
     pub fn len(&self) -> u64 {
@@ -820,7 +1002,7 @@ impl Directory {
 
         new_data.version_vector_mut().increment(*self.branch().id());
 
-        self.begin_insert_entry(tx, changeset, name.to_owned(), new_data)
+        self.begin_insert_entry(tx, changeset, name.to_owned(), new_data, true)
             .await
     }
 
@@ -843,19 +1025,28 @@ impl Directory {
         }
     }
 
+    /// Inserts the entry into the content of this directory without finalizing it. `modified`
+    /// tells whether this is a local modification of this directory (as opposed to a merge) which
+    /// updates its modification time.
     async fn begin_insert_entry(
         &mut self,
         tx: &mut ReadTransaction,
         changeset: &mut Changeset,
         name: String,
         data: EntryData,
+        modified: bool,
     ) -> Result<Content> {
         self.refresh_in(tx).await?;
 
         let mut content = self.content.clone();
         let diff = content.insert(name, data)?;
         self.save(tx, changeset, &content).await?;
-        self.bump(tx, changeset, Bump::Add(diff)).await?;
+
+        if modified {
+            self.bump_modified(tx, changeset, Bump::Add(diff)).await?;
+        } else {
+            self.bump(tx, changeset, Bump::Add(diff)).await?;
+        }
 
         Ok(content)
     }
@@ -887,7 +1078,7 @@ impl Directory {
         content: &Content,
     ) -> Result<()> {
         // Save the directory content into the store
-        let buffer = content.serialize();
+        let buffer = content.serialize(self.branch().is_directory_compression_enabled());
         self.blob.truncate(0)?;
         self.blob.write_all(tx, changeset, &buffer).await?;
         self.blob.flush(tx, changeset).await?;
@@ -895,6 +1086,22 @@ impl Directory {
         Ok(())
     }
 
+    /// Applies the changeset to the transaction without committing it.
+    async fn apply(&self, tx: &mut WriteTransaction, changeset: Changeset) -> Result<()> {
+        changeset
+            .apply(
+                tx,
+                self.branch().id(),
+                self.branch()
+                    .keys()
+                    .write()
+                    .ok_or(Error::PermissionDenied)?,
+            )
+            .await?;
+
+        Ok(())
+    }
+
     /// Atomically commits the transaction and sends notification event.
     async fn commit(&mut self, tx: WriteTransaction, changeset: Changeset) -> Result<()> {
         commit(tx, changeset, self.branch()).await
@@ -919,6 +1126,24 @@ impl Directory {
         }
     }
 
+    /// Like `bump` but also sets the modification time of this directory to now. Use when the
+    /// entries of this directory are changed locally (not by a merge).
+    async fn bump_modified(
+        &mut self,
+        tx: &mut ReadTransaction,
+        changeset: &mut Changeset,
+        bump: Bump,
+    ) -> Result<()> {
+        if let Some(parent) = self.parent.as_mut() {
+            parent
+                .bump_modified(tx, changeset, self.blob.branch().clone(), bump)
+                .await
+        } else {
+            changeset.bump(bump);
+            Ok(())
+        }
+    }
+
     /// Finalize pending modifications. Call this only after the db transaction has been committed.
     fn finalize(&mut self, content: Content) {
         self.content = content;
@@ -6,10 +6,11 @@ use crate::{
         lock::{LockKind, ReadLock},
     },
     branch::Branch,
-    directory::{content::EntryExists, Directory},
+    directory::{content::EntryExists, Directory, EntryData},
     error::Result,
     protocol::Bump,
     store::{Changeset, ReadTransaction},
+    time::Timestamp,
     version_vector::VersionVector,
 };
 use tracing::{field, instrument, Span};
@@ -52,10 +53,44 @@ impl ParentContext {
         changeset: &mut Changeset,
         branch: Branch,
         bump: Bump,
+    ) -> Result<()> {
+        self.bump_with(tx, changeset, branch, bump, false).await
+    }
+
+    /// Like `bump` but also sets the modification time of this entry to now. Use for the local
+    /// modifications of the entry, not for merges (which keep the timestamps of the merged
+    /// versions) and not for the modifications of its descendants.
+    pub async fn bump_modified(
+        &self,
+        tx: &mut ReadTransaction,
+        changeset: &mut Changeset,
+        branch: Branch,
+        bump: Bump,
+    ) -> Result<()> {
+        self.bump_with(tx, changeset, branch, bump, true).await
+    }
+
+    async fn bump_with(
+        &self,
+        tx: &mut ReadTransaction,
+        changeset: &mut Changeset,
+        branch: Branch,
+        bump: Bump,
+        modified: bool,
     ) -> Result<()> {
         let mut directory = self.open_in(tx, branch).await?;
         let mut content = directory.content.clone();
         let diff = content.bump(&self.entry_name, bump)?;
+
+        if modified {
+            if let Some(times) = content
+                .get_mut(&self.entry_name)
+                .and_then(EntryData::times_mut)
+            {
+                times.modified = Timestamp::now();
+            }
+        }
+
         directory.save(tx, changeset, &content).await?;
         directory.bump(tx, changeset, Bump::Add(diff)).await?;
 
@@ -181,6 +216,15 @@ impl ParentContext {
         &self.entry_name
     }
 
+    /// Depth of this entry in the directory tree (the entries of the root directory have depth 1).
+    pub(super) fn depth(&self) -> u32 {
+        1 + self
+            .parent
+            .as_ref()
+            .map(|parent| parent.depth())
+            .unwrap_or(0)
+    }
+
     /// Returns the version vector of this entry.
     pub async fn entry_version_vector(&self, branch: Branch) -> Result<VersionVector> {
         Ok(self
@@ -8,7 +8,7 @@ use crate::{
     test_utils,
 };
 use assert_matches::assert_matches;
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, time::UNIX_EPOCH};
 use tempfile::TempDir;
 use tracing::Instrument;
 
@@ -713,6 +713,148 @@ async fn create_tombstone_is_idempotent() {
     assert_eq!(proof2, proof1);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn create_entry_over_limits() {
+    let (_base_dir, pool) = db::create_temp().await.unwrap();
+    let shared = BranchShared::new();
+    *shared.directory_limits.write().unwrap() = DirectoryLimits {
+        max_entries: 2,
+        max_depth: 2,
+        max_name_length: 8,
+    };
+    let branch = Branch::new(
+        PublicKey::random(),
+        Store::new(pool),
+        AccessKeys::from(WriteSecrets::random()),
+        shared,
+        EventSender::new(1),
+    );
+
+    let mut root = branch.open_or_create_root().await.unwrap();
+
+    assert_matches!(
+        root.create_file("too-long-name".into()).await,
+        Err(Error::NameTooLong)
+    );
+
+    let mut dir = root
+        .create_directory("dir".into(), rand::random(), &VersionVector::new())
+        .await
+        .unwrap();
+    root.create_file("file".into()).await.unwrap();
+
+    assert_matches!(
+        root.create_file("other".into()).await,
+        Err(Error::TooManyEntries)
+    );
+
+    // Tombstones don't count.
+    let file_vv = root.lookup("file").unwrap().version_vector().clone();
+    root.remove_entry("file", branch.id(), file_vv)
+        .await
+        .unwrap();
+    root.create_file("other".into()).await.unwrap();
+
+    assert_matches!(
+        root.create_file("file".into()).await,
+        Err(Error::TooManyEntries)
+    );
+
+    // Renaming within the directory doesn't increase the number of entries.
+    let data = root.lookup("other").unwrap().clone_data();
+    root.clone()
+        .move_entry(
+            "other",
+            data,
+            &mut root,
+            "renamed",
+            VersionVector::first(*branch.id()),
+        )
+        .await
+        .unwrap();
+
+    // Moving an entry into a full directory is not allowed.
+    dir.create_file("moved".into()).await.unwrap();
+    let data = dir.lookup("moved").unwrap().clone_data();
+    assert_matches!(
+        dir.move_entry(
+            "moved",
+            data,
+            &mut root,
+            "moved",
+            VersionVector::first(*branch.id()),
+        )
+        .await,
+        Err(Error::TooManyEntries)
+    );
+
+    let mut subdir = dir
+        .create_directory("subdir".into(), rand::random(), &VersionVector::new())
+        .await
+        .unwrap();
+
+    assert_matches!(
+        subdir.create_file("file".into()).await,
+        Err(Error::PathTooDeep)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entry_times() {
+    let (_base_dir, branch) = setup().await;
+
+    let mut root = branch.open_or_create_root().await.unwrap();
+    let mut dir = root
+        .create_directory("dir".into(), rand::random(), &VersionVector::new())
+        .await
+        .unwrap();
+
+    let mut file = dir.create_file("file.txt".into()).await.unwrap();
+
+    let root = branch
+        .open_root(DirectoryLocking::Enabled, DirectoryFallback::Disabled)
+        .await
+        .unwrap();
+    let dir_entry = root.lookup("dir").unwrap();
+    let dir_times = *dir_entry.times().unwrap();
+
+    // Adding an entry modifies the directory.
+    assert_ne!(dir_entry.created(), Some(UNIX_EPOCH));
+    assert!(dir_times.modified > dir_times.created);
+
+    let dir = dir_entry
+        .directory()
+        .unwrap()
+        .open(DirectoryFallback::Disabled)
+        .await
+        .unwrap();
+    let file_times = *dir.lookup("file.txt").unwrap().times().unwrap();
+    assert_eq!(file_times.modified, file_times.created);
+    assert!(file_times.created > dir_times.created);
+
+    file.write_all(b"hello").await.unwrap();
+    file.flush().await.unwrap();
+
+    let root = branch
+        .open_root(DirectoryLocking::Enabled, DirectoryFallback::Disabled)
+        .await
+        .unwrap();
+    let dir_entry = root.lookup("dir").unwrap();
+
+    // Modifying a file doesn't modify its parent directory.
+    assert_eq!(*dir_entry.times().unwrap(), dir_times);
+
+    let dir = dir_entry
+        .directory()
+        .unwrap()
+        .open(DirectoryFallback::Disabled)
+        .await
+        .unwrap();
+    let new_file_times = *dir.lookup("file.txt").unwrap().times().unwrap();
+    assert_eq!(new_file_times.created, file_times.created);
+    assert!(new_file_times.modified > file_times.modified);
+}
+
 async fn setup() -> (TempDir, Branch) {
     let (base_dir, [branch]) = setup_multiple().await;
     (base_dir, branch)
@@ -0,0 +1,178 @@
+//! Merkle proofs that the content of a file is part of a snapshot signed by a writer of the
+//! repository. They allow third parties that know only the repository id (no read access needed)
+//! to audit that a replica (e.g., a mirror) serves authentic blocks.
+
+use crate::{
+    crypto::{
+        sign::{PublicKey, Signature},
+        Hash, Hashable,
+    },
+    protocol::{
+        get_bucket, BlockId, InnerNode, InnerNodes, LeafNode, LeafNodes, RootNode,
+        SingleBlockPresence, Summary, UntrustedProof, INNER_LAYER_COUNT,
+    },
+    repository::RepositoryId,
+    store::{self, ReadTransaction},
+    version_vector::VersionVector,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Proof that the blocks of a file are part of a signed snapshot of a branch. Obtained with
+/// `Repository::prove_entry` and checked with `verify_entry_proof`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EntryProof {
+    /// Id of the branch whose snapshot contains the file.
+    pub branch_id: PublicKey,
+    /// Version vector of the snapshot.
+    pub version_vector: VersionVector,
+    /// Root hash of the snapshot.
+    pub root_hash: Hash,
+    /// Signature of the snapshot by the repository write key.
+    pub signature: Signature,
+    /// Proofs of the blocks of the file, in order.
+    pub blocks: Vec<BlockProof>,
+}
+
+/// Chain of hashes from a single block to the root of the snapshot.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BlockProof {
+    /// Encoded locator of the block.
+    pub locator: Hash,
+    pub block_id: BlockId,
+    /// Hashes of the inner nodes on the path from the root to the block, one set of siblings per
+    /// layer, starting with the children of the root.
+    pub inner_layers: Vec<BTreeMap<u8, Hash>>,
+    /// The leaf nodes (locator and block id) that are siblings of the block, including the block
+    /// itself.
+    pub leaves: Vec<(Hash, BlockId)>,
+}
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum EntryProofError {
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("hash mismatch")]
+    HashMismatch,
+}
+
+/// Checks that the proof is signed with the write key of the given repository and that all its
+/// blocks are part of the signed snapshot. Returns the ids of the blocks of the file, in order.
+pub fn verify_entry_proof(
+    proof: &EntryProof,
+    repository_id: &RepositoryId,
+) -> Result<Vec<BlockId>, EntryProofError> {
+    UntrustedProof {
+        writer_id: proof.branch_id,
+        version_vector: proof.version_vector.clone(),
+        hash: proof.root_hash,
+        signature: proof.signature,
+    }
+    .verify(repository_id)
+    .map_err(|_| EntryProofError::InvalidSignature)?;
+
+    proof
+        .blocks
+        .iter()
+        .map(|block| verify_block(block, &proof.root_hash))
+        .collect()
+}
+
+fn verify_block(block: &BlockProof, root_hash: &Hash) -> Result<BlockId, EntryProofError> {
+    if block.inner_layers.len() != INNER_LAYER_COUNT {
+        return Err(EntryProofError::HashMismatch);
+    }
+
+    let mut parent_hash = *root_hash;
+
+    for (layer, children) in block.inner_layers.iter().enumerate() {
+        let nodes: InnerNodes = children
+            .iter()
+            .map(|(bucket, hash)| (*bucket, InnerNode::new(*hash, Summary::INCOMPLETE)))
+            .collect();
+
+        if nodes.hash() != parent_hash {
+            return Err(EntryProofError::HashMismatch);
+        }
+
+        parent_hash = *children
+            .get(&get_bucket(&block.locator, layer))
+            .ok_or(EntryProofError::HashMismatch)?;
+    }
+
+    let leaves: LeafNodes = block
+        .leaves
+        .iter()
+        .map(|(locator, block_id)| LeafNode {
+            locator: *locator,
+            block_id: *block_id,
+            block_presence: SingleBlockPresence::Missing,
+        })
+        .collect();
+
+    if leaves.hash() != parent_hash {
+        return Err(EntryProofError::HashMismatch);
+    }
+
+    match leaves.get(&block.locator) {
+        Some(leaf) if leaf.block_id == block.block_id => Ok(block.block_id),
+        _ => Err(EntryProofError::HashMismatch),
+    }
+}
+
+/// Builds the proof of the blocks with the given (encoded) locators in the snapshot given by
+/// `root_node`.
+pub(crate) async fn build(
+    tx: &mut ReadTransaction,
+    root_node: &RootNode,
+    locators: impl IntoIterator<Item = Hash>,
+) -> Result<EntryProof, store::Error> {
+    let mut blocks = Vec::new();
+
+    for locator in locators {
+        let mut parent_hash = root_node.proof.hash;
+        let mut inner_layers = Vec::with_capacity(INNER_LAYER_COUNT);
+
+        for layer in 0..INNER_LAYER_COUNT {
+            let nodes = tx.load_inner_nodes(&parent_hash).await?;
+
+            parent_hash = nodes
+                .get(get_bucket(&locator, layer))
+                .ok_or(store::Error::LocatorNotFound)?
+                .hash;
+
+            inner_layers.push(
+                nodes
+                    .iter()
+                    .map(|(bucket, node)| (bucket, node.hash))
+                    .collect(),
+            );
+        }
+
+        let nodes = tx.load_leaf_nodes(&parent_hash).await?;
+        let block_id = nodes
+            .get(&locator)
+            .ok_or(store::Error::LocatorNotFound)?
+            .block_id;
+        let leaves = nodes
+            .iter()
+            .map(|node| (node.locator, node.block_id))
+            .collect();
+
+        blocks.push(BlockProof {
+            locator,
+            block_id,
+            inner_layers,
+            leaves,
+        });
+    }
+
+    Ok(EntryProof {
+        branch_id: root_node.proof.writer_id,
+        version_vector: root_node.proof.version_vector.clone(),
+        root_hash: root_node.proof.hash,
+        signature: root_node.proof.signature,
+        blocks,
+    })
+}
@@ -45,6 +45,16 @@ pub enum Error {
     StorageVersionMismatch,
     #[error("file or directory is locked")]
     Locked,
+    #[error("directory has too many entries")]
+    TooManyEntries,
+    #[error("path is too deep")]
+    PathTooDeep,
+    #[error("entry name is too long")]
+    NameTooLong,
+    #[error("local branch is outdated")]
+    Outdated,
+    #[error("not enough free space left on the device")]
+    StorageFull,
 }
 
 impl Error {
@@ -53,6 +63,41 @@ impl Error {
     pub fn verbose(&self) -> Verbose {
         Verbose(self)
     }
+
+    /// Returns whether the operation that failed with this error might succeed when retried.
+    ///
+    /// Only the errors caused by transient conditions (the database being busy, the entry being
+    /// locked by another handle or the local branch being modified concurrently) have a chance to
+    /// go away on their own. Note that the failed operation might have already committed some of
+    /// its steps (see the crate-level docs), so it should be retried only if repeating those steps
+    /// is harmless.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Db(db::Error::Query(error)) | Self::Store(store::Error::Db(error)) => {
+                is_busy(error)
+            }
+            Self::Store(store::Error::OutdatedRootNode | store::Error::ConcurrentRootNode)
+            | Self::Locked
+            | Self::Outdated => true,
+            _ => false,
+        }
+    }
+}
+
+fn is_busy(error: &sqlx::Error) -> bool {
+    // SQLITE_BUSY and SQLITE_LOCKED, including their extended codes.
+    const BUSY: i32 = 5;
+    const LOCKED: i32 = 6;
+
+    match error {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(error) => error
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .map(|code| matches!(code & 0xff, BUSY | LOCKED))
+            .unwrap_or(false),
+        _ => false,
+    }
 }
 
 impl From<TryFromSliceError> for Error {
@@ -85,3 +130,21 @@ impl fmt::Display for Verbose<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_retryable() {
+        assert!(Error::Locked.is_retryable());
+        assert!(Error::Outdated.is_retryable());
+        assert!(Error::from(sqlx::Error::PoolTimedOut).is_retryable());
+        assert!(Error::Store(store::Error::ConcurrentRootNode).is_retryable());
+
+        assert!(!Error::EntryNotFound.is_retryable());
+        assert!(!Error::PermissionDenied.is_retryable());
+        assert!(!Error::from(sqlx::Error::RowNotFound).is_retryable());
+        assert!(!Error::Store(store::Error::BlockNotFound).is_retryable());
+    }
+}
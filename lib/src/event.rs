@@ -2,7 +2,6 @@
 #![allow(clippy::declare_interior_mutable_const)]
 
 use crate::{crypto::sign::PublicKey, protocol::BlockId};
-use core::fmt;
 use futures_util::{stream, Stream};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::sync::broadcast;
@@ -19,6 +18,29 @@ pub enum Payload {
     /// This event is useful mostly for diagnostics or testing and can be safely ignored in other
     /// contexts.
     MaintenanceCompleted,
+    /// The local metadata of the repository (see `Repository::metadata`) has been modified. This
+    /// includes the display name, icon and color.
+    MetadataChanged,
+    /// The block with the specified id was found to be corrupted (its content doesn't match its
+    /// id) by the scrubber (see `Repository::set_scrub_enabled`).
+    BlockCorrupted(BlockId),
+    /// The repository has been wiped because of a wipe directive targeted at this device (see
+    /// `Repository::publish_wipe_directive`). All its data and its database files have been
+    /// deleted and it should be closed.
+    Wiped,
+    /// The free space on the device holding the repository dropped below the threshold (see
+    /// `Repository::set_min_free_space`). Local modifications fail with `Error::StorageFull` and
+    /// syncing is paused until some space is freed. Sent only once per such drop.
+    StorageFull,
+    /// A writer published a notice that the replicas with some access mode are now limited to a
+    /// lower one (see `Repository::publish_access_notice`). Obtain it with
+    /// `Repository::access_notice`.
+    AccessNotice,
+    /// Never sent by the repository itself. Emitted by the event streams (see
+    /// `Repository::events`) in place of the events that were missed because the subscriber
+    /// lagged behind. Anything could have changed in the meantime, so the subscriber should reload
+    /// whatever state it tracks.
+    Resync,
 }
 
 /// Notification event
@@ -87,25 +109,13 @@ impl EventSender {
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct Lagged;
-
-impl fmt::Display for Lagged {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "event channel lagged")
-    }
-}
-
-impl std::error::Error for Lagged {}
-
-/// Converts event receiver into a `Stream`.
-pub(crate) fn into_stream(
-    rx: broadcast::Receiver<Event>,
-) -> impl Stream<Item = Result<Event, Lagged>> {
+/// Converts event receiver into a `Stream`. Lagging is reported with a single `Payload::Resync`
+/// event. The stream ends when the sender is dropped.
+pub(crate) fn into_stream(rx: broadcast::Receiver<Event>) -> impl Stream<Item = Event> {
     stream::unfold(rx, |mut rx| async move {
         match rx.recv().await {
-            Ok(event) => Some((Ok(event), rx)),
-            Err(broadcast::error::RecvError::Lagged(_)) => Some((Err(Lagged), rx)),
+            Ok(event) => Some((event, rx)),
+            Err(broadcast::error::RecvError::Lagged(_)) => Some((Event::new(Payload::Resync), rx)),
             Err(broadcast::error::RecvError::Closed) => None,
         }
     })
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+/// Bitmap of which blocks of a file are present locally. Useful for visualizing the sync progress
+/// of a file (e.g., as an availability bar).
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BlockPresence {
+    /// Total number of blocks of the file.
+    pub block_count: u32,
+    /// The bitmap. Bit `i % 8` (least significant first) of byte `i / 8` is set iff block `i` is
+    /// present.
+    #[serde(with = "serde_bytes")]
+    pub bits: Vec<u8>,
+}
+
+impl BlockPresence {
+    pub(crate) fn new(block_count: u32) -> Self {
+        Self {
+            block_count,
+            bits: vec![0; (block_count as usize).div_ceil(8)],
+        }
+    }
+
+    pub(crate) fn set_present(&mut self, index: u32) {
+        self.bits[index as usize / 8] |= 1 << (index % 8);
+    }
+
+    /// Is the block with the given index present? Returns `false` if the index is out of bounds.
+    pub fn is_present(&self, index: u32) -> bool {
+        index < self.block_count && self.bits[index as usize / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// Number of present blocks.
+    pub fn present_count(&self) -> u32 {
+        self.bits.iter().map(|byte| byte.count_ones()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_and_query() {
+        let mut presence = BlockPresence::new(10);
+        assert_eq!(presence.bits.len(), 2);
+        assert_eq!(presence.present_count(), 0);
+
+        presence.set_present(0);
+        presence.set_present(9);
+
+        assert!(presence.is_present(0));
+        assert!(!presence.is_present(1));
+        assert!(presence.is_present(9));
+        assert!(!presence.is_present(10));
+        assert_eq!(presence.bits, [0b0000_0001, 0b0000_0010]);
+        assert_eq!(presence.present_count(), 2);
+    }
+}
@@ -1,17 +1,26 @@
+mod block_presence;
 mod progress_cache;
 
+pub use block_presence::BlockPresence;
 pub(crate) use progress_cache::FileProgressCache;
 
 use crate::{
     blob::{lock::UpgradableLock, Blob, ReadWriteError},
     branch::Branch,
     directory::{Directory, ParentContext},
+    entry_proof::{self, EntryProof},
     error::{Error, Result},
-    protocol::{Bump, Locator, BLOCK_SIZE},
-    store::{Changeset, ReadTransaction},
+    memory::{MemoryCategory, MemoryReservation},
+    protocol::{Bump, Locator, RootNodeFilter, SingleBlockPresence, BLOCK_SIZE},
+    store::{self, Changeset, ReadTransaction, WriteTransaction},
     version_vector::VersionVector,
 };
-use std::{fmt, future::Future, io::SeekFrom};
+use std::{
+    fmt,
+    future::Future,
+    io::SeekFrom,
+    sync::{atomic::AtomicBool, Arc},
+};
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 pub struct File {
@@ -107,6 +116,61 @@ impl File {
         }
     }
 
+    /// Which blocks of this file are present locally. This reads only the index, not the blocks
+    /// themselves. Blocks not yet flushed are reported as not present.
+    /// NOTE: Same as with `progress`, the returned future doesn't borrow from `self`.
+    pub fn block_presence(&self) -> impl Future<Output = Result<BlockPresence>> {
+        let branch = self.branch().clone();
+        let locator = Locator::head(*self.blob.id());
+        let block_count = self.blob.block_count();
+
+        async move {
+            let mut tx = branch.store().begin_read().await?;
+            let root_node = tx.load_root_node(branch.id(), RootNodeFilter::Any).await?;
+            let mut presence = BlockPresence::new(block_count);
+
+            for index in 0..block_count {
+                let encoded_locator = locator.nth(index).encode(branch.keys().read());
+
+                match tx
+                    .find_block_presence_at(&root_node, &encoded_locator)
+                    .await
+                {
+                    Ok(SingleBlockPresence::Present) => presence.set_present(index),
+                    Ok(SingleBlockPresence::Missing | SingleBlockPresence::Expired)
+                    | Err(store::Error::LocatorNotFound) => (),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+
+            Ok(presence)
+        }
+    }
+
+    /// Builds the proof that the content of this file is part of the latest published snapshot of
+    /// its branch.
+    pub(crate) async fn prove(&self) -> Result<EntryProof> {
+        let branch = self.branch();
+        let locator = Locator::head(*self.blob.id());
+        let read_key = branch.keys().read();
+
+        let mut tx = branch.store().begin_read().await?;
+        let root_node = tx
+            .load_root_node(branch.id(), RootNodeFilter::Published)
+            .await?;
+        let locators =
+            (0..self.blob.block_count()).map(|index| locator.nth(index).encode(read_key));
+
+        Ok(entry_proof::build(&mut tx, &root_node, locators).await?)
+    }
+
+    /// Bypass the block cache when reading this file: the blocks are dropped from memory as soon
+    /// as the next one is needed. Use for huge one-pass reads (e.g., backups) so they don't evict
+    /// the cached data of the other open files. Modified blocks are still cached until flushed.
+    pub fn set_cache_bypass(&mut self, bypass: bool) {
+        self.blob.set_cache_bypass(bypass);
+    }
+
     /// Reads data from this file. Returns the number of bytes actually read.
     pub async fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
         loop {
@@ -123,29 +187,73 @@ impl File {
         }
     }
 
+    /// Reads data from this file until `buffer` is full or the end of the file is reached. Returns
+    /// the number of bytes actually read.
+    ///
+    /// The blocks already in the cache are served without touching the store and all the missing
+    /// ones are loaded using a single read transaction. This keeps reads that span several blocks
+    /// (e.g., readahead) cheap.
     pub async fn read_all(&mut self, buffer: &mut [u8]) -> Result<usize> {
         let mut offset = 0;
+        let mut snapshot = None;
 
         loop {
-            match self.read(&mut buffer[offset..]).await? {
-                0 => return Ok(offset),
-                n => {
-                    offset += n;
+            match self.blob.read(&mut buffer[offset..]) {
+                Ok(0) => return Ok(offset),
+                Ok(len) => {
+                    offset += len;
+                }
+                Err(ReadWriteError::CacheMiss) => {
+                    let (tx, root_node) = match &mut snapshot {
+                        Some(snapshot) => snapshot,
+                        None => {
+                            let mut tx = self.branch().store().begin_read().await?;
+                            let root_node = tx
+                                .load_root_node(self.branch().id(), RootNodeFilter::Any)
+                                .await?;
+                            snapshot.insert((tx, root_node))
+                        }
+                    };
+
+                    self.blob.warmup_at(tx, root_node).await?;
+                }
+                Err(ReadWriteError::CacheFull) => {
+                    // Flushing creates a new snapshot.
+                    snapshot = None;
+                    self.flush().await?;
                 }
             }
         }
     }
 
+    /// Reads data from this file starting at `offset` until `buffer` is full or the end of the
+    /// file is reached. Returns the number of bytes actually read. Equivalent to seeking to
+    /// `offset` followed by `read_all`.
+    pub async fn read_all_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        self.blob.seek(SeekFrom::Start(offset));
+        self.read_all(buffer).await
+    }
+
     /// Read all data from this file from the current seek position until the end and return then
     /// in a `Vec`.
     pub async fn read_to_end(&mut self) -> Result<Vec<u8>> {
-        let mut buffer = vec![
-            0;
-            (self.blob.len() - self.blob.seek_position())
-                .try_into()
-                .unwrap_or(usize::MAX)
-        ];
-        self.read_all(&mut buffer[..]).await?;
+        self.read_up_to(u64::MAX).await
+    }
+
+    /// Reads at most `len` bytes from the current seek position and returns them in a `Vec`. The
+    /// returned `Vec` is shorter than `len` if the end of the file is reached first.
+    pub async fn read_up_to(&mut self, len: u64) -> Result<Vec<u8>> {
+        let len = len
+            .min(self.blob.len().saturating_sub(self.blob.seek_position()))
+            .try_into()
+            .unwrap_or(usize::MAX);
+
+        let _memory = self.reserve_read_buffer(len);
+        let mut buffer = vec![0; len];
+
+        let len = self.read_all(&mut buffer[..]).await?;
+        buffer.truncate(len);
+
         Ok(buffer)
     }
 
@@ -191,6 +299,11 @@ impl File {
         self.blob.truncate(len)
     }
 
+    /// Does this file have any modifications not yet saved to the store?
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.blob.is_dirty()
+    }
+
     /// Atomically saves any pending modifications and updates the version vectors of this file and
     /// all its ancestors.
     pub async fn flush(&mut self) -> Result<()> {
@@ -198,12 +311,35 @@ impl File {
             return Ok(());
         }
 
+        let mut tx = self.branch().store().begin_write().await?;
+        self.flush_in(&mut tx).await?;
+
+        let event_tx = self.branch().notify();
+        tx.commit_and_then(move || event_tx.send()).await?;
+
+        Ok(())
+    }
+
+    /// Like `flush` but saves at most `max_blocks` modified blocks per call, so a big flush can be
+    /// split into several short ones (e.g., when running under a tight time budget). Each call
+    /// commits the saved blocks and updates the version vectors. Returns `true` when there are no
+    /// more pending modifications.
+    pub async fn flush_partial(&mut self, max_blocks: usize) -> Result<bool> {
+        if !self.blob.is_dirty() {
+            return Ok(true);
+        }
+
         let mut tx = self.branch().store().begin_write().await?;
         let mut changeset = Changeset::new();
 
-        self.blob.flush(&mut tx, &mut changeset).await?;
+        self.branch().check_write_fence(&mut tx).await?;
+        self.branch().check_disk_space()?;
+        let done = self
+            .blob
+            .flush_partial(&mut tx, &mut changeset, max_blocks)
+            .await?;
         self.parent
-            .bump(
+            .bump_modified(
                 &mut tx,
                 &mut changeset,
                 self.branch().clone(),
@@ -225,6 +361,89 @@ impl File {
         let event_tx = self.branch().notify();
         tx.commit_and_then(move || event_tx.send()).await?;
 
+        Ok(done)
+    }
+
+    /// Like `flush` but within an existing transaction. The caller is responsible for committing
+    /// it and for notifying the branch.
+    pub(crate) async fn flush_in(&mut self, tx: &mut WriteTransaction) -> Result<()> {
+        self.save_in(tx, None).await
+    }
+
+    /// Like `flush_in` but the file stays dirty until `committed` is set (which the caller does
+    /// after committing `tx`), so the modifications are not lost if `tx` is dropped instead.
+    pub(crate) async fn flush_in_pending(
+        &mut self,
+        tx: &mut WriteTransaction,
+        committed: Arc<AtomicBool>,
+    ) -> Result<()> {
+        self.save_in(tx, Some(committed)).await
+    }
+
+    async fn save_in(
+        &mut self,
+        tx: &mut WriteTransaction,
+        committed: Option<Arc<AtomicBool>>,
+    ) -> Result<()> {
+        if !self.blob.is_dirty() {
+            return Ok(());
+        }
+
+        let mut changeset = Changeset::new();
+
+        self.branch().check_write_fence(tx).await?;
+        self.branch().check_disk_space()?;
+
+        if let Some(committed) = committed {
+            self.blob
+                .flush_pending(tx, &mut changeset, committed)
+                .await?;
+        } else {
+            self.blob.flush(tx, &mut changeset).await?;
+        }
+
+        self.parent
+            .bump_modified(
+                tx,
+                &mut changeset,
+                self.branch().clone(),
+                Bump::increment(*self.branch().id()),
+            )
+            .await?;
+
+        changeset
+            .apply(
+                tx,
+                self.branch().id(),
+                self.branch()
+                    .keys()
+                    .write()
+                    .ok_or(Error::PermissionDenied)?,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Like `write_all` but within an existing transaction: the modifications that don't fit into
+    /// the cache are flushed into it (see `flush_in`) instead of into their own transactions.
+    pub(crate) async fn write_all_in(
+        &mut self,
+        tx: &mut WriteTransaction,
+        buffer: &[u8],
+    ) -> Result<()> {
+        self.acquire_write_lock()?;
+
+        let mut offset = 0;
+
+        while offset < buffer.len() {
+            match self.blob.write(&buffer[offset..]) {
+                Ok(len) => offset += len,
+                Err(ReadWriteError::CacheMiss) => self.blob.warmup(tx).await?,
+                Err(ReadWriteError::CacheFull) => self.flush_in(tx).await?,
+            }
+        }
+
         Ok(())
     }
 
@@ -242,6 +461,7 @@ impl File {
     /// Copy the entire contents of this file into the provided writer (e.g. a file on a regular
     /// filesystem)
     pub async fn copy_to_writer<W: AsyncWrite + Unpin>(&mut self, dst: &mut W) -> Result<()> {
+        let _memory = self.reserve_read_buffer(BLOCK_SIZE);
         let mut buffer = vec![0; BLOCK_SIZE];
 
         loop {
@@ -273,7 +493,9 @@ impl File {
 
         let blob = {
             let mut tx = dst_branch.store().begin_read().await?;
-            Blob::open(&mut tx, dst_branch, *self.blob.id()).await?
+            let mut blob = Blob::open(&mut tx, dst_branch, *self.blob.id()).await?;
+            blob.set_cache_bypass(self.blob.cache_bypass());
+            blob
         };
 
         *self = Self { blob, parent, lock };
@@ -287,6 +509,11 @@ impl File {
             .await
     }
 
+    /// Stable id of this file (see `EntryRef::id`).
+    pub fn id(&self) -> u128 {
+        self.blob.id().entry_id()
+    }
+
     /// BlobId of this file.
     #[cfg(test)]
     pub(crate) fn blob_id(&self) -> &crate::blob::BlobId {
@@ -296,6 +523,17 @@ impl File {
     fn acquire_write_lock(&mut self) -> Result<()> {
         self.lock.upgrade().then_some(()).ok_or(Error::Locked)
     }
+
+    // Accounts a buffer of `size` bytes the content of this file is read into.
+    fn reserve_read_buffer(&self, size: usize) -> MemoryReservation {
+        let mut memory = self
+            .branch()
+            .store()
+            .memory_budget()
+            .reserve(MemoryCategory::ReadBuffer);
+        memory.set(size);
+        memory
+    }
 }
 
 impl fmt::Debug for File {
@@ -460,6 +698,126 @@ mod tests {
         assert_eq!(dst_content, src_content);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn block_presence() {
+        let (_base_dir, [branch]) = setup().await;
+
+        let mut file = branch.ensure_file_exists("data.bin".into()).await.unwrap();
+        file.write_all(&vec![0xab; 2 * BLOCK_SIZE]).await.unwrap();
+        file.flush().await.unwrap();
+
+        let presence = file.block_presence().await.unwrap();
+        assert_eq!(presence.block_count, 3);
+        assert_eq!(presence.present_count(), 3);
+        assert!((0..3).all(|index| presence.is_present(index)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_all_at() {
+        let (_base_dir, [branch]) = setup().await;
+
+        let content: Vec<u8> = (0..3 * BLOCK_SIZE).map(|i| i as u8).collect();
+
+        let mut file = branch.ensure_file_exists("data.bin".into()).await.unwrap();
+        file.write_all(&content).await.unwrap();
+        file.flush().await.unwrap();
+        drop(file);
+
+        // Reopen so only the first block is cached.
+        let mut file = branch
+            .open_root(DirectoryLocking::Enabled, DirectoryFallback::Disabled)
+            .await
+            .unwrap()
+            .lookup("data.bin")
+            .unwrap()
+            .file()
+            .unwrap()
+            .open()
+            .await
+            .unwrap();
+
+        // Spanning several blocks.
+        let offset = BLOCK_SIZE / 2;
+        let mut buffer = vec![0; 2 * BLOCK_SIZE];
+        assert_eq!(
+            file.read_all_at(offset as u64, &mut buffer).await.unwrap(),
+            buffer.len()
+        );
+        assert_eq!(buffer, content[offset..offset + buffer.len()]);
+
+        // Page sized, out of order.
+        let mut buffer = vec![0; 4096];
+        for offset in [2 * BLOCK_SIZE, 4096, 0] {
+            assert_eq!(
+                file.read_all_at(offset as u64, &mut buffer).await.unwrap(),
+                buffer.len()
+            );
+            assert_eq!(buffer, content[offset..offset + buffer.len()]);
+        }
+
+        // Past the end.
+        let offset = content.len() - 100;
+        assert_eq!(
+            file.read_all_at(offset as u64, &mut buffer).await.unwrap(),
+            100
+        );
+        assert_eq!(buffer[..100], content[offset..]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_up_to() {
+        let (_base_dir, [branch]) = setup().await;
+
+        let content: Vec<u8> = (0..BLOCK_SIZE + 100).map(|i| i as u8).collect();
+
+        let mut file = branch.ensure_file_exists("data.bin".into()).await.unwrap();
+        file.write_all(&content).await.unwrap();
+        file.flush().await.unwrap();
+
+        file.seek(SeekFrom::Start(0));
+        assert_eq!(file.read_up_to(10).await.unwrap(), content[..10]);
+
+        // Shorter than requested at the end of the file.
+        file.seek(SeekFrom::Start(BLOCK_SIZE as u64));
+        assert_eq!(
+            file.read_up_to(u64::MAX).await.unwrap(),
+            content[BLOCK_SIZE..]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn flush_partial() {
+        let (_base_dir, [branch]) = setup().await;
+
+        let content: Vec<u8> = (0..4 * BLOCK_SIZE).map(|i| i as u8).collect();
+
+        let mut file = branch.ensure_file_exists("big.dat".into()).await.unwrap();
+        file.write_all(&content).await.unwrap();
+
+        let mut calls = 0;
+        while !file.flush_partial(1).await.unwrap() {
+            calls += 1;
+        }
+
+        assert!(calls > 1);
+        assert!(file.flush_partial(1).await.unwrap());
+        drop(file);
+
+        let mut file = branch
+            .open_root(DirectoryLocking::Enabled, DirectoryFallback::Disabled)
+            .await
+            .unwrap()
+            .lookup("big.dat")
+            .unwrap()
+            .file()
+            .unwrap()
+            .open()
+            .await
+            .unwrap();
+
+        assert_eq!(file.read_to_end().await.unwrap(), content);
+    }
+
     async fn setup<const N: usize>() -> (TempDir, [Branch; N]) {
         let (base_dir, pool) = db::create_temp().await.unwrap();
         let store = Store::new(pool);
@@ -2,9 +2,10 @@
 mod tests;
 
 use crate::{
+    blob,
     branch::Branch,
     conflict,
-    crypto::sign::PublicKey,
+    crypto::{sign::PublicKey, Hashable},
     directory::{
         self, Directory, DirectoryFallback, DirectoryRef, EntryRef, EntryTombstoneData, EntryType,
         FileRef,
@@ -17,15 +18,22 @@ use crate::{
     versioned::{self, PreferBranch},
 };
 use async_recursion::async_recursion;
-use camino::{Utf8Component, Utf8Path};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 use either::Either;
+use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, VecDeque},
+    collections::{BTreeMap, BTreeSet, VecDeque},
     fmt, iter, mem,
+    time::{SystemTime, UNIX_EPOCH},
 };
+use tokio::task;
 use tracing::{instrument, Instrument};
 
+// Merge yields to the scheduler after processing this many entries so that merging large
+// directories doesn't starve other tasks (e.g., reads).
+const MERGE_YIELD_INTERVAL: usize = 16;
+
 /// Unified view over multiple concurrent versions of a directory.
 #[derive(Clone)]
 pub struct JointDirectory {
@@ -64,6 +72,12 @@ impl JointDirectory {
             .and_then(|branch| self.versions.get_mut(branch.id()))
     }
 
+    /// Stable id of this directory (see `EntryRef::id`). The concurrent versions of a directory
+    /// usually share the blob id, if they don't the smallest id is used so all the replicas agree.
+    pub fn id(&self) -> u128 {
+        self.versions.values().map(Directory::id).min().unwrap_or(0)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.entries().next().is_none()
     }
@@ -76,8 +90,48 @@ impl JointDirectory {
             .flat_map(|(_, merge)| merge.ignore_tombstones())
     }
 
+    /// Like `entries` but resumes the listing after the given cursor (obtained with
+    /// `JointEntryRef::cursor`) or starts from the beginning if `None`. Use for paginated listing.
+    /// The cursor remains valid when the directory is refreshed or reopened in the meantime: the
+    /// listing continues with the entries that sort after it, whether the entry itself still
+    /// exists or not. The entries before the cursor are skipped without merging them.
+    ///
+    /// Note that the concurrent versions of the same entry are ordered by their unique names here
+    /// so the order can differ from `entries` in that case.
+    pub fn entries_after<'a>(
+        &'a self,
+        cursor: Option<&EntryCursor>,
+    ) -> impl Iterator<Item = JointEntryRef<'a>> + 'a {
+        let entries =
+            self.merge_entries_from(cursor.map(|cursor| cursor.name.as_str()).unwrap_or(""));
+        let cursor = cursor.cloned();
+
+        entries.flat_map(move |(_, merge)| {
+            let mut entries: Vec<_> = merge
+                .ignore_tombstones()
+                .map(|entry| (entry.cursor(), entry))
+                .collect();
+
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries.retain(|(entry_cursor, _)| match &cursor {
+                Some(cursor) => entry_cursor > cursor,
+                None => true,
+            });
+
+            entries.into_iter().map(|(_, entry)| entry)
+        })
+    }
+
     fn merge_entries(&self) -> impl Iterator<Item = (&str, Merge)> {
-        let entries = self.versions.values().map(|directory| directory.entries());
+        self.merge_entries_from("")
+    }
+
+    // Merges the entries whose names are greater than or equal to `start`.
+    fn merge_entries_from(&self, start: &str) -> impl Iterator<Item = (&str, Merge)> {
+        let entries = self
+            .versions
+            .values()
+            .map(|directory| directory.entries_from(start));
         let entries = SortedUnion::new(entries, |entry| entry.name());
         let entries = Accumulate::new(entries, |entry| entry.name());
         entries.map(|(name, entries)| {
@@ -250,8 +304,19 @@ impl JointDirectory {
     /// In the presence of conflicts (multiple concurrent versions of the same file) this function
     /// still proceeds as far as it can, but the conflicting files remain unmerged. It signals this
     /// by returning `Error::AmbiguousEntry`.
-    #[async_recursion]
     pub async fn merge(&mut self) -> Result<Directory> {
+        self.merge_collecting_conflicts(Utf8Path::new("/"), &mut BTreeSet::new())
+            .await
+    }
+
+    /// Like `merge` but also collects the paths of the conflicting entries into `conflicts`.
+    /// `path` is the path of this directory.
+    #[async_recursion]
+    pub(crate) async fn merge_collecting_conflicts(
+        &mut self,
+        path: &Utf8Path,
+        conflicts: &mut BTreeSet<Utf8PathBuf>,
+    ) -> Result<Directory> {
         let old_version_vector = if let Some(local_version) = self.local_version() {
             local_version.version_vector().await?
         } else {
@@ -276,7 +341,11 @@ impl JointDirectory {
         let mut conflict = false;
         let mut check_for_removal = Vec::new();
 
-        for (name, merge) in self.merge_entries() {
+        for (index, (name, merge)) in self.merge_entries().enumerate() {
+            if index > 0 && index % MERGE_YIELD_INTERVAL == 0 {
+                task::yield_now().await;
+            }
+
             match merge {
                 Merge::Existing(existing) => {
                     for entry in existing {
@@ -289,6 +358,7 @@ impl JointDirectory {
                                         // so can't be automatically merged. We still proceed with merging the
                                         // remaining entries but we won't mark this directory as merged (by bumping its
                                         // vv) to prevent the conflicting remote file from being collected.
+                                        conflicts.insert(path.join(name));
                                         conflict = true;
                                     }
                                     Err(error) => return Err(error),
@@ -302,7 +372,7 @@ impl JointDirectory {
                                     )
                                     .await?;
                                 match dir
-                                    .merge()
+                                    .merge_collecting_conflicts(&path.join(name), conflicts)
                                     .instrument(tracing::info_span!("dir", message = name))
                                     .await
                                 {
@@ -420,6 +490,39 @@ impl<'a> JointEntryRef<'a> {
         }
     }
 
+    /// Stable id of this entry (see `EntryRef::id`).
+    pub fn id(&self) -> u128 {
+        match self {
+            Self::File(r) => r.id(),
+            Self::Directory(r) => r.id(),
+        }
+    }
+
+    /// Creation time of this entry (see `EntryRef::created`).
+    pub fn created(&self) -> SystemTime {
+        match self {
+            Self::File(r) => r.created(),
+            Self::Directory(r) => r.created(),
+        }
+    }
+
+    /// Modification time of this entry (see `EntryRef::modified`).
+    pub fn modified(&self) -> SystemTime {
+        match self {
+            Self::File(r) => r.modified(),
+            Self::Directory(r) => r.modified(),
+        }
+    }
+
+    /// Position of this entry in the directory listing. Pass it to `JointDirectory::entries_after`
+    /// to continue the listing after this entry.
+    pub fn cursor(&self) -> EntryCursor {
+        EntryCursor {
+            name: self.name().to_owned(),
+            unique_name: self.unique_name().into_owned(),
+        }
+    }
+
     pub fn entry_type(&self) -> EntryType {
         match self {
             Self::File(_) => EntryType::File,
@@ -478,6 +581,17 @@ impl<'a> JointFileRef<'a> {
         }
     }
 
+    /// Stable id of this file (see `EntryRef::id`). The concurrent versions of a file share the
+    /// blob id, so when they are shown side by side the id is mixed with the branch id to keep
+    /// them distinct.
+    pub fn id(&self) -> u128 {
+        if self.needs_disambiguation {
+            blob::entry_id((self.file.blob_id(), self.branch().id()).hash().as_ref())
+        } else {
+            self.file.id()
+        }
+    }
+
     pub async fn open(&self) -> Result<File> {
         self.file.open().await
     }
@@ -490,6 +604,14 @@ impl<'a> JointFileRef<'a> {
         self.file.version_vector()
     }
 
+    pub fn created(&self) -> SystemTime {
+        self.file.created()
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.file.modified()
+    }
+
     pub fn branch(&self) -> &Branch {
         self.file.branch()
     }
@@ -503,6 +625,16 @@ impl<'a> JointFileRef<'a> {
     }
 }
 
+/// Position in the listing of a `JointDirectory`, see `JointDirectory::entries_after`. It's keyed
+/// by the entry name and unique name (not by index) so it stays valid across refreshes.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
+pub struct EntryCursor {
+    // Field order matters for the derived `Ord`: first by name, then by the unique name which
+    // orders the concurrent versions of the same entry.
+    name: String,
+    unique_name: String,
+}
+
 pub struct JointDirectoryRef<'a> {
     versions: Vec<DirectoryRef<'a>>,
     local_branch: Option<&'a Branch>,
@@ -541,6 +673,34 @@ impl<'a> JointDirectoryRef<'a> {
         }
     }
 
+    /// Stable id of this directory (see `JointDirectory::id`).
+    pub fn id(&self) -> u128 {
+        self.versions
+            .iter()
+            .map(DirectoryRef::id)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Earliest known creation time of the versions of this directory.
+    pub fn created(&self) -> SystemTime {
+        self.versions
+            .iter()
+            .map(DirectoryRef::created)
+            .filter(|time| *time != UNIX_EPOCH)
+            .min()
+            .unwrap_or(UNIX_EPOCH)
+    }
+
+    /// Latest modification time of the versions of this directory.
+    pub fn modified(&self) -> SystemTime {
+        self.versions
+            .iter()
+            .map(DirectoryRef::modified)
+            .max()
+            .unwrap_or(UNIX_EPOCH)
+    }
+
     pub fn version_vector(&self) -> VersionVector {
         self.versions
             .iter()
@@ -14,8 +14,11 @@ use crate::{
 use assert_matches::assert_matches;
 use camino::Utf8PathBuf;
 use futures_util::future;
+use proptest::{arbitrary::any, collection::vec};
 use rand::{rngs::StdRng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet};
 use tempfile::TempDir;
+use test_strategy::{proptest, Arbitrary};
 
 #[tokio::test(flavor = "multi_thread")]
 async fn no_conflict() {
@@ -105,6 +108,71 @@ async fn conflict_independent_files() {
     assert_unique_and_ordered(2, root.entries());
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn entries_after() {
+    let (_base_dir, [branch0, branch1]) = setup().await;
+
+    let mut root0 = branch0.open_or_create_root().await.unwrap();
+    create_file(&mut root0, "a.txt", &[]).await;
+    create_file(&mut root0, "file.txt", &[]).await;
+    create_file(&mut root0, "z.txt", &[]).await;
+
+    let mut root1 = branch1.open_or_create_root().await.unwrap();
+    create_file(&mut root1, "b.txt", &[]).await;
+    create_file(&mut root1, "file.txt", &[]).await;
+
+    let root = JointDirectory::new(Some(branch0.clone()), [root0.clone(), root1.clone()]);
+
+    // The paginated listing yields all the entries, even when the concurrent versions of an entry
+    // end up on different pages.
+    let mut listed = Vec::new();
+    let mut cursor = None;
+
+    loop {
+        let page: Vec<_> = root.entries_after(cursor.as_ref()).take(3).collect();
+        let Some(last) = page.last() else {
+            break;
+        };
+
+        cursor = Some(last.cursor());
+        listed.extend(page.iter().map(|entry| entry.unique_name().into_owned()));
+    }
+
+    let mut expected: Vec<_> = root
+        .entries()
+        .map(|entry| entry.unique_name().into_owned())
+        .collect();
+    expected.sort();
+
+    assert_eq!(listed, expected);
+
+    // The cursor remains valid when entries are added before it and when the entry it points to
+    // is removed.
+    let cursor = root.lookup_unique("b.txt").unwrap().cursor();
+
+    create_file(&mut root0, "0.txt", &[]).await;
+    create_file(&mut root0, "c.txt", &[]).await;
+
+    let vv = root1.lookup("b.txt").unwrap().version_vector().clone();
+    root1.remove_entry("b.txt", branch1.id(), vv).await.unwrap();
+
+    let root = JointDirectory::new(Some(branch0.clone()), [root0, root1]);
+    let listed: Vec<_> = root
+        .entries_after(Some(&cursor))
+        .map(|entry| entry.unique_name().into_owned())
+        .collect();
+
+    let mut expected = vec![
+        "c.txt".to_owned(),
+        conflict::create_unique_name("file.txt", branch0.id()),
+        conflict::create_unique_name("file.txt", branch1.id()),
+        "z.txt".to_owned(),
+    ];
+    expected.sort();
+
+    assert_eq!(listed, expected);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn conflict_forked_files() {
     let (_base_dir, [branch0, branch1]) = setup().await;
@@ -1121,6 +1189,36 @@ async fn merge_moved_file() {
     assert_eq!(file.read_to_end().await.unwrap(), file_content);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn merge_collects_conflicts() {
+    let (_base_dir, [branch0, branch1]) = setup().await;
+
+    generate(&branch0, &["dir/file.txt", "a.txt"])
+        .await
+        .unwrap();
+    generate(&branch1, &["dir/file.txt", "b.txt"])
+        .await
+        .unwrap();
+
+    let roots =
+        future::try_join_all([&branch0, &branch1].map(|branch| branch.open_or_create_root()))
+            .await
+            .unwrap();
+
+    let mut conflicts = BTreeSet::new();
+
+    assert_matches!(
+        JointDirectory::new(Some(branch0.clone()), roots)
+            .merge_collecting_conflicts(Utf8Path::new("/"), &mut conflicts)
+            .await,
+        Err(Error::AmbiguousEntry)
+    );
+    assert_eq!(
+        conflicts.into_iter().collect::<Vec<_>>(),
+        [Utf8PathBuf::from("/dir/file.txt")]
+    );
+}
+
 // TODO: merge directory with missing blocks
 
 #[tokio::test(flavor = "multi_thread")]
@@ -1166,6 +1264,269 @@ async fn remove_non_empty_subdirectory() {
     assert_matches!(local_root.lookup("dir1"), Ok(EntryRef::Directory(_)));
 }
 
+// Model test: applies random interleavings of local modifications and merges to a fixed number of
+// branches and checks the result against a reference model which only tracks which file versions
+// (content at a given name) haven't been superseded yet.
+const MODEL_BRANCHES: usize = 3;
+const MODEL_NAMES: [&str; 3] = ["a.txt", "b.txt", "c.txt"];
+
+#[derive(Arbitrary, Debug)]
+enum ModelOp {
+    Create {
+        #[strategy(0..MODEL_BRANCHES)]
+        branch: usize,
+        #[strategy(0..MODEL_NAMES.len())]
+        name: usize,
+    },
+    Modify {
+        #[strategy(0..MODEL_BRANCHES)]
+        branch: usize,
+        #[strategy(0..MODEL_NAMES.len())]
+        name: usize,
+    },
+    Remove {
+        #[strategy(0..MODEL_BRANCHES)]
+        branch: usize,
+        #[strategy(0..MODEL_NAMES.len())]
+        name: usize,
+    },
+    Move {
+        #[strategy(0..MODEL_BRANCHES)]
+        branch: usize,
+        #[strategy(0..MODEL_NAMES.len())]
+        src: usize,
+        #[strategy(0..MODEL_NAMES.len())]
+        dst: usize,
+    },
+    Merge {
+        #[strategy(0..MODEL_BRANCHES)]
+        dst: usize,
+        #[strategy(0..MODEL_BRANCHES)]
+        src: usize,
+    },
+}
+
+#[proptest(cases = 32)]
+fn merge_model(
+    #[strategy(vec(any::<ModelOp>(), 1..24))] ops: Vec<ModelOp>,
+    #[strategy(test_utils::rng_seed_strategy())] rng_seed: u64,
+) {
+    test_utils::run(merge_model_case(ops, rng_seed))
+}
+
+async fn merge_model_case(ops: Vec<ModelOp>, rng_seed: u64) {
+    let (_base_dir, branches) =
+        setup_with_rng::<MODEL_BRANCHES>(StdRng::seed_from_u64(rng_seed)).await;
+
+    // The reference model: file versions, as (content, name), that no operation has superseded
+    // (modified, removed or moved away) yet. Each of them must stay visible in the joint view.
+    let mut expected = BTreeSet::new();
+
+    for (index, op) in ops.into_iter().enumerate() {
+        // Every write has unique content so the versions can be told apart.
+        let content = format!("content-{index}");
+
+        match op {
+            ModelOp::Create { branch, name } => {
+                let branch = &branches[branch];
+                let name = MODEL_NAMES[name];
+                let mut root = branch.open_or_create_root().await.unwrap();
+
+                if read_local(&root, name).await.is_none() {
+                    create_file(&mut root, name, content.as_bytes()).await;
+                    expected.insert((content, name));
+                }
+            }
+            ModelOp::Modify { branch, name } => {
+                let branch = &branches[branch];
+                let name = MODEL_NAMES[name];
+                let root = branch.open_or_create_root().await.unwrap();
+
+                if let Some(old) = read_local(&root, name).await {
+                    update_file(&root, name, content.as_bytes(), branch).await;
+                    expected.remove(&(old, name));
+                    expected.insert((content, name));
+                }
+            }
+            ModelOp::Remove { branch, name } => {
+                let branch = &branches[branch];
+                let name = MODEL_NAMES[name];
+                let mut root = branch.open_or_create_root().await.unwrap();
+
+                if let Some(old) = read_local(&root, name).await {
+                    let vv = root.lookup(name).unwrap().version_vector().clone();
+                    root.remove_entry(name, branch.id(), vv).await.unwrap();
+                    expected.remove(&(old, name));
+                }
+            }
+            ModelOp::Move { branch, src, dst } => {
+                let branch = &branches[branch];
+                let src = MODEL_NAMES[src];
+                let dst = MODEL_NAMES[dst];
+
+                if src == dst {
+                    continue;
+                }
+
+                let mut root = branch.open_or_create_root().await.unwrap();
+
+                let Some(moved) = read_local(&root, src).await else {
+                    continue;
+                };
+                let replaced = read_local(&root, dst).await;
+
+                // Same as in `Repository::move_entry`.
+                let src_data = root.lookup(src).unwrap().clone_data();
+                let dst_vv = root
+                    .lookup(dst)
+                    .map(|entry| entry.version_vector().clone())
+                    .unwrap_or_default()
+                    .merged(src_data.version_vector())
+                    .incremented(*branch.id());
+
+                let mut dst_dir = root.clone();
+                root.move_entry(src, src_data, &mut dst_dir, dst, dst_vv)
+                    .await
+                    .unwrap();
+
+                expected.remove(&(moved.clone(), src));
+
+                if let Some(replaced) = replaced {
+                    expected.remove(&(replaced, dst));
+                }
+
+                expected.insert((moved, dst));
+            }
+            ModelOp::Merge { dst, src } => {
+                if dst != src {
+                    merge_allowing_conflicts(&[&branches[dst], &branches[src]]).await;
+                }
+            }
+        }
+
+        check_not_lost(&branches, &expected).await;
+    }
+
+    // Converge: merge every branch with all the others. After the first round every branch holds
+    // a maximal version of every entry, the second one is for good measure.
+    for _ in 0..2 {
+        merge_all(&branches).await;
+    }
+
+    let snapshots = snapshot_all(&branches).await;
+
+    // Further merges change nothing.
+    merge_all(&branches).await;
+    assert_eq!(snapshot_all(&branches).await, snapshots);
+
+    // No branch keeps an outdated version.
+    let names: BTreeSet<_> = snapshots
+        .iter()
+        .flat_map(|snapshot| snapshot.keys())
+        .collect();
+
+    for name in names {
+        for (index, snapshot) in snapshots.iter().enumerate() {
+            let Some((vv, _)) = snapshot.get(name) else {
+                // Missing is only ok if all the other versions are tombstones.
+                assert!(
+                    snapshots
+                        .iter()
+                        .filter_map(|snapshot| snapshot.get(name))
+                        .all(|(_, content)| content.is_none()),
+                    "branch {index} is missing {name}"
+                );
+                continue;
+            };
+
+            for other in &snapshots {
+                if let Some((other_vv, _)) = other.get(name) {
+                    assert!(!(vv < other_vv), "branch {index} has outdated {name}");
+                }
+            }
+        }
+    }
+
+    check_not_lost(&branches, &expected).await;
+}
+
+// Content of the file at `name` in the given (local) directory, if any.
+async fn read_local(dir: &Directory, name: &str) -> Option<String> {
+    let mut file = dir.lookup(name).ok()?.file().ok()?.open().await.unwrap();
+    Some(String::from_utf8(file.read_to_end().await.unwrap()).unwrap())
+}
+
+async fn merge_allowing_conflicts(branches: &[&Branch]) {
+    match merge(branches).await {
+        Ok(()) | Err(Error::AmbiguousEntry) => (),
+        Err(error) => panic!("merge failed: {error:?}"),
+    }
+}
+
+async fn merge_all(branches: &[Branch]) {
+    for index in 0..branches.len() {
+        let ordered: Vec<_> = iter::once(&branches[index])
+            .chain(
+                branches
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .map(|(_, branch)| branch),
+            )
+            .collect();
+
+        merge_allowing_conflicts(&ordered).await;
+    }
+}
+
+// Asserts that all the expected file versions are visible in the joint view of all the branches.
+async fn check_not_lost(branches: &[Branch], expected: &BTreeSet<(String, &str)>) {
+    let roots = future::try_join_all(branches.iter().map(|branch| branch.open_or_create_root()))
+        .await
+        .unwrap();
+    let joint = JointDirectory::new(None, roots);
+
+    for (content, name) in expected {
+        let mut visible = Vec::new();
+
+        for entry in joint.lookup(name) {
+            if let JointEntryRef::File(entry) = entry {
+                let mut file = entry.open().await.unwrap();
+                visible.push(String::from_utf8(file.read_to_end().await.unwrap()).unwrap());
+            }
+        }
+
+        assert!(
+            visible.contains(content),
+            "{content} lost from {name} (visible: {visible:?})"
+        );
+    }
+}
+
+// Version vector and content (`None` for tombstones) of each entry in the root of each branch.
+async fn snapshot_all(
+    branches: &[Branch],
+) -> Vec<BTreeMap<String, (VersionVector, Option<String>)>> {
+    let mut snapshots = Vec::new();
+
+    for branch in branches {
+        let root = branch.open_or_create_root().await.unwrap();
+        let mut snapshot = BTreeMap::new();
+
+        for entry in root.entries() {
+            let name = entry.name().to_owned();
+            let vv = entry.version_vector().clone();
+            let content = read_local(&root, &name).await;
+
+            snapshot.insert(name, (vv, content));
+        }
+
+        snapshots.push(snapshot);
+    }
+
+    snapshots
+}
+
 async fn setup<const N: usize>() -> (TempDir, [Branch; N]) {
     setup_with_rng::<N>(StdRng::from_entropy()).await
 }
@@ -0,0 +1,280 @@
+use crate::{
+    directory::EntryType,
+    error::{Error, Result},
+    file::File,
+    joint_directory::JointDirectory,
+    repository::Repository,
+    version_vector::VersionVector,
+};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use std::{borrow::Cow, collections::BTreeMap, io::SeekFrom, sync::Arc};
+use tokio::io::AsyncWrite;
+
+/// Read-only view which aggregates the root directories of several repositories into a single
+/// tree. The root of the view contains one directory per repository (named by the name the
+/// repository was inserted under) and everything below them is the content of the respective
+/// repository.
+#[derive(Clone, Default)]
+pub struct JointRepositoryView {
+    repositories: BTreeMap<String, Arc<Repository>>,
+}
+
+impl JointRepositoryView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts the repository under the given name. Fails with `EntryExists` if another repository
+    /// is already inserted under the same name and with `InvalidArgument` if the name is not a
+    /// valid single path component.
+    pub fn insert(&mut self, name: impl Into<String>, repository: Arc<Repository>) -> Result<()> {
+        let name = name.into();
+
+        if !is_valid_name(&name) {
+            return Err(Error::InvalidArgument);
+        }
+
+        if self.repositories.contains_key(&name) {
+            return Err(Error::EntryExists);
+        }
+
+        self.repositories.insert(name, repository);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Arc<Repository>> {
+        self.repositories.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Repository>> {
+        self.repositories.get(name)
+    }
+
+    /// Names of the repositories in this view, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.repositories.keys().map(|name| name.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.repositories.is_empty()
+    }
+
+    /// Splits the path into the repository it points into and the path within that repository.
+    /// Returns `None` if the path points to the root of the view.
+    fn resolve<'a>(
+        &'a self,
+        path: &Utf8Path,
+    ) -> Result<Option<(&'a Arc<Repository>, Utf8PathBuf)>> {
+        let mut components = path
+            .components()
+            .filter(|component| *component != Utf8Component::RootDir);
+
+        let name = match components.next() {
+            Some(Utf8Component::Normal(name)) => name,
+            Some(_) => return Err(Error::InvalidArgument),
+            None => return Ok(None),
+        };
+
+        let repository = self.repositories.get(name).ok_or(Error::EntryNotFound)?;
+        let path = components.collect();
+
+        Ok(Some((repository, path)))
+    }
+
+    pub async fn open_directory(
+        &self,
+        path: impl AsRef<Utf8Path>,
+    ) -> Result<JointRepositoryDirectory<'_>> {
+        match self.resolve(path.as_ref())? {
+            Some((repository, path)) => Ok(JointRepositoryDirectory::Repository(
+                repository.open_directory(path).await?,
+            )),
+            None => Ok(JointRepositoryDirectory::Root(self)),
+        }
+    }
+
+    pub async fn open_file(&self, path: impl AsRef<Utf8Path>) -> Result<ReadOnlyFile> {
+        match self.resolve(path.as_ref())? {
+            Some((repository, path)) => Ok(ReadOnlyFile(repository.open_file(path).await?)),
+            None => Err(Error::EntryIsDirectory),
+        }
+    }
+}
+
+/// File opened through a `JointRepositoryView`. Exposes only the reading operations of `File`.
+pub struct ReadOnlyFile(File);
+
+impl ReadOnlyFile {
+    pub fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+
+    /// See `File::progress`.
+    pub async fn progress(&self) -> Result<u64> {
+        self.0.progress().await
+    }
+
+    /// See `File::read`.
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.0.read(buffer).await
+    }
+
+    /// See `File::read_all`.
+    pub async fn read_all(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.0.read_all(buffer).await
+    }
+
+    /// See `File::read_all_at`.
+    pub async fn read_all_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        self.0.read_all_at(offset, buffer).await
+    }
+
+    /// See `File::read_to_end`.
+    pub async fn read_to_end(&mut self) -> Result<Vec<u8>> {
+        self.0.read_to_end().await
+    }
+
+    pub fn seek(&mut self, pos: SeekFrom) -> u64 {
+        self.0.seek(pos)
+    }
+
+    /// See `File::copy_to_writer`.
+    pub async fn copy_to_writer<W: AsyncWrite + Unpin>(&mut self, dst: &mut W) -> Result<()> {
+        self.0.copy_to_writer(dst).await
+    }
+
+    pub async fn version_vector(&self) -> Result<VersionVector> {
+        self.0.version_vector().await
+    }
+
+    /// See `File::id`.
+    pub fn id(&self) -> u128 {
+        self.0.id()
+    }
+}
+
+/// Directory in a `JointRepositoryView`.
+pub enum JointRepositoryDirectory<'a> {
+    /// The root of the view which lists the repositories.
+    Root(&'a JointRepositoryView),
+    /// Directory inside one of the repositories.
+    Repository(JointDirectory),
+}
+
+impl JointRepositoryDirectory<'_> {
+    /// Returns the names and types of the entries of this directory. Concurrent versions of the
+    /// same file are disambiguated using their unique names.
+    pub fn entries(&self) -> Vec<(Cow<'_, str>, EntryType)> {
+        match self {
+            Self::Root(view) => view
+                .names()
+                .map(|name| (Cow::Borrowed(name), EntryType::Directory))
+                .collect(),
+            Self::Repository(dir) => dir
+                .entries()
+                .map(|entry| (entry.unique_name(), entry.entry_type()))
+                .collect(),
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut components = Utf8Path::new(name).components();
+
+    matches!(
+        (components.next(), components.next()),
+        (Some(Utf8Component::Normal(component)), None) if component == name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access_control::WriteSecrets, repository::RepositoryParams, Access};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn open_directory() {
+        let base_dir = TempDir::new().unwrap();
+        let repo_a = create_repository(&base_dir, "a").await;
+        let repo_b = create_repository(&base_dir, "b").await;
+
+        repo_a.create_directory("foo").await.unwrap();
+        repo_b
+            .create_file("bar.txt")
+            .await
+            .unwrap()
+            .flush()
+            .await
+            .unwrap();
+
+        let mut view = JointRepositoryView::new();
+        view.insert("a", repo_a.clone()).unwrap();
+        view.insert("b", repo_b).unwrap();
+
+        assert_eq!(
+            view.open_directory("/").await.unwrap().entries(),
+            [
+                (Cow::Borrowed("a"), EntryType::Directory),
+                (Cow::Borrowed("b"), EntryType::Directory)
+            ]
+        );
+        assert_eq!(
+            view.open_directory("a").await.unwrap().entries(),
+            [(Cow::Borrowed("foo"), EntryType::Directory)]
+        );
+        assert_eq!(
+            view.open_directory("/b").await.unwrap().entries(),
+            [(Cow::Borrowed("bar.txt"), EntryType::File)]
+        );
+        assert!(view
+            .open_directory("a/foo")
+            .await
+            .unwrap()
+            .entries()
+            .is_empty());
+        assert!(view
+            .open_file("b/bar.txt")
+            .await
+            .unwrap()
+            .read_to_end()
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(matches!(
+            view.open_directory("c").await,
+            Err(Error::EntryNotFound)
+        ));
+        assert!(matches!(
+            view.open_file("/").await,
+            Err(Error::EntryIsDirectory)
+        ));
+        assert!(matches!(
+            view.insert("a", repo_a.clone()),
+            Err(Error::EntryExists)
+        ));
+        assert!(matches!(
+            view.insert("a/b", repo_a),
+            Err(Error::InvalidArgument)
+        ));
+    }
+
+    async fn create_repository(base_dir: &TempDir, name: &str) -> Arc<Repository> {
+        Arc::new(
+            Repository::create(
+                &RepositoryParams::new(base_dir.path().join(name)),
+                Access::WriteUnlocked {
+                    secrets: WriteSecrets::random(),
+                },
+            )
+            .await
+            .unwrap(),
+        )
+    }
+}
@@ -1,3 +1,17 @@
+//! Secure peer-to-peer file syncing library.
+//!
+//! The entry point is [`Repository`]. Together with the types it hands out ([`File`],
+//! [`Directory`], [`JointDirectory`], [`Metadata`], [`Batch`], ...) and with
+//! [`network::Network`] it forms the stable API. None of its methods requires passing a database
+//! connection or transaction.
+//!
+//! Operations are not necessarily atomic. An operation that modifies an entry commits the change
+//! itself in a single transaction, but it might first fork the parent directories into the local
+//! branch, which is committed separately. Operations spanning several entries (creating a
+//! directory together with its missing parents, removing a directory recursively, ...) commit
+//! each step separately too. A failed operation can therefore leave some of its changes applied.
+//! Retrying it is safe only when the operation itself is idempotent (see [`Error::is_retryable`]).
+
 // From experience, this lint is almost never useful. Disabling it globally.
 #![allow(clippy::large_enum_variant)]
 // This affects lots of parts of the code but it's just aesthetic so silencing it for now.
@@ -7,9 +21,12 @@
 #[macro_use]
 mod macros;
 
+pub mod blocking;
 pub mod crypto;
 pub mod network;
 pub mod path;
+pub mod test_vectors;
+pub mod time;
 
 mod access_control;
 mod blob;
@@ -21,17 +38,21 @@ mod db;
 mod debug;
 mod device_id;
 mod directory;
+mod entry_proof;
 mod error;
 mod event;
 mod file;
-mod format;
 mod future;
 mod iterator;
 mod joint_directory;
 mod joint_entry;
+mod joint_repository_view;
+mod memory;
 mod progress;
 mod protocol;
+mod replica_tracker;
 mod repository;
+mod rng;
 mod storage_size;
 mod store;
 mod sync;
@@ -43,26 +64,41 @@ mod versioned;
 
 pub use self::{
     access_control::{
-        Access, AccessChange, AccessMode, AccessSecrets, KeyAndSalt, LocalSecret, SetLocalSecret,
-        ShareToken, WriteSecrets,
+        combine_secret_shares, split_write_secrets, Access, AccessChange, AccessMode,
+        AccessSecrets, KeyAndSalt, LocalSecret, SecretShare, SetLocalSecret, ShareToken,
+        UnlockToken, WriteSecrets,
     },
     blob::HEADER_SIZE as BLOB_HEADER_SIZE,
     branch::Branch,
-    db::SCHEMA_VERSION,
+    db::{
+        DatabaseId, DbRecovery, DbSynchronous, DbTuning, FreezeGuard, FreezeMode,
+        UnknownDbTuningPreset, DEFAULT_MIN_FREE_SPACE, SCHEMA_VERSION,
+    },
     debug::DebugPrinter,
     device_id::DeviceId,
-    directory::{Directory, EntryRef, EntryType, DIRECTORY_VERSION},
+    directory::{
+        DiffKind, Directory, DirectoryLimits, EntryDiff, EntryRef, EntryType, DIRECTORY_VERSION,
+    },
+    entry_proof::{verify_entry_proof, BlockProof, EntryProof, EntryProofError},
     error::{Error, Result},
     event::{Event, Payload},
-    file::File,
-    joint_directory::{JointDirectory, JointEntryRef},
+    file::{BlockPresence, File},
+    joint_directory::{EntryCursor, JointDirectory, JointEntryRef},
     joint_entry::JointEntry,
+    joint_repository_view::{JointRepositoryDirectory, JointRepositoryView, ReadOnlyFile},
+    memory::MemoryBudget,
     network::{peer_addr::PeerAddr, PeerInfo, PeerInfoCollector, PublicRuntimeId, SecretRuntimeId},
     progress::Progress,
-    protocol::BLOCK_SIZE,
+    protocol::{
+        BlockId, BLOCK_SIZE, DEFAULT_MAX_DIRECTORY_ENTRIES, DEFAULT_MAX_NAME_LENGTH,
+        DEFAULT_MAX_PATH_DEPTH,
+    },
     repository::{
-        delete as delete_repository, Credentials, Metadata, Repository, RepositoryHandle,
-        RepositoryId, RepositoryParams,
+        delete as delete_repository, AccessNotice, Batch, BlockRequestState, BulkIngest, Conflicts,
+        ContributionStats, Credentials, FileVersionExplanation, HealthIssue, Metadata,
+        MissingBlockExplanation, Repository, RepositoryHandle, RepositoryHealth, RepositoryId,
+        RepositoryParams, ShareOrigin, SnapshotBlockPresence, SnapshotInfo, SnapshotState,
+        SyncExplanation, UnreplicatedChanges,
     },
     storage_size::StorageSize,
     store::{Error as StoreError, DATA_VERSION},
@@ -0,0 +1,345 @@
+//! Accounting of memory used by the caches and buffers of a session, with a configurable ceiling.
+//!
+//! The accounting is approximate: it tracks only the biggest consumers (the caches, the received
+//! responses waiting to be processed and the buffers files are read into) and estimates their
+//! sizes instead of measuring the actual allocations. The queues of outgoing messages and of
+//! received requests are not tracked as they are short and their messages carry only ids and
+//! hashes. When the ceiling is exceeded the caches shrink and the concurrent block decryptions are
+//! throttled until the usage drops below it again.
+
+use crate::storage_size::StorageSize;
+use state_monitor::{MonitoredValue, StateMonitor};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+// Max number of blocks that can be decrypted concurrently when the memory usage is within the
+// limit. When the limit is exceeded, only one block can be decrypted at a time.
+const MAX_CONCURRENT_DECRYPTS: u32 = 16;
+
+// The usage reported to the state monitor is updated only when it changes by at least this much,
+// to avoid flooding the monitor subscribers with change notifications.
+const MONITOR_GRANULARITY: usize = 1024 * 1024;
+
+/// What is the memory used for.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum MemoryCategory {
+    /// Cache of index nodes.
+    IndexCache = 0,
+    /// Cache of blocks of open files.
+    BlockCache = 1,
+    /// Responses received from the peers, waiting to be processed.
+    MessageQueue = 2,
+    /// Buffers the content of files is read into.
+    ReadBuffer = 3,
+}
+
+impl MemoryCategory {
+    const ALL: [Self; 4] = [
+        Self::IndexCache,
+        Self::BlockCache,
+        Self::MessageQueue,
+        Self::ReadBuffer,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::IndexCache => "index cache",
+            Self::BlockCache => "block cache",
+            Self::MessageQueue => "message queue",
+            Self::ReadBuffer => "read buffers",
+        }
+    }
+}
+
+/// Shared memory budget. Cloning it returns a handle to the same budget.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    shared: Arc<Shared>,
+}
+
+impl MemoryBudget {
+    /// Creates a new memory budget without limit whose usage is reported in the given monitor.
+    pub fn new(monitor: StateMonitor) -> Self {
+        let usage_monitors = MemoryCategory::ALL
+            .map(|category| monitor.make_value(category.name(), Bytes(0)))
+            .into_iter()
+            .collect();
+        let total_monitor = monitor.make_value("total", Bytes(0));
+        let limit_monitor = monitor.make_value("limit", None::<Bytes>);
+
+        Self {
+            shared: Arc::new(Shared {
+                usage: Default::default(),
+                published: Default::default(),
+                limit: AtomicU64::new(u64::MAX),
+                decrypts: Semaphore::new(MAX_CONCURRENT_DECRYPTS as usize),
+                monitors: Some(Monitors {
+                    usage: usage_monitors,
+                    total: total_monitor,
+                    limit: limit_monitor,
+                }),
+            }),
+        }
+    }
+
+    /// Sets the memory ceiling. `None` means unlimited.
+    pub fn set_limit(&self, limit: Option<StorageSize>) {
+        self.shared.limit.store(
+            limit.map(StorageSize::to_bytes).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+
+        if let Some(monitors) = &self.shared.monitors {
+            *monitors.limit.get() = limit.map(|limit| Bytes(limit.to_bytes() as usize));
+        }
+    }
+
+    /// Returns the current memory ceiling or `None` if unlimited.
+    pub fn limit(&self) -> Option<StorageSize> {
+        match self.shared.limit.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            limit => Some(StorageSize::from_bytes(limit)),
+        }
+    }
+
+    /// Returns the current total (estimated) memory usage.
+    pub fn usage(&self) -> StorageSize {
+        StorageSize::from_bytes(self.shared.total() as u64)
+    }
+
+    /// Is the current usage over the limit?
+    pub(crate) fn is_exceeded(&self) -> bool {
+        self.shared.is_exceeded()
+    }
+
+    /// Creates a new, initially empty, reservation in the given category.
+    pub(crate) fn reserve(&self, category: MemoryCategory) -> MemoryReservation {
+        MemoryReservation {
+            shared: self.shared.clone(),
+            category,
+            size: 0,
+        }
+    }
+
+    /// Waits until a block can be decrypted. The returned permit should be held for the duration
+    /// of the decryption.
+    pub(crate) async fn acquire_decrypt_permit(&self) -> SemaphorePermit<'_> {
+        // When over the limit, take all the permits to make sure no other decryption runs
+        // concurrently with this one.
+        let permits = if self.is_exceeded() {
+            MAX_CONCURRENT_DECRYPTS
+        } else {
+            1
+        };
+
+        // unwrap is ok because we never `close` the semaphore.
+        self.shared.decrypts.acquire_many(permits).await.unwrap()
+    }
+}
+
+impl Default for MemoryBudget {
+    /// Creates a new memory budget without limit which is not reported to any monitor.
+    fn default() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                usage: Default::default(),
+                published: Default::default(),
+                limit: AtomicU64::new(u64::MAX),
+                decrypts: Semaphore::new(MAX_CONCURRENT_DECRYPTS as usize),
+                monitors: None,
+            }),
+        }
+    }
+}
+
+/// Amount of memory accounted to a particular consumer. The memory is released when this is
+/// dropped.
+pub(crate) struct MemoryReservation {
+    shared: Arc<Shared>,
+    category: MemoryCategory,
+    size: usize,
+}
+
+impl MemoryReservation {
+    /// Updates the size of this reservation.
+    pub fn set(&mut self, size: usize) {
+        if size > self.size {
+            self.shared.add(self.category, size - self.size);
+        } else if size < self.size {
+            self.shared.sub(self.category, self.size - size);
+        }
+
+        self.size = size;
+    }
+
+    pub fn grow(&mut self, size: usize) {
+        self.set(self.size.saturating_add(size))
+    }
+
+    pub fn shrink(&mut self, size: usize) {
+        self.set(self.size.saturating_sub(size))
+    }
+
+    /// Is the usage of the whole budget this reservation belongs to over the limit?
+    pub fn is_budget_exceeded(&self) -> bool {
+        self.shared.is_exceeded()
+    }
+
+    /// Returns a new empty reservation in the same budget and category.
+    pub fn new_empty(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            category: self.category,
+            size: 0,
+        }
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.set(0)
+    }
+}
+
+struct Shared {
+    usage: [AtomicUsize; MemoryCategory::ALL.len()],
+    published: [AtomicUsize; MemoryCategory::ALL.len()],
+    limit: AtomicU64,
+    decrypts: Semaphore,
+    monitors: Option<Monitors>,
+}
+
+impl Shared {
+    fn total(&self) -> usize {
+        self.usage
+            .iter()
+            .map(|usage| usage.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.total() as u64 > self.limit.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, category: MemoryCategory, size: usize) {
+        let new = self.usage[category as usize].fetch_add(size, Ordering::Relaxed) + size;
+        self.publish(category, new);
+    }
+
+    fn sub(&self, category: MemoryCategory, size: usize) {
+        let new = self.usage[category as usize]
+            .fetch_sub(size, Ordering::Relaxed)
+            .saturating_sub(size);
+        self.publish(category, new);
+    }
+
+    fn publish(&self, category: MemoryCategory, new: usize) {
+        let Some(monitors) = &self.monitors else {
+            return;
+        };
+
+        let published = &self.published[category as usize];
+        let old = published.load(Ordering::Relaxed);
+
+        if old.abs_diff(new) < MONITOR_GRANULARITY && new != 0 {
+            return;
+        }
+
+        if published
+            .compare_exchange(old, new, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            // Someone else is publishing concurrently.
+            return;
+        }
+
+        *monitors.usage[category as usize].get() = Bytes(new);
+        *monitors.total.get() = Bytes(self.total());
+    }
+}
+
+struct Monitors {
+    usage: Vec<MonitoredValue<Bytes>>,
+    total: MonitoredValue<Bytes>,
+    limit: MonitoredValue<Option<Bytes>>,
+}
+
+// Wrapper to display the memory usage in human readable format in the state monitor.
+struct Bytes(usize);
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", StorageSize::from_bytes(self.0 as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn reservation() {
+        let budget = MemoryBudget::default();
+        budget.set_limit(Some(StorageSize::from_bytes(100)));
+
+        let mut a = budget.reserve(MemoryCategory::IndexCache);
+        let mut b = budget.reserve(MemoryCategory::BlockCache);
+
+        a.set(60);
+        assert_eq!(budget.usage().to_bytes(), 60);
+        assert!(!budget.is_exceeded());
+
+        b.grow(50);
+        assert_eq!(budget.usage().to_bytes(), 110);
+        assert!(budget.is_exceeded());
+
+        a.shrink(20);
+        assert_eq!(budget.usage().to_bytes(), 90);
+        assert!(!budget.is_exceeded());
+
+        drop(b);
+        assert_eq!(budget.usage().to_bytes(), 40);
+
+        drop(a);
+        assert_eq!(budget.usage().to_bytes(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn decrypt_throttling() {
+        let budget = MemoryBudget::default();
+        budget.set_limit(Some(StorageSize::from_bytes(10)));
+
+        let _permit = budget.acquire_decrypt_permit().await;
+
+        // Within the limit the decryptions run concurrently.
+        let other = time::timeout(TIMEOUT, budget.acquire_decrypt_permit())
+            .await
+            .unwrap();
+        drop(other);
+
+        let mut reservation = budget.reserve(MemoryCategory::BlockCache);
+        reservation.set(20);
+
+        // Over the limit a decryption has to wait for the one in progress.
+        time::timeout(TIMEOUT, budget.acquire_decrypt_permit())
+            .await
+            .unwrap_err();
+
+        // Once the memory is released it can proceed.
+        reservation.set(0);
+
+        time::timeout(TIMEOUT, budget.acquire_decrypt_permit())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+//! Propagation of access notices (see `Repository::publish_access_notice`) over a link.
+
+use super::message::Content;
+use crate::repository::{AccessNotice, Vault};
+use tokio::{select, sync::mpsc};
+
+/// Sends the current access notice of the local repository (if any) to the peer whenever it
+/// changes and stores the notices coming from the peer. Returns only on error.
+pub(super) async fn run(
+    vault: &Vault,
+    content_tx: mpsc::Sender<Content>,
+    mut notice_rx: mpsc::Receiver<AccessNotice>,
+) {
+    let mut current_rx = vault.access_notice.subscribe();
+    current_rx.mark_changed();
+
+    loop {
+        select! {
+            result = current_rx.changed() => {
+                if result.is_err() {
+                    break;
+                }
+
+                let notice = current_rx.borrow_and_update().clone();
+
+                if let Some(notice) = notice {
+                    if content_tx.send(Content::AccessNotice(notice)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            notice = notice_rx.recv() => {
+                let Some(notice) = notice else {
+                    break;
+                };
+
+                match vault.receive_access_notice(notice).await {
+                    Ok(true) => tracing::info!("Access notice received"),
+                    Ok(false) => (),
+                    Err(error) => tracing::error!(?error, "Failed to store access notice"),
+                }
+            }
+        }
+    }
+}
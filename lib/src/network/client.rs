@@ -1,68 +1,97 @@
 use super::{
-    constants::MAX_PENDING_REQUESTS_PER_CLIENT,
+    constants::{
+        BULK_BLOCK_BATCH_SIZE, BULK_CHILD_NODES_BATCH_SIZE, BULK_MAX_PENDING_REQUESTS_PER_CLIENT,
+        MAX_PENDING_REQUESTS_PER_CLIENT, RECEIPT_INTERVAL, STORAGE_FULL_RECHECK_INTERVAL,
+    },
     debug_payload::{DebugResponse, PendingDebugRequest},
-    message::{Content, Response, ResponseDisambiguator},
+    message::{Content, MirrorReceipt, Request, Response, ResponseDisambiguator},
     pending::{PendingRequest, PendingRequests, PendingResponse, ProcessedResponse},
+    request_queue::RequestQueue,
+    verifier::Verifier,
 };
 use crate::{
     block_tracker::{BlockPromise, OfferState, TrackerClient},
     crypto::{sign::PublicKey, CacheHash, Hashable},
     error::{Error, Result},
+    memory::{MemoryCategory, MemoryReservation},
     protocol::{
-        Block, BlockId, InnerNodes, LeafNodes, MultiBlockPresence, RootNodeFilter, UntrustedProof,
+        Block, BlockId, InnerNodes, LeafNodes, MultiBlockPresence, ProofError, RootNodeFilter,
+        SingleBlockPresence, UntrustedProof, BLOCK_SIZE,
     },
+    replica_tracker::ReplicaTrackerClient,
     repository::{BlockRequestMode, Vault},
     store::{self, ReceiveFilter},
 };
-use std::{pin::pin, sync::Arc, time::Instant};
+use futures_util::TryStreamExt;
+use std::{
+    future, iter,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 use tokio::{
     select,
     sync::{mpsc, Semaphore},
+    time::{self, MissedTickBehavior},
 };
 use tracing::{instrument, Level};
 
 pub(super) struct Client {
     inner: Inner,
     rx: mpsc::Receiver<Response>,
-    send_queue_rx: mpsc::UnboundedReceiver<(PendingRequest, Instant)>,
-    recv_queue_rx: mpsc::Receiver<(PendingResponse, Instant)>,
+    recv_queue_rx: mpsc::Receiver<(PendingResponse, Instant, MemoryReservation)>,
 }
 
 impl Client {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         vault: Vault,
         tx: mpsc::Sender<Content>,
         rx: mpsc::Receiver<Response>,
         peer_request_limiter: Arc<Semaphore>,
+        verifier: Verifier,
+        replicas: Arc<ReplicaTrackerClient>,
+        bulk: bool,
     ) -> Self {
         let pending_requests = PendingRequests::new(vault.monitor.clone());
-        let receive_filter = vault.store().receive_filter();
+        let receive_filter = vault.receive_filter();
         let block_tracker = vault.block_tracker.client();
 
-        // We run the sender in a separate task so we can keep sending requests while we're
-        // processing responses (which sometimes takes a while).
-        let (send_queue_tx, send_queue_rx) = mpsc::unbounded_channel();
+        let max_pending_requests = if bulk {
+            BULK_MAX_PENDING_REQUESTS_PER_CLIENT
+        } else {
+            MAX_PENDING_REQUESTS_PER_CLIENT
+        };
 
-        // We're making sure to not send more requests than MAX_PENDING_REQUESTS_PER_CLIENT, but
-        // there may be some unsolicited responses and also the peer may be malicious and send us
-        // too many responses (so we shoulnd't use unbounded_channel).
-        let (recv_queue_tx, recv_queue_rx) = mpsc::channel(2 * MAX_PENDING_REQUESTS_PER_CLIENT);
+        // We're making sure to not send more requests than `max_pending_requests`, but there may be
+        // some unsolicited responses and also the peer may be malicious and send us too many
+        // responses (so we shoulnd't use unbounded_channel).
+        let (recv_queue_tx, recv_queue_rx) = mpsc::channel(2 * max_pending_requests);
 
         let inner = Inner {
             vault,
             pending_requests,
             peer_request_limiter,
+            verifier,
             receive_filter,
             block_tracker,
+            replicas,
             tx,
-            send_queue_tx,
+            send_queue: RequestQueue::new(),
             recv_queue_tx,
+            max_pending_requests,
+            bulk,
+            blocks_received: AtomicU64::new(0),
+            blocks_receipted: AtomicU64::new(0),
+            storage_full: AtomicBool::new(false),
         };
 
         Self {
             inner,
             rx,
-            send_queue_rx,
             recv_queue_rx,
         }
     }
@@ -73,11 +102,10 @@ impl Client {
         let Self {
             inner,
             rx,
-            send_queue_rx,
             recv_queue_rx,
         } = self;
 
-        inner.run(rx, send_queue_rx, recv_queue_rx).await
+        inner.run(rx, recv_queue_rx).await
     }
 }
 
@@ -85,26 +113,40 @@ struct Inner {
     vault: Vault,
     pending_requests: PendingRequests,
     peer_request_limiter: Arc<Semaphore>,
+    verifier: Verifier,
     receive_filter: ReceiveFilter,
     block_tracker: TrackerClient,
+    replicas: Arc<ReplicaTrackerClient>,
     tx: mpsc::Sender<Content>,
-    send_queue_tx: mpsc::UnboundedSender<(PendingRequest, Instant)>,
-    recv_queue_tx: mpsc::Sender<(PendingResponse, Instant)>,
+    send_queue: RequestQueue,
+    recv_queue_tx: mpsc::Sender<(PendingResponse, Instant, MemoryReservation)>,
+    max_pending_requests: usize,
+    // Whether to send the requests in batches (bulk transfer). Falls back to single requests if the
+    // peer doesn't respond to the batches (see `PendingRequests::batches_supported`).
+    bulk: bool,
+    // Number of blocks received from the peer...
+    blocks_received: AtomicU64,
+    // ...and the number of them at the time we last sent the receipts.
+    blocks_receipted: AtomicU64,
+    // Whether the sending of requests is paused because there is not enough disk space to store
+    // the responses.
+    storage_full: AtomicBool,
 }
 
 impl Inner {
     async fn run(
         &mut self,
         rx: &mut mpsc::Receiver<Response>,
-        send_queue_rx: &mut mpsc::UnboundedReceiver<(PendingRequest, Instant)>,
-        recv_queue_rx: &mut mpsc::Receiver<(PendingResponse, Instant)>,
+        recv_queue_rx: &mut mpsc::Receiver<(PendingResponse, Instant, MemoryReservation)>,
     ) -> Result<()> {
         self.receive_filter.reset().await?;
 
         let mut reload_index_rx = self.vault.store().client_reload_index_tx.subscribe();
         let mut block_offers = self.block_tracker.offers();
 
-        let mut send_requests = pin!(self.send_requests(send_queue_rx));
+        // We run the sender concurrently so we can keep sending requests while we're processing
+        // responses (which sometimes takes a while).
+        let mut send_requests = pin!(self.send_requests());
 
         // NOTE: It is important to keep `remove`ing requests from `pending_requests` in parallel
         // with response handling. It is because response handling may take a long time (e.g. due
@@ -114,11 +156,25 @@ impl Inner {
         let mut enqueue_responses = pin!(self.enqueue_responses(rx));
         let mut handle_responses = pin!(self.handle_responses(recv_queue_rx));
 
+        let mut receipt_interval = time::interval(RECEIPT_INTERVAL);
+        receipt_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
         loop {
             select! {
                 block_offer = block_offers.next() => {
                     let debug = PendingDebugRequest::start();
-                    self.enqueue_request(PendingRequest::Block(block_offer, debug));
+
+                    if self.bulk && self.pending_requests.batches_supported() {
+                        let mut batch = vec![block_offer];
+                        batch.extend(
+                            iter::from_fn(|| block_offers.try_next())
+                                .take(BULK_BLOCK_BATCH_SIZE - 1),
+                        );
+
+                        self.enqueue_request(PendingRequest::Blocks(batch, debug));
+                    } else {
+                        self.enqueue_request(PendingRequest::Block(block_offer, debug));
+                    }
                 }
                 _ = &mut send_requests => break,
                 _ = &mut enqueue_responses => break,
@@ -129,6 +185,9 @@ impl Inner {
                 result = reload_index_rx.changed(), if !reload_index_rx.is_closed() => {
                     self.refresh_branches(result.ok().into_iter().flatten());
                 }
+                _ = receipt_interval.tick() => {
+                    self.send_receipts().await?;
+                }
             }
         }
 
@@ -136,20 +195,17 @@ impl Inner {
     }
 
     fn enqueue_request(&self, request: PendingRequest) {
-        self.send_queue_tx.send((request, Instant::now())).ok();
+        self.send_queue.push(request);
     }
 
-    async fn send_requests(
-        &self,
-        send_queue_rx: &mut mpsc::UnboundedReceiver<(PendingRequest, Instant)>,
-    ) {
+    async fn send_requests(&self) {
         // Limits requests per link (peer + repo)
-        let link_request_limiter = Arc::new(Semaphore::new(MAX_PENDING_REQUESTS_PER_CLIENT));
+        let link_request_limiter = Arc::new(Semaphore::new(self.max_pending_requests));
 
         loop {
-            let Some((request, timestamp)) = send_queue_rx.recv().await else {
-                break;
-            };
+            self.wait_for_disk_space().await;
+
+            let (mut request, timestamp, window_permit) = self.send_queue.pop().await;
 
             // Unwraps OK because we never `close()` the semaphores.
             //
@@ -165,14 +221,33 @@ impl Inner {
                 .await
                 .unwrap();
 
+            let mut permits = vec![(link_permit, peer_permit)];
+
+            // A batch is sent with as many of its requests as there are permits available right
+            // now. Waiting for all of them could starve the other clients of this peer. The rest
+            // of the batch goes back to the queue.
+            while permits.len() < request.response_count() {
+                let Ok(link_permit) = link_request_limiter.clone().try_acquire_owned() else {
+                    break;
+                };
+
+                let Ok(peer_permit) = self.peer_request_limiter.clone().try_acquire_owned() else {
+                    break;
+                };
+
+                permits.push((link_permit, peer_permit));
+            }
+
+            if let Some(rest) = request.split_off(permits.len()) {
+                self.enqueue_request(rest);
+            }
+
             self.vault
                 .monitor
                 .request_queue_time
                 .record(timestamp.elapsed());
 
-            let Some(request) = self
-                .pending_requests
-                .insert(request, link_permit, peer_permit)
+            let Some(request) = self.pending_requests.insert(request, permits, window_permit)
             else {
                 // The same request is already in-flight.
                 continue;
@@ -195,11 +270,25 @@ impl Inner {
             // processed concurrently. Consider using a separate queue and a separate `select`
             // branch for it to speed things up.
 
+            // Hashing the received nodes and blocks is CPU intensive so it's done on the verifier
+            // pool. This also throttles the receiving when the pool can't keep up.
+            let response = self
+                .verifier
+                .run(move || ProcessedResponse::from(response))
+                .await;
             let response = self.pending_requests.remove(response);
 
+            // Account the response until it's been processed.
+            let mut memory = self
+                .vault
+                .store()
+                .memory_budget()
+                .reserve(MemoryCategory::MessageQueue);
+            memory.set(response.response.estimated_size());
+
             if self
                 .recv_queue_tx
-                .send((response, Instant::now()))
+                .send((response, Instant::now(), memory))
                 .await
                 .is_err()
             {
@@ -210,18 +299,28 @@ impl Inner {
 
     async fn handle_responses(
         &self,
-        recv_queue_rx: &mut mpsc::Receiver<(PendingResponse, Instant)>,
+        recv_queue_rx: &mut mpsc::Receiver<(PendingResponse, Instant, MemoryReservation)>,
     ) -> Result<()> {
         loop {
             match recv_queue_rx.recv().await {
-                Some((response, timestamp)) => {
+                Some((response, timestamp, _memory)) => {
                     self.vault
                         .monitor
                         .response_queue_time
                         .record(timestamp.elapsed());
 
                     let start = Instant::now();
-                    self.handle_response(response).await?;
+
+                    match self.handle_response(response).await {
+                        Ok(()) => (),
+                        Err(Error::StorageFull) => {
+                            // Keep the link but stop requesting more until some space is freed.
+                            // The dropped response is requested again once the requests resume.
+                            self.storage_full.store(true, Ordering::Relaxed);
+                        }
+                        Err(error) => return Err(error),
+                    }
+
                     self.vault
                         .monitor
                         .response_handle_time
@@ -239,7 +338,7 @@ impl Inner {
             }
 
             ProcessedResponse::InnerNodes(nodes, _, debug) => {
-                self.handle_inner_nodes(nodes, debug).await
+                self.handle_inner_nodes(nodes, response.depth, debug).await
             }
             ProcessedResponse::LeafNodes(nodes, _, debug) => {
                 self.handle_leaf_nodes(nodes, debug).await
@@ -276,12 +375,30 @@ impl Inner {
         debug_payload: DebugResponse,
     ) -> Result<()> {
         let hash = proof.hash;
-        let status = self.vault.receive_root_node(proof, block_presence).await?;
+
+        let repository_id = *self.vault.repository_id();
+        let proof = match self
+            .verifier
+            .run(move || proof.verify(&repository_id))
+            .await
+        {
+            Ok(proof) => proof,
+            Err(ProofError(proof)) => {
+                tracing::trace!(branch_id = ?proof.writer_id, hash = ?proof.hash, "Invalid proof");
+                return Ok(());
+            }
+        };
+
+        let status = self
+            .vault
+            .receive_verified_root_node(proof, block_presence)
+            .await?;
 
         if status.request_children {
             self.enqueue_request(PendingRequest::ChildNodes(
                 hash,
                 ResponseDisambiguator::new(block_presence),
+                0,
                 debug_payload.follow_up(),
             ));
         }
@@ -303,6 +420,7 @@ impl Inner {
     async fn handle_inner_nodes(
         &self,
         nodes: CacheHash<InnerNodes>,
+        depth: u8,
         debug_payload: DebugResponse,
     ) -> Result<()> {
         let total = nodes.len();
@@ -326,12 +444,33 @@ impl Inner {
                 .collect::<Vec<_>>()
         );
 
-        for node in status.request_children {
-            self.enqueue_request(PendingRequest::ChildNodes(
-                node.hash,
-                ResponseDisambiguator::new(node.summary.block_presence),
-                debug.clone(),
-            ));
+        // With bulk transfer the children of all the nodes that differ from ours are requested in
+        // batches instead of one request per node.
+        if self.bulk && self.pending_requests.batches_supported() {
+            for chunk in status.request_children.chunks(BULK_CHILD_NODES_BATCH_SIZE) {
+                self.enqueue_request(PendingRequest::ChildNodesBatch(
+                    chunk
+                        .iter()
+                        .map(|node| {
+                            (
+                                node.hash,
+                                ResponseDisambiguator::new(node.summary.block_presence),
+                            )
+                        })
+                        .collect(),
+                    depth.saturating_add(1),
+                    debug.clone(),
+                ));
+            }
+        } else {
+            for node in status.request_children {
+                self.enqueue_request(PendingRequest::ChildNodes(
+                    node.hash,
+                    ResponseDisambiguator::new(node.summary.block_presence),
+                    depth.saturating_add(1),
+                    debug.clone(),
+                ));
+            }
         }
 
         if quota.is_some() {
@@ -353,6 +492,16 @@ impl Inner {
         debug_payload: DebugResponse,
     ) -> Result<()> {
         let total = nodes.len();
+
+        for node in nodes.iter() {
+            match node.block_presence {
+                SingleBlockPresence::Present => self.replicas.insert(node.block_id),
+                SingleBlockPresence::Missing | SingleBlockPresence::Expired => {
+                    self.replicas.remove(&node.block_id)
+                }
+            }
+        }
+
         let quota = self.vault.quota().await?.map(Into::into);
         let status = self.vault.receive_leaf_nodes(nodes, quota).await?;
 
@@ -407,6 +556,9 @@ impl Inner {
         block_id: BlockId,
         debug_payload: DebugResponse,
     ) -> Result<()> {
+        // The peer offers only the blocks it has.
+        self.replicas.insert(block_id);
+
         let Some(offer_state) = self.vault.offer_state(&block_id).await? else {
             return Ok(());
         };
@@ -437,9 +589,22 @@ impl Inner {
         tracing::trace!("Received block");
 
         match self.vault.receive_block(&block, block_promise).await {
+            Ok(()) => {
+                self.vault.contribution.record_downloaded(BLOCK_SIZE as u64);
+                self.blocks_received.fetch_add(1, Ordering::Relaxed);
+
+                // Some offers were discarded because the peer offered more blocks than we track
+                // per client. Now that there is room again, request all the branches again so
+                // the discarded blocks get offered once more.
+                if self.block_tracker.take_overflowed() {
+                    self.refresh_branches(self.load_branch_ids().await?);
+                }
+
+                Ok(())
+            }
             // Ignore `BlockNotReferenced` errors as they only mean that the block is no longer
             // needed.
-            Ok(()) | Err(Error::Store(store::Error::BlockNotReferenced)) => Ok(()),
+            Err(Error::Store(store::Error::BlockNotReferenced)) => Ok(()),
             Err(error) => Err(error),
         }
     }
@@ -456,6 +621,48 @@ impl Inner {
             .await
     }
 
+    /// If the sending of requests is paused because the storage is full, waits until enough disk
+    /// space is freed and then requests all the branches again, to re-request what was dropped in
+    /// the meantime.
+    async fn wait_for_disk_space(&self) {
+        if !self.storage_full.load(Ordering::Relaxed) {
+            return;
+        }
+
+        tracing::debug!("Requests paused, storage full");
+
+        let mut interval = time::interval(STORAGE_FULL_RECHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if self.vault.check_disk_space().is_ok() {
+                break;
+            }
+        }
+
+        tracing::debug!("Requests resumed");
+
+        self.storage_full.store(false, Ordering::Relaxed);
+
+        match self.load_branch_ids().await {
+            Ok(branches) => self.refresh_branches(branches),
+            Err(error) => tracing::error!(?error, "Failed to load branches"),
+        }
+    }
+
+    async fn load_branch_ids(&self) -> Result<Vec<PublicKey>> {
+        Ok(self
+            .vault
+            .store()
+            .acquire_read()
+            .await?
+            .load_root_nodes()
+            .map_ok(|node| node.proof.writer_id)
+            .try_collect()
+            .await?)
+    }
+
     // Request again the branches that became completed. This is to cover the following edge
     // case:
     //
@@ -482,6 +689,45 @@ impl Inner {
         }
     }
 
+    // Send receipts for all the complete snapshots (those that have all their blocks) to the peer,
+    // but only if we received any new blocks from them since the last time.
+    async fn send_receipts(&self) -> Result<()> {
+        let block_count = self.blocks_received.load(Ordering::Relaxed);
+
+        if block_count == self.blocks_receipted.swap(block_count, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let root_nodes: Vec<_> = self
+            .vault
+            .store()
+            .acquire_read()
+            .await?
+            .load_root_nodes()
+            .try_filter(|node| {
+                future::ready(node.summary.block_presence == MultiBlockPresence::Full)
+            })
+            .try_collect()
+            .await?;
+
+        for root_node in root_nodes {
+            let receipt = MirrorReceipt::new(
+                &self.vault.receipt_keys,
+                self.vault.repository_id(),
+                root_node.proof.writer_id,
+                root_node.proof.hash,
+                block_count,
+            );
+
+            self.tx
+                .send(Content::Request(Request::Receipt(receipt)))
+                .await
+                .unwrap_or(());
+        }
+
+        Ok(())
+    }
+
     /// Log new approved snapshots
     async fn log_approved(&self, branches: &[PublicKey]) {
         if !tracing::enabled!(Level::DEBUG) {
@@ -1,6 +1,6 @@
 use super::{
-    peer_addr::PeerAddr, peer_info::PeerInfo, peer_source::PeerSource, peer_state::PeerState,
-    runtime_id::PublicRuntimeId,
+    connection_stats::ConnectionType, peer_addr::PeerAddr, peer_info::PeerInfo,
+    peer_source::PeerSource, peer_state::PeerState, runtime_id::PublicRuntimeId,
 };
 use crate::collections::{hash_map::Entry, HashMap};
 use deadlock::BlockingMutex;
@@ -8,7 +8,7 @@ use serde::Serialize;
 use std::{
     fmt,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicU32, AtomicU64, Ordering},
         Arc,
     },
 };
@@ -64,6 +64,8 @@ impl ConnectionDeduplicator {
                     id,
                     state: PeerState::Known,
                     source,
+                    connection_type: None,
+                    secure_links: Arc::default(),
                     on_release: on_release_tx,
                 });
                 self.on_change_tx.send(()).unwrap_or(());
@@ -105,7 +107,7 @@ impl ConnectionDeduplicator {
         connections
             .get(&incoming)
             .or_else(|| connections.get(&outgoing))
-            .map(|peer| PeerInfo::new(addr, peer.source, peer.state))
+            .map(|peer| peer.info(addr))
     }
 
     pub fn on_change(&self) -> uninitialized_watch::Receiver<()> {
@@ -128,7 +130,7 @@ impl PeerInfoCollector {
             .lock()
             .unwrap()
             .iter()
-            .map(|(key, peer)| PeerInfo::new(key.addr, peer.source, peer.state))
+            .map(|(key, peer)| peer.info(key.addr))
             .collect()
     }
 }
@@ -137,9 +139,50 @@ pub(super) struct Peer {
     id: PermitId,
     state: PeerState,
     source: PeerSource,
+    connection_type: Option<ConnectionType>,
+    secure_links: Arc<AtomicU32>,
     on_release: DropAwaitable,
 }
 
+impl Peer {
+    fn info(&self, addr: PeerAddr) -> PeerInfo {
+        PeerInfo::new(
+            addr,
+            self.source,
+            self.state,
+            self.connection_type,
+            self.secure_links.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Counts the repository links currently established with a peer. Shared by all the connections to
+/// the same peer.
+#[derive(Clone)]
+pub(super) struct LinkCounter {
+    count: Arc<AtomicU32>,
+    on_deduplicator_change: Arc<uninitialized_watch::Sender<()>>,
+}
+
+impl LinkCounter {
+    /// Increments the counter. It gets decremented again when the returned guard is dropped.
+    pub fn increment(&self) -> LinkCounterGuard {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.on_deduplicator_change.send(()).unwrap_or(());
+
+        LinkCounterGuard(self.clone())
+    }
+}
+
+pub(super) struct LinkCounterGuard(LinkCounter);
+
+impl Drop for LinkCounterGuard {
+    fn drop(&mut self) {
+        self.0.count.fetch_sub(1, Ordering::Relaxed);
+        self.0.on_deduplicator_change.send(()).unwrap_or(());
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize)]
 pub(super) enum ConnectionDirection {
     Incoming,
@@ -197,6 +240,37 @@ impl ConnectionPermit {
         self.set_state(PeerState::Active(runtime_id));
     }
 
+    pub fn set_connection_type(&self, connection_type: ConnectionType) {
+        let mut lock = self.connections.lock().unwrap();
+
+        // unwrap is ok because if `self` exists then the entry should exists as well.
+        let peer = lock.get_mut(&self.info).unwrap();
+
+        if peer.connection_type != Some(connection_type) {
+            peer.connection_type = Some(connection_type);
+            self.on_deduplicator_change.send(()).unwrap_or(());
+        }
+    }
+
+    /// Creates a new link counter not yet associated with any connection.
+    pub fn new_link_counter(&self) -> LinkCounter {
+        LinkCounter {
+            count: Arc::default(),
+            on_deduplicator_change: self.on_deduplicator_change.clone(),
+        }
+    }
+
+    /// Associates the link counter with the connection of this permit.
+    pub fn set_link_counter(&self, counter: &LinkCounter) {
+        let mut lock = self.connections.lock().unwrap();
+
+        // unwrap is ok because if `self` exists then the entry should exists as well.
+        let peer = lock.get_mut(&self.info).unwrap();
+        peer.secure_links = counter.count.clone();
+
+        self.on_deduplicator_change.send(()).unwrap_or(());
+    }
+
     fn set_state(&self, new_state: PeerState) {
         let mut lock = self.connections.lock().unwrap();
 
@@ -277,6 +351,10 @@ impl ConnectionPermitHalf {
     pub fn id(&self) -> PermitId {
         self.0.id
     }
+
+    pub fn addr(&self) -> PeerAddr {
+        self.0.addr()
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -0,0 +1,236 @@
+use deadlock::BlockingMutex;
+use net::stun::NatBehavior;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use serde::{Deserialize, Serialize};
+use state_monitor::{MonitoredValue, StateMonitor};
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+/// How the connection to a peer was established.
+#[derive(
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    Serialize,
+    Deserialize,
+    Debug,
+    IntoPrimitive,
+    TryFromPrimitive,
+)]
+#[repr(u8)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum ConnectionType {
+    /// The peer connected to us or we connected to the peer on the first attempt.
+    Direct,
+    /// We connected to the peer only after punching a hole through the NAT.
+    HolePunched,
+    /// The connection goes through a relay. Relays are not supported yet so this is currently
+    /// never reported.
+    Relayed,
+}
+
+/// Outcomes of the outgoing connection attempts.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct ConnectionOutcomes {
+    pub direct: u64,
+    pub hole_punched: u64,
+    pub relayed: u64,
+    /// Attempts that were given up because the peer stopped being seen before we managed to
+    /// connect to it.
+    pub failed: u64,
+}
+
+impl ConnectionOutcomes {
+    pub fn attempts(&self) -> u64 {
+        self.direct + self.hole_punched + self.relayed + self.failed
+    }
+
+    /// Ratio of the successful attempts to all attempts or `None` if there were no attempts.
+    pub fn success_rate(&self) -> Option<f64> {
+        match self.attempts() {
+            0 => None,
+            attempts => Some((attempts - self.failed) as f64 / attempts as f64),
+        }
+    }
+
+    fn record(&mut self, outcome: Option<ConnectionType>) {
+        match outcome {
+            Some(ConnectionType::Direct) => self.direct += 1,
+            Some(ConnectionType::HolePunched) => self.hole_punched += 1,
+            Some(ConnectionType::Relayed) => self.relayed += 1,
+            None => self.failed += 1,
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.direct += other.direct;
+        self.hole_punched += other.hole_punched;
+        self.relayed += other.relayed;
+        self.failed += other.failed;
+    }
+}
+
+/// Outcomes of the outgoing connection attempts aggregated per the behavior of the NAT we were
+/// behind at the time of the attempt (as reported by `Network::nat_behavior`, or "unknown").
+#[derive(Clone, Default, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct ConnectionStats(pub BTreeMap<String, ConnectionOutcomes>);
+
+/// Collects the `ConnectionStats` and reports them to the state monitor.
+#[derive(Clone)]
+pub(super) struct ConnectionStatsTracker {
+    inner: Arc<BlockingMutex<Inner>>,
+}
+
+impl ConnectionStatsTracker {
+    pub fn new(monitor: StateMonitor) -> Self {
+        let nat_monitor = monitor.make_value("NAT behavior", nat_name(None));
+
+        Self {
+            inner: Arc::new(BlockingMutex::new(Inner {
+                nat: None,
+                stats: ConnectionStats::default(),
+                monitor,
+                nat_monitor,
+                outcome_monitors: BTreeMap::new(),
+            })),
+        }
+    }
+
+    pub fn set_nat_behavior(&self, nat: Option<NatBehavior>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.nat = nat;
+        *inner.nat_monitor.get() = nat_name(nat);
+    }
+
+    /// Records the outcome of an outgoing connection attempt. `None` means failure.
+    pub fn record(&self, outcome: Option<ConnectionType>) {
+        let mut inner = self.inner.lock().unwrap();
+        let key = nat_name(inner.nat);
+
+        inner
+            .stats
+            .0
+            .entry(key.to_owned())
+            .or_default()
+            .record(outcome);
+        inner.publish(key);
+    }
+
+    pub fn get(&self) -> ConnectionStats {
+        self.inner.lock().unwrap().stats.clone()
+    }
+
+    /// Adds the previously collected stats (e.g., loaded from a previous run) to the current ones.
+    pub fn merge(&self, stats: &ConnectionStats) {
+        let mut inner = self.inner.lock().unwrap();
+
+        for (key, outcomes) in &stats.0 {
+            inner
+                .stats
+                .0
+                .entry(key.clone())
+                .or_default()
+                .merge(outcomes);
+            inner.publish(key);
+        }
+    }
+}
+
+struct Inner {
+    nat: Option<NatBehavior>,
+    stats: ConnectionStats,
+    monitor: StateMonitor,
+    nat_monitor: MonitoredValue<&'static str>,
+    outcome_monitors: BTreeMap<String, OutcomeMonitors>,
+}
+
+impl Inner {
+    fn publish(&mut self, key: &str) {
+        let Some(outcomes) = self.stats.0.get(key).copied() else {
+            return;
+        };
+
+        let monitors = self
+            .outcome_monitors
+            .entry(key.to_owned())
+            .or_insert_with(|| OutcomeMonitors::new(&self.monitor.make_child(key)));
+
+        *monitors.direct.get() = outcomes.direct;
+        *monitors.hole_punched.get() = outcomes.hole_punched;
+        *monitors.relayed.get() = outcomes.relayed;
+        *monitors.failed.get() = outcomes.failed;
+        *monitors.success_rate.get() = Percent(outcomes.success_rate());
+    }
+}
+
+struct OutcomeMonitors {
+    direct: MonitoredValue<u64>,
+    hole_punched: MonitoredValue<u64>,
+    relayed: MonitoredValue<u64>,
+    failed: MonitoredValue<u64>,
+    success_rate: MonitoredValue<Percent>,
+}
+
+impl OutcomeMonitors {
+    fn new(monitor: &StateMonitor) -> Self {
+        Self {
+            direct: monitor.make_value("direct", 0),
+            hole_punched: monitor.make_value("hole punched", 0),
+            relayed: monitor.make_value("relayed", 0),
+            failed: monitor.make_value("failed", 0),
+            success_rate: monitor.make_value("success rate", Percent(None)),
+        }
+    }
+}
+
+// Wrapper to display the success rate in human readable format in the state monitor.
+struct Percent(Option<f64>);
+
+impl fmt::Debug for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{:.1}%", value * 100.0),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+fn nat_name(nat: Option<NatBehavior>) -> &'static str {
+    match nat {
+        Some(NatBehavior::EndpointIndependent) => "endpoint independent",
+        Some(NatBehavior::AddressDependent) => "address dependent",
+        Some(NatBehavior::AddressAndPortDependent) => "address and port dependent",
+        None => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_merge() {
+        let tracker = ConnectionStatsTracker::new(StateMonitor::make_root());
+
+        tracker.record(Some(ConnectionType::Direct));
+        tracker.set_nat_behavior(Some(NatBehavior::AddressAndPortDependent));
+        tracker.record(Some(ConnectionType::HolePunched));
+        tracker.record(None);
+
+        let stats = tracker.get();
+        assert_eq!(stats.0["unknown"].direct, 1);
+
+        let outcomes = stats.0["address and port dependent"];
+        assert_eq!(outcomes.hole_punched, 1);
+        assert_eq!(outcomes.failed, 1);
+        assert_eq!(outcomes.success_rate(), Some(0.5));
+
+        let other = ConnectionStatsTracker::new(StateMonitor::make_root());
+        other.merge(&stats);
+        other.merge(&stats);
+        assert_eq!(other.get().0["unknown"].direct, 2);
+    }
+}
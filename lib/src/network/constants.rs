@@ -16,3 +16,37 @@ pub(super) const MAX_IN_FLIGHT_REQUESTS_PER_PEER: usize = 32;
 /// hasn't yet been processed (although it may have been received).
 /// NOTE: This limit is protecting us against being overhelmed by too many responses from the peer.
 pub(super) const MAX_PENDING_REQUESTS_PER_CLIENT: usize = 2 * MAX_IN_FLIGHT_REQUESTS_PER_PEER;
+
+/// Maximum number of child nodes requests on a given `Client` that have been sent but whose response
+/// hasn't yet been processed. This is the window within which the subtrees of the index are
+/// traversed concurrently.
+pub(super) const CHILD_NODES_REQUEST_WINDOW: usize = MAX_IN_FLIGHT_REQUESTS_PER_PEER;
+
+/// `MAX_IN_FLIGHT_REQUESTS_PER_PEER` for the peers with bulk transfer enabled (see
+/// `Network::set_lan_bulk_transfer_enabled`). Those are on the local network where the round trip
+/// is short and the bandwidth high, so the more requests in flight the better.
+pub(super) const BULK_MAX_IN_FLIGHT_REQUESTS_PER_PEER: usize = 8 * MAX_IN_FLIGHT_REQUESTS_PER_PEER;
+
+/// `MAX_PENDING_REQUESTS_PER_CLIENT` for the peers with bulk transfer enabled.
+pub(super) const BULK_MAX_PENDING_REQUESTS_PER_CLIENT: usize =
+    2 * BULK_MAX_IN_FLIGHT_REQUESTS_PER_PEER;
+
+/// Maximum number of blocks requested in a single message from a peer with bulk transfer enabled.
+/// Batches with more blocks are rejected.
+pub(super) const BULK_BLOCK_BATCH_SIZE: usize = 32;
+
+/// Maximum number of nodes whose children are requested in a single message from a peer with bulk
+/// transfer enabled. Batches with more nodes are rejected.
+pub(super) const BULK_CHILD_NODES_BATCH_SIZE: usize = 32;
+
+// How often to offer the blocks that only we hold ("at risk" blocks) to a peer.
+pub(super) const AT_RISK_OFFER_INTERVAL: Duration = Duration::from_secs(30);
+// Max number of at risk blocks to offer to a peer at once.
+pub(super) const MAX_AT_RISK_OFFERS: usize = 32;
+
+// How often to send mirror receipts to a peer we downloaded blocks from.
+pub(super) const RECEIPT_INTERVAL: Duration = Duration::from_secs(60);
+
+// How often to check whether enough disk space has been freed when the requests to a peer are
+// paused because the storage is full.
+pub(super) const STORAGE_FULL_RECHECK_INTERVAL: Duration = Duration::from_secs(5);
@@ -6,8 +6,20 @@
 //! Using the salted hash of the secret repository id as the pre-shared key. This way only the
 //! replicas that posses the secret repository id are able to communicate and no authentication
 //! based on the identity of the replicas is needed.
+//!
+//! The session keys are periodically rekeyed (see [`RekeyPolicy`]) so that long-lived links don't
+//! encrypt an unbounded amount of data with the same key. The sender announces a rekey by sending
+//! an empty message and then both sides rekey their respective cipher states as described in the
+//! Noise specification (section 11.3). Regular messages are never empty so the announcement can't
+//! be confused with them. Rekeying doesn't reset the nonces, so when they are exhausted the link
+//! needs a new handshake (see [`SendError::Exhausted`] and [`RecvError::Exhausted`]).
+//!
+//! The handshake is bound to the message channel (used as the Noise prologue) so a handshake
+//! message recorded on one channel can't be replayed on another one. Replays on the same channel
+//! are rejected by [`ReplayGuard`].
 
 use super::{
+    message::MessageChannelId,
     message_dispatcher::{ChannelClosed, ContentSink, ContentStream, ContentStreamError},
     runtime_id::PublicRuntimeId,
     traffic_tracker::TrafficTracker,
@@ -15,8 +27,12 @@ use super::{
 use crate::repository::RepositoryId;
 use noise_protocol::Cipher as _;
 use noise_rust_crypto::{Blake2s, ChaCha20Poly1305, X25519};
-use std::mem;
+use std::{collections::VecDeque, mem};
 use thiserror::Error;
+use tokio::time::{Duration, Instant};
+
+/// Name of the protocol used to encrypt the repository links.
+pub(super) const PROTOCOL_NAME: &str = "Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s";
 
 type Cipher = ChaCha20Poly1305;
 type CipherState = noise_protocol::CipherState<Cipher>;
@@ -57,41 +73,125 @@ impl Role {
 }
 
 // This also determines the maximum number of messages we can send or receive in a single protocol
-// session.
+// session. Rekeying doesn't reset the nonce, so when this is reached the link needs to be
+// re-established with a new handshake.
 const MAX_NONCE: u64 = u64::MAX - 1;
 
+// Every handshake message starts with the ephemeral public key of its sender.
+const EPHEMERAL_KEY_LEN: usize = 32;
+// Number of the most recent handshake messages from the peer remembered by `ReplayGuard`.
+const REPLAY_GUARD_CAPACITY: usize = 64;
+
+// Rekey the outgoing session key after this many bytes have been encrypted with it...
+const REKEY_AFTER_BYTES: u64 = 512 * 1024 * 1024;
+// ...or after it's been used for this long, whichever comes first.
+const REKEY_AFTER_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// When to rekey the outgoing session key.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct RekeyPolicy {
+    pub max_bytes: u64,
+    pub max_duration: Duration,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: REKEY_AFTER_BYTES,
+            max_duration: REKEY_AFTER_DURATION,
+        }
+    }
+}
+
+/// Rejects the handshake messages that have already been received from the peer. A replayed
+/// message can't lead to an established session (the ephemeral keys are fresh in every
+/// handshake), but rejecting it right away avoids replying to it and doing the key exchange for
+/// nothing. Meant to live as long as the link so it covers all its handshakes.
+#[derive(Default)]
+pub(super) struct ReplayGuard {
+    seen: VecDeque<[u8; EPHEMERAL_KEY_LEN]>,
+}
+
+impl ReplayGuard {
+    /// Records the ephemeral key of the sender of the handshake message. Returns `false` if the
+    /// key has already been seen.
+    fn insert(&mut self, message: &[u8]) -> bool {
+        let Some(key) = message
+            .get(..EPHEMERAL_KEY_LEN)
+            .and_then(|key| <[u8; EPHEMERAL_KEY_LEN]>::try_from(key).ok())
+        else {
+            // Malformed message, the handshake rejects it.
+            return true;
+        };
+
+        if self.seen.contains(&key) {
+            return false;
+        }
+
+        if self.seen.len() >= REPLAY_GUARD_CAPACITY {
+            self.seen.pop_front();
+        }
+
+        self.seen.push_back(key);
+
+        true
+    }
+}
+
 /// Wrapper for [`ContentStream`] that decrypts incoming messages.
 pub(super) struct DecryptingStream<'a> {
     inner: &'a mut ContentStream,
     cipher: CipherState,
     buffer: Vec<u8>,
     tracker: TrafficTracker,
+    rekey_count: u64,
+    max_nonce: u64,
 }
 
 impl DecryptingStream<'_> {
     pub async fn recv(&mut self) -> Result<Vec<u8>, RecvError> {
-        if self.cipher.get_next_n() >= MAX_NONCE {
-            return Err(RecvError::Exhausted);
+        loop {
+            if self.cipher.get_next_n() >= self.max_nonce {
+                return Err(RecvError::Exhausted);
+            }
+
+            let mut content = self.inner.recv().await?;
+
+            let plain_len = content
+                .len()
+                .checked_sub(Cipher::tag_len())
+                .ok_or(RecvError::Crypto)?;
+            self.buffer.resize(plain_len, 0);
+            self.cipher
+                .decrypt_ad(self.inner.channel().as_ref(), &content, &mut self.buffer)
+                .map_err(|_| RecvError::Crypto)?;
+
+            // Record the ciphertext length to account for the encryption overhead, but do it only
+            // after succesfull decryption to avoid including invalid data (e.g., spam).
+            self.tracker.record_recv(content.len() as u64);
+
+            // Empty message announces that the sender rekeyed.
+            if plain_len == 0 {
+                self.cipher.rekey();
+                self.rekey_count += 1;
+                continue;
+            }
+
+            mem::swap(&mut content, &mut self.buffer);
+
+            return Ok(content);
         }
+    }
 
-        let mut content = self.inner.recv().await?;
-
-        let plain_len = content
-            .len()
-            .checked_sub(Cipher::tag_len())
-            .ok_or(RecvError::Crypto)?;
-        self.buffer.resize(plain_len, 0);
-        self.cipher
-            .decrypt_ad(self.inner.channel().as_ref(), &content, &mut self.buffer)
-            .map_err(|_| RecvError::Crypto)?;
-
-        // Record the ciphertext length to account for the encryption overhead, but do it only
-        // after succesfull decryption to avoid including invalid data (e.g., spam).
-        self.tracker.record_recv(content.len() as u64);
-
-        mem::swap(&mut content, &mut self.buffer);
+    /// Number of times the incoming session key has been rekeyed.
+    #[cfg(test)]
+    pub fn rekey_count(&self) -> u64 {
+        self.rekey_count
+    }
 
-        Ok(content)
+    #[cfg(test)]
+    pub fn set_max_nonce(&mut self, max_nonce: u64) {
+        self.max_nonce = max_nonce;
     }
 }
 
@@ -101,11 +201,64 @@ pub(super) struct EncryptingSink<'a> {
     cipher: CipherState,
     buffer: Vec<u8>,
     tracker: TrafficTracker,
+    rekey_policy: RekeyPolicy,
+    rekey_count: u64,
+    // Bytes encrypted and time elapsed since the last rekey (or since the handshake).
+    bytes_since_rekey: u64,
+    last_rekey: Instant,
+    max_nonce: u64,
 }
 
 impl EncryptingSink<'_> {
-    pub async fn send(&mut self, mut content: Vec<u8>) -> Result<(), SendError> {
-        if self.cipher.get_next_n() >= MAX_NONCE {
+    pub async fn send(&mut self, content: Vec<u8>) -> Result<(), SendError> {
+        // Empty messages are reserved for rekey announcements.
+        if content.is_empty() {
+            return Err(SendError::EmptyMessage);
+        }
+
+        if self.bytes_since_rekey >= self.rekey_policy.max_bytes
+            || self.last_rekey.elapsed() >= self.rekey_policy.max_duration
+        {
+            self.rekey().await?;
+        }
+
+        self.bytes_since_rekey += content.len() as u64;
+        self.encrypt_and_send(content).await
+    }
+
+    /// Number of times the outgoing session key has been rekeyed.
+    #[cfg(test)]
+    pub fn rekey_count(&self) -> u64 {
+        self.rekey_count
+    }
+
+    #[cfg(test)]
+    pub fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.rekey_policy = policy;
+    }
+
+    #[cfg(test)]
+    pub fn set_max_nonce(&mut self, max_nonce: u64) {
+        self.max_nonce = max_nonce;
+    }
+
+    async fn rekey(&mut self) -> Result<(), SendError> {
+        // Announce the rekey first so the receiver knows to rekey too before decrypting the next
+        // message.
+        self.encrypt_and_send(Vec::new()).await?;
+
+        self.cipher.rekey();
+        self.rekey_count += 1;
+        self.bytes_since_rekey = 0;
+        self.last_rekey = Instant::now();
+
+        tracing::trace!(count = self.rekey_count, "Outgoing session key rekeyed");
+
+        Ok(())
+    }
+
+    async fn encrypt_and_send(&mut self, mut content: Vec<u8>) -> Result<(), SendError> {
+        if self.cipher.get_next_n() >= self.max_nonce {
             return Err(SendError::Exhausted);
         }
 
@@ -130,13 +283,14 @@ pub(super) async fn establish_channel<'a>(
     stream: &'a mut ContentStream,
     sink: &'a mut ContentSink,
     tracker: TrafficTracker,
+    replay_guard: &mut ReplayGuard,
 ) -> Result<(DecryptingStream<'a>, EncryptingSink<'a>), EstablishError> {
-    let mut handshake_state = build_handshake_state(role, repo_id);
+    let mut handshake_state = build_handshake_state(role, repo_id, stream.channel());
 
     let (recv_cipher, send_cipher) = match role {
         Role::Initiator => {
             handshake_send(&mut handshake_state, sink, &[]).await?;
-            handshake_recv(&mut handshake_state, stream).await?;
+            handshake_recv(&mut handshake_state, stream, replay_guard).await?;
 
             assert!(handshake_state.completed());
 
@@ -144,7 +298,7 @@ pub(super) async fn establish_channel<'a>(
             (recv_cipher, send_cipher)
         }
         Role::Responder => {
-            handshake_recv(&mut handshake_state, stream).await?;
+            handshake_recv(&mut handshake_state, stream, replay_guard).await?;
             handshake_send(&mut handshake_state, sink, &[]).await?;
 
             assert!(handshake_state.completed());
@@ -158,6 +312,8 @@ pub(super) async fn establish_channel<'a>(
         cipher: recv_cipher,
         buffer: vec![],
         tracker: tracker.clone(),
+        rekey_count: 0,
+        max_nonce: MAX_NONCE,
     };
 
     let sink = EncryptingSink {
@@ -165,6 +321,11 @@ pub(super) async fn establish_channel<'a>(
         cipher: send_cipher,
         buffer: vec![],
         tracker,
+        rekey_policy: RekeyPolicy::default(),
+        rekey_count: 0,
+        bytes_since_rekey: 0,
+        last_rekey: Instant::now(),
+        max_nonce: MAX_NONCE,
     };
 
     Ok((stream, sink))
@@ -174,8 +335,11 @@ pub(super) async fn establish_channel<'a>(
 pub(super) enum SendError {
     #[error("channel closed")]
     Closed,
+    /// No more messages can be sent in this session. The link needs a new handshake.
     #[error("nonce counter exhausted")]
     Exhausted,
+    #[error("empty message")]
+    EmptyMessage,
 }
 
 impl From<ChannelClosed> for SendError {
@@ -190,6 +354,7 @@ pub(super) enum RecvError {
     Crypto,
     #[error("channel closed")]
     Closed,
+    /// No more messages can be received in this session. The link needs a new handshake.
     #[error("nonce counter exhausted")]
     Exhausted,
     #[error("network transport changed")]
@@ -209,6 +374,8 @@ impl From<ContentStreamError> for RecvError {
 pub(super) enum EstablishError {
     #[error("encryption / decryption failed")]
     Crypto,
+    #[error("handshake message replayed")]
+    Replay,
     #[error("channel closed")]
     Closed,
     #[error("network transport changed")]
@@ -236,13 +403,17 @@ impl From<ContentStreamError> for EstablishError {
     }
 }
 
-fn build_handshake_state(role: Role, repo_id: &RepositoryId) -> HandshakeState {
+fn build_handshake_state(
+    role: Role,
+    repo_id: &RepositoryId,
+    channel: &MessageChannelId,
+) -> HandshakeState {
     use noise_protocol::patterns;
 
     let mut state = HandshakeState::new(
         patterns::noise_nn_psk0(),
         role == Role::Initiator,
-        [],
+        channel.as_ref(),
         None,
         None,
         None,
@@ -264,7 +435,208 @@ async fn handshake_send(
 async fn handshake_recv(
     state: &mut HandshakeState,
     stream: &mut ContentStream,
+    replay_guard: &mut ReplayGuard,
 ) -> Result<Vec<u8>, EstablishError> {
     let content = stream.recv().await?;
+
+    if !replay_guard.insert(&content) {
+        return Err(EstablishError::Replay);
+    }
+
     Ok(state.read_message_vec(&content)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{
+        connection::ConnectionPermit, keep_alive::KeepAlive, message_dispatcher::MessageDispatcher,
+        raw,
+    };
+    use assert_matches::assert_matches;
+    use futures_util::future;
+    use net::tcp::{TcpListener, TcpStream};
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn rekey_after_bytes() {
+        let (dispatcher_a, dispatcher_b) = setup().await;
+        let repo_id = RepositoryId::random();
+        let channel = MessageChannelId::random();
+
+        let mut stream_a = dispatcher_a.open_recv(channel);
+        let mut sink_a = dispatcher_a.open_send(channel);
+        let mut stream_b = dispatcher_b.open_recv(channel);
+        let mut sink_b = dispatcher_b.open_send(channel);
+
+        let ((_, mut sink), (mut stream, _)) = future::try_join(
+            establish_channel(
+                Role::Initiator,
+                &repo_id,
+                &mut stream_a,
+                &mut sink_a,
+                TrafficTracker::new(),
+                &mut ReplayGuard::default(),
+            ),
+            establish_channel(
+                Role::Responder,
+                &repo_id,
+                &mut stream_b,
+                &mut sink_b,
+                TrafficTracker::new(),
+                &mut ReplayGuard::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        sink.set_rekey_policy(RekeyPolicy {
+            max_bytes: 8,
+            max_duration: Duration::MAX,
+        });
+
+        // 2 * 4 bytes => rekey before the third message.
+        for content in [b"one!", b"two!", b"six!"] {
+            sink.send(content.to_vec()).await.unwrap();
+            assert_eq!(stream.recv().await.unwrap(), content);
+        }
+
+        assert_eq!(sink.rekey_count(), 1);
+        assert_eq!(stream.rekey_count(), 1);
+
+        // Empty messages are rejected (they would be mistaken for rekey announcements).
+        assert_matches!(sink.send(Vec::new()).await, Err(SendError::EmptyMessage));
+        assert_eq!(sink.rekey_count(), 1);
+
+        sink.send(b"ten!".to_vec()).await.unwrap();
+        assert_eq!(stream.recv().await.unwrap(), b"ten!");
+        assert_eq!(stream.rekey_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn rekey_after_duration() {
+        let (dispatcher_a, dispatcher_b) = setup().await;
+        let repo_id = RepositoryId::random();
+        let channel = MessageChannelId::random();
+
+        let mut stream_a = dispatcher_a.open_recv(channel);
+        let mut sink_a = dispatcher_a.open_send(channel);
+        let mut stream_b = dispatcher_b.open_recv(channel);
+        let mut sink_b = dispatcher_b.open_send(channel);
+
+        let ((_, mut sink), (mut stream, _)) = future::try_join(
+            establish_channel(
+                Role::Initiator,
+                &repo_id,
+                &mut stream_a,
+                &mut sink_a,
+                TrafficTracker::new(),
+                &mut ReplayGuard::default(),
+            ),
+            establish_channel(
+                Role::Responder,
+                &repo_id,
+                &mut stream_b,
+                &mut sink_b,
+                TrafficTracker::new(),
+                &mut ReplayGuard::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        // Zero duration => rekey before every message.
+        sink.set_rekey_policy(RekeyPolicy {
+            max_bytes: u64::MAX,
+            max_duration: Duration::ZERO,
+        });
+
+        for content in [b"one", b"two", b"six"] {
+            sink.send(content.to_vec()).await.unwrap();
+            assert_eq!(stream.recv().await.unwrap(), content);
+        }
+
+        assert_eq!(sink.rekey_count(), 3);
+        assert_eq!(stream.rekey_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn nonce_exhausted() {
+        let (dispatcher_a, dispatcher_b) = setup().await;
+        let repo_id = RepositoryId::random();
+        let channel = MessageChannelId::random();
+
+        let mut stream_a = dispatcher_a.open_recv(channel);
+        let mut sink_a = dispatcher_a.open_send(channel);
+        let mut stream_b = dispatcher_b.open_recv(channel);
+        let mut sink_b = dispatcher_b.open_send(channel);
+
+        let ((_, mut sink), (mut stream, _)) = future::try_join(
+            establish_channel(
+                Role::Initiator,
+                &repo_id,
+                &mut stream_a,
+                &mut sink_a,
+                TrafficTracker::new(),
+                &mut ReplayGuard::default(),
+            ),
+            establish_channel(
+                Role::Responder,
+                &repo_id,
+                &mut stream_b,
+                &mut sink_b,
+                TrafficTracker::new(),
+                &mut ReplayGuard::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        sink.set_max_nonce(2);
+        stream.set_max_nonce(2);
+
+        for content in [b"one", b"two"] {
+            sink.send(content.to_vec()).await.unwrap();
+            assert_eq!(stream.recv().await.unwrap(), content);
+        }
+
+        assert_matches!(sink.send(b"six".to_vec()).await, Err(SendError::Exhausted));
+        assert_matches!(stream.recv().await, Err(RecvError::Exhausted));
+    }
+
+    #[test]
+    fn replay_guard() {
+        let mut guard = ReplayGuard::default();
+        let message_a = [1; EPHEMERAL_KEY_LEN + 16];
+        let message_b = [2; EPHEMERAL_KEY_LEN + 16];
+
+        assert!(guard.insert(&message_a));
+        assert!(guard.insert(&message_b));
+        assert!(!guard.insert(&message_a));
+
+        // Only the most recent ones are remembered.
+        for i in 0..REPLAY_GUARD_CAPACITY {
+            guard.insert(&[(i + 3) as u8; EPHEMERAL_KEY_LEN]);
+        }
+
+        assert!(guard.insert(&message_a));
+    }
+
+    async fn setup() -> (MessageDispatcher, MessageDispatcher) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0u16))
+            .await
+            .unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let client_dispatcher = MessageDispatcher::new(KeepAlive::default());
+        client_dispatcher.bind(raw::Stream::Tcp(client), ConnectionPermit::dummy());
+
+        let server_dispatcher = MessageDispatcher::new(KeepAlive::default());
+        server_dispatcher.bind(raw::Stream::Tcp(server), ConnectionPermit::dummy());
+
+        (client_dispatcher, server_dispatcher)
+    }
+}
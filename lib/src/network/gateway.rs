@@ -1,4 +1,12 @@
-use super::{ip, peer_addr::PeerAddr, peer_source::PeerSource, raw, seen_peers::SeenPeer};
+use super::{
+    connection_stats::{ConnectionStatsTracker, ConnectionType},
+    ip,
+    keep_alive::KeepAlive,
+    peer_addr::{self, PeerAddr},
+    peer_source::PeerSource,
+    raw,
+    seen_peers::SeenPeer,
+};
 use crate::sync::atomic_slot::AtomicSlot;
 use backoff::{backoff::Backoff, ExponentialBackoffBuilder};
 use net::{
@@ -6,62 +14,136 @@ use net::{
     tcp::{TcpListener, TcpStream},
 };
 use scoped_task::ScopedJoinHandle;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    fmt::Write as _,
     net::{IpAddr, SocketAddr},
     sync::{Arc, Mutex},
 };
 use thiserror::Error;
 use tokio::{
     select,
-    sync::{mpsc, oneshot},
+    sync::{mpsc, oneshot, Notified, Notify},
     time::{self, Duration},
 };
 use tracing::{field, Instrument, Span};
 
+/// Outcome of binding a single listener.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BindStatus {
+    /// Address the listener was requested to bind to.
+    #[serde(with = "peer_addr::as_str")]
+    pub requested: PeerAddr,
+    /// Address the listener is actually bound to or `None` if the binding failed. Might differ
+    /// from the requested one if the requested port was not available.
+    #[serde(with = "peer_addr::option_as_str")]
+    pub bound: Option<PeerAddr>,
+    /// Why the binding failed or why the requested port couldn't be used.
+    pub error: Option<String>,
+}
+
+impl BindStatus {
+    fn new(requested: PeerAddr, result: Result<PeerAddr, String>) -> Self {
+        match result {
+            Ok(bound) => {
+                let error =
+                    (requested.port() != 0 && bound.port() != requested.port()).then(|| {
+                        format!(
+                            "port {} not available, using {} instead",
+                            requested.port(),
+                            bound.port()
+                        )
+                    });
+
+                Self {
+                    requested,
+                    bound: Some(bound),
+                    error,
+                }
+            }
+            Err(error) => Self {
+                requested,
+                bound: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
 /// Established incoming and outgoing connections.
 pub(super) struct Gateway {
     stacks: AtomicSlot<Stacks>,
     incoming_tx: mpsc::Sender<(raw::Stream, PeerAddr)>,
+    keep_alive: KeepAlive,
+    bind_status: Mutex<Vec<BindStatus>>,
+    network_change: Notify,
+    connection_stats: ConnectionStatsTracker,
 }
 
 impl Gateway {
     /// Create a new `Gateway` that is initially disabled.
     ///
     /// `incoming_tx` is the sender for the incoming connections.
-    pub fn new(incoming_tx: mpsc::Sender<(raw::Stream, PeerAddr)>) -> Self {
+    pub fn new(
+        incoming_tx: mpsc::Sender<(raw::Stream, PeerAddr)>,
+        keep_alive: KeepAlive,
+        connection_stats: ConnectionStatsTracker,
+    ) -> Self {
         let stacks = Stacks::unbound();
         let stacks = AtomicSlot::new(stacks);
 
         Self {
             stacks,
             incoming_tx,
+            keep_alive,
+            bind_status: Mutex::new(Vec::new()),
+            network_change: Notify::new(),
+            connection_stats,
         }
     }
 
+    /// Outcome of the last bind, one entry per requested listener.
+    pub fn bind_status(&self) -> Vec<BindStatus> {
+        self.bind_status.lock().unwrap().clone()
+    }
+
     pub fn listener_local_addrs(&self) -> Vec<PeerAddr> {
-        let stacks = self.stacks.read();
-        [
-            stacks
-                .quic_listener_local_addr_v4()
-                .copied()
-                .map(PeerAddr::Quic),
-            stacks
-                .quic_listener_local_addr_v6()
-                .copied()
-                .map(PeerAddr::Quic),
-            stacks
-                .tcp_listener_local_addr_v4()
-                .copied()
-                .map(PeerAddr::Tcp),
-            stacks
-                .tcp_listener_local_addr_v6()
-                .copied()
-                .map(PeerAddr::Tcp),
-        ]
-        .into_iter()
-        .flatten()
-        .collect()
+        self.stacks.read().listener_local_addrs()
+    }
+
+    /// Addresses to bind to again after the network changed: the requested ones, except that the
+    /// ports picked by the OS (when the requested port was zero) are kept.
+    pub fn rebind_addrs(&self) -> Vec<PeerAddr> {
+        self.bind_status
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|status| {
+                let mut addr = status.requested;
+
+                if let (0, Some(bound)) = (addr.port(), status.bound) {
+                    addr.set_port(bound.port());
+                }
+
+                addr
+            })
+            .collect()
+    }
+
+    /// Closes all the listeners and connectors.
+    pub async fn unbind(&self) {
+        self.bind(&StackAddresses::from(&[][..])).await;
+    }
+
+    /// Interrupts the waiting between the connection attempts so they are retried immediately.
+    pub fn notify_network_change(&self) {
+        self.network_change.notify_waiters();
+    }
+
+    /// Completes on the next `notify_network_change`.
+    pub fn on_network_change(&self) -> Notified<'_> {
+        self.network_change.notified()
     }
 
     /// Binds the gateway to the specified addresses. Rebinds if already bound.
@@ -72,25 +154,31 @@ impl Gateway {
         Option<quic::SideChannelMaker>,
         Option<quic::SideChannelMaker>,
     ) {
-        let (next, side_channel_maker_v4, side_channel_maker_v6) =
-            Stacks::bind(bind, self.incoming_tx.clone()).await;
+        let (next, side_channel_maker_v4, side_channel_maker_v6, bind_status) = Stacks::bind(
+            bind,
+            self.incoming_tx.clone(),
+            self.keep_alive.config().quic(),
+        )
+        .await;
+
+        *self.bind_status.lock().unwrap() = bind_status;
 
         let prev = self.stacks.swap(next);
         let next = self.stacks.read();
 
-        if prev.quic_v4.is_some() && next.quic_v4.is_none() {
+        if !prev.quic_v4.is_empty() && next.quic_v4.is_empty() {
             tracing::info!("Terminated IPv4 QUIC stack");
         }
 
-        if prev.quic_v6.is_some() && next.quic_v6.is_none() {
+        if !prev.quic_v6.is_empty() && next.quic_v6.is_empty() {
             tracing::info!("Terminated IPv6 QUIC stack");
         }
 
-        if prev.tcp_v4.is_some() && next.tcp_v4.is_none() {
+        if !prev.tcp_v4.is_empty() && next.tcp_v4.is_empty() {
             tracing::info!("Terminated IPv4 TCP stack");
         }
 
-        if prev.tcp_v6.is_some() && next.tcp_v6.is_none() {
+        if !prev.tcp_v6.is_empty() && next.tcp_v6.is_empty() {
             tracing::info!("Terminated IPv6 TCP stack");
         }
 
@@ -99,11 +187,13 @@ impl Gateway {
         (side_channel_maker_v4, side_channel_maker_v6)
     }
 
+    /// Connects to the given peer, retrying until it succeeds or until the peer stops being
+    /// seen. Returns the connection together with how it was established.
     pub async fn connect_with_retries(
         &self,
         peer: &SeenPeer,
         source: PeerSource,
-    ) -> Option<raw::Stream> {
+    ) -> Option<(raw::Stream, ConnectionType)> {
         if !ok_to_connect(peer.addr_if_seen()?.socket_addr(), source) {
             tracing::debug!("Invalid peer address - discarding");
             return None;
@@ -117,12 +207,19 @@ impl Gateway {
             .build();
 
         let mut hole_punching_task = None;
+        let mut failed = false;
 
         loop {
             // Note: This needs to be probed each time the loop starts. When the `addr` fn returns
             // `None` that means whatever discovery mechanism (LocalDiscovery or DhtDiscovery)
             // found it is no longer seeing it.
-            let addr = *peer.addr_if_seen()?;
+            let Some(addr) = peer.addr_if_seen().copied() else {
+                if failed {
+                    self.connection_stats.record(None);
+                }
+
+                return None;
+            };
 
             // Note: we need to grab fresh stacks on each loop because the network might get
             // re-bound in the meantime which would change the connectors.
@@ -134,7 +231,17 @@ impl Gateway {
 
             match stacks.connect(addr).await {
                 Ok(socket) => {
-                    return Some(socket);
+                    // If the first attempt failed but a later one succeeded while punching holes,
+                    // we assume it was thanks to the hole punching.
+                    let connection_type = if failed && hole_punching_task.is_some() {
+                        ConnectionType::HolePunched
+                    } else {
+                        ConnectionType::Direct
+                    };
+
+                    self.connection_stats.record(Some(connection_type));
+
+                    return Some((socket, connection_type));
                 }
                 Err(error) => {
                     tracing::debug!(?error, "Connection failed");
@@ -144,10 +251,19 @@ impl Gateway {
                         return None;
                     }
 
+                    failed = true;
+
                     match backoff.next_backoff() {
                         Some(duration) => {
                             tracing::debug!("Next connection attempt in {:?}", duration);
-                            time::sleep(duration).await;
+
+                            select! {
+                                _ = time::sleep(duration) => (),
+                                _ = self.on_network_change() => {
+                                    tracing::debug!("Network changed - retrying now");
+                                    backoff.reset();
+                                }
+                            }
                         }
                         // We set max elapsed time to None above.
                         None => unreachable!(),
@@ -183,60 +299,45 @@ impl ConnectError {
     }
 }
 
+// There can be multiple stacks per protocol/family (e.g., when listening on several interfaces).
+// The first one of each family is the primary one: it's used for the outgoing QUIC connections,
+// hole punching and its side channel is shared with the DHT and STUN. The others only accept
+// incoming connections.
 struct Stacks {
-    quic_v4: Option<QuicStack>,
-    quic_v6: Option<QuicStack>,
-    tcp_v4: Option<TcpStack>,
-    tcp_v6: Option<TcpStack>,
+    quic_v4: Vec<QuicStack>,
+    quic_v6: Vec<QuicStack>,
+    tcp_v4: Vec<TcpStack>,
+    tcp_v6: Vec<TcpStack>,
 }
 
 impl Stacks {
     fn unbound() -> Self {
         Self {
-            quic_v4: None,
-            quic_v6: None,
-            tcp_v4: None,
-            tcp_v6: None,
+            quic_v4: Vec::new(),
+            quic_v6: Vec::new(),
+            tcp_v4: Vec::new(),
+            tcp_v6: Vec::new(),
         }
     }
 
     async fn bind(
         bind: &StackAddresses,
         incoming_tx: mpsc::Sender<(raw::Stream, PeerAddr)>,
+        quic_keep_alive: quic::KeepAlive,
     ) -> (
         Self,
         Option<quic::SideChannelMaker>,
         Option<quic::SideChannelMaker>,
+        Vec<BindStatus>,
     ) {
-        let (quic_v4, side_channel_maker_v4) = if let Some(addr) = bind.quic_v4 {
-            QuicStack::new(addr, incoming_tx.clone())
-                .await
-                .map(|(stack, side_channel)| (Some(stack), Some(side_channel)))
-                .unwrap_or((None, None))
-        } else {
-            (None, None)
-        };
+        let mut status = Vec::new();
 
-        let (quic_v6, side_channel_maker_v6) = if let Some(addr) = bind.quic_v6 {
-            QuicStack::new(addr, incoming_tx.clone())
-                .await
-                .map(|(stack, side_channel)| (Some(stack), Some(side_channel)))
-                .unwrap_or((None, None))
-        } else {
-            (None, None)
-        };
-
-        let tcp_v4 = if let Some(addr) = bind.tcp_v4 {
-            TcpStack::new(addr, incoming_tx.clone()).await
-        } else {
-            None
-        };
-
-        let tcp_v6 = if let Some(addr) = bind.tcp_v6 {
-            TcpStack::new(addr, incoming_tx).await
-        } else {
-            None
-        };
+        let (quic_v4, side_channel_maker_v4) =
+            bind_quic(&bind.quic_v4, &incoming_tx, quic_keep_alive, &mut status).await;
+        let (quic_v6, side_channel_maker_v6) =
+            bind_quic(&bind.quic_v6, &incoming_tx, quic_keep_alive, &mut status).await;
+        let tcp_v4 = bind_tcp(&bind.tcp_v4, &incoming_tx, &mut status).await;
+        let tcp_v6 = bind_tcp(&bind.tcp_v6, &incoming_tx, &mut status).await;
 
         let this = Self {
             quic_v4,
@@ -245,36 +346,31 @@ impl Stacks {
             tcp_v6,
         };
 
-        (this, side_channel_maker_v4, side_channel_maker_v6)
+        (this, side_channel_maker_v4, side_channel_maker_v6, status)
     }
 
     fn addresses(&self) -> StackAddresses {
         StackAddresses {
-            quic_v4: self.quic_v4.as_ref().map(|stack| stack.listener_local_addr),
-            quic_v6: self.quic_v6.as_ref().map(|stack| stack.listener_local_addr),
-            tcp_v4: self.tcp_v6.as_ref().map(|stack| stack.listener_local_addr),
-            tcp_v6: self.tcp_v6.as_ref().map(|stack| stack.listener_local_addr),
+            quic_v4: quic_addrs(&self.quic_v4),
+            quic_v6: quic_addrs(&self.quic_v6),
+            tcp_v4: tcp_addrs(&self.tcp_v4),
+            tcp_v6: tcp_addrs(&self.tcp_v6),
         }
     }
 
-    fn quic_listener_local_addr_v4(&self) -> Option<&SocketAddr> {
-        self.quic_v4
-            .as_ref()
-            .map(|stack| &stack.listener_local_addr)
-    }
-
-    fn quic_listener_local_addr_v6(&self) -> Option<&SocketAddr> {
-        self.quic_v6
-            .as_ref()
-            .map(|stack| &stack.listener_local_addr)
-    }
-
-    fn tcp_listener_local_addr_v4(&self) -> Option<&SocketAddr> {
-        self.tcp_v4.as_ref().map(|stack| &stack.listener_local_addr)
-    }
-
-    fn tcp_listener_local_addr_v6(&self) -> Option<&SocketAddr> {
-        self.tcp_v6.as_ref().map(|stack| &stack.listener_local_addr)
+    fn listener_local_addrs(&self) -> Vec<PeerAddr> {
+        let quic = self
+            .quic_v4
+            .iter()
+            .chain(&self.quic_v6)
+            .map(|stack| PeerAddr::Quic(stack.listener_local_addr));
+        let tcp = self
+            .tcp_v4
+            .iter()
+            .chain(&self.tcp_v6)
+            .map(|stack| PeerAddr::Tcp(stack.listener_local_addr));
+
+        quic.chain(tcp).collect()
     }
 
     async fn connect(&self, addr: PeerAddr) -> Result<raw::Stream, ConnectError> {
@@ -353,20 +449,84 @@ impl Stacks {
 
     fn quic_stack_for(&self, ip: &IpAddr) -> Option<&QuicStack> {
         match ip {
-            IpAddr::V4(_) => self.quic_v4.as_ref(),
-            IpAddr::V6(_) => self.quic_v6.as_ref(),
+            IpAddr::V4(_) => self.quic_v4.first(),
+            IpAddr::V6(_) => self.quic_v6.first(),
         }
     }
 
     fn close(&self) {
-        if let Some(stack) = &self.quic_v4 {
+        for stack in self.quic_v4.iter().chain(&self.quic_v6) {
             stack.close();
         }
+    }
+}
 
-        if let Some(stack) = &self.quic_v6 {
-            stack.close();
+async fn bind_quic(
+    addrs: &[SocketAddr],
+    incoming_tx: &mpsc::Sender<(raw::Stream, PeerAddr)>,
+    keep_alive: quic::KeepAlive,
+    status: &mut Vec<BindStatus>,
+) -> (Vec<QuicStack>, Option<quic::SideChannelMaker>) {
+    let mut stacks = Vec::with_capacity(addrs.len());
+    let mut primary_side_channel_maker = None;
+
+    for addr in addrs {
+        match QuicStack::new(*addr, incoming_tx.clone(), keep_alive).await {
+            Ok((stack, side_channel_maker)) => {
+                status.push(BindStatus::new(
+                    PeerAddr::Quic(*addr),
+                    Ok(PeerAddr::Quic(stack.listener_local_addr)),
+                ));
+
+                primary_side_channel_maker.get_or_insert(side_channel_maker);
+                stacks.push(stack);
+            }
+            Err(error) => {
+                status.push(BindStatus::new(PeerAddr::Quic(*addr), Err(error)));
+            }
         }
     }
+
+    (stacks, primary_side_channel_maker)
+}
+
+async fn bind_tcp(
+    addrs: &[SocketAddr],
+    incoming_tx: &mpsc::Sender<(raw::Stream, PeerAddr)>,
+    status: &mut Vec<BindStatus>,
+) -> Vec<TcpStack> {
+    let mut stacks = Vec::with_capacity(addrs.len());
+
+    for addr in addrs {
+        match TcpStack::new(*addr, incoming_tx.clone()).await {
+            Ok(stack) => {
+                status.push(BindStatus::new(
+                    PeerAddr::Tcp(*addr),
+                    Ok(PeerAddr::Tcp(stack.listener_local_addr)),
+                ));
+                stacks.push(stack);
+            }
+            Err(error) => {
+                status.push(BindStatus::new(PeerAddr::Tcp(*addr), Err(error)));
+            }
+        }
+    }
+
+    stacks
+}
+
+fn quic_addrs(stacks: &[QuicStack]) -> Vec<SocketAddr> {
+    stacks
+        .iter()
+        .map(|stack| stack.listener_local_addr)
+        .collect()
+}
+
+fn tcp_addrs(stacks: &[TcpStack]) -> Vec<SocketAddr> {
+    stacks
+        .iter()
+        .map(|stack| stack.listener_local_addr)
+        .collect()
 }
 
 struct QuicStack {
@@ -380,29 +540,31 @@ impl QuicStack {
     async fn new(
         bind_addr: SocketAddr,
         incoming_tx: mpsc::Sender<(raw::Stream, PeerAddr)>,
-    ) -> Option<(Self, quic::SideChannelMaker)> {
+        keep_alive: quic::KeepAlive,
+    ) -> Result<(Self, quic::SideChannelMaker), String> {
         let span = tracing::info_span!("listener", addr = field::Empty);
 
-        let (connector, listener, side_channel_maker) = match quic::configure(bind_addr).await {
-            Ok((connector, listener, side_channel_maker)) => {
-                span.record(
-                    "addr",
-                    field::display(PeerAddr::Quic(*listener.local_addr())),
-                );
-                tracing::info!(parent: &span, "Listener started");
+        let (connector, listener, side_channel_maker) =
+            match quic::configure(bind_addr, keep_alive).await {
+                Ok((connector, listener, side_channel_maker)) => {
+                    span.record(
+                        "addr",
+                        field::display(PeerAddr::Quic(*listener.local_addr())),
+                    );
+                    tracing::info!(parent: &span, "Listener started");
 
-                (connector, listener, side_channel_maker)
-            }
-            Err(error) => {
-                tracing::warn!(
-                    parent: &span,
-                    bind_addr = %PeerAddr::Quic(bind_addr),
-                    ?error,
-                    "Failed to start listener"
-                );
-                return None;
-            }
-        };
+                    (connector, listener, side_channel_maker)
+                }
+                Err(error) => {
+                    tracing::warn!(
+                        parent: &span,
+                        bind_addr = %PeerAddr::Quic(bind_addr),
+                        ?error,
+                        "Failed to start listener"
+                    );
+                    return Err(error_message(&error));
+                }
+            };
 
         let listener_local_addr = *listener.local_addr();
         let listener_task =
@@ -417,7 +579,7 @@ impl QuicStack {
             hole_puncher,
         };
 
-        Some((this, side_channel_maker))
+        Ok((this, side_channel_maker))
     }
 
     fn close(&self) {
@@ -435,7 +597,7 @@ impl TcpStack {
     async fn new(
         bind_addr: SocketAddr,
         incoming_tx: mpsc::Sender<(raw::Stream, PeerAddr)>,
-    ) -> Option<Self> {
+    ) -> Result<Self, String> {
         let span = tracing::info_span!("listener", addr = field::Empty);
 
         let listener = match TcpListener::bind(bind_addr).await {
@@ -447,7 +609,7 @@ impl TcpStack {
                     ?error,
                     "Failed to start listener",
                 );
-                return None;
+                return Err(error_message(&error));
             }
         };
 
@@ -465,20 +627,33 @@ impl TcpStack {
                     ?error,
                     "Failed to get listener local address",
                 );
-                return None;
+                return Err(error_message(&error));
             }
         };
 
         let listener_task =
             scoped_task::spawn(run_tcp_listener(listener, incoming_tx).instrument(span));
 
-        Some(Self {
+        Ok(Self {
             listener_local_addr,
             _listener_task: listener_task,
         })
     }
 }
 
+// Formats the error together with all its sources (e.g. "IO error: Address already in use").
+fn error_message(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+
+    while let Some(error) = source {
+        write!(message, ": {error}").ok();
+        source = error.source();
+    }
+
+    message
+}
+
 async fn run_tcp_listener(listener: TcpListener, tx: mpsc::Sender<(raw::Stream, PeerAddr)>) {
     loop {
         let result = select! {
@@ -605,88 +780,192 @@ fn ok_to_connect(addr: &SocketAddr, source: PeerSource) -> bool {
 
 #[derive(Debug)]
 pub(super) struct StackAddresses {
-    quic_v4: Option<SocketAddr>,
-    quic_v6: Option<SocketAddr>,
-    tcp_v4: Option<SocketAddr>,
-    tcp_v6: Option<SocketAddr>,
+    quic_v4: Vec<SocketAddr>,
+    quic_v6: Vec<SocketAddr>,
+    tcp_v4: Vec<SocketAddr>,
+    tcp_v6: Vec<SocketAddr>,
 }
 
 impl StackAddresses {
     pub(super) fn any_stack_needs_rebind(&self, new_stack_addresses: &StackAddresses) -> bool {
-        needs_rebind(&self.quic_v4, &new_stack_addresses.quic_v4)
-            || needs_rebind(&self.quic_v6, &new_stack_addresses.quic_v6)
-            || needs_rebind(&self.tcp_v4, &new_stack_addresses.tcp_v4)
-            || needs_rebind(&self.tcp_v6, &new_stack_addresses.tcp_v6)
+        any_needs_rebind(&self.quic_v4, &new_stack_addresses.quic_v4)
+            || any_needs_rebind(&self.quic_v6, &new_stack_addresses.quic_v6)
+            || any_needs_rebind(&self.tcp_v4, &new_stack_addresses.tcp_v4)
+            || any_needs_rebind(&self.tcp_v6, &new_stack_addresses.tcp_v6)
     }
 }
 
-fn needs_rebind(old_addr: &Option<SocketAddr>, new_addr: &Option<SocketAddr>) -> bool {
-    match (old_addr, new_addr) {
-        (Some(old_addr), Some(new_addr)) => {
-            let old_ip = old_addr.ip();
-            let old_port = old_addr.port();
-            let new_ip = new_addr.ip();
-            let new_port = new_addr.port();
-
-            // Just for readability as "true" and "false" have different lengths.
-            const T: bool = true;
-            const F: bool = false;
-
-            // `old_port` is not expected to be 0, but doesn't hurt to cover that case as well.
-            match (
-                old_ip.is_unspecified(),
-                old_port == 0,
-                new_ip.is_unspecified(),
-                new_port == 0,
-            ) {
-                (T, T, T, T) => false,
-                (F, T, T, T) => true,
-                (T, F, T, T) => false,
-                (F, F, T, T) => true,
-                (T, T, F, T) => true,
-                (F, T, F, T) => old_ip != new_ip,
-                (T, F, F, T) => true,
-                (F, F, F, T) => old_ip != new_ip,
-                (T, T, T, F) => true,
-                (F, T, T, F) => true,
-                (T, F, T, F) => old_port != new_port,
-                (F, F, T, F) => true,
-                (T, T, F, F) => true,
-                (F, T, F, F) => true,
-                (T, F, F, F) => true,
-                (F, F, F, F) => old_ip != new_ip || old_port != new_port,
-            }
-        }
-        (Some(_), None) => true,
-        (None, Some(_)) => true,
-        (None, None) => false,
+fn any_needs_rebind(old_addrs: &[SocketAddr], new_addrs: &[SocketAddr]) -> bool {
+    old_addrs.len() != new_addrs.len()
+        || old_addrs
+            .iter()
+            .zip(new_addrs)
+            .any(|(old_addr, new_addr)| needs_rebind(old_addr, new_addr))
+}
+
+fn needs_rebind(old_addr: &SocketAddr, new_addr: &SocketAddr) -> bool {
+    let old_ip = old_addr.ip();
+    let old_port = old_addr.port();
+    let new_ip = new_addr.ip();
+    let new_port = new_addr.port();
+
+    // Just for readability as "true" and "false" have different lengths.
+    const T: bool = true;
+    const F: bool = false;
+
+    // `old_port` is not expected to be 0, but doesn't hurt to cover that case as well.
+    match (
+        old_ip.is_unspecified(),
+        old_port == 0,
+        new_ip.is_unspecified(),
+        new_port == 0,
+    ) {
+        (T, T, T, T) => false,
+        (F, T, T, T) => true,
+        (T, F, T, T) => false,
+        (F, F, T, T) => true,
+        (T, T, F, T) => true,
+        (F, T, F, T) => old_ip != new_ip,
+        (T, F, F, T) => true,
+        (F, F, F, T) => old_ip != new_ip,
+        (T, T, T, F) => true,
+        (F, T, T, F) => true,
+        (T, F, T, F) => old_port != new_port,
+        (F, F, T, F) => true,
+        (T, T, F, F) => true,
+        (F, T, F, F) => true,
+        (T, F, F, F) => true,
+        (F, F, F, F) => old_ip != new_ip || old_port != new_port,
     }
 }
 
 impl From<&[PeerAddr]> for StackAddresses {
     fn from(addrs: &[PeerAddr]) -> Self {
-        let quic_v4 = addrs.iter().find_map(|addr| match addr {
-            PeerAddr::Quic(addr @ SocketAddr::V4(_)) => Some(*addr),
-            _ => None,
-        });
-        let quic_v6 = addrs.iter().find_map(|addr| match addr {
-            PeerAddr::Quic(addr @ SocketAddr::V6(_)) => Some(*addr),
-            _ => None,
-        });
-        let tcp_v4 = addrs.iter().find_map(|addr| match addr {
-            PeerAddr::Tcp(addr @ SocketAddr::V4(_)) => Some(*addr),
-            _ => None,
-        });
-        let tcp_v6 = addrs.iter().find_map(|addr| match addr {
-            PeerAddr::Tcp(addr @ SocketAddr::V6(_)) => Some(*addr),
-            _ => None,
-        });
+        let mut this = StackAddresses {
+            quic_v4: Vec::new(),
+            quic_v6: Vec::new(),
+            tcp_v4: Vec::new(),
+            tcp_v6: Vec::new(),
+        };
 
-        StackAddresses {
-            quic_v4,
-            quic_v6,
-            tcp_v4,
-            tcp_v6,
+        for addr in addrs {
+            let (list, addr) = match addr {
+                PeerAddr::Quic(addr @ SocketAddr::V4(_)) => (&mut this.quic_v4, addr),
+                PeerAddr::Quic(addr @ SocketAddr::V6(_)) => (&mut this.quic_v6, addr),
+                PeerAddr::Tcp(addr @ SocketAddr::V4(_)) => (&mut this.tcp_v4, addr),
+                PeerAddr::Tcp(addr @ SocketAddr::V6(_)) => (&mut this.tcp_v6, addr),
+            };
+
+            if !list.contains(addr) {
+                list.push(*addr);
+            }
+        }
+
+        this
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state_monitor::StateMonitor;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bind_status() {
+        let (incoming_tx, _incoming_rx) = mpsc::channel(1);
+        let gateway = Gateway::new(
+            incoming_tx,
+            KeepAlive::default(),
+            ConnectionStatsTracker::new(StateMonitor::make_root()),
+        );
+
+        // Occupy a port so the gateway can't use it.
+        let taken = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let taken_addr = PeerAddr::Tcp(taken.local_addr().unwrap());
+        let free_addr = PeerAddr::Quic((Ipv4Addr::LOCALHOST, 0).into());
+
+        gateway
+            .bind(&StackAddresses::from(&[taken_addr, free_addr][..]))
+            .await;
+
+        let status = gateway.bind_status();
+        assert_eq!(status.len(), 2);
+
+        let free = status.iter().find(|s| s.requested == free_addr).unwrap();
+        assert!(free.bound.is_some());
+        assert_eq!(free.error, None);
+
+        let taken = status.iter().find(|s| s.requested == taken_addr).unwrap();
+        assert_ne!(taken.bound.map(|addr| addr.port()), Some(taken_addr.port()));
+        assert!(taken.error.is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rebind_after_network_change() {
+        let (incoming_tx, _incoming_rx) = mpsc::channel(1);
+        let gateway = Gateway::new(
+            incoming_tx,
+            KeepAlive::default(),
+            ConnectionStatsTracker::new(StateMonitor::make_root()),
+        );
+
+        let addrs = [
+            PeerAddr::Tcp((Ipv4Addr::LOCALHOST, 0).into()),
+            PeerAddr::Quic((Ipv4Addr::LOCALHOST, 0).into()),
+        ];
+
+        gateway.bind(&StackAddresses::from(&addrs[..])).await;
+
+        let mut before = gateway.listener_local_addrs();
+        before.sort();
+
+        // The ports picked by the OS are kept.
+        let mut rebind_addrs = gateway.rebind_addrs();
+        rebind_addrs.sort();
+        assert_eq!(rebind_addrs, before);
+
+        gateway.unbind().await;
+        assert!(gateway.listener_local_addrs().is_empty());
+
+        gateway.bind(&StackAddresses::from(&rebind_addrs[..])).await;
+
+        let mut after = gateway.listener_local_addrs();
+        after.sort();
+        assert_eq!(after, before);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn multiple_listeners_per_family() {
+        let (incoming_tx, _incoming_rx) = mpsc::channel(1);
+        let gateway = Gateway::new(
+            incoming_tx,
+            KeepAlive::default(),
+            ConnectionStatsTracker::new(StateMonitor::make_root()),
+        );
+
+        let addrs = [
+            PeerAddr::Tcp((Ipv4Addr::LOCALHOST, 0).into()),
+            PeerAddr::Tcp((Ipv4Addr::UNSPECIFIED, 0).into()),
+            PeerAddr::Quic((Ipv4Addr::LOCALHOST, 0).into()),
+            PeerAddr::Quic((Ipv4Addr::UNSPECIFIED, 0).into()),
+        ];
+
+        gateway.bind(&StackAddresses::from(&addrs[..])).await;
+
+        let local_addrs = gateway.listener_local_addrs();
+        assert_eq!(local_addrs.len(), 4);
+
+        for addr in addrs {
+            assert!(local_addrs
+                .iter()
+                .any(|local_addr| local_addr.is_quic() == addr.is_quic()
+                    && local_addr.ip() == addr.ip()));
         }
+
+        // Binding to the same addresses again is a no-op.
+        assert!(!gateway
+            .addresses()
+            .any_stack_needs_rebind(&StackAddresses::from(&addrs[..])));
     }
 }
@@ -1,5 +1,5 @@
 use crate::collections::HashSet;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
 use tokio::{
     sync::mpsc,
     time::{sleep, Duration},
@@ -59,6 +59,105 @@ pub(crate) fn watch_ipv4_multicast_interfaces() -> mpsc::Receiver<InterfaceChang
     rx
 }
 
+// Tells us when the addresses of the network interfaces change (e.g., when a mobile device switches
+// from WiFi to cellular or when a VPN is turned on). Loopback and IPv6 link-local addresses are
+// ignored.
+pub(crate) fn watch_interface_addrs() -> mpsc::Receiver<()> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let mut seen_addrs = find_interface_addrs().await;
+
+        loop {
+            sleep(INTERFACE_REFRESH_DELAY).await;
+
+            if tx.is_closed() {
+                return;
+            }
+
+            // `None` means the addresses couldn't be retrieved. Don't report it as a change.
+            let Some(found_addrs) = find_interface_addrs().await else {
+                continue;
+            };
+
+            if seen_addrs.as_ref() == Some(&found_addrs) {
+                continue;
+            }
+
+            // Don't report the initial retrieval as a change.
+            if seen_addrs.replace(found_addrs).is_none() {
+                continue;
+            }
+
+            // If the channel is full, the previous change hasn't been handled yet which is fine
+            // because it's going to cover this one as well.
+            tx.try_send(()).ok();
+        }
+    });
+
+    rx
+}
+
+async fn find_interface_addrs() -> Option<HashSet<IpAddr>> {
+    tokio::task::spawn_blocking(find_interface_addrs_sync)
+        .await
+        .ok()
+        .flatten()
+        .map(|addrs| addrs.into_iter().filter(is_relevant).collect())
+}
+
+fn is_relevant(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => !addr.is_loopback(),
+        IpAddr::V6(addr) => !addr.is_loopback() && (addr.segments()[0] & 0xffc0) != 0xfe80,
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn find_interface_addrs_sync() -> Option<HashSet<IpAddr>> {
+    use std::net::{SocketAddrV4, SocketAddrV6};
+
+    let addrs = nix::ifaddrs::getifaddrs().ok()?;
+
+    Some(
+        addrs
+            .filter_map(|ifaddr| ifaddr.address)
+            .filter_map(|addr| {
+                if let Some(addr) = addr.as_sockaddr_in() {
+                    Some(IpAddr::V4(*SocketAddrV4::from(*addr).ip()))
+                } else {
+                    addr.as_sockaddr_in6()
+                        .map(|addr| IpAddr::V6(*SocketAddrV6::from(*addr).ip()))
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(target_family = "windows")]
+fn find_interface_addrs_sync() -> Option<HashSet<IpAddr>> {
+    use network_interface::{Addr, NetworkInterface, NetworkInterfaceConfig};
+
+    let network_interfaces = match NetworkInterface::show() {
+        Ok(network_interfaces) => network_interfaces,
+        Err(err) => {
+            tracing::warn!("Failed to retrieve network interfaces: {:?}", err);
+            return None;
+        }
+    };
+
+    Some(
+        network_interfaces
+            .iter()
+            .filter_map(|itf| itf.addr)
+            .map(|addr| match addr {
+                Addr::V4(addr) => IpAddr::V4(addr.ip),
+                Addr::V6(addr) => IpAddr::V6(addr.ip),
+            })
+            .collect(),
+    )
+}
+
 async fn find_ipv4_multicast_interfaces() -> HashSet<Ipv4Addr> {
     match tokio::task::spawn_blocking(find_ipv4_multicast_interfaces_sync).await {
         Ok(interfaces) => interfaces,
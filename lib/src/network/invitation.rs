@@ -0,0 +1,77 @@
+//! Redemption of one-time invitations (see `Repository::create_invitation`) over a link.
+
+use super::message::{Content, InvitationPayload};
+use crate::repository::{RedeemReply, Vault};
+use tokio::{select, sync::mpsc};
+
+/// Sends the pending redemption (if any) of the local repository to the peer and handles the
+/// redemptions coming from the peer. Returns only on error.
+pub(super) async fn run(
+    vault: &Vault,
+    content_tx: mpsc::Sender<Content>,
+    mut payload_rx: mpsc::Receiver<InvitationPayload>,
+) {
+    let mut started_rx = vault.redemption.subscribe();
+    started_rx.mark_changed();
+
+    loop {
+        select! {
+            result = started_rx.changed() => {
+                if result.is_err() {
+                    break;
+                }
+
+                if let Some((id, writer_id, proof)) = vault.redemption.pending() {
+                    let payload = InvitationPayload::Redeem { id, writer_id, proof };
+
+                    if content_tx.send(Content::Invitation(payload)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            payload = payload_rx.recv() => {
+                let Some(payload) = payload else {
+                    break;
+                };
+
+                if let Some(reply) = handle(vault, payload).await {
+                    if content_tx.send(Content::Invitation(reply)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn handle(vault: &Vault, payload: InvitationPayload) -> Option<InvitationPayload> {
+    match payload {
+        InvitationPayload::Redeem {
+            id,
+            writer_id,
+            proof,
+        } => match vault.redeem_invitation(&id, writer_id, &proof).await {
+            Ok(Some(RedeemReply::Granted(sealed))) => {
+                tracing::info!(?writer_id, "Invitation redeemed");
+                Some(InvitationPayload::Granted { id, sealed })
+            }
+            Ok(Some(RedeemReply::Rejected)) => {
+                tracing::warn!(?writer_id, "Invitation already redeemed by another writer");
+                Some(InvitationPayload::Rejected { id })
+            }
+            Ok(None) => None,
+            Err(error) => {
+                tracing::error!(?error, "Failed to redeem invitation");
+                None
+            }
+        },
+        InvitationPayload::Granted { id, sealed } => {
+            vault.redemption.complete(&id, RedeemReply::Granted(sealed));
+            None
+        }
+        InvitationPayload::Rejected { id } => {
+            vault.redemption.complete(&id, RedeemReply::Rejected);
+            None
+        }
+    }
+}
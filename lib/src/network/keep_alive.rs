@@ -2,11 +2,14 @@ use super::{
     message::Message,
     message_io::{MessageSink, MessageStream, SendError},
 };
+use crate::sync::uninitialized_watch;
+use deadlock::BlockingMutex;
 use futures_util::{ready, FutureExt, Sink, SinkExt, Stream, StreamExt};
 use std::{
     io,
     marker::PhantomData,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 use tokio::{
@@ -19,6 +22,105 @@ use tokio::{
 use tokio_stream::Timeout;
 use tokio_util::sync::{PollSendError, PollSender};
 
+/// Keep-alive (ping) parameters of the peer connections.
+///
+/// When no message has been sent on a connection for `interval`, a ping (empty keep-alive message)
+/// is sent. When no message (including pings) has been received for `timeout`, the peer is
+/// considered dead and the connection is closed. The timeout should be a few multiples of the
+/// interval to tolerate a lost ping or two.
+///
+/// Because the peers don't know each other's config, the protocol requires every peer to ping at
+/// least every `MAX_INTERVAL`. The interval is therefore capped at `MAX_INTERVAL` and the timeout
+/// can't be shorter than `MIN_TIMEOUT`, otherwise we would disconnect peers that follow the
+/// protocol but use a longer interval than us.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct KeepAliveConfig {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl KeepAliveConfig {
+    pub const MAX_INTERVAL: Duration = Duration::from_secs(10);
+    pub const MIN_TIMEOUT: Duration = Duration::from_secs(20);
+
+    /// Returns this config with the interval and timeout adjusted to the bounds required by the
+    /// protocol.
+    pub fn clamped(self) -> Self {
+        Self {
+            interval: self.interval.min(Self::MAX_INTERVAL),
+            timeout: self.timeout.max(Self::MIN_TIMEOUT),
+        }
+    }
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl KeepAliveConfig {
+    /// Keep-alive parameters of the underlying QUIC transport. Its idle timeout is a bit longer
+    /// than ours so that a dead peer is detected (and reported) by us first.
+    pub(super) fn quic(&self) -> net::quic::KeepAlive {
+        net::quic::KeepAlive {
+            interval: self.interval,
+            idle_timeout: self.timeout + Duration::from_secs(2),
+        }
+    }
+}
+
+/// Keep-alive settings shared by all the connections of a network, together with the notifier of
+/// the connections that timed out.
+#[derive(Clone)]
+pub(super) struct KeepAlive {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    config: BlockingMutex<KeepAliveConfig>,
+    on_timeout_tx: uninitialized_watch::Sender<()>,
+}
+
+impl KeepAlive {
+    pub fn new(config: KeepAliveConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                config: BlockingMutex::new(config),
+                on_timeout_tx: uninitialized_watch::channel().0,
+            }),
+        }
+    }
+
+    /// Changes the config. Applies only to the connections established afterwards. The config is
+    /// clamped to the protocol bounds (see `KeepAliveConfig`).
+    pub fn set_config(&self, config: KeepAliveConfig) {
+        *self.shared.config.lock().unwrap() = config.clamped();
+    }
+
+    pub fn config(&self) -> KeepAliveConfig {
+        *self.shared.config.lock().unwrap()
+    }
+
+    /// Subscribe to notifications of connections closed because the peer stopped responding.
+    pub fn on_timeout(&self) -> uninitialized_watch::Receiver<()> {
+        self.shared.on_timeout_tx.subscribe()
+    }
+
+    pub fn notify_timeout(&self) {
+        self.shared.on_timeout_tx.send(()).unwrap_or(());
+    }
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::new(KeepAliveConfig::default())
+    }
+}
+
 /// Adapter for `MessageStream` which yields error when no message is received within the specified
 /// timeout.
 pub(super) struct KeepAliveStream<R> {
@@ -49,10 +151,15 @@ where
         loop {
             let item = match ready!(self.inner.poll_next_unpin(cx)) {
                 Some(Ok(Ok(message))) => {
-                    if message.is_keep_alive() {
+                    if !message.is_keep_alive() {
+                        Some(Ok(message))
+                    } else if message.is_valid_keep_alive() {
                         continue;
                     } else {
-                        Some(Ok(message))
+                        Some(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid keep-alive message",
+                        )))
                     }
                 }
                 Some(Ok(Err(error))) => Some(Err(error)),
@@ -308,6 +415,42 @@ mod tests {
         assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
     }
 
+    #[tokio::test]
+    async fn stream_rejects_invalid_keep_alive_messages() {
+        let (client, server) = create_connected_sockets().await;
+
+        let mut sink = MessageSink::new(client);
+        let mut stream =
+            KeepAliveStream::new(MessageStream::new(server), Duration::from_millis(250));
+
+        sink.send(Message {
+            tag: Type::KeepAlive,
+            channel: MessageChannelId::default(),
+            content: b"hello".to_vec(),
+        })
+        .await
+        .unwrap();
+
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn config_clamped() {
+        let config = KeepAliveConfig {
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(1),
+        }
+        .clamped();
+
+        assert_eq!(config.interval, KeepAliveConfig::MAX_INTERVAL);
+        assert_eq!(config.timeout, KeepAliveConfig::MIN_TIMEOUT);
+        assert_eq!(
+            KeepAliveConfig::default().clamped(),
+            KeepAliveConfig::default()
+        );
+    }
+
     async fn create_connected_sockets() -> (TcpStream, TcpStream) {
         let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
         let client = TcpStream::connect(listener.local_addr().unwrap())
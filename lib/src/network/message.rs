@@ -1,62 +1,16 @@
-use super::{
-    crypto::Role,
-    debug_payload::{DebugRequest, DebugResponse},
-    peer_exchange::PexPayload,
-    runtime_id::PublicRuntimeId,
-};
+use super::{crypto::Role, runtime_id::PublicRuntimeId};
 use crate::{
-    crypto::{sign::PublicKey, Hash, Hashable},
-    protocol::{
-        BlockContent, BlockId, BlockNonce, InnerNodes, LeafNodes, MultiBlockPresence,
-        UntrustedProof,
-    },
+    crypto::{Hash, Hashable},
     repository::RepositoryId,
 };
+use ouisync_protocol::define_byte_array_wrapper;
 use serde::{Deserialize, Serialize};
 use std::{fmt, io::Write};
 
-#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
-pub(crate) enum Request {
-    RootNode(PublicKey, DebugRequest),
-    ChildNodes(Hash, ResponseDisambiguator, DebugRequest),
-    Block(BlockId, DebugRequest),
-}
-
-/// ResponseDisambiguator is used to uniquelly assign a response to a request.
-/// What we want to avoid is that an outdated response clears out a newer pending request.
-#[derive(Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Debug)]
-#[serde(transparent)]
-pub(crate) struct ResponseDisambiguator(MultiBlockPresence);
-
-impl ResponseDisambiguator {
-    pub fn new(multi_block_presence: MultiBlockPresence) -> Self {
-        Self(multi_block_presence)
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) enum Response {
-    /// Send the latest root node of this replica to another replica.
-    /// NOTE: This is both a response and notification - the server sends this as a response to
-    /// `Request::RootNode` but also on its own when it detects change in the repo.
-    RootNode(UntrustedProof, MultiBlockPresence, DebugResponse),
-    /// Send that a RootNode request failed
-    RootNodeError(PublicKey, DebugResponse),
-    /// Send inner nodes.
-    InnerNodes(InnerNodes, ResponseDisambiguator, DebugResponse),
-    /// Send leaf nodes.
-    LeafNodes(LeafNodes, ResponseDisambiguator, DebugResponse),
-    /// Send that a ChildNodes request failed
-    ChildNodesError(Hash, ResponseDisambiguator, DebugResponse),
-    /// Send a notification that a block became available on this replica.
-    /// NOTE: This is always unsolicited - the server sends it on its own when it detects a newly
-    /// received block.
-    BlockOffer(BlockId, DebugResponse),
-    /// Send a requested block.
-    Block(BlockContent, BlockNonce, DebugResponse),
-    /// Send that a Block request failed
-    BlockError(BlockId, DebugResponse),
-}
+pub(crate) use ouisync_protocol::message::{
+    Content, InvitationPayload, MirrorReceipt, PexPayload, PunchPayload, Request, Response,
+    ResponseDisambiguator,
+};
 
 #[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Debug)]
 pub(crate) enum Type {
@@ -145,6 +99,13 @@ impl Message {
     pub fn is_keep_alive(&self) -> bool {
         self.tag == Type::KeepAlive
     }
+
+    /// Keep-alive messages must be empty and on the default channel.
+    pub fn is_valid_keep_alive(&self) -> bool {
+        self.is_keep_alive()
+            && self.channel == MessageChannelId::default()
+            && self.content.is_empty()
+    }
 }
 
 impl fmt::Debug for Message {
@@ -208,38 +169,6 @@ impl ArrayWriter<'_> {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) enum Content {
-    Request(Request),
-    Response(Response),
-    // Peer exchange
-    Pex(PexPayload),
-}
-
-#[cfg(test)]
-impl From<Content> for Request {
-    fn from(content: Content) -> Self {
-        match content {
-            Content::Request(request) => request,
-            Content::Response(_) | Content::Pex(_) => {
-                panic!("not a request: {:?}", content)
-            }
-        }
-    }
-}
-
-#[cfg(test)]
-impl From<Content> for Response {
-    fn from(content: Content) -> Self {
-        match content {
-            Content::Response(response) => response,
-            Content::Request(_) | Content::Pex(_) => {
-                panic!("not a response: {:?}", content)
-            }
-        }
-    }
-}
-
 define_byte_array_wrapper! {
     // TODO: consider lower size (truncate the hash) which should still be enough to be unique
     // while reducing the message size.
@@ -1,22 +1,33 @@
 use super::{
+    access_notice,
     barrier::{Barrier, BarrierError},
     choke,
     client::Client,
-    connection::ConnectionPermit,
-    constants::MAX_IN_FLIGHT_REQUESTS_PER_PEER,
-    crypto::{self, DecryptingStream, EncryptingSink, EstablishError, RecvError, Role, SendError},
-    message::{Content, MessageChannelId, Request, Response},
+    connection::{ConnectionPermit, LinkCounter},
+    constants::{BULK_MAX_IN_FLIGHT_REQUESTS_PER_PEER, MAX_IN_FLIGHT_REQUESTS_PER_PEER},
+    crypto::{
+        self, DecryptingStream, EncryptingSink, EstablishError, RecvError, ReplayGuard, Role,
+        SendError,
+    },
+    invitation,
+    keep_alive::KeepAlive,
+    message::{Content, InvitationPayload, MessageChannelId, Request, Response},
     message_dispatcher::{ContentSink, ContentStream, MessageDispatcher},
     peer_exchange::{PexPeer, PexReceiver, PexRepository, PexSender},
+    peer_source::PeerSource,
+    protocol_trace::{MessageDirection, PeerTracer},
     raw,
     runtime_id::PublicRuntimeId,
     server::Server,
     traffic_tracker::TrafficTracker,
+    verifier::Verifier,
+    wipe_directive,
 };
 use crate::{
     collections::{hash_map::Entry, HashMap},
-    network::constants::MAX_PENDING_REQUESTS_PER_CLIENT,
-    repository::{LocalId, Vault},
+    network::constants::BULK_MAX_PENDING_REQUESTS_PER_CLIENT,
+    replica_tracker::ReplicaTrackerClient,
+    repository::{AccessNotice, LocalId, Vault, WipeDirective},
 };
 use backoff::{backoff::Backoff, ExponentialBackoffBuilder};
 use state_monitor::StateMonitor;
@@ -44,13 +55,20 @@ pub(super) struct MessageBroker {
     dispatcher: MessageDispatcher,
     links: HashMap<LocalId, oneshot::Sender<()>>,
     request_limiter: Arc<Semaphore>,
+    verifier: Verifier,
     pex_peer: PexPeer,
     monitor: StateMonitor,
     tracker: TrafficTracker,
+    tracer: PeerTracer,
+    link_counter: LinkCounter,
+    bulk: bool,
+    // Source of the connection that created this broker.
+    source: PeerSource,
     span: Span,
 }
 
 impl MessageBroker {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         this_runtime_id: PublicRuntimeId,
         that_runtime_id: PublicRuntimeId,
@@ -59,23 +77,38 @@ impl MessageBroker {
         pex_peer: PexPeer,
         monitor: StateMonitor,
         tracker: TrafficTracker,
+        tracer: PeerTracer,
+        verifier: Verifier,
+        keep_alive: KeepAlive,
+        bulk: bool,
     ) -> Self {
         let span = tracing::info_span!(
             "message_broker",
             message = ?that_runtime_id.as_public_key(),
         );
 
-        tracing::info!(parent: &span, "Message broker created");
+        tracing::info!(parent: &span, bulk, "Message broker created");
+
+        let max_in_flight_requests = if bulk {
+            BULK_MAX_IN_FLIGHT_REQUESTS_PER_PEER
+        } else {
+            MAX_IN_FLIGHT_REQUESTS_PER_PEER
+        };
 
         let this = Self {
             this_runtime_id,
             that_runtime_id,
-            dispatcher: MessageDispatcher::new(),
+            dispatcher: MessageDispatcher::new(keep_alive),
             links: HashMap::default(),
-            request_limiter: Arc::new(Semaphore::new(MAX_IN_FLIGHT_REQUESTS_PER_PEER)),
+            request_limiter: Arc::new(Semaphore::new(max_in_flight_requests)),
+            verifier,
             pex_peer,
             monitor,
             tracker,
+            tracer,
+            link_counter: permit.new_link_counter(),
+            bulk,
+            source: permit.source(),
             span,
         };
 
@@ -86,9 +119,15 @@ impl MessageBroker {
     pub fn add_connection(&self, stream: raw::Stream, permit: ConnectionPermit) {
         self.pex_peer
             .handle_connection(permit.addr(), permit.source(), permit.released());
+        permit.set_link_counter(&self.link_counter);
         self.dispatcher.bind(stream, permit)
     }
 
+    /// How was the peer of this broker discovered (for the first of its connections).
+    pub fn source(&self) -> PeerSource {
+        self.source
+    }
+
     /// Has this broker at least one live connection?
     pub fn has_connections(&self) -> bool {
         !self.dispatcher.is_closed()
@@ -149,11 +188,16 @@ impl MessageBroker {
             sink: self.dispatcher.open_send(channel_id),
             vault,
             request_limiter: self.request_limiter.clone(),
+            verifier: self.verifier.clone(),
             pex_tx,
             pex_rx,
             choker: choke_manager.new_choker(),
             monitor,
             tracker: self.tracker.clone(),
+            tracer: self.tracer.clone(),
+            link_counter: self.link_counter.clone(),
+            bulk: self.bulk,
+            replay_guard: ReplayGuard::default(),
         };
 
         drop(span_enter);
@@ -192,11 +236,16 @@ struct Link {
     sink: ContentSink,
     vault: Vault,
     request_limiter: Arc<Semaphore>,
+    verifier: Verifier,
     pex_tx: PexSender,
     pex_rx: PexReceiver,
     choker: choke::Choker,
     monitor: StateMonitor,
     tracker: TrafficTracker,
+    tracer: PeerTracer,
+    link_counter: LinkCounter,
+    bulk: bool,
+    replay_guard: ReplayGuard,
 }
 
 impl Link {
@@ -247,29 +296,41 @@ impl Link {
                 &mut self.sink,
                 &self.vault,
                 self.tracker.clone(),
+                &mut self.replay_guard,
             )
             .await
             {
                 Ok(io) => io,
                 Err(EstablishError::Crypto) => continue,
+                Err(EstablishError::Replay) => continue,
                 Err(EstablishError::Closed) => break,
                 Err(EstablishError::TransportChanged) => continue,
             };
 
             *state.get() = State::Running;
+            let _link_guard = self.link_counter.increment();
 
             match run_link(
                 crypto_stream,
                 crypto_sink,
                 &self.vault,
                 self.request_limiter.clone(),
+                self.verifier.clone(),
                 &mut self.pex_tx,
                 &mut self.pex_rx,
                 self.choker.clone(),
+                self.bulk,
+                &self.tracer,
             )
             .await
             {
                 ControlFlow::Continue => continue,
+                ControlFlow::Rehandshake => {
+                    // Nothing's wrong with the peer so don't back off.
+                    next_sleep = None;
+                    backoff.reset();
+                    continue;
+                }
                 ControlFlow::Break => break,
             }
         }
@@ -282,8 +343,18 @@ async fn establish_channel<'a>(
     sink: &'a mut ContentSink,
     vault: &Vault,
     tracker: TrafficTracker,
+    replay_guard: &mut ReplayGuard,
 ) -> Result<(DecryptingStream<'a>, EncryptingSink<'a>), EstablishError> {
-    match crypto::establish_channel(role, vault.repository_id(), stream, sink, tracker).await {
+    match crypto::establish_channel(
+        role,
+        vault.repository_id(),
+        stream,
+        sink,
+        tracker,
+        replay_guard,
+    )
+    .await
+    {
         Ok(io) => {
             tracing::debug!("Established encrypted channel");
             Ok(io)
@@ -296,30 +367,58 @@ async fn establish_channel<'a>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_link(
     stream: DecryptingStream<'_>,
     sink: EncryptingSink<'_>,
     repo: &Vault,
     request_limiter: Arc<Semaphore>,
+    verifier: Verifier,
     pex_tx: &mut PexSender,
     pex_rx: &mut PexReceiver,
     choker: choke::Choker,
+    bulk: bool,
+    tracer: &PeerTracer,
 ) -> ControlFlow {
     // If the peer is choked we may still receive requests from them but we won't process them until
     // the peer is unchoked. Therefore, the capacity of this channel must be large enough to
-    // accomodate any such requests.
-    let (request_tx, request_rx) = mpsc::channel(MAX_PENDING_REQUESTS_PER_CLIENT);
+    // accomodate any such requests. Whether the peer uses bulk transfer is up to them so always
+    // assume it does.
+    let (request_tx, request_rx) = mpsc::channel(BULK_MAX_PENDING_REQUESTS_PER_CLIENT);
     let (response_tx, response_rx) = mpsc::channel(1);
     let (content_tx, content_rx) = mpsc::channel(1);
+    let (invitation_tx, invitation_rx) = mpsc::channel(1);
+    let (notice_tx, notice_rx) = mpsc::channel(1);
+    let (directive_tx, directive_rx) = mpsc::channel(1);
+    let replicas = Arc::new(repo.replica_tracker.client());
+
+    let senders = MessageSenders {
+        request: request_tx,
+        response: response_tx,
+        invitation: invitation_tx,
+        notice: notice_tx,
+        directive: directive_tx,
+    };
 
     tracing::info!("Link opened");
 
     // Run everything in parallel:
     let flow = select! {
-        flow = run_client(repo.clone(), content_tx.clone(), response_rx, request_limiter) => flow,
-        flow = run_server(repo.clone(), content_tx.clone(), request_rx, choker) => flow,
-        flow = recv_messages(stream, request_tx, response_tx, pex_rx) => flow,
-        flow = send_messages(content_rx, sink) => flow,
+        flow = run_client(
+            repo.clone(),
+            content_tx.clone(),
+            response_rx,
+            request_limiter,
+            verifier,
+            replicas.clone(),
+            bulk,
+        ) => flow,
+        flow = run_server(repo.clone(), content_tx.clone(), request_rx, choker, replicas) => flow,
+        flow = recv_messages(stream, senders, pex_rx, tracer) => flow,
+        flow = send_messages(content_rx, sink, tracer) => flow,
+        _ = invitation::run(repo, content_tx.clone(), invitation_rx) => ControlFlow::Continue,
+        _ = access_notice::run(repo, content_tx.clone(), notice_rx) => ControlFlow::Continue,
+        _ = wipe_directive::run(repo, content_tx.clone(), directive_rx) => ControlFlow::Continue,
         _ = pex_tx.run(content_tx) => ControlFlow::Continue,
     };
 
@@ -328,12 +427,21 @@ async fn run_link(
     flow
 }
 
+// Senders of the incoming messages to the tasks that handle them.
+struct MessageSenders {
+    request: mpsc::Sender<Request>,
+    response: mpsc::Sender<Response>,
+    invitation: mpsc::Sender<InvitationPayload>,
+    notice: mpsc::Sender<AccessNotice>,
+    directive: mpsc::Sender<WipeDirective>,
+}
+
 // Handle incoming messages
 async fn recv_messages(
     mut stream: DecryptingStream<'_>,
-    request_tx: mpsc::Sender<Request>,
-    response_tx: mpsc::Sender<Response>,
+    senders: MessageSenders,
     pex_rx: &PexReceiver,
+    tracer: &PeerTracer,
 ) -> ControlFlow {
     loop {
         let content = match stream.recv().await {
@@ -343,8 +451,8 @@ async fn recv_messages(
                 return ControlFlow::Continue;
             }
             Err(RecvError::Exhausted) => {
-                tracing::debug!("Incoming message nonce counter exhausted",);
-                return ControlFlow::Continue;
+                tracing::info!("Incoming message nonce counter exhausted, renewing session");
+                return ControlFlow::Rehandshake;
             }
             Err(RecvError::Closed) => {
                 tracing::debug!("Message stream closed");
@@ -356,18 +464,28 @@ async fn recv_messages(
             }
         };
 
+        let size = content.len();
         let content: Content = match bincode::deserialize(&content) {
             Ok(content) => content,
             Err(error) => {
                 tracing::warn!(?error, "Failed to deserialize incoming message");
+                tracer.record(MessageDirection::Inbound, "Undecodable", size);
                 continue; // TODO: should we return `ControlFlow::Continue` here as well?
             }
         };
 
+        tracer.record(MessageDirection::Inbound, content.kind(), size);
+
         match content {
-            Content::Request(request) => request_tx.send(request).await.unwrap_or(()),
-            Content::Response(response) => response_tx.send(response).await.unwrap_or(()),
+            Content::Request(request) => senders.request.send(request).await.unwrap_or(()),
+            Content::Response(response) => senders.response.send(response).await.unwrap_or(()),
             Content::Pex(payload) => pex_rx.handle_message(payload).await,
+            Content::Punch(payload) => pex_rx.handle_punch(payload).await,
+            Content::Invitation(payload) => senders.invitation.send(payload).await.unwrap_or(()),
+            Content::AccessNotice(notice) => senders.notice.send(notice).await.unwrap_or(()),
+            Content::WipeDirective(directive) => {
+                senders.directive.send(directive).await.unwrap_or(())
+            }
         }
     }
 }
@@ -376,6 +494,7 @@ async fn recv_messages(
 async fn send_messages(
     mut content_rx: mpsc::Receiver<Content>,
     mut sink: EncryptingSink<'_>,
+    tracer: &PeerTracer,
 ) -> ControlFlow {
     loop {
         let content = if let Some(content) = content_rx.recv().await {
@@ -384,32 +503,51 @@ async fn send_messages(
             forever().await
         };
 
+        let kind = content.kind();
+
         // unwrap is OK because serialization into a vec should never fail unless we have a bug
         // somewhere.
         let content = bincode::serialize(&content).unwrap();
 
+        tracer.record(MessageDirection::Outbound, kind, content.len());
+
         match sink.send(content).await {
             Ok(()) => (),
             Err(SendError::Exhausted) => {
-                tracing::debug!("Outgoing message nonce counter exhausted");
-                return ControlFlow::Continue;
+                tracing::info!("Outgoing message nonce counter exhausted, renewing session");
+                return ControlFlow::Rehandshake;
             }
             Err(SendError::Closed) => {
                 tracing::debug!("Message sink closed");
                 return ControlFlow::Break;
             }
+            Err(SendError::EmptyMessage) => {
+                tracing::warn!(?kind, "Not sending empty message");
+            }
         }
     }
 }
 
 // Create and run client. Returns only on error.
+#[allow(clippy::too_many_arguments)]
 async fn run_client(
     repo: Vault,
     content_tx: mpsc::Sender<Content>,
     response_rx: mpsc::Receiver<Response>,
     request_limiter: Arc<Semaphore>,
+    verifier: Verifier,
+    replicas: Arc<ReplicaTrackerClient>,
+    bulk: bool,
 ) -> ControlFlow {
-    let mut client = Client::new(repo, content_tx, response_rx, request_limiter);
+    let mut client = Client::new(
+        repo,
+        content_tx,
+        response_rx,
+        request_limiter,
+        verifier,
+        replicas,
+        bulk,
+    );
     let result = client.run().await;
 
     tracing::debug!("Client stopped running with result {:?}", result);
@@ -426,8 +564,9 @@ async fn run_server(
     content_tx: mpsc::Sender<Content>,
     request_rx: mpsc::Receiver<Request>,
     choker: choke::Choker,
+    replicas: Arc<ReplicaTrackerClient>,
 ) -> ControlFlow {
-    let mut server = Server::new(repo, content_tx, request_rx, choker);
+    let mut server = Server::new(repo, content_tx, request_rx, choker, replicas);
 
     let result = server.run().await;
 
@@ -446,5 +585,7 @@ async fn forever() -> ! {
 
 enum ControlFlow {
     Continue,
+    // Establish a new session right away, without backing off.
+    Rehandshake,
     Break,
 }
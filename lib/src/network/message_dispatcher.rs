@@ -2,7 +2,7 @@
 
 use super::{
     connection::{ConnectionPermit, ConnectionPermitHalf, PermitId},
-    keep_alive::{KeepAliveSink, KeepAliveStream},
+    keep_alive::{KeepAlive, KeepAliveSink, KeepAliveStream},
     message::{Message, MessageChannelId, Type},
     message_io::{MessageSink, MessageStream, SendError},
     raw,
@@ -14,13 +14,13 @@ use futures_util::{ready, stream::SelectAll, Sink, SinkExt, Stream, StreamExt};
 use scoped_task::ScopedJoinHandle;
 use std::{
     future::Future,
+    io,
     pin::Pin,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     task::{Context, Poll, Waker},
-    time::Duration,
 };
 use tokio::{
     runtime, select,
@@ -28,11 +28,6 @@ use tokio::{
     sync::{Mutex as AsyncMutex, Notify, Semaphore},
 };
 
-// Time after which if no message is received, the connection is dropped.
-const KEEP_ALIVE_RECV_INTERVAL: Duration = Duration::from_secs(60);
-// How often to send keep-alive messages if no regular messages have been sent.
-const KEEP_ALIVE_SEND_INTERVAL: Duration = Duration::from_secs(30);
-
 /// Reads/writes messages from/to the underlying TCP or QUIC streams and dispatches them to
 /// individual streams/sinks based on their channel ids (in the MessageDispatcher's and
 /// MessageBroker's contexts, there is a one-to-one relationship between the channel id and a
@@ -41,13 +36,15 @@ const KEEP_ALIVE_SEND_INTERVAL: Duration = Duration::from_secs(30);
 pub(super) struct MessageDispatcher {
     recv: Arc<RecvState>,
     send: Arc<MultiSink>,
+    keep_alive: KeepAlive,
 }
 
 impl MessageDispatcher {
-    pub fn new() -> Self {
+    pub fn new(keep_alive: KeepAlive) -> Self {
         Self {
             recv: Arc::new(RecvState::new()),
             send: Arc::new(MultiSink::new()),
+            keep_alive,
         }
     }
 
@@ -57,8 +54,13 @@ impl MessageDispatcher {
         let (reader, writer) = stream.into_split();
         let (reader_permit, writer_permit) = permit.split();
 
-        self.recv.add(PermittedStream::new(reader, reader_permit));
-        self.send.add(PermittedSink::new(writer, writer_permit));
+        self.recv.add(PermittedStream::new(
+            reader,
+            reader_permit,
+            self.keep_alive.clone(),
+        ));
+        self.send
+            .add(PermittedSink::new(writer, writer_permit, &self.keep_alive));
     }
 
     /// Opens a stream for receiving messages with the given id.
@@ -326,13 +328,19 @@ impl RecvState {
 struct PermittedStream {
     inner: KeepAliveStream<raw::OwnedReadHalf>,
     permit: ConnectionPermitHalf,
+    keep_alive: KeepAlive,
 }
 
 impl PermittedStream {
-    fn new(stream: raw::OwnedReadHalf, permit: ConnectionPermitHalf) -> Self {
+    fn new(
+        stream: raw::OwnedReadHalf,
+        permit: ConnectionPermitHalf,
+        keep_alive: KeepAlive,
+    ) -> Self {
         Self {
-            inner: KeepAliveStream::new(MessageStream::new(stream), KEEP_ALIVE_RECV_INTERVAL),
+            inner: KeepAliveStream::new(MessageStream::new(stream), keep_alive.config().timeout),
             permit,
+            keep_alive,
         }
     }
 }
@@ -343,6 +351,11 @@ impl Stream for PermittedStream {
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match ready!(self.inner.poll_next_unpin(cx)) {
             Some(Ok(message)) => Poll::Ready(Some((self.permit.id(), message))),
+            Some(Err(error)) if error.kind() == io::ErrorKind::TimedOut => {
+                tracing::debug!(addr = ?self.permit.addr(), "Peer stopped responding");
+                self.keep_alive.notify_timeout();
+                Poll::Ready(None)
+            }
             Some(Err(_)) | None => Poll::Ready(None),
         }
     }
@@ -356,9 +369,13 @@ struct PermittedSink {
 }
 
 impl PermittedSink {
-    fn new(stream: raw::OwnedWriteHalf, permit: ConnectionPermitHalf) -> Self {
+    fn new(
+        stream: raw::OwnedWriteHalf,
+        permit: ConnectionPermitHalf,
+        keep_alive: &KeepAlive,
+    ) -> Self {
         Self {
-            inner: KeepAliveSink::new(MessageSink::new(stream), KEEP_ALIVE_SEND_INTERVAL),
+            inner: KeepAliveSink::new(MessageSink::new(stream), keep_alive.config().interval),
             _permit: permit,
         }
     }
@@ -596,9 +613,10 @@ impl Future for Send<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::keep_alive::KeepAliveConfig;
     use assert_matches::assert_matches;
     use net::tcp::{TcpListener, TcpStream};
-    use std::{net::Ipv4Addr, str::from_utf8};
+    use std::{net::Ipv4Addr, str::from_utf8, time::Duration};
 
     #[tokio::test(flavor = "multi_thread")]
     async fn recv_on_stream() {
@@ -753,6 +771,29 @@ mod tests {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn multi_stream_peer_timeout() {
+        let (_client, server) = create_connected_sockets().await;
+        let (server_reader, _server_writer) = server.into_split();
+
+        let keep_alive = KeepAlive::new(KeepAliveConfig {
+            interval: Duration::from_millis(50),
+            timeout: Duration::from_millis(100),
+        });
+        let mut on_timeout = keep_alive.on_timeout();
+
+        let stream = MultiStream::new();
+        stream.add(PermittedStream::new(
+            server_reader,
+            ConnectionPermit::dummy().split().0,
+            keep_alive,
+        ));
+
+        // The client never sends anything so the stream times out.
+        assert!(stream.recv().await.is_none());
+        on_timeout.changed().await.unwrap();
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn multi_stream_close() {
         let (client, server) = create_connected_sockets().await;
@@ -762,6 +803,7 @@ mod tests {
         stream.add(PermittedStream::new(
             server_reader,
             ConnectionPermit::dummy().split().0,
+            KeepAlive::default(),
         ));
 
         let mut client = MessageSink::new(client);
@@ -783,7 +825,7 @@ mod tests {
         let (client, server) = create_connected_sockets().await;
         let client_writer = MessageSink::new(client);
 
-        let server_dispatcher = MessageDispatcher::new();
+        let server_dispatcher = MessageDispatcher::new(KeepAlive::default());
         server_dispatcher.bind(server, ConnectionPermit::dummy());
 
         (client_writer, server_dispatcher)
@@ -792,10 +834,10 @@ mod tests {
     async fn setup_two_dispatchers() -> (MessageDispatcher, MessageDispatcher) {
         let (client, server) = create_connected_sockets().await;
 
-        let client_dispatcher = MessageDispatcher::new();
+        let client_dispatcher = MessageDispatcher::new(KeepAlive::default());
         client_dispatcher.bind(client, ConnectionPermit::dummy());
 
-        let server_dispatcher = MessageDispatcher::new();
+        let server_dispatcher = MessageDispatcher::new(KeepAlive::default());
         server_dispatcher.bind(server, ConnectionPermit::dummy());
 
         (client_dispatcher, server_dispatcher)
@@ -1,20 +1,21 @@
 pub mod dht_discovery;
-pub mod peer_addr;
 
+mod access_notice;
 mod barrier;
 mod choke;
 mod client;
 mod connection;
 mod connection_monitor;
+mod connection_stats;
 mod constants;
 mod crypto;
-mod debug_payload;
 mod gateway;
 mod interface;
+mod invitation;
 mod ip;
 mod keep_alive;
 mod local_discovery;
-mod message;
+pub(crate) mod message;
 mod message_broker;
 mod message_dispatcher;
 mod message_io;
@@ -24,40 +25,59 @@ mod peer_source;
 mod peer_state;
 mod pending;
 mod protocol;
+mod protocol_trace;
 mod raw;
+mod request_queue;
 mod runtime_id;
 mod seen_peers;
 mod server;
+mod snapshot_throttle;
 mod stun;
 mod stun_server_list;
+mod tags;
 #[cfg(test)]
 mod tests;
 mod traffic_tracker;
 mod upnp;
+mod verifier;
+mod wipe_directive;
 
 pub use self::{
     connection::PeerInfoCollector,
-    peer_info::PeerInfo,
+    connection_stats::{ConnectionOutcomes, ConnectionStats, ConnectionType},
+    gateway::BindStatus,
+    keep_alive::KeepAliveConfig,
+    peer_info::{EncryptionInfo, PeerInfo, TransportEncryption},
     peer_source::PeerSource,
     peer_state::PeerState,
+    protocol_trace::{MessageDirection, PeerProtocolTrace, TracedMessage},
     runtime_id::{PublicRuntimeId, SecretRuntimeId},
+    tags::RoutingTags,
     traffic_tracker::TrafficStats,
 };
 pub use net::stun::NatBehavior;
+pub use ouisync_protocol::{message::MirrorReceipt, peer_addr};
+
+pub(crate) use ouisync_protocol::message::debug_payload;
 
 use self::{
     connection::{ConnectionDeduplicator, ConnectionPermit, ReserveResult},
     connection_monitor::ConnectionMonitor,
+    connection_stats::ConnectionStatsTracker,
     dht_discovery::{DhtContactsStoreTrait, DhtDiscovery},
     gateway::{Gateway, StackAddresses},
+    keep_alive::KeepAlive,
     local_discovery::LocalDiscovery,
     message_broker::MessageBroker,
     peer_addr::{PeerAddr, PeerPort},
     peer_exchange::{PexDiscovery, PexRepository},
     protocol::{Version, MAGIC, VERSION},
+    protocol_trace::ProtocolTracer,
     seen_peers::{SeenPeer, SeenPeers},
     stun::StunClients,
+    tags::TagPeerSources,
     traffic_tracker::TrafficTracker,
+    verifier::Verifier,
 };
 use crate::{
     collections::{hash_map::Entry, HashMap, HashSet},
@@ -67,6 +87,7 @@ use crate::{
 use backoff::{backoff::Backoff, ExponentialBackoffBuilder};
 use btdht::{self, InfoHash, INFO_HASH_LEN};
 use deadlock::BlockingMutex;
+use rand::{rngs::OsRng, CryptoRng, Rng};
 use scoped_task::ScopedAbortHandle;
 use slab::Slab;
 use state_monitor::StateMonitor;
@@ -74,11 +95,15 @@ use std::{
     future::Future,
     io, mem,
     net::{SocketAddr, SocketAddrV4, SocketAddrV6},
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
 };
 use thiserror::Error;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
+    select,
     sync::mpsc,
     task::{AbortHandle, JoinSet},
     time::Duration,
@@ -87,6 +112,7 @@ use tracing::{Instrument, Span};
 
 const DHT_ENABLED: &str = "dht_enabled";
 const PEX_ENABLED: &str = "pex_enabled";
+const ROUTING_TAGS: &str = "routing_tags";
 
 pub struct Network {
     inner: Arc<Inner>,
@@ -100,9 +126,22 @@ impl Network {
     pub fn new(
         dht_contacts: Option<Arc<dyn DhtContactsStoreTrait>>,
         monitor: StateMonitor,
+    ) -> Self {
+        Self::with_rng(dht_contacts, monitor, &mut OsRng)
+    }
+
+    /// Like `new` but generates the runtime id (the identity of this replica in the network, see
+    /// `this_runtime_id`) with the given generator, e.g. to make tests reproducible. The other
+    /// randomness in the networking (e.g., the timing jitter) is not affected.
+    pub fn with_rng<R: Rng + CryptoRng>(
+        dht_contacts: Option<Arc<dyn DhtContactsStoreTrait>>,
+        monitor: StateMonitor,
+        rng: &mut R,
     ) -> Self {
         let (incoming_tx, incoming_rx) = mpsc::channel(1);
-        let gateway = Gateway::new(incoming_tx);
+        let keep_alive = KeepAlive::default();
+        let connection_stats = ConnectionStatsTracker::new(monitor.make_child("NAT traversal"));
+        let gateway = Gateway::new(incoming_tx, keep_alive.clone(), connection_stats.clone());
 
         // Note that we're now only using quic for the transport discovered over the dht.
         // This is because the dht doesn't let us specify whether the remote peer SocketAddr is
@@ -123,7 +162,7 @@ impl Network {
 
         let user_provided_peers = SeenPeers::new();
 
-        let this_runtime_id = SecretRuntimeId::random();
+        let this_runtime_id = Arc::new(SecretRuntimeId::generate(rng));
         let this_runtime_id_public = this_runtime_id.public();
 
         let connections_monitor = monitor.make_child("Connections");
@@ -136,6 +175,12 @@ impl Network {
             connections_monitor,
             peers_monitor,
             traffic_tracker: TrafficTracker::new(),
+            protocol_tracer: ProtocolTracer::new(),
+            verifier: Verifier::default(),
+            keep_alive,
+            lan_bulk_transfer_enabled: AtomicBool::new(false),
+            tag_peer_sources: TagPeerSources::default(),
+            connection_stats,
             span: Span::current(),
             gateway,
             this_runtime_id,
@@ -165,6 +210,7 @@ impl Network {
         inner.spawn(inner.clone().handle_incoming_connections(incoming_rx));
         inner.spawn(inner.clone().run_dht(dht_discovery_rx));
         inner.spawn(inner.clone().run_peer_exchange(pex_discovery_rx));
+        inner.spawn(inner.clone().handle_interface_changes());
 
         tracing::debug!(this_runtime_id = ?this_runtime_id_public.as_public_key(), "Network created");
 
@@ -177,16 +223,35 @@ impl Network {
     /// Binds the network to the specified addresses.
     /// Rebinds if already bound. Unbinds and disables the network if `addrs` is empty.
     ///
-    /// NOTE: currently at most one address per protocol (QUIC/TCP) and family (IPv4/IPv6) is used
-    /// and the rest are ignored, but this might change in the future.
+    /// Multiple addresses per protocol (QUIC/TCP) and family (IPv4/IPv6) can be given, for example
+    /// to listen on both a LAN and a VPN interface. The first QUIC address of each family is the
+    /// primary one which is also used for the outgoing QUIC connections, DHT and STUN.
     pub async fn bind(&self, addrs: &[PeerAddr]) {
         self.inner.bind(addrs).await
     }
 
+    /// Should be called when the device switches networks (e.g., from WiFi to cellular). Rebinds
+    /// the listeners to the same addresses and ports, refreshes the external addresses (STUN, DHT,
+    /// port forwarding) and retries the pending connection attempts immediately instead of waiting
+    /// for their backoff to expire.
+    ///
+    /// Changes of the network interfaces are also detected automatically where supported, but the
+    /// detection is periodic so calling this from the platform's network change callback makes the
+    /// reaction faster.
+    pub async fn handle_network_change(&self) {
+        self.inner.handle_network_change().await
+    }
+
     pub fn listener_local_addrs(&self) -> Vec<PeerAddr> {
         self.inner.gateway.listener_local_addrs()
     }
 
+    /// Returns the outcome of the last bind for each requested listener: whether it succeeded,
+    /// the actually bound address and the error if any.
+    pub fn bind_status(&self) -> Vec<BindStatus> {
+        self.inner.gateway.bind_status()
+    }
+
     pub fn set_port_forwarding_enabled(&self, enabled: bool) {
         let mut state = self.inner.port_forwarder_state.lock().unwrap();
 
@@ -249,7 +314,22 @@ impl Network {
     /// Determine the behaviour of the NAT we are behind. Returns `None` on unknown.
     /// Currently IPv4 only.
     pub async fn nat_behavior(&self) -> Option<NatBehavior> {
-        self.inner.stun_clients.nat_behavior().await
+        let nat = self.inner.stun_clients.nat_behavior().await;
+        self.inner.connection_stats.set_nat_behavior(nat);
+        nat
+    }
+
+    /// Returns the outcomes of the outgoing connection attempts (whether they were direct,
+    /// hole-punched, relayed or failed) aggregated per the behavior of the NAT we were behind.
+    /// They are also reported in the state monitor.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.inner.connection_stats.get()
+    }
+
+    /// Adds previously collected connection stats (e.g., persisted from a previous run) to the
+    /// current ones.
+    pub fn merge_connection_stats(&self, stats: &ConnectionStats) {
+        self.inner.connection_stats.merge(stats)
     }
 
     /// Get the network traffic stats (total bytes sent and received).
@@ -257,6 +337,71 @@ impl Network {
         self.inner.traffic_tracker.get()
     }
 
+    /// Enables recording of the last `capacity` protocol messages exchanged with each peer (only
+    /// their kinds and sizes, not the content), for diagnosing sync problems. Zero disables the
+    /// recording and discards the recorded messages. Disabled by default.
+    pub fn set_protocol_trace_capacity(&self, capacity: usize) {
+        self.inner.protocol_tracer.set_capacity(capacity)
+    }
+
+    pub fn protocol_trace_capacity(&self) -> usize {
+        self.inner.protocol_tracer.capacity()
+    }
+
+    /// Returns the protocol messages recorded per peer, see `set_protocol_trace_capacity`.
+    pub fn protocol_trace(&self) -> Vec<PeerProtocolTrace> {
+        self.inner.protocol_tracer.dump()
+    }
+
+    /// Sets the number of threads used to verify (hash, check signatures) the data received from
+    /// the peers. Lower values reduce the CPU load (and the latency of the other tasks) during
+    /// sync at the expense of the sync speed.
+    pub fn set_verify_threads(&self, threads: usize) {
+        self.inner.verifier.set_threads(threads)
+    }
+
+    pub fn verify_threads(&self) -> usize {
+        self.inner.verifier.threads()
+    }
+
+    /// Sets the keep-alive (ping) interval and the timeout after which an unresponsive peer is
+    /// disconnected. Applies to the connections established afterwards. The keep-alive of the
+    /// underlying QUIC transport is updated on the next rebind. The values are clamped to the bounds
+    /// required by the protocol (see [`KeepAliveConfig`]).
+    pub fn set_keep_alive(&self, config: KeepAliveConfig) {
+        self.inner.keep_alive.set_config(config)
+    }
+
+    pub fn keep_alive(&self) -> KeepAliveConfig {
+        self.inner.keep_alive.config()
+    }
+
+    /// Enables/disables bulk transfer with the peers on the local network. With it the blocks are
+    /// requested in batches and many more requests are kept in flight, which speeds up syncing over
+    /// a fast LAN but puts more load on the peer, so it should be enabled only when the devices on
+    /// the local network are trusted. Applies to the connections established afterwards.
+    pub fn set_lan_bulk_transfer_enabled(&self, enabled: bool) {
+        self.inner
+            .lan_bulk_transfer_enabled
+            .store(enabled, Ordering::Relaxed)
+    }
+
+    pub fn is_lan_bulk_transfer_enabled(&self) -> bool {
+        self.inner.lan_bulk_transfer_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Restricts the repositories with the given routing tag (see `Registration::set_tags`) to be
+    /// served only by the peers from the given sources. For example, restricting a tag to
+    /// `UserProvided` keeps its repositories off the DHT and the local discovery. `None` removes
+    /// the restriction. Applies to the connections established afterwards.
+    pub fn set_tag_peer_sources(&self, tag: &str, sources: Option<HashSet<PeerSource>>) {
+        self.inner.tag_peer_sources.set(tag.to_owned(), sources);
+    }
+
+    pub fn tag_peer_sources(&self, tag: &str) -> Option<HashSet<PeerSource>> {
+        self.inner.tag_peer_sources.get(tag)
+    }
+
     pub fn add_user_provided_peer(&self, peer: &PeerAddr) {
         self.inner.clone().establish_user_provided_connection(peer);
     }
@@ -290,6 +435,11 @@ impl Network {
         self.inner.on_protocol_mismatch_tx.subscribe()
     }
 
+    /// Subscribe to events of peers being disconnected because they stopped responding.
+    pub fn on_peer_timeout(&self) -> uninitialized_watch::Receiver<()> {
+        self.inner.keep_alive.on_timeout()
+    }
+
     /// Subscribe change in connected peers events.
     pub fn on_peer_set_change(&self) -> uninitialized_watch::Receiver<()> {
         self.inner.connection_deduplicator.on_change()
@@ -318,8 +468,15 @@ impl Network {
             .await
             .unwrap_or(Some(false))
             .unwrap_or(false);
+        let tags = metadata
+            .get::<String>(ROUTING_TAGS)
+            .await
+            .ok()
+            .flatten()
+            .map(|tags| tags::decode(&tags))
+            .unwrap_or_default();
 
-        let dht = if dht_enabled {
+        let dht = if dht_enabled && self.inner.tag_peer_sources.allows(&tags, PeerSource::Dht) {
             Some(
                 self.inner
                     .start_dht_lookup(repository_info_hash(handle.vault.repository_id())),
@@ -335,10 +492,14 @@ impl Network {
 
         let mut network_state = self.inner.state.lock().unwrap();
 
-        network_state.create_link(handle.vault.clone(), &pex, &choke_manager);
+        network_state.create_link(handle.vault.clone(), &pex, &choke_manager, |source| {
+            self.inner.tag_peer_sources.allows(&tags, source)
+        });
 
         let key = network_state.registry.insert(RegistrationHolder {
             vault: handle.vault,
+            tags,
+            dht_enabled,
             dht,
             pex,
             choke_manager,
@@ -385,14 +546,8 @@ impl Registration {
         let mut state = self.inner.state.lock().unwrap();
         let holder = &mut state.registry[self.key];
 
-        if enabled {
-            holder.dht = Some(
-                self.inner
-                    .start_dht_lookup(repository_info_hash(holder.vault.repository_id())),
-            );
-        } else {
-            holder.dht = None;
-        }
+        holder.dht_enabled = enabled;
+        self.update_dht(holder);
     }
 
     /// This function provides the information to the user whether DHT is enabled for this
@@ -401,7 +556,7 @@ impl Registration {
     /// is disabled.
     pub fn is_dht_enabled(&self) -> bool {
         let state = self.inner.state.lock().unwrap();
-        state.registry[self.key].dht.is_some()
+        state.registry[self.key].dht_enabled
     }
 
     pub async fn set_pex_enabled(&self, enabled: bool) {
@@ -415,6 +570,45 @@ impl Registration {
         let state = self.inner.state.lock().unwrap();
         state.registry[self.key].pex.is_enabled()
     }
+
+    /// Sets the routing tags of this repository. They restrict which peer sources serve the
+    /// repository (see `Network::set_tag_peer_sources`). Apply to the connections established
+    /// afterwards.
+    pub async fn set_tags(&self, tags: RoutingTags) {
+        let metadata = self.inner.state.lock().unwrap().registry[self.key]
+            .vault
+            .metadata();
+        metadata.set(ROUTING_TAGS, tags::encode(&tags)).await.ok();
+
+        let mut state = self.inner.state.lock().unwrap();
+        let holder = &mut state.registry[self.key];
+
+        holder.tags = tags;
+        self.update_dht(holder);
+    }
+
+    pub fn tags(&self) -> RoutingTags {
+        let state = self.inner.state.lock().unwrap();
+        state.registry[self.key].tags.clone()
+    }
+
+    // Starts or stops the DHT lookup according to whether DHT is enabled and allowed by the tags.
+    fn update_dht(&self, holder: &mut RegistrationHolder) {
+        let allowed = holder.dht_enabled
+            && self
+                .inner
+                .tag_peer_sources
+                .allows(&holder.tags, PeerSource::Dht);
+
+        if !allowed {
+            holder.dht = None;
+        } else if holder.dht.is_none() {
+            holder.dht = Some(
+                self.inner
+                    .start_dht_lookup(repository_info_hash(holder.vault.repository_id())),
+            );
+        }
+    }
 }
 
 impl Drop for Registration {
@@ -438,6 +632,8 @@ async fn set_metadata_bool(inner: &Inner, key: usize, name: &str, value: bool) {
 
 struct RegistrationHolder {
     vault: Vault,
+    tags: RoutingTags,
+    dht_enabled: bool,
     dht: Option<dht_discovery::LookupRequest>,
     pex: PexRepository,
     choke_manager: choke::Manager,
@@ -448,9 +644,15 @@ struct Inner {
     connections_monitor: StateMonitor,
     peers_monitor: StateMonitor,
     traffic_tracker: TrafficTracker,
+    protocol_tracer: ProtocolTracer,
+    verifier: Verifier,
+    keep_alive: KeepAlive,
+    lan_bulk_transfer_enabled: AtomicBool,
+    tag_peer_sources: TagPeerSources,
+    connection_stats: ConnectionStatsTracker,
     span: Span,
     gateway: Gateway,
-    this_runtime_id: SecretRuntimeId,
+    this_runtime_id: Arc<SecretRuntimeId>,
     state: BlockingMutex<State>,
     port_forwarder: upnp::PortForwarder,
     port_forwarder_state: BlockingMutex<ComponentState<PortMappings>>,
@@ -477,10 +679,18 @@ struct State {
 }
 
 impl State {
-    fn create_link(&mut self, repo: Vault, pex: &PexRepository, choke_manager: &choke::Manager) {
+    fn create_link(
+        &mut self,
+        repo: Vault,
+        pex: &PexRepository,
+        choke_manager: &choke::Manager,
+        allowed: impl Fn(PeerSource) -> bool,
+    ) {
         if let Some(brokers) = &mut self.message_brokers {
             for broker in brokers.values_mut() {
-                broker.create_link(repo.clone(), pex, choke_manager)
+                if allowed(broker.source()) {
+                    broker.create_link(repo.clone(), pex, choke_manager)
+                }
             }
         }
     }
@@ -492,15 +702,52 @@ impl Inner {
     }
 
     async fn bind(self: &Arc<Self>, bind: &[PeerAddr]) {
-        let conn = Connectivity::infer(bind);
+        // TODO: Would be preferable to only rebind those stacks that actually need rebinding.
+        if !self
+            .gateway
+            .addresses()
+            .any_stack_needs_rebind(&StackAddresses::from(bind))
+        {
+            return;
+        }
 
-        let bind = StackAddresses::from(bind);
+        self.rebind(bind).await
+    }
 
-        // TODO: Would be preferable to only rebind those stacks that actually need rebinding.
-        if !self.gateway.addresses().any_stack_needs_rebind(&bind) {
+    // Binds to the same addresses again, on fresh sockets. This refreshes the external addresses
+    // (STUN, DHT, port mappings) and retries the pending connection attempts immediately.
+    async fn handle_network_change(self: &Arc<Self>) {
+        let addrs = self.gateway.rebind_addrs();
+
+        if addrs.is_empty() {
             return;
         }
 
+        tracing::info!(parent: &self.span, "Network changed - rebinding");
+
+        // Close the current sockets first so their ports can be reused.
+        self.gateway.unbind().instrument(self.span.clone()).await;
+        self.rebind(&addrs).await;
+        self.gateway.notify_network_change();
+    }
+
+    async fn handle_interface_changes(self: Arc<Self>) {
+        let mut watcher = interface::watch_interface_addrs();
+
+        while watcher.recv().await.is_some() {
+            if self.is_shutdown() {
+                break;
+            }
+
+            self.handle_network_change().await;
+        }
+    }
+
+    async fn rebind(self: &Arc<Self>, bind: &[PeerAddr]) {
+        let conn = Connectivity::infer(bind);
+
+        let bind = StackAddresses::from(bind);
+
         // Gateway
         let side_channel_makers = self.gateway.bind(&bind).instrument(self.span.clone()).await;
 
@@ -515,6 +762,24 @@ impl Inner {
             side_channel_maker_v6.as_ref().map(|m| m.make()),
         );
 
+        // Detect the NAT behavior in the background so the outcomes of the connection attempts
+        // can be attributed to it.
+        match conn {
+            Connectivity::Full => {
+                let this = self.clone();
+                self.spawn(
+                    async move {
+                        let nat = this.stun_clients.nat_behavior().await;
+                        this.connection_stats.set_nat_behavior(nat);
+                    }
+                    .instrument(self.span.clone()),
+                );
+            }
+            Connectivity::LocalOnly | Connectivity::Disabled => {
+                self.connection_stats.set_nat_behavior(None);
+            }
+        }
+
         // DHT
         self.dht_discovery
             .rebind(side_channel_maker_v4, side_channel_maker_v6);
@@ -692,6 +957,7 @@ impl Inner {
                         )
                     });
                     monitor.mark_as_connecting(permit.id());
+                    permit.set_connection_type(ConnectionType::Direct);
 
                     self.spawn(async move {
                         this.handle_connection(stream, permit, &monitor).await;
@@ -737,7 +1003,11 @@ impl Inner {
 
             if let Some(sleep) = next_sleep {
                 tracing::debug!(parent: monitor.span(), "Next connection attempt in {:?}", sleep);
-                tokio::time::sleep(sleep).await;
+
+                select! {
+                    _ = tokio::time::sleep(sleep) => (),
+                    _ = self.gateway.on_network_change() => backoff.reset(),
+                }
             }
 
             next_sleep = backoff.next_backoff();
@@ -774,7 +1044,10 @@ impl Inner {
                 .instrument(monitor.span().clone())
                 .await
             {
-                Some(socket) => socket,
+                Some((socket, connection_type)) => {
+                    permit.set_connection_type(connection_type);
+                    socket
+                }
                 None => break,
             };
 
@@ -843,15 +1116,22 @@ impl Inner {
                         .peers_monitor
                         .make_child(format!("{:?}", that_runtime_id.as_public_key()));
 
+                    let bulk = self.lan_bulk_transfer_enabled.load(Ordering::Relaxed)
+                        && !ip::is_global(&permit.addr().ip());
+
                     let mut broker = self.span.in_scope(|| {
                         MessageBroker::new(
-                            self.this_runtime_id.public(),
+                            self.this_runtime_id.clone(),
                             that_runtime_id,
                             stream,
                             permit,
                             self.pex_discovery.new_peer(),
                             monitor,
                             self.traffic_tracker.clone(),
+                            self.protocol_tracer.peer(that_runtime_id),
+                            self.verifier.clone(),
+                            self.keep_alive.clone(),
+                            bulk,
                         )
                     });
 
@@ -859,6 +1139,10 @@ impl Inner {
                     // lookup but make sure we correctly handle edge cases, for example, when we have
                     // more than one repository shared with the peer.
                     for (_, holder) in &state.registry {
+                        if !self.tag_peer_sources.allows(&holder.tags, broker.source()) {
+                            continue;
+                        }
+
                         broker.create_link(
                             holder.vault.clone(),
                             &holder.pex,
@@ -1,18 +1,25 @@
 //! Peer exchange - a mechanism by which peers exchange information about other peers with each
 //! other in order to discover new peers.
+//!
+//! It's also used to coordinate hole punching: when we learn about a peer from a mutual peer, we
+//! ask the mutual peer to introduce us to it. The introduced peer then starts connecting to us at
+//! the same time as we are connecting to it, which opens the NAT mappings on both sides
+//! (simultaneous open) instead of relying on the blind periodic punch packets alone.
 
 use super::{
     connection::ConnectionDirection,
     ip,
-    message::Content,
+    message::{Content, PexPayload, PunchPayload},
     peer_addr::PeerAddr,
     seen_peers::{SeenPeer, SeenPeers},
     PeerSource,
 };
-use crate::{collections::HashSet, sync::AwaitDrop};
+use crate::{
+    collections::{HashMap, HashSet},
+    sync::AwaitDrop,
+};
 use rand::Rng;
 use scoped_task::ScopedJoinHandle;
-use serde::{Deserialize, Serialize};
 use slab::Slab;
 use std::{
     fmt,
@@ -32,9 +39,6 @@ const SEND_INTERVAL_RANGE: Range<Duration> = Duration::from_secs(60)..Duration::
 /// Duration of a single round for the `SeenPeers` machinery.
 const ROUND_DURATION: Duration = Duration::from_secs(10 * 60);
 
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) struct PexPayload(HashSet<PeerAddr>);
-
 /// Entry point to the peer exchange.
 pub(crate) struct PexDiscovery {
     state: Arc<RwLock<State>>,
@@ -173,6 +177,7 @@ impl PexPeer {
 
         let rx = PexReceiver {
             repo_id: repo.repo_id,
+            peer_id: self.peer_id,
             state: self.state.clone(),
             seen_peers: self.seen_peers.clone(),
             discover_tx: self.discover_tx.clone(),
@@ -205,6 +210,8 @@ impl PexSender {
             return;
         };
 
+        collector.set_content_tx(content_tx.clone());
+
         loop {
             let addrs = match collector.collect() {
                 Ok(addrs) => addrs,
@@ -243,6 +250,13 @@ impl PexSender {
 pub(crate) struct PexCollector<'a>(&'a PexSender);
 
 impl<'a> PexCollector<'a> {
+    // Makes the link available for sending the hole punching coordination messages.
+    fn set_content_tx(&self, content_tx: mpsc::Sender<Content>) {
+        if let Some(repo) = self.0.state.write().unwrap().repos.get_mut(self.0.repo_id) {
+            repo.links.insert(self.0.peer_id, content_tx);
+        }
+    }
+
     fn collect(&self) -> Result<HashSet<PeerAddr>, CollectError> {
         let state = self.0.state.read().unwrap();
 
@@ -287,6 +301,7 @@ impl<'a> Drop for PexCollector<'a> {
     fn drop(&mut self) {
         if let Some(repo) = self.0.state.write().unwrap().repos.get_mut(self.0.repo_id) {
             repo.peers.remove(&self.0.peer_id);
+            repo.links.remove(&self.0.peer_id);
         }
     }
 }
@@ -315,6 +330,7 @@ impl fmt::Debug for CollectError {
 /// Receives contacts from other peers and pushes them to the peer discovery channel.
 pub(crate) struct PexReceiver {
     repo_id: RepoId,
+    peer_id: PeerId,
     state: Arc<RwLock<State>>,
     seen_peers: SeenPeers,
     discover_tx: mpsc::Sender<SeenPeer>,
@@ -329,10 +345,97 @@ impl PexReceiver {
         for addr in payload.0 {
             if let Some(seen_peer) = self.seen_peers.insert(addr) {
                 self.discover_tx.send(seen_peer).await.ok();
+
+                if is_punchable(&addr) {
+                    self.send_to(self.peer_id, PunchPayload::Request { target: addr });
+                }
             }
         }
     }
 
+    pub async fn handle_punch(&self, payload: PunchPayload) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        match payload {
+            PunchPayload::Request { target } => self.introduce(target),
+            PunchPayload::Introduce { addrs } => {
+                for addr in addrs {
+                    if !is_punchable(&addr) {
+                        continue;
+                    }
+
+                    if let Some(seen_peer) = self.seen_peers.insert(addr) {
+                        self.discover_tx.send(seen_peer).await.ok();
+                    }
+                }
+            }
+        }
+    }
+
+    // Introduces the peer on this link to the peer with the `target` address, if that one is
+    // connected to us and shares the repository.
+    fn introduce(&self, target: PeerAddr) {
+        let (target_id, addrs) = {
+            let state = self.state.read().unwrap();
+
+            let Some(repo) = state.repos.get(self.repo_id) else {
+                return;
+            };
+
+            let Some(target_id) = repo
+                .links
+                .keys()
+                .filter(|peer_id| **peer_id != self.peer_id)
+                .find(|peer_id| {
+                    state
+                        .peers
+                        .get(**peer_id)
+                        .map(|peer| peer.addrs.contains(&target))
+                        .unwrap_or(false)
+                })
+                .copied()
+            else {
+                return;
+            };
+
+            // Send only the addresses the target can punch a hole to (the addresses of the
+            // requester as seen by us).
+            let addrs: HashSet<_> = state
+                .peers
+                .get(self.peer_id)
+                .into_iter()
+                .flat_map(|peer| &peer.addrs)
+                .filter(|addr| is_punchable(addr))
+                .copied()
+                .collect();
+
+            (target_id, addrs)
+        };
+
+        if addrs.is_empty() {
+            return;
+        }
+
+        self.send_to(target_id, PunchPayload::Introduce { addrs });
+    }
+
+    // Best effort - if the link is busy, the message is dropped.
+    fn send_to(&self, peer_id: PeerId, payload: PunchPayload) {
+        let state = self.state.read().unwrap();
+
+        let Some(content_tx) = state
+            .repos
+            .get(self.repo_id)
+            .and_then(|repo| repo.links.get(&peer_id))
+        else {
+            return;
+        };
+
+        content_tx.try_send(Content::Punch(payload)).ok();
+    }
+
     fn is_enabled(&self) -> bool {
         self.state
             .read()
@@ -359,6 +462,13 @@ struct RepoState {
     enabled: bool,
     // Set of peers sharing this repo.
     peers: HashSet<PeerId>,
+    // Senders of the messages on the links to the peers sharing this repo.
+    links: HashMap<PeerId, mpsc::Sender<Content>>,
+}
+
+// Hole punching applies only to QUIC connections over the internet.
+fn is_punchable(addr: &PeerAddr) -> bool {
+    addr.is_quic() && ip::is_global(&addr.ip())
 }
 
 #[derive(Default)]
@@ -417,6 +527,66 @@ mod tests {
         assert_eq!(c1_collector.collect().unwrap(), into_set([]));
     }
 
+    #[tokio::test]
+    async fn introduce() {
+        let (discover_tx, mut discover_rx) = mpsc::channel(1);
+        let discovery = PexDiscovery::new(discover_tx);
+
+        let repo = discovery.new_repository();
+        repo.set_enabled(true);
+
+        let peer_a = discovery.new_peer();
+        let addr_a = make_global_peer_addr(1);
+        let close_a = DropAwaitable::new();
+        peer_a.handle_connection(addr_a, PeerSource::Dht, close_a.subscribe());
+
+        let peer_c = discovery.new_peer();
+        let addr_c = make_global_peer_addr(2);
+        let close_c = DropAwaitable::new();
+        peer_c.handle_connection(addr_c, PeerSource::Dht, close_c.subscribe());
+
+        let (a_tx, a_rx) = peer_a.new_link(&repo);
+        let (c_tx, c_rx) = peer_c.new_link(&repo);
+
+        let (a_content_tx, _a_content_rx) = mpsc::channel(1);
+        let a_collector = a_tx.enable().unwrap();
+        a_collector.set_content_tx(a_content_tx);
+
+        let (c_content_tx, mut c_content_rx) = mpsc::channel(1);
+        let c_collector = c_tx.enable().unwrap();
+        c_collector.set_content_tx(c_content_tx);
+
+        // A asks us to introduce it to C.
+        a_rx.handle_punch(PunchPayload::Request { target: addr_c })
+            .await;
+
+        match c_content_rx.try_recv().unwrap() {
+            Content::Punch(PunchPayload::Introduce { addrs }) => {
+                assert_eq!(addrs, into_set([addr_a]))
+            }
+            content => panic!("unexpected content: {content:?}"),
+        }
+
+        // Unknown target is ignored.
+        a_rx.handle_punch(PunchPayload::Request {
+            target: make_global_peer_addr(3),
+        })
+        .await;
+        assert!(c_content_rx.try_recv().is_err());
+
+        // Introduced peer is discovered.
+        let addr_d = make_global_peer_addr(4);
+        c_rx.handle_punch(PunchPayload::Introduce {
+            addrs: into_set([addr_d]),
+        })
+        .await;
+        assert_eq!(*discover_rx.recv().await.unwrap().initial_addr(), addr_d);
+    }
+
+    fn make_global_peer_addr(octet: u8) -> PeerAddr {
+        PeerAddr::Quic((Ipv4Addr::new(1, 2, 3, octet), 22222).into())
+    }
+
     fn make_peer_addr() -> PeerAddr {
         static NEXT_OCTET: AtomicU8 = AtomicU8::new(1);
         PeerAddr::Quic(
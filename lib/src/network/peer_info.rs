@@ -1,39 +1,75 @@
-use super::{peer_addr::PeerAddr, peer_source::PeerSource, peer_state::PeerState};
-use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use super::{
+    connection_stats::ConnectionType,
+    crypto,
+    peer_addr::{self, PeerAddr},
+    peer_source::PeerSource,
+    peer_state::PeerState,
+};
+use serde::{Deserialize, Serialize};
 
 /// Information about a peer.
 #[derive(Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize, Debug)]
 pub struct PeerInfo {
-    #[serde(with = "as_str")]
+    #[serde(with = "peer_addr::as_str")]
     pub addr: PeerAddr,
     pub source: PeerSource,
     pub state: PeerState,
+    /// How the connection was established or `None` if not connected yet.
+    pub connection_type: Option<ConnectionType>,
+    /// How the connection is encrypted.
+    pub encryption: EncryptionInfo,
 }
 
 impl PeerInfo {
-    pub(super) fn new(addr: PeerAddr, source: PeerSource, state: PeerState) -> Self {
+    pub(super) fn new(
+        addr: PeerAddr,
+        source: PeerSource,
+        state: PeerState,
+        connection_type: Option<ConnectionType>,
+        secure_links: u32,
+    ) -> Self {
         Self {
             addr,
             source,
             state,
+            connection_type,
+            encryption: EncryptionInfo {
+                transport: TransportEncryption::from_addr(&addr),
+                link_protocol: crypto::PROTOCOL_NAME.to_owned(),
+                secure_links,
+            },
         }
     }
 }
 
-mod as_str {
-    use super::*;
+/// How the connection to a peer is encrypted. There are two layers: the transport (which protects
+/// the whole connection but doesn't authenticate the peer) and the repository links (each one
+/// encrypted with a key derived from the secret id of the repository it syncs).
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize, Debug)]
+pub struct EncryptionInfo {
+    pub transport: TransportEncryption,
+    /// Name of the Noise protocol (including the cipher) used to encrypt the repository links.
+    pub link_protocol: String,
+    /// Number of repository links currently established with this peer using the handshake keyed
+    /// by the secret repository id. If zero, no repository is being synced with the peer yet.
+    pub secure_links: u32,
+}
 
-    pub fn serialize<S>(value: &PeerAddr, s: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        value.to_string().serialize(s)
-    }
+/// Encryption of the transport connection.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Debug)]
+pub enum TransportEncryption {
+    /// Plain TCP. The content is protected by the repository link encryption only.
+    None,
+    /// QUIC with TLS 1.3. The certificates are self-signed and not verified, so this protects
+    /// against passive observers only.
+    Tls13,
+}
 
-    pub fn deserialize<'de, D>(d: D) -> Result<PeerAddr, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        <&str>::deserialize(d)?.parse().map_err(D::Error::custom)
+impl TransportEncryption {
+    fn from_addr(addr: &PeerAddr) -> Self {
+        match addr {
+            PeerAddr::Tcp(_) => Self::None,
+            PeerAddr::Quic(_) => Self::Tls13,
+        }
     }
 }
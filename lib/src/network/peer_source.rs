@@ -9,6 +9,7 @@ use std::fmt;
     Debug,
     Eq,
     PartialEq,
+    Hash,
     Ord,
     PartialOrd,
     Serialize,
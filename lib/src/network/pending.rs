@@ -6,19 +6,69 @@ use super::{
 use crate::{
     block_tracker::{BlockOffer, BlockPromise},
     crypto::{sign::PublicKey, CacheHash, Hash, Hashable},
-    protocol::{Block, BlockId, InnerNodes, LeafNodes, MultiBlockPresence, UntrustedProof},
+    protocol::{
+        Block, BlockId, InnerNode, InnerNodes, LeafNode, LeafNodes, MultiBlockPresence,
+        UntrustedProof, BLOCK_SIZE,
+    },
     repository::RepositoryMonitor,
     sync::delay_map::DelayMap,
 };
 use deadlock::BlockingMutex;
-use std::{future, sync::Arc, task::ready};
+use std::{
+    future, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::ready,
+};
 use std::{task::Poll, time::Instant};
 use tokio::{sync::OwnedSemaphorePermit, task};
 
 pub(crate) enum PendingRequest {
     RootNode(PublicKey, PendingDebugRequest),
-    ChildNodes(Hash, ResponseDisambiguator, PendingDebugRequest),
+    /// Request for the children of the node with the given hash. The `u8` is the depth of the
+    /// requested nodes in the tree (0 for the children of the root node).
+    ChildNodes(Hash, ResponseDisambiguator, u8, PendingDebugRequest),
     Block(BlockOffer, PendingDebugRequest),
+    /// Batch of block requests sent as a single message. Used only with bulk transfer.
+    Blocks(Vec<BlockOffer>, PendingDebugRequest),
+    /// Batch of child nodes requests for the nodes at the same depth, sent as a single message.
+    /// Used only with bulk transfer.
+    ChildNodesBatch(Vec<(Hash, ResponseDisambiguator)>, u8, PendingDebugRequest),
+}
+
+impl PendingRequest {
+    /// Number of responses this request expects (the number of requests in a batch, one
+    /// otherwise).
+    pub fn response_count(&self) -> usize {
+        match self {
+            Self::Blocks(offers, _) => offers.len(),
+            Self::ChildNodesBatch(nodes, _, _) => nodes.len(),
+            Self::RootNode(..) | Self::ChildNodes(..) | Self::Block(..) => 1,
+        }
+    }
+
+    /// Splits a batch into two at the given index. Returns the part after the index or `None` if
+    /// there is none.
+    pub fn split_off(&mut self, at: usize) -> Option<Self> {
+        if at >= self.response_count() {
+            return None;
+        }
+
+        match self {
+            Self::Blocks(offers, _) => Some(Self::Blocks(
+                offers.split_off(at),
+                PendingDebugRequest::start(),
+            )),
+            Self::ChildNodesBatch(nodes, depth, _) => Some(Self::ChildNodesBatch(
+                nodes.split_off(at),
+                *depth,
+                PendingDebugRequest::start(),
+            )),
+            Self::RootNode(..) | Self::ChildNodes(..) | Self::Block(..) => None,
+        }
+    }
 }
 
 pub(super) struct PendingResponse {
@@ -27,6 +77,9 @@ pub(super) struct PendingResponse {
     // afterwards.
     pub _client_permit: Option<ClientPermit>,
     pub block_promise: Option<BlockPromise>,
+    // Depth of the received nodes in the tree. Zero for the other response types or if the
+    // request timeouted.
+    pub depth: u8,
 }
 
 pub(super) enum ProcessedResponse {
@@ -57,6 +110,22 @@ impl ProcessedResponse {
             Self::BlockError(block_id, _) => Key::Block(*block_id),
         }
     }
+
+    /// Approximate amount of memory occupied by this response.
+    pub fn estimated_size(&self) -> usize {
+        let payload = match self {
+            Self::InnerNodes(nodes, ..) => nodes.len() * mem::size_of::<InnerNode>(),
+            Self::LeafNodes(nodes, ..) => nodes.len() * mem::size_of::<LeafNode>(),
+            Self::Block(..) => BLOCK_SIZE,
+            Self::RootNode(..)
+            | Self::BlockOffer(..)
+            | Self::RootNodeError(..)
+            | Self::ChildNodesError(..)
+            | Self::BlockError(..) => 0,
+        };
+
+        mem::size_of::<Self>() + payload
+    }
 }
 
 impl From<Response> for ProcessedResponse {
@@ -95,6 +164,7 @@ pub(crate) enum Key {
 pub(super) struct PendingRequests {
     monitor: Arc<RepositoryMonitor>,
     map: Arc<BlockingMutex<DelayMap<Key, RequestData>>>,
+    batches: Arc<BatchSupport>,
 }
 
 impl PendingRequests {
@@ -102,46 +172,177 @@ impl PendingRequests {
         Self {
             monitor,
             map: Arc::new(BlockingMutex::new(DelayMap::default())),
+            batches: Arc::new(BatchSupport::default()),
         }
     }
 
+    /// Returns `false` if a batched request timed out before any batched request has been
+    /// responded to. That means the peer doesn't understand the batched requests (it's on a version
+    /// which predates them and drops them as undecodable) and the client should fall back to
+    /// sending the requests one by one.
+    pub fn batches_supported(&self) -> bool {
+        self.batches.answered.load(Ordering::Relaxed)
+            || !self.batches.expired.load(Ordering::Relaxed)
+    }
+
+    /// Inserts the request and returns the message to send or `None` if it's already in-flight (or
+    /// the offer(s) it's for have been rejected). `permits` contains the link and peer permits for
+    /// each of the expected responses (see `PendingRequest::response_count`). `window_permit` is
+    /// the child nodes request window permit (see `RequestQueue`) and is held until the response
+    /// is processed (for a batch, until the response to its first inserted request is processed).
     pub fn insert(
         &self,
         pending_request: PendingRequest,
-        link_permit: OwnedSemaphorePermit,
-        peer_permit: OwnedSemaphorePermit,
+        permits: Vec<(OwnedSemaphorePermit, OwnedSemaphorePermit)>,
+        window_permit: Option<OwnedSemaphorePermit>,
     ) -> Option<Request> {
-        let (key, block_promise, request) = match pending_request {
-            PendingRequest::RootNode(public_key, debug) => (
-                Key::RootNode(public_key),
-                None,
-                Request::RootNode(public_key, debug.send()),
-            ),
-            PendingRequest::ChildNodes(hash, disambiguator, debug) => (
-                Key::ChildNodes(hash, disambiguator),
-                None,
-                Request::ChildNodes(hash, disambiguator, debug.send()),
-            ),
-            PendingRequest::Block(offer, debug) => {
-                let promise = offer.accept()?;
-                let block_id = *promise.block_id();
+        let mut permits = permits.into_iter();
 
-                (
-                    Key::Block(block_id),
-                    Some(promise),
-                    Request::Block(block_id, debug.send()),
+        match pending_request {
+            PendingRequest::RootNode(public_key, debug) => {
+                let (link, peer) = permits.next()?;
+                self.try_insert(
+                    Key::RootNode(public_key),
+                    None,
+                    0,
+                    RequestPermits {
+                        link,
+                        peer,
+                        window: None,
+                    },
+                    false,
                 )
+                .ok()?;
+
+                Some(Request::RootNode(public_key, debug.send()))
             }
-        };
+            PendingRequest::ChildNodes(hash, disambiguator, depth, debug) => {
+                let (link, peer) = permits.next()?;
+                self.try_insert(
+                    Key::ChildNodes(hash, disambiguator),
+                    None,
+                    depth,
+                    RequestPermits {
+                        link,
+                        peer,
+                        window: window_permit,
+                    },
+                    false,
+                )
+                .ok()?;
+
+                Some(Request::ChildNodes(hash, disambiguator, debug.send()))
+            }
+            PendingRequest::Block(offer, debug) => {
+                let (link, peer) = permits.next()?;
+                let block_id = self.try_insert_block(offer, link, peer, false)?;
+
+                Some(Request::Block(block_id, debug.send()))
+            }
+            PendingRequest::Blocks(offers, debug) => {
+                let block_ids: Vec<_> = offers
+                    .into_iter()
+                    .zip(permits)
+                    .filter_map(|(offer, (link, peer))| {
+                        self.try_insert_block(offer, link, peer, true)
+                    })
+                    .collect();
+
+                if block_ids.is_empty() {
+                    None
+                } else {
+                    Some(Request::Blocks(block_ids, debug.send()))
+                }
+            }
+            PendingRequest::ChildNodesBatch(nodes, depth, debug) => {
+                let mut window_permit = window_permit;
+
+                let nodes: Vec<_> = nodes
+                    .into_iter()
+                    .zip(permits)
+                    .filter_map(|((hash, disambiguator), (link, peer))| {
+                        let permits = RequestPermits {
+                            link,
+                            peer,
+                            window: window_permit.take(),
+                        };
+
+                        match self.try_insert(
+                            Key::ChildNodes(hash, disambiguator),
+                            None,
+                            depth,
+                            permits,
+                            true,
+                        ) {
+                            Ok(()) => Some((hash, disambiguator)),
+                            Err(permits) => {
+                                // Pass the window permit on to the next request in the batch.
+                                window_permit = permits.window;
+                                None
+                            }
+                        }
+                    })
+                    .collect();
+
+                if nodes.is_empty() {
+                    None
+                } else {
+                    Some(Request::ChildNodesBatch(nodes, debug.send()))
+                }
+            }
+        }
+    }
+
+    fn try_insert_block(
+        &self,
+        offer: BlockOffer,
+        link: OwnedSemaphorePermit,
+        peer: OwnedSemaphorePermit,
+        batched: bool,
+    ) -> Option<BlockId> {
+        let promise = offer.accept()?;
+        let block_id = *promise.block_id();
 
+        self.try_insert(
+            Key::Block(block_id),
+            Some(promise),
+            0,
+            RequestPermits {
+                link,
+                peer,
+                window: None,
+            },
+            batched,
+        )
+        .ok()?;
+
+        Some(block_id)
+    }
+
+    fn try_insert(
+        &self,
+        key: Key,
+        block_promise: Option<BlockPromise>,
+        depth: u8,
+        permits: RequestPermits,
+        batched: bool,
+    ) -> Result<(), RequestPermits> {
         let mut map = self.map.lock().unwrap();
 
-        map.try_insert(key)?.insert(
+        let Some(entry) = map.try_insert(key) else {
+            // Give the permits back so the caller can decide what to do with them.
+            return Err(permits);
+        };
+
+        entry.insert(
             RequestData {
                 timestamp: Instant::now(),
                 block_promise,
-                link_permit,
-                _peer_permit: peer_permit,
+                depth,
+                link_permit: permits.link,
+                _peer_permit: permits.peer,
+                window_permit: permits.window,
+                batched,
             },
             REQUEST_TIMEOUT,
         );
@@ -152,43 +353,49 @@ impl PendingRequests {
             task::spawn(run_expiration_tracker(
                 self.monitor.clone(),
                 self.map.clone(),
+                self.batches.clone(),
             ));
         }
 
         request_added(&self.monitor, &key);
 
-        Some(request)
+        Ok(())
     }
 
-    pub fn remove(&self, response: Response) -> PendingResponse {
-        let response = ProcessedResponse::from(response);
+    pub fn remove(&self, response: ProcessedResponse) -> PendingResponse {
         let key = response.to_key();
 
-        let (client_permit, block_promise) =
+        let (client_permit, block_promise, depth) =
             if let Some(request_data) = self.map.lock().unwrap().remove(&key) {
                 request_removed(&self.monitor, &key);
 
+                if request_data.batched {
+                    self.batches.answered.store(true, Ordering::Relaxed);
+                }
+
                 self.monitor
                     .request_latency
                     .record(request_data.timestamp.elapsed());
 
-                // We `drop` the `peer_permit` here but the `Client` will need the `client_permit` and
-                // only `drop` it once the request is processed.
+                // We `drop` the `peer_permit` here but the `Client` will need the `client_permit`
+                // and only `drop` it once the request is processed.
                 let client_permit = Some(ClientPermit {
                     _link_permit: request_data.link_permit,
+                    _window_permit: request_data.window_permit,
                     monitor: self.monitor.clone(),
                 });
                 let block_promise = request_data.block_promise;
 
-                (client_permit, block_promise)
+                (client_permit, block_promise, request_data.depth)
             } else {
-                (None, None)
+                (None, None, 0)
             };
 
         PendingResponse {
             response,
             _client_permit: client_permit,
             block_promise,
+            depth,
         }
     }
 }
@@ -220,10 +427,15 @@ fn request_removed(monitor: &RepositoryMonitor, key: &Key) {
 async fn run_expiration_tracker(
     monitor: Arc<RepositoryMonitor>,
     request_map: Arc<BlockingMutex<DelayMap<Key, RequestData>>>,
+    batches: Arc<BatchSupport>,
 ) {
-    while let Some((key, _)) = expired(&request_map).await {
+    while let Some((key, data)) = expired(&request_map).await {
         monitor.request_timeouts.increment(1);
         request_removed(&monitor, &key);
+
+        if data.batched {
+            batches.expired.store(true, Ordering::Relaxed);
+        }
     }
 }
 
@@ -241,15 +453,36 @@ impl Drop for PendingRequests {
     }
 }
 
+// Permits held by a request while it's in flight (see `PendingRequests::insert`).
+struct RequestPermits {
+    link: OwnedSemaphorePermit,
+    peer: OwnedSemaphorePermit,
+    window: Option<OwnedSemaphorePermit>,
+}
+
 struct RequestData {
     timestamp: Instant,
     block_promise: Option<BlockPromise>,
+    depth: u8,
     link_permit: OwnedSemaphorePermit,
     _peer_permit: OwnedSemaphorePermit,
+    window_permit: Option<OwnedSemaphorePermit>,
+    // Whether the request was sent as part of a batch.
+    batched: bool,
+}
+
+// Tracks whether the peer responds to batched requests.
+#[derive(Default)]
+struct BatchSupport {
+    // Any batched request has been responded to.
+    answered: AtomicBool,
+    // Any batched request has timed out.
+    expired: AtomicBool,
 }
 
 pub(super) struct ClientPermit {
     _link_permit: OwnedSemaphorePermit,
+    _window_permit: Option<OwnedSemaphorePermit>,
     monitor: Arc<RepositoryMonitor>,
 }
 
@@ -258,3 +491,110 @@ impl Drop for ClientPermit {
         self.monitor.requests_pending.decrement(1.0);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_tracker::{BlockTracker, OfferState};
+    use assert_matches::assert_matches;
+    use metrics::NoopRecorder;
+    use state_monitor::StateMonitor;
+    use std::time::Duration;
+    use tokio::{sync::Semaphore, time};
+
+    #[tokio::test(start_paused = true)]
+    async fn unanswered_batch_disables_batching() {
+        let pending = PendingRequests::new(Arc::new(RepositoryMonitor::new(
+            StateMonitor::make_root(),
+            &NoopRecorder,
+        )));
+
+        let tracker = BlockTracker::new();
+        let client = tracker.client();
+
+        let block_id: BlockId = rand::random();
+        tracker.require(block_id);
+        client.register(block_id, OfferState::Approved);
+        let offer = client.offers().try_next().unwrap();
+
+        let semaphore = Arc::new(Semaphore::new(2));
+        let permits = vec![(
+            semaphore.clone().try_acquire_owned().unwrap(),
+            semaphore.clone().try_acquire_owned().unwrap(),
+        )];
+
+        assert!(pending
+            .insert(
+                PendingRequest::Blocks(vec![offer], PendingDebugRequest::start()),
+                permits,
+                None,
+            )
+            .is_some());
+        assert!(pending.batches_supported());
+
+        // The peer never responds (e.g., because it doesn't understand the batch).
+        time::sleep(REQUEST_TIMEOUT + Duration::from_secs(1)).await;
+        task::yield_now().await;
+
+        assert!(!pending.batches_supported());
+    }
+
+    #[tokio::test]
+    async fn batch_keeps_window_permit_when_request_already_in_flight() {
+        let pending = PendingRequests::new(Arc::new(RepositoryMonitor::new(
+            StateMonitor::make_root(),
+            &NoopRecorder,
+        )));
+
+        let disambiguator = ResponseDisambiguator::new(MultiBlockPresence::Full);
+        let hash_a: Hash = rand::random();
+        let hash_b: Hash = rand::random();
+
+        let semaphore = Arc::new(Semaphore::new(6));
+        let acquire = || {
+            (
+                semaphore.clone().try_acquire_owned().unwrap(),
+                semaphore.clone().try_acquire_owned().unwrap(),
+            )
+        };
+
+        assert!(pending
+            .insert(
+                PendingRequest::ChildNodes(hash_a, disambiguator, 0, PendingDebugRequest::start()),
+                vec![acquire()],
+                None,
+            )
+            .is_some());
+
+        let window = Arc::new(Semaphore::new(1));
+        let window_permit = window.clone().try_acquire_owned().unwrap();
+
+        // The request for `a` is already in flight so only `b` gets inserted.
+        let request = pending.insert(
+            PendingRequest::ChildNodesBatch(
+                vec![(hash_a, disambiguator), (hash_b, disambiguator)],
+                0,
+                PendingDebugRequest::start(),
+            ),
+            vec![acquire(), acquire()],
+            Some(window_permit),
+        );
+        assert_matches!(
+            request,
+            Some(Request::ChildNodesBatch(nodes, _)) if nodes == [(hash_b, disambiguator)]
+        );
+
+        // The window permit is held until the response to `b` is processed.
+        assert_eq!(window.available_permits(), 0);
+
+        let response = pending.remove(ProcessedResponse::ChildNodesError(
+            hash_b,
+            disambiguator,
+            DebugResponse::unsolicited(),
+        ));
+        assert_eq!(window.available_permits(), 0);
+
+        drop(response);
+        assert_eq!(window.available_permits(), 1);
+    }
+}
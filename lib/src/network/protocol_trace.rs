@@ -0,0 +1,193 @@
+//! Opt-in recording of the last protocol messages exchanged with each peer, for diagnosing sync
+//! protocol disagreements (e.g., between different app versions). Only the kind and the size of
+//! each message is recorded, never its content.
+
+use super::runtime_id::PublicRuntimeId;
+use crate::collections::HashMap;
+use deadlock::BlockingMutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Records the messages of all the peers. Disabled (zero capacity) by default.
+#[derive(Default, Clone)]
+pub(super) struct ProtocolTracer {
+    inner: Arc<Inner>,
+}
+
+impl ProtocolTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of messages kept per peer. Zero disables the tracing and discards the
+    /// messages recorded so far.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut peers = self.inner.peers.lock().unwrap();
+
+        self.inner.capacity.store(capacity, Ordering::Relaxed);
+
+        if capacity == 0 {
+            peers.clear();
+        } else {
+            for messages in peers.values_mut() {
+                let excess = messages.len().saturating_sub(capacity);
+                messages.drain(..excess);
+            }
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Tracer of the messages exchanged with the given peer.
+    pub fn peer(&self, runtime_id: PublicRuntimeId) -> PeerTracer {
+        PeerTracer {
+            inner: self.inner.clone(),
+            runtime_id,
+        }
+    }
+
+    pub fn dump(&self) -> Vec<PeerProtocolTrace> {
+        let mut traces: Vec<_> = self
+            .inner
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(runtime_id, messages)| PeerProtocolTrace {
+                runtime_id: *runtime_id,
+                messages: messages.iter().cloned().collect(),
+            })
+            .collect();
+
+        traces.sort_by(|a, b| a.runtime_id.cmp(&b.runtime_id));
+        traces
+    }
+}
+
+/// Records the messages of a single peer.
+#[derive(Clone)]
+pub(super) struct PeerTracer {
+    inner: Arc<Inner>,
+    runtime_id: PublicRuntimeId,
+}
+
+impl PeerTracer {
+    pub fn record(&self, direction: MessageDirection, kind: &str, size: usize) {
+        // Cheap check first so the disabled tracer doesn't slow down the message loops.
+        if self.inner.capacity.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let mut peers = self.inner.peers.lock().unwrap();
+
+        // Re-check under the lock in case the tracing got disabled in the meantime.
+        let capacity = self.inner.capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+
+        let messages = peers.entry(self.runtime_id).or_default();
+
+        if messages.len() >= capacity {
+            messages.pop_front();
+        }
+
+        messages.push_back(TracedMessage {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+                .try_into()
+                .unwrap_or(u64::MAX),
+            direction,
+            kind: kind.to_owned(),
+            size: size as u64,
+        });
+    }
+}
+
+/// Messages recently exchanged with a peer, oldest first. Obtained with
+/// `Network::protocol_trace`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PeerProtocolTrace {
+    pub runtime_id: PublicRuntimeId,
+    pub messages: Vec<TracedMessage>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TracedMessage {
+    /// Milliseconds since the Unix epoch when the message was sent or received.
+    pub timestamp: u64,
+    pub direction: MessageDirection,
+    /// Kind of the message (e.g., "Request::RootNode"), or "Undecodable" for a received message
+    /// that couldn't be deserialized (which usually means the peer runs an incompatible version).
+    pub kind: String,
+    /// Size of the serialized message in bytes (before encryption).
+    pub size: u64,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageDirection {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Default)]
+struct Inner {
+    capacity: AtomicUsize,
+    peers: BlockingMutex<HashMap<PublicRuntimeId, VecDeque<TracedMessage>>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::SecretRuntimeId;
+
+    #[test]
+    fn ring_buffer() {
+        let tracer = ProtocolTracer::new();
+        let peer = tracer.peer(SecretRuntimeId::random().public());
+
+        // Disabled by default.
+        peer.record(MessageDirection::Outbound, "Request::RootNode", 10);
+        assert!(tracer.dump().is_empty());
+
+        tracer.set_capacity(2);
+
+        peer.record(MessageDirection::Outbound, "Request::RootNode", 10);
+        peer.record(MessageDirection::Inbound, "Response::RootNode", 20);
+        peer.record(MessageDirection::Inbound, "Response::BlockOffer", 30);
+
+        let traces = tracer.dump();
+        assert_eq!(traces.len(), 1);
+
+        let kinds: Vec<_> = traces[0]
+            .messages
+            .iter()
+            .map(|message| (message.direction, message.kind.as_str(), message.size))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (MessageDirection::Inbound, "Response::RootNode", 20),
+                (MessageDirection::Inbound, "Response::BlockOffer", 30),
+            ]
+        );
+
+        tracer.set_capacity(1);
+        assert_eq!(tracer.dump()[0].messages.len(), 1);
+
+        tracer.set_capacity(0);
+        assert!(tracer.dump().is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use super::{constants::CHILD_NODES_REQUEST_WINDOW, pending::PendingRequest};
+use deadlock::BlockingMutex;
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc, time::Instant};
+use tokio::{
+    select,
+    sync::{Notify, OwnedSemaphorePermit, Semaphore},
+};
+
+/// Queue of requests waiting to be sent. Unlike a plain FIFO, which would traverse the index level
+/// by level (all the children of the root first, then all their children, etc...), this queue
+/// sends the requests for the deeper nodes first. This way the requests for multiple subtrees are
+/// in flight at the same time (up to the number of permitted in-flight requests) and the leaf
+/// nodes (and thus the blocks) are discovered much sooner, which matters especially for deep
+/// trees with many small directories.
+///
+/// The number of child nodes requests that are being processed at the same time is limited by a
+/// window (`CHILD_NODES_REQUEST_WINDOW`) so the index traversal doesn't take all the request slots
+/// of the link. A window slot is held until the response to the request has been processed (that
+/// is, until the requests for its children have been enqueued).
+pub(super) struct RequestQueue {
+    inner: BlockingMutex<Inner>,
+    notify: Notify,
+    window: Arc<Semaphore>,
+}
+
+impl RequestQueue {
+    pub fn new() -> Self {
+        Self::with_window(CHILD_NODES_REQUEST_WINDOW)
+    }
+
+    fn with_window(window: usize) -> Self {
+        Self {
+            inner: BlockingMutex::new(Inner {
+                child_nodes: BinaryHeap::new(),
+                other: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+            notify: Notify::new(),
+            window: Arc::new(Semaphore::new(window)),
+        }
+    }
+
+    pub fn push(&self, request: PendingRequest) {
+        let mut inner = self.inner.lock().unwrap();
+
+        let seq = inner.next_seq;
+        inner.next_seq = inner.next_seq.wrapping_add(1);
+
+        let entry = Entry {
+            priority: priority(&request),
+            seq,
+            request,
+            timestamp: Instant::now(),
+        };
+
+        if matches!(
+            entry.request,
+            PendingRequest::ChildNodes(..) | PendingRequest::ChildNodesBatch(..)
+        ) {
+            inner.child_nodes.push(entry);
+        } else {
+            inner.other.push(entry);
+        }
+
+        self.notify.notify_one();
+    }
+
+    /// Waits for the next request to send. Returns it together with the time it was enqueued and,
+    /// for child nodes requests, the window permit which should be held until the response is
+    /// processed.
+    pub async fn pop(&self) -> (PendingRequest, Instant, Option<OwnedSemaphorePermit>) {
+        loop {
+            let window_full = match self.try_pop() {
+                Pop::Ready(entry, permit) => return (entry.request, entry.timestamp, permit),
+                Pop::Empty => false,
+                Pop::WindowFull => true,
+            };
+
+            // If the window is full we wait also for a new request to be pushed because it might
+            // be one that is not subject to the window (e.g., a block request).
+            select! {
+                _ = self.notify.notified() => (),
+                permit = self.window.clone().acquire_owned(), if window_full => {
+                    // Unwrap OK because we never `close()` the semaphore.
+                    drop(permit.unwrap());
+                }
+            }
+        }
+    }
+
+    fn try_pop(&self) -> Pop {
+        let mut inner = self.inner.lock().unwrap();
+
+        let permit = if inner.child_nodes.is_empty() {
+            None
+        } else {
+            self.window.clone().try_acquire_owned().ok()
+        };
+
+        let take_child_nodes = match (&permit, inner.child_nodes.peek(), inner.other.peek()) {
+            (Some(_), Some(child_nodes), Some(other)) => child_nodes > other,
+            (Some(_), Some(_), None) => true,
+            _ => false,
+        };
+
+        if take_child_nodes {
+            // Unwrap OK because we just peeked it.
+            return Pop::Ready(inner.child_nodes.pop().unwrap(), permit);
+        }
+
+        if let Some(entry) = inner.other.pop() {
+            Pop::Ready(entry, None)
+        } else if inner.child_nodes.is_empty() {
+            Pop::Empty
+        } else {
+            Pop::WindowFull
+        }
+    }
+}
+
+enum Pop {
+    Ready(Entry, Option<OwnedSemaphorePermit>),
+    Empty,
+    WindowFull,
+}
+
+struct Inner {
+    // Child nodes requests are kept separately because they are subject to the window.
+    child_nodes: BinaryHeap<Entry>,
+    other: BinaryHeap<Entry>,
+    next_seq: u64,
+}
+
+struct Entry {
+    priority: u16,
+    seq: u64,
+    request: PendingRequest,
+    timestamp: Instant,
+}
+
+// Higher priority first, then FIFO.
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+fn priority(request: &PendingRequest) -> u16 {
+    match request {
+        PendingRequest::RootNode(..) => 0,
+        PendingRequest::ChildNodes(_, _, depth, _)
+        | PendingRequest::ChildNodesBatch(_, depth, _) => 1 + *depth as u16,
+        // Blocks are usually requested because someone is waiting for them (e.g., reading a file)
+        // so they go before any index request.
+        PendingRequest::Block(..) | PendingRequest::Blocks(..) => u16::MAX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{sign::PublicKey, Hashable},
+        network::{debug_payload::PendingDebugRequest, message::ResponseDisambiguator},
+        protocol::MultiBlockPresence,
+    };
+
+    #[test]
+    fn deeper_first() {
+        let queue = RequestQueue::new();
+
+        let hash = |id: u8| [id].hash();
+        let child_nodes = |id, depth| {
+            PendingRequest::ChildNodes(
+                hash(id),
+                ResponseDisambiguator::new(MultiBlockPresence::Full),
+                depth,
+                PendingDebugRequest::start(),
+            )
+        };
+
+        queue.push(PendingRequest::RootNode(
+            PublicKey::random(),
+            PendingDebugRequest::start(),
+        ));
+        queue.push(child_nodes(1, 0));
+        queue.push(child_nodes(2, 0));
+        queue.push(child_nodes(3, 1));
+        queue.push(child_nodes(4, 2));
+
+        let mut order = Vec::new();
+
+        while let Pop::Ready(Entry { request, .. }, _) = queue.try_pop() {
+            order.push(match request {
+                PendingRequest::RootNode(..) => None,
+                PendingRequest::ChildNodes(hash, ..) => Some(hash),
+                PendingRequest::ChildNodesBatch(..)
+                | PendingRequest::Block(..)
+                | PendingRequest::Blocks(..) => unreachable!(),
+            });
+        }
+
+        assert_eq!(
+            order,
+            vec![
+                Some(hash(4)),
+                Some(hash(3)),
+                Some(hash(1)),
+                Some(hash(2)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn child_nodes_window() {
+        let queue = RequestQueue::with_window(2);
+
+        let child_nodes = |id: u8| {
+            PendingRequest::ChildNodes(
+                [id].hash(),
+                ResponseDisambiguator::new(MultiBlockPresence::Full),
+                0,
+                PendingDebugRequest::start(),
+            )
+        };
+
+        for id in 0..3 {
+            queue.push(child_nodes(id));
+        }
+
+        let Pop::Ready(_, permit0) = queue.try_pop() else {
+            panic!("unexpected pop result");
+        };
+        let Pop::Ready(_, permit1) = queue.try_pop() else {
+            panic!("unexpected pop result");
+        };
+        assert!(permit0.is_some());
+        assert!(permit1.is_some());
+
+        // The window is full now.
+        assert!(matches!(queue.try_pop(), Pop::WindowFull));
+
+        // Requests not subject to the window can still go through, even if they have lower
+        // priority.
+        queue.push(PendingRequest::RootNode(
+            PublicKey::random(),
+            PendingDebugRequest::start(),
+        ));
+        assert!(matches!(
+            queue.try_pop(),
+            Pop::Ready(
+                Entry {
+                    request: PendingRequest::RootNode(..),
+                    ..
+                },
+                None
+            )
+        ));
+
+        // Processing a response frees a window slot.
+        drop(permit0);
+        assert!(matches!(queue.try_pop(), Pop::Ready(_, Some(_))));
+        assert!(matches!(queue.try_pop(), Pop::Empty));
+
+        drop(permit1);
+    }
+}
@@ -2,7 +2,7 @@ use crate::crypto::{
     sign::{Keypair, PublicKey, Signature},
     Digest, Hashable,
 };
-use rand::{rngs::OsRng, Rng};
+use rand::{rngs::OsRng, CryptoRng, Rng};
 use serde::{Deserialize, Serialize};
 use std::io;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
@@ -17,8 +17,12 @@ pub struct SecretRuntimeId {
 
 impl SecretRuntimeId {
     pub fn random() -> Self {
+        Self::generate(&mut OsRng)
+    }
+
+    pub fn generate<R: Rng + CryptoRng>(rng: &mut R) -> Self {
         Self {
-            keypair: Keypair::random(),
+            keypair: Keypair::generate(rng),
         }
     }
 
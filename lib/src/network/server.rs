@@ -1,27 +1,28 @@
 use super::{
     choke::Choker,
+    constants::{
+        AT_RISK_OFFER_INTERVAL, BULK_BLOCK_BATCH_SIZE, BULK_CHILD_NODES_BATCH_SIZE,
+        MAX_AT_RISK_OFFERS,
+    },
     debug_payload::{DebugRequest, DebugResponse},
-    message::{Content, Request, Response, ResponseDisambiguator},
+    message::{Content, MirrorReceipt, Request, Response, ResponseDisambiguator},
+    snapshot_throttle::SnapshotThrottle,
 };
 use crate::{
     crypto::{sign::PublicKey, Hash},
     error::{Error, Result},
     event,
-    protocol::{BlockContent, BlockId, RootNode, RootNodeFilter},
+    protocol::{BlockContent, BlockId, RootNode, RootNodeFilter, BLOCK_SIZE},
+    replica_tracker::ReplicaTrackerClient,
     repository::Vault,
     store,
 };
-use futures_util::{
-    stream::{self, FuturesUnordered},
-    Stream, StreamExt, TryStreamExt,
-};
-use std::{collections::HashSet, pin::pin};
+use futures_util::{future, stream::FuturesUnordered, Stream, StreamExt, TryStreamExt};
+use std::{collections::HashSet, pin::pin, sync::Arc};
 use tokio::{
     select,
-    sync::{
-        broadcast::{self, error::RecvError},
-        mpsc,
-    },
+    sync::{broadcast, mpsc},
+    time::{self, Instant, MissedTickBehavior},
 };
 use tracing::instrument;
 
@@ -37,9 +38,14 @@ impl Server {
         tx: mpsc::Sender<Content>,
         rx: mpsc::Receiver<Request>,
         choker: Choker,
+        replicas: Arc<ReplicaTrackerClient>,
     ) -> Self {
         Self {
-            inner: Inner { vault, tx },
+            inner: Inner {
+                vault,
+                tx,
+                replicas,
+            },
             rx,
             choker,
         }
@@ -55,6 +61,7 @@ impl Server {
 struct Inner {
     vault: Vault,
     tx: mpsc::Sender<Content>,
+    replicas: Arc<ReplicaTrackerClient>,
 }
 
 impl Inner {
@@ -71,6 +78,9 @@ impl Inner {
         let mut accumulator = EventAccumulator::default();
         let mut choked = true;
 
+        // Coalesces the changes of busy branches so we don't send a root node per commit.
+        let mut throttle = SnapshotThrottle::default();
+
         // This is to handle multiple requests / events at once.
         // TODO: Do we need to limit the number of concurrent request handlers? We have a limit on
         // the number of read DB transactions, so that might be enough, but perhaps we also want to
@@ -82,7 +92,18 @@ impl Inner {
         // immediatelly)
         accumulator.insert(Event::Unknown);
 
+        // Periodically offer the blocks that only we hold so the peer can download them. The first
+        // offer is delayed to give the peer's client time to tell us which blocks it already has.
+        let mut at_risk_interval = time::interval_at(
+            Instant::now() + AT_RISK_OFFER_INTERVAL,
+            AT_RISK_OFFER_INTERVAL,
+        );
+        at_risk_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
         loop {
+            let max_latency = *self.vault.snapshot_max_latency.lock().unwrap();
+            let throttle_deadline = throttle.deadline(max_latency);
+
             select! {
                 request = rx.recv(), if !choked => {
                     let Some(request)  = request else {
@@ -97,12 +118,38 @@ impl Inner {
                         break;
                     };
 
+                    if let Event::BranchChanged(branch_id) = &event {
+                        if !throttle.admit(*branch_id, max_latency, Instant::now()) {
+                            continue;
+                        }
+                    }
+
                     if choked {
                         accumulator.insert(event);
                     } else {
                         event_handlers.push(self.handle_event(event));
                     }
                 },
+                _ = time::sleep_until(throttle_deadline.unwrap_or_else(Instant::now)),
+                    if throttle_deadline.is_some() =>
+                {
+                    for branch_id in throttle.take_due(max_latency, Instant::now()) {
+                        let event = Event::BranchChanged(branch_id);
+
+                        if choked {
+                            accumulator.insert(event);
+                        } else {
+                            event_handlers.push(self.handle_event(event));
+                        }
+                    }
+                }
+                _ = at_risk_interval.tick() => {
+                    if choked {
+                        accumulator.insert(Event::AtRisk);
+                    } else {
+                        event_handlers.push(self.handle_event(Event::AtRisk));
+                    }
+                }
                 new_choked = choker.changed() => {
                     choked = new_choked;
 
@@ -131,6 +178,39 @@ impl Inner {
                 self.handle_child_nodes(hash, disambiguator, debug).await
             }
             Request::Block(block_id, debug) => self.handle_block(block_id, debug).await,
+            Request::Blocks(block_ids, debug) => {
+                if block_ids.len() > BULK_BLOCK_BATCH_SIZE {
+                    tracing::warn!(len = block_ids.len(), "Too many blocks requested at once");
+                    return Ok(());
+                }
+
+                let mut result = Ok(());
+
+                // Keep going on errors so the peer gets a response to every requested block.
+                for block_id in block_ids {
+                    result = result.and(self.handle_block(block_id, debug.clone()).await);
+                }
+
+                result
+            }
+            Request::ChildNodesBatch(nodes, debug) => {
+                if nodes.len() > BULK_CHILD_NODES_BATCH_SIZE {
+                    tracing::warn!(len = nodes.len(), "Too many child nodes requested at once");
+                    return Ok(());
+                }
+
+                let mut result = Ok(());
+
+                for (hash, disambiguator) in nodes {
+                    result = result.and(
+                        self.handle_child_nodes(hash, disambiguator, debug.clone())
+                            .await,
+                    );
+                }
+
+                result
+            }
+            Request::Receipt(receipt) => self.handle_receipt(receipt).await,
         }
     }
 
@@ -219,6 +299,23 @@ impl Inner {
         Ok(())
     }
 
+    #[instrument(skip(self), err(Debug))]
+    async fn handle_receipt(&self, receipt: MirrorReceipt) -> Result<()> {
+        if !receipt.verify(self.vault.repository_id()) {
+            tracing::warn!("Received invalid mirror receipt");
+            return Ok(());
+        }
+
+        if !self.vault.store_mirror_receipt(receipt).await? {
+            tracing::warn!("Received mirror receipt for unknown snapshot");
+            return Ok(());
+        }
+
+        tracing::trace!("Received mirror receipt");
+
+        Ok(())
+    }
+
     #[instrument(skip(self, debug), err(Debug))]
     async fn handle_block(&self, block_id: BlockId, debug: DebugRequest) -> Result<()> {
         let debug = debug.begin_reply();
@@ -236,6 +333,9 @@ impl Inner {
                 tracing::trace!("block found");
                 self.send_response(Response::Block(content, nonce, debug.send()))
                     .await;
+                // The peer holds the block now.
+                self.replicas.insert(block_id);
+                self.vault.contribution.record_served(BLOCK_SIZE as u64);
                 Ok(())
             }
             Err(store::Error::BlockNotFound) => {
@@ -257,6 +357,7 @@ impl Inner {
             Event::BranchChanged(branch_id) => self.handle_branch_changed_event(branch_id).await,
             Event::BlockReceived(block_id) => self.handle_block_received_event(block_id).await,
             Event::Unknown => self.handle_unknown_event().await,
+            Event::AtRisk => self.handle_at_risk_event().await,
         }
     }
 
@@ -279,6 +380,15 @@ impl Inner {
         Ok(())
     }
 
+    async fn handle_at_risk_event(&self) -> Result<()> {
+        for block_id in self.replicas.take_at_risk(MAX_AT_RISK_OFFERS) {
+            self.send_response(Response::BlockOffer(block_id, DebugResponse::unsolicited()))
+                .await;
+        }
+
+        Ok(())
+    }
+
     async fn handle_unknown_event(&self) -> Result<()> {
         let root_nodes = self.load_root_nodes().await?;
         for root_node in root_nodes {
@@ -364,22 +474,18 @@ impl Inner {
 }
 
 fn events(rx: broadcast::Receiver<event::Event>) -> impl Stream<Item = Event> {
-    stream::unfold(rx, |mut rx| async move {
-        loop {
-            match rx.recv().await {
-                Ok(event::Event { payload, .. }) => match payload {
-                    event::Payload::BranchChanged(branch_id) => {
-                        return Some((Event::BranchChanged(branch_id), rx))
-                    }
-                    event::Payload::BlockReceived(block_id) => {
-                        return Some((Event::BlockReceived(block_id), rx))
-                    }
-                    event::Payload::MaintenanceCompleted => continue,
-                },
-                Err(RecvError::Lagged(_)) => return Some((Event::Unknown, rx)),
-                Err(RecvError::Closed) => return None,
-            }
-        }
+    event::into_stream(rx).filter_map(|event| {
+        future::ready(match event.payload {
+            event::Payload::BranchChanged(branch_id) => Some(Event::BranchChanged(branch_id)),
+            event::Payload::BlockReceived(block_id) => Some(Event::BlockReceived(block_id)),
+            event::Payload::Resync => Some(Event::Unknown),
+            event::Payload::MaintenanceCompleted
+            | event::Payload::MetadataChanged
+            | event::Payload::BlockCorrupted(_)
+            | event::Payload::Wiped
+            | event::Payload::StorageFull
+            | event::Payload::AccessNotice => None,
+        })
     })
 }
 
@@ -388,6 +494,8 @@ enum Event {
     BranchChanged(PublicKey),
     BlockReceived(BlockId),
     Unknown,
+    // Time to offer the at risk blocks.
+    AtRisk,
 }
 
 #[derive(Default)]
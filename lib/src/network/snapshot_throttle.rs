@@ -0,0 +1,140 @@
+use crate::crypto::sign::PublicKey;
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+
+/// Limits how often the snapshots (root nodes) of a branch are published to a peer. The first
+/// change of a branch after a quiet period is published immediately, but the changes that follow
+/// within `max_latency` of the last publication are coalesced and published together once that
+/// time elapses. Under sustained write load this publishes at most one snapshot per branch per
+/// `max_latency` instead of one per commit.
+#[derive(Default)]
+pub(super) struct SnapshotThrottle {
+    branches: HashMap<PublicKey, BranchState>,
+}
+
+struct BranchState {
+    last_published: Instant,
+    pending: bool,
+}
+
+impl SnapshotThrottle {
+    /// Records a change of the given branch. Returns whether it should be published right away.
+    /// If not, it's returned later from `take_due`.
+    pub fn admit(&mut self, branch_id: PublicKey, max_latency: Duration, now: Instant) -> bool {
+        match self.branches.get_mut(&branch_id) {
+            Some(state) if now.duration_since(state.last_published) < max_latency => {
+                state.pending = true;
+                false
+            }
+            Some(state) => {
+                state.last_published = now;
+                state.pending = false;
+                true
+            }
+            None => {
+                self.branches.insert(
+                    branch_id,
+                    BranchState {
+                        last_published: now,
+                        pending: false,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Time when the next held back change becomes due, if any.
+    pub fn deadline(&self, max_latency: Duration) -> Option<Instant> {
+        self.branches
+            .values()
+            .filter(|state| state.pending)
+            .map(|state| state.last_published + max_latency)
+            .min()
+    }
+
+    /// Returns the branches whose held back changes are due now.
+    pub fn take_due(&mut self, max_latency: Duration, now: Instant) -> Vec<PublicKey> {
+        let mut due = Vec::new();
+
+        self.branches.retain(|branch_id, state| {
+            if now.duration_since(state.last_published) < max_latency {
+                return true;
+            }
+
+            if state.pending {
+                state.last_published = now;
+                state.pending = false;
+                due.push(*branch_id);
+                true
+            } else {
+                // Quiet for long enough, the next change is published immediately anyway.
+                false
+            }
+        });
+
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LATENCY: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn idle_branch_is_published_immediately() {
+        let mut throttle = SnapshotThrottle::default();
+        let branch_id = PublicKey::random();
+        let now = Instant::now();
+
+        assert!(throttle.admit(branch_id, LATENCY, now));
+        assert_eq!(throttle.deadline(LATENCY), None);
+
+        assert!(throttle.admit(branch_id, LATENCY, now + 2 * LATENCY));
+        assert_eq!(throttle.deadline(LATENCY), None);
+    }
+
+    #[test]
+    fn busy_branch_is_coalesced() {
+        let mut throttle = SnapshotThrottle::default();
+        let branch_id = PublicKey::random();
+        let now = Instant::now();
+
+        assert!(throttle.admit(branch_id, LATENCY, now));
+
+        for i in 1..10 {
+            assert!(!throttle.admit(branch_id, LATENCY, now + LATENCY * i / 20));
+        }
+
+        assert_eq!(throttle.deadline(LATENCY), Some(now + LATENCY));
+        assert!(throttle.take_due(LATENCY, now + LATENCY / 2).is_empty());
+        assert_eq!(throttle.take_due(LATENCY, now + LATENCY), [branch_id]);
+        assert_eq!(throttle.deadline(LATENCY), None);
+        assert!(throttle.take_due(LATENCY, now + 3 * LATENCY).is_empty());
+    }
+
+    #[test]
+    fn branches_are_throttled_independently() {
+        let mut throttle = SnapshotThrottle::default();
+        let branch_a = PublicKey::random();
+        let branch_b = PublicKey::random();
+        let now = Instant::now();
+
+        assert!(throttle.admit(branch_a, LATENCY, now));
+        assert!(!throttle.admit(branch_a, LATENCY, now + LATENCY / 4));
+        assert!(throttle.admit(branch_b, LATENCY, now + LATENCY / 2));
+    }
+
+    #[test]
+    fn zero_latency_disables_throttling() {
+        let mut throttle = SnapshotThrottle::default();
+        let branch_id = PublicKey::random();
+        let now = Instant::now();
+
+        assert!(throttle.admit(branch_id, Duration::ZERO, now));
+        assert!(throttle.admit(branch_id, Duration::ZERO, now));
+        assert_eq!(throttle.deadline(Duration::ZERO), None);
+    }
+}
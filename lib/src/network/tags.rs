@@ -0,0 +1,102 @@
+use super::PeerSource;
+use deadlock::BlockingMutex;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Routing tags of a repository (e.g., "work", "home"). They determine which peer sources (that
+/// is, which connections and discovery mechanisms) serve the repository.
+pub type RoutingTags = BTreeSet<String>;
+
+/// Restrictions of the peer sources per routing tag.
+#[derive(Default)]
+pub(super) struct TagPeerSources(BlockingMutex<HashMap<String, HashSet<PeerSource>>>);
+
+impl TagPeerSources {
+    /// Restricts the repositories with the given tag to be served only by the given peer sources.
+    /// `None` removes the restriction.
+    pub fn set(&self, tag: String, sources: Option<HashSet<PeerSource>>) {
+        let mut map = self.0.lock().unwrap();
+
+        if let Some(sources) = sources {
+            map.insert(tag, sources);
+        } else {
+            map.remove(&tag);
+        }
+    }
+
+    pub fn get(&self, tag: &str) -> Option<HashSet<PeerSource>> {
+        self.0.lock().unwrap().get(tag).cloned()
+    }
+
+    /// Can a repository with the given tags be served by a peer from the given source? Untagged
+    /// repositories are served by any source and a tagged one by any source allowed by at least
+    /// one of its tags. Tags without restriction allow any source.
+    pub fn allows(&self, tags: &RoutingTags, source: PeerSource) -> bool {
+        if tags.is_empty() {
+            return true;
+        }
+
+        let map = self.0.lock().unwrap();
+
+        tags.iter().any(|tag| {
+            map.get(tag)
+                .map(|sources| sources.contains(&source))
+                .unwrap_or(true)
+        })
+    }
+}
+
+/// Encodes the tags for storing in the repository metadata.
+pub(super) fn encode(tags: &RoutingTags) -> String {
+    tags.iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub(super) fn decode(s: &str) -> RoutingTags {
+    s.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows() {
+        let sources = TagPeerSources::default();
+        sources.set(
+            "work".to_owned(),
+            Some([PeerSource::UserProvided].into_iter().collect()),
+        );
+
+        let untagged = RoutingTags::new();
+        let work = decode("work");
+        let home = decode("home");
+        let both = decode("work,home");
+
+        assert!(sources.allows(&untagged, PeerSource::Dht));
+        assert!(sources.allows(&work, PeerSource::UserProvided));
+        assert!(!sources.allows(&work, PeerSource::Dht));
+        assert!(sources.allows(&home, PeerSource::Dht));
+        assert!(sources.allows(&both, PeerSource::Dht));
+
+        sources.set("work".to_owned(), None);
+        assert!(sources.allows(&work, PeerSource::Dht));
+    }
+
+    #[test]
+    fn encode_decode() {
+        let tags = decode(" work, ,home");
+        assert_eq!(
+            tags,
+            ["home", "work"].into_iter().map(String::from).collect()
+        );
+        assert_eq!(encode(&tags), "home,work");
+        assert_eq!(decode(&encode(&tags)), tags);
+        assert!(decode("").is_empty());
+    }
+}
@@ -1,15 +1,17 @@
 use super::{
     choke,
     client::Client,
-    constants::MAX_IN_FLIGHT_REQUESTS_PER_PEER,
+    constants::{BULK_BLOCK_BATCH_SIZE, MAX_IN_FLIGHT_REQUESTS_PER_PEER},
     message::{Content, Request, Response},
     server::Server,
+    verifier::Verifier,
 };
 use crate::{
     block_tracker::OfferState,
     crypto::sign::{Keypair, PublicKey},
     db,
     event::{Event, EventSender, Payload},
+    memory::MemoryBudget,
     protocol::{
         test_utils::{receive_blocks, receive_nodes, Snapshot},
         Block, BlockId, Bump, RootNode, SingleBlockPresence,
@@ -175,6 +177,73 @@ async fn transfer_blocks_between_two_replicas_case(block_count: usize, rng_seed:
     b_vault.store().close().await.unwrap();
 }
 
+#[tokio::test]
+async fn transfer_blocks_in_bulk() {
+    test_utils::init_log();
+
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let write_keys = Keypair::generate(&mut rng);
+    let (_a_base_dir, a_vault, a_choker, a_id) = create_repository(&mut rng, &write_keys).await;
+    let (_b_base_dir, b_vault, _, _) = create_repository(&mut rng, &write_keys).await;
+
+    // More blocks than fit into a single batch.
+    let snapshot = Snapshot::generate(&mut rng, 3 * BULK_BLOCK_BATCH_SIZE + 1);
+    save_snapshot(&a_vault, a_id, &write_keys, &snapshot).await;
+    receive_blocks(&a_vault, &snapshot).await;
+
+    let mut server = create_server(a_vault.clone(), &a_choker);
+    let mut client = create_client_with_bulk(b_vault.clone(), true);
+
+    // The blocks are requested in batches.
+    let drive = async {
+        for id in snapshot.blocks().keys() {
+            wait_until_block_exists(&b_vault, id).await;
+        }
+    };
+
+    simulate_connection_until(&mut server, &mut client, drive).await;
+
+    drop(client);
+
+    // HACK: prevent "too many open files" error.
+    a_vault.store().close().await.unwrap();
+    b_vault.store().close().await.unwrap();
+}
+
+// The time of the last received snapshot is recorded when the snapshot arrives through the client.
+#[tokio::test]
+async fn record_last_snapshot_receive_time() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let write_keys = Keypair::generate(&mut rng);
+    let (_a_base_dir, a_vault, a_choke, a_id) = create_repository(&mut rng, &write_keys).await;
+    let (_b_base_dir, b_vault, _, _) = create_repository(&mut rng, &write_keys).await;
+
+    let snapshot = Snapshot::generate(&mut rng, 1);
+    save_snapshot(&a_vault, a_id, &write_keys, &snapshot).await;
+    receive_blocks(&a_vault, &snapshot).await;
+
+    assert!(b_vault.last_root_node_received.lock().unwrap().is_none());
+
+    let mut server = create_server(a_vault.clone(), &a_choke);
+    let mut client = create_client(b_vault.clone());
+
+    simulate_connection_until(
+        &mut server,
+        &mut client,
+        wait_until_snapshots_in_sync(&a_vault, a_id, &b_vault),
+    )
+    .await;
+
+    assert!(b_vault.last_root_node_received.lock().unwrap().is_some());
+
+    drop(client);
+
+    // HACK: prevent "too many open files" error.
+    a_vault.store().close().await.unwrap();
+    b_vault.store().close().await.unwrap();
+}
+
 // Receive a `LeafNode` with non-missing block, then drop the connection before the block itself is
 // received, then re-establish the connection and make sure the block gets received then.
 #[tokio::test]
@@ -405,6 +474,7 @@ async fn create_repository<R: Rng + CryptoRng>(
         repository_id,
         event_tx,
         db,
+        MemoryBudget::default(),
         BlockRequestMode::Greedy,
         RepositoryMonitor::new(StateMonitor::make_root(), &NoopRecorder),
     );
@@ -606,19 +676,28 @@ type ClientData = (Client, mpsc::Receiver<Content>, mpsc::Sender<Response>);
 fn create_server(repo: Vault, choke_manager: &choke::Manager) -> ServerData {
     let (send_tx, send_rx) = mpsc::channel(1);
     let (recv_tx, recv_rx) = mpsc::channel(CAPACITY);
-    let server = Server::new(repo, send_tx, recv_rx, choke_manager.new_choker());
+    let replicas = Arc::new(repo.replica_tracker.client());
+    let server = Server::new(repo, send_tx, recv_rx, choke_manager.new_choker(), replicas);
 
     (server, send_rx, recv_tx)
 }
 
 fn create_client(repo: Vault) -> ClientData {
+    create_client_with_bulk(repo, false)
+}
+
+fn create_client_with_bulk(repo: Vault, bulk: bool) -> ClientData {
     let (send_tx, send_rx) = mpsc::channel(1);
     let (recv_tx, recv_rx) = mpsc::channel(CAPACITY);
+    let replicas = Arc::new(repo.replica_tracker.client());
     let client = Client::new(
         repo,
         send_tx,
         recv_rx,
         Arc::new(Semaphore::new(MAX_IN_FLIGHT_REQUESTS_PER_PEER)),
+        Verifier::new(1),
+        replicas,
+        bulk,
     );
 
     (client, send_rx, recv_tx)
@@ -0,0 +1,165 @@
+//! Dedicated thread pool for the CPU intensive processing of the received data (hashing of blocks
+//! and nodes, verification of signatures) so that it doesn't stall the async runtime.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    thread,
+};
+use tokio::sync::{mpsc, oneshot};
+
+// Max number of jobs waiting to be picked up by a worker. When the queue is full, submitting new
+// jobs waits which applies backpressure to the receive path.
+const QUEUE_CAPACITY: usize = 32;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Clone)]
+pub(super) struct Verifier {
+    job_tx: mpsc::Sender<Job>,
+    shared: Arc<Shared>,
+}
+
+impl Verifier {
+    /// Creates the pool with the given number of worker threads (at least one).
+    pub fn new(threads: usize) -> Self {
+        let (job_tx, job_rx) = mpsc::channel(QUEUE_CAPACITY);
+
+        let this = Self {
+            job_tx,
+            shared: Arc::new(Shared {
+                job_rx: Mutex::new(job_rx),
+                threads: Mutex::new(ThreadCount {
+                    target: 0,
+                    running: 0,
+                }),
+            }),
+        };
+
+        this.set_threads(threads);
+        this
+    }
+
+    /// Changes the number of worker threads (at least one). When decreased, the excess threads
+    /// exit after finishing their current job.
+    pub fn set_threads(&self, threads: usize) {
+        let mut count = self.shared.threads.lock().unwrap();
+        count.target = threads.max(1);
+
+        while count.running < count.target {
+            let shared = self.shared.clone();
+            let result = thread::Builder::new()
+                .name("ouisync-verify".to_owned())
+                .spawn(move || shared.run_worker());
+
+            match result {
+                Ok(_) => count.running += 1,
+                Err(error) => {
+                    tracing::error!(?error, "Failed to spawn verifier thread");
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.shared.threads.lock().unwrap().target
+    }
+
+    /// Runs `f` on the pool and waits for its result. If the pool is busy, waits until there is
+    /// room in the job queue first.
+    pub async fn run<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let job = Box::new(move || {
+            result_tx
+                .send(panic::catch_unwind(AssertUnwindSafe(f)))
+                .ok();
+        });
+
+        // The receiver lives in `shared` which we hold so the send can't fail.
+        if self.job_tx.send(job).await.is_err() {
+            unreachable!()
+        }
+
+        // unwrap is ok because every job either completes or its panic is caught.
+        match result_rx.await.unwrap() {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl Default for Verifier {
+    /// Creates the pool with one thread per available CPU core except one which is left for the
+    /// async runtime.
+    fn default() -> Self {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .saturating_sub(1);
+
+        Self::new(threads)
+    }
+}
+
+struct Shared {
+    job_rx: Mutex<mpsc::Receiver<Job>>,
+    threads: Mutex<ThreadCount>,
+}
+
+impl Shared {
+    fn run_worker(&self) {
+        loop {
+            // The lock is held while waiting for the job which is fine because the other idle
+            // workers would be waiting anyway.
+            let Some(job) = self.job_rx.lock().unwrap().blocking_recv() else {
+                // All senders dropped.
+                break;
+            };
+
+            job();
+
+            let mut count = self.threads.lock().unwrap();
+            if count.running > count.target {
+                count.running -= 1;
+                break;
+            }
+        }
+    }
+}
+
+struct ThreadCount {
+    target: usize,
+    running: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future;
+
+    #[tokio::test]
+    async fn run() {
+        let verifier = Verifier::new(2);
+
+        let results = future::join_all((0..100u32).map(|n| verifier.run(move || n * 2))).await;
+        assert_eq!(results, (0..100u32).map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn resize() {
+        let verifier = Verifier::new(4);
+        assert_eq!(verifier.threads(), 4);
+
+        verifier.set_threads(1);
+        assert_eq!(verifier.threads(), 1);
+        assert_eq!(verifier.run(|| 42).await, 42);
+
+        verifier.set_threads(0);
+        assert_eq!(verifier.threads(), 1);
+    }
+}
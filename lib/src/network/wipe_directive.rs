@@ -0,0 +1,45 @@
+//! Propagation of wipe directives (see `Repository::publish_wipe_directive`) over a link.
+
+use super::message::Content;
+use crate::repository::{Vault, WipeDirective};
+use tokio::{select, sync::mpsc};
+
+/// Sends all the wipe directives of the local repository to the peer whenever they change and
+/// stores the directives coming from the peer. Returns only on error.
+pub(super) async fn run(
+    vault: &Vault,
+    content_tx: mpsc::Sender<Content>,
+    mut directive_rx: mpsc::Receiver<WipeDirective>,
+) {
+    let mut current_rx = vault.wipe_directives.subscribe();
+    current_rx.mark_changed();
+
+    loop {
+        select! {
+            result = current_rx.changed() => {
+                if result.is_err() {
+                    break;
+                }
+
+                let directives = current_rx.borrow_and_update().clone();
+
+                for directive in directives {
+                    if content_tx.send(Content::WipeDirective(directive)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            directive = directive_rx.recv() => {
+                let Some(directive) = directive else {
+                    break;
+                };
+
+                match vault.receive_wipe_directive(directive).await {
+                    Ok(true) => tracing::info!("Wipe directive received"),
+                    Ok(false) => (),
+                    Err(error) => tracing::error!(?error, "Failed to store wipe directive"),
+                }
+            }
+        }
+    }
+}
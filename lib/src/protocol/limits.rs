@@ -0,0 +1,14 @@
+//! Default limits on the shape of the directory tree. They are not enforced by the protocol itself
+//! (a replica never rejects data it receives from its peers because of them) but every replica
+//! refuses to create entries that would exceed them. This prevents pathological trees (e.g.,
+//! created by buggy scripts) which would degrade the performance of the whole repository and of
+//! all the peers that sync it.
+
+/// Default maximum number of entries (including tombstones) in a single directory.
+pub const DEFAULT_MAX_DIRECTORY_ENTRIES: u32 = 65_536;
+
+/// Default maximum depth of the directory tree. The entries of the root directory have depth 1.
+pub const DEFAULT_MAX_PATH_DEPTH: u32 = 128;
+
+/// Default maximum length of an entry name in bytes (of its UTF-8 encoding).
+pub const DEFAULT_MAX_NAME_LENGTH: u32 = 255;
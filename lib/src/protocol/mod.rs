@@ -1,30 +1,29 @@
 //! Datatypes for the ouisync protocol and for interoperability between the network layer and the
-//! storage later
+//! storage later. The types exchanged over the wire are defined in the `ouisync-protocol` crate and
+//! re-exported here.
 
 mod block;
 mod bump;
-mod inner_node;
-mod leaf_node;
+mod limits;
 mod locator;
-mod proof;
 mod root_node;
-mod summary;
 
 #[cfg(test)]
 pub(crate) mod test_utils;
 
-pub use self::block::BLOCK_SIZE;
+pub use self::limits::{
+    DEFAULT_MAX_DIRECTORY_ENTRIES, DEFAULT_MAX_NAME_LENGTH, DEFAULT_MAX_PATH_DEPTH,
+};
+pub use ouisync_protocol::{BlockId, BLOCK_SIZE};
 
 pub(crate) use self::{
-    block::{Block, BlockContent, BlockId, BlockNonce, BLOCK_RECORD_SIZE},
+    block::{Block, BLOCK_RECORD_SIZE},
     bump::Bump,
-    inner_node::{get_bucket, InnerNode, InnerNodes, EMPTY_INNER_HASH, INNER_LAYER_COUNT},
-    leaf_node::{LeafNode, LeafNodes, EMPTY_LEAF_HASH},
     locator::Locator,
-    proof::{Proof, ProofError, UntrustedProof},
     root_node::{RootNode, RootNodeFilter, RootNodeKind},
-    summary::{MultiBlockPresence, NodeState, SingleBlockPresence, Summary},
 };
-
-#[cfg(test)]
-pub(crate) use self::block::BLOCK_NONCE_SIZE;
+pub(crate) use ouisync_protocol::{
+    get_bucket, BlockContent, BlockNonce, InnerNode, InnerNodes, LeafNode, LeafNodes,
+    MultiBlockPresence, NodeState, Proof, ProofError, SingleBlockPresence, Summary, UntrustedProof,
+    BLOCK_NONCE_SIZE, EMPTY_INNER_HASH, EMPTY_LEAF_HASH, INNER_LAYER_COUNT,
+};
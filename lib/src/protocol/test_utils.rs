@@ -1,4 +1,4 @@
-use super::{proof::Proof, MultiBlockPresence, NodeState, SingleBlockPresence, Summary};
+use super::{MultiBlockPresence, NodeState, Proof, SingleBlockPresence, Summary};
 use crate::{
     block_tracker::OfferState,
     collections::HashMap,
@@ -0,0 +1,290 @@
+use crate::{
+    collections::{HashMap, HashSet},
+    protocol::BlockId,
+};
+use deadlock::BlockingMutex;
+use slab::Slab;
+use std::sync::Arc;
+
+/// Max number of blocks tracked per remote replica. Blocks beyond this limit are not recorded and
+/// so might be considered at risk even when the replica holds them.
+const MAX_HELD_PER_CLIENT: usize = 16 * 1024;
+
+/// Helper for tracking which blocks are held by which connected remote replicas. The information
+/// is learned from the peers (block presence in the received leaf nodes, block offers and served
+/// block requests) and so is incomplete, but it's enough to find the blocks which only we hold
+/// ("at risk" blocks) and prioritize uploading them.
+///
+/// Disabled by default, in which case nothing is recorded.
+#[derive(Clone)]
+pub(crate) struct ReplicaTracker {
+    shared: Arc<BlockingMutex<Inner>>,
+}
+
+impl ReplicaTracker {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(BlockingMutex::new(Inner {
+                replicas: HashMap::default(),
+                clients: Slab::new(),
+                at_risk: Vec::new(),
+                enabled: false,
+            })),
+        }
+    }
+
+    /// Creates a new client representing a single remote replica.
+    pub fn client(&self) -> ReplicaTrackerClient {
+        let client_id = self
+            .shared
+            .lock()
+            .unwrap()
+            .clients
+            .insert(Client::default());
+
+        ReplicaTrackerClient {
+            shared: self.shared.clone(),
+            client_id,
+        }
+    }
+
+    /// Number of the connected remote replicas known to hold the given block.
+    pub fn replica_count(&self, block_id: &BlockId) -> usize {
+        self.shared
+            .lock()
+            .unwrap()
+            .replicas
+            .get(block_id)
+            .map(|clients| clients.len())
+            .unwrap_or(0)
+    }
+
+    /// Number of the connected remote replicas.
+    pub fn client_count(&self) -> usize {
+        self.shared.lock().unwrap().clients.len()
+    }
+
+    /// Sets the at risk blocks to be offered to the remote replicas.
+    pub fn set_at_risk(&self, block_ids: Vec<BlockId>) {
+        let mut inner = self.shared.lock().unwrap();
+
+        if inner.enabled {
+            inner.at_risk = block_ids;
+        }
+    }
+
+    /// Enables or disables the tracking. Disabling it forgets everything recorded so far.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut inner = self.shared.lock().unwrap();
+        inner.enabled = enabled;
+
+        if !enabled {
+            inner.replicas.clear();
+            inner.at_risk.clear();
+
+            for (_, client) in &mut inner.clients {
+                *client = Client::default();
+            }
+        }
+    }
+}
+
+pub(crate) struct ReplicaTrackerClient {
+    shared: Arc<BlockingMutex<Inner>>,
+    client_id: ClientId,
+}
+
+impl ReplicaTrackerClient {
+    /// Records that the remote replica holds the given block.
+    pub fn insert(&self, block_id: BlockId) {
+        let mut inner = self.shared.lock().unwrap();
+
+        if !inner.enabled {
+            return;
+        }
+
+        let held = &mut inner.clients[self.client_id].held;
+
+        if held.len() >= MAX_HELD_PER_CLIENT {
+            return;
+        }
+
+        if held.insert(block_id) {
+            inner
+                .replicas
+                .entry(block_id)
+                .or_default()
+                .insert(self.client_id);
+        }
+    }
+
+    /// Records that the remote replica doesn't hold the given block.
+    pub fn remove(&self, block_id: &BlockId) {
+        let mut inner = self.shared.lock().unwrap();
+
+        if inner.clients[self.client_id].held.remove(block_id) {
+            inner.remove_replica(block_id, self.client_id);
+        }
+    }
+
+    /// Returns up to `limit` at risk blocks that the remote replica isn't known to hold. Continues
+    /// from where the previous call stopped and starts from the beginning again once all the at
+    /// risk blocks have been returned.
+    pub fn take_at_risk(&self, limit: usize) -> Vec<BlockId> {
+        let mut inner = self.shared.lock().unwrap();
+        let inner = &mut *inner;
+        let client = &mut inner.clients[self.client_id];
+
+        let start = if client.next_offer < inner.at_risk.len() {
+            client.next_offer
+        } else {
+            0
+        };
+
+        let mut block_ids = Vec::new();
+        let mut end = start;
+
+        for block_id in &inner.at_risk[start..] {
+            if block_ids.len() >= limit {
+                break;
+            }
+
+            end += 1;
+
+            if !client.held.contains(block_id) {
+                block_ids.push(*block_id);
+            }
+        }
+
+        client.next_offer = end;
+
+        block_ids
+    }
+}
+
+impl Drop for ReplicaTrackerClient {
+    fn drop(&mut self) {
+        let mut inner = self.shared.lock().unwrap();
+        let client = inner.clients.remove(self.client_id);
+
+        for block_id in client.held {
+            inner.remove_replica(&block_id, self.client_id);
+        }
+    }
+}
+
+type ClientId = usize;
+
+struct Inner {
+    // Invariant: `replicas[block_id]` contains `client_id` iff `clients[client_id].held` contains
+    // `block_id`.
+    replicas: HashMap<BlockId, HashSet<ClientId>>,
+    clients: Slab<Client>,
+    at_risk: Vec<BlockId>,
+    enabled: bool,
+}
+
+#[derive(Default)]
+struct Client {
+    // Blocks the remote replica is known to hold (at most `MAX_HELD_PER_CLIENT`).
+    held: HashSet<BlockId>,
+    // Position in `at_risk` to continue offering from.
+    next_offer: usize,
+}
+
+impl Inner {
+    fn remove_replica(&mut self, block_id: &BlockId, client_id: ClientId) {
+        let Some(clients) = self.replicas.get_mut(block_id) else {
+            return;
+        };
+
+        clients.remove(&client_id);
+
+        if clients.is_empty() {
+            self.replicas.remove(block_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replica_count() {
+        let tracker = ReplicaTracker::new();
+        tracker.set_enabled(true);
+
+        let block_id: BlockId = rand::random();
+
+        let client0 = tracker.client();
+        let client1 = tracker.client();
+        assert_eq!(tracker.replica_count(&block_id), 0);
+
+        client0.insert(block_id);
+        client0.insert(block_id);
+        assert_eq!(tracker.replica_count(&block_id), 1);
+
+        client1.insert(block_id);
+        assert_eq!(tracker.replica_count(&block_id), 2);
+
+        client0.remove(&block_id);
+        assert_eq!(tracker.replica_count(&block_id), 1);
+
+        drop(client1);
+        assert_eq!(tracker.replica_count(&block_id), 0);
+    }
+
+    #[test]
+    fn at_risk() {
+        let tracker = ReplicaTracker::new();
+        tracker.set_enabled(true);
+
+        let block_ids: [BlockId; 3] = rand::random();
+
+        let client = tracker.client();
+        client.insert(block_ids[1]);
+
+        tracker.set_at_risk(block_ids.to_vec());
+
+        assert_eq!(client.take_at_risk(1), [block_ids[0]]);
+        assert_eq!(client.take_at_risk(8), [block_ids[2]]);
+        assert_eq!(client.take_at_risk(8), [block_ids[0], block_ids[2]]);
+    }
+
+    #[test]
+    fn disabled() {
+        let tracker = ReplicaTracker::new();
+        let block_ids: [BlockId; 2] = rand::random();
+
+        let client = tracker.client();
+        client.insert(block_ids[0]);
+        tracker.set_at_risk(block_ids.to_vec());
+
+        assert_eq!(tracker.replica_count(&block_ids[0]), 0);
+        assert!(client.take_at_risk(8).is_empty());
+
+        tracker.set_enabled(true);
+        client.insert(block_ids[0]);
+        assert_eq!(tracker.replica_count(&block_ids[0]), 1);
+
+        tracker.set_enabled(false);
+        assert_eq!(tracker.replica_count(&block_ids[0]), 0);
+    }
+
+    #[test]
+    fn held_limit() {
+        let tracker = ReplicaTracker::new();
+        tracker.set_enabled(true);
+
+        let client = tracker.client();
+
+        for _ in 0..MAX_HELD_PER_CLIENT {
+            client.insert(rand::random());
+        }
+
+        let block_id: BlockId = rand::random();
+        client.insert(block_id);
+        assert_eq!(tracker.replica_count(&block_id), 0);
+    }
+}
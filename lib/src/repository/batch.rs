@@ -0,0 +1,101 @@
+use super::metadata::{self, MetadataSet};
+use crate::{
+    branch::BranchEventSender,
+    error::Result,
+    event::{EventSender, Payload},
+    file::File,
+    store::WriteTransaction,
+};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Combines metadata writes and file modifications into a single atomic transaction, so that
+/// either all of them are applied or none of them. Useful to keep a metadata entry consistent with
+/// the content it describes (e.g., a "last backup" timestamp). Obtained with
+/// [`Repository::batch`](super::Repository::batch).
+///
+/// The batch holds the repository write transaction from its creation until it's committed or
+/// dropped. During that time every other write to the repository (including the ones done by the
+/// background merge and prune jobs and the ones of the data received from the peers) waits, so keep
+/// the batch short-lived and don't write to the repository other than through the batch while it's
+/// alive (doing so would deadlock).
+pub struct Batch {
+    tx: WriteTransaction,
+    event_tx: EventSender,
+    branch_event_txs: Vec<BranchEventSender>,
+    metadata_changed: bool,
+    committed: Arc<AtomicBool>,
+}
+
+impl Batch {
+    pub(super) fn new(tx: WriteTransaction, event_tx: EventSender) -> Self {
+        Self {
+            tx,
+            event_tx,
+            branch_event_txs: Vec::new(),
+            metadata_changed: false,
+            committed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub async fn set_metadata<'a, T>(&mut self, name: &'a str, value: T) -> Result<()>
+    where
+        T: MetadataSet<'a> + fmt::Debug,
+    {
+        metadata::set_public(self.tx.db(), name.as_bytes(), value).await?;
+        self.metadata_changed = true;
+
+        Ok(())
+    }
+
+    pub async fn remove_metadata(&mut self, name: &str) -> Result<()> {
+        metadata::remove_public(self.tx.db(), name.as_bytes()).await?;
+        self.metadata_changed = true;
+
+        Ok(())
+    }
+
+    /// Adds the pending modifications of the file to this batch. The file needs to be written to
+    /// before calling this (writes that don't fit into the file cache are flushed on their own and
+    /// so are not part of the batch). The file stays dirty until the batch is committed, so if the
+    /// batch is dropped instead, the modifications are kept in the file and written by its next
+    /// flush.
+    pub async fn flush_file(&mut self, file: &mut File) -> Result<()> {
+        file.flush_in_pending(&mut self.tx, self.committed.clone())
+            .await?;
+        self.branch_event_txs.push(file.branch().notify());
+
+        Ok(())
+    }
+
+    /// Commits all the changes in this batch and notifies the subscribers.
+    pub async fn commit(self) -> Result<()> {
+        let Self {
+            tx,
+            event_tx,
+            branch_event_txs,
+            metadata_changed,
+            committed,
+        } = self;
+
+        tx.commit_and_then(move || {
+            committed.store(true, Ordering::Release);
+
+            for branch_event_tx in branch_event_txs {
+                branch_event_tx.send();
+            }
+
+            if metadata_changed {
+                event_tx.send(Payload::MetadataChanged);
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    directory::Directory,
+    error::{Error, Result},
+    file::File,
+    path,
+    store::WriteTransaction,
+};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+
+/// Imports many files and directories into the local branch in a single write transaction.
+/// Obtained with [`Repository::begin_bulk_ingest`](super::Repository::begin_bulk_ingest).
+///
+/// Unlike the regular operations, which each commit their own transaction and publish a new
+/// snapshot to the peers, the whole ingestion is published as a single snapshot when it's
+/// committed. If it's dropped without committing, nothing is imported.
+///
+/// The ingestion holds the repository write transaction until it's committed or dropped. Any other
+/// write to the repository (including merging the remote branches) waits until then, so don't
+/// write to the repository other than through the ingestion while it's alive.
+pub struct BulkIngest {
+    tx: WriteTransaction,
+    root: Directory,
+    // Most recently used directory, so consecutive files in the same directory don't open it again.
+    dir: Option<(Utf8PathBuf, Directory)>,
+    // File being written, see `create_file`.
+    file: Option<File>,
+}
+
+impl BulkIngest {
+    pub(super) fn new(tx: WriteTransaction, root: Directory) -> Self {
+        Self {
+            tx,
+            root,
+            dir: None,
+            file: None,
+        }
+    }
+
+    /// Creates the directory at the given path, including all its ancestors, unless it already
+    /// exists.
+    pub async fn create_directory<P: AsRef<Utf8Path>>(&mut self, path: P) -> Result<()> {
+        self.finish_file().await?;
+        self.enter_directory(path.as_ref()).await
+    }
+
+    /// Creates a new file at the given path (including its missing ancestor directories) and makes
+    /// it the target of the subsequent [`Self::write`] calls. An existing file at the path is
+    /// replaced.
+    pub async fn create_file<P: AsRef<Utf8Path>>(&mut self, path: P) -> Result<()> {
+        self.finish_file().await?;
+
+        let (parent, name) = path::decompose(path.as_ref()).ok_or(Error::EntryIsDirectory)?;
+        self.enter_directory(parent).await?;
+
+        let Some((_, dir)) = &mut self.dir else {
+            unreachable!()
+        };
+        let file = dir.create_file_in(&mut self.tx, name.to_owned()).await?;
+
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    /// Appends `buffer` to the file created by the last [`Self::create_file`] call. Fails with
+    /// `OperationNotSupported` if there is no such file.
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<()> {
+        let file = self.file.as_mut().ok_or(Error::OperationNotSupported)?;
+        file.write_all_in(&mut self.tx, buffer).await
+    }
+
+    /// Commits everything imported so far as a single snapshot and notifies the subscribers.
+    pub async fn commit(mut self) -> Result<()> {
+        self.finish_file().await?;
+
+        let event_tx = self.root.branch().notify();
+        self.tx.commit_and_then(move || event_tx.send()).await?;
+
+        Ok(())
+    }
+
+    async fn finish_file(&mut self) -> Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush_in(&mut self.tx).await?;
+        }
+
+        Ok(())
+    }
+
+    // Opens the directory at the given path (creating it if needed) and makes it the current one.
+    async fn enter_directory(&mut self, path: &Utf8Path) -> Result<()> {
+        let path = normalize(path)?;
+
+        if matches!(&self.dir, Some((dir_path, _)) if *dir_path == path) {
+            return Ok(());
+        }
+
+        let mut curr = self.root.clone();
+
+        for name in path.iter() {
+            curr = curr.open_or_create_directory_in(&mut self.tx, name).await?;
+        }
+
+        self.dir = Some((path, curr));
+
+        Ok(())
+    }
+}
+
+// Strips the root and the `.` components. Like `Branch::ensure_directory_exists`, doesn't support
+// `..` and drive prefixes.
+fn normalize(path: &Utf8Path) -> Result<Utf8PathBuf> {
+    let mut output = Utf8PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Utf8Component::RootDir | Utf8Component::CurDir => (),
+            Utf8Component::Normal(name) => output.push(name),
+            Utf8Component::Prefix(_) | Utf8Component::ParentDir => {
+                return Err(Error::OperationNotSupported)
+            }
+        }
+    }
+
+    Ok(output)
+}
@@ -0,0 +1,47 @@
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Maximum number of paths in `Conflicts::sample`.
+const SAMPLE_LEN: usize = 16;
+
+/// Entries with concurrent versions which couldn't be merged automatically, as found by the last
+/// merge. Obtained with `Repository::conflicts`.
+#[derive(Clone, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Conflicts {
+    pub count: u64,
+    /// Paths of some of the conflicting entries (the first ones in alphabetical order).
+    pub sample: Vec<String>,
+}
+
+impl Conflicts {
+    pub(super) fn new(paths: &BTreeSet<Utf8PathBuf>) -> Self {
+        Self {
+            count: paths.len() as u64,
+            sample: paths
+                .iter()
+                .take(SAMPLE_LEN)
+                .map(|path| path.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let paths: BTreeSet<_> = (0..SAMPLE_LEN + 2)
+            .map(|i| Utf8PathBuf::from(format!("/file-{i:02}.txt")))
+            .collect();
+
+        let conflicts = Conflicts::new(&paths);
+        assert_eq!(conflicts.count, SAMPLE_LEN as u64 + 2);
+        assert_eq!(conflicts.sample.len(), SAMPLE_LEN);
+        assert_eq!(conflicts.sample[0], "/file-00.txt");
+
+        assert_eq!(Conflicts::new(&BTreeSet::new()), Conflicts::default());
+    }
+}
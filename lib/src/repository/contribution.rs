@@ -0,0 +1,89 @@
+use super::metadata;
+use crate::{db, store::Error as StoreError};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Amount of data this replica served to and downloaded from the other replicas over the whole
+/// lifetime of the repository. Useful to see whether a replica contributes to the swarm as much as
+/// it takes from it.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ContributionStats {
+    pub bytes_served: u64,
+    pub bytes_downloaded: u64,
+}
+
+impl ContributionStats {
+    /// Ratio of the served to the downloaded bytes ("seeding ratio"). Returns `None` if nothing has
+    /// been downloaded yet.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.bytes_downloaded > 0 {
+            Some(self.bytes_served as f64 / self.bytes_downloaded as f64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Counts the bytes served and downloaded since the stats were last persisted.
+#[derive(Default)]
+pub(crate) struct ContributionTracker {
+    served: AtomicU64,
+    downloaded: AtomicU64,
+}
+
+impl ContributionTracker {
+    pub fn record_served(&self, bytes: u64) {
+        self.served.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_downloaded(&self, bytes: u64) {
+        self.downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the persisted stats plus the ones not yet persisted.
+    pub async fn load(&self, db: &db::Pool) -> Result<ContributionStats, StoreError> {
+        let mut conn = db.acquire().await?;
+        let stats = metadata::contribution::get(&mut conn).await?;
+
+        Ok(ContributionStats {
+            bytes_served: stats
+                .bytes_served
+                .saturating_add(self.served.load(Ordering::Relaxed)),
+            bytes_downloaded: stats
+                .bytes_downloaded
+                .saturating_add(self.downloaded.load(Ordering::Relaxed)),
+        })
+    }
+
+    /// Adds the not yet persisted stats to the persisted ones.
+    pub async fn persist(&self, db: &db::Pool) -> Result<(), StoreError> {
+        let served = self.served.swap(0, Ordering::Relaxed);
+        let downloaded = self.downloaded.swap(0, Ordering::Relaxed);
+
+        if served == 0 && downloaded == 0 {
+            return Ok(());
+        }
+
+        let result = async {
+            let mut tx = db.begin_write().await?;
+            let stats = metadata::contribution::get(&mut tx).await?;
+            let stats = ContributionStats {
+                bytes_served: stats.bytes_served.saturating_add(served),
+                bytes_downloaded: stats.bytes_downloaded.saturating_add(downloaded),
+            };
+            metadata::contribution::set(&mut tx, &stats).await?;
+            tx.commit().await?;
+
+            Ok::<_, StoreError>(())
+        }
+        .await;
+
+        if result.is_err() {
+            // Put them back so they are not lost and can be persisted next time.
+            self.record_served(served);
+            self.record_downloaded(downloaded);
+        }
+
+        result
+    }
+}
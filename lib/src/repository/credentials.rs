@@ -5,6 +5,7 @@ use crate::{
     error::{Error, Result},
 };
 use bincode::Options;
+use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
 
 /// Credentials for accessing a repository.
@@ -18,7 +19,7 @@ impl Credentials {
     pub fn with_random_writer_id(secrets: AccessSecrets) -> Self {
         Self {
             secrets,
-            writer_id: metadata::generate_writer_id(),
+            writer_id: metadata::generate_writer_id(&mut OsRng),
         }
     }
 
@@ -0,0 +1,73 @@
+//! Cache of data derived from the file contents by the app, e.g. thumbnails or previews. The
+//! results are keyed by the file version (blob id and version vector), not by the path and mtime,
+//! so they survive syncs that don't change the file and are never returned for a version they
+//! were not derived from. The cache is local to this replica, it's not synced.
+
+use crate::{
+    blob::BlobId,
+    crypto::{Hash, Hashable},
+    db,
+    error::Result,
+    version_vector::VersionVector,
+};
+use sqlx::Row;
+
+/// Identifies a specific version of a file.
+pub(super) fn version_hash(blob_id: &BlobId, version_vector: &VersionVector) -> Hash {
+    (blob_id, version_vector).hash()
+}
+
+/// Loads the data of the given kind derived from the file, provided it was derived from the given
+/// version of it. Stale data is left in place until replaced by `set`.
+pub(super) async fn get(
+    conn: &mut db::Connection,
+    blob_id: &BlobId,
+    kind: &str,
+    version_hash: &Hash,
+) -> Result<Option<Vec<u8>>> {
+    let row = sqlx::query(
+        "SELECT data FROM derived_data WHERE blob_id = ? AND kind = ? AND version_hash = ?",
+    )
+    .bind(blob_id.as_ref())
+    .bind(kind)
+    .bind(version_hash)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row.map(|row| row.get(0)))
+}
+
+/// Stores the data of the given kind derived from the given version of the file, replacing any
+/// previously stored one.
+pub(super) async fn set(
+    tx: &mut db::WriteTransaction,
+    blob_id: &BlobId,
+    kind: &str,
+    version_hash: &Hash,
+    data: &[u8],
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO derived_data (blob_id, kind, version_hash, data)
+         VALUES (?, ?, ?, ?)
+         ON CONFLICT (blob_id, kind) DO UPDATE SET
+             version_hash = excluded.version_hash,
+             data = excluded.data",
+    )
+    .bind(blob_id.as_ref())
+    .bind(kind)
+    .bind(version_hash)
+    .bind(data)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes all the derived data.
+pub(super) async fn clear(tx: &mut db::WriteTransaction) -> Result<()> {
+    sqlx::query("DELETE FROM derived_data")
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use crate::{
+    block_tracker::BlockInspection, crypto::sign::PublicKey, protocol::BlockId,
+    version_vector::VersionVector,
+};
+use serde::{Deserialize, Serialize};
+
+/// Why a file is or isn't synced: its versions in the branches, which of their blocks are missing
+/// and what's being done to get them. Obtained with `Repository::explain_sync`. Meant for
+/// diagnostics only, the content of the report may change between releases.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SyncExplanation {
+    /// The versions of the file, one per branch referencing it.
+    pub versions: Vec<FileVersionExplanation>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FileVersionExplanation {
+    pub branch_id: PublicKey,
+    pub version_vector: VersionVector,
+    /// Number of the blocks of this version found in the index. If the first block is missing,
+    /// the length of the file is unknown and so this counts only the blocks whose index nodes
+    /// have already been received.
+    pub block_count: u64,
+    pub missing_blocks: Vec<MissingBlockExplanation>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct MissingBlockExplanation {
+    pub block_id: BlockId,
+    /// Number of the connected peers currently offering the block to us.
+    pub offering_peers: u64,
+    /// Number of the connected peers known to hold the block (learned the same way as for
+    /// `Repository::at_risk_block_count`). Can be higher than `offering_peers` because a peer
+    /// offers a block only once it has sent us the index node referencing it. Zero unless the at
+    /// risk tracking is enabled.
+    pub holding_peers: u64,
+    pub request_state: BlockRequestState,
+}
+
+impl MissingBlockExplanation {
+    pub(super) fn new(
+        block_id: BlockId,
+        inspection: Option<BlockInspection>,
+        holding_peers: usize,
+    ) -> Self {
+        let offering_peers = inspection.map(|inspection| inspection.offers).unwrap_or(0);
+
+        let request_state = match inspection {
+            None
+            | Some(BlockInspection {
+                offers: 0,
+                required: false,
+                ..
+            }) => BlockRequestState::Untracked,
+            Some(BlockInspection { offers: 0, .. }) => BlockRequestState::AwaitingOffer,
+            Some(BlockInspection {
+                requested: true, ..
+            }) => BlockRequestState::Requested,
+            Some(BlockInspection {
+                required: false, ..
+            }) => BlockRequestState::NotRequired,
+            Some(BlockInspection {
+                approved: false, ..
+            }) => BlockRequestState::AwaitingApproval,
+            Some(_) => BlockRequestState::Queued,
+        };
+
+        Self {
+            block_id,
+            offering_peers: offering_peers as u64,
+            holding_peers: holding_peers as u64,
+            request_state,
+        }
+    }
+}
+
+/// What the block tracker is currently doing about a missing block.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockRequestState {
+    /// The block is neither required nor offered. This is the case e.g. when the index nodes
+    /// referencing it haven't been fully processed yet.
+    Untracked,
+    /// The block is required but no connected peer offered it.
+    AwaitingOffer,
+    /// The block is offered but not required yet (the index traversal hasn't reached it).
+    NotRequired,
+    /// The block is offered but its snapshot hasn't passed the quota check yet.
+    AwaitingApproval,
+    /// The block is offered and will be requested once a request slot frees up.
+    Queued,
+    /// The block has been requested from one of the offering peers and the response is pending.
+    Requested,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Block;
+
+    fn state(inspection: Option<BlockInspection>) -> BlockRequestState {
+        MissingBlockExplanation::new(rand::random::<Block>().id, inspection, 0).request_state
+    }
+
+    fn inspection(offers: usize, required: bool, approved: bool) -> Option<BlockInspection> {
+        Some(BlockInspection {
+            offers,
+            required,
+            approved,
+            requested: false,
+        })
+    }
+
+    #[test]
+    fn request_state() {
+        assert_eq!(state(None), BlockRequestState::Untracked);
+        assert_eq!(
+            state(inspection(0, false, true)),
+            BlockRequestState::Untracked
+        );
+        assert_eq!(
+            state(inspection(0, true, false)),
+            BlockRequestState::AwaitingOffer
+        );
+        assert_eq!(
+            state(inspection(2, false, true)),
+            BlockRequestState::NotRequired
+        );
+        assert_eq!(
+            state(inspection(1, true, false)),
+            BlockRequestState::AwaitingApproval
+        );
+        assert_eq!(state(inspection(1, true, true)), BlockRequestState::Queued);
+        assert_eq!(
+            state(Some(BlockInspection {
+                offers: 1,
+                required: true,
+                approved: true,
+                requested: true,
+            })),
+            BlockRequestState::Requested
+        );
+    }
+}
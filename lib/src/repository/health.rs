@@ -0,0 +1,156 @@
+use crate::{network::NatBehavior, progress::Progress};
+use serde::{Deserialize, Serialize};
+
+/// Signals of how well a repository syncs together with the issues detected from them, to tell
+/// the user why the repository isn't syncing and what to do about it. Obtained with
+/// `Repository::health`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RepositoryHealth {
+    pub sync_enabled: bool,
+    /// Number of the remote replicas currently linked with this repository.
+    pub connected_replicas: u64,
+    /// Whether we are behind a NAT which makes hole punching unlikely to succeed. `None` if the
+    /// NAT behavior couldn't be determined.
+    pub restrictive_nat: Option<bool>,
+    /// Seconds since a snapshot was last received from a remote replica. `None` if none has been
+    /// received since the repository was opened.
+    pub last_snapshot_age: Option<u64>,
+    pub at_risk_blocks: u64,
+    pub sync_progress: Progress,
+    /// Whether the local branch is strictly behind another branch, that is, a merge is pending.
+    pub outdated: bool,
+    /// The detected issues, most severe first.
+    pub issues: Vec<HealthIssue>,
+}
+
+impl RepositoryHealth {
+    pub(super) fn new(
+        sync_enabled: bool,
+        connected_replicas: u64,
+        nat_behavior: Option<NatBehavior>,
+        last_snapshot_age: Option<u64>,
+        at_risk_blocks: u64,
+        sync_progress: Progress,
+        outdated: bool,
+    ) -> Self {
+        let mut health = Self {
+            sync_enabled,
+            connected_replicas,
+            restrictive_nat: nat_behavior.map(|nat| nat == NatBehavior::AddressAndPortDependent),
+            last_snapshot_age,
+            at_risk_blocks,
+            sync_progress,
+            outdated,
+            issues: Vec::new(),
+        };
+
+        health.issues = health.detect_issues();
+        health
+    }
+
+    fn detect_issues(&self) -> Vec<HealthIssue> {
+        let mut issues = Vec::new();
+
+        if !self.sync_enabled {
+            issues.push(HealthIssue::SyncDisabled);
+        } else if self.connected_replicas == 0 {
+            if self.restrictive_nat == Some(true) {
+                issues.push(HealthIssue::RestrictiveNat);
+            }
+
+            issues.push(HealthIssue::NoPeers);
+        }
+
+        if self.at_risk_blocks > 0 {
+            issues.push(HealthIssue::BlocksAtRisk);
+        }
+
+        // All the blocks are present so nothing prevents the merge.
+        if self.outdated && self.sync_progress.value >= self.sync_progress.total {
+            issues.push(HealthIssue::MergeStalled);
+        }
+
+        if self.sync_progress.value < self.sync_progress.total {
+            issues.push(HealthIssue::Downloading);
+        }
+
+        issues
+    }
+}
+
+/// Machine-readable code of an issue detected by `Repository::health`.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthIssue {
+    /// Syncing is disabled for this repository.
+    SyncDisabled,
+    /// No remote replica is linked with this repository.
+    NoPeers,
+    /// We are behind a NAT which likely prevents the peers from connecting to us. Enabling port
+    /// forwarding or adding a peer (e.g., a mirror) with a public address helps.
+    RestrictiveNat,
+    /// Some blocks are held by no connected replica and would be lost together with this one.
+    BlocksAtRisk,
+    /// The local branch is behind another branch even though all the blocks are present.
+    MergeStalled,
+    /// Not all the blocks have been downloaded yet.
+    Downloading,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(value: u64, total: u64) -> Progress {
+        Progress { value, total }
+    }
+
+    #[test]
+    fn healthy() {
+        let health = RepositoryHealth::new(true, 2, None, Some(10), 0, progress(5, 5), false);
+        assert!(health.issues.is_empty());
+    }
+
+    #[test]
+    fn isolated() {
+        let health = RepositoryHealth::new(
+            true,
+            0,
+            Some(NatBehavior::AddressAndPortDependent),
+            None,
+            3,
+            progress(1, 5),
+            false,
+        );
+        assert_eq!(
+            health.issues,
+            [
+                HealthIssue::RestrictiveNat,
+                HealthIssue::NoPeers,
+                HealthIssue::BlocksAtRisk,
+                HealthIssue::Downloading,
+            ]
+        );
+
+        let health = RepositoryHealth::new(
+            false,
+            0,
+            Some(NatBehavior::AddressAndPortDependent),
+            None,
+            0,
+            progress(5, 5),
+            false,
+        );
+        assert_eq!(health.issues, [HealthIssue::SyncDisabled]);
+    }
+
+    #[test]
+    fn merge_stalled() {
+        let health = RepositoryHealth::new(true, 1, None, Some(0), 0, progress(5, 5), true);
+        assert_eq!(health.issues, [HealthIssue::MergeStalled]);
+
+        // Still downloading, the merge is expected to wait.
+        let health = RepositoryHealth::new(true, 1, None, Some(0), 0, progress(4, 5), true);
+        assert_eq!(health.issues, [HealthIssue::Downloading]);
+    }
+}
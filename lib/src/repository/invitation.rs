@@ -0,0 +1,108 @@
+//! One-time invitations. An invitation is a (usually blind) share token carrying a random key. The
+//! replica that created it stores the access secrets to grant encrypted with that key and hands
+//! them out to the first peer that redeems the invitation. Any later redemption (by a different
+//! writer) is rejected, so a leaked invite link can't be used to gain access once it was redeemed.
+//!
+//! The invitation id is sent to every peer of the repository, so knowing it is not enough to
+//! redeem the invitation. The redemption also carries a proof, keyed by the invitation key and
+//! bound to the redeeming writer id, which only the holder of the token can produce.
+
+use crate::crypto::{cipher, sign::PublicKey, Hash};
+use deadlock::BlockingMutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, watch};
+
+pub(crate) use ouisync_protocol::message::invitation::{
+    auth_key, invitation_id, redemption_proof, InvitationId, SealedSecrets,
+};
+
+/// Invitation created by this replica, as stored in the repository metadata.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct StoredInvitation {
+    pub id: InvitationId,
+    /// Key to verify the redemption proofs with (see `auth_key`).
+    pub auth_key: cipher::SecretKey,
+    pub sealed: SealedSecrets,
+    /// Writer id of the peer that redeemed the invitation, if any.
+    pub redeemed_by: Option<PublicKey>,
+}
+
+/// Answer of the inviting replica to a redemption.
+#[derive(Debug)]
+pub(crate) enum RedeemReply {
+    Granted(SealedSecrets),
+    Rejected,
+}
+
+/// Redemption of an invitation by this replica that is waiting for the reply from a peer.
+pub(crate) struct Redemption {
+    pending: BlockingMutex<Option<PendingRedemption>>,
+    // Notifies the links that a new redemption started so they can send it to their peers.
+    started: watch::Sender<()>,
+}
+
+struct PendingRedemption {
+    id: InvitationId,
+    writer_id: PublicKey,
+    proof: Hash,
+    reply_tx: oneshot::Sender<RedeemReply>,
+}
+
+impl Default for Redemption {
+    fn default() -> Self {
+        Self {
+            pending: BlockingMutex::new(None),
+            started: watch::channel(()).0,
+        }
+    }
+}
+
+impl Redemption {
+    /// Starts redeeming the invitation with the given key, replacing the previous pending
+    /// redemption (if any).
+    pub fn start(
+        &self,
+        key: &cipher::SecretKey,
+        writer_id: PublicKey,
+    ) -> oneshot::Receiver<RedeemReply> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        *self.pending.lock().unwrap() = Some(PendingRedemption {
+            id: invitation_id(key),
+            writer_id,
+            proof: redemption_proof(&auth_key(key), &writer_id),
+            reply_tx,
+        });
+        self.started.send_replace(());
+
+        reply_rx
+    }
+
+    /// Id of the pending invitation, the writer id to register with it and the proof of knowing
+    /// the invitation key.
+    pub fn pending(&self) -> Option<(InvitationId, PublicKey, Hash)> {
+        self.pending
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|pending| !pending.reply_tx.is_closed())
+            .map(|pending| (pending.id, pending.writer_id, pending.proof))
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.started.subscribe()
+    }
+
+    /// Completes the pending redemption of the given invitation.
+    pub fn complete(&self, id: &InvitationId, reply: RedeemReply) {
+        let mut pending = self.pending.lock().unwrap();
+
+        if pending.as_ref().map(|pending| &pending.id) != Some(id) {
+            return;
+        }
+
+        if let Some(pending) = pending.take() {
+            pending.reply_tx.send(reply).ok();
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     access_control::{
-        Access, AccessSecrets, KeyAndSalt, LocalSecret, SetLocalSecret, WriteSecrets,
+        Access, AccessMode, AccessSecrets, KeyAndSalt, LocalSecret, SetLocalSecret, WriteSecrets,
     },
     crypto::{
         cipher::{self, Nonce},
@@ -8,10 +8,11 @@ use crate::{
     },
     db::{self, DatabaseId},
     device_id::DeviceId,
+    event::{EventSender, Payload},
     repository::RepositoryId,
     store::Error as StoreError,
 };
-use rand::{rngs::OsRng, Rng};
+use rand::{CryptoRng, Rng};
 use sqlx::Row;
 use std::{borrow::Cow, fmt, time::Duration};
 use tracing::instrument;
@@ -35,6 +36,30 @@ const READ_KEY_VALIDATOR: &[u8] = b"read_key_validator";
 
 const QUOTA: &[u8] = b"quota";
 const BLOCK_EXPIRATION: &[u8] = b"block_expiration";
+const RECEIVE_FILTER_PERSISTENT: &[u8] = b"receive_filter_persistent";
+const MAX_DIRECTORY_ENTRIES: &[u8] = b"max_directory_entries";
+const MAX_PATH_DEPTH: &[u8] = b"max_path_depth";
+const MAX_NAME_LENGTH: &[u8] = b"max_name_length";
+const WRITE_FENCE: &[u8] = b"write_fence";
+const DIRECTORY_COMPRESSION: &[u8] = b"directory_compression";
+const CONTRIBUTION_BYTES_SERVED: &[u8] = b"contribution_bytes_served";
+const CONTRIBUTION_BYTES_DOWNLOADED: &[u8] = b"contribution_bytes_downloaded";
+const MIRROR_RECEIPTS: &[u8] = b"mirror_receipts";
+const MIRROR_RECEIPT_KEYS: &[u8] = b"mirror_receipt_keys";
+const INVITATIONS: &[u8] = b"invitations";
+const SHARE_ORIGIN: &[u8] = b"share_origin";
+const ACCESS_NOTICE: &[u8] = b"access_notice";
+const WIPE_DIRECTIVES: &[u8] = b"wipe_directives";
+const UNLOCK_TOKEN_VALIDATOR: &[u8] = b"unlock_token_validator";
+const UNLOCK_TOKEN_READ_KEY: &[u8] = b"unlock_token_read_key";
+const UNLOCK_TOKEN_WRITE_KEY: &[u8] = b"unlock_token_write_key";
+const UNLOCK_TOKEN_WRITER_ID: &[u8] = b"unlock_token_writer_id";
+
+// Labels shown by the frontends. They are stored as the user-defined metadata so they are also
+// accessible using `Metadata::get` / `Metadata::set` under these names.
+pub(crate) const DISPLAY_NAME: &str = "display_name";
+pub(crate) const ICON: &str = "icon";
+pub(crate) const COLOR: &str = "color";
 
 // Support for data migrations.
 const DATA_VERSION: &[u8] = b"data_version";
@@ -61,11 +86,12 @@ const DEPRECATED_PASSWORD_SALT: &[u8] = b"password_salt";
 // -------------------------------------------------------------------
 pub struct Metadata {
     db: db::Pool,
+    event_tx: EventSender,
 }
 
 impl Metadata {
-    pub(crate) fn new(db: db::Pool) -> Self {
-        Self { db }
+    pub(crate) fn new(db: db::Pool, event_tx: EventSender) -> Self {
+        Self { db, event_tx }
     }
 
     #[instrument(skip(self), fields(value))]
@@ -99,12 +125,14 @@ impl Metadata {
     pub async fn write(&self) -> Result<MetadataWriter, StoreError> {
         Ok(MetadataWriter {
             tx: self.db.begin_write().await?,
+            event_tx: self.event_tx.clone(),
         })
     }
 }
 
 pub struct MetadataWriter {
     tx: db::WriteTransaction,
+    event_tx: EventSender,
 }
 
 impl MetadataWriter {
@@ -126,8 +154,11 @@ impl MetadataWriter {
         remove_public(&mut self.tx, name.as_bytes()).await
     }
 
+    /// Commits the changes and notifies the subscribers with `Payload::MetadataChanged`.
     pub async fn commit(self) -> Result<(), StoreError> {
         self.tx.commit().await?;
+        self.event_tx.send(Payload::MetadataChanged);
+
         Ok(())
     }
 }
@@ -180,12 +211,15 @@ pub(crate) fn secret_to_key_and_salt(secret: &'_ SetLocalSecret) -> Cow<'_, KeyA
 // -------------------------------------------------------------------
 // Database ID
 // -------------------------------------------------------------------
-pub(crate) async fn get_or_generate_database_id(db: &db::Pool) -> Result<DatabaseId, StoreError> {
+pub(crate) async fn get_or_generate_database_id(
+    db: &db::Pool,
+    rng: &mut impl Rng,
+) -> Result<DatabaseId, StoreError> {
     let mut tx = db.begin_write().await?;
     let database_id = match get_public_blob(&mut tx, DATABASE_ID).await {
         Ok(Some(database_id)) => database_id,
         Ok(None) => {
-            let database_id: DatabaseId = OsRng.gen();
+            let database_id: DatabaseId = rng.gen();
             set_public_blob(&mut tx, DATABASE_ID, &database_id).await?;
             tx.commit().await?;
             database_id
@@ -217,18 +251,19 @@ pub(crate) async fn set_writer_id(
 
 // TODO: Writer IDs are currently practically just UUIDs with no real security (any replica with a
 // write access may impersonate any other replica).
-pub(crate) fn generate_writer_id() -> sign::PublicKey {
-    sign::Keypair::random().public_key()
+pub(crate) fn generate_writer_id(rng: &mut (impl Rng + CryptoRng)) -> sign::PublicKey {
+    sign::Keypair::generate(rng).public_key()
 }
 
 pub(crate) async fn get_or_generate_writer_id(
     tx: &mut db::WriteTransaction,
     local_key: Option<&cipher::SecretKey>,
+    rng: &mut (impl Rng + CryptoRng),
 ) -> Result<sign::PublicKey, StoreError> {
     let writer_id = if let Some(writer_id) = get_writer_id(tx, local_key).await? {
         writer_id
     } else {
-        let writer_id = generate_writer_id();
+        let writer_id = generate_writer_id(rng);
         set_writer_id(tx, &writer_id, local_key).await?;
         writer_id
     };
@@ -615,6 +650,41 @@ pub(crate) async fn get_access_secrets<'a>(
     Ok((AccessSecrets::Blind { id }, None))
 }
 
+/// Re-encrypts the read and/or write keys locked with the `old` local secret using the `new` one.
+/// The keys themselves stay the same so nothing else needs to be re-encrypted. Returns the highest
+/// access mode whose key was re-encrypted (`Blind` if `old` doesn't unlock any).
+pub(crate) async fn change_local_secret(
+    tx: &mut db::WriteTransaction,
+    old: &LocalSecret,
+    new: &KeyAndSalt,
+) -> Result<AccessMode, StoreError> {
+    let id = get_repository_id(tx).await?;
+    let mut mode = AccessMode::Blind;
+
+    let old_read_key = secret_to_key(tx, KeyType::Read, old).await?;
+
+    if let Some(read_key) = get_read_key(tx, Some(&*old_read_key), &id).await? {
+        set_read_key(tx, &id, &read_key, Some(new)).await?;
+        mode = AccessMode::Read;
+    }
+
+    let old_write_key = secret_to_key(tx, KeyType::Write, old).await?;
+
+    if let Some(write_keys) = get_write_key(tx, Some(&*old_write_key), &id).await? {
+        let writer_id = get_writer_id(tx, Some(&*old_write_key)).await?;
+
+        set_write_key(tx, &WriteSecrets::from(write_keys), Some(new)).await?;
+
+        if let Some(writer_id) = writer_id {
+            set_writer_id(tx, &writer_id, Some(&new.key)).await?;
+        }
+
+        mode = AccessMode::Write;
+    }
+
+    Ok(mode)
+}
+
 /// Returns Ok(None) when the key is there but isn't valid.
 async fn get_write_key(
     conn: &mut db::Connection,
@@ -725,6 +795,455 @@ pub(crate) mod block_expiration {
     }
 }
 
+// -------------------------------------------------------------------
+// Receive filter persistence
+// -------------------------------------------------------------------
+pub(crate) mod receive_filter_persistent {
+    use super::*;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<bool, StoreError> {
+        Ok(get_public(conn, RECEIVE_FILTER_PERSISTENT)
+            .await?
+            .unwrap_or(false))
+    }
+
+    pub(crate) async fn set(tx: &mut db::WriteTransaction, value: bool) -> Result<(), StoreError> {
+        set_public(tx, RECEIVE_FILTER_PERSISTENT, value).await
+    }
+}
+
+// -------------------------------------------------------------------
+// Directory limits
+// -------------------------------------------------------------------
+pub(crate) mod directory_limits {
+    use super::*;
+    use crate::directory::DirectoryLimits;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<DirectoryLimits, StoreError> {
+        let default = DirectoryLimits::default();
+
+        Ok(DirectoryLimits {
+            max_entries: get_limit(conn, MAX_DIRECTORY_ENTRIES, default.max_entries).await?,
+            max_depth: get_limit(conn, MAX_PATH_DEPTH, default.max_depth).await?,
+            max_name_length: get_limit(conn, MAX_NAME_LENGTH, default.max_name_length).await?,
+        })
+    }
+
+    pub(crate) async fn set(
+        tx: &mut db::WriteTransaction,
+        value: DirectoryLimits,
+    ) -> Result<(), StoreError> {
+        set_public(tx, MAX_DIRECTORY_ENTRIES, u64::from(value.max_entries)).await?;
+        set_public(tx, MAX_PATH_DEPTH, u64::from(value.max_depth)).await?;
+        set_public(tx, MAX_NAME_LENGTH, u64::from(value.max_name_length)).await?;
+
+        Ok(())
+    }
+
+    async fn get_limit(
+        conn: &mut db::Connection,
+        id: &[u8],
+        default: u32,
+    ) -> Result<u32, StoreError> {
+        Ok(get_public::<u64>(conn, id)
+            .await?
+            .map(|value| value.try_into().unwrap_or(u32::MAX))
+            .unwrap_or(default))
+    }
+}
+
+// -------------------------------------------------------------------
+// Write fence
+// -------------------------------------------------------------------
+pub(crate) mod write_fence {
+    use super::*;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<bool, StoreError> {
+        Ok(get_public(conn, WRITE_FENCE).await?.unwrap_or(false))
+    }
+
+    pub(crate) async fn set(tx: &mut db::WriteTransaction, value: bool) -> Result<(), StoreError> {
+        set_public(tx, WRITE_FENCE, value).await
+    }
+}
+
+// -------------------------------------------------------------------
+// Directory compression
+// -------------------------------------------------------------------
+pub(crate) mod directory_compression {
+    use super::*;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<bool, StoreError> {
+        Ok(get_public(conn, DIRECTORY_COMPRESSION)
+            .await?
+            .unwrap_or(false))
+    }
+
+    pub(crate) async fn set(tx: &mut db::WriteTransaction, value: bool) -> Result<(), StoreError> {
+        set_public(tx, DIRECTORY_COMPRESSION, value).await
+    }
+}
+
+// -------------------------------------------------------------------
+// Contribution stats
+// -------------------------------------------------------------------
+pub(crate) mod contribution {
+    use super::*;
+    use crate::repository::ContributionStats;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<ContributionStats, StoreError> {
+        Ok(ContributionStats {
+            bytes_served: get_public(conn, CONTRIBUTION_BYTES_SERVED)
+                .await?
+                .unwrap_or(0),
+            bytes_downloaded: get_public(conn, CONTRIBUTION_BYTES_DOWNLOADED)
+                .await?
+                .unwrap_or(0),
+        })
+    }
+
+    pub(crate) async fn set(
+        tx: &mut db::WriteTransaction,
+        value: &ContributionStats,
+    ) -> Result<(), StoreError> {
+        set_public(tx, CONTRIBUTION_BYTES_SERVED, value.bytes_served).await?;
+        set_public(tx, CONTRIBUTION_BYTES_DOWNLOADED, value.bytes_downloaded).await
+    }
+}
+
+// -------------------------------------------------------------------
+// Mirror receipts
+// -------------------------------------------------------------------
+pub(crate) mod mirror_receipts {
+    use super::*;
+    use crate::network::MirrorReceipt;
+
+    // Max number of persisted receipts. When exceeded, the oldest ones are discarded.
+    const MAX_RECEIPTS: usize = 256;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<Vec<MirrorReceipt>, StoreError> {
+        let Some(bytes) = get_public_blob::<Vec<u8>>(conn, MIRROR_RECEIPTS).await? else {
+            return Ok(Vec::new());
+        };
+
+        bincode::deserialize(&bytes).map_err(|_| StoreError::MalformedData)
+    }
+
+    /// Inserts the receipts, replacing the previous receipts (if any) from the same mirror for the
+    /// same branch.
+    pub(crate) async fn insert_all(
+        tx: &mut db::WriteTransaction,
+        new_receipts: &[MirrorReceipt],
+    ) -> Result<(), StoreError> {
+        let mut receipts = get(tx).await?;
+
+        for receipt in new_receipts {
+            receipts.retain(|existing| !existing.is_superseded_by(receipt));
+            receipts.push(receipt.clone());
+        }
+
+        if receipts.len() > MAX_RECEIPTS {
+            receipts.drain(..receipts.len() - MAX_RECEIPTS);
+        }
+
+        let bytes = bincode::serialize(&receipts).expect("failed to serialize mirror receipts");
+        set_public_blob(tx, MIRROR_RECEIPTS, bytes).await
+    }
+
+    /// Returns the keys this replica signs its own receipts with, generating them if they don't
+    /// exist yet. Unlike the runtime id, they stay the same across restarts so the receipts issued
+    /// by this replica can be attributed to it.
+    pub(crate) async fn get_or_generate_keys(
+        db: &db::Pool,
+        rng: &mut (impl Rng + CryptoRng),
+    ) -> Result<sign::Keypair, StoreError> {
+        let mut tx = db.begin_write().await?;
+        let keys = match get_public_blob::<sign::Keypair>(&mut tx, MIRROR_RECEIPT_KEYS).await? {
+            Some(keys) => keys,
+            None => {
+                let keys = sign::Keypair::generate(rng);
+                set_public_blob(&mut tx, MIRROR_RECEIPT_KEYS, keys.to_bytes()).await?;
+                tx.commit().await?;
+                keys
+            }
+        };
+
+        Ok(keys)
+    }
+}
+
+// -------------------------------------------------------------------
+// Invitations
+// -------------------------------------------------------------------
+pub(crate) mod invitations {
+    use super::*;
+    use crate::repository::invitation::{self, InvitationId, RedeemReply, StoredInvitation};
+
+    pub(crate) async fn get(
+        conn: &mut db::Connection,
+    ) -> Result<Vec<StoredInvitation>, StoreError> {
+        let Some(bytes) = get_public_blob::<Vec<u8>>(conn, INVITATIONS).await? else {
+            return Ok(Vec::new());
+        };
+
+        bincode::deserialize(&bytes).map_err(|_| StoreError::MalformedData)
+    }
+
+    pub(crate) async fn insert(
+        tx: &mut db::WriteTransaction,
+        invitation: StoredInvitation,
+    ) -> Result<(), StoreError> {
+        let mut invitations = get(tx).await?;
+        invitations.push(invitation);
+        set(tx, &invitations).await
+    }
+
+    /// Marks the invitation as redeemed by the given writer and returns its sealed secrets.
+    /// Rejects the redemption if the invitation was already redeemed by another writer.
+    /// Redeeming again by the same writer succeeds so the redemption can be retried when the
+    /// reply gets lost. Returns `None` if there is no such invitation or if the proof doesn't
+    /// match the writer id, so a peer that only learned the invitation id can't redeem it.
+    pub(crate) async fn redeem(
+        tx: &mut db::WriteTransaction,
+        id: &InvitationId,
+        writer_id: sign::PublicKey,
+        proof: &Hash,
+    ) -> Result<Option<RedeemReply>, StoreError> {
+        let mut invitations = get(tx).await?;
+
+        let Some(invitation) = invitations
+            .iter_mut()
+            .find(|invitation| &invitation.id == id)
+        else {
+            return Ok(None);
+        };
+
+        if &invitation::redemption_proof(&invitation.auth_key, &writer_id) != proof {
+            return Ok(None);
+        }
+
+        match invitation.redeemed_by {
+            Some(redeemed_by) if redeemed_by != writer_id => {
+                return Ok(Some(RedeemReply::Rejected))
+            }
+            Some(_) => return Ok(Some(RedeemReply::Granted(invitation.sealed.clone()))),
+            None => (),
+        }
+
+        invitation.redeemed_by = Some(writer_id);
+        let sealed = invitation.sealed.clone();
+
+        set(tx, &invitations).await?;
+
+        Ok(Some(RedeemReply::Granted(sealed)))
+    }
+
+    async fn set(
+        tx: &mut db::WriteTransaction,
+        invitations: &[StoredInvitation],
+    ) -> Result<(), StoreError> {
+        let bytes = bincode::serialize(invitations).expect("failed to serialize invitations");
+        set_public_blob(tx, INVITATIONS, bytes).await
+    }
+}
+
+// -------------------------------------------------------------------
+// Share origin
+// -------------------------------------------------------------------
+pub(crate) mod share_origin {
+    use super::*;
+    use crate::repository::ShareOrigin;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<Option<ShareOrigin>, StoreError> {
+        let Some(bytes) = get_public_blob::<Vec<u8>>(conn, SHARE_ORIGIN).await? else {
+            return Ok(None);
+        };
+
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|_| StoreError::MalformedData)
+    }
+
+    /// Stores the origin unless some is already stored (the origin is recorded only once). Returns
+    /// whether it was stored.
+    pub(crate) async fn init(
+        tx: &mut db::WriteTransaction,
+        origin: &ShareOrigin,
+    ) -> Result<bool, StoreError> {
+        if get(tx).await?.is_some() {
+            return Ok(false);
+        }
+
+        let bytes = bincode::serialize(origin).expect("failed to serialize share origin");
+        set_public_blob(tx, SHARE_ORIGIN, bytes).await?;
+
+        Ok(true)
+    }
+}
+
+// -------------------------------------------------------------------
+// Access notice
+// -------------------------------------------------------------------
+pub(crate) mod access_notice {
+    use super::*;
+    use crate::repository::AccessNotice;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<Option<AccessNotice>, StoreError> {
+        let Some(bytes) = get_public_blob::<Vec<u8>>(conn, ACCESS_NOTICE).await? else {
+            return Ok(None);
+        };
+
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|_| StoreError::MalformedData)
+    }
+
+    /// Stores the notice unless a newer or equally old one is already stored. Returns whether it
+    /// was stored.
+    pub(crate) async fn set(
+        tx: &mut db::WriteTransaction,
+        notice: &AccessNotice,
+    ) -> Result<bool, StoreError> {
+        if let Some(current) = get(tx).await? {
+            if current.issued_at >= notice.issued_at {
+                return Ok(false);
+            }
+        }
+
+        let bytes = bincode::serialize(notice).expect("failed to serialize access notice");
+        set_public_blob(tx, ACCESS_NOTICE, bytes).await?;
+
+        Ok(true)
+    }
+}
+
+// -------------------------------------------------------------------
+// Wipe directives
+// -------------------------------------------------------------------
+pub(crate) mod wipe_directives {
+    use super::*;
+    use crate::repository::WipeDirective;
+
+    // Max number of persisted directives. When exceeded, the oldest ones are discarded.
+    const MAX_DIRECTIVES: usize = 64;
+
+    pub(crate) async fn get(conn: &mut db::Connection) -> Result<Vec<WipeDirective>, StoreError> {
+        let Some(bytes) = get_public_blob::<Vec<u8>>(conn, WIPE_DIRECTIVES).await? else {
+            return Ok(Vec::new());
+        };
+
+        bincode::deserialize(&bytes).map_err(|_| StoreError::MalformedData)
+    }
+
+    /// Stores the directive unless it's already stored. Returns all the stored directives if it
+    /// was stored or `None` if not.
+    pub(crate) async fn insert(
+        tx: &mut db::WriteTransaction,
+        directive: WipeDirective,
+    ) -> Result<Option<Vec<WipeDirective>>, StoreError> {
+        let mut directives = get(tx).await?;
+
+        if directives.contains(&directive) {
+            return Ok(None);
+        }
+
+        directives.push(directive);
+
+        if directives.len() > MAX_DIRECTIVES {
+            directives.drain(..directives.len() - MAX_DIRECTIVES);
+        }
+
+        let bytes = bincode::serialize(&directives).expect("failed to serialize wipe directives");
+        set_public_blob(tx, WIPE_DIRECTIVES, bytes).await?;
+
+        Ok(Some(directives))
+    }
+}
+
+// -------------------------------------------------------------------
+// Unlock token
+// -------------------------------------------------------------------
+pub(crate) mod unlock_token {
+    use super::*;
+
+    /// Stores the secrets (and the writer id if they are write secrets) encrypted with the token
+    /// key, replacing the previous token (if any).
+    pub(crate) async fn set(
+        tx: &mut db::WriteTransaction,
+        secrets: &AccessSecrets,
+        writer_id: &sign::PublicKey,
+        key: &cipher::SecretKey,
+    ) -> Result<(), StoreError> {
+        remove(tx).await?;
+
+        match secrets {
+            AccessSecrets::Blind { .. } => return Ok(()),
+            AccessSecrets::Read { read_key, .. } => {
+                set_secret_blob(tx, UNLOCK_TOKEN_READ_KEY, read_key, key).await?;
+            }
+            AccessSecrets::Write(secrets) => {
+                set_secret_blob(tx, UNLOCK_TOKEN_READ_KEY, &secrets.read_key, key).await?;
+                set_secret_blob(
+                    tx,
+                    UNLOCK_TOKEN_WRITE_KEY,
+                    secrets.write_keys.to_bytes(),
+                    key,
+                )
+                .await?;
+                set_secret_blob(tx, UNLOCK_TOKEN_WRITER_ID, writer_id, key).await?;
+            }
+        }
+
+        set_secret_blob(tx, UNLOCK_TOKEN_VALIDATOR, validator(secrets.id()), key).await
+    }
+
+    /// Returns the secrets and, for write secrets, the writer id stored with the token key or
+    /// `None` if there is no token or the key doesn't match it.
+    pub(crate) async fn get(
+        conn: &mut db::Connection,
+        id: &RepositoryId,
+        key: &cipher::SecretKey,
+    ) -> Result<Option<(AccessSecrets, Option<sign::PublicKey>)>, StoreError> {
+        let actual_validator: Option<Hash> =
+            get_secret_blob(conn, UNLOCK_TOKEN_VALIDATOR, key).await?;
+
+        if actual_validator != Some(validator(id)) {
+            return Ok(None);
+        }
+
+        if let Some(write_keys) =
+            get_secret_blob::<sign::Keypair>(conn, UNLOCK_TOKEN_WRITE_KEY, key).await?
+        {
+            let writer_id = get_secret_blob(conn, UNLOCK_TOKEN_WRITER_ID, key).await?;
+            let secrets = AccessSecrets::Write(WriteSecrets::from(write_keys));
+
+            return Ok(Some((secrets, writer_id)));
+        }
+
+        let read_key = get_secret_blob(conn, UNLOCK_TOKEN_READ_KEY, key)
+            .await?
+            .ok_or(StoreError::MalformedData)?;
+
+        Ok(Some((AccessSecrets::Read { id: *id, read_key }, None)))
+    }
+
+    pub(crate) async fn remove(tx: &mut db::WriteTransaction) -> Result<(), StoreError> {
+        sqlx::query("DELETE FROM metadata_secret WHERE name IN (?, ?, ?, ?)")
+            .bind(UNLOCK_TOKEN_VALIDATOR)
+            .bind(UNLOCK_TOKEN_READ_KEY)
+            .bind(UNLOCK_TOKEN_WRITE_KEY)
+            .bind(UNLOCK_TOKEN_WRITER_ID)
+            .execute(tx)
+            .await?;
+
+        Ok(())
+    }
+
+    fn validator(id: &RepositoryId) -> Hash {
+        id.salted_hash(b"ouisync unlock token validator")
+    }
+}
+
 // -------------------------------------------------------------------
 // Data version
 // -------------------------------------------------------------------
@@ -794,7 +1313,7 @@ where
     }
 }
 
-async fn set_public<'a, T>(
+pub(super) async fn set_public<'a, T>(
     tx: &mut db::WriteTransaction,
     id: &'a [u8],
     value: T,
@@ -810,7 +1329,10 @@ where
     Ok(())
 }
 
-async fn remove_public(tx: &mut db::WriteTransaction, id: &[u8]) -> Result<(), StoreError> {
+pub(super) async fn remove_public(
+    tx: &mut db::WriteTransaction,
+    id: &[u8],
+) -> Result<(), StoreError> {
     sqlx::query("DELETE FROM metadata_public WHERE name = ?")
         .bind(id)
         .execute(tx)
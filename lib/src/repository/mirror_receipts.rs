@@ -0,0 +1,67 @@
+use super::metadata;
+use crate::{db, network::MirrorReceipt, store::Error as StoreError};
+use deadlock::BlockingMutex;
+use std::mem;
+
+/// Mirror receipts received since they were last persisted. Receipts can arrive frequently, so
+/// instead of writing each one to the db immediately, they are collected here and persisted in
+/// batches.
+#[derive(Default)]
+pub(crate) struct MirrorReceiptTracker {
+    pending: BlockingMutex<Vec<MirrorReceipt>>,
+}
+
+impl MirrorReceiptTracker {
+    /// Records the receipt, replacing the pending receipt (if any) from the same mirror for the
+    /// same branch.
+    pub fn record(&self, receipt: MirrorReceipt) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|existing| !existing.is_superseded_by(&receipt));
+        pending.push(receipt);
+    }
+
+    /// Returns the persisted receipts plus the ones not yet persisted.
+    pub async fn load(&self, db: &db::Pool) -> Result<Vec<MirrorReceipt>, StoreError> {
+        let mut conn = db.acquire().await?;
+        let mut receipts = metadata::mirror_receipts::get(&mut conn).await?;
+
+        for receipt in self.pending.lock().unwrap().iter() {
+            receipts.retain(|existing| !existing.is_superseded_by(receipt));
+            receipts.push(receipt.clone());
+        }
+
+        Ok(receipts)
+    }
+
+    /// Persists the pending receipts in a single transaction.
+    pub async fn persist(&self, db: &db::Pool) -> Result<(), StoreError> {
+        let pending = mem::take(&mut *self.pending.lock().unwrap());
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let result = async {
+            let mut tx = db.begin_write().await?;
+            metadata::mirror_receipts::insert_all(&mut tx, &pending).await?;
+            tx.commit().await?;
+
+            Ok::<_, StoreError>(())
+        }
+        .await;
+
+        if result.is_err() {
+            // Put them back so they are not lost and can be persisted next time. Receipts recorded
+            // in the meantime are newer so they take precedence.
+            let mut current = self.pending.lock().unwrap();
+            let newer = mem::replace(&mut *current, pending);
+            drop(current);
+
+            for receipt in newer {
+                self.record(receipt);
+            }
+        }
+
+        result
+    }
+}
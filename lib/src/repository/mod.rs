@@ -1,9 +1,23 @@
+mod batch;
+mod bulk_ingest;
+mod conflicts;
+mod contribution;
 mod credentials;
+mod derived_data;
+mod explain_sync;
+mod health;
 mod id;
+mod invitation;
 mod metadata;
+mod mirror_receipts;
 mod monitor;
 mod params;
+mod prune;
+mod share_origin;
+mod snapshot;
+mod unreplicated;
 mod vault;
+mod wipe;
 mod worker;
 
 #[cfg(test)]
@@ -12,56 +26,105 @@ mod tests;
 mod vault_tests;
 
 pub use self::{
-    credentials::Credentials, id::RepositoryId, metadata::Metadata, params::RepositoryParams,
+    batch::Batch,
+    bulk_ingest::BulkIngest,
+    conflicts::Conflicts,
+    contribution::ContributionStats,
+    credentials::Credentials,
+    explain_sync::{
+        BlockRequestState, FileVersionExplanation, MissingBlockExplanation, SyncExplanation,
+    },
+    health::{HealthIssue, RepositoryHealth},
+    id::RepositoryId,
+    metadata::Metadata,
+    params::RepositoryParams,
+    share_origin::ShareOrigin,
+    snapshot::{SnapshotBlockPresence, SnapshotInfo, SnapshotState},
+    unreplicated::UnreplicatedChanges,
 };
+pub use ouisync_protocol::message::AccessNotice;
 
 pub(crate) use self::{
+    contribution::ContributionTracker,
     id::LocalId,
-    metadata::{data_version, quota},
+    invitation::{InvitationId, RedeemReply, SealedSecrets},
+    metadata::{data_version, quota, MetadataSet},
+    mirror_receipts::MirrorReceiptTracker,
     monitor::RepositoryMonitor,
     vault::{BlockRequestMode, Vault},
 };
+pub(crate) use ouisync_protocol::message::WipeDirective;
 
 use crate::{
-    access_control::{Access, AccessChange, AccessKeys, AccessMode, AccessSecrets, LocalSecret},
-    branch::{Branch, BranchShared},
-    crypto::{sign::PublicKey, PasswordSalt},
-    db::{self, DatabaseId},
+    access_control::{
+        Access, AccessChange, AccessKeys, AccessMode, AccessSecrets, LocalSecret, SetLocalSecret,
+        ShareToken, UnlockToken,
+    },
+    blob::{BlobId, BlockIds},
+    branch::{self, Branch, BranchShared},
+    crypto::{cipher, sign::PublicKey, PasswordSalt},
+    db::{self, DatabaseId, DbRecovery, FreezeGuard, FreezeMode},
     debug::DebugPrinter,
-    directory::{Directory, DirectoryFallback, DirectoryLocking, EntryRef, EntryType},
+    directory::{
+        self, Directory, DirectoryFallback, DirectoryLimits, DirectoryLocking, EntryDiff, EntryRef,
+        EntryType,
+    },
+    entry_proof::EntryProof,
     error::{Error, Result},
-    event::{Event, EventSender},
+    event::{self, Event, EventSender, Payload},
     file::File,
     joint_directory::{JointDirectory, JointEntryRef, MissingVersionStrategy},
+    memory::MemoryBudget,
+    network::{MirrorReceipt, NatBehavior},
     path,
     progress::Progress,
-    protocol::{RootNodeFilter, BLOCK_SIZE},
+    protocol::{Block, BlockContent, BlockId, BlockNonce, RootNodeFilter, BLOCK_SIZE},
+    rng::SharedRng,
     storage_size::StorageSize,
     store,
     sync::stream::Throttle,
+    time::Timestamp,
     version_vector::VersionVector,
 };
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use deadlock::{BlockingMutex, BlockingRwLock};
 use futures_util::{future, TryStreamExt};
-use futures_util::{stream, StreamExt};
+use futures_util::{stream, Stream, StreamExt};
 use metrics::Recorder;
 use scoped_task::ScopedJoinHandle;
 use state_monitor::StateMonitor;
-use std::{borrow::Cow, io, path::Path, pin::pin, sync::Arc};
+use std::{
+    borrow::Cow,
+    fmt, io,
+    path::Path,
+    pin::pin,
+    sync::{atomic::Ordering, Arc},
+    time::SystemTime,
+};
 use tokio::{
     fs,
-    sync::broadcast::{self, error::RecvError},
-    time::Duration,
+    sync::broadcast,
+    task,
+    time::{self, Duration, MissedTickBehavior},
 };
 use tracing::instrument::Instrument;
 
 const EVENT_CHANNEL_CAPACITY: usize = 256;
+const SCRUB_INTERVAL: Duration = Duration::from_millis(250);
+const AT_RISK_INTERVAL: Duration = Duration::from_secs(30);
+const AT_RISK_PAGE_SIZE: u32 = 1024;
+// Max number of at risk blocks to be offered to the remote replicas per `AT_RISK_INTERVAL`.
+const MAX_AT_RISK_SAMPLE: usize = 1024;
+const CONTRIBUTION_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct Repository {
     shared: Arc<Shared>,
     worker_handle: BlockingMutex<Option<ScopedJoinHandle<()>>>,
     progress_reporter_handle: BlockingMutex<Option<ScopedJoinHandle<()>>>,
+    scrubber_handle: BlockingMutex<Option<ScopedJoinHandle<()>>>,
+    at_risk_tracker_handle: BlockingMutex<Option<ScopedJoinHandle<()>>>,
+    contribution_persister_handle: BlockingMutex<Option<ScopedJoinHandle<()>>>,
+    wipe_watcher_handle: BlockingMutex<Option<ScopedJoinHandle<()>>>,
 }
 
 /// Delete the repository database
@@ -93,12 +156,15 @@ impl Repository {
         let pool = params.create().await?;
         let device_id = params.device_id();
         let monitor = params.monitor();
+        let memory = params.memory_budget().clone();
+        let mut rng = params.rng();
 
         let mut tx = pool.begin_write().await?;
 
         let local_keys = metadata::initialize_access_secrets(&mut tx, &access).await?;
         let writer_id =
-            metadata::get_or_generate_writer_id(&mut tx, local_keys.write.as_deref()).await?;
+            metadata::get_or_generate_writer_id(&mut tx, local_keys.write.as_deref(), &mut rng)
+                .await?;
         metadata::set_device_id(&mut tx, &device_id).await?;
 
         tx.commit().await?;
@@ -108,7 +174,7 @@ impl Repository {
             writer_id,
         };
 
-        Self::new(pool, credentials, monitor).await
+        Self::new(pool, memory, credentials, monitor, rng).await
     }
 
     /// Opens an existing repository.
@@ -120,6 +186,8 @@ impl Repository {
         let pool = params.open().await?;
         let monitor = params.monitor();
         let device_id = params.device_id();
+        let memory = params.memory_budget().clone();
+        let mut rng = params.rng();
 
         let mut tx = pool.begin_write().await?;
 
@@ -130,9 +198,9 @@ impl Repository {
 
         let writer_id = if metadata::check_device_id(&mut tx, &device_id).await? {
             if secrets.can_write() {
-                metadata::get_or_generate_writer_id(&mut tx, local_key.as_deref()).await?
+                metadata::get_or_generate_writer_id(&mut tx, local_key.as_deref(), &mut rng).await?
             } else {
-                metadata::generate_writer_id()
+                metadata::generate_writer_id(&mut rng)
             }
         } else {
             // Device id changed, likely because the repo database has been transferred to a
@@ -141,7 +209,7 @@ impl Repository {
             // Note we need to do this even when not currently opening the repo in write mode. This
             // is so that when the access mode is subsequently switched to write
             // (with [set_access_mode]) we don't end up using the wrong writer_id.
-            let writer_id = metadata::generate_writer_id();
+            let writer_id = metadata::generate_writer_id(&mut rng);
 
             metadata::set_device_id(&mut tx, &device_id).await?;
             metadata::set_writer_id(&mut tx, &writer_id, local_key.as_deref()).await?;
@@ -153,13 +221,15 @@ impl Repository {
 
         let credentials = Credentials { secrets, writer_id };
 
-        Self::new(pool, credentials, monitor).await
+        Self::new(pool, memory, credentials, monitor, rng).await
     }
 
     async fn new(
         pool: db::Pool,
+        memory: MemoryBudget,
         credentials: Credentials,
         monitor: RepositoryMonitor,
+        mut rng: SharedRng,
     ) -> Result<Self> {
         let event_tx = EventSender::new(EVENT_CHANNEL_CAPACITY);
 
@@ -169,13 +239,17 @@ impl Repository {
             BlockRequestMode::Greedy
         };
 
-        let vault = Vault::new(
+        let receipt_keys = metadata::mirror_receipts::get_or_generate_keys(&pool, &mut rng).await?;
+
+        let mut vault = Vault::new(
             *credentials.secrets.id(),
             event_tx,
             pool,
+            memory,
             block_request_mode,
             monitor,
         );
+        vault.receipt_keys = Arc::new(receipt_keys);
 
         if let Some(keys) = credentials
             .secrets
@@ -188,11 +262,38 @@ impl Repository {
                 .await?;
         }
 
+        let branch_shared = BranchShared::with_rng(rng);
+
         {
             let mut conn = vault.store().db().acquire().await?;
             if let Some(block_expiration) = metadata::block_expiration::get(&mut conn).await? {
                 vault.set_block_expiration(Some(block_expiration)).await?;
             }
+
+            vault.store().set_receive_filter_persistent(
+                metadata::receive_filter_persistent::get(&mut conn).await?,
+            );
+
+            *branch_shared.directory_limits.write().unwrap() =
+                metadata::directory_limits::get(&mut conn).await?;
+
+            branch_shared.write_fence.store(
+                metadata::write_fence::get(&mut conn).await?,
+                Ordering::Release,
+            );
+
+            branch_shared.directory_compression.store(
+                metadata::directory_compression::get(&mut conn).await?,
+                Ordering::Release,
+            );
+
+            vault
+                .access_notice
+                .send_replace(metadata::access_notice::get(&mut conn).await?);
+
+            vault
+                .wipe_directives
+                .send_replace(metadata::wipe_directives::get(&mut conn).await?);
         }
 
         tracing::debug!(
@@ -205,7 +306,8 @@ impl Repository {
         let shared = Arc::new(Shared {
             vault,
             credentials: BlockingRwLock::new(credentials),
-            branch_shared: BranchShared::new(),
+            branch_shared,
+            conflicts: BlockingMutex::new(Conflicts::default()),
         });
 
         let worker_handle = spawn_worker(shared.clone());
@@ -217,15 +319,36 @@ impl Repository {
         );
         let progress_reporter_handle = BlockingMutex::new(Some(progress_reporter_handle));
 
+        let contribution_persister_handle = scoped_task::spawn(
+            persist_contribution(shared.vault.clone())
+                .instrument(shared.vault.monitor.span().clone()),
+        );
+        let contribution_persister_handle = BlockingMutex::new(Some(contribution_persister_handle));
+
+        let wipe_watcher_handle = scoped_task::spawn(
+            wipe::watch(shared.clone()).instrument(shared.vault.monitor.span().clone()),
+        );
+        let wipe_watcher_handle = BlockingMutex::new(Some(wipe_watcher_handle));
+
         Ok(Self {
             shared,
             worker_handle,
             progress_reporter_handle,
+            scrubber_handle: BlockingMutex::new(None),
+            at_risk_tracker_handle: BlockingMutex::new(None),
+            contribution_persister_handle,
+            wipe_watcher_handle,
         })
     }
 
     pub async fn database_id(&self) -> Result<DatabaseId> {
-        Ok(metadata::get_or_generate_database_id(self.db()).await?)
+        Ok(metadata::get_or_generate_database_id(self.db(), &mut self.rng()).await?)
+    }
+
+    /// What was done to recover the database files when this repository was opened, in case it
+    /// hadn't been closed cleanly before (e.g., the app crashed).
+    pub fn db_recovery(&self) -> DbRecovery {
+        self.db().recovery()
     }
 
     pub async fn requires_local_secret_for_reading(&self) -> Result<bool> {
@@ -257,11 +380,14 @@ impl Repository {
     ///
     /// Disabling the read mode while keeping write mode enabled is allowed but not very useful as
     /// write mode also grants read access.
+    ///
+    /// Any change also revokes the unlock token (see [`Self::create_unlock_token`]).
     pub async fn set_access(
         &self,
         read_change: Option<AccessChange>,
         write_change: Option<AccessChange>,
     ) -> Result<()> {
+        let changed = read_change.is_some() || write_change.is_some();
         let mut tx = self.db().begin_write().await?;
 
         if let Some(change) = read_change {
@@ -272,11 +398,94 @@ impl Repository {
             self.set_write_access(&mut tx, change).await?;
         }
 
+        if changed {
+            metadata::unlock_token::remove(&mut tx).await?;
+        }
+
         tx.commit().await?;
 
         Ok(())
     }
 
+    /// Changes the local secret protecting the stored read and/or write keys from `old` to `new`.
+    /// Only the keys that `old` unlocks are affected, so if the read and write access use
+    /// different secrets, each needs to be changed separately. The content is not re-encrypted,
+    /// so apart from deriving the key from the new password this is instant. Also revokes the
+    /// unlock token (see [`Self::create_unlock_token`]). Fails with `PermissionDenied` if `old`
+    /// doesn't unlock any key.
+    pub async fn change_local_secret(&self, old: LocalSecret, new: SetLocalSecret) -> Result<()> {
+        let new = metadata::secret_to_key_and_salt(&new);
+
+        let mut tx = self.db().begin_write().await?;
+
+        if metadata::change_local_secret(&mut tx, &old, &new).await? == AccessMode::Blind {
+            return Err(Error::PermissionDenied);
+        }
+
+        metadata::unlock_token::remove(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Creates a token which unlocks the repository in its current access mode without the local
+    /// secret (see [`Self::unlock_with_token`]). Meant to be obtained after the user unlocked the
+    /// repository with their password and stored behind the platform biometric APIs. Creating a
+    /// new token revokes the previous one, and so does changing the access (see
+    /// [`Self::set_access`] and [`Self::change_local_secret`]). Fails with `PermissionDenied` in
+    /// blind mode.
+    pub async fn create_unlock_token(&self) -> Result<UnlockToken> {
+        let credentials = self.credentials();
+
+        if !credentials.secrets.can_read() {
+            return Err(Error::PermissionDenied);
+        }
+
+        let token = UnlockToken::random();
+
+        let mut tx = self.db().begin_write().await?;
+        metadata::unlock_token::set(
+            &mut tx,
+            &credentials.secrets,
+            &credentials.writer_id,
+            token.key(),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(token)
+    }
+
+    /// Revokes the unlock token created by [`Self::create_unlock_token`] (if any).
+    pub async fn revoke_unlock_token(&self) -> Result<()> {
+        let mut tx = self.db().begin_write().await?;
+        metadata::unlock_token::remove(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Switches the repository to the access mode granted by the unlock token, unless the current
+    /// mode is already higher. Fails with `PermissionDenied` if the token is not valid (e.g., it
+    /// has been revoked).
+    pub async fn unlock_with_token(&self, token: &UnlockToken) -> Result<()> {
+        let (secrets, writer_id) = {
+            let mut conn = self.db().acquire().await?;
+            metadata::unlock_token::get(&mut conn, self.secrets().id(), token.key())
+                .await?
+                .ok_or(Error::PermissionDenied)?
+        };
+
+        if secrets.access_mode() <= self.access_mode() {
+            return Ok(());
+        }
+
+        let writer_id = writer_id.unwrap_or_else(|| metadata::generate_writer_id(&mut self.rng()));
+
+        self.set_credentials(Credentials { secrets, writer_id })
+            .await
+    }
+
     async fn set_read_access(
         &self,
         tx: &mut db::WriteTransaction,
@@ -403,9 +612,10 @@ impl Repository {
         let secrets = secrets.with_mode(access_mode);
 
         let writer_id = if secrets.can_write() {
-            metadata::get_or_generate_writer_id(&mut tx, local_key.as_deref()).await?
+            metadata::get_or_generate_writer_id(&mut tx, local_key.as_deref(), &mut self.rng())
+                .await?
         } else {
-            metadata::generate_writer_id()
+            metadata::generate_writer_id(&mut self.rng())
         };
 
         tx.commit().await?;
@@ -482,6 +692,76 @@ impl Repository {
         self.shared.vault.metadata()
     }
 
+    /// Starts a batch of metadata writes and file modifications to be committed atomically. See
+    /// [`Batch`] for details.
+    pub async fn batch(&self) -> Result<Batch> {
+        let tx = self.shared.vault.store().begin_write().await?;
+        Ok(Batch::new(tx, self.shared.vault.event_tx.clone()))
+    }
+
+    /// Starts a bulk ingestion of files and directories into the local branch, committed and
+    /// published to the peers as a single snapshot. Much faster than creating the entries one by
+    /// one when importing a big number of files. See [`BulkIngest`] for details.
+    pub async fn begin_bulk_ingest(&self) -> Result<BulkIngest> {
+        let root = self.local_branch()?.open_or_create_root().await?;
+        let tx = self.shared.vault.store().begin_write().await?;
+
+        Ok(BulkIngest::new(tx, root))
+    }
+
+    /// Waits until all the writes submitted before this call (file flushes, batches, metadata
+    /// changes, ...) are committed and the snapshot they produced is the latest one of the local
+    /// branch, that is, the one announced to the peers. Unlike `File::flush`, which only waits for
+    /// its own write, this also covers the writes already in progress in the other tasks. Useful
+    /// for precise "saved" indicators.
+    ///
+    /// Don't call this while holding a [`Batch`], it would wait for the batch to finish forever.
+    pub async fn barrier(&self) -> Result<()> {
+        // The write transactions are serialized and a committed transaction finishes its
+        // notifications before the next one begins, so once we get one all the previous writes
+        // are published. Nothing is written so dropping it just rolls it back.
+        self.shared.vault.store().begin_write().await?;
+        Ok(())
+    }
+
+    /// Set the human readable name of this repository. It's meant to be shown by the frontends
+    /// instead of the name of the database file so all the frontends on the same device show the
+    /// same name even if the file gets moved. Like all metadata, it's not synced to other
+    /// replicas. Use `None` to remove it.
+    pub async fn set_display_name(&self, name: Option<&str>) -> Result<()> {
+        set_label(&self.metadata(), metadata::DISPLAY_NAME, name).await
+    }
+
+    /// Get the display name of this repository or `None` if not set.
+    pub async fn display_name(&self) -> Result<Option<String>> {
+        Ok(self.metadata().get(metadata::DISPLAY_NAME).await?)
+    }
+
+    /// Set the icon of this repository. The format of the value (e.g., an emoji or a name of an
+    /// icon from some icon set) is up to the frontends. Use `None` to remove it.
+    pub async fn set_icon(&self, icon: Option<&str>) -> Result<()> {
+        set_label(&self.metadata(), metadata::ICON, icon).await
+    }
+
+    /// Get the icon of this repository or `None` if not set.
+    pub async fn icon(&self) -> Result<Option<String>> {
+        Ok(self.metadata().get(metadata::ICON).await?)
+    }
+
+    /// Set the color of this repository as `0xAARRGGBB`. Use `None` to remove it.
+    pub async fn set_color(&self, color: Option<u32>) -> Result<()> {
+        set_label(&self.metadata(), metadata::COLOR, color.map(u64::from)).await
+    }
+
+    /// Get the color of this repository or `None` if not set.
+    pub async fn color(&self) -> Result<Option<u32>> {
+        self.metadata()
+            .get::<u64>(metadata::COLOR)
+            .await?
+            .map(|color| u32::try_from(color).map_err(|_| Error::MalformedData))
+            .transpose()
+    }
+
     /// Set the storage quota in bytes. Use `None` to disable quota. Default is `None`.
     pub async fn set_quota(&self, quota: Option<StorageSize>) -> Result<()> {
         self.shared.vault.set_quota(quota).await
@@ -511,6 +791,584 @@ impl Repository {
         self.shared.vault.block_expiration().await
     }
 
+    /// Set whether the filter of already received index nodes persists across restarts. When
+    /// enabled, re-connecting peers don't need to re-send the subtrees that haven't changed since
+    /// the last time they were received. Takes effect for connections established after this call.
+    /// Default is `false`.
+    pub async fn set_receive_filter_persistent(&self, persistent: bool) -> Result<()> {
+        {
+            let mut tx = self.db().begin_write().await?;
+            metadata::receive_filter_persistent::set(&mut tx, persistent).await?;
+            tx.commit().await?;
+        }
+
+        self.shared
+            .vault
+            .store()
+            .set_receive_filter_persistent(persistent);
+
+        Ok(())
+    }
+
+    /// Is the receive filter persistent?
+    pub fn is_receive_filter_persistent(&self) -> bool {
+        self.shared.vault.store().is_receive_filter_persistent()
+    }
+
+    /// Set the limits on the directory tree which are checked when creating new files and
+    /// directories. Default is `DirectoryLimits::default()`.
+    pub async fn set_directory_limits(&self, limits: DirectoryLimits) -> Result<()> {
+        {
+            let mut tx = self.db().begin_write().await?;
+            metadata::directory_limits::set(&mut tx, limits).await?;
+            tx.commit().await?;
+        }
+
+        *self.shared.branch_shared.directory_limits.write().unwrap() = limits;
+
+        Ok(())
+    }
+
+    /// Get the limits on the directory tree.
+    pub fn directory_limits(&self) -> DirectoryLimits {
+        *self.shared.branch_shared.directory_limits.read().unwrap()
+    }
+
+    /// Enable or disable write fencing. When enabled, local modifications (creating, writing,
+    /// removing or moving entries) fail with `Error::Outdated` while the local branch is strictly
+    /// behind some other branch, that is, until the changes from the other replicas are merged.
+    /// This prevents divergence in workflows where only one replica is expected to be modified at
+    /// a time. Default is `false`.
+    pub async fn set_write_fence(&self, enabled: bool) -> Result<()> {
+        {
+            let mut tx = self.db().begin_write().await?;
+            metadata::write_fence::set(&mut tx, enabled).await?;
+            tx.commit().await?;
+        }
+
+        self.shared
+            .branch_shared
+            .write_fence
+            .store(enabled, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Is write fencing enabled?
+    pub fn is_write_fence_enabled(&self) -> bool {
+        self.shared
+            .branch_shared
+            .write_fence
+            .load(Ordering::Acquire)
+    }
+
+    /// Enable or disable compression of large directories. Compressed directories use a newer
+    /// serialization format (see `DIRECTORY_VERSION`) which older versions of this library can't
+    /// read, so enable this only when all the replicas of the repository have been upgraded.
+    /// Directories are (de)compressed when saved, so already saved directories are not affected
+    /// until they are modified again. Reading compressed directories is always supported. Default
+    /// is `false`.
+    pub async fn set_directory_compression(&self, enabled: bool) -> Result<()> {
+        {
+            let mut tx = self.db().begin_write().await?;
+            metadata::directory_compression::set(&mut tx, enabled).await?;
+            tx.commit().await?;
+        }
+
+        self.shared
+            .branch_shared
+            .directory_compression
+            .store(enabled, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Is compression of large directories enabled?
+    pub fn is_directory_compression_enabled(&self) -> bool {
+        self.shared
+            .branch_shared
+            .directory_compression
+            .load(Ordering::Acquire)
+    }
+
+    /// Sets the minimum free space (in bytes) to keep on the device holding the repository. When
+    /// the free space drops below it, local modifications fail with `Error::StorageFull`, syncing
+    /// pauses until enough space is freed and `Payload::StorageFull` is emitted, so the database
+    /// never runs out of space in the middle of a write. Removing entries and the garbage
+    /// collection still work so space can be freed. Zero disables the check. Not persisted,
+    /// defaults to `DEFAULT_MIN_FREE_SPACE` when the repository is opened.
+    ///
+    /// NOTE: The free space is currently determined only on unix platforms (including Android and
+    /// macOS). Elsewhere (e.g. on Windows) this has no effect.
+    pub fn set_min_free_space(&self, bytes: u64) {
+        self.db().disk_space().set_min_free(bytes)
+    }
+
+    /// Minimum free space (in bytes) on the device holding the repository. See
+    /// `set_min_free_space`.
+    pub fn min_free_space(&self) -> u64 {
+        self.db().disk_space().min_free()
+    }
+
+    /// Freezes the repository so its store files can be copied consistently by an external backup
+    /// tool: waits for the ongoing write (if any) to finish and then blocks all the writes to the
+    /// store, both the local modifications and the data received from the peers, until the
+    /// returned guard is dropped. `mode` specifies whether the writes wait or fail meanwhile.
+    /// Reading is not affected. See `FreezeGuard::checkpointed` for which files to copy.
+    ///
+    /// Closing the repository waits for the guard to be dropped.
+    pub async fn freeze(&self, mode: FreezeMode) -> Result<FreezeGuard> {
+        Ok(self.db().freeze(mode).await?)
+    }
+
+    /// Is the local branch strictly behind some other branch? If write fencing is enabled, local
+    /// modifications are refused while this is `true`.
+    pub async fn is_outdated(&self) -> Result<bool> {
+        let local_branch = self.local_branch()?;
+        let mut tx = self.shared.vault.store().begin_read().await?;
+
+        branch::is_outdated(&mut tx, local_branch.id()).await
+    }
+
+    /// Enable or disable the scrubber - a low priority background task which periodically verifies
+    /// the stored blocks against their ids to detect local data corruption. Corrupted blocks are
+    /// reported with `Payload::BlockCorrupted`. Default is `false`.
+    pub fn set_scrub_enabled(&self, enabled: bool) {
+        let mut handle = self.scrubber_handle.lock().unwrap();
+
+        if !enabled {
+            *handle = None;
+        } else if handle.is_none() {
+            *handle = Some(scoped_task::spawn(
+                scrub(self.shared.vault.clone())
+                    .instrument(self.shared.vault.monitor.span().clone()),
+            ));
+        }
+    }
+
+    /// Enable or disable tracking of the at risk blocks - the locally present blocks that no
+    /// connected remote replica is known to hold. When enabled, the blocks the peers hold are
+    /// recorded as they are learned from them, the local blocks are periodically scanned to find
+    /// the at risk ones and those are offered to the peers. Default is `false`.
+    pub fn set_at_risk_tracking_enabled(&self, enabled: bool) {
+        let mut handle = self.at_risk_tracker_handle.lock().unwrap();
+
+        self.shared.vault.replica_tracker.set_enabled(enabled);
+
+        if !enabled {
+            *handle = None;
+            *self.shared.vault.monitor.at_risk_blocks.get() = 0;
+        } else if handle.is_none() {
+            *handle = Some(scoped_task::spawn(
+                track_at_risk_blocks(self.shared.vault.clone())
+                    .instrument(self.shared.vault.monitor.span().clone()),
+            ));
+        }
+    }
+
+    /// Is the at risk blocks tracking enabled?
+    pub fn is_at_risk_tracking_enabled(&self) -> bool {
+        self.at_risk_tracker_handle.lock().unwrap().is_some()
+    }
+
+    /// Set the maximum time a change of a branch can be held back before its snapshot is sent to
+    /// the peers. The first change after a quiet period is sent immediately but the changes that
+    /// follow within this time are coalesced into a single snapshot, which reduces the number of
+    /// snapshots the peers need to process when the repository is being written to continuously.
+    /// `Duration::ZERO` sends every snapshot immediately. Default is 500 ms.
+    pub fn set_snapshot_max_latency(&self, latency: Duration) {
+        *self.shared.vault.snapshot_max_latency.lock().unwrap() = latency;
+    }
+
+    /// Get the maximum time a snapshot can be held back before being sent to the peers.
+    pub fn snapshot_max_latency(&self) -> Duration {
+        *self.shared.vault.snapshot_max_latency.lock().unwrap()
+    }
+
+    /// Number of the locally present blocks that no connected remote replica is known to hold,
+    /// that is, the blocks that would be lost if this replica was lost. Updated periodically and
+    /// only while the at risk tracking is enabled (see [`Self::set_at_risk_tracking_enabled`]),
+    /// otherwise zero.
+    pub fn at_risk_block_count(&self) -> u64 {
+        *self.shared.vault.monitor.at_risk_blocks.get()
+    }
+
+    /// Local changes not yet observed by any peer. A change counts as observed once a remote
+    /// branch includes it (the peer merged it) or a mirror sent a receipt for a local snapshot
+    /// including it. Only the receipts for the snapshots still stored locally are considered, so
+    /// this errs on the side of reporting changes as unreplicated. Requires write access.
+    pub async fn unreplicated_changes(&self) -> Result<UnreplicatedChanges> {
+        let local_id = *self.local_branch()?.id();
+        let receipts = self.mirror_receipts().await?;
+
+        let mut reader = self.shared.vault.store().acquire_read().await?;
+        let root_nodes: Vec<_> = reader.load_root_nodes().try_collect().await?;
+
+        let mut local = VersionVector::new();
+        let mut acknowledged = VersionVector::new();
+
+        for node in root_nodes {
+            if node.proof.writer_id == local_id {
+                local = node.proof.version_vector.clone();
+            } else {
+                acknowledged.merge(&node.proof.version_vector);
+            }
+        }
+
+        let local_nodes: Vec<_> = reader
+            .load_root_nodes_by_writer_in_any_state(&local_id)
+            .try_collect()
+            .await?;
+
+        for node in local_nodes {
+            if receipts.iter().any(|receipt| {
+                receipt.branch_id == local_id && receipt.root_hash == node.proof.hash
+            }) {
+                acknowledged.merge(&node.proof.version_vector);
+            }
+        }
+
+        Ok(UnreplicatedChanges::new(&local_id, local, acknowledged))
+    }
+
+    /// Aggregates the signals of how well this repository syncs and detects the issues preventing
+    /// it from syncing. Whether syncing is enabled and the NAT behavior are known only to the
+    /// network so they need to be passed in.
+    pub async fn health(
+        &self,
+        sync_enabled: bool,
+        nat_behavior: Option<NatBehavior>,
+    ) -> Result<RepositoryHealth> {
+        let vault = &self.shared.vault;
+        let last_snapshot_age = vault
+            .last_root_node_received
+            .lock()
+            .unwrap()
+            .map(|instant| instant.elapsed().as_secs());
+
+        // Only writers merge.
+        let outdated = if self.access_mode() == AccessMode::Write {
+            self.is_outdated().await?
+        } else {
+            false
+        };
+
+        Ok(RepositoryHealth::new(
+            sync_enabled,
+            vault.replica_tracker.client_count() as u64,
+            nat_behavior,
+            last_snapshot_age,
+            self.at_risk_block_count(),
+            self.sync_progress().await?,
+            outdated,
+        ))
+    }
+
+    /// Entries with concurrent versions which the last merge couldn't merge automatically. Always
+    /// empty unless this replica has write access (only writers merge).
+    pub fn conflicts(&self) -> Conflicts {
+        self.shared.conflicts.lock().unwrap().clone()
+    }
+
+    /// Lists the snapshots (root nodes) of all the branches, at most `limit_per_branch` most recent
+    /// ones per branch. Includes also the snapshots that are not complete or approved yet.
+    pub async fn snapshots(&self, limit_per_branch: usize) -> Result<Vec<SnapshotInfo>> {
+        let mut reader = self.shared.vault.store().acquire_read().await?;
+        let writer_ids: Vec<_> = reader.load_writer_ids().try_collect().await?;
+        let mut snapshots = Vec::new();
+
+        for writer_id in &writer_ids {
+            let nodes: Vec<_> = reader
+                .load_root_nodes_by_writer_in_any_state(writer_id)
+                .take(limit_per_branch)
+                .try_collect()
+                .await?;
+
+            snapshots.extend(nodes.into_iter().map(SnapshotInfo::from));
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Explains why the file at the given path is or isn't synced: which branches reference it,
+    /// which blocks of each version are missing, how many peers offer or hold them and what the
+    /// block tracker is doing about them. Meant for diagnostics, it reads the whole index of the
+    /// file so it can be slow for large files.
+    pub async fn explain_sync<P: AsRef<Utf8Path>>(&self, path: P) -> Result<SyncExplanation> {
+        let (parent, name) = path::decompose(path.as_ref()).ok_or(Error::EntryIsDirectory)?;
+        let parent = self.cd(parent).await?;
+        let vault = &self.shared.vault;
+
+        let mut versions = Vec::new();
+        let mut found = false;
+
+        for entry in parent.lookup(name) {
+            found = true;
+
+            let JointEntryRef::File(entry) = entry else {
+                continue;
+            };
+
+            let mut block_ids =
+                BlockIds::open(entry.branch().clone(), *entry.inner().blob_id()).await?;
+            let mut all_block_ids = Vec::new();
+
+            while let Some(block_id) = block_ids.try_next().await? {
+                all_block_ids.push(block_id);
+            }
+
+            let mut reader = vault.store().acquire_read().await?;
+            let mut missing_blocks = Vec::new();
+
+            for block_id in &all_block_ids {
+                if reader.block_exists(block_id).await? {
+                    continue;
+                }
+
+                missing_blocks.push(MissingBlockExplanation::new(
+                    *block_id,
+                    vault.block_tracker.inspect(block_id),
+                    vault.replica_tracker.replica_count(block_id),
+                ));
+            }
+
+            versions.push(FileVersionExplanation {
+                branch_id: *entry.branch().id(),
+                version_vector: entry.version_vector().clone(),
+                block_count: all_block_ids.len() as u64,
+                missing_blocks,
+            });
+        }
+
+        match (found, versions.is_empty()) {
+            (false, _) => Err(Error::EntryNotFound),
+            (true, true) => Err(Error::EntryIsDirectory),
+            (true, false) => Ok(SyncExplanation { versions }),
+        }
+    }
+
+    /// Amount of data this replica served to and downloaded from the other replicas.
+    pub async fn contribution_stats(&self) -> Result<ContributionStats> {
+        Ok(self.shared.vault.contribution.load(self.db()).await?)
+    }
+
+    /// Receipts from the remote replicas (typically mirrors) acknowledging they hold complete
+    /// snapshots of this repository which they downloaded (at least partially) from us. Only the
+    /// latest receipt per replica and branch is kept.
+    pub async fn mirror_receipts(&self) -> Result<Vec<MirrorReceipt>> {
+        self.shared.vault.mirror_receipts().await
+    }
+
+    /// Records how this repository came to this device: from the given share token or, if `None`,
+    /// created as a new one. Should be called right after the repository is created. The origin is
+    /// recorded only once, subsequent calls do nothing.
+    pub async fn record_share_origin(&self, token: Option<&ShareToken>) -> Result<()> {
+        let origin = ShareOrigin::new(token, SystemTime::now());
+
+        let mut tx = self.db().begin_write().await?;
+        if metadata::share_origin::init(&mut tx, &origin).await? {
+            tx.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    /// How this repository came to this device (see `record_share_origin`). `None` if it wasn't
+    /// recorded, e.g. because the repository was created by an older version.
+    pub async fn share_origin(&self) -> Result<Option<ShareOrigin>> {
+        let mut conn = self.db().acquire().await?;
+        Ok(metadata::share_origin::get(&mut conn).await?)
+    }
+
+    /// Publishes a notice that the replicas with the `from` access mode are now limited to the `to`
+    /// one, e.g. after the write keys were replaced so the old write tokens only grant read
+    /// access. The notice is signed with the write keys and propagated to the peers which emit
+    /// `Payload::AccessNotice`, so the affected replicas learn about it before failing on the next
+    /// write. Only the latest notice is kept.
+    ///
+    /// Requires write access. Fails with `Error::InvalidArgument` unless `to` is lower than `from`.
+    pub async fn publish_access_notice(&self, from: AccessMode, to: AccessMode) -> Result<()> {
+        if to >= from {
+            return Err(Error::InvalidArgument);
+        }
+
+        // From the hybrid logical clock, so the notice supersedes all the ones this replica has
+        // seen even if its wall clock is behind the one of their issuer.
+        let issued_at = Timestamp::now().as_millis();
+
+        let notice = {
+            let credentials = self.shared.credentials.read().unwrap();
+            let write_secrets = credentials
+                .secrets
+                .write_secrets()
+                .ok_or(Error::PermissionDenied)?;
+
+            AccessNotice::new(
+                self.shared.vault.repository_id(),
+                from,
+                to,
+                issued_at,
+                &write_secrets.write_keys,
+            )
+        };
+
+        self.shared.vault.receive_access_notice(notice).await?;
+
+        Ok(())
+    }
+
+    /// The latest access notice published by a writer of this repository (see
+    /// `publish_access_notice`), if any. Use `AccessNotice::affects` to check whether it concerns
+    /// this replica.
+    pub fn access_notice(&self) -> Option<AccessNotice> {
+        self.shared.vault.access_notice.borrow().clone()
+    }
+
+    /// Creates a one-time invitation granting the given access mode (at most the current one). The
+    /// returned token is blind, the recipient obtains the actual secrets with
+    /// [`Self::redeem_invitation`] which succeeds only once: it registers the recipient's writer id
+    /// with the invitation and any later redemption by another writer is rejected.
+    pub async fn create_invitation(&self, access_mode: AccessMode) -> Result<ShareToken> {
+        let secrets = self.secrets().with_mode(access_mode);
+
+        if secrets.access_mode() == AccessMode::Blind {
+            return Err(Error::PermissionDenied);
+        }
+
+        let key = cipher::SecretKey::random();
+
+        self.shared
+            .vault
+            .store_invitation(invitation::StoredInvitation {
+                id: invitation::invitation_id(&key),
+                auth_key: invitation::auth_key(&key),
+                sealed: invitation::SealedSecrets::seal(&secrets, &key),
+                redeemed_by: None,
+            })
+            .await?;
+
+        Ok(ShareToken::from(secrets.with_mode(AccessMode::Blind)).with_invitation(key))
+    }
+
+    /// Redeems a one-time invitation (see [`Self::create_invitation`]) with the replica that
+    /// created it. Waits until that replica is connected and replies, so the network needs to be
+    /// running. On success the granted secrets become the current credentials of this repository
+    /// and the granted access mode is returned. Use [`Self::set_access`] to persist them. Fails
+    /// with `PermissionDenied` if the invitation has already been redeemed by someone else.
+    pub async fn redeem_invitation(&self, token: &ShareToken) -> Result<AccessMode> {
+        let key = token.invitation().ok_or(Error::PermissionDenied)?;
+        let vault = &self.shared.vault;
+
+        if token.id() != vault.repository_id() {
+            return Err(Error::PermissionDenied);
+        }
+
+        let writer_id = self.credentials().writer_id;
+        let reply = vault
+            .redemption
+            .start(key, writer_id)
+            .await
+            .map_err(|_| Error::PermissionDenied)?;
+
+        let sealed = match reply {
+            invitation::RedeemReply::Granted(sealed) => sealed,
+            invitation::RedeemReply::Rejected => return Err(Error::PermissionDenied),
+        };
+
+        let secrets = sealed
+            .open(key, vault.repository_id())
+            .ok_or(Error::MalformedData)?;
+        let access_mode = secrets.access_mode();
+
+        self.set_credentials(Credentials { secrets, writer_id })
+            .await?;
+
+        Ok(access_mode)
+    }
+
+    /// Publishes a directive for the device with the given writer id to wipe its local copy of this
+    /// repository. Once the device receives the directive, it securely deletes the repository data
+    /// including the keys, removes the database files and emits `Payload::Wiped`. The directive is
+    /// stored in the repository metadata, not in the directory tree. Requires write access.
+    pub async fn publish_wipe_directive(&self, writer_id: PublicKey) -> Result<()> {
+        let directive = {
+            let credentials = self.shared.credentials.read().unwrap();
+            let write_secrets = credentials
+                .secrets
+                .write_secrets()
+                .ok_or(Error::PermissionDenied)?;
+
+            WipeDirective::new(
+                self.shared.vault.repository_id(),
+                writer_id,
+                &write_secrets.write_keys,
+            )
+        };
+
+        self.shared.vault.receive_wipe_directive(directive).await?;
+
+        Ok(())
+    }
+
+    /// Removes the branch of the given writer together with all its snapshots, e.g., of a device
+    /// that will never sync again. The blocks referenced only by that branch are then collected
+    /// by the background worker. Unlike the automatic pruning, this works also when the branch is
+    /// still concurrent with the local one because a merge is blocked by a conflict elsewhere.
+    ///
+    /// Requires write access. Fails with `Error::Outdated` unless every entry of the branch has
+    /// been merged into the local branch, with `Error::Locked` if any of its files or directories
+    /// is in use and with `Error::OperationNotSupported` when trying to prune the local branch.
+    ///
+    /// Note replicas that still have the branch send it again unless they prune it as well.
+    pub async fn prune_branch(&self, writer_id: PublicKey) -> Result<()> {
+        let local_branch = self.local_branch()?;
+
+        if writer_id == *local_branch.id() {
+            return Err(Error::OperationNotSupported);
+        }
+
+        // Prevent modifications of the branch while it's being checked and removed.
+        let _lock = self
+            .shared
+            .branch_shared
+            .locker
+            .branch(writer_id)
+            .try_unique(BlobId::ROOT)
+            .map_err(|_| Error::Locked)?;
+
+        let remote_root = self
+            .shared
+            .get_branch(writer_id)?
+            .open_root(DirectoryLocking::Disabled, DirectoryFallback::Disabled)
+            .await?;
+        let local_root = local_branch.open_or_create_root().await?;
+
+        if !prune::is_merged(&remote_root, &local_root).await? {
+            return Err(Error::Outdated);
+        }
+
+        let mut tx = self.shared.vault.store().begin_write().await?;
+        let root_node = tx.load_root_node(&writer_id, RootNodeFilter::Any).await?;
+        tx.remove_branch(&root_node).await?;
+        let epoch = tx.retire_writers().await?;
+        tx.commit().await?;
+
+        tracing::info!(?writer_id, ?epoch, "Branch pruned");
+
+        // Wakes up the worker to collect the unreachable blocks.
+        self.shared
+            .vault
+            .event_tx
+            .send(Payload::BranchChanged(writer_id));
+
+        Ok(())
+    }
+
+    /// Is the scrubber enabled?
+    pub fn is_scrub_enabled(&self) -> bool {
+        self.scrubber_handle.lock().unwrap().is_some()
+    }
+
     /// Get the total size of the data stored in this repository.
     pub async fn size(&self) -> Result<StorageSize> {
         self.shared.vault.size().await
@@ -549,6 +1407,119 @@ impl Repository {
         }
     }
 
+    /// Version vector of the entry at the given path in the merged view of all the branches. It
+    /// identifies the version of the entry and can be passed to [`Self::wait_for_entry_version`]
+    /// on another replica to wait until that replica sees (at least) this version.
+    pub async fn entry_version_vector<P: AsRef<Utf8Path>>(&self, path: P) -> Result<VersionVector> {
+        let (parent, name) = path::decompose(path.as_ref()).ok_or(Error::OperationNotSupported)?;
+        let parent = self.cd(parent).await?;
+        let entry = parent.lookup_unique(name)?;
+
+        Ok(entry.version_vector().into_owned())
+    }
+
+    /// Data of the given kind (e.g., "thumbnail") derived from the current version of the file at
+    /// the given path, as stored with [`Self::set_derived_data`]. Returns `None` if there is none
+    /// or if it was derived from a different version of the file, in which case the app should
+    /// derive it again. Unlike caching by path and modification time this survives syncs that
+    /// don't modify the file and never returns data derived from another version.
+    pub async fn derived_data<P: AsRef<Utf8Path>>(
+        &self,
+        path: P,
+        kind: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let (blob_id, version_vector) = self.file_version(path.as_ref()).await?;
+        let version_hash = derived_data::version_hash(&blob_id, &version_vector);
+
+        let mut conn = self.db().acquire().await?;
+        derived_data::get(&mut conn, &blob_id, kind, &version_hash).await
+    }
+
+    /// Stores data of the given kind derived from the file at the given path, replacing the
+    /// previously stored one. `version_vector` is the version of the file the data was derived
+    /// from (see [`Self::entry_version_vector`]), so data derived from a version that's been
+    /// replaced in the meantime is never returned. The data is kept only locally.
+    pub async fn set_derived_data<P: AsRef<Utf8Path>>(
+        &self,
+        path: P,
+        kind: &str,
+        version_vector: &VersionVector,
+        data: &[u8],
+    ) -> Result<()> {
+        let (blob_id, _) = self.file_version(path.as_ref()).await?;
+        let version_hash = derived_data::version_hash(&blob_id, version_vector);
+
+        let mut tx = self.db().begin_write().await?;
+        derived_data::set(&mut tx, &blob_id, kind, &version_hash, data).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Removes all the derived data (see [`Self::set_derived_data`]), e.g. to free up space.
+    pub async fn clear_derived_data(&self) -> Result<()> {
+        let mut tx = self.db().begin_write().await?;
+        derived_data::clear(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Blob id and version vector of the file at the given path.
+    async fn file_version(&self, path: &Utf8Path) -> Result<(BlobId, VersionVector)> {
+        let (parent, name) = path::decompose(path).ok_or(Error::EntryIsDirectory)?;
+        let parent = self.cd(parent).await?;
+        let file = parent.lookup_unique(name)?.file()?;
+
+        Ok((*file.blob_id(), file.version_vector().clone()))
+    }
+
+    /// Waits until the merged view of this repository contains a version of the entry at the given
+    /// path that is equal to or newer than `version_vector` (as returned by
+    /// [`Self::entry_version_vector`], possibly on another replica). This provides
+    /// read-your-writes consistency across replicas. Note the blocks of the entry might still be
+    /// missing at that point - they are fetched on demand when read.
+    pub async fn wait_for_entry_version<P: AsRef<Utf8Path>>(
+        &self,
+        path: P,
+        version_vector: &VersionVector,
+    ) -> Result<()> {
+        let (parent, name) = path::decompose(path.as_ref()).ok_or(Error::OperationNotSupported)?;
+        let mut events = pin!(self.events());
+
+        loop {
+            if self.has_entry_version(parent, name, version_vector).await? {
+                return Ok(());
+            }
+
+            if events.next().await.is_none() {
+                // The event sender is owned by `self` so this can't happen.
+                future::pending::<()>().await;
+            }
+        }
+    }
+
+    async fn has_entry_version(
+        &self,
+        parent: &Utf8Path,
+        name: &str,
+        version_vector: &VersionVector,
+    ) -> Result<bool> {
+        let parent = match self.cd(parent).await {
+            Ok(parent) => parent,
+            Err(Error::EntryNotFound) => return Ok(false),
+            Err(error) => return Err(error),
+        };
+
+        // There can be multiple concurrent versions of the entry, it's enough if one of them is
+        // up to date.
+        let found = parent
+            .lookup(name)
+            .any(|entry| entry.version_vector().as_ref() >= version_vector);
+
+        Ok(found)
+    }
+
     /// Opens a file at the given path (relative to the repository root)
     pub async fn open_file<P: AsRef<Utf8Path>>(&self, path: P) -> Result<File> {
         let (parent, name) = path::decompose(path.as_ref()).ok_or(Error::EntryIsDirectory)?;
@@ -561,6 +1532,13 @@ impl Repository {
             .await
     }
 
+    /// Builds the Merkle proof that the content of the file at the given path is part of the latest
+    /// published snapshot of its branch. The proof can be checked with `verify_entry_proof`
+    /// knowing only the repository id.
+    pub async fn prove_entry<P: AsRef<Utf8Path>>(&self, path: P) -> Result<EntryProof> {
+        self.open_file(path).await?.prove().await
+    }
+
     /// Open a specific version of the file at the given path.
     pub async fn open_file_version<P: AsRef<Utf8Path>>(
         &self,
@@ -576,6 +1554,32 @@ impl Repository {
             .await
     }
 
+    /// Opens all the concurrent versions of the file at the given path, e.g. to show the
+    /// differences between conflicting versions side by side. Unlike `open_file` this doesn't fail
+    /// with `Error::AmbiguousEntry` when there are multiple versions. Use `File::branch` to tell
+    /// them apart.
+    pub async fn open_file_versions<P: AsRef<Utf8Path>>(&self, path: P) -> Result<Vec<File>> {
+        let (parent, name) = path::decompose(path.as_ref()).ok_or(Error::EntryIsDirectory)?;
+        let parent = self.cd(parent).await?;
+
+        let mut files = Vec::new();
+        let mut found = false;
+
+        for entry in parent.lookup(name) {
+            found = true;
+
+            if let JointEntryRef::File(entry) = entry {
+                files.push(entry.open().await?);
+            }
+        }
+
+        match (found, files.is_empty()) {
+            (false, _) => Err(Error::EntryNotFound),
+            (true, true) => Err(Error::EntryIsDirectory),
+            (true, false) => Ok(files),
+        }
+    }
+
     /// Opens a directory at the given path (relative to the repository root)
     pub async fn open_directory<P: AsRef<Utf8Path>>(&self, path: P) -> Result<JointDirectory> {
         self.cd(path).await
@@ -712,7 +1716,7 @@ impl Repository {
     /// Returns the branch corresponding to the given id or `Error::PermissionDenied. if this repo
     /// doesn't have at least read access.
     #[cfg(test)]
-    pub fn get_branch(&self, id: PublicKey) -> Result<Branch> {
+    pub(crate) fn get_branch(&self, id: PublicKey) -> Result<Branch> {
         self.shared.get_branch(id)
     }
 
@@ -730,11 +1734,110 @@ impl Repository {
             .into_version_vector())
     }
 
+    /// Returns the ids of all the branches of this repository.
+    pub async fn branch_ids(&self) -> Result<Vec<PublicKey>> {
+        Ok(self
+            .shared
+            .load_branches()
+            .await?
+            .into_iter()
+            .map(|branch| *branch.id())
+            .collect())
+    }
+
+    /// Compares the directory at `path` in the branch `old` with the same directory in the branch
+    /// `new` and returns the entries that were added, removed or modified. This is computed only
+    /// from the directory metadata, without reading the content of the entries, so it's cheap.
+    /// A directory missing in a branch is treated as empty. The comparison is not recursive - to
+    /// find changes in the subdirectories, call this again for the ones reported as modified.
+    pub async fn diff<P: AsRef<Utf8Path>>(
+        &self,
+        old: &PublicKey,
+        new: &PublicKey,
+        path: P,
+    ) -> Result<Vec<EntryDiff>> {
+        let path = path.as_ref();
+        let old = directory::open_in_branch(&self.shared.get_branch(*old)?, path).await?;
+        let new = directory::open_in_branch(&self.shared.get_branch(*new)?, path).await?;
+
+        Ok(directory::diff(old.as_ref(), new.as_ref()))
+    }
+
     /// Subscribe to event notifications.
     pub fn subscribe(&self) -> broadcast::Receiver<Event> {
         self.shared.vault.event_tx.subscribe()
     }
 
+    /// Stream of the event notifications. Unlike [`Self::subscribe`], lagging behind is not an
+    /// error: the missed events are replaced with a single `Payload::Resync` event. The stream
+    /// ends when the repository is closed.
+    pub fn events(&self) -> impl Stream<Item = Event> + Send + 'static {
+        event::into_stream(self.subscribe())
+    }
+
+    /// Stream of the events affecting the entry at the given path (relative to the repository
+    /// root): the `BranchChanged` events after which the entry has a different version (this
+    /// includes its creation and removal) and the `Resync` events. All the `BranchChanged` events
+    /// affect the root.
+    pub async fn events_for_path(
+        &self,
+        path: impl Into<Utf8PathBuf>,
+    ) -> impl Stream<Item = Event> + '_ {
+        let path = path.into();
+
+        // Subscribe before loading the current versions so no change is missed.
+        let events = Box::pin(self.events());
+        let versions = self.entry_versions(&path).await;
+
+        stream::unfold(
+            (events, path, versions),
+            move |(mut events, path, mut versions)| async move {
+                loop {
+                    let event = events.next().await?;
+
+                    match event.payload {
+                        Payload::BranchChanged(_) => {
+                            let new_versions = self.entry_versions(&path).await;
+
+                            if versions.is_some() && new_versions == versions {
+                                continue;
+                            }
+
+                            versions = new_versions;
+                        }
+                        Payload::Resync => {
+                            versions = self.entry_versions(&path).await;
+                        }
+                        Payload::BlockReceived(_)
+                        | Payload::MaintenanceCompleted
+                        | Payload::MetadataChanged
+                        | Payload::BlockCorrupted(_)
+                        | Payload::Wiped
+                        | Payload::StorageFull
+                        | Payload::AccessNotice => continue,
+                    }
+
+                    return Some((event, (events, path, versions)));
+                }
+            },
+        )
+    }
+
+    // Version vectors of all the concurrent versions of the entry at the given path (empty if it
+    // doesn't exist) or `None` if the path is the root.
+    async fn entry_versions(&self, path: &Utf8Path) -> Option<Vec<VersionVector>> {
+        let (parent, name) = path::decompose(path)?;
+        let versions = match self.cd(parent).await {
+            Ok(parent) => parent
+                .lookup(name)
+                .map(|entry| entry.version_vector().into_owned())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        Some(versions)
+    }
+
     /// Gets the syncing progress of this repository (number of downloaded blocks / number of
     /// all blocks)
     pub async fn sync_progress(&self) -> Result<Progress> {
@@ -819,9 +1922,20 @@ impl Repository {
     /// Close all db connections held by this repository. After this function returns, any
     /// subsequent operation on this repository that requires to access the db returns an error.
     pub async fn close(&self) -> Result<()> {
+        // Interrupt the long running store operations first, otherwise awaiting the tasks below
+        // would wait until those operations complete.
+        self.shared.vault.store().cancel();
+
         // Abort and *await* the tasks to make sure that the state they are holding is definitely
         // dropped before we return from this function.
-        for task in [&self.worker_handle, &self.progress_reporter_handle] {
+        for task in [
+            &self.worker_handle,
+            &self.progress_reporter_handle,
+            &self.scrubber_handle,
+            &self.at_risk_tracker_handle,
+            &self.contribution_persister_handle,
+            &self.wipe_watcher_handle,
+        ] {
             let task = task.lock().unwrap().take();
             if let Some(task) = task {
                 task.abort();
@@ -829,11 +1943,51 @@ impl Repository {
             }
         }
 
+        if let Err(error) = self.shared.vault.contribution.persist(self.db()).await {
+            tracing::error!(?error, "Failed to persist contribution stats");
+        }
+
+        if let Err(error) = self.shared.vault.mirror_receipts.persist(self.db()).await {
+            tracing::error!(?error, "Failed to persist mirror receipts");
+        }
+
         self.shared.vault.store().close().await?;
 
         Ok(())
     }
 
+    /// Closes the repository without waiting for the ongoing operations. The background tasks are
+    /// aborted, the long running store operations are interrupted and any subsequent operation on
+    /// this repository fails. The db connections are closed in the background as soon as the
+    /// operations currently holding them give them up. Unlike `close`, this doesn't persist the
+    /// contribution stats and mirror receipts collected since they were last persisted.
+    ///
+    /// Use when the storage is about to disappear (e.g., a removable media being ejected).
+    pub fn force_close(&self) {
+        self.shared.vault.store().cancel();
+
+        for task in [
+            &self.worker_handle,
+            &self.progress_reporter_handle,
+            &self.scrubber_handle,
+            &self.at_risk_tracker_handle,
+            &self.contribution_persister_handle,
+            &self.wipe_watcher_handle,
+        ] {
+            if let Some(task) = task.lock().unwrap().take() {
+                task.abort();
+            }
+        }
+
+        let store = self.shared.vault.store().clone();
+
+        task::spawn(async move {
+            if let Err(error) = store.close().await {
+                tracing::error!(?error, "Failed to close the store");
+            }
+        });
+    }
+
     pub async fn debug_print_root(&self) {
         self.debug_print(DebugPrinter::new()).await
     }
@@ -877,12 +2031,102 @@ impl Repository {
         self.shared.vault.debug_print(print).await;
     }
 
+    /// Imports blocks from another store of this repository found locally (e.g., a copy of the
+    /// database on an external disk) so that a new device doesn't have to download them over the
+    /// network. Only the blocks that are referenced from the local index and missing are imported
+    /// and each one is verified against its id first, so the source store doesn't need to be
+    /// trusted. The index itself is not imported which means this should be called after the index
+    /// has been synced from the peers. The remaining missing blocks are then downloaded as usual.
+    ///
+    /// The source store is opened read-only and is neither recovered nor migrated, so it is left
+    /// untouched. It must be at the current schema version.
+    ///
+    /// Returns the number of imported blocks. Fails with `InvalidArgument` if the store at
+    /// `store_path` belongs to a different repository.
+    pub async fn adopt_blocks_from(&self, store_path: impl AsRef<Path>) -> Result<u64> {
+        let src =
+            store::Store::new(db::open_read_only(store_path, &db::DbTuning::default()).await?);
+
+        let result = self.adopt_blocks_from_store(&src).await;
+        src.close().await?;
+
+        result
+    }
+
+    /// Imports a block delivered out of band (e.g., by email or on a USB stick) as if it was
+    /// received from a peer. `ciphertext` and `nonce` are as returned from `export_block` on
+    /// another replica. The block is verified against `id` first, so the channel doesn't need to be
+    /// trusted. Doesn't require read access.
+    ///
+    /// Returns `false` if the block is not referenced by the index (not needed by this replica).
+    /// Fails with `InvalidArgument` if `ciphertext` or `nonce` have wrong length and with
+    /// `MalformedData` if they don't match `id`.
+    pub async fn ingest_block(&self, id: BlockId, ciphertext: &[u8], nonce: &[u8]) -> Result<bool> {
+        if ciphertext.len() != BLOCK_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        let nonce: BlockNonce = nonce.try_into().map_err(|_| Error::InvalidArgument)?;
+        let mut content = BlockContent::new();
+        content.copy_from_slice(ciphertext);
+
+        let block = Block::new(content, nonce);
+        if block.id != id {
+            return Err(Error::MalformedData);
+        }
+
+        match self.shared.vault.receive_block(&block, None).await {
+            Ok(()) => Ok(true),
+            Err(Error::Store(store::Error::BlockNotReferenced)) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Exports the given block in its encrypted form as a `(ciphertext, nonce)` pair, to be
+    /// delivered to another replica out of band and imported there with `ingest_block`. Doesn't
+    /// require read access.
+    pub async fn export_block(&self, id: &BlockId) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut content = BlockContent::new();
+        let nonce = self
+            .shared
+            .vault
+            .store()
+            .acquire_read()
+            .await?
+            .read_block(id, &mut content)
+            .await?;
+
+        Ok((content.to_vec(), nonce.to_vec()))
+    }
+
+    async fn adopt_blocks_from_store(&self, src: &store::Store) -> Result<u64> {
+        let src_id = {
+            let mut conn = src.db().acquire().await?;
+            metadata::get_repository_id(&mut conn).await?
+        };
+
+        let this_id = {
+            let mut conn = self.db().acquire().await?;
+            metadata::get_repository_id(&mut conn).await?
+        };
+
+        if src_id != this_id {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.shared.vault.adopt_blocks_from(src).await
+    }
+
     /// Returns the total number of blocks in this repository. This is useful for diagnostics and
     /// tests.
     pub async fn count_blocks(&self) -> Result<u64> {
         Ok(self.shared.vault.store().count_blocks().await?)
     }
 
+    fn rng(&self) -> SharedRng {
+        self.shared.branch_shared.rng.clone()
+    }
+
     fn db(&self) -> &db::Pool {
         self.shared.vault.store().db()
     }
@@ -908,6 +2152,8 @@ struct Shared {
     vault: Vault,
     credentials: BlockingRwLock<Credentials>,
     branch_shared: BranchShared,
+    // Conflicts found by the last merge.
+    conflicts: BlockingMutex<Conflicts>,
 }
 
 impl Shared {
@@ -957,20 +2203,139 @@ impl Shared {
     }
 }
 
+// Sets the metadata entry if `value` is `Some`, otherwise removes it.
+async fn set_label<'a, T>(metadata: &Metadata, name: &'a str, value: Option<T>) -> Result<()>
+where
+    T: MetadataSet<'a> + fmt::Debug,
+{
+    if let Some(value) = value {
+        metadata.set(name, value).await?;
+    } else {
+        metadata.remove(name).await?;
+    }
+
+    Ok(())
+}
+
 fn spawn_worker(shared: Arc<Shared>) -> ScopedJoinHandle<()> {
     let span = shared.vault.monitor.span().clone();
     scoped_task::spawn(worker::run(shared).instrument(span))
 }
 
+// Verifies the integrity of the stored blocks, one block per `SCRUB_INTERVAL`, wrapping around to
+// the first block after the last one has been verified.
+async fn scrub(vault: Vault) {
+    let mut interval = time::interval(SCRUB_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut last = None;
+
+    loop {
+        interval.tick().await;
+
+        let result = async {
+            vault
+                .store()
+                .acquire_read()
+                .await?
+                .verify_next_block(last.as_ref())
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(Some((block_id, valid))) => {
+                vault.monitor.scrub_blocks_verified.increment(1);
+
+                if !valid {
+                    tracing::error!(?block_id, "Corrupted block found");
+                    vault.monitor.scrub_failures.increment(1);
+                    vault.event_tx.send(Payload::BlockCorrupted(block_id));
+                }
+
+                last = Some(block_id);
+            }
+            Ok(None) => {
+                last = None;
+            }
+            Err(error) => {
+                tracing::error!(?error, "Failed to verify block");
+            }
+        }
+    }
+}
+
+// Periodically finds the blocks that no connected remote replica is known to hold and hands them
+// to the replica tracker to be offered to the peers.
+async fn track_at_risk_blocks(vault: Vault) {
+    let mut interval = time::interval(AT_RISK_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        match find_at_risk_blocks(&vault).await {
+            Ok((count, sample)) => {
+                *vault.monitor.at_risk_blocks.get() = count;
+                vault.replica_tracker.set_at_risk(sample);
+            }
+            Err(error) => {
+                tracing::error!(?error, "Failed to find at risk blocks");
+            }
+        }
+    }
+}
+
+async fn find_at_risk_blocks(vault: &Vault) -> Result<(u64, Vec<BlockId>)> {
+    let mut page = vault.store().block_ids(AT_RISK_PAGE_SIZE);
+    let mut count = 0;
+    let mut sample = Vec::new();
+
+    loop {
+        let block_ids = page.next().await?;
+        if block_ids.is_empty() {
+            break;
+        }
+
+        for block_id in block_ids {
+            if vault.replica_tracker.replica_count(&block_id) > 0 {
+                continue;
+            }
+
+            count += 1;
+
+            if sample.len() < MAX_AT_RISK_SAMPLE {
+                sample.push(block_id);
+            }
+        }
+    }
+
+    Ok((count, sample))
+}
+
+// Periodically persists the contribution stats and the mirror receipts. They are also persisted on
+// close.
+async fn persist_contribution(vault: Vault) {
+    let mut interval = time::interval(CONTRIBUTION_PERSIST_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = vault.contribution.persist(vault.store().db()).await {
+            tracing::error!(?error, "Failed to persist contribution stats");
+        }
+
+        if let Err(error) = vault.mirror_receipts.persist(vault.store().db()).await {
+            tracing::error!(?error, "Failed to persist mirror receipts");
+        }
+    }
+}
+
 async fn report_sync_progress(vault: Vault) {
     let mut prev_progress = Progress { value: 0, total: 0 };
 
-    let events = stream::unfold(vault.event_tx.subscribe(), |mut rx| async move {
-        match rx.recv().await {
-            Ok(_) | Err(RecvError::Lagged(_)) => Some(((), rx)),
-            Err(RecvError::Closed) => None,
-        }
-    });
+    let events = event::into_stream(vault.event_tx.subscribe()).map(|_| ());
     let events = Throttle::new(events, Duration::from_secs(1));
     let mut events = pin!(events);
 
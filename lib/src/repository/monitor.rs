@@ -19,6 +19,10 @@ use tracing::{Instrument, Span};
 
 pub(crate) struct RepositoryMonitor {
     pub info_hash: MonitoredValue<Option<InfoHash>>,
+    // Number of events received since the last merge started.
+    pub merge_queue_depth: MonitoredValue<usize>,
+    // Number of locally present blocks that no connected remote replica is known to hold.
+    pub at_risk_blocks: MonitoredValue<u64>,
 
     // Total number of index requests sent.
     pub index_requests_sent: Counter,
@@ -28,6 +32,12 @@ pub(crate) struct RepositoryMonitor {
     pub block_requests_sent: Counter,
     // Current number of sent block request for which responses haven't been received yet.
     pub block_requests_inflight: Gauge,
+    // Current number of block offers handed out by the block tracker which haven't been completed
+    // or dropped yet.
+    pub block_offers_outstanding: Gauge,
+    // Total number of times a block offer was withheld because the client had too many outstanding
+    // ones.
+    pub block_offers_throttled: Counter,
     // Total number of received requests
     pub requests_received: Counter,
     // Current number of send requests (index + block) for which responses haven't been handled yet
@@ -49,6 +59,16 @@ pub(crate) struct RepositoryMonitor {
     // Time to handle a response.
     pub response_handle_time: Histogram,
 
+    // Total number of received nodes skipped because they contained no new information.
+    pub receive_filter_hits: Counter,
+    // Total number of received nodes that passed through the receive filter.
+    pub receive_filter_misses: Counter,
+
+    // Total number of blocks verified by the scrubber.
+    pub scrub_blocks_verified: Counter,
+    // Total number of corrupted blocks found by the scrubber.
+    pub scrub_failures: Counter,
+
     pub scan_job: JobMonitor,
     pub merge_job: JobMonitor,
     pub prune_job: JobMonitor,
@@ -66,6 +86,8 @@ impl RepositoryMonitor {
         let span = tracing::info_span!("repo", message = node.id().name());
 
         let info_hash = node.make_value("info-hash", None);
+        let merge_queue_depth = node.make_value("merge queue depth", 0);
+        let at_risk_blocks = node.make_value("at risk blocks", 0);
 
         let index_requests_sent = create_counter(recorder, "index requests sent", Unit::Count);
         let index_requests_inflight =
@@ -73,6 +95,10 @@ impl RepositoryMonitor {
         let block_requests_sent = create_counter(recorder, "block requests sent", Unit::Count);
         let block_requests_inflight =
             create_gauge(recorder, "block requests inflight", Unit::Count);
+        let block_offers_outstanding =
+            create_gauge(recorder, "block offers outstanding", Unit::Count);
+        let block_offers_throttled =
+            create_counter(recorder, "block offers throttled", Unit::Count);
 
         let requests_received = create_counter(recorder, "requests received", Unit::Count);
         let requests_pending = create_gauge(recorder, "requests pending", Unit::Count);
@@ -86,6 +112,12 @@ impl RepositoryMonitor {
         let response_handle_time =
             create_histogram(recorder, "response handle time", Unit::Seconds);
 
+        let receive_filter_hits = create_counter(recorder, "receive filter hits", Unit::Count);
+        let receive_filter_misses = create_counter(recorder, "receive filter misses", Unit::Count);
+
+        let scrub_blocks_verified = create_counter(recorder, "scrub blocks verified", Unit::Count);
+        let scrub_failures = create_counter(recorder, "scrub failures", Unit::Count);
+
         let scan_job = JobMonitor::new(&node, recorder, "scan");
         let merge_job = JobMonitor::new(&node, recorder, "merge");
         let prune_job = JobMonitor::new(&node, recorder, "prune");
@@ -93,11 +125,15 @@ impl RepositoryMonitor {
 
         Self {
             info_hash,
+            merge_queue_depth,
+            at_risk_blocks,
 
             index_requests_sent,
             index_requests_inflight,
             block_requests_sent,
             block_requests_inflight,
+            block_offers_outstanding,
+            block_offers_throttled,
             requests_received,
             requests_pending,
             request_latency,
@@ -109,6 +145,12 @@ impl RepositoryMonitor {
             response_queue_time,
             response_handle_time,
 
+            receive_filter_hits,
+            receive_filter_misses,
+
+            scrub_blocks_verified,
+            scrub_failures,
+
             scan_job,
             merge_job,
             prune_job,
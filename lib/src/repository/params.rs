@@ -1,6 +1,13 @@
 use super::RepositoryMonitor;
-use crate::{db, device_id::DeviceId, error::Result};
+use crate::{
+    db::{self, DbTuning},
+    device_id::DeviceId,
+    error::Result,
+    memory::MemoryBudget,
+    rng::SharedRng,
+};
 use metrics::{NoopRecorder, Recorder};
+use rand::{CryptoRng, RngCore};
 use state_monitor::{metrics::MetricsRecorder, StateMonitor};
 use std::{
     borrow::Cow,
@@ -12,6 +19,9 @@ pub struct RepositoryParams<R> {
     device_id: DeviceId,
     parent_monitor: Option<StateMonitor>,
     recorder: Option<R>,
+    memory: MemoryBudget,
+    db_tuning: DbTuning,
+    rng: SharedRng,
 }
 
 impl<R> RepositoryParams<R> {
@@ -26,18 +36,49 @@ impl<R> RepositoryParams<R> {
         }
     }
 
+    /// Account the memory used by the repository in the given (possibly shared) budget.
+    pub fn with_memory_budget(self, memory: MemoryBudget) -> Self {
+        Self { memory, ..self }
+    }
+
+    /// Tuning of the repository database. Defaults to `DbTuning::mobile()`.
+    pub fn with_db_tuning(self, db_tuning: DbTuning) -> Self {
+        Self { db_tuning, ..self }
+    }
+
+    /// Generate the identifiers of the repository (the writer id when creating it and the blob and
+    /// database ids) with the given generator instead of the OS one, e.g. to make tests
+    /// reproducible. Combine with `with_device_id` to make the device id deterministic too. The
+    /// keys and other secrets are not affected.
+    ///
+    /// Don't reuse the same sequence for the same repository (e.g., by reopening it with a
+    /// generator seeded the same way) as the ids drawn from it again would collide with the
+    /// existing ones.
+    pub fn with_rng<G>(self, rng: G) -> Self
+    where
+        G: RngCore + CryptoRng + Send + 'static,
+    {
+        Self {
+            rng: SharedRng::new(rng),
+            ..self
+        }
+    }
+
     pub fn with_recorder<S>(self, recorder: S) -> RepositoryParams<S> {
         RepositoryParams {
             store: self.store,
             device_id: self.device_id,
             parent_monitor: self.parent_monitor,
             recorder: Some(recorder),
+            memory: self.memory,
+            db_tuning: self.db_tuning,
+            rng: self.rng,
         }
     }
 
     pub(super) async fn create(&self) -> Result<db::Pool, db::Error> {
         match &self.store {
-            Store::Path(path) => db::create(path).await,
+            Store::Path(path) => db::create(path, &self.db_tuning).await,
             #[cfg(test)]
             Store::Pool { pool, .. } => Ok(pool.clone()),
         }
@@ -45,7 +86,7 @@ impl<R> RepositoryParams<R> {
 
     pub(super) async fn open(&self) -> Result<db::Pool, db::Error> {
         match &self.store {
-            Store::Path(path) => db::open(path).await,
+            Store::Path(path) => db::open(path, &self.db_tuning).await,
             #[cfg(test)]
             Store::Pool { pool, .. } => Ok(pool.clone()),
         }
@@ -54,6 +95,14 @@ impl<R> RepositoryParams<R> {
     pub(super) fn device_id(&self) -> DeviceId {
         self.device_id
     }
+
+    pub(super) fn memory_budget(&self) -> &MemoryBudget {
+        &self.memory
+    }
+
+    pub(super) fn rng(&self) -> SharedRng {
+        self.rng.clone()
+    }
 }
 
 impl<R> RepositoryParams<R>
@@ -96,6 +145,9 @@ impl RepositoryParams<NoopRecorder> {
             device_id: rand::random(),
             parent_monitor: None,
             recorder: None,
+            memory: MemoryBudget::default(),
+            db_tuning: DbTuning::default(),
+            rng: SharedRng::default(),
         }
     }
 }
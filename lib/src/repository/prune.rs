@@ -0,0 +1,122 @@
+//! Safety check for the explicit pruning of a remote branch (`Repository::prune_branch`).
+
+use crate::{
+    directory::{Directory, DirectoryFallback, EntryRef},
+    error::Result,
+};
+use async_recursion::async_recursion;
+
+/// Returns whether every file and directory version in `remote` is superseded by (that is,
+/// happens-before or equal to) the version at the same path in `local`, so that removing `remote`
+/// loses nothing. Tombstones are ignored as there is nothing to lose by removing them.
+///
+/// This differs from comparing the version vectors of the branch roots which stay concurrent
+/// whenever some merge is blocked by a conflict, even one with a different branch.
+#[async_recursion]
+pub(super) async fn is_merged(remote: &Directory, local: &Directory) -> Result<bool> {
+    for remote_entry in remote.entries() {
+        if remote_entry.is_tombstone() {
+            continue;
+        }
+
+        let local_entry = match local.lookup(remote_entry.name()) {
+            Ok(entry) => entry,
+            Err(_) => return Ok(false),
+        };
+
+        if remote_entry.version_vector() <= local_entry.version_vector() {
+            continue;
+        }
+
+        // Concurrent directories can still have all their content merged.
+        match (remote_entry, local_entry) {
+            (EntryRef::Directory(remote_entry), EntryRef::Directory(local_entry)) => {
+                let remote = remote_entry.open(DirectoryFallback::Disabled).await?;
+                let local = local_entry.open(DirectoryFallback::Disabled).await?;
+
+                if !is_merged(&remote, &local).await? {
+                    return Ok(false);
+                }
+            }
+            _ => return Ok(false),
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access_control::WriteSecrets,
+        branch::{Branch, BranchShared},
+        crypto::sign::PublicKey,
+        db,
+        event::EventSender,
+        joint_directory::JointDirectory,
+        store::Store,
+    };
+    use camino::Utf8Path;
+    use tempfile::TempDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn merged() {
+        let (_base_dir, [local, remote]) = setup().await;
+
+        local
+            .ensure_file_exists(Utf8Path::new("a.txt"))
+            .await
+            .unwrap();
+        remote
+            .ensure_file_exists(Utf8Path::new("dir/b.txt"))
+            .await
+            .unwrap();
+
+        // The remote file is not in the local branch.
+        assert!(!check(&local, &remote).await);
+
+        let local_root = local.open_or_create_root().await.unwrap();
+        let remote_root = remote.open_or_create_root().await.unwrap();
+        JointDirectory::new(Some(local.clone()), [local_root, remote_root])
+            .merge()
+            .await
+            .unwrap();
+
+        assert!(check(&local, &remote).await);
+
+        // Modified concurrently.
+        remote
+            .ensure_file_exists(Utf8Path::new("dir/c.txt"))
+            .await
+            .unwrap();
+        assert!(!check(&local, &remote).await);
+    }
+
+    async fn check(local: &Branch, remote: &Branch) -> bool {
+        let local = local.open_or_create_root().await.unwrap();
+        let remote = remote.open_or_create_root().await.unwrap();
+
+        is_merged(&remote, &local).await.unwrap()
+    }
+
+    async fn setup() -> (TempDir, [Branch; 2]) {
+        let (base_dir, pool) = db::create_temp().await.unwrap();
+        let store = Store::new(pool);
+        let event_tx = EventSender::new(1);
+        let secrets = WriteSecrets::random();
+        let shared = BranchShared::new();
+
+        let branches = [(); 2].map(|_| {
+            Branch::new(
+                PublicKey::random(),
+                store.clone(),
+                secrets.clone().into(),
+                shared.clone(),
+                event_tx.clone(),
+            )
+        });
+
+        (base_dir, branches)
+    }
+}
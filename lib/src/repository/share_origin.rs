@@ -0,0 +1,50 @@
+use crate::access_control::{AccessMode, ShareToken};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How the repository came to this device, recorded once when it's created so all the frontends
+/// can classify the repositories consistently (e.g., "my files" vs "shared with me"). Obtained
+/// with `Repository::share_origin`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ShareOrigin {
+    /// Whether the repository was created from a share token (that is, shared with us) as opposed
+    /// to created as a new repository.
+    pub shared: bool,
+    /// Access mode the repository was created with. Later changes of the access mode (e.g., by
+    /// redeeming an invitation or by `Repository::set_access`) don't affect it.
+    pub original_mode: AccessMode,
+    /// Repository name suggested by the token. `None` if not created from a token.
+    pub suggested_name: Option<String>,
+    /// Whether the token was a one-time invitation (see `Repository::create_invitation`).
+    pub invitation: bool,
+    /// Milliseconds since the Unix epoch when the repository was created.
+    pub created_at: u64,
+}
+
+impl ShareOrigin {
+    pub(super) fn new(token: Option<&ShareToken>, created_at: SystemTime) -> Self {
+        let created_at = created_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+            .try_into()
+            .unwrap_or(u64::MAX);
+
+        match token {
+            Some(token) => Self {
+                shared: true,
+                original_mode: token.access_mode(),
+                suggested_name: Some(token.suggested_name().into_owned()),
+                invitation: token.invitation().is_some(),
+                created_at,
+            },
+            None => Self {
+                shared: false,
+                original_mode: AccessMode::Write,
+                suggested_name: None,
+                invitation: false,
+                created_at,
+            },
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    crypto::{sign::PublicKey, Hash},
+    protocol::{MultiBlockPresence, NodeState, RootNode},
+    version_vector::VersionVector,
+};
+use serde::{Deserialize, Serialize};
+
+/// Information about a snapshot (root node) of a branch.
+///
+/// NOTE: Snapshots carry no timestamp, their order is given by `snapshot_id` (within the local
+/// replica) and by `version_vector` (across replicas).
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub branch_id: PublicKey,
+    /// Locally assigned id. Higher means more recent.
+    pub snapshot_id: u32,
+    pub version_vector: VersionVector,
+    pub hash: Hash,
+    pub state: SnapshotState,
+    pub block_presence: SnapshotBlockPresence,
+}
+
+impl From<RootNode> for SnapshotInfo {
+    fn from(node: RootNode) -> Self {
+        Self {
+            branch_id: node.proof.writer_id,
+            snapshot_id: node.snapshot_id,
+            version_vector: node.proof.version_vector.clone(),
+            hash: node.proof.hash,
+            state: node.summary.state.into(),
+            block_presence: node.summary.block_presence.into(),
+        }
+    }
+}
+
+/// Whether all the index nodes of a snapshot have been received and whether it passed the quota
+/// check.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotState {
+    /// Some index nodes are still missing.
+    Incomplete,
+    /// All index nodes are present but the quota check hasn't been performed yet.
+    Complete,
+    /// The snapshot passed the quota check. Only approved snapshots are visible and shared with
+    /// the peers.
+    Approved,
+    /// The snapshot failed the quota check.
+    Rejected,
+}
+
+impl From<NodeState> for SnapshotState {
+    fn from(state: NodeState) -> Self {
+        match state {
+            NodeState::Incomplete => Self::Incomplete,
+            NodeState::Complete => Self::Complete,
+            NodeState::Approved => Self::Approved,
+            NodeState::Rejected => Self::Rejected,
+        }
+    }
+}
+
+/// How many of the blocks referenced by a snapshot are present locally.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotBlockPresence {
+    None,
+    Some,
+    Full,
+}
+
+impl From<MultiBlockPresence> for SnapshotBlockPresence {
+    fn from(presence: MultiBlockPresence) -> Self {
+        match presence {
+            MultiBlockPresence::None => Self::None,
+            MultiBlockPresence::Some(_) => Self::Some,
+            MultiBlockPresence::Full => Self::Full,
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use super::*;
 use crate::{
     blob, db,
+    directory::DiffKind,
+    event::Payload,
     protocol::{BlockId, BLOCK_NONCE_SIZE, BLOCK_SIZE},
     test_utils, LocalSecret, SetLocalSecret, WriteSecrets,
 };
 use assert_matches::assert_matches;
-use rand::Rng;
-use std::{future::Future, io::SeekFrom};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{future::Future, io::SeekFrom, path::Path};
 use tempfile::TempDir;
 use tokio::{
     sync::broadcast::Receiver,
@@ -1028,6 +1030,389 @@ async fn file_conflict_attempt_to_fork_and_modify_remote() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn conflicts() {
+    let (_base_dir, repo) = setup().await;
+    assert_eq!(repo.conflicts(), Conflicts::default());
+
+    let local_branch = repo.local_branch().unwrap();
+    let remote_branch = repo
+        .get_branch(PublicKey::random())
+        .unwrap()
+        .reopen(repo.secrets().keys().unwrap());
+
+    create_file_in_branch(&local_branch, "test.txt", b"local v1").await;
+    create_file_in_branch(&remote_branch, "test.txt", b"remote v1").await;
+
+    // The merge reports no event when it only finds conflicts, so poll.
+    time::timeout(Duration::from_secs(10), async {
+        while repo.conflicts().count == 0 {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(repo.conflicts().count, 1);
+    assert_eq!(repo.conflicts().sample, ["/test.txt"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_file_versions() {
+    let (_base_dir, repo) = setup().await;
+
+    let local_branch = repo.local_branch().unwrap();
+    let remote_id = PublicKey::random();
+
+    drop(create_file_in_branch(&local_branch, "test.txt", b"local").await);
+
+    let files = repo.open_file_versions("test.txt").await.unwrap();
+    assert_eq!(files.len(), 1);
+
+    drop(files);
+    drop(create_remote_file(&repo, remote_id, "test.txt", b"remote").await);
+
+    assert_matches!(repo.open_file("test.txt").await, Err(Error::AmbiguousEntry));
+
+    let mut files = repo.open_file_versions("test.txt").await.unwrap();
+    files.sort_by_key(|file| *file.branch().id() != *local_branch.id());
+
+    let mut contents = Vec::new();
+    for file in &mut files {
+        contents.push(file.read_to_end().await.unwrap());
+    }
+
+    assert_eq!(*files[0].branch().id(), *local_branch.id());
+    assert_eq!(*files[1].branch().id(), remote_id);
+    assert_eq!(contents, [b"local".to_vec(), b"remote".to_vec()]);
+
+    repo.create_directory("dir").await.unwrap();
+    assert_matches!(
+        repo.open_file_versions("dir").await,
+        Err(Error::EntryIsDirectory)
+    );
+    assert_matches!(
+        repo.open_file_versions("missing.txt").await,
+        Err(Error::EntryNotFound)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn explain_sync() {
+    let (_base_dir, repo) = setup().await;
+    let local_branch = repo.local_branch().unwrap();
+
+    let mut file = repo.create_file("test.dat").await.unwrap();
+    file.write_all(&random_bytes(2 * BLOCK_SIZE)).await.unwrap();
+    file.flush().await.unwrap();
+    let blob_id = *file.blob_id();
+    drop(file);
+
+    let explanation = repo.explain_sync("test.dat").await.unwrap();
+    assert_eq!(explanation.versions.len(), 1);
+    assert_eq!(explanation.versions[0].branch_id, *local_branch.id());
+    assert_eq!(explanation.versions[0].block_count, 3);
+    assert!(explanation.versions[0].missing_blocks.is_empty());
+
+    // Simulate a block that's been synced in the index but not yet downloaded.
+    let mut block_ids = BlockIds::open(local_branch, blob_id).await.unwrap();
+    let mut last_block_id = None;
+    while let Some(block_id) = block_ids.try_next().await.unwrap() {
+        last_block_id = Some(block_id);
+    }
+    let last_block_id = last_block_id.unwrap();
+
+    let mut tx = repo.shared.vault.store().begin_write().await.unwrap();
+    tx.remove_block(&last_block_id).await.unwrap();
+    tx.commit().await.unwrap();
+
+    repo.shared.vault.block_tracker.require(last_block_id);
+
+    let explanation = repo.explain_sync("test.dat").await.unwrap();
+    assert_eq!(explanation.versions[0].block_count, 3);
+    assert_eq!(
+        explanation.versions[0].missing_blocks,
+        [MissingBlockExplanation {
+            block_id: last_block_id,
+            offering_peers: 0,
+            holding_peers: 0,
+            request_state: BlockRequestState::AwaitingOffer,
+        }]
+    );
+
+    repo.create_directory("dir").await.unwrap();
+    assert_matches!(repo.explain_sync("dir").await, Err(Error::EntryIsDirectory));
+    assert_matches!(
+        repo.explain_sync("missing.txt").await,
+        Err(Error::EntryNotFound)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn derived_data() {
+    let (_base_dir, repo) = setup().await;
+
+    let mut file = repo.create_file("photo.jpg").await.unwrap();
+    file.write_all(b"v1").await.unwrap();
+    file.flush().await.unwrap();
+
+    assert_eq!(
+        repo.derived_data("photo.jpg", "thumbnail").await.unwrap(),
+        None
+    );
+
+    let vv = repo.entry_version_vector("photo.jpg").await.unwrap();
+    repo.set_derived_data("photo.jpg", "thumbnail", &vv, b"thumb v1")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        repo.derived_data("photo.jpg", "thumbnail").await.unwrap(),
+        Some(b"thumb v1".to_vec())
+    );
+    assert_eq!(
+        repo.derived_data("photo.jpg", "preview").await.unwrap(),
+        None
+    );
+
+    // Modifying the file invalidates the derived data.
+    file.write_all(b"v2").await.unwrap();
+    file.flush().await.unwrap();
+
+    assert_eq!(
+        repo.derived_data("photo.jpg", "thumbnail").await.unwrap(),
+        None
+    );
+
+    // Data derived from an outdated version is never returned.
+    repo.set_derived_data("photo.jpg", "thumbnail", &vv, b"thumb v1")
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.derived_data("photo.jpg", "thumbnail").await.unwrap(),
+        None
+    );
+
+    let vv = repo.entry_version_vector("photo.jpg").await.unwrap();
+    repo.set_derived_data("photo.jpg", "thumbnail", &vv, b"thumb v2")
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.derived_data("photo.jpg", "thumbnail").await.unwrap(),
+        Some(b"thumb v2".to_vec())
+    );
+
+    repo.clear_derived_data().await.unwrap();
+    assert_eq!(
+        repo.derived_data("photo.jpg", "thumbnail").await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prune_unmerged_branch() {
+    let (_base_dir, repo) = setup().await;
+
+    let local_branch = repo.local_branch().unwrap();
+    let remote_id = PublicKey::random();
+
+    assert_matches!(
+        repo.prune_branch(*local_branch.id()).await,
+        Err(Error::OperationNotSupported)
+    );
+
+    // Concurrent versions of the same file keep the branches concurrent so the remote one is
+    // neither merged nor pruned automatically.
+    drop(create_file_in_branch(&local_branch, "test.txt", b"local v1").await);
+    drop(create_remote_file(&repo, remote_id, "test.txt", b"remote v1").await);
+
+    assert_matches!(repo.prune_branch(remote_id).await, Err(Error::Outdated));
+    assert!(repo.get_branch_version_vector(&remote_id).await.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn share_origin() {
+    let (_base_dir, repo) = setup().await;
+    assert_eq!(repo.share_origin().await.unwrap(), None);
+
+    let token = ShareToken::from(repo.secrets().with_mode(AccessMode::Read)).with_name("photos");
+    repo.record_share_origin(Some(&token)).await.unwrap();
+
+    let origin = repo.share_origin().await.unwrap().unwrap();
+    assert!(origin.shared);
+    assert_eq!(origin.original_mode, AccessMode::Read);
+    assert_eq!(origin.suggested_name.as_deref(), Some("photos"));
+    assert!(!origin.invitation);
+
+    // Recorded only once.
+    repo.record_share_origin(None).await.unwrap();
+    assert_eq!(repo.share_origin().await.unwrap(), Some(origin));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn access_notice() {
+    test_utils::init_log();
+
+    let base_dir = TempDir::new().unwrap();
+    let params = RepositoryParams::new(base_dir.path().join(DEFAULT_REPO_NAME));
+    let repo = Repository::create(
+        &params,
+        Access::WriteUnlocked {
+            secrets: WriteSecrets::random(),
+        },
+    )
+    .await
+    .unwrap();
+    let mut rx = repo.subscribe();
+
+    assert_eq!(repo.access_notice(), None);
+
+    // Not a downgrade.
+    assert_matches!(
+        repo.publish_access_notice(AccessMode::Read, AccessMode::Write)
+            .await,
+        Err(Error::InvalidArgument)
+    );
+
+    repo.publish_access_notice(AccessMode::Write, AccessMode::Read)
+        .await
+        .unwrap();
+
+    let notice = repo.access_notice().unwrap();
+    assert_eq!(notice.from, AccessMode::Write);
+    assert_eq!(notice.to, AccessMode::Read);
+    assert!(notice.affects(AccessMode::Write));
+    assert!(!notice.affects(AccessMode::Read));
+
+    timeout(Duration::from_secs(10), async {
+        loop {
+            if let Event {
+                payload: Payload::AccessNotice,
+                ..
+            } = rx.recv().await.unwrap()
+            {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    drop(rx);
+    drop(repo);
+
+    // The notice is persisted but publishing requires write access.
+    let repo = Repository::open(&params, None, AccessMode::Read)
+        .await
+        .unwrap();
+    assert_eq!(repo.access_notice(), Some(notice));
+    assert_matches!(
+        repo.publish_access_notice(AccessMode::Read, AccessMode::Blind)
+            .await,
+        Err(Error::PermissionDenied)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn access_notice_from_skewed_clock() {
+    let base_dir = TempDir::new().unwrap();
+    let secrets = WriteSecrets::random();
+    let repo = Repository::create(
+        &RepositoryParams::new(base_dir.path().join(DEFAULT_REPO_NAME)),
+        Access::WriteUnlocked {
+            secrets: secrets.clone(),
+        },
+    )
+    .await
+    .unwrap();
+
+    // Notice from a writer whose clock is an hour ahead of ours.
+    let issued_at = Timestamp::now().as_millis() + 60 * 60 * 1000;
+    let remote_notice = AccessNotice::new(
+        &secrets.id,
+        AccessMode::Write,
+        AccessMode::Read,
+        issued_at,
+        &secrets.write_keys,
+    );
+    assert!(repo
+        .shared
+        .vault
+        .receive_access_notice(remote_notice)
+        .await
+        .unwrap());
+
+    // Our notice is issued later so it supersedes the received one despite our clock being
+    // behind.
+    repo.publish_access_notice(AccessMode::Read, AccessMode::Blind)
+        .await
+        .unwrap();
+
+    let notice = repo.access_notice().unwrap();
+    assert_eq!(notice.from, AccessMode::Read);
+    assert!(notice.issued_at > issued_at);
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn storage_full() {
+    let (_base_dir, repo) = setup().await;
+    let mut rx = repo.subscribe();
+
+    let mut file = repo.create_file("test.txt").await.unwrap();
+    file.write_all(b"hello").await.unwrap();
+
+    // No device has this much free space.
+    repo.set_min_free_space(u64::MAX);
+
+    assert_matches!(file.flush().await, Err(Error::StorageFull));
+    assert_matches!(repo.create_file("other.txt").await, Err(Error::StorageFull));
+
+    timeout(Duration::from_secs(10), async {
+        loop {
+            if let Event {
+                payload: Payload::StorageFull,
+                ..
+            } = rx.recv().await.unwrap()
+            {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    // Removing is still possible.
+    drop(file);
+    repo.remove_entry("test.txt").await.unwrap();
+
+    repo.set_min_free_space(0);
+    repo.create_file("other.txt").await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn freeze() {
+    let (_base_dir, repo) = setup().await;
+
+    let mut file = repo.create_file("test.txt").await.unwrap();
+    file.write_all(b"hello").await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    let guard = repo.freeze(FreezeMode::FailFast).await.unwrap();
+
+    // Writes fail but reads still work.
+    let error = repo.create_file("other.txt").await.unwrap_err();
+    assert!(error.is_retryable());
+
+    let mut file = repo.open_file("test.txt").await.unwrap();
+    assert_eq!(file.read_to_end().await.unwrap(), b"hello");
+    drop(file);
+
+    drop(guard);
+    repo.create_file("other.txt").await.unwrap();
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn size() {
     let (_base_dir, repo) = setup().await;
@@ -1163,60 +1548,911 @@ async fn access_mode() {
 }
 
 #[tokio::test(flavor = "multi_thread")]
-async fn set_access_mode_is_idempotent() {
+async fn change_local_secret() {
     test_utils::init_log();
 
     let base_dir = TempDir::new().unwrap();
     let params = RepositoryParams::new(base_dir.path().join("repo.db"));
-    let local_secret = SetLocalSecret::random();
+    let old_secret = SetLocalSecret::random();
+    let new_secret = SetLocalSecret::random();
 
     let repo = Repository::create(
         &params,
         Access::WriteLocked {
-            local_read_secret: local_secret.clone(),
-            local_write_secret: local_secret.clone(),
+            local_read_secret: old_secret.clone(),
+            local_write_secret: old_secret.clone(),
             secrets: WriteSecrets::random(),
         },
     )
     .await
     .unwrap();
 
+    let writer_id = *repo.local_branch().unwrap().id();
+
+    assert_matches!(
+        repo.change_local_secret(new_secret.clone().into(), old_secret.clone())
+            .await,
+        Err(Error::PermissionDenied)
+    );
+
+    repo.change_local_secret(old_secret.clone().into(), new_secret.clone())
+        .await
+        .unwrap();
     repo.close().await.unwrap();
 
     let repo = Repository::open(&params, None, AccessMode::Blind)
         .await
         .unwrap();
 
-    repo.set_access_mode(AccessMode::Write, Some(local_secret.into()))
+    repo.set_access_mode(AccessMode::Write, Some(old_secret.into()))
         .await
         .unwrap();
-    let writer_id_0 = *repo.local_branch().unwrap().id();
-
-    repo.set_access_mode(AccessMode::Write, None).await.unwrap();
-    let writer_id_1 = *repo.local_branch().unwrap().id();
+    assert_eq!(repo.access_mode(), AccessMode::Blind);
 
-    assert_eq!(writer_id_0, writer_id_1);
+    repo.set_access_mode(AccessMode::Write, Some(new_secret.into()))
+        .await
+        .unwrap();
+    assert_eq!(repo.access_mode(), AccessMode::Write);
+    assert_eq!(*repo.local_branch().unwrap().id(), writer_id);
 }
 
 #[tokio::test(flavor = "multi_thread")]
-async fn aux_db_files_are_deleted_on_close() {
-    test_utils::init_log();
-
-    let (temp_dir, repo) = setup().await;
+async fn injected_rng() {
+    let base_dir = TempDir::new().unwrap();
+    let secrets = WriteSecrets::random();
 
-    repo.close().await.unwrap();
+    let mut ids = Vec::new();
 
-    let mut read_dir = fs::read_dir(temp_dir.path()).await.unwrap();
-    let mut entries = Vec::new();
+    for name in ["a.db", "b.db"] {
+        let repo = Repository::create(
+            &RepositoryParams::new(base_dir.path().join(name)).with_rng(StdRng::seed_from_u64(0)),
+            Access::WriteUnlocked {
+                secrets: secrets.clone(),
+            },
+        )
+        .await
+        .unwrap();
 
-    while let Some(entry) = read_dir.next_entry().await.unwrap() {
-        entries.push(entry.path());
+        let file = repo.create_file("test.txt").await.unwrap();
+        ids.push((*file.branch().id(), *file.blob_id()));
     }
 
-    assert_eq!(entries, [temp_dir.path().join(DEFAULT_REPO_NAME)]);
+    assert_eq!(ids[0], ids[1]);
 }
 
-const DEFAULT_REPO_NAME: &str = "repo.db";
+#[tokio::test(flavor = "multi_thread")]
+async fn unlock_token() {
+    test_utils::init_log();
+
+    let base_dir = TempDir::new().unwrap();
+    let params = RepositoryParams::new(base_dir.path().join("repo.db"));
+    let local_secret = SetLocalSecret::random();
+
+    let repo = Repository::create(
+        &params,
+        Access::WriteLocked {
+            local_read_secret: local_secret.clone(),
+            local_write_secret: local_secret.clone(),
+            secrets: WriteSecrets::random(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let writer_id = *repo.local_branch().unwrap().id();
+    let token = repo.create_unlock_token().await.unwrap();
+    repo.close().await.unwrap();
+
+    // The token survives the conversion to string.
+    let token: UnlockToken = token.to_string().parse().unwrap();
+
+    let repo = Repository::open(&params, None, AccessMode::Blind)
+        .await
+        .unwrap();
+
+    assert_matches!(
+        repo.unlock_with_token(&UnlockToken::random()).await,
+        Err(Error::PermissionDenied)
+    );
+    assert_eq!(repo.access_mode(), AccessMode::Blind);
+
+    repo.unlock_with_token(&token).await.unwrap();
+    assert_eq!(repo.access_mode(), AccessMode::Write);
+    assert_eq!(*repo.local_branch().unwrap().id(), writer_id);
+
+    repo.revoke_unlock_token().await.unwrap();
+    repo.close().await.unwrap();
+
+    let repo = Repository::open(&params, None, AccessMode::Blind)
+        .await
+        .unwrap();
+
+    assert_matches!(
+        repo.unlock_with_token(&token).await,
+        Err(Error::PermissionDenied)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unlock_token_revoked_by_disabling_access() {
+    test_utils::init_log();
+
+    let base_dir = TempDir::new().unwrap();
+    let params = RepositoryParams::new(base_dir.path().join("repo.db"));
+    let local_secret = SetLocalSecret::random();
+
+    let repo = Repository::create(
+        &params,
+        Access::WriteLocked {
+            local_read_secret: local_secret.clone(),
+            local_write_secret: local_secret.clone(),
+            secrets: WriteSecrets::random(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let token = repo.create_unlock_token().await.unwrap();
+
+    repo.set_access(Some(AccessChange::Disable), Some(AccessChange::Disable))
+        .await
+        .unwrap();
+    repo.close().await.unwrap();
+
+    let repo = Repository::open(&params, None, AccessMode::Blind)
+        .await
+        .unwrap();
+
+    assert_matches!(
+        repo.unlock_with_token(&token).await,
+        Err(Error::PermissionDenied)
+    );
+    assert_eq!(repo.access_mode(), AccessMode::Blind);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unlock_token_revoked_by_changing_local_secret() {
+    test_utils::init_log();
+
+    let base_dir = TempDir::new().unwrap();
+    let params = RepositoryParams::new(base_dir.path().join("repo.db"));
+    let old_secret = SetLocalSecret::random();
+    let new_secret = SetLocalSecret::random();
+
+    let repo = Repository::create(
+        &params,
+        Access::WriteLocked {
+            local_read_secret: old_secret.clone(),
+            local_write_secret: old_secret.clone(),
+            secrets: WriteSecrets::random(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let token = repo.create_unlock_token().await.unwrap();
+
+    repo.change_local_secret(old_secret.into(), new_secret)
+        .await
+        .unwrap();
+    repo.close().await.unwrap();
+
+    let repo = Repository::open(&params, None, AccessMode::Blind)
+        .await
+        .unwrap();
+
+    assert_matches!(
+        repo.unlock_with_token(&token).await,
+        Err(Error::PermissionDenied)
+    );
+    assert_eq!(repo.access_mode(), AccessMode::Blind);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn set_access_mode_is_idempotent() {
+    test_utils::init_log();
+
+    let base_dir = TempDir::new().unwrap();
+    let params = RepositoryParams::new(base_dir.path().join("repo.db"));
+    let local_secret = SetLocalSecret::random();
+
+    let repo = Repository::create(
+        &params,
+        Access::WriteLocked {
+            local_read_secret: local_secret.clone(),
+            local_write_secret: local_secret.clone(),
+            secrets: WriteSecrets::random(),
+        },
+    )
+    .await
+    .unwrap();
+
+    repo.close().await.unwrap();
+
+    let repo = Repository::open(&params, None, AccessMode::Blind)
+        .await
+        .unwrap();
+
+    repo.set_access_mode(AccessMode::Write, Some(local_secret.into()))
+        .await
+        .unwrap();
+    let writer_id_0 = *repo.local_branch().unwrap().id();
+
+    repo.set_access_mode(AccessMode::Write, None).await.unwrap();
+    let writer_id_1 = *repo.local_branch().unwrap().id();
+
+    assert_eq!(writer_id_0, writer_id_1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn aux_db_files_are_deleted_on_close() {
+    test_utils::init_log();
+
+    let (temp_dir, repo) = setup().await;
+
+    repo.close().await.unwrap();
+
+    let mut read_dir = fs::read_dir(temp_dir.path()).await.unwrap();
+    let mut entries = Vec::new();
+
+    while let Some(entry) = read_dir.next_entry().await.unwrap() {
+        entries.push(entry.path());
+    }
+
+    assert_eq!(entries, [temp_dir.path().join(DEFAULT_REPO_NAME)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn force_close() {
+    test_utils::init_log();
+
+    let (temp_dir, repo) = setup().await;
+
+    let mut file = repo.create_file("test.txt").await.unwrap();
+    file.write_all(b"hello").await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    // Simulate an ongoing operation. A regular `close` would wait for it to finish.
+    let tx = repo.shared.vault.store().begin_write().await.unwrap();
+
+    repo.force_close();
+
+    // Subsequent operations fail immediately.
+    assert_matches!(
+        repo.open_file("test.txt").await,
+        Err(Error::Store(crate::store::Error::Cancelled))
+    );
+
+    // The db is closed as soon as the ongoing operation gives up its connection.
+    drop(tx);
+
+    time::timeout(Duration::from_secs(10), async {
+        loop {
+            let mut read_dir = fs::read_dir(temp_dir.path()).await.unwrap();
+            let mut entries = Vec::new();
+
+            while let Some(entry) = read_dir.next_entry().await.unwrap() {
+                entries.push(entry.path());
+            }
+
+            if entries == [temp_dir.path().join(DEFAULT_REPO_NAME)] {
+                break;
+            }
+
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn labels() {
+    let (_base_dir, repo) = setup().await;
+    let mut rx = repo.subscribe();
+
+    assert_eq!(repo.display_name().await.unwrap(), None);
+
+    repo.set_display_name(Some("holiday photos")).await.unwrap();
+    repo.set_icon(Some("🏖")).await.unwrap();
+    repo.set_color(Some(0xff00_80ff)).await.unwrap();
+
+    assert_eq!(
+        repo.display_name().await.unwrap().as_deref(),
+        Some("holiday photos")
+    );
+    assert_eq!(repo.icon().await.unwrap().as_deref(), Some("🏖"));
+    assert_eq!(repo.color().await.unwrap(), Some(0xff00_80ff));
+
+    // The labels are accessible also as regular metadata.
+    assert_eq!(
+        repo.metadata()
+            .get::<String>("display_name")
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("holiday photos")
+    );
+
+    timeout(Duration::from_secs(5), async {
+        loop {
+            if let Event {
+                payload: Payload::MetadataChanged,
+                ..
+            } = rx.recv().await.unwrap()
+            {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    repo.set_display_name(None).await.unwrap();
+    assert_eq!(repo.display_name().await.unwrap(), None);
+}
+
+const DEFAULT_REPO_NAME: &str = "repo.db";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn diff() {
+    let (_base_dir, repo) = setup().await;
+
+    let local_id = *repo.local_branch().unwrap().id();
+    let missing_id = PublicKey::random();
+
+    let mut file = repo.create_file("a.txt").await.unwrap();
+    file.write_all(b"foo").await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    repo.create_directory("dir").await.unwrap();
+    let mut file = repo.create_file("dir/b.txt").await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    assert_eq!(repo.branch_ids().await.unwrap(), [local_id]);
+
+    let added = |name: &str, entry_type| EntryDiff {
+        name: name.to_owned(),
+        kind: DiffKind::Added,
+        entry_type,
+    };
+
+    assert_eq!(
+        repo.diff(&missing_id, &local_id, "/").await.unwrap(),
+        [
+            added("a.txt", EntryType::File),
+            added("dir", EntryType::Directory)
+        ]
+    );
+    assert_eq!(
+        repo.diff(&missing_id, &local_id, "dir").await.unwrap(),
+        [added("b.txt", EntryType::File)]
+    );
+    assert_eq!(
+        repo.diff(&local_id, &missing_id, "/").await.unwrap(),
+        [
+            EntryDiff {
+                name: "a.txt".to_owned(),
+                kind: DiffKind::Removed,
+                entry_type: EntryType::File,
+            },
+            EntryDiff {
+                name: "dir".to_owned(),
+                kind: DiffKind::Removed,
+                entry_type: EntryType::Directory,
+            }
+        ]
+    );
+    assert!(repo
+        .diff(&local_id, &local_id, "/")
+        .await
+        .unwrap()
+        .is_empty());
+    assert_matches!(
+        repo.diff(&local_id, &local_id, "a.txt").await,
+        Err(Error::EntryIsFile)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wait_for_entry_version() {
+    let (_base_dir, repo) = setup().await;
+    let local_id = *repo.local_branch().unwrap().id();
+
+    let mut file = repo.create_file("test.txt").await.unwrap();
+    file.write_all(b"one").await.unwrap();
+    file.flush().await.unwrap();
+
+    // Already satisfied.
+    let vv = repo.entry_version_vector("test.txt").await.unwrap();
+    repo.wait_for_entry_version("test.txt", &vv).await.unwrap();
+
+    // Satisfied by the next write.
+    let vv = vv.incremented(local_id);
+    let waiter = repo.wait_for_entry_version("test.txt", &vv);
+    let writer = async {
+        file.write_all(b"two").await.unwrap();
+        file.flush().await.unwrap();
+    };
+
+    let (result, _) = timeout(Duration::from_secs(10), future::join(waiter, writer))
+        .await
+        .unwrap();
+    result.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_for_path() {
+    let (_base_dir, repo) = setup().await;
+
+    let mut file_a = repo.create_file("a.txt").await.unwrap();
+    file_a.flush().await.unwrap();
+
+    let mut file_b = repo.create_file("b.txt").await.unwrap();
+    file_b.flush().await.unwrap();
+
+    let mut events = pin!(repo.events_for_path("a.txt").await);
+
+    // Modifying another entry doesn't affect the path.
+    file_b.write_all(b"b").await.unwrap();
+    file_b.flush().await.unwrap();
+
+    assert!(timeout(Duration::from_millis(500), events.next())
+        .await
+        .is_err());
+
+    file_a.write_all(b"a").await.unwrap();
+    file_a.flush().await.unwrap();
+
+    assert_matches!(
+        timeout(Duration::from_secs(5), events.next()).await,
+        Ok(Some(Event {
+            payload: Payload::BranchChanged(_),
+            ..
+        }))
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batch() {
+    let (_base_dir, repo) = setup().await;
+
+    repo.create_file("backup.txt").await.unwrap();
+
+    // Committed batch applies both the file and the metadata changes.
+    let mut file = repo.open_file("backup.txt").await.unwrap();
+    file.write_all(b"one").await.unwrap();
+
+    let mut batch = repo.batch().await.unwrap();
+    batch.flush_file(&mut file).await.unwrap();
+    batch.set_metadata("last_backup", 1u64).await.unwrap();
+    batch.commit().await.unwrap();
+    assert!(!file.is_dirty());
+    drop(file);
+
+    assert_eq!(read_file(&repo, "backup.txt").await, b"one");
+    assert_eq!(
+        repo.metadata().get::<u64>("last_backup").await.unwrap(),
+        Some(1)
+    );
+
+    // Dropped batch applies neither but the file modifications are not lost.
+    let mut file = repo.open_file("backup.txt").await.unwrap();
+    file.write_all(b"two").await.unwrap();
+
+    let mut batch = repo.batch().await.unwrap();
+    batch.flush_file(&mut file).await.unwrap();
+    batch.set_metadata("last_backup", 2u64).await.unwrap();
+    drop(batch);
+
+    assert_eq!(read_file(&repo, "backup.txt").await, b"one");
+    assert_eq!(
+        repo.metadata().get::<u64>("last_backup").await.unwrap(),
+        Some(1)
+    );
+
+    assert!(file.is_dirty());
+    file.flush().await.unwrap();
+    drop(file);
+
+    assert_eq!(read_file(&repo, "backup.txt").await, b"two");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn barrier() {
+    let (_base_dir, repo) = setup().await;
+
+    repo.barrier().await.unwrap();
+
+    // The barrier waits for the write in progress.
+    let mut batch = repo.batch().await.unwrap();
+    batch.set_metadata("saved", 1u64).await.unwrap();
+
+    assert!(timeout(Duration::from_millis(100), repo.barrier())
+        .await
+        .is_err());
+
+    let (barrier, commit) = tokio::join!(repo.barrier(), batch.commit());
+    barrier.unwrap();
+    commit.unwrap();
+
+    assert_eq!(repo.metadata().get::<u64>("saved").await.unwrap(), Some(1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bulk_ingest() {
+    let (_base_dir, repo) = setup().await;
+
+    let small = b"hello";
+    let mut large = vec![0; 4 * BLOCK_SIZE + 1];
+    rand::thread_rng().fill(&mut large[..]);
+
+    let mut ingest = repo.begin_bulk_ingest().await.unwrap();
+    ingest.create_directory("a/b").await.unwrap();
+    ingest.create_file("a/b/small.txt").await.unwrap();
+    ingest.write(small).await.unwrap();
+    ingest.create_file("/large.dat").await.unwrap();
+
+    for chunk in large.chunks(1000) {
+        ingest.write(chunk).await.unwrap();
+    }
+
+    ingest.create_directory("c").await.unwrap();
+
+    // Nothing is visible until committed.
+    assert_matches!(
+        repo.open_file("a/b/small.txt").await,
+        Err(Error::EntryNotFound)
+    );
+
+    ingest.commit().await.unwrap();
+
+    let mut file = repo.open_file("a/b/small.txt").await.unwrap();
+    assert_eq!(file.read_to_end().await.unwrap(), small);
+
+    let mut file = repo.open_file("large.dat").await.unwrap();
+    assert_eq!(file.read_to_end().await.unwrap(), large);
+
+    repo.open_directory("c").await.unwrap();
+
+    // Dropping without committing discards everything.
+    let mut ingest = repo.begin_bulk_ingest().await.unwrap();
+    ingest.create_file("d.txt").await.unwrap();
+    ingest.write(small).await.unwrap();
+    drop(ingest);
+
+    assert_matches!(repo.open_file("d.txt").await, Err(Error::EntryNotFound));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unreplicated_changes() {
+    let (_base_dir, repo) = setup().await;
+
+    let changes = repo.unreplicated_changes().await.unwrap();
+    assert!(changes.is_empty());
+
+    let mut file = repo.create_file("test.txt").await.unwrap();
+    file.write_all(b"hello").await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    let changes = repo.unreplicated_changes().await.unwrap();
+    assert!(!changes.is_empty());
+    assert_eq!(
+        changes.pending,
+        changes.local.get(repo.local_branch().unwrap().id())
+    );
+    assert!(changes.acknowledged.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn list_snapshots() {
+    let (_base_dir, repo) = setup().await;
+
+    let mut file = repo.create_file("test.txt").await.unwrap();
+    file.write_all(b"hello").await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    let local_branch = repo.local_branch().unwrap();
+    let snapshots = repo.snapshots(10).await.unwrap();
+
+    assert!(!snapshots.is_empty());
+
+    let latest = &snapshots[0];
+    assert_eq!(latest.branch_id, *local_branch.id());
+    assert_eq!(
+        latest.version_vector,
+        local_branch.version_vector().await.unwrap()
+    );
+    assert_eq!(latest.state, SnapshotState::Approved);
+    assert_eq!(latest.block_presence, SnapshotBlockPresence::Full);
+
+    assert_eq!(repo.snapshots(1).await.unwrap().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_entry() {
+    let (_base_dir, repo) = setup().await;
+    let repository_id = *repo.secrets().id();
+
+    let content = random_bytes(2 * BLOCK_SIZE + 100);
+    let mut file = repo.create_file("test.dat").await.unwrap();
+    file.write_all(&content).await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    let proof = repo.prove_entry("test.dat").await.unwrap();
+    assert_eq!(proof.branch_id, *repo.local_branch().unwrap().id());
+    assert_eq!(proof.blocks.len(), 3);
+
+    let block_ids = crate::verify_entry_proof(&proof, &repository_id).unwrap();
+    assert_eq!(block_ids.len(), 3);
+
+    // Wrong repository
+    assert_eq!(
+        crate::verify_entry_proof(&proof, &RepositoryId::random()),
+        Err(crate::EntryProofError::InvalidSignature)
+    );
+
+    // Tampered block id
+    let mut tampered = proof.clone();
+    let leaf = tampered.blocks[1]
+        .leaves
+        .iter_mut()
+        .find(|(locator, _)| *locator == proof.blocks[1].locator)
+        .unwrap();
+    leaf.1 = proof.blocks[0].block_id;
+    tampered.blocks[1].block_id = proof.blocks[0].block_id;
+
+    assert_eq!(
+        crate::verify_entry_proof(&tampered, &repository_id),
+        Err(crate::EntryProofError::HashMismatch)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn contribution_stats() {
+    let (_base_dir, repo) = setup().await;
+    let contribution = &repo.shared.vault.contribution;
+
+    assert_eq!(
+        repo.contribution_stats().await.unwrap(),
+        ContributionStats::default()
+    );
+    assert_eq!(repo.contribution_stats().await.unwrap().ratio(), None);
+
+    contribution.record_served(6);
+    contribution.record_downloaded(3);
+    contribution.persist(repo.db()).await.unwrap();
+    contribution.record_served(2);
+
+    let stats = repo.contribution_stats().await.unwrap();
+    assert_eq!(
+        stats,
+        ContributionStats {
+            bytes_served: 8,
+            bytes_downloaded: 3,
+        }
+    );
+    assert_eq!(stats.ratio(), Some(8.0 / 3.0));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wipe_directive() {
+    let (base_dir, repo) = setup().await;
+    let mut rx = repo.subscribe();
+    let writer_id = repo.shared.credentials.read().unwrap().writer_id;
+    let store_path = base_dir.path().join(DEFAULT_REPO_NAME);
+
+    repo.create_file("secret.txt").await.unwrap();
+
+    // Directive targeted at another device is ignored.
+    repo.publish_wipe_directive(PublicKey::random())
+        .await
+        .unwrap();
+    assert!(!wipe::check(
+        &repo.shared,
+        &repo.shared.vault.wipe_directives.borrow()
+    ));
+
+    // The directive is not visible in the directory tree.
+    assert_eq!(repo.open_directory("/").await.unwrap().entries().count(), 1);
+
+    repo.publish_wipe_directive(writer_id).await.unwrap();
+
+    timeout(Duration::from_secs(10), async {
+        loop {
+            if let Event {
+                payload: Payload::Wiped,
+                ..
+            } = rx.recv().await.unwrap()
+            {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(repo.open_file("secret.txt").await.is_err());
+
+    for suffix in ["", "-wal", "-shm"] {
+        let mut path = store_path.clone().into_os_string();
+        path.push(suffix);
+        assert!(!Path::new(&path).exists());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn export_and_ingest_blocks() {
+    let (_base_dir, repo) = setup().await;
+
+    let content = random_bytes(2 * BLOCK_SIZE);
+
+    let mut file = repo.create_file("test.dat").await.unwrap();
+    file.write_all(&content).await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    let block_ids = repo
+        .shared
+        .vault
+        .store()
+        .block_ids(1024)
+        .next()
+        .await
+        .unwrap();
+
+    let mut exported = Vec::new();
+    for block_id in &block_ids {
+        exported.push((*block_id, repo.export_block(block_id).await.unwrap()));
+    }
+
+    // Simulate a device which synced the index but none of the blocks.
+    let mut tx = repo.shared.vault.store().begin_write().await.unwrap();
+    for block_id in &block_ids {
+        tx.remove_block(block_id).await.unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    let (id, (ciphertext, nonce)) = &exported[0];
+
+    // Wrong length
+    assert_matches!(
+        repo.ingest_block(*id, &ciphertext[1..], nonce).await,
+        Err(Error::InvalidArgument)
+    );
+
+    // Tampered content
+    let mut tampered = ciphertext.clone();
+    tampered[0] ^= 1;
+    assert_matches!(
+        repo.ingest_block(*id, &tampered, nonce).await,
+        Err(Error::MalformedData)
+    );
+
+    for (id, (ciphertext, nonce)) in &exported {
+        assert!(repo.ingest_block(*id, ciphertext, nonce).await.unwrap());
+    }
+
+    assert_eq!(read_file(&repo, "test.dat").await, content);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn adopt_blocks_from_store_copy() {
+    let (base_dir, repo) = setup().await;
+    let store_path = base_dir.path().join(DEFAULT_REPO_NAME);
+
+    let content = random_bytes(3 * BLOCK_SIZE);
+
+    let mut file = repo.create_file("test.dat").await.unwrap();
+    file.write_all(&content).await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    // Copy the store, as if onto an external disk.
+    repo.close().await.unwrap();
+
+    let copy_path = base_dir.path().join("copy.db");
+    fs::copy(&store_path, &copy_path).await.unwrap();
+
+    let snapshot_path = base_dir.path().join("copy-snapshot.db");
+    fs::copy(&copy_path, &snapshot_path).await.unwrap();
+
+    // Simulate a device which synced the index but none of the blocks.
+    let repo = Repository::open(&RepositoryParams::new(&store_path), None, AccessMode::Write)
+        .await
+        .unwrap();
+
+    let block_ids = repo
+        .shared
+        .vault
+        .store()
+        .block_ids(1024)
+        .next()
+        .await
+        .unwrap();
+    let mut tx = repo.shared.vault.store().begin_write().await.unwrap();
+    for block_id in &block_ids {
+        tx.remove_block(block_id).await.unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    assert_eq!(repo.count_blocks().await.unwrap(), 0);
+
+    assert_eq!(
+        repo.adopt_blocks_from(&copy_path).await.unwrap(),
+        block_ids.len() as u64
+    );
+    assert_eq!(read_file(&repo, "test.dat").await, content);
+
+    // Nothing left to adopt.
+    assert_eq!(repo.adopt_blocks_from(&copy_path).await.unwrap(), 0);
+
+    // The copy is left untouched.
+    assert_eq!(
+        fs::read(&copy_path).await.unwrap(),
+        fs::read(&snapshot_path).await.unwrap()
+    );
+
+    // Stores of other repositories are rejected.
+    let other_path = base_dir.path().join("other.db");
+    Repository::create(
+        &RepositoryParams::new(&other_path),
+        Access::WriteUnlocked {
+            secrets: WriteSecrets::random(),
+        },
+    )
+    .await
+    .unwrap()
+    .close()
+    .await
+    .unwrap();
+
+    assert_matches!(
+        repo.adopt_blocks_from(&other_path).await,
+        Err(Error::InvalidArgument)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entry_id() {
+    let (_base_dir, repo) = setup().await;
+
+    let file = repo.create_file("a.txt").await.unwrap();
+    let file_id = file.id();
+    drop(file);
+
+    let dir_id = repo.create_directory("dir").await.unwrap().id();
+
+    assert_eq!(repo.open_directory("/").await.unwrap().id(), 0);
+    assert_ne!(file_id, dir_id);
+    // The ids are not truncated to 64 bits.
+    assert_ne!(file_id >> 64, 0);
+
+    let root = repo.open_directory("/").await.unwrap();
+    assert_eq!(root.lookup_unique("a.txt").unwrap().id(), file_id);
+    assert_eq!(root.lookup_unique("dir").unwrap().id(), dir_id);
+    drop(root);
+
+    // The id is preserved on move.
+    repo.move_entry("/", "a.txt", "dir", "b.txt").await.unwrap();
+
+    assert_eq!(repo.open_file("dir/b.txt").await.unwrap().id(), file_id);
+    assert_eq!(
+        repo.open_directory("dir")
+            .await
+            .unwrap()
+            .lookup_unique("b.txt")
+            .unwrap()
+            .id(),
+        file_id
+    );
+}
 
 async fn setup() -> (TempDir, Repository) {
     test_utils::init_log();
@@ -0,0 +1,39 @@
+use crate::{crypto::sign::PublicKey, version_vector::VersionVector};
+use serde::{Deserialize, Serialize};
+
+/// Local changes not yet observed by any peer, that is, the changes that would be lost if this
+/// device was lost. Obtained with `Repository::unreplicated_changes`.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct UnreplicatedChanges {
+    /// Version vector of the local branch.
+    pub local: VersionVector,
+    /// Merge of the version vectors acknowledged by the peers: those of the remote branches
+    /// (which include our changes once the peer merged them) and those of the local snapshots
+    /// covered by a mirror receipt.
+    pub acknowledged: VersionVector,
+    /// Number of the local versions (edits made on this device) not yet acknowledged by any peer.
+    pub pending: u64,
+}
+
+impl UnreplicatedChanges {
+    pub(super) fn new(
+        local_id: &PublicKey,
+        local: VersionVector,
+        acknowledged: VersionVector,
+    ) -> Self {
+        let pending = local
+            .get(local_id)
+            .saturating_sub(acknowledged.get(local_id));
+
+        Self {
+            local,
+            acknowledged,
+            pending,
+        }
+    }
+
+    /// Whether all the local changes have been observed by some peer.
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+}
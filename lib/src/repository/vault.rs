@@ -1,37 +1,75 @@
 //! Repository state and operations that don't require read or write access.
 
-use super::{quota, LocalId, Metadata, RepositoryId, RepositoryMonitor};
+use super::{
+    invitation::{InvitationId, RedeemReply, Redemption, StoredInvitation},
+    metadata::{access_notice, invitations, wipe_directives},
+    quota, AccessNotice, ContributionTracker, LocalId, Metadata, MirrorReceiptTracker,
+    RepositoryId, RepositoryMonitor, WipeDirective,
+};
 use crate::{
     block_tracker::{BlockPromise, BlockTracker, OfferState},
-    crypto::{sign::PublicKey, CacheHash},
+    crypto::{
+        sign::{Keypair, PublicKey},
+        CacheHash, Hash,
+    },
     db,
     debug::DebugPrinter,
-    error::Result,
+    error::{Error, Result},
     event::{EventSender, Payload},
+    memory::MemoryBudget,
+    network::MirrorReceipt,
     protocol::{
-        Block, BlockId, InnerNodes, LeafNodes, MultiBlockPresence, NodeState, ProofError,
+        Block, BlockId, InnerNodes, LeafNodes, MultiBlockPresence, NodeState, Proof, ProofError,
         UntrustedProof,
     },
+    replica_tracker::ReplicaTracker,
     storage_size::StorageSize,
     store::{
         self, InnerNodeReceiveStatus, LeafNodeReceiveStatus, ReceiveFilter, RootNodeReceiveStatus,
         Store, WriteTransaction,
     },
+    time::{self, Timestamp},
 };
+use deadlock::BlockingMutex;
 use futures_util::TryStreamExt;
 use sqlx::Row;
-use std::{sync::Arc, time::Duration};
+use std::{
+    pin::pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
 use tracing::Instrument;
 
+/// Number of blocks imported per transaction by `adopt_blocks_from`.
+const ADOPT_BLOCKS_PAGE_SIZE: u32 = 64;
+
+/// Default maximum time a change of a branch can be held back before its snapshot is published to
+/// the peers (see `Repository::set_snapshot_max_latency`).
+const DEFAULT_SNAPSHOT_MAX_LATENCY: Duration = Duration::from_millis(500);
+
 #[derive(Clone)]
 pub(crate) struct Vault {
     repository_id: RepositoryId,
     store: Store,
     pub event_tx: EventSender,
     pub block_tracker: BlockTracker,
+    pub replica_tracker: ReplicaTracker,
+    pub contribution: Arc<ContributionTracker>,
+    pub mirror_receipts: Arc<MirrorReceiptTracker>,
+    // Keys this replica signs its mirror receipts with.
+    pub receipt_keys: Arc<Keypair>,
     pub block_request_mode: BlockRequestMode,
     pub local_id: LocalId,
     pub monitor: Arc<RepositoryMonitor>,
+    pub snapshot_max_latency: Arc<BlockingMutex<Duration>>,
+    pub redemption: Arc<Redemption>,
+    // Latest access notice (see `Repository::publish_access_notice`), gossiped to the peers.
+    pub access_notice: Arc<watch::Sender<Option<AccessNotice>>>,
+    // Wipe directives (see `Repository::publish_wipe_directive`), gossiped to the peers.
+    pub wipe_directives: Arc<watch::Sender<Vec<WipeDirective>>>,
+    // When was a root node last received from a remote replica.
+    pub last_root_node_received: Arc<BlockingMutex<Option<Instant>>>,
 }
 
 impl Vault {
@@ -39,19 +77,32 @@ impl Vault {
         repository_id: RepositoryId,
         event_tx: EventSender,
         pool: db::Pool,
+        memory: MemoryBudget,
         block_request_mode: BlockRequestMode,
         monitor: RepositoryMonitor,
     ) -> Self {
-        let store = Store::new(pool);
+        let store = Store::with_memory_budget(pool, memory);
 
         Self {
             repository_id,
             store,
             event_tx,
-            block_tracker: BlockTracker::new(),
+            block_tracker: BlockTracker::with_metrics(
+                monitor.block_offers_outstanding.clone(),
+                monitor.block_offers_throttled.clone(),
+            ),
+            replica_tracker: ReplicaTracker::new(),
+            contribution: Arc::new(ContributionTracker::default()),
+            mirror_receipts: Arc::new(MirrorReceiptTracker::default()),
+            receipt_keys: Arc::new(Keypair::random()),
             block_request_mode,
             local_id: LocalId::new(),
             monitor: Arc::new(monitor),
+            snapshot_max_latency: Arc::new(BlockingMutex::new(DEFAULT_SNAPSHOT_MAX_LATENCY)),
+            redemption: Arc::new(Redemption::default()),
+            access_notice: Arc::new(watch::channel(None).0),
+            wipe_directives: Arc::new(watch::channel(Vec::new()).0),
+            last_root_node_received: Arc::new(BlockingMutex::new(None)),
         }
     }
 
@@ -78,11 +129,24 @@ impl Vault {
             }
         };
 
+        self.receive_verified_root_node(proof, block_presence).await
+    }
+
+    /// Same as `receive_root_node` but for a proof that has already been verified.
+    pub async fn receive_verified_root_node(
+        &self,
+        proof: Proof,
+        block_presence: MultiBlockPresence,
+    ) -> Result<RootNodeReceiveStatus> {
         // Ignore branches with empty version vectors because they have no content yet.
         if proof.version_vector.is_empty() {
             return Ok(RootNodeReceiveStatus::default());
         }
 
+        *self.last_root_node_received.lock().unwrap() = Some(Instant::now());
+
+        self.check_disk_space()?;
+
         let mut tx = self.store().begin_write().await?;
         let status = tx.receive_root_node(proof, block_presence).await?;
         self.finalize_receive(tx, &status.new_approved).await?;
@@ -99,6 +163,8 @@ impl Vault {
         receive_filter: &ReceiveFilter,
         quota: Option<StorageSize>,
     ) -> Result<InnerNodeReceiveStatus> {
+        self.check_disk_space()?;
+
         let mut tx = self.store().begin_write().await?;
         let status = tx.receive_inner_nodes(nodes, receive_filter, quota).await?;
         self.finalize_receive(tx, &status.new_approved).await?;
@@ -114,6 +180,8 @@ impl Vault {
         nodes: CacheHash<LeafNodes>,
         quota: Option<StorageSize>,
     ) -> Result<LeafNodeReceiveStatus> {
+        self.check_disk_space()?;
+
         let mut tx = self.store().begin_write().await?;
         let status = tx.receive_leaf_nodes(nodes, quota).await?;
         self.finalize_receive(tx, &status.new_approved).await?;
@@ -126,6 +194,8 @@ impl Vault {
         let block_id = block.id;
         let event_tx = self.event_tx.clone();
 
+        self.check_disk_space()?;
+
         let mut tx = self.store().begin_write().await?;
         match tx.receive_block(block).await {
             Ok(()) => (),
@@ -153,6 +223,71 @@ impl Vault {
         Ok(())
     }
 
+    /// Fails with `Error::StorageFull` when there is not enough free space left on the device to
+    /// store what is received (see `Repository::set_min_free_space`).
+    pub fn check_disk_space(&self) -> Result<()> {
+        match self.store().db().disk_space().check() {
+            Ok(()) => Ok(()),
+            Err(crossed) => {
+                if crossed {
+                    self.event_tx.send(Payload::StorageFull);
+                }
+
+                Err(Error::StorageFull)
+            }
+        }
+    }
+
+    /// Imports the blocks from `src` (another store of the same repository) which are referenced
+    /// by this store's index but missing. Blocks whose content doesn't match their id are skipped.
+    /// Returns the number of imported blocks.
+    pub async fn adopt_blocks_from(&self, src: &Store) -> Result<u64> {
+        let mut after = None;
+        let mut count = 0;
+
+        loop {
+            let blocks = src
+                .acquire_read()
+                .await?
+                .load_blocks_page(after.as_ref(), ADOPT_BLOCKS_PAGE_SIZE)
+                .await?;
+
+            let Some((last_id, _)) = blocks.last() else {
+                break;
+            };
+            after = Some(*last_id);
+
+            let mut tx = self.store().begin_write().await?;
+            let mut adopted = Vec::new();
+
+            for (id, block) in blocks {
+                let Some(block) = block.filter(|block| block.id == id) else {
+                    tracing::warn!(?id, "Not adopting corrupted block");
+                    continue;
+                };
+
+                if !tx.is_block_missing(&id).await? {
+                    continue;
+                }
+
+                tx.receive_block(&block).await?;
+                adopted.push(id);
+            }
+
+            count += adopted.len() as u64;
+
+            let event_tx = self.event_tx.clone();
+            tx.commit_and_then(move || {
+                for block_id in adopted {
+                    event_tx.send(Payload::BlockReceived(block_id));
+                }
+            })
+            .await?;
+        }
+
+        Ok(count)
+    }
+
     /// Receive a message that the block has been found on the peer.
     pub async fn receive_block_not_found(
         &self,
@@ -196,7 +331,7 @@ impl Vault {
     }
 
     pub fn metadata(&self) -> Metadata {
-        Metadata::new(self.store().db().clone())
+        Metadata::new(self.store().db().clone(), self.event_tx.clone())
     }
 
     /// Total size of the stored data
@@ -233,6 +368,92 @@ impl Vault {
         Ok(quota::get(&mut conn).await?.map(StorageSize::from_bytes))
     }
 
+    /// Stores the receipt if it's for a snapshot we actually have. Returns whether it was stored.
+    pub async fn store_mirror_receipt(&self, receipt: MirrorReceipt) -> Result<bool> {
+        let mut reader = self.store().acquire_read().await?;
+        let mut nodes = pin!(reader.load_root_nodes_by_writer_in_any_state(&receipt.branch_id));
+
+        while let Some(node) = nodes.try_next().await? {
+            if node.proof.hash == receipt.root_hash {
+                self.mirror_receipts.record(receipt);
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    pub async fn mirror_receipts(&self) -> Result<Vec<MirrorReceipt>> {
+        Ok(self.mirror_receipts.load(self.store().db()).await?)
+    }
+
+    /// Stores the wipe directive and gossips it to the peers if it's authentic and not stored yet.
+    /// Returns whether it was accepted.
+    pub async fn receive_wipe_directive(&self, directive: WipeDirective) -> Result<bool> {
+        if !directive.verify(&self.repository_id) {
+            tracing::warn!(?directive, "Wipe directive with invalid signature");
+            return Ok(false);
+        }
+
+        let mut tx = self.store().db().begin_write().await?;
+        let Some(directives) = wipe_directives::insert(&mut tx, directive).await? else {
+            return Ok(false);
+        };
+        tx.commit().await?;
+
+        self.wipe_directives.send_replace(directives);
+
+        Ok(true)
+    }
+
+    /// Stores the access notice and notifies the subscribers and the peers if it's authentic and
+    /// newer than the current one. Returns whether it was accepted.
+    pub async fn receive_access_notice(&self, notice: AccessNotice) -> Result<bool> {
+        if !notice.verify(&self.repository_id) {
+            tracing::warn!(?notice, "Access notice with invalid signature");
+            return Ok(false);
+        }
+
+        // The notices have millisecond resolution, so observe the end of the millisecond to make
+        // sure the next notice issued by this replica is strictly newer.
+        time::observe(Timestamp::from_millis(notice.issued_at.saturating_add(1)));
+
+        let mut tx = self.store().db().begin_write().await?;
+        if !access_notice::set(&mut tx, &notice).await? {
+            return Ok(false);
+        }
+        tx.commit().await?;
+
+        self.access_notice.send_replace(Some(notice));
+        self.event_tx.send(Payload::AccessNotice);
+
+        Ok(true)
+    }
+
+    pub async fn store_invitation(&self, invitation: StoredInvitation) -> Result<()> {
+        let mut tx = self.store().db().begin_write().await?;
+        invitations::insert(&mut tx, invitation).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Redeems an invitation created by this replica on behalf of the given writer. Returns `None`
+    /// if this replica didn't create the invitation or the proof is invalid. See
+    /// `invitations::redeem` for details.
+    pub async fn redeem_invitation(
+        &self,
+        id: &InvitationId,
+        writer_id: PublicKey,
+        proof: &Hash,
+    ) -> Result<Option<RedeemReply>> {
+        let mut tx = self.store().db().begin_write().await?;
+        let reply = invitations::redeem(&mut tx, id, writer_id, proof).await?;
+        tx.commit().await?;
+
+        Ok(reply)
+    }
+
     pub async fn set_block_expiration(&self, duration: Option<Duration>) -> Result<()> {
         Ok(self
             .store
@@ -245,6 +466,14 @@ impl Vault {
         self.store.block_expiration().await
     }
 
+    /// Creates a receive filter whose hits and misses are reported to the repository monitor.
+    pub fn receive_filter(&self) -> ReceiveFilter {
+        self.store.receive_filter().with_counters(
+            self.monitor.receive_filter_hits.clone(),
+            self.monitor.receive_filter_misses.clone(),
+        )
+    }
+
     pub async fn approve_offers(&self, branch_id: &PublicKey) -> Result<()> {
         let mut tx = self.store().begin_read().await?;
         let mut block_ids = tx.missing_block_ids_in_branch(branch_id);
@@ -10,6 +10,8 @@ use crate::{
     db,
     error::Error,
     event::EventSender,
+    memory::MemoryBudget,
+    network::MirrorReceipt,
     progress::Progress,
     protocol::{
         test_utils::{receive_blocks, receive_nodes, Snapshot},
@@ -1094,6 +1096,49 @@ fn sync_progress(
     test_utils::run(sync_progress_case(block_count, branch_count, rng_seed))
 }
 
+#[tokio::test]
+async fn store_mirror_receipts() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let (_base_dir, vault, secrets) = setup_with_rng(&mut rng).await;
+
+    let remote_id = PublicKey::generate(&mut rng);
+    let snapshot = Snapshot::generate(&mut rng, 1);
+    receive_snapshot(&vault, remote_id, &snapshot, &secrets.write_keys).await;
+
+    let mirror_keys = Keypair::generate(&mut rng);
+    let make_receipt = |root_hash: Hash, block_count: u64| MirrorReceipt {
+        mirror_id: mirror_keys.public_key(),
+        branch_id: remote_id,
+        root_hash,
+        block_count,
+        signature: mirror_keys.sign(b"dummy"),
+    };
+
+    // Receipt for a snapshot we don't have is ignored.
+    assert!(!vault
+        .store_mirror_receipt(make_receipt(rng.gen(), 1))
+        .await
+        .unwrap());
+    assert!(vault.mirror_receipts().await.unwrap().is_empty());
+
+    // Receipt for a known snapshot is accepted but not persisted yet.
+    let receipt = make_receipt(*snapshot.root_hash(), 1);
+    assert!(vault.store_mirror_receipt(receipt.clone()).await.unwrap());
+    assert_eq!(vault.mirror_receipts().await.unwrap(), [receipt]);
+
+    // Newer receipt from the same mirror for the same branch replaces the previous one.
+    let receipt = make_receipt(*snapshot.root_hash(), 2);
+    assert!(vault.store_mirror_receipt(receipt.clone()).await.unwrap());
+    assert_eq!(vault.mirror_receipts().await.unwrap(), [receipt.clone()]);
+
+    vault
+        .mirror_receipts
+        .persist(vault.store().db())
+        .await
+        .unwrap();
+    assert_eq!(vault.mirror_receipts().await.unwrap(), [receipt]);
+}
+
 async fn sync_progress_case(block_count: usize, branch_count: usize, rng_seed: u64) {
     let mut rng = StdRng::seed_from_u64(rng_seed);
 
@@ -1174,6 +1219,7 @@ async fn setup_with_rng(rng: &mut StdRng) -> (TempDir, Vault, WriteSecrets) {
         repository_id,
         EventSender::new(1),
         pool,
+        MemoryBudget::default(),
         BlockRequestMode::Lazy,
         RepositoryMonitor::new(StateMonitor::make_root(), &NoopRecorder),
     );
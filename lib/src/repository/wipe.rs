@@ -0,0 +1,53 @@
+//! Remote wipe of lost devices.
+//!
+//! A writer publishes a signed wipe directive targeted at the writer id of a device. The directives
+//! are stored in the repository metadata (not in the user-visible directory tree) and gossiped over
+//! the links (see `network::wipe_directive`). When the target device receives it, it securely
+//! deletes its local copy of the repository (including the keys), removes the database files and
+//! notifies the subscribers with `Payload::Wiped`.
+
+use super::Shared;
+use crate::event::Payload;
+use ouisync_protocol::message::WipeDirective;
+use std::sync::Arc;
+
+/// Waits until a wipe directive targeted at this device is received and then wipes the
+/// repository.
+pub(super) async fn watch(shared: Arc<Shared>) {
+    let mut rx = shared.vault.wipe_directives.subscribe();
+    // Check also on start in case the directive has been received before the repository was
+    // last closed.
+    rx.mark_changed();
+
+    while rx.changed().await.is_ok() {
+        if !check(&shared, &rx.borrow_and_update()) {
+            continue;
+        }
+
+        tracing::warn!("Wipe directive received, wiping the repository");
+
+        let store = shared.vault.store();
+
+        if let Err(error) = store.wipe().await {
+            tracing::error!(?error, "Failed to wipe the repository");
+        }
+
+        if let Err(error) = super::delete(store.db().path()).await {
+            tracing::error!(?error, "Failed to delete the repository database");
+        }
+
+        // Notify even on failure as the repository might be partially wiped and unusable.
+        shared.vault.event_tx.send(Payload::Wiped);
+
+        break;
+    }
+}
+
+// Checks whether any of the directives is targeted at this device. The directives are verified
+// before being stored so they don't need to be verified again.
+pub(super) fn check(shared: &Shared, directives: &[WipeDirective]) -> bool {
+    let writer_id = shared.credentials.read().unwrap().writer_id;
+    directives
+        .iter()
+        .any(|directive| directive.target == writer_id)
+}
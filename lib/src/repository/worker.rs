@@ -5,15 +5,20 @@ use crate::{
     branch::Branch,
     directory::{DirectoryFallback, DirectoryLocking},
     error::{Error, Result},
-    event::{self, Event, EventScope, Lagged, Payload},
+    event::{self, Event, EventScope, Payload},
     joint_directory::{JointDirectory, JointEntryRef, MissingVersionStrategy},
     store, versioned,
 };
 use async_recursion::async_recursion;
 use futures_util::{stream, StreamExt};
-use std::{future, sync::Arc};
+use std::{future, sync::Arc, time::Duration};
 use tokio::select;
 
+// Events arriving within this window after the first one are coalesced into a single job run.
+const DEBOUNCE_WINDOW: Duration = Duration::from_millis(100);
+// Max number of events coalesced into a single job run. Caps the delay under continuous load.
+const MAX_BATCH_SIZE: usize = 64;
+
 /// Background worker to perform various jobs on the repository:
 /// - merge remote branches into the local one
 /// - remove outdated branches and snapshots
@@ -36,25 +41,30 @@ pub(super) async fn run(shared: Arc<Shared>) {
         // - Ignore events from the same scope to prevent infinite loop
         // - On `BranchChanged` interrupt and restart the current job to avoid unnecessary work on
         //   potentially outdated branches.
-        // - On any other event (including `Lagged`), let the current job run to completion and
+        // - On any other event (including `Resync`), let the current job run to completion and
         //   then restart it.
         let events =
             event::into_stream(shared.vault.event_tx.subscribe()).filter_map(move |event| {
                 future::ready(match event {
-                    Ok(Event { scope, .. }) if scope == event_scope => None,
-                    Ok(Event {
+                    Event { scope, .. } if scope == event_scope => None,
+                    Event {
                         payload: Payload::BranchChanged(_),
                         ..
-                    }) => Some(Command::Interrupt),
-                    Ok(Event {
-                        payload: Payload::BlockReceived { .. },
+                    } => Some(Command::Interrupt),
+                    Event {
+                        payload: Payload::BlockReceived { .. } | Payload::Resync,
                         ..
-                    })
-                    | Err(Lagged) => Some(Command::Wait),
-                    Ok(Event {
-                        payload: Payload::MaintenanceCompleted,
+                    } => Some(Command::Wait),
+                    Event {
+                        payload:
+                            Payload::MaintenanceCompleted
+                            | Payload::MetadataChanged
+                            | Payload::BlockCorrupted(_)
+                            | Payload::Wiped
+                            | Payload::StorageFull
+                            | Payload::AccessNotice,
                         ..
-                    }) => None,
+                    } => None,
                 })
             });
 
@@ -67,7 +77,10 @@ pub(super) async fn run(shared: Arc<Shared>) {
             }
         });
 
-        let commands = stream::select(events, unlocks);
+        let commands = stream::select(events, unlocks).inspect(|_| {
+            *shared.vault.monitor.merge_queue_depth.get() += 1;
+        });
+        let commands = utils::coalesce(commands, DEBOUNCE_WINDOW, MAX_BATCH_SIZE);
 
         utils::run(
             || maintain(&shared, local_branch.as_ref(), &unlock_tx, &prune_counter),
@@ -83,24 +96,32 @@ pub(super) async fn run(shared: Arc<Shared>) {
         // - On `BranchChanged` from this scope, let the current job run to completion and then
         //   restart it. This is because such event can only come from `merge` which does not
         //   change the set of missing and required blocks.
-        // - On any other event (including `Lagged`), let the current job run to completion and
+        // - On any other event (including `Resync`), let the current job run to completion and
         //   then restart it.
         let commands =
             event::into_stream(shared.vault.event_tx.subscribe()).filter_map(move |event| {
                 future::ready(match event {
-                    Ok(Event {
+                    Event {
                         payload: Payload::BranchChanged(_),
                         scope,
-                    }) if scope != event_scope => Some(Command::Interrupt),
-                    Ok(Event {
-                        payload: Payload::BranchChanged(_) | Payload::BlockReceived { .. },
+                    } if scope != event_scope => Some(Command::Interrupt),
+                    Event {
+                        payload:
+                            Payload::BranchChanged(_)
+                            | Payload::BlockReceived { .. }
+                            | Payload::Resync,
                         ..
-                    })
-                    | Err(Lagged) => Some(Command::Wait),
-                    Ok(Event {
-                        payload: Payload::MaintenanceCompleted,
+                    } => Some(Command::Wait),
+                    Event {
+                        payload:
+                            Payload::MaintenanceCompleted
+                            | Payload::MetadataChanged
+                            | Payload::BlockCorrupted(_)
+                            | Payload::Wiped
+                            | Payload::StorageFull
+                            | Payload::AccessNotice,
                         ..
-                    }) => None,
+                    } => None,
                 })
             });
 
@@ -122,6 +143,9 @@ async fn maintain(
 ) {
     let mut success = true;
 
+    // All the events received so far are handled by this run.
+    *shared.vault.monitor.merge_queue_depth.get() = 0;
+
     // Merge branches
     if let Some(local_branch) = local_branch {
         let job_success = shared
@@ -311,7 +335,9 @@ mod scan {
 /// Merge remote branches into the local one.
 mod merge {
     use super::*;
-    use crate::store;
+    use crate::{repository::Conflicts, store};
+    use camino::Utf8Path;
+    use std::collections::BTreeSet;
 
     pub(super) async fn run(shared: &Shared, local_branch: &Branch) -> Result<()> {
         let branches: Vec<_> = shared.load_branches().await?;
@@ -335,11 +361,18 @@ mod merge {
             }
         }
 
+        let mut conflicts = BTreeSet::new();
+
         match JointDirectory::new(Some(local_branch.clone()), roots)
-            .merge()
+            .merge_collecting_conflicts(Utf8Path::new("/"), &mut conflicts)
             .await
         {
-            Ok(_) | Err(Error::AmbiguousEntry) => Ok(()),
+            Ok(_) | Err(Error::AmbiguousEntry) => {
+                // The merge visits every directory which still has unmerged (including
+                // conflicting) entries so it finds all the conflicts.
+                *shared.conflicts.lock().unwrap() = Conflicts::new(&conflicts);
+                Ok(())
+            }
             Err(error) => Err(error),
         }
     }
@@ -373,6 +406,8 @@ mod prune {
 
         // Remove outdated branches
         for node in outdated {
+            shared.vault.store().check_cancelled()?;
+
             // Never remove local branch
             if node.proof.writer_id == writer_id {
                 continue;
@@ -409,6 +444,16 @@ mod prune {
             );
         }
 
+        // Retire the writers whose branches have been fully merged and removed so that stale
+        // copies of their branches received from other replicas are not restored.
+        let mut tx = shared.vault.store().begin_write().await?;
+        let epoch = tx.retire_writers().await?;
+        tx.commit().await?;
+
+        if let Some(epoch) = epoch {
+            tracing::debug!(epoch, "writers retired");
+        }
+
         // Remove outdated snapshots.
         for node in uptodate {
             shared
@@ -448,6 +493,8 @@ mod trash {
             shared.vault.store().block_ids(UNREACHABLE_BLOCKS_PAGE_SIZE);
 
         loop {
+            shared.vault.store().check_cancelled()?;
+
             let mut unreachable_block_ids = unreachable_block_ids_page.next().await?;
             if unreachable_block_ids.is_empty() {
                 break;
@@ -707,15 +754,20 @@ mod trash {
 }
 
 mod utils {
-    use futures_util::{Stream, StreamExt};
+    use futures_util::{stream, Stream, StreamExt};
     use std::{
         future::Future,
         pin::pin,
         sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+    use tokio::{
+        select,
+        time::{self, Instant},
     };
-    use tokio::select;
 
     /// Control how the next job should run
+    #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
     pub(super) enum Command {
         // Wait for the current job to finish before starting a new one
         Wait,
@@ -723,6 +775,43 @@ mod utils {
         Interrupt,
     }
 
+    /// Coalesces bursts of commands. A command received after a quiet period of at least
+    /// `window` is yielded immediately (together with any commands that are already queued).
+    /// Otherwise waits until `window` elapses since the previously yielded command or until
+    /// `max_batch_size` commands are received (whichever comes first). The yielded command is
+    /// `Interrupt` if any of the coalesced commands was `Interrupt`, and `Wait` otherwise.
+    pub(super) fn coalesce<Commands>(
+        commands: Commands,
+        window: Duration,
+        max_batch_size: usize,
+    ) -> impl Stream<Item = Command>
+    where
+        Commands: Stream<Item = Command>,
+    {
+        stream::unfold(
+            (Box::pin(commands), None),
+            move |(mut commands, last): (_, Option<Instant>)| async move {
+                let mut command = commands.next().await?;
+
+                let deadline = match last {
+                    Some(last) if last.elapsed() < window => last + window,
+                    _ => Instant::now(),
+                };
+
+                // NOTE: `timeout_at` polls the stream before checking the deadline so the already
+                // queued commands are coalesced even if the deadline has already passed.
+                for _ in 1..max_batch_size {
+                    match time::timeout_at(deadline, commands.next()).await {
+                        Ok(Some(next)) => command = command.max(next),
+                        Ok(None) | Err(_) => break,
+                    }
+                }
+
+                Some((command, (commands, Some(Instant::now()))))
+            },
+        )
+    }
+
     /// Runs the given job in a loop based on commands received from the given command stream.
     pub(super) async fn run<JobFn, Job, Commands>(mut job_fn: JobFn, commands: Commands)
     where
@@ -846,4 +935,52 @@ mod utils {
             self.0.fetch_add(1, Ordering::Relaxed);
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test(start_paused = true)]
+        async fn coalesce_commands() {
+            let window = Duration::from_millis(100);
+
+            // Commands within the window are coalesced, with `Interrupt` taking precedence.
+            let commands = stream::iter([Command::Wait, Command::Interrupt, Command::Wait]);
+            let output: Vec<_> = coalesce(commands, window, 8).collect().await;
+            assert_eq!(output, [Command::Interrupt]);
+
+            // The batch size is capped.
+            let commands = stream::iter([Command::Wait; 5]);
+            let output: Vec<_> = coalesce(commands, window, 2).collect().await;
+            assert_eq!(output, [Command::Wait; 3]);
+
+            // Commands outside of the window are not coalesced.
+            let commands =
+                stream::iter([Command::Wait, Command::Interrupt]).then(|command| async move {
+                    time::sleep(window * 2).await;
+                    command
+                });
+            let output: Vec<_> = coalesce(commands, window, 8).collect().await;
+            assert_eq!(output, [Command::Wait, Command::Interrupt]);
+
+            // The first command after a quiet period is not delayed.
+            let commands = stream::iter([Command::Wait]).chain(stream::pending());
+            let start = Instant::now();
+            let output = pin!(coalesce(commands, window, 8)).next().await;
+            assert_eq!(output, Some(Command::Wait));
+            assert_eq!(start.elapsed(), Duration::ZERO);
+
+            // Commands following shortly after are coalesced until the window elapses.
+            let commands = stream::iter([Command::Wait, Command::Wait, Command::Interrupt])
+                .then(|command| async move {
+                    time::sleep(window / 4).await;
+                    command
+                })
+                .chain(stream::pending());
+            let start = Instant::now();
+            let output: Vec<_> = coalesce(commands, window, 8).take(2).collect().await;
+            assert_eq!(output, [Command::Wait, Command::Interrupt]);
+            assert_eq!(start.elapsed(), window / 4 + window);
+        }
+    }
 }
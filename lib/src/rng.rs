@@ -0,0 +1,48 @@
+//! Source of randomness for the identifiers a repository generates (the writer id, the blob ids,
+//! the database id). Uses the OS generator unless one was provided with
+//! `RepositoryParams::with_rng`, which makes the repository content reproducible in tests. The
+//! keys and other secrets always come from the OS generator.
+
+use deadlock::BlockingMutex;
+use rand::{rngs::OsRng, CryptoRng, Error, RngCore};
+use std::sync::Arc;
+
+#[derive(Clone, Default)]
+pub(crate) struct SharedRng(Option<Arc<BlockingMutex<Box<dyn RngCore + Send>>>>);
+
+impl SharedRng {
+    pub fn new<G>(rng: G) -> Self
+    where
+        G: RngCore + CryptoRng + Send + 'static,
+    {
+        Self(Some(Arc::new(BlockingMutex::new(Box::new(rng)))))
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        match &self.0 {
+            Some(rng) => f(&mut **rng.lock().unwrap()),
+            None => f(&mut OsRng),
+        }
+    }
+}
+
+impl RngCore for SharedRng {
+    fn next_u32(&mut self) -> u32 {
+        self.with(|rng| rng.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.with(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.with(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+// Only constructed from a `CryptoRng` (or the OS generator).
+impl CryptoRng for SharedRng {}
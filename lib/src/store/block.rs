@@ -112,6 +112,85 @@ pub(super) async fn remove(tx: &mut db::WriteTransaction, id: &BlockId) -> Resul
     Ok(())
 }
 
+/// Loads the block with the smallest id greater than `after` (or the smallest id overall if `after`
+/// is `None`) and checks that its id matches its content. Returns the id of the block and whether
+/// the check passed or `None` if there are no more blocks.
+pub(super) async fn verify_next(
+    conn: &mut db::Connection,
+    after: Option<&BlockId>,
+) -> Result<Option<(BlockId, bool)>, Error> {
+    let query =
+        sqlx::query("SELECT id, nonce, content FROM blocks WHERE id > ? ORDER BY id LIMIT 1");
+    let query = match after {
+        Some(id) => query.bind(id),
+        // Any id is greater than an empty blob.
+        None => query.bind(&[] as &[u8]),
+    };
+
+    let Some(row) = query.fetch_optional(conn).await? else {
+        return Ok(None);
+    };
+
+    let id: BlockId = row.get(0);
+
+    let nonce: &[u8] = row.get(1);
+    let Ok(nonce) = BlockNonce::try_from(nonce) else {
+        return Ok(Some((id, false)));
+    };
+
+    let src_content: &[u8] = row.get(2);
+    if src_content.len() != BLOCK_SIZE {
+        return Ok(Some((id, false)));
+    }
+
+    let mut content = BlockContent::new();
+    content.copy_from_slice(src_content);
+
+    Ok(Some((id, BlockId::new(&content, &nonce) == id)))
+}
+
+/// Loads up to `limit` blocks with ids greater than `after` (or from the smallest id if `after` is
+/// `None`), ordered by id. Each block is returned along with the id it's stored under. The id of
+/// the returned `Block` is computed from its content so comparing the two detects corruption. Rows
+/// that can't even be decoded into a block are returned as `None`.
+pub(super) async fn load_page(
+    conn: &mut db::Connection,
+    after: Option<&BlockId>,
+    limit: u32,
+) -> Result<Vec<(BlockId, Option<Block>)>, Error> {
+    let query =
+        sqlx::query("SELECT id, nonce, content FROM blocks WHERE id > ? ORDER BY id LIMIT ?");
+    let query = match after {
+        Some(id) => query.bind(id),
+        // Any id is greater than an empty blob.
+        None => query.bind(&[] as &[u8]),
+    };
+
+    query
+        .bind(limit)
+        .fetch(conn)
+        .map_ok(|row| {
+            let id: BlockId = row.get(0);
+
+            let nonce: &[u8] = row.get(1);
+            let src_content: &[u8] = row.get(2);
+
+            let block = BlockNonce::try_from(nonce)
+                .ok()
+                .filter(|_| src_content.len() == BLOCK_SIZE)
+                .map(|nonce| {
+                    let mut content = BlockContent::new();
+                    content.copy_from_slice(src_content);
+                    Block::new(content, nonce)
+                });
+
+            (id, block)
+        })
+        .err_into()
+        .try_collect()
+        .await
+}
+
 /// Returns the total number of blocks in the store.
 pub(super) async fn count(conn: &mut db::Connection) -> Result<u64, Error> {
     Ok(db::decode_u64(
@@ -181,6 +260,47 @@ mod tests {
         write(&mut tx, &block).await.unwrap();
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verify_blocks() {
+        let (_base_dir, pool) = setup().await;
+
+        let mut blocks: Vec<Block> = (0..3).map(|_| rand::random()).collect();
+        blocks.sort_by_key(|block| block.id);
+
+        let mut tx = pool.begin_write().await.unwrap();
+
+        for block in &blocks {
+            write(&mut tx, block).await.unwrap();
+        }
+
+        // Corrupt the middle block.
+        let mut content = blocks[1].content.clone();
+        content[0] ^= 1;
+        sqlx::query("UPDATE blocks SET content = ? WHERE id = ?")
+            .bind(&content[..])
+            .bind(&blocks[1].id)
+            .execute(&mut tx)
+            .await
+            .unwrap();
+
+        let mut results = Vec::new();
+        let mut after = None;
+
+        while let Some((id, valid)) = verify_next(&mut tx, after.as_ref()).await.unwrap() {
+            results.push((id, valid));
+            after = Some(id);
+        }
+
+        assert_eq!(
+            results,
+            [
+                (blocks[0].id, true),
+                (blocks[1].id, false),
+                (blocks[2].id, true)
+            ]
+        );
+    }
+
     async fn setup() -> (TempDir, db::Pool) {
         db::create_temp().await.unwrap()
     }
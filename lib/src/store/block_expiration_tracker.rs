@@ -514,7 +514,7 @@ mod test {
             Duration::from_secs(1),
             BlockDownloadTracker::new(),
             broadcast_hash_set::channel().0,
-            Arc::new(Cache::new()),
+            Arc::new(Cache::default()),
         )
         .await
         .unwrap();
@@ -1,34 +1,35 @@
 use crate::{
     collections::HashMap,
     crypto::{sign::PublicKey, Hash},
-    protocol::{InnerNodes, LeafNodes, RootNode, Summary},
+    memory::{MemoryBudget, MemoryCategory, MemoryReservation},
+    protocol::{InnerNode, InnerNodes, LeafNode, LeafNodes, RootNode, Summary},
 };
 use deadlock::BlockingMutex;
 use lru::LruCache;
-use std::{num::NonZeroUsize, sync::Arc};
+use std::{mem, num::NonZeroUsize, sync::Arc};
 
 /// Cache for index nodes
 pub(super) struct Cache {
     roots: BlockingMutex<HashMap<PublicKey, RootNode>>,
-    inners: BlockingMutex<LruCache<Hash, InnerNodes>>,
-    leaves: BlockingMutex<LruCache<Hash, LeafNodes>>,
+    inners: BlockingMutex<Lru<InnerNodes>>,
+    leaves: BlockingMutex<Lru<LeafNodes>>,
+    memory: MemoryBudget,
 }
 
 impl Cache {
-    pub fn new() -> Self {
-        const ERROR: &str = "cache capacity must be non-zero";
-
+    pub fn new(memory: MemoryBudget) -> Self {
         Self {
             roots: BlockingMutex::new(HashMap::default()),
-            inners: BlockingMutex::new(LruCache::new(
-                NonZeroUsize::new(INNERS_CAPACITY).expect(ERROR),
-            )),
-            leaves: BlockingMutex::new(LruCache::new(
-                NonZeroUsize::new(LEAVES_CAPACITY).expect(ERROR),
-            )),
+            inners: BlockingMutex::new(Lru::new(INNERS_CAPACITY, &memory)),
+            leaves: BlockingMutex::new(Lru::new(LEAVES_CAPACITY, &memory)),
+            memory,
         }
     }
 
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        &self.memory
+    }
+
     pub fn begin(self: &Arc<Self>) -> CacheTransaction {
         CacheTransaction {
             cache: self.clone(),
@@ -41,7 +42,7 @@ impl Cache {
 
 impl Default for Cache {
     fn default() -> Self {
-        Self::new()
+        Self::new(MemoryBudget::default())
     }
 }
 
@@ -53,6 +54,10 @@ pub(super) struct CacheTransaction {
 }
 
 impl CacheTransaction {
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        self.cache.memory_budget()
+    }
+
     pub fn put_root(&mut self, node: RootNode) {
         self.roots.insert(node.proof.writer_id, Some(node));
     }
@@ -135,7 +140,7 @@ impl CacheTransaction {
             let mut inners = self.cache.inners.lock().unwrap();
 
             for (parent_hash, summaries) in self.inner_summaries {
-                if let Some(nodes) = inners.get_mut(&parent_hash) {
+                if let Some(nodes) = inners.entries.get_mut(&parent_hash) {
                     for (bucket, summary) in summaries {
                         if let Some(node) = nodes.get_mut(bucket) {
                             node.summary = summary;
@@ -147,9 +152,67 @@ impl CacheTransaction {
     }
 }
 
+/// LRU cache which accounts the (approximate) size of its entries in the memory budget and
+/// which shrinks itself when the budget is exceeded.
+struct Lru<T> {
+    entries: LruCache<Hash, T>,
+    memory: MemoryReservation,
+}
+
+impl<T: EstimateSize> Lru<T> {
+    fn new(capacity: usize, memory: &MemoryBudget) -> Self {
+        Self {
+            entries: LruCache::new(
+                NonZeroUsize::new(capacity).expect("cache capacity must be non-zero"),
+            ),
+            memory: memory.reserve(MemoryCategory::IndexCache),
+        }
+    }
+
+    fn get(&mut self, key: &Hash) -> Option<&T> {
+        self.entries.get(key)
+    }
+
+    fn put(&mut self, key: Hash, value: T) {
+        self.memory.grow(value.estimate_size());
+
+        if let Some((_, old)) = self.entries.push(key, value) {
+            self.memory.shrink(old.estimate_size());
+        }
+
+        while self.entries.len() > MIN_CAPACITY && self.memory.is_budget_exceeded() {
+            if let Some((_, old)) = self.entries.pop_lru() {
+                self.memory.shrink(old.estimate_size());
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+trait EstimateSize {
+    fn estimate_size(&self) -> usize;
+}
+
+impl EstimateSize for InnerNodes {
+    fn estimate_size(&self) -> usize {
+        mem::size_of::<Self>() + self.len() * (mem::size_of::<u8>() + mem::size_of::<InnerNode>())
+    }
+}
+
+impl EstimateSize for LeafNodes {
+    fn estimate_size(&self) -> usize {
+        mem::size_of::<Self>() + self.len() * mem::size_of::<LeafNode>()
+    }
+}
+
 // Max number of leaf node sets in the cache.
 const LEAVES_CAPACITY: usize = 1024;
 
 // Max number of inner node maps in the cache. Assuming nodes are uniformly distributed, there
 // should be roughly twice as many inner nodes than leaf nodes (for number of leaf nodes < 65536).
 const INNERS_CAPACITY: usize = 2 * LEAVES_CAPACITY;
+
+// When the memory budget is exceeded, the caches are shrunk but never below this number of entries
+// to keep the index traversal reasonably efficient.
+const MIN_CAPACITY: usize = 32;
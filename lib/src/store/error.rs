@@ -18,4 +18,6 @@ pub enum Error {
     BlockNotFound,
     #[error("block is not referenced from the index")]
     BlockNotReferenced,
+    #[error("operation cancelled because the store is closing")]
+    Cancelled,
 }
@@ -2,6 +2,7 @@
 
 use super::{
     cache::CacheTransaction,
+    check_cancelled,
     error::Error,
     inner_node,
     quota::{self, QuotaError},
@@ -16,6 +17,7 @@ use crate::{
     storage_size::StorageSize,
 };
 use sqlx::Row;
+use tokio_util::sync::CancellationToken;
 
 /// Status of receiving nodes from remote replica.
 #[derive(Debug)]
@@ -100,6 +102,7 @@ pub(super) async fn update_summaries(
 pub(super) async fn finalize(
     write_tx: &mut db::WriteTransaction,
     cache_tx: &mut CacheTransaction,
+    cancel: &CancellationToken,
     hash: Hash,
     quota: Option<StorageSize>,
 ) -> Result<ReceiveStatus, Error> {
@@ -109,6 +112,10 @@ pub(super) async fn finalize(
     // CAVEAT: the quota check would need some kind of unique lock to prevent multiple
     // concurrent checks to succeed where they would otherwise fail if ran sequentially.
 
+    // Give up if the store is closing. The transaction is then rolled back, so nothing is left
+    // half-done.
+    check_cancelled(cancel)?;
+
     let states =
         update_summaries(write_tx, cache_tx, vec![hash], UpdateSummaryReason::Other).await?;
 
@@ -126,6 +133,9 @@ pub(super) async fn finalize(
         }
 
         let approve = if let Some(quota) = quota {
+            // The quota check can take long, check for cancellation before each one.
+            check_cancelled(cancel)?;
+
             match quota::check(write_tx, &hash, quota).await {
                 Ok(()) => true,
                 Err(QuotaError::Exceeded(size)) => {
@@ -406,7 +416,7 @@ mod tests {
         let mut rng = StdRng::seed_from_u64(rng_seed);
 
         let (_base_dir, pool) = db::create_temp().await.unwrap();
-        let cache = Arc::new(Cache::new());
+        let cache = Arc::new(Cache::default());
 
         let mut write_tx = pool.begin_write().await.unwrap();
         let mut cache_tx = cache.begin();
@@ -509,7 +519,7 @@ mod tests {
     async fn summary_case(leaf_count: usize, rng_seed: u64) {
         let mut rng = StdRng::seed_from_u64(rng_seed);
         let (_base_dir, pool) = db::create_temp().await.unwrap();
-        let cache = Arc::new(Cache::new());
+        let cache = Arc::new(Cache::default());
 
         let mut write_tx = pool.begin_write().await.unwrap();
         let mut cache_tx = cache.begin();
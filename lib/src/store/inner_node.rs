@@ -2,7 +2,9 @@ use super::{error::Error, leaf_node, ReceiveFilter};
 use crate::{
     crypto::{sign::PublicKey, Hash},
     db,
-    protocol::{InnerNode, InnerNodes, LeafNodes, Summary, EMPTY_INNER_HASH, EMPTY_LEAF_HASH},
+    protocol::{
+        InnerNode, InnerNodes, LeafNodes, NodeState, Summary, EMPTY_INNER_HASH, EMPTY_LEAF_HASH,
+    },
 };
 use futures_util::{future, Stream, TryStreamExt};
 use sqlx::Row;
@@ -252,14 +254,26 @@ pub(super) async fn filter_nodes_with_new_blocks(
     let mut output = Vec::with_capacity(remote_nodes.len());
 
     for (_, remote_node) in remote_nodes {
+        let local_node = load(tx, &remote_node.hash).await?;
+        let complete = local_node.as_ref().is_some_and(|node| {
+            matches!(
+                node.summary.state,
+                NodeState::Complete | NodeState::Approved
+            )
+        });
+
         if !receive_filter
-            .check(tx, &remote_node.hash, &remote_node.summary.block_presence)
+            .check(
+                tx,
+                &remote_node.hash,
+                &remote_node.summary.block_presence,
+                complete,
+            )
             .await?
         {
             continue;
         }
 
-        let local_node = load(tx, &remote_node.hash).await?;
         let insert = if let Some(local_node) = local_node {
             local_node.summary.is_outdated(&remote_node.summary)
         } else {
@@ -13,6 +13,7 @@ mod patch;
 mod quota;
 mod receive_filter;
 mod root_node;
+mod version_vector_tombstones;
 
 #[cfg(test)]
 mod tests;
@@ -41,10 +42,12 @@ use crate::{
     },
     db,
     debug::DebugPrinter,
+    memory::MemoryBudget,
     progress::Progress,
     protocol::{
-        get_bucket, Block, BlockContent, BlockId, BlockNonce, InnerNodes, LeafNodes,
-        MultiBlockPresence, NodeState, Proof, RootNode, RootNodeFilter, Summary, INNER_LAYER_COUNT,
+        get_bucket, Block, BlockContent, BlockId, BlockNonce, InnerNodes, LeafNode, LeafNodes,
+        MultiBlockPresence, NodeState, Proof, RootNode, RootNodeFilter, SingleBlockPresence,
+        Summary, INNER_LAYER_COUNT,
     },
     storage_size::StorageSize,
     sync::broadcast_hash_set,
@@ -53,11 +56,15 @@ use futures_util::{Stream, TryStreamExt};
 use std::{
     borrow::Cow,
     ops::{Deref, DerefMut},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 // TODO: Consider creating an async `RwLock` in the `deadlock` module and use it here.
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 
 /// Data store
 #[derive(Clone)]
@@ -66,20 +73,35 @@ pub(crate) struct Store {
     cache: Arc<Cache>,
     pub client_reload_index_tx: broadcast_hash_set::Sender<PublicKey>,
     block_expiration_tracker: Arc<RwLock<Option<Arc<BlockExpirationTracker>>>>,
+    receive_filter_persistent: Arc<AtomicBool>,
+    // Cancelled on `close` to interrupt the long running operations.
+    cancel: CancellationToken,
 }
 
 impl Store {
     pub fn new(db: db::Pool) -> Self {
+        Self::with_memory_budget(db, MemoryBudget::default())
+    }
+
+    /// Creates the store whose caches are accounted in the given memory budget.
+    pub fn with_memory_budget(db: db::Pool, memory: MemoryBudget) -> Self {
         let client_reload_index_tx = broadcast_hash_set::channel().0;
 
         Self {
             db,
-            cache: Arc::new(Cache::new()),
+            cache: Arc::new(Cache::new(memory)),
             client_reload_index_tx,
             block_expiration_tracker: Arc::new(RwLock::new(None)),
+            receive_filter_persistent: Arc::new(AtomicBool::new(false)),
+            cancel: CancellationToken::new(),
         }
     }
 
+    /// Memory budget the caches of this store are accounted in.
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        self.cache.memory_budget()
+    }
+
     /// Runs data migrations. Does nothing if already at the latest version.
     pub async fn migrate_data(
         &self,
@@ -143,32 +165,41 @@ impl Store {
 
     /// Acquires a `Reader`
     pub async fn acquire_read(&self) -> Result<Reader, Error> {
+        self.check_cancelled()?;
+
         Ok(Reader {
             inner: Handle::Connection(self.db.acquire().await?),
             cache: self.cache.begin(),
             block_expiration_tracker: self.block_expiration_tracker.read().await.clone(),
+            cancel: self.cancel.clone(),
         })
     }
 
     /// Begins a `ReadTransaction`
     pub async fn begin_read(&self) -> Result<ReadTransaction, Error> {
+        self.check_cancelled()?;
+
         Ok(ReadTransaction {
             inner: Reader {
                 inner: Handle::ReadTransaction(self.db.begin_read().await?),
                 cache: self.cache.begin(),
                 block_expiration_tracker: self.block_expiration_tracker.read().await.clone(),
+                cancel: self.cancel.clone(),
             },
         })
     }
 
     /// Begins a `WriteTransaction`
     pub async fn begin_write(&self) -> Result<WriteTransaction, Error> {
+        self.check_cancelled()?;
+
         Ok(WriteTransaction {
             inner: ReadTransaction {
                 inner: Reader {
                     inner: Handle::WriteTransaction(self.db.begin_write().await?),
                     cache: self.cache.begin(),
                     block_expiration_tracker: self.block_expiration_tracker.read().await.clone(),
+                    cancel: self.cancel.clone(),
                 },
             },
             untrack_blocks: None,
@@ -210,6 +241,8 @@ impl Store {
         let mut new = Cow::Borrowed(root_node);
 
         while let Some(old) = reader.load_prev_root_node(&new).await? {
+            self.check_cancelled()?;
+
             if old.proof.version_vector == new.proof.version_vector {
                 // `new` is a draft and so we can't remove `old`. Try the previous snapshot.
                 tracing::trace!(
@@ -254,7 +287,21 @@ impl Store {
     }
 
     pub fn receive_filter(&self) -> ReceiveFilter {
-        ReceiveFilter::new(self.db.clone())
+        ReceiveFilter::new(
+            self.db.clone(),
+            self.receive_filter_persistent.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Sets whether the receive filters created from now on persist across restarts. Filters that
+    /// already exist are not affected.
+    pub fn set_receive_filter_persistent(&self, persistent: bool) {
+        self.receive_filter_persistent
+            .store(persistent, Ordering::Relaxed);
+    }
+
+    pub fn is_receive_filter_persistent(&self) -> bool {
+        self.receive_filter_persistent.load(Ordering::Relaxed)
     }
 
     /// Returns all block ids referenced from complete snapshots. The result is paginated (with
@@ -271,11 +318,52 @@ impl Store {
     }
 
     /// Closes the store. Waits until all `Reader`s and `{Read|Write}Transactions` obtained from
-    /// this store are dropped.
+    /// this store are dropped. The long running operations in progress are interrupted (see
+    /// `cancel`) so their holders drop them promptly.
     pub async fn close(&self) -> Result<(), Error> {
+        self.cancel();
         Ok(self.db.close().await?)
     }
 
+    /// Interrupts the long running operations in progress (they fail with `Error::Cancelled`) and
+    /// makes any subsequent ones fail immediately. Call this before waiting for the tasks that
+    /// might be running such operations, so the wait doesn't take until they complete.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Fails with `Error::Cancelled` if the store is being closed. Long running operations should
+    /// call this regularly.
+    pub fn check_cancelled(&self) -> Result<(), Error> {
+        check_cancelled(&self.cancel)
+    }
+
+    /// Deletes all the data in the store, including the metadata (and so the keys), overwriting
+    /// the deleted content with zeros, and then closes the store.
+    pub async fn wipe(&self) -> Result<(), Error> {
+        let mut tx = self.db.begin_write().await?;
+
+        sqlx::query("PRAGMA secure_delete = ON")
+            .execute(&mut tx)
+            .await?;
+
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        for table in tables {
+            sqlx::query(&format!("DELETE FROM \"{table}\""))
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        self.close().await
+    }
+
     /// Access the underlying database pool.
     /// TODO: make this non-public when the store extraction is complete.
     pub fn db(&self) -> &db::Pool {
@@ -288,6 +376,7 @@ pub(crate) struct Reader {
     inner: Handle,
     cache: CacheTransaction,
     block_expiration_tracker: Option<Arc<BlockExpirationTracker>>,
+    cancel: CancellationToken,
 }
 
 impl Reader {
@@ -311,6 +400,11 @@ impl Reader {
         result
     }
 
+    /// Memory budget the caches of this store are accounted in.
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        self.cache.memory_budget()
+    }
+
     /// Checks whether the block exists in the store.
     pub async fn block_exists(&mut self, id: &BlockId) -> Result<bool, Error> {
         block::exists(self.db(), id).await
@@ -325,6 +419,24 @@ impl Reader {
         leaf_node::is_missing(self.db(), id).await
     }
 
+    /// Loads the next page of blocks after `after`. See `block::load_page` for details.
+    pub async fn load_blocks_page(
+        &mut self,
+        after: Option<&BlockId>,
+        limit: u32,
+    ) -> Result<Vec<(BlockId, Option<Block>)>, Error> {
+        block::load_page(self.db(), after, limit).await
+    }
+
+    /// Verifies the integrity of the next block after `after`. See `block::verify_next` for
+    /// details.
+    pub async fn verify_next_block(
+        &mut self,
+        after: Option<&BlockId>,
+    ) -> Result<Option<(BlockId, bool)>, Error> {
+        block::verify_next(self.db(), after).await
+    }
+
     /// Returns the total number of blocks in the store.
     pub async fn count_blocks(&mut self) -> Result<u64, Error> {
         block::count(self.db()).await
@@ -393,7 +505,7 @@ impl Reader {
         root_node::load_all(self.db())
     }
 
-    #[cfg(test)]
+    /// Load all the root nodes of the given writer, from the most recent to the least recent.
     pub fn load_root_nodes_by_writer_in_any_state<'a>(
         &'a mut self,
         writer_id: &'a PublicKey,
@@ -466,6 +578,28 @@ impl ReadTransaction {
         root_node: &RootNode,
         encoded_locator: &Hash,
     ) -> Result<BlockId, Error> {
+        self.find_leaf_node_at(root_node, encoded_locator)
+            .await
+            .map(|node| node.block_id)
+    }
+
+    /// Finds the local presence of the block corresponding to the given locator in the snapshot
+    /// given by `root_node`. This only reads the index, not the block itself.
+    pub async fn find_block_presence_at(
+        &mut self,
+        root_node: &RootNode,
+        encoded_locator: &Hash,
+    ) -> Result<SingleBlockPresence, Error> {
+        self.find_leaf_node_at(root_node, encoded_locator)
+            .await
+            .map(|node| node.block_presence)
+    }
+
+    async fn find_leaf_node_at(
+        &mut self,
+        root_node: &RootNode,
+        encoded_locator: &Hash,
+    ) -> Result<LeafNode, Error> {
         // TODO: On cache miss load only the one node we actually need per layer.
 
         let mut parent_hash = root_node.proof.hash;
@@ -482,7 +616,7 @@ impl ReadTransaction {
         self.load_leaf_nodes_with_cache(&parent_hash)
             .await?
             .get(encoded_locator)
-            .map(|node| node.block_id)
+            .copied()
             .ok_or(Error::LocatorNotFound)
     }
 
@@ -570,12 +704,20 @@ impl WriteTransaction {
         Ok(())
     }
 
+    /// Retires the writers whose branches have been fully merged and removed so that stale copies
+    /// of their branches are not restored. Returns the new tombstone epoch if any writer was
+    /// retired.
+    pub async fn retire_writers(&mut self) -> Result<Option<u64>, Error> {
+        version_vector_tombstones::retire(self.db()).await
+    }
+
     /// Write a root node received from a remote replica.
     pub async fn receive_root_node(
         &mut self,
         proof: Proof,
         block_presence: MultiBlockPresence,
     ) -> Result<RootNodeReceiveStatus, Error> {
+        let cancel = self.cancel.clone();
         let (db, cache) = self.db_and_cache();
         let hash = proof.hash;
 
@@ -595,7 +737,7 @@ impl WriteTransaction {
             // Ignoring quota here because if the snapshot became complete by receiving this root
             // node it means that we already have all the other nodes and so the quota validation
             // already took place.
-            let status = index::finalize(db, cache, hash, None).await?;
+            let status = index::finalize(db, cache, &cancel, hash, None).await?;
 
             Ok(RootNodeReceiveStatus {
                 new_approved: status.new_approved,
@@ -618,6 +760,7 @@ impl WriteTransaction {
         receive_filter: &ReceiveFilter,
         quota: Option<StorageSize>,
     ) -> Result<InnerNodeReceiveStatus, Error> {
+        let cancel = self.cancel.clone();
         let (db, cache) = self.db_and_cache();
         let parent_hash = nodes.hash();
 
@@ -632,7 +775,7 @@ impl WriteTransaction {
         inner_node::inherit_summaries(db, &mut nodes).await?;
         inner_node::save_all(db, &nodes, &parent_hash).await?;
 
-        let status = index::finalize(db, cache, parent_hash, quota).await?;
+        let status = index::finalize(db, cache, &cancel, parent_hash, quota).await?;
 
         Ok(InnerNodeReceiveStatus {
             new_approved: status.new_approved,
@@ -648,6 +791,7 @@ impl WriteTransaction {
         nodes: CacheHash<LeafNodes>,
         quota: Option<StorageSize>,
     ) -> Result<LeafNodeReceiveStatus, Error> {
+        let cancel = self.cancel.clone();
         let (db, cache) = self.db_and_cache();
         let parent_hash = nodes.hash();
 
@@ -659,7 +803,7 @@ impl WriteTransaction {
 
         leaf_node::save_all(db, &nodes.into_inner().into_missing(), &parent_hash).await?;
 
-        let status = index::finalize(db, cache, parent_hash, quota).await?;
+        let status = index::finalize(db, cache, &cancel, parent_hash, quota).await?;
 
         Ok(LeafNodeReceiveStatus {
             old_approved: status.old_approved,
@@ -812,7 +956,8 @@ impl WriteTransaction {
     }
 
     // Access the underlying database transaction.
-    fn db(&mut self) -> &mut db::WriteTransaction {
+    // TODO: Make this private, but first we need to move the `metadata` module to `store`.
+    pub(crate) fn db(&mut self) -> &mut db::WriteTransaction {
         self.inner.inner.inner.as_write()
     }
 
@@ -881,3 +1026,11 @@ impl DerefMut for Handle {
         }
     }
 }
+
+fn check_cancelled(cancel: &CancellationToken) -> Result<(), Error> {
+    if cancel.is_cancelled() {
+        Err(Error::Cancelled)
+    } else {
+        Ok(())
+    }
+}
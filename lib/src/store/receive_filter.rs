@@ -1,46 +1,127 @@
 use super::error::Error;
 use crate::{crypto::Hash, db, protocol::MultiBlockPresence};
+use deadlock::BlockingMutex;
+use metrics::Counter;
 use sqlx::Row;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::{
+    collections::HashMap,
+    mem,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
 use tokio::task;
 
+/// Max number of entries in a single filter. When exceeded, the least recently used entries are
+/// evicted.
+const CAPACITY: u64 = 64 * 1024;
+
+/// Number of entries to evict at once when the capacity is exceeded, to amortize the cost of the
+/// eviction.
+const EVICTION_BATCH: u64 = CAPACITY / 8;
+
+/// Client id of the persistent filter. The non-persistent filters get ids starting from 1.
+const PERSISTENT_ID: u64 = 0;
+
 /// Filter for received nodes to avoid processing a node that doesn't contain any new information
 /// compared to the last time we received that same node.
+///
+/// Normally each client has its own filter which is cleared when the client starts or stops. A
+/// persistent filter is instead shared by all the clients of the repository and survives restarts
+/// so that re-connecting peers don't re-send the same unchanged subtrees. Because of that, a node
+/// is added to the persistent filter only once its whole subtree has been received, otherwise a
+/// subtree whose download got interrupted would be filtered out forever.
+///
+/// To avoid writing to the db on every hit, the time the entries were last used is tracked in
+/// memory and written to the db only before evicting.
 pub(crate) struct ReceiveFilter {
     id: u64,
     db: db::Pool,
+    // Approximate number of entries in this filter.
+    len: AtomicU64,
+    // Entries that were hit since the last eviction, with the time of the last hit.
+    used: BlockingMutex<HashMap<u64, u64>>,
+    hits: Counter,
+    misses: Counter,
 }
 
 impl ReceiveFilter {
-    pub(super) fn new(db: db::Pool) -> Self {
-        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    pub(super) fn new(db: db::Pool, persistent: bool) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(PERSISTENT_ID + 1);
+
+        let id = if persistent {
+            PERSISTENT_ID
+        } else {
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        };
 
         Self {
-            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            id,
             db,
+            len: AtomicU64::new(0),
+            used: BlockingMutex::new(HashMap::new()),
+            hits: Counter::noop(),
+            misses: Counter::noop(),
         }
     }
 
+    /// Count the nodes that were filtered out (hits) and those that were let through (misses).
+    pub fn with_counters(self, hits: Counter, misses: Counter) -> Self {
+        Self {
+            hits,
+            misses,
+            ..self
+        }
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.id == PERSISTENT_ID
+    }
+
     pub async fn reset(&self) -> Result<(), Error> {
-        try_remove_all(&self.db, self.id).await
+        if self.is_persistent() {
+            let mut conn = self.db.acquire().await?;
+            self.len
+                .store(count(&mut conn, self.id).await?, Ordering::Relaxed);
+            Ok(())
+        } else {
+            try_remove_all(&self.db, self.id).await?;
+            self.len.store(0, Ordering::Relaxed);
+            Ok(())
+        }
     }
 
+    /// Checks whether the node with the given hash and block presence should be processed
+    /// (returns `true`) or filtered out (returns `false`). `complete` is whether the local copy of
+    /// the node has its whole subtree present.
     pub async fn check(
         &self,
         tx: &mut db::WriteTransaction,
         hash: &Hash,
         new_presence: &MultiBlockPresence,
+        complete: bool,
     ) -> Result<bool, Error> {
+        let now = timestamp();
+
         if let Some((row_id, old_presence)) = load(tx, self.id, hash).await? {
             if !old_presence.is_outdated(new_presence) {
+                self.used.lock().unwrap().insert(row_id, now);
+                self.hits.increment(1);
                 return Ok(false);
             }
 
-            update(tx, row_id, new_presence).await?;
-        } else {
-            insert(tx, self.id, hash, new_presence).await?;
+            update(tx, row_id, new_presence, now).await?;
+        } else if complete || !self.is_persistent() {
+            insert(tx, self.id, hash, new_presence, now).await?;
+
+            if self.len.fetch_add(1, Ordering::Relaxed) + 1 > CAPACITY {
+                let used = mem::take(&mut *self.used.lock().unwrap());
+                let len = evict(tx, self.id, CAPACITY - EVICTION_BATCH, used).await?;
+                self.len.store(len, Ordering::Relaxed);
+            }
         }
 
+        self.misses.increment(1);
+
         Ok(true)
     }
 
@@ -56,7 +137,9 @@ impl ReceiveFilter {
 
 impl Drop for ReceiveFilter {
     fn drop(&mut self) {
-        task::spawn(remove_all(self.db.clone(), self.id));
+        if !self.is_persistent() {
+            task::spawn(remove_all(self.db.clone(), self.id));
+        }
     }
 }
 
@@ -100,15 +183,17 @@ async fn insert(
     client_id: u64,
     hash: &Hash,
     presence: &MultiBlockPresence,
+    now: u64,
 ) -> Result<(), Error> {
     sqlx::query(
         "INSERT INTO received_nodes
-         (client_id, hash, block_presence)
-         VALUES (?, ?, ?)",
+         (client_id, hash, block_presence, last_used)
+         VALUES (?, ?, ?, ?)",
     )
     .bind(db::encode_u64(client_id))
     .bind(hash)
     .bind(presence)
+    .bind(db::encode_u64(now))
     .execute(conn)
     .await?;
 
@@ -119,13 +204,15 @@ async fn update(
     conn: &mut db::Connection,
     row_id: u64,
     presence: &MultiBlockPresence,
+    now: u64,
 ) -> Result<(), Error> {
     sqlx::query(
         "UPDATE received_nodes
-         SET block_presence = ?
+         SET block_presence = ?, last_used = ?
          WHERE rowid = ?",
     )
     .bind(presence)
+    .bind(db::encode_u64(now))
     .bind(db::encode_u64(row_id))
     .execute(conn)
     .await?;
@@ -133,6 +220,64 @@ async fn update(
     Ok(())
 }
 
+async fn touch(conn: &mut db::Connection, row_id: u64, now: u64) -> Result<(), Error> {
+    sqlx::query("UPDATE received_nodes SET last_used = ? WHERE rowid = ?")
+        .bind(db::encode_u64(now))
+        .bind(db::encode_u64(row_id))
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+async fn count(conn: &mut db::Connection, client_id: u64) -> Result<u64, Error> {
+    Ok(db::decode_u64(
+        sqlx::query("SELECT COUNT(*) FROM received_nodes WHERE client_id = ?")
+            .bind(db::encode_u64(client_id))
+            .fetch_one(conn)
+            .await?
+            .get(0),
+    ))
+}
+
+/// Evicts the least recently used entries so that at most `keep` remain. `used` are the entries
+/// that were used since the last eviction. Returns the number of remaining entries.
+async fn evict(
+    conn: &mut db::Connection,
+    client_id: u64,
+    keep: u64,
+    used: HashMap<u64, u64>,
+) -> Result<u64, Error> {
+    for (row_id, last_used) in used {
+        touch(conn, row_id, last_used).await?;
+    }
+
+    sqlx::query(
+        "DELETE FROM received_nodes
+         WHERE rowid IN (
+             SELECT rowid FROM received_nodes
+             WHERE client_id = ?
+             ORDER BY last_used DESC
+             LIMIT -1 OFFSET ?
+         )",
+    )
+    .bind(db::encode_u64(client_id))
+    .bind(db::encode_u64(keep))
+    .execute(&mut *conn)
+    .await?;
+
+    count(conn, client_id).await
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
 async fn remove_all(pool: db::Pool, client_id: u64) {
     if let Err(error) = try_remove_all(&pool, client_id).await {
         tracing::error!(
@@ -1,4 +1,4 @@
-use super::error::Error;
+use super::{error::Error, version_vector_tombstones};
 use crate::{
     crypto::{sign::PublicKey, Hash},
     db,
@@ -528,6 +528,16 @@ pub(super) async fn decide_action(
         request_children: true,
     };
 
+    // Snapshot of a retired writer (whose branch has been merged and removed) that contains
+    // nothing new from that writer is just a stale copy of the removed branch - discard it so the
+    // branch is not restored.
+    let tombstones = version_vector_tombstones::load(tx).await?;
+    if tombstones.is_stale(&new_proof.writer_id, &new_proof.version_vector) {
+        action.insert = false;
+        action.request_children = false;
+        return Ok(action);
+    }
+
     let mut old_nodes = load_all_in_any_state(tx);
     while let Some(old_node) = old_nodes.try_next().await? {
         match new_proof
@@ -639,7 +649,6 @@ pub(super) async fn debug_print(conn: &mut db::Connection, printer: DebugPrinter
 
 /// Returns a stream of all root nodes corresponding to the specified writer ordered from the
 /// most recent to the least recent.
-#[cfg(test)]
 pub(super) fn load_all_by_writer_in_any_state<'a>(
     conn: &'a mut db::Connection,
     writer_id: &'a PublicKey,
@@ -3,6 +3,7 @@ use crate::{
     crypto::{cipher::SecretKey, sign::Keypair},
     protocol::{Bump, Locator, SingleBlockPresence, EMPTY_INNER_HASH},
     test_utils,
+    version_vector::VersionVector,
 };
 use proptest::{arbitrary::any, collection::vec};
 use rand::{
@@ -445,6 +446,172 @@ async fn prune_case(ops: Vec<PruneTestOp>, rng_seed: u64) {
     }
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn persistent_receive_filter() {
+    let (_base_dir, store) = setup().await;
+
+    let hash = rand::random::<[u8; 32]>().hash();
+    let presence = MultiBlockPresence::Full;
+
+    let check = |filter: ReceiveFilter, complete| {
+        let store = store.clone();
+        async move {
+            let mut tx = store.db().begin_write().await.unwrap();
+            let result = filter
+                .check(&mut tx, &hash, &presence, complete)
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+            result
+        }
+    };
+
+    // Non-persistent filters don't share anything.
+    assert!(!store.receive_filter().is_persistent());
+    assert!(check(store.receive_filter(), true).await);
+    assert!(check(store.receive_filter(), true).await);
+
+    store.set_receive_filter_persistent(true);
+
+    // Nodes whose subtree is not complete yet are not remembered, so an interrupted download of
+    // the subtree can be resumed later.
+    assert!(check(store.receive_filter(), false).await);
+    assert!(check(store.receive_filter(), false).await);
+
+    // Persistent filters remember the complete nodes even after being dropped.
+    assert!(check(store.receive_filter(), true).await);
+    assert!(!check(store.receive_filter(), true).await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retire_writers() {
+    let (_base_dir, store) = setup().await;
+    let write_keys = Keypair::random();
+
+    let retired_id = PublicKey::random();
+    let branch_id_0 = PublicKey::random();
+    let branch_id_1 = PublicKey::random();
+
+    // Linking a block makes the snapshot complete (and thus approved).
+    let bump = |branch_id, bump| {
+        let store = store.clone();
+        let write_keys = &write_keys;
+
+        async move {
+            let mut tx = store.begin_write().await.unwrap();
+            let mut changeset = Changeset::new();
+            changeset.link_block(
+                random_head_locator().encode(&SecretKey::random()),
+                rand::random(),
+                SingleBlockPresence::Present,
+            );
+            changeset.bump(bump);
+            changeset.force_bump(true);
+            changeset
+                .apply(&mut tx, &branch_id, &write_keys)
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+        }
+    };
+
+    bump(retired_id, Bump::increment(retired_id)).await;
+    bump(branch_id_0, Bump::increment(branch_id_0)).await;
+    bump(branch_id_1, Bump::increment(branch_id_1)).await;
+
+    // Merge the retired branch into the others.
+    bump(branch_id_0, Bump::Merge(vv![retired_id => 1])).await;
+    bump(branch_id_1, Bump::Merge(vv![retired_id => 1])).await;
+
+    // The branch still exists.
+    let mut tx = store.begin_write().await.unwrap();
+    assert_eq!(tx.retire_writers().await.unwrap(), None);
+
+    let node = tx
+        .load_root_node(&retired_id, RootNodeFilter::Any)
+        .await
+        .unwrap();
+    tx.remove_branch(&node).await.unwrap();
+
+    assert_eq!(tx.retire_writers().await.unwrap(), Some(1));
+    tx.commit().await.unwrap();
+
+    // Stale copy of the removed branch received from another replica is discarded.
+    let proof = Proof::new(
+        retired_id,
+        vv![retired_id => 1],
+        *EMPTY_INNER_HASH,
+        &write_keys,
+    );
+    let mut tx = store.begin_write().await.unwrap();
+    let status = tx
+        .receive_root_node(proof, MultiBlockPresence::Full)
+        .await
+        .unwrap();
+    assert!(!status.new_snapshot);
+    assert!(!status.request_children);
+
+    // Newer snapshot of the writer is accepted (the writer is active again).
+    let proof = Proof::new(
+        retired_id,
+        vv![retired_id => 2],
+        *EMPTY_INNER_HASH,
+        &write_keys,
+    );
+    let status = tx
+        .receive_root_node(proof, MultiBlockPresence::Full)
+        .await
+        .unwrap();
+    assert!(status.new_snapshot);
+    tx.commit().await.unwrap();
+
+    // Retiring doesn't change the version vectors of new snapshots (they are signed and compared
+    // with the ones from the other replicas).
+    bump(branch_id_0, Bump::increment(branch_id_0)).await;
+
+    let vv = store
+        .acquire_read()
+        .await
+        .unwrap()
+        .load_root_node(&branch_id_0, RootNodeFilter::Any)
+        .await
+        .unwrap()
+        .proof
+        .into_version_vector();
+    assert_eq!(vv.get(&retired_id), 1);
+    assert_eq!(vv.get(&branch_id_0), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn close_interrupts_receive() {
+    let (_base_dir, store) = setup().await;
+    let write_keys = Keypair::random();
+    let branch_id = write_keys.public_key();
+
+    let mut tx = store.begin_write().await.unwrap();
+
+    let close = tokio::spawn({
+        let store = store.clone();
+        async move { store.close().await }
+    });
+    store.cancel.cancelled().await;
+
+    let proof = Proof::new(
+        branch_id,
+        VersionVector::first(branch_id),
+        *EMPTY_INNER_HASH,
+        &write_keys,
+    );
+    assert!(matches!(
+        tx.receive_root_node(proof, MultiBlockPresence::None).await,
+        Err(Error::Cancelled)
+    ));
+
+    // Dropping the interrupted transaction lets the store close.
+    drop(tx);
+    close.await.unwrap().unwrap();
+}
+
 async fn setup() -> (TempDir, Store) {
     let (temp_dir, pool) = db::create_temp().await.unwrap();
     let store = Store::new(pool);
@@ -0,0 +1,131 @@
+//! Retiring of writers whose branches have been fully merged and removed. The tombstones are
+//! local only - they don't affect the version vectors signed into the proofs.
+
+use super::{error::Error, root_node};
+use crate::{crypto::sign::PublicKey, db, version_vector::VersionVector};
+use futures_util::TryStreamExt;
+use sqlx::Row;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Final versions of the retired writers, that is, writers whose branches have been fully merged
+/// into all the other branches and then removed. The epoch is incremented every time new writers
+/// are retired.
+///
+/// NOTE: The tombstones are local to a replica. They are never applied to the version vectors
+/// signed into the proofs or compared with the ones received from other replicas, because those
+/// replicas might have retired a different set of writers (or none).
+#[derive(Default, Clone, Debug)]
+pub(super) struct Tombstones {
+    epoch: u64,
+    versions: BTreeMap<PublicKey, u64>,
+}
+
+impl Tombstones {
+    pub(super) fn new(epoch: u64, versions: BTreeMap<PublicKey, u64>) -> Self {
+        Self { epoch, versions }
+    }
+
+    pub(super) fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Version the given writer has been retired at or 0 if it's not retired.
+    pub(super) fn get(&self, writer_id: &PublicKey) -> u64 {
+        self.versions.get(writer_id).copied().unwrap_or(0)
+    }
+
+    /// Is a snapshot with the given version vector in the branch of `writer_id` just a stale copy
+    /// of a retired branch (that is, it has nothing from the writer newer than the version it was
+    /// retired at)?
+    pub(super) fn is_stale(&self, writer_id: &PublicKey, vv: &VersionVector) -> bool {
+        self.versions
+            .get(writer_id)
+            .is_some_and(|version| vv.get(writer_id) <= *version)
+    }
+}
+
+/// Loads the final versions of all the retired writers.
+pub(super) async fn load(conn: &mut db::Connection) -> Result<Tombstones, Error> {
+    let epoch: i64 = sqlx::query("SELECT COALESCE(MAX(epoch), 0) FROM version_vector_tombstones")
+        .fetch_one(&mut *conn)
+        .await?
+        .get(0);
+
+    let versions = sqlx::query("SELECT writer_id, version FROM version_vector_tombstones")
+        .fetch(conn)
+        .map_ok(|row| (row.get(0), row.get::<i64, _>(1) as u64))
+        .try_collect()
+        .await?;
+
+    Ok(Tombstones::new(epoch as u64, versions))
+}
+
+/// Retires the writers that no longer have any branch but whose final version is included in the
+/// latest snapshots of all the remaining branches (that is, their branches have been fully merged
+/// and then removed). Returns the new epoch if any writer was retired.
+pub(super) async fn retire(tx: &mut db::WriteTransaction) -> Result<Option<u64>, Error> {
+    let tombstones = load(tx).await?;
+
+    let vvs: Vec<VersionVector> = root_node::load_all(tx)
+        .map_ok(|node| node.proof.into_version_vector())
+        .try_collect()
+        .await?;
+
+    let branch_ids: BTreeSet<PublicKey> = root_node::load_all_in_any_state(tx)
+        .map_ok(|node| node.proof.writer_id)
+        .try_collect()
+        .await?;
+
+    let Some(first) = vvs.first() else {
+        return Ok(None);
+    };
+
+    let retired: BTreeMap<_, _> = first
+        .iter()
+        .map(|(writer_id, version)| (*writer_id, *version))
+        .filter(|(writer_id, _)| !branch_ids.contains(writer_id))
+        .filter(|(writer_id, version)| *version > tombstones.get(writer_id))
+        .filter(|(writer_id, version)| vvs.iter().all(|vv| vv.get(writer_id) == *version))
+        .collect();
+
+    if retired.is_empty() {
+        return Ok(None);
+    }
+
+    let epoch = tombstones.epoch() + 1;
+
+    for (writer_id, version) in retired {
+        sqlx::query(
+            "INSERT INTO version_vector_tombstones (writer_id, version, epoch)
+             VALUES (?, ?, ?)
+             ON CONFLICT (writer_id) DO UPDATE SET version = excluded.version, epoch = excluded.epoch",
+        )
+        .bind(&writer_id)
+        .bind(version as i64)
+        .bind(epoch as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(Some(epoch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tombstones_is_stale() {
+        let active = PublicKey::random();
+        let retired = PublicKey::random();
+
+        let tombstones = Tombstones::new(1, [(retired, 5)].into_iter().collect());
+
+        assert!(tombstones.is_stale(&retired, &vv![retired => 5, active => 2]));
+        assert!(tombstones.is_stale(&retired, &vv![retired => 3]));
+        // The writer is active again.
+        assert!(!tombstones.is_stale(&retired, &vv![retired => 6]));
+        // Not retired.
+        assert!(!tombstones.is_stale(&active, &vv![active => 1]));
+    }
+}
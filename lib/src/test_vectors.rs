@@ -0,0 +1,255 @@
+//! Deterministic test vectors of the formats that other implementations need to be compatible
+//! with: share tokens, snapshot proofs, serialized directories, encrypted blocks and wire messages.
+//! The vectors of the formats defined in `ouisync-protocol` come from that crate (see
+//! [`ouisync_protocol::test_vectors`]), this module adds the directories and the encrypted blocks.
+//!
+//! The vectors returned by [`generate`] are checked into the repository (`lib/tests/vectors.json`)
+//! and a test makes sure they stay up to date. [`validate`] checks that a vector agrees with this
+//! implementation, that is, that its encoded form decodes into the value described by its decoded
+//! fields and that the value encodes back into exactly the same encoded form. An independent
+//! implementation can test its decoders against the checked in vectors and its encoders by
+//! validating vectors it produced itself.
+//!
+//! NOTE: The wire messages are encoded differently when the `analyze-protocol` feature is enabled.
+//! The message vectors describe the encoding without it.
+
+pub use ouisync_protocol::test_vectors::{TestVector, TestVectorKind, ValidationError};
+
+use crate::{
+    access_control::WriteSecrets,
+    blob::{self, BlobId, HEADER_SIZE},
+    crypto::cipher,
+    directory::{Content as DirectoryContent, EntryData, EntryTombstoneData, TombstoneCause},
+    protocol::{Block, BlockContent, BlockNonce, Locator, BLOCK_SIZE},
+    version_vector::VersionVector,
+};
+use ouisync_protocol::test_vectors::{self, describe_version_vector, keypair};
+use std::collections::BTreeMap;
+
+/// Generates the test vectors. The output is always the same unless some of the formats change.
+pub fn generate() -> Vec<TestVector> {
+    let mut vectors = Vec::new();
+    vectors.extend(test_vectors::share_tokens());
+    vectors.extend(test_vectors::proofs());
+    vectors.push(directory());
+    vectors.push(block());
+    vectors.extend(test_vectors::messages());
+    vectors
+}
+
+/// Checks that the test vector agrees with this implementation.
+pub fn validate(vector: &TestVector) -> Result<(), ValidationError> {
+    let (decoded, encoded) = match vector.kind {
+        TestVectorKind::Directory => {
+            let content = hex::decode(&vector.encoded)
+                .ok()
+                .and_then(|content| DirectoryContent::deserialize(&content).ok())
+                .ok_or(ValidationError::Malformed)?;
+
+            (
+                describe_directory(&content),
+                hex::encode(content.serialize(false)),
+            )
+        }
+        TestVectorKind::Block => {
+            // The read key, the locator and the nonce are needed to decrypt the block.
+            let read_key: [u8; cipher::SecretKey::SIZE] = hex_field(vector, "read_key")?;
+            let read_key = cipher::SecretKey::try_from(&read_key[..])
+                .map_err(|_| ValidationError::DecodedMismatch)?;
+            let blob_id = BlobId::from(hex_field::<{ BlobId::SIZE }>(vector, "blob_id")?);
+            let number: u32 = vector
+                .decoded
+                .get("block_number")
+                .and_then(|number| number.parse().ok())
+                .ok_or(ValidationError::DecodedMismatch)?;
+            let nonce: BlockNonce = hex_field(vector, "nonce")?;
+
+            let ciphertext =
+                hex::decode(&vector.encoded).map_err(|_| ValidationError::Malformed)?;
+            if ciphertext.len() != BLOCK_SIZE {
+                return Err(ValidationError::Malformed);
+            }
+
+            let mut plaintext = BlockContent::new();
+            plaintext.copy_from_slice(&ciphertext);
+            blob::decrypt_block(&read_key, &nonce, &mut plaintext);
+
+            let locator = Locator::head(blob_id).nth(number);
+            let block = encrypt_block(&read_key, &locator, &plaintext);
+
+            (
+                describe_block(&read_key, &locator, &plaintext, &block),
+                hex::encode(&block.content[..]),
+            )
+        }
+        TestVectorKind::ShareToken | TestVectorKind::Proof | TestVectorKind::Message => {
+            return test_vectors::validate(vector)
+        }
+    };
+
+    test_vectors::check(vector, &decoded, &encoded)
+}
+
+fn directory() -> TestVector {
+    let writer_id = keypair(2).public_key();
+    let mut content = DirectoryContent::empty();
+
+    for (name, data) in [
+        (
+            "file.txt",
+            EntryData::file(
+                BlobId::from([0x11; BlobId::SIZE]),
+                VersionVector::first(writer_id),
+            ),
+        ),
+        (
+            "subdirectory",
+            EntryData::directory(
+                BlobId::from([0x22; BlobId::SIZE]),
+                [(writer_id, 2)].into_iter().collect(),
+            ),
+        ),
+        (
+            "removed.txt",
+            EntryData::Tombstone(EntryTombstoneData::new(
+                TombstoneCause::Removed,
+                [(writer_id, 3)].into_iter().collect(),
+            )),
+        ),
+        (
+            "moved.txt",
+            EntryData::Tombstone(EntryTombstoneData::new(
+                TombstoneCause::Moved,
+                VersionVector::first(writer_id),
+            )),
+        ),
+    ] {
+        // The names are unique so the insertion can't fail.
+        content.insert(name.to_owned(), data).ok();
+    }
+
+    TestVector {
+        kind: TestVectorKind::Directory,
+        name: "entries of all types".to_owned(),
+        decoded: describe_directory(&content),
+        encoded: hex::encode(content.serialize(false)),
+    }
+}
+
+fn block() -> TestVector {
+    let read_key = WriteSecrets::from(keypair(1)).read_key;
+    let locator = Locator::head(BlobId::from([0x11; BlobId::SIZE]));
+
+    // Head block of a blob: the blob length followed by the blob content.
+    let data = b"hello, world!";
+    let mut plaintext = BlockContent::new();
+    plaintext.write_u64(0, data.len() as u64);
+    plaintext.write(HEADER_SIZE, data);
+
+    let block = encrypt_block(&read_key, &locator, &plaintext);
+
+    TestVector {
+        kind: TestVectorKind::Block,
+        name: "head block".to_owned(),
+        decoded: describe_block(&read_key, &locator, &plaintext, &block),
+        encoded: hex::encode(&block.content[..]),
+    }
+}
+
+fn describe_directory(content: &DirectoryContent) -> BTreeMap<String, String> {
+    content
+        .iter()
+        .map(|(name, data)| {
+            let value = match data {
+                EntryData::File(data) => format!(
+                    "file {} {}",
+                    hex::encode(data.blob_id),
+                    describe_version_vector(&data.version_vector)
+                ),
+                EntryData::Directory(data) => format!(
+                    "directory {} {}",
+                    hex::encode(data.blob_id),
+                    describe_version_vector(&data.version_vector)
+                ),
+                EntryData::Tombstone(data) => format!(
+                    "tombstone {} {}",
+                    match data.cause {
+                        TombstoneCause::Removed => "removed",
+                        TombstoneCause::Moved => "moved",
+                    },
+                    describe_version_vector(&data.version_vector)
+                ),
+            };
+
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+fn describe_block(
+    read_key: &cipher::SecretKey,
+    locator: &Locator,
+    plaintext: &BlockContent,
+    block: &Block,
+) -> BTreeMap<String, String> {
+    let len = plaintext
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map(|index| index + 1)
+        .unwrap_or(0);
+
+    let mut fields = BTreeMap::new();
+    fields.insert("read_key".to_owned(), hex::encode(read_key.as_ref()));
+    fields.insert("blob_id".to_owned(), hex::encode(locator.blob_id()));
+    fields.insert("block_number".to_owned(), locator.number().to_string());
+    fields.insert("locator".to_owned(), hex::encode(locator.encode(read_key)));
+    fields.insert("plaintext".to_owned(), hex::encode(&plaintext[..len]));
+    fields.insert("nonce".to_owned(), hex::encode(block.nonce));
+    fields.insert("id".to_owned(), hex::encode(block.id));
+    fields
+}
+
+fn encrypt_block(
+    read_key: &cipher::SecretKey,
+    locator: &Locator,
+    plaintext: &BlockContent,
+) -> Block {
+    let nonce = blob::make_block_nonce(locator, plaintext, read_key);
+    let mut content = plaintext.clone();
+    blob::encrypt_block(read_key, &nonce, &mut content);
+
+    Block::new(content, nonce)
+}
+
+fn hex_field<const N: usize>(vector: &TestVector, name: &str) -> Result<[u8; N], ValidationError> {
+    vector
+        .decoded
+        .get(name)
+        .and_then(|value| hex::decode(value).ok())
+        .and_then(|value| value.try_into().ok())
+        .ok_or(ValidationError::DecodedMismatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tampered_vectors_are_invalid() {
+        let mut directory = directory();
+        directory.encoded.truncate(directory.encoded.len() - 2);
+        assert!(matches!(
+            validate(&directory),
+            Err(ValidationError::Malformed)
+        ));
+
+        let mut block = block();
+        block
+            .decoded
+            .insert("nonce".to_owned(), hex::encode([0xcd; 32]));
+        assert!(matches!(
+            validate(&block),
+            Err(ValidationError::DecodedMismatch)
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+//! Hybrid logical clock. Its timestamps follow the wall clock but they are strictly increasing on
+//! this device even if the wall clock goes back and they are greater than any timestamp received
+//! from the other replicas (see `observe`), so of two causally related events the later one always
+//! has the greater timestamp regardless of the clock skew between the devices. Used for the
+//! timestamps of the directory entries and of the access notices.
+//!
+//! The clock is seeded from the wall clock and advanced by the timestamps found in the snapshots
+//! received from the peers (when their directories are loaded) and in the received access notices.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const COUNTER_BITS: u32 = 16;
+
+// Timestamps from the other replicas further in the future than this are not observed, so a peer
+// with a badly wrong clock can't drag our clock along.
+const MAX_DRIFT: Duration = Duration::from_secs(24 * 60 * 60);
+
+static LAST: AtomicU64 = AtomicU64::new(0);
+
+/// Timestamp of the hybrid logical clock: the wall clock time in milliseconds since the Unix epoch
+/// in the high bits and a counter, which orders the timestamps taken within the same millisecond,
+/// in the low bits. Zero means unknown.
+#[derive(
+    Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const ZERO: Self = Self(0);
+
+    /// Returns a new timestamp, greater than all the ones returned or observed before.
+    pub fn now() -> Self {
+        let physical = physical_now();
+        let mut last = LAST.load(Ordering::Relaxed);
+
+        loop {
+            let next = physical.max(last.saturating_add(1));
+
+            match LAST.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return Self(next),
+                Err(actual) => last = actual,
+            }
+        }
+    }
+
+    /// Timestamp at the start of the given millisecond since the Unix epoch.
+    pub fn from_millis(millis: u64) -> Self {
+        Self(millis.saturating_mul(1 << COUNTER_BITS))
+    }
+
+    /// Milliseconds since the Unix epoch of the wall clock time the timestamp was taken at.
+    pub fn as_millis(&self) -> u64 {
+        self.0 >> COUNTER_BITS
+    }
+
+    /// Wall clock time the timestamp was taken at.
+    pub fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.as_millis())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Makes sure the subsequent timestamps are greater than `timestamp` (one received from another
+/// replica) unless it's too far in the future.
+pub(crate) fn observe(timestamp: Timestamp) {
+    if timestamp.0 <= physical_now().saturating_add(from_duration(MAX_DRIFT)) {
+        LAST.fetch_max(timestamp.0, Ordering::Relaxed);
+    }
+}
+
+fn physical_now() -> u64 {
+    from_duration(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+    )
+}
+
+fn from_duration(duration: Duration) -> u64 {
+    Timestamp::from_millis(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monotonic() {
+        let a = Timestamp::now();
+        let b = Timestamp::now();
+        assert!(b > a);
+
+        let ahead = Timestamp(b.0 + 1000);
+        observe(ahead);
+        assert!(Timestamp::now() > ahead);
+
+        // Too far in the future.
+        let far = Timestamp(physical_now() + 2 * from_duration(MAX_DRIFT));
+        observe(far);
+        assert!(Timestamp::now() < far);
+
+        let time = Timestamp::now().to_system_time();
+        let drift = time.duration_since(SystemTime::now()).unwrap_or_default();
+        assert!(drift < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn millis() {
+        let timestamp = Timestamp::from_millis(1234);
+        assert_eq!(timestamp.as_millis(), 1234);
+        assert!(Timestamp(timestamp.0 + 1) > timestamp);
+        assert_eq!(Timestamp(timestamp.0 + 1).as_millis(), 1234);
+    }
+}
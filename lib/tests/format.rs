@@ -0,0 +1,34 @@
+//! Tests of the test vectors of the protocol and on-disk formats.
+
+// The wire messages are encoded differently with this feature.
+#![cfg(not(feature = "analyze-protocol"))]
+
+use ouisync::test_vectors::{self, TestVector};
+use std::{env, fs, path::PathBuf};
+
+/// Fails when any of the formats changes. If the change is intended, rerun this test with env
+/// variable `TEST_VECTORS_UPDATE=1`, then check the updated vectors into the source control.
+#[test]
+fn vectors() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vectors.json");
+    let generated = test_vectors::generate();
+
+    if env::var("TEST_VECTORS_UPDATE").is_ok() {
+        let content = serde_json::to_string_pretty(&generated).unwrap() + "\n";
+        fs::write(&path, content).unwrap();
+    }
+
+    let stored: Vec<TestVector> = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+
+    for vector in &stored {
+        if let Err(error) = test_vectors::validate(vector) {
+            panic!("{:?} {:?}: {}", vector.kind, vector.name, error);
+        }
+    }
+
+    assert_eq!(
+        stored, generated,
+        "The test vectors are outdated. Rerun this test with env variable \
+         `TEST_VECTORS_UPDATE=1`, then check the updated vectors into the source control."
+    );
+}
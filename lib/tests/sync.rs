@@ -1257,6 +1257,70 @@ fn file_progress() {
     });
 }
 
+#[test]
+fn redeem_invitation() {
+    let mut env = Env::new();
+    let (token_tx, _) = broadcast::channel(1);
+    let (redeemed_tx, _) = broadcast::channel(1);
+    let (done_tx, mut done_rx) = mpsc::channel(1);
+
+    env.actor("inviter", {
+        let token_tx = token_tx.clone();
+
+        async move {
+            let (_network, repo, _reg) = actor::setup().await;
+
+            let token = repo.create_invitation(AccessMode::Write).await.unwrap();
+            assert_eq!(token.access_mode(), AccessMode::Blind);
+            token_tx.send(token).unwrap();
+
+            done_rx.recv().await.unwrap();
+        }
+    });
+
+    env.actor("recipient", {
+        let mut token_rx = token_tx.subscribe();
+        let redeemed_tx = redeemed_tx.clone();
+
+        async move {
+            let network = actor::create_network(Proto::Tcp).await;
+            let repo = actor::create_repo_with_mode(DEFAULT_REPO, AccessMode::Blind).await;
+            let _reg = network.register(repo.handle()).await;
+            network.add_user_provided_peer(&actor::lookup_addr("inviter").await);
+
+            let token = token_rx.recv().await.unwrap();
+            assert_eq!(
+                repo.redeem_invitation(&token).await.unwrap(),
+                AccessMode::Write
+            );
+            assert_eq!(repo.access_mode(), AccessMode::Write);
+
+            redeemed_tx.send(token).unwrap();
+        }
+    });
+
+    env.actor("intruder", {
+        let mut redeemed_rx = redeemed_tx.subscribe();
+
+        async move {
+            let network = actor::create_network(Proto::Tcp).await;
+            let repo = actor::create_repo_with_mode(DEFAULT_REPO, AccessMode::Blind).await;
+            let _reg = network.register(repo.handle()).await;
+            network.add_user_provided_peer(&actor::lookup_addr("inviter").await);
+
+            // Redeeming an already redeemed invitation is rejected.
+            let token = redeemed_rx.recv().await.unwrap();
+            assert_matches!(
+                repo.redeem_invitation(&token).await,
+                Err(Error::PermissionDenied)
+            );
+            assert_eq!(repo.access_mode(), AccessMode::Blind);
+
+            done_tx.send(()).await.unwrap();
+        }
+    });
+}
+
 #[instrument(skip(repo))]
 async fn expect_local_directory_exists(repo: &Repository, path: &str) {
     common::eventually(repo, || async {
@@ -24,6 +24,31 @@ const MAX_IDLE_TIMEOUT_MS: u32 = 3 * KEEP_ALIVE_INTERVAL_MS + 2_000;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Keep-alive parameters of the QUIC connections.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct KeepAlive {
+    /// How often to send keep-alive packets when the connection is otherwise idle.
+    pub interval: Duration,
+    /// Time of inactivity after which the connection is closed.
+    pub idle_timeout: Duration,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(KEEP_ALIVE_INTERVAL_MS.into()),
+            idle_timeout: Duration::from_millis(MAX_IDLE_TIMEOUT_MS.into()),
+        }
+    }
+}
+
+impl KeepAlive {
+    fn idle_timeout(&self) -> quinn::IdleTimeout {
+        let ms = u32::try_from(self.idle_timeout.as_millis()).unwrap_or(u32::MAX);
+        quinn::VarInt::from_u32(ms).into()
+    }
+}
+
 //------------------------------------------------------------------------------
 pub struct Connector {
     endpoint: quinn::Endpoint,
@@ -321,8 +346,11 @@ impl Drop for OwnedWriteHalf {
 }
 
 //------------------------------------------------------------------------------
-pub async fn configure(bind_addr: SocketAddr) -> Result<(Connector, Acceptor, SideChannelMaker)> {
-    let server_config = make_server_config()?;
+pub async fn configure(
+    bind_addr: SocketAddr,
+    keep_alive: KeepAlive,
+) -> Result<(Connector, Acceptor, SideChannelMaker)> {
+    let server_config = make_server_config(&keep_alive)?;
     let custom_socket = CustomUdpSocket::bind(bind_addr).await?;
     let side_channel_maker = custom_socket.side_channel_maker();
 
@@ -333,7 +361,7 @@ pub async fn configure(bind_addr: SocketAddr) -> Result<(Connector, Acceptor, Si
         Arc::new(quinn::TokioRuntime),
     )?;
 
-    endpoint.set_default_client_config(make_client_config());
+    endpoint.set_default_client_config(make_client_config(&keep_alive));
 
     let local_addr = endpoint.local_addr()?;
 
@@ -387,7 +415,7 @@ impl rustls::client::ServerCertVerifier for SkipServerVerification {
     }
 }
 
-fn make_client_config() -> quinn::ClientConfig {
+fn make_client_config(keep_alive: &KeepAlive) -> quinn::ClientConfig {
     let crypto = rustls::ClientConfig::builder()
         .with_safe_defaults()
         .with_custom_certificate_verifier(Arc::new(SkipServerVerification {}))
@@ -403,14 +431,14 @@ fn make_client_config() -> quinn::ClientConfig {
         // to be on the client side with the reasoning that the server side has a better chance of
         // being behind a non restrictive NAT, and so that sending the packets from the client side
         // shall assist in hole punching.
-        .keep_alive_interval(Some(Duration::from_millis(KEEP_ALIVE_INTERVAL_MS.into())))
-        .max_idle_timeout(Some(quinn::VarInt::from_u32(MAX_IDLE_TIMEOUT_MS).into()));
+        .keep_alive_interval(Some(keep_alive.interval))
+        .max_idle_timeout(Some(keep_alive.idle_timeout()));
 
     client_config.transport_config(Arc::new(transport_config));
     client_config
 }
 
-fn make_server_config() -> Result<quinn::ServerConfig> {
+fn make_server_config(keep_alive: &KeepAlive) -> Result<quinn::ServerConfig> {
     // Generate a self signed certificate.
     let cert = rcgen::generate_simple_self_signed(vec![CERT_DOMAIN.into()]).unwrap();
     let cert_der = cert.serialize_der().unwrap();
@@ -424,7 +452,7 @@ fn make_server_config() -> Result<quinn::ServerConfig> {
 
     transport_config
         .max_concurrent_uni_streams(0_u8.into())
-        .max_idle_timeout(Some(quinn::VarInt::from_u32(MAX_IDLE_TIMEOUT_MS).into()));
+        .max_idle_timeout(Some(keep_alive.idle_timeout()));
 
     server_config.transport_config(Arc::new(transport_config));
 
@@ -648,7 +676,9 @@ mod tests {
     #[tokio::test(flavor = "multi_thread")]
     async fn small_data_exchange() {
         let (connector, mut acceptor, _) =
-            configure((Ipv4Addr::LOCALHOST, 0).into()).await.unwrap();
+            configure((Ipv4Addr::LOCALHOST, 0).into(), KeepAlive::default())
+                .await
+                .unwrap();
 
         let addr = *acceptor.local_addr();
 
@@ -674,7 +704,9 @@ mod tests {
     #[tokio::test(flavor = "multi_thread")]
     async fn side_channel() {
         let (_connector, mut acceptor, side_channel_maker) =
-            configure((Ipv4Addr::LOCALHOST, 0).into()).await.unwrap();
+            configure((Ipv4Addr::LOCALHOST, 0).into(), KeepAlive::default())
+                .await
+                .unwrap();
         let addr = *acceptor.local_addr();
         let side_channel = side_channel_maker.make();
 
@@ -1,5 +1,5 @@
 use super::{AccessMode, AccessSecrets, DecodeError};
-use crate::repository::RepositoryId;
+use crate::{crypto::cipher, repository_id::RepositoryId};
 use bincode::Options;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
@@ -18,6 +18,7 @@ pub const VERSION: u64 = 1;
 pub struct ShareToken {
     secrets: AccessSecrets,
     name: String,
+    invitation: Option<cipher::SecretKey>,
 }
 
 impl ShareToken {
@@ -29,6 +30,20 @@ impl ShareToken {
         }
     }
 
+    /// Attach the key of a one-time invitation. Such token is usually blind and the actual access
+    /// secrets are obtained by redeeming the invitation with the replica that created it.
+    pub fn with_invitation(self, key: cipher::SecretKey) -> Self {
+        Self {
+            invitation: Some(key),
+            ..self
+        }
+    }
+
+    /// Key of the one-time invitation, if this token carries one.
+    pub fn invitation(&self) -> Option<&cipher::SecretKey> {
+        self.invitation.as_ref()
+    }
+
     /// Id of the repository to share.
     pub fn id(&self) -> &RepositoryId {
         self.secrets.id()
@@ -64,6 +79,7 @@ impl From<AccessSecrets> for ShareToken {
         Self {
             secrets,
             name: String::new(),
+            invitation: None,
         }
     }
 }
@@ -92,20 +108,34 @@ impl FromStr for ShareToken {
 
         let secrets: AccessSecrets = bincode::options().deserialize(input)?;
         let name = parse_name(params)?;
+        let invitation = parse_invitation(params)?;
 
-        Ok(Self::from(secrets).with_name(name))
+        Ok(Self {
+            invitation,
+            ..Self::from(secrets).with_name(name)
+        })
     }
 }
 
 fn parse_name(query: &str) -> Result<String, DecodeError> {
-    let value = query
-        .split('&')
-        .find_map(|param| param.strip_prefix("name="))
-        .unwrap_or("");
-
+    let value = find_param(query, "name").unwrap_or("");
     Ok(urlencoding::decode(value)?.into_owned())
 }
 
+fn parse_invitation(query: &str) -> Result<Option<cipher::SecretKey>, DecodeError> {
+    find_param(query, "invite")
+        .map(|value| cipher::SecretKey::parse_hex(value).map_err(|_| DecodeError))
+        .transpose()
+}
+
+fn find_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|param| {
+        param
+            .strip_prefix(name)
+            .and_then(|param| param.strip_prefix('='))
+    })
+}
+
 fn encode_version(output: &mut Vec<u8>, version: u64) {
     let version = vint64::encode(version);
     output.extend_from_slice(version.as_ref());
@@ -136,8 +166,15 @@ impl fmt::Display for ShareToken {
             base64::encode_config(buffer, base64::URL_SAFE_NO_PAD)
         )?;
 
+        let mut separator = '?';
+
         if !self.name.is_empty() {
-            write!(f, "?name={}", urlencoding::encode(&self.name))?
+            write!(f, "{}name={}", separator, urlencoding::encode(&self.name))?;
+            separator = '&';
+        }
+
+        if let Some(key) = &self.invitation {
+            write!(f, "{}invite={}", separator, hex::encode(key.as_ref()))?;
         }
 
         Ok(())
@@ -232,4 +269,27 @@ mod tests {
             assert_eq!(access.id, token_id);
         });
     }
+
+    #[test]
+    fn to_string_from_string_invitation() {
+        let token_id = RepositoryId::random();
+        let key = cipher::SecretKey::random();
+
+        let token =
+            ShareToken::from(AccessSecrets::Blind { id: token_id }).with_invitation(key.clone());
+        let decoded: ShareToken = token.to_string().parse().unwrap();
+        assert_eq!(decoded.name, "");
+        assert_eq!(decoded.invitation(), Some(&key));
+
+        let token = token.with_name("foo");
+        let decoded: ShareToken = token.to_string().parse().unwrap();
+        assert_eq!(decoded.name, "foo");
+        assert_eq!(decoded.invitation(), Some(&key));
+
+        let decoded: ShareToken = ShareToken::from(AccessSecrets::Blind { id: token_id })
+            .to_string()
+            .parse()
+            .unwrap();
+        assert_eq!(decoded.invitation(), None);
+    }
 }
@@ -16,9 +16,8 @@ use thiserror::Error;
 use zeroize::{Zeroize, Zeroizing};
 
 /// Nonce
-pub(crate) type Nonce = [u8; NONCE_SIZE];
-pub(crate) const NONCE_SIZE: usize =
-    <<chacha20::Nonce as GenericSequence<_>>::Length as Unsigned>::USIZE;
+pub type Nonce = [u8; NONCE_SIZE];
+pub const NONCE_SIZE: usize = <<chacha20::Nonce as GenericSequence<_>>::Length as Unsigned>::USIZE;
 
 /// Symmetric encryption/decryption secret key.
 ///
@@ -98,13 +97,13 @@ impl SecretKey {
     // with a single function (what should it be called?).
 
     /// Encrypt a message in place without using Authenticated Encryption with Associated Data
-    pub(crate) fn encrypt_no_aead(&self, nonce: &Nonce, buffer: &mut [u8]) {
+    pub fn encrypt_no_aead(&self, nonce: &Nonce, buffer: &mut [u8]) {
         let mut cipher = ChaCha20::new(self.as_ref().into(), nonce.into());
         cipher.apply_keystream(buffer)
     }
 
     /// Decrypt a message in place without using Authenticated Encryption with Associated Data.
-    pub(crate) fn decrypt_no_aead(&self, nonce: &Nonce, buffer: &mut [u8]) {
+    pub fn decrypt_no_aead(&self, nonce: &Nonce, buffer: &mut [u8]) {
         let mut cipher = ChaCha20::new(self.as_ref().into(), nonce.into());
         cipher.apply_keystream(buffer)
     }
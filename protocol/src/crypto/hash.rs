@@ -2,6 +2,11 @@ pub use blake3::traits::digest::Digest;
 
 use crate::format;
 use generic_array::{typenum::U32, GenericArray};
+#[cfg(any(test, feature = "test-utils"))]
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     array::TryFromSliceError,
@@ -11,12 +16,6 @@ use std::{
     slice,
 };
 
-#[cfg(test)]
-use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
-};
-
 /// Wrapper for a 256-bit hash digest. Also implements friendly formatting.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[repr(transparent)]
@@ -97,9 +96,10 @@ impl Hashable for Hash {
     }
 }
 
+#[cfg(feature = "sqlx")]
 derive_sqlx_traits_for_byte_array_wrapper!(Hash);
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-utils"))]
 impl Distribution<Hash> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Hash {
         let array: [u8; Hash::SIZE] = rng.gen();
@@ -245,7 +245,7 @@ impl_hashable_for_tuple!(T0 T1 T2);
 impl_hashable_for_tuple!(T0 T1 T2 T3);
 
 /// Wrapper that caches the hash of the inner value.
-pub(crate) struct CacheHash<T> {
+pub struct CacheHash<T> {
     owner: T,
     hash: Hash,
 }
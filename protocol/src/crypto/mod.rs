@@ -0,0 +1,9 @@
+pub mod cipher;
+mod hash;
+mod password;
+pub mod sign;
+
+pub use self::{
+    hash::{CacheHash, Digest, Hash, Hashable},
+    password::{Password, PasswordSalt},
+};
@@ -5,10 +5,6 @@ use crate::{
 use ed25519_dalek::{self as ext, Signer, Verifier};
 use rand::{rngs::OsRng, CryptoRng, Rng};
 use serde::{Deserialize, Serialize};
-use sqlx::{
-    sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
-    Sqlite,
-};
 use std::{
     cmp::Ordering,
     fmt,
@@ -68,12 +64,12 @@ pub struct PublicKey(ext::VerifyingKey);
 impl PublicKey {
     pub const SIZE: usize = ext::PUBLIC_KEY_LENGTH;
 
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn generate<R: Rng + CryptoRng>(rng: &mut R) -> Self {
         Keypair::generate(rng).public_key()
     }
 
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn random() -> Self {
         Self::generate(&mut OsRng)
     }
@@ -165,9 +161,10 @@ impl FromStr for PublicKey {
 #[error("failed to parse public key")]
 pub struct ParseError;
 
+#[cfg(feature = "sqlx")]
 derive_sqlx_traits_for_byte_array_wrapper!(PublicKey);
 
-#[cfg(test)]
+#[cfg(feature = "proptest")]
 mod test_utils {
     use super::{Keypair, PublicKey};
     use proptest::{
@@ -224,24 +221,33 @@ impl fmt::Debug for Signature {
     }
 }
 
-impl sqlx::Type<Sqlite> for Signature {
-    fn type_info() -> SqliteTypeInfo {
-        <&[u8] as sqlx::Type<Sqlite>>::type_info()
+#[cfg(feature = "sqlx")]
+mod sqlx_impls {
+    use super::Signature;
+    use sqlx::{
+        sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
+        Sqlite,
+    };
+
+    impl sqlx::Type<Sqlite> for Signature {
+        fn type_info() -> SqliteTypeInfo {
+            <&[u8] as sqlx::Type<Sqlite>>::type_info()
+        }
     }
-}
 
-impl<'q> sqlx::Encode<'q, Sqlite> for &'q Signature {
-    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> sqlx::encode::IsNull {
-        // It seems there is no way to avoid the allocation here because sqlx doesn't implement
-        // `Encode` for arrays.
-        sqlx::Encode::<Sqlite>::encode(self.to_bytes().to_vec(), args)
+    impl<'q> sqlx::Encode<'q, Sqlite> for &'q Signature {
+        fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> sqlx::encode::IsNull {
+            // It seems there is no way to avoid the allocation here because sqlx doesn't implement
+            // `Encode` for arrays.
+            sqlx::Encode::<Sqlite>::encode(self.to_bytes().to_vec(), args)
+        }
     }
-}
 
-impl<'r> sqlx::Decode<'r, Sqlite> for Signature {
-    fn decode(value: SqliteValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
-        let slice = <&[u8] as sqlx::Decode<Sqlite>>::decode(value)?;
-        Ok(slice.try_into()?)
+    impl<'r> sqlx::Decode<'r, Sqlite> for Signature {
+        fn decode(value: SqliteValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+            let slice = <&[u8] as sqlx::Decode<Sqlite>>::decode(value)?;
+            Ok(slice.try_into()?)
+        }
     }
 }
 
@@ -2,7 +2,7 @@ use std::fmt;
 
 // Format the byte slice as hex with optional truncation.
 // This is a helper for implementing the `LowerHex` trait.
-pub(crate) fn hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+pub fn hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
     let len = f
         .width()
         .map(|w| w / 2)
@@ -27,7 +27,7 @@ pub(crate) fn hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
     Ok(())
 }
 
-pub(crate) struct Hex<'a>(pub &'a [u8]);
+pub struct Hex<'a>(pub &'a [u8]);
 
 impl fmt::LowerHex for Hex<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
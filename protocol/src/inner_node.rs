@@ -1,14 +1,14 @@
-use super::{Summary, EMPTY_LEAF_HASH};
 use crate::crypto::{Digest, Hash, Hashable};
+use crate::{Summary, EMPTY_LEAF_HASH};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::collections::{btree_map, BTreeMap};
 
 /// Number of layers in the tree excluding the layer with root and the layer with leaf nodes.
-pub(crate) const INNER_LAYER_COUNT: usize = 3;
+pub const INNER_LAYER_COUNT: usize = 3;
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
-pub(crate) struct InnerNode {
+pub struct InnerNode {
     pub hash: Hash,
     pub summary: Summary,
 }
@@ -31,14 +31,13 @@ impl Hashable for InnerNode {
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
-pub(crate) struct InnerNodes(BTreeMap<u8, InnerNode>);
+pub struct InnerNodes(BTreeMap<u8, InnerNode>);
 
 impl InnerNodes {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
 
-    #[allow(unused)]
     pub fn len(&self) -> usize {
         self.0.len()
     }
@@ -131,9 +130,9 @@ impl Hashable for InnerNodes {
 }
 
 // Cached hash of an empty InnerNodeMap.
-pub(crate) static EMPTY_INNER_HASH: Lazy<Hash> = Lazy::new(|| InnerNodes::default().hash());
+pub static EMPTY_INNER_HASH: Lazy<Hash> = Lazy::new(|| InnerNodes::default().hash());
 
-pub(crate) struct InnerNodesIter<'a>(btree_map::Iter<'a, u8, InnerNode>);
+pub struct InnerNodesIter<'a>(btree_map::Iter<'a, u8, InnerNode>);
 
 impl<'a> Iterator for InnerNodesIter<'a> {
     type Item = (u8, &'a InnerNode);
@@ -143,7 +142,7 @@ impl<'a> Iterator for InnerNodesIter<'a> {
     }
 }
 
-pub(crate) struct InnerNodesIterMut<'a>(btree_map::IterMut<'a, u8, InnerNode>);
+pub struct InnerNodesIterMut<'a>(btree_map::IterMut<'a, u8, InnerNode>);
 
 impl<'a> Iterator for InnerNodesIterMut<'a> {
     type Item = (u8, &'a mut InnerNode);
@@ -154,6 +153,6 @@ impl<'a> Iterator for InnerNodesIterMut<'a> {
 }
 
 /// Get the bucket for `locator` at the specified `inner_layer`.
-pub(crate) fn get_bucket(locator: &Hash, inner_layer: usize) -> u8 {
+pub fn get_bucket(locator: &Hash, inner_layer: usize) -> u8 {
     locator.as_ref()[inner_layer]
 }
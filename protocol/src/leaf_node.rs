@@ -1,14 +1,13 @@
-use super::SingleBlockPresence;
 use crate::{
     crypto::{Digest, Hash, Hashable},
-    protocol::BlockId,
+    BlockId, SingleBlockPresence,
 };
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::{slice, vec};
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
-pub(crate) struct LeafNode {
+pub struct LeafNode {
     pub locator: Hash,
     pub block_id: BlockId,
     pub block_presence: SingleBlockPresence,
@@ -17,7 +16,7 @@ pub(crate) struct LeafNode {
 impl LeafNode {
     /// Creates a leaf node whose block is assumed to be present (not missing) in this replica.
     /// (currently test-only).
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn present(locator: Hash, block_id: BlockId) -> Self {
         Self {
             locator,
@@ -28,7 +27,7 @@ impl LeafNode {
 
     /// Creates a leaf node whose block is assumed to be missing in this replica
     /// (currently test-only).
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn missing(locator: Hash, block_id: BlockId) -> Self {
         Self {
             locator,
@@ -47,14 +46,13 @@ impl Hashable for LeafNode {
 
 /// Collection that acts as a ordered set of `LeafNode`s
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
-pub(crate) struct LeafNodes(Vec<LeafNode>);
+pub struct LeafNodes(Vec<LeafNode>);
 
 impl LeafNodes {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
 
-    #[allow(unused)]
     pub fn len(&self) -> usize {
         self.0.len()
     }
@@ -171,4 +169,4 @@ impl Hashable for LeafNodes {
 }
 
 // Cached hash of an empty LeafNodeSet.
-pub(crate) static EMPTY_LEAF_HASH: Lazy<Hash> = Lazy::new(|| LeafNodes::default().hash());
+pub static EMPTY_LEAF_HASH: Lazy<Hash> = Lazy::new(|| LeafNodes::default().hash());
@@ -0,0 +1,43 @@
+//! Data types of the Ouisync protocol and of its persistent formats: cryptographic primitives,
+//! version vectors, proofs, repository ids, access secrets, share tokens, blocks, the index nodes
+//! and the messages exchanged between replicas.
+//!
+//! This crate does no I/O so it can be used by external tools to decode and encode the data
+//! without pulling in the whole `ouisync` library.
+
+#[macro_use]
+mod macros;
+
+pub mod access_control;
+pub mod crypto;
+pub mod message;
+pub mod peer_addr;
+pub mod test_vectors;
+
+#[doc(hidden)]
+pub mod format;
+
+mod block;
+mod inner_node;
+mod leaf_node;
+mod proof;
+mod repository_id;
+mod summary;
+mod version_vector;
+
+pub use self::{
+    access_control::{
+        AccessKeys, AccessMode, AccessSecrets, DecodeError, ShareToken, WriteSecrets,
+    },
+    block::{BlockContent, BlockId, BlockNonce, BLOCK_NONCE_SIZE, BLOCK_SIZE},
+    inner_node::{
+        get_bucket, InnerNode, InnerNodes, InnerNodesIter, InnerNodesIterMut, EMPTY_INNER_HASH,
+        INNER_LAYER_COUNT,
+    },
+    leaf_node::{LeafNode, LeafNodes, EMPTY_LEAF_HASH},
+    peer_addr::{PeerAddr, PeerPort},
+    proof::{Proof, ProofError, UntrustedProof},
+    repository_id::RepositoryId,
+    summary::{MultiBlockPresence, NodeState, SingleBlockPresence, Summary},
+    version_vector::VersionVector,
+};
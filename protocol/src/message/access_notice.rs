@@ -0,0 +1,102 @@
+//! Notices of access mode downgrades. A writer publishes a signed notice that the replicas with
+//! some access mode (e.g., those that got the repository from a write token) are now limited to a
+//! lower one, for example after the keys were replaced. The notice is gossiped over the links so
+//! the affected replicas can tell their users right away instead of failing on the next write.
+
+use crate::{
+    access_control::AccessMode,
+    crypto::{
+        sign::{Keypair, Signature},
+        Hashable,
+    },
+    RepositoryId,
+};
+use serde::{Deserialize, Serialize};
+
+const TO_SIGN_PREFIX: &[u8] = b"access-notice";
+
+/// Notice that the replicas with the `from` access mode are now limited to the `to` one.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct AccessNotice {
+    pub from: AccessMode,
+    pub to: AccessMode,
+    /// Milliseconds since the Unix epoch when the notice was issued, according to the hybrid logical
+    /// clock of the issuing replica. A newer notice supersedes an older one.
+    pub issued_at: u64,
+    // Signed with the repository write keys, so only writers can issue the notice.
+    signature: Signature,
+}
+
+impl AccessNotice {
+    pub fn new(
+        repository_id: &RepositoryId,
+        from: AccessMode,
+        to: AccessMode,
+        issued_at: u64,
+        write_keys: &Keypair,
+    ) -> Self {
+        let signature = write_keys.sign(&to_sign(repository_id, from, to, issued_at));
+
+        Self {
+            from,
+            to,
+            issued_at,
+            signature,
+        }
+    }
+
+    pub fn verify(&self, repository_id: &RepositoryId) -> bool {
+        repository_id.write_public_key().verify(
+            &to_sign(repository_id, self.from, self.to, self.issued_at),
+            &self.signature,
+        )
+    }
+
+    /// Whether the notice affects a replica with the given access mode.
+    pub fn affects(&self, mode: AccessMode) -> bool {
+        mode == self.from
+    }
+}
+
+fn to_sign(
+    repository_id: &RepositoryId,
+    from: AccessMode,
+    to: AccessMode,
+    issued_at: u64,
+) -> Vec<u8> {
+    let hash = (repository_id, [u8::from(from), u8::from(to)], issued_at).hash();
+
+    let mut out = TO_SIGN_PREFIX.to_vec();
+    out.extend_from_slice(hash.as_ref());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_control::WriteSecrets;
+
+    #[test]
+    fn verify() {
+        let secrets = WriteSecrets::random();
+        let notice = AccessNotice::new(
+            &secrets.id,
+            AccessMode::Write,
+            AccessMode::Read,
+            1,
+            &secrets.write_keys,
+        );
+
+        assert!(notice.verify(&secrets.id));
+        assert!(!notice.verify(&RepositoryId::random()));
+
+        let forged = AccessNotice {
+            to: AccessMode::Blind,
+            ..notice.clone()
+        };
+        assert!(!forged.verify(&secrets.id));
+
+        assert!(notice.affects(AccessMode::Write));
+        assert!(!notice.affects(AccessMode::Read));
+    }
+}
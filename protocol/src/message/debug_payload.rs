@@ -1,8 +1,8 @@
 #[cfg(feature = "analyze-protocol")]
-pub(crate) use meaningful_data::*;
+pub use meaningful_data::*;
 
 #[cfg(not(feature = "analyze-protocol"))]
-pub(crate) use dummy_data::*;
+pub use dummy_data::*;
 
 #[cfg(feature = "analyze-protocol")]
 mod meaningful_data {
@@ -12,17 +12,17 @@ mod meaningful_data {
     static NEXT_ID: AtomicU64 = AtomicU64::new(0);
 
     #[derive(Clone, Eq, PartialEq, Hash, Debug)]
-    pub(crate) struct PendingDebugRequest {
+    pub struct PendingDebugRequest {
         exchange_id: u64,
     }
 
     impl PendingDebugRequest {
-        pub(crate) fn start() -> Self {
+        pub fn start() -> Self {
             let exchange_id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
             Self { exchange_id }
         }
 
-        pub(crate) fn send(&self) -> DebugRequest {
+        pub fn send(&self) -> DebugRequest {
             DebugRequest {
                 exchange_id: self.exchange_id,
             }
@@ -30,12 +30,12 @@ mod meaningful_data {
     }
 
     #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-    pub(crate) struct DebugRequest {
+    pub struct DebugRequest {
         exchange_id: u64,
     }
 
     impl DebugRequest {
-        pub(crate) fn begin_reply(self) -> PendingDebugResponse {
+        pub fn begin_reply(self) -> PendingDebugResponse {
             PendingDebugResponse {
                 exchange_id: self.exchange_id,
             }
@@ -43,12 +43,12 @@ mod meaningful_data {
     }
 
     #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-    pub(crate) struct PendingDebugResponse {
+    pub struct PendingDebugResponse {
         exchange_id: u64,
     }
 
     impl PendingDebugResponse {
-        pub(crate) fn send(self) -> DebugResponse {
+        pub fn send(self) -> DebugResponse {
             DebugResponse {
                 exchange_id: self.exchange_id,
             }
@@ -56,17 +56,17 @@ mod meaningful_data {
     }
 
     #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-    pub(crate) struct DebugResponse {
+    pub struct DebugResponse {
         exchange_id: u64,
     }
 
     impl DebugResponse {
-        pub(crate) fn unsolicited() -> Self {
+        pub fn unsolicited() -> Self {
             let exchange_id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
             Self { exchange_id }
         }
 
-        pub(crate) fn follow_up(self) -> PendingDebugRequest {
+        pub fn follow_up(self) -> PendingDebugRequest {
             PendingDebugRequest {
                 exchange_id: self.exchange_id,
             }
@@ -79,45 +79,45 @@ mod dummy_data {
     use serde::{Deserialize, Serialize};
 
     #[derive(Clone, Eq, PartialEq, Hash, Debug)]
-    pub(crate) struct PendingDebugRequest {}
+    pub struct PendingDebugRequest {}
 
     impl PendingDebugRequest {
-        pub(crate) fn start() -> Self {
+        pub fn start() -> Self {
             Self {}
         }
 
-        pub(crate) fn send(&self) -> DebugRequest {
+        pub fn send(&self) -> DebugRequest {
             DebugRequest {}
         }
     }
 
     #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-    pub(crate) struct DebugRequest {}
+    pub struct DebugRequest {}
 
     impl DebugRequest {
-        pub(crate) fn begin_reply(self) -> PendingDebugResponse {
+        pub fn begin_reply(self) -> PendingDebugResponse {
             PendingDebugResponse {}
         }
     }
 
     #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-    pub(crate) struct PendingDebugResponse {}
+    pub struct PendingDebugResponse {}
 
     impl PendingDebugResponse {
-        pub(crate) fn send(self) -> DebugResponse {
+        pub fn send(self) -> DebugResponse {
             DebugResponse {}
         }
     }
 
     #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-    pub(crate) struct DebugResponse {}
+    pub struct DebugResponse {}
 
     impl DebugResponse {
-        pub(crate) fn unsolicited() -> Self {
+        pub fn unsolicited() -> Self {
             Self {}
         }
 
-        pub(crate) fn follow_up(self) -> PendingDebugRequest {
+        pub fn follow_up(self) -> PendingDebugRequest {
             PendingDebugRequest {}
         }
     }
@@ -0,0 +1,159 @@
+//! One-time invitations. An invitation is a (usually blind) share token carrying a random key. The
+//! replica that created it stores the access secrets to grant encrypted with that key and hands
+//! them out to the first peer that redeems the invitation.
+//!
+//! The invitation id is sent to every peer of the repository, so knowing it is not enough to
+//! redeem the invitation. The redemption also carries a proof, keyed by the invitation key and
+//! bound to the redeeming writer id, which only the holder of the token can produce.
+
+use crate::{
+    access_control::AccessSecrets,
+    crypto::{
+        cipher::{self, Nonce},
+        sign::PublicKey,
+        Hash, Hashable,
+    },
+    RepositoryId,
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305,
+};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+pub type InvitationId = Hash;
+
+/// Derives the invitation id from its key. Only the id is ever sent over the network.
+pub fn invitation_id(key: &cipher::SecretKey) -> InvitationId {
+    (key.as_array(), b"ouisync invitation").hash()
+}
+
+/// Derives the key the inviting replica uses to verify redemption proofs (see `redemption_proof`).
+pub fn auth_key(key: &cipher::SecretKey) -> cipher::SecretKey {
+    cipher::SecretKey::derive_from_key(key.as_array(), b"ouisync invitation auth")
+}
+
+/// Proof that the peer redeeming the invitation on behalf of the given writer knows the
+/// invitation key.
+pub fn redemption_proof(auth_key: &cipher::SecretKey, writer_id: &PublicKey) -> Hash {
+    Hash::from(*blake3::keyed_hash(auth_key.as_array(), writer_id.as_ref()).as_bytes())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum InvitationPayload {
+    /// Redeem the invitation with the given id, registering the given writer id with it. The
+    /// proof shows the sender knows the invitation key (see `redemption_proof`).
+    Redeem {
+        id: InvitationId,
+        writer_id: PublicKey,
+        proof: Hash,
+    },
+    /// The invitation was redeemed, here are the secrets encrypted with the invitation key.
+    Granted {
+        id: InvitationId,
+        sealed: SealedSecrets,
+    },
+    /// The invitation was already redeemed by someone else. Peers that don't know the invitation
+    /// don't reply at all.
+    Rejected { id: InvitationId },
+}
+
+/// Access secrets encrypted and authenticated with the invitation key.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SealedSecrets {
+    nonce: Nonce,
+    #[serde(with = "serde_bytes")]
+    ciphertext: Vec<u8>,
+}
+
+impl SealedSecrets {
+    pub fn seal(secrets: &AccessSecrets, key: &cipher::SecretKey) -> Self {
+        let nonce: Nonce = rand::random();
+        // unwrap is ok because serialization into a vector can't fail unless we have a bug.
+        let plaintext = Zeroizing::new(bincode::serialize(secrets).unwrap());
+        // unwrap is ok because encryption fails only if the plaintext is too long.
+        let ciphertext = cipher(key)
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: secrets.id().as_ref(),
+                },
+            )
+            .unwrap();
+
+        Self { nonce, ciphertext }
+    }
+
+    /// Decrypts the secrets. Returns `None` if they are malformed, have been tampered with or are
+    /// not for the given repository.
+    pub fn open(&self, key: &cipher::SecretKey, id: &RepositoryId) -> Option<AccessSecrets> {
+        let plaintext = cipher(key)
+            .decrypt(
+                &self.nonce.into(),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: id.as_ref(),
+                },
+            )
+            .ok()
+            .map(Zeroizing::new)?;
+
+        let secrets: AccessSecrets = bincode::deserialize(&plaintext).ok()?;
+        (secrets.id() == id).then_some(secrets)
+    }
+}
+
+fn cipher(key: &cipher::SecretKey) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(key.as_array().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_open() {
+        let secrets = AccessSecrets::random_write();
+        let key = cipher::SecretKey::random();
+        let sealed = SealedSecrets::seal(&secrets, &key);
+
+        let opened = sealed.open(&key, secrets.id()).unwrap();
+        assert_eq!(opened.access_mode(), secrets.access_mode());
+        assert_eq!(opened.id(), secrets.id());
+
+        assert!(sealed
+            .open(&cipher::SecretKey::random(), secrets.id())
+            .is_none());
+        assert!(sealed.open(&key, &RepositoryId::random()).is_none());
+    }
+
+    #[test]
+    fn open_tampered() {
+        let secrets = AccessSecrets::random_write();
+        let key = cipher::SecretKey::random();
+        let mut sealed = SealedSecrets::seal(&secrets, &key);
+
+        sealed.ciphertext[0] ^= 1;
+
+        assert!(sealed.open(&key, secrets.id()).is_none());
+    }
+
+    #[test]
+    fn redemption_proof_requires_key() {
+        let key = cipher::SecretKey::random();
+        let writer_id = PublicKey::random();
+        let proof = redemption_proof(&auth_key(&key), &writer_id);
+
+        assert_eq!(redemption_proof(&auth_key(&key), &writer_id), proof);
+        assert_ne!(
+            redemption_proof(&auth_key(&cipher::SecretKey::random()), &writer_id),
+            proof
+        );
+        assert_ne!(
+            redemption_proof(&auth_key(&key), &PublicKey::random()),
+            proof
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Content of the messages exchanged between replicas over a link, before it's split into frames
+//! and encrypted. The content is serialized with `bincode`.
+
+pub mod access_notice;
+pub mod debug_payload;
+pub mod invitation;
+pub mod peer_exchange;
+pub mod receipt;
+pub mod wipe_directive;
+
+pub use self::{
+    access_notice::AccessNotice,
+    invitation::{InvitationPayload, SealedSecrets},
+    peer_exchange::{PexPayload, PunchPayload},
+    receipt::MirrorReceipt,
+    wipe_directive::WipeDirective,
+};
+
+use self::debug_payload::{DebugRequest, DebugResponse};
+use crate::{
+    crypto::{sign::PublicKey, Hash},
+    BlockContent, BlockId, BlockNonce, InnerNodes, LeafNodes, MultiBlockPresence, UntrustedProof,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub enum Request {
+    RootNode(PublicKey, DebugRequest),
+    ChildNodes(Hash, ResponseDisambiguator, DebugRequest),
+    Block(BlockId, DebugRequest),
+    /// Request multiple blocks at once. Each of them is responded to separately, the same as if it
+    /// was requested with `Block`. Sent only to the peers with bulk transfer enabled. Contains at
+    /// most `BULK_BLOCK_BATCH_SIZE` ids.
+    Blocks(Vec<BlockId>, DebugRequest),
+    /// Acknowledge that we hold a complete snapshot which we (at least partially) downloaded from
+    /// the peer. Doesn't expect a response.
+    Receipt(MirrorReceipt),
+    /// Request the children of multiple nodes at once. Each of them is responded to separately, the
+    /// same as if it was requested with `ChildNodes`. Sent only to the peers with bulk transfer
+    /// enabled. Contains at most `BULK_CHILD_NODES_BATCH_SIZE` nodes.
+    ChildNodesBatch(Vec<(Hash, ResponseDisambiguator)>, DebugRequest),
+}
+
+/// ResponseDisambiguator is used to uniquelly assign a response to a request.
+/// What we want to avoid is that an outdated response clears out a newer pending request.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Debug)]
+#[serde(transparent)]
+pub struct ResponseDisambiguator(MultiBlockPresence);
+
+impl ResponseDisambiguator {
+    pub fn new(multi_block_presence: MultiBlockPresence) -> Self {
+        Self(multi_block_presence)
+    }
+
+    pub fn block_presence(&self) -> &MultiBlockPresence {
+        &self.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    /// Send the latest root node of this replica to another replica.
+    /// NOTE: This is both a response and notification - the server sends this as a response to
+    /// `Request::RootNode` but also on its own when it detects change in the repo.
+    RootNode(UntrustedProof, MultiBlockPresence, DebugResponse),
+    /// Send that a RootNode request failed
+    RootNodeError(PublicKey, DebugResponse),
+    /// Send inner nodes.
+    InnerNodes(InnerNodes, ResponseDisambiguator, DebugResponse),
+    /// Send leaf nodes.
+    LeafNodes(LeafNodes, ResponseDisambiguator, DebugResponse),
+    /// Send that a ChildNodes request failed
+    ChildNodesError(Hash, ResponseDisambiguator, DebugResponse),
+    /// Send a notification that a block became available on this replica.
+    /// NOTE: This is always unsolicited - the server sends it on its own when it detects a newly
+    /// received block.
+    BlockOffer(BlockId, DebugResponse),
+    /// Send a requested block.
+    Block(BlockContent, BlockNonce, DebugResponse),
+    /// Send that a Block request failed
+    BlockError(BlockId, DebugResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Content {
+    Request(Request),
+    Response(Response),
+    // Peer exchange
+    Pex(PexPayload),
+    // Hole punching coordination
+    Punch(PunchPayload),
+    // Redemption of one-time invitations
+    Invitation(InvitationPayload),
+    // Access downgrade notices
+    AccessNotice(AccessNotice),
+    // Remote wipe directives
+    WipeDirective(WipeDirective),
+}
+
+impl Content {
+    /// Name of the variant, without any of the payload (for the protocol trace).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Request(Request::RootNode(..)) => "Request::RootNode",
+            Self::Request(Request::ChildNodes(..)) => "Request::ChildNodes",
+            Self::Request(Request::Block(..)) => "Request::Block",
+            Self::Request(Request::Blocks(..)) => "Request::Blocks",
+            Self::Request(Request::ChildNodesBatch(..)) => "Request::ChildNodesBatch",
+            Self::Request(Request::Receipt(..)) => "Request::Receipt",
+            Self::Response(Response::RootNode(..)) => "Response::RootNode",
+            Self::Response(Response::RootNodeError(..)) => "Response::RootNodeError",
+            Self::Response(Response::InnerNodes(..)) => "Response::InnerNodes",
+            Self::Response(Response::LeafNodes(..)) => "Response::LeafNodes",
+            Self::Response(Response::ChildNodesError(..)) => "Response::ChildNodesError",
+            Self::Response(Response::BlockOffer(..)) => "Response::BlockOffer",
+            Self::Response(Response::Block(..)) => "Response::Block",
+            Self::Response(Response::BlockError(..)) => "Response::BlockError",
+            Self::Pex(_) => "Pex",
+            Self::Punch(_) => "Punch",
+            Self::Invitation(_) => "Invitation",
+            Self::AccessNotice(_) => "AccessNotice",
+            Self::WipeDirective(_) => "WipeDirective",
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl From<Content> for Request {
+    fn from(content: Content) -> Self {
+        match content {
+            Content::Request(request) => request,
+            Content::Response(_)
+            | Content::Pex(_)
+            | Content::Punch(_)
+            | Content::Invitation(_)
+            | Content::AccessNotice(_)
+            | Content::WipeDirective(_) => {
+                panic!("not a request: {:?}", content)
+            }
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl From<Content> for Response {
+    fn from(content: Content) -> Self {
+        match content {
+            Content::Response(response) => response,
+            Content::Request(_)
+            | Content::Pex(_)
+            | Content::Punch(_)
+            | Content::Invitation(_)
+            | Content::AccessNotice(_)
+            | Content::WipeDirective(_) => {
+                panic!("not a response: {:?}", content)
+            }
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Payloads of the peer exchange, by which peers tell each other about other peers, and of the
+//! hole punching coordinated through a mutual peer.
+
+use crate::peer_addr::PeerAddr;
+use rand::RandomState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Addresses of the peers the sender is connected to.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PexPayload(pub HashSet<PeerAddr, RandomState>);
+
+/// Hole punching coordination through a peer connected to both sides.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum PunchPayload {
+    /// Asks the recipient (who told us about `target` via peer exchange) to introduce us to
+    /// `target`.
+    Request { target: PeerAddr },
+    /// Tells the recipient that the peer with the given addresses is connecting to it so it should
+    /// connect to that peer at the same time.
+    Introduce {
+        addrs: HashSet<PeerAddr, RandomState>,
+    },
+}
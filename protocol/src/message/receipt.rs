@@ -0,0 +1,115 @@
+use crate::{
+    crypto::{
+        sign::{Keypair, PublicKey, Signature},
+        Hash, Hashable,
+    },
+    RepositoryId,
+};
+use serde::{Deserialize, Serialize};
+
+/// Signed acknowledgement by a remote replica (typically a mirror / cache server) that it holds
+/// all the blocks of a snapshot of a branch. The replica sends it to the peers it downloaded the
+/// blocks from, who persist it. It can then be used to prove the remote replica actually holds the
+/// snapshot.
+///
+/// The receipt is signed with keys the replica generates once per repository, so receipts issued
+/// by the same replica can be attributed to it even across its restarts.
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct MirrorReceipt {
+    /// Public key of the replica that issued this receipt.
+    pub mirror_id: PublicKey,
+    /// Id of the branch whose snapshot the replica holds.
+    pub branch_id: PublicKey,
+    /// Root hash of the snapshot.
+    pub root_hash: Hash,
+    /// Number of blocks the replica received from us on the link over which it sent this receipt.
+    pub block_count: u64,
+    pub signature: Signature,
+}
+
+impl MirrorReceipt {
+    pub fn new(
+        keys: &Keypair,
+        repository_id: &RepositoryId,
+        branch_id: PublicKey,
+        root_hash: Hash,
+        block_count: u64,
+    ) -> Self {
+        let signature = keys.sign(&to_sign(repository_id, &branch_id, &root_hash, block_count));
+
+        Self {
+            mirror_id: keys.public_key(),
+            branch_id,
+            root_hash,
+            block_count,
+            signature,
+        }
+    }
+
+    /// Checks that this receipt has been signed by `mirror_id` and is for the given repository.
+    pub fn verify(&self, repository_id: &RepositoryId) -> bool {
+        self.mirror_id.verify(
+            &to_sign(
+                repository_id,
+                &self.branch_id,
+                &self.root_hash,
+                self.block_count,
+            ),
+            &self.signature,
+        )
+    }
+
+    /// Whether `other` replaces this receipt, that is, it's from the same mirror for the same
+    /// branch.
+    pub fn is_superseded_by(&self, other: &Self) -> bool {
+        self.mirror_id == other.mirror_id && self.branch_id == other.branch_id
+    }
+}
+
+const TO_SIGN_PREFIX: &[u8] = b"mirror-receipt";
+
+fn to_sign(
+    repository_id: &RepositoryId,
+    branch_id: &PublicKey,
+    root_hash: &Hash,
+    block_count: u64,
+) -> Vec<u8> {
+    let hash = (repository_id, branch_id, root_hash, block_count).hash();
+
+    let mut out = Vec::with_capacity(TO_SIGN_PREFIX.len() + Hash::SIZE);
+    out.extend_from_slice(TO_SIGN_PREFIX);
+    out.extend_from_slice(hash.as_ref());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        let keys = Keypair::random();
+        let repository_id = RepositoryId::random();
+
+        let receipt = MirrorReceipt::new(
+            &keys,
+            &repository_id,
+            PublicKey::random(),
+            rand::random(),
+            42,
+        );
+        assert!(receipt.verify(&repository_id));
+
+        // Different repository
+        assert!(!receipt.verify(&RepositoryId::random()));
+
+        // Tampered with
+        let mut tampered = receipt.clone();
+        tampered.block_count += 1;
+        assert!(!tampered.verify(&repository_id));
+
+        let mut tampered = receipt;
+        tampered.mirror_id = PublicKey::random();
+        assert!(!tampered.verify(&repository_id));
+    }
+}
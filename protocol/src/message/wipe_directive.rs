@@ -0,0 +1,42 @@
+//! Remote wipe of lost devices. A writer publishes a signed wipe directive targeted at the writer
+//! id of a device and the directive is gossiped over the links. When the target device receives
+//! it, it deletes its local copy of the repository.
+
+use crate::{
+    crypto::{
+        sign::{Keypair, PublicKey, Signature},
+        Hashable,
+    },
+    RepositoryId,
+};
+use serde::{Deserialize, Serialize};
+
+const TO_SIGN_PREFIX: &[u8] = b"wipe-directive";
+
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct WipeDirective {
+    pub target: PublicKey,
+    // Signed with the repository write keys, so only writers can issue the directive.
+    signature: Signature,
+}
+
+impl WipeDirective {
+    pub fn new(repository_id: &RepositoryId, target: PublicKey, write_keys: &Keypair) -> Self {
+        let signature = write_keys.sign(&to_sign(repository_id, &target));
+        Self { target, signature }
+    }
+
+    pub fn verify(&self, repository_id: &RepositoryId) -> bool {
+        repository_id
+            .write_public_key()
+            .verify(&to_sign(repository_id, &self.target), &self.signature)
+    }
+}
+
+fn to_sign(repository_id: &RepositoryId, target: &PublicKey) -> Vec<u8> {
+    let hash = (repository_id, target).hash();
+
+    let mut out = TO_SIGN_PREFIX.to_vec();
+    out.extend_from_slice(hash.as_ref());
+    out
+}
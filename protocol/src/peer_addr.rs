@@ -126,6 +126,46 @@ impl<'de> Deserialize<'de> for PeerAddr {
     }
 }
 
+/// Serialize/deserialize `PeerAddr` as string even in non human-readable formats.
+pub mod as_str {
+    use super::*;
+
+    pub fn serialize<S>(value: &PeerAddr, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        value.to_string().serialize(s)
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<PeerAddr, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        <&str>::deserialize(d)?.parse().map_err(D::Error::custom)
+    }
+}
+
+/// Like `as_str` but for `Option<PeerAddr>`.
+pub mod option_as_str {
+    use super::*;
+
+    pub fn serialize<S>(value: &Option<PeerAddr>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        value.map(|value| value.to_string()).serialize(s)
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<Option<PeerAddr>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<&str>::deserialize(d)?
+            .map(|value| value.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
 // Proxy to serialize/deserialize PeerAddr in non human-readable formats.
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "PeerAddr")]
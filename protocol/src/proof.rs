@@ -3,7 +3,7 @@ use crate::{
         sign::{Keypair, PublicKey, Signature},
         Hash, Hashable,
     },
-    repository::RepositoryId,
+    repository_id::RepositoryId,
     version_vector::VersionVector,
 };
 use serde::{Deserialize, Serialize};
@@ -13,7 +13,7 @@ use thiserror::Error;
 /// Information that prove that a snapshot was created by a replica that has write access to the
 /// repository.
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub(crate) struct Proof(UntrustedProof);
+pub struct Proof(UntrustedProof);
 
 impl Proof {
     /// Create new proof signed with the given write keys.
@@ -60,7 +60,7 @@ impl Deref for Proof {
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
-pub(crate) struct UntrustedProof {
+pub struct UntrustedProof {
     pub writer_id: PublicKey,
     pub version_vector: VersionVector,
     pub hash: Hash,
@@ -94,4 +94,4 @@ fn signature_material(writer_id: &PublicKey, version_vector: &VersionVector, has
 
 #[derive(Debug, Error)]
 #[error("proof is invalid")]
-pub(crate) struct ProofError(pub UntrustedProof);
+pub struct ProofError(pub UntrustedProof);
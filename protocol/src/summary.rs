@@ -1,6 +1,6 @@
-use super::{InnerNodes, LeafNodes};
-use crate::format::Hex;
+use crate::{format::Hex, InnerNodes, LeafNodes};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "sqlx")]
 use sqlx::{
     encode::IsNull,
     error::BoxDynError,
@@ -8,13 +8,14 @@ use sqlx::{
     Decode, Encode, Sqlite, Type,
 };
 use std::{fmt, hash::Hasher};
+#[cfg(feature = "sqlx")]
 use thiserror::Error;
 use twox_hash::xxh3::{Hash128, HasherExt};
 
 /// Summary info of a snapshot subtree. Contains whether the subtree has been completely downloaded
 /// and the number of missing blocks in the subtree.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
-pub(crate) struct Summary {
+pub struct Summary {
     // TODO: The `state` field is not used by the peer after deserialization. Consider using
     // `#[serde(skip)]` on it.
     pub state: NodeState,
@@ -95,7 +96,7 @@ impl Summary {
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 #[repr(u8)]
-pub(crate) enum NodeState {
+pub enum NodeState {
     Incomplete = 0, // Some nodes are missing
     Complete = 1,   // All nodes are present, but the quota check wasn't performed yet
     Approved = 2,   // Quota check passed
@@ -118,18 +119,21 @@ impl NodeState {
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl Type<Sqlite> for NodeState {
     fn type_info() -> SqliteTypeInfo {
         <u8 as Type<Sqlite>>::type_info()
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl<'q> Encode<'q, Sqlite> for NodeState {
     fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
         Encode::<Sqlite>::encode(*self as u8, args)
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl<'r> Decode<'r, Sqlite> for NodeState {
     fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
         let num = <u8 as Decode<Sqlite>>::decode(value)?;
@@ -144,13 +148,14 @@ impl<'r> Decode<'r, Sqlite> for NodeState {
     }
 }
 
+#[cfg(feature = "sqlx")]
 #[derive(Debug, Error)]
 #[error("invalid value: {0}")]
-pub(crate) struct InvalidValue(u8);
+struct InvalidValue(u8);
 
 /// Information about the presence of a single block.
 #[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
-pub(crate) enum SingleBlockPresence {
+pub enum SingleBlockPresence {
     Missing,
     Present,
     Expired,
@@ -166,6 +171,7 @@ impl SingleBlockPresence {
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl Type<Sqlite> for SingleBlockPresence {
     fn type_info() -> SqliteTypeInfo {
         <u8 as Type<Sqlite>>::type_info()
@@ -176,6 +182,7 @@ impl Type<Sqlite> for SingleBlockPresence {
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl<'q> Encode<'q, Sqlite> for SingleBlockPresence {
     fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
         let n = match self {
@@ -188,6 +195,7 @@ impl<'q> Encode<'q, Sqlite> for SingleBlockPresence {
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl<'r> Decode<'r, Sqlite> for SingleBlockPresence {
     fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
         match <u8 as Decode<'r, Sqlite>>::decode(value)? {
@@ -211,7 +219,7 @@ impl fmt::Debug for SingleBlockPresence {
 
 /// Summary information about the presence of multiple blocks belonging to a subtree.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
-pub(crate) enum MultiBlockPresence {
+pub enum MultiBlockPresence {
     /// All blocks missing
     None,
     /// Some blocks present. The contained checksum is used to determine whether two subtrees have
@@ -248,18 +256,21 @@ impl MultiBlockPresence {
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl Type<Sqlite> for MultiBlockPresence {
     fn type_info() -> SqliteTypeInfo {
         <&[u8] as Type<Sqlite>>::type_info()
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl<'q> Encode<'q, Sqlite> for &'q MultiBlockPresence {
     fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
         Encode::<Sqlite>::encode(self.checksum(), args)
     }
 }
 
+#[cfg(feature = "sqlx")]
 impl<'r> Decode<'r, Sqlite> for MultiBlockPresence {
     fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
         let slice = <&[u8] as Decode<Sqlite>>::decode(value)?;
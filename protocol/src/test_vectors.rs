@@ -0,0 +1,585 @@
+//! Deterministic test vectors of the formats defined in this crate that other implementations
+//! need to be compatible with: share tokens, snapshot proofs and wire messages. The `ouisync` crate
+//! extends them with the vectors of its own formats (directories and encrypted blocks).
+//!
+//! [`validate`] checks that a vector agrees with this implementation, that is, that its encoded
+//! form decodes into the value described by its decoded fields and that the value encodes back
+//! into exactly the same encoded form. An independent implementation can test its decoders against
+//! the checked in vectors and its encoders by validating vectors it produced itself.
+//!
+//! NOTE: The wire messages are encoded differently when the `analyze-protocol` feature is enabled.
+//! The message vectors describe the encoding without it.
+
+use crate::{
+    access_control::{AccessMode, AccessSecrets, ShareToken, WriteSecrets},
+    crypto::{cipher, sign::Keypair, Hash, Hashable},
+    message::{
+        debug_payload::{DebugRequest, DebugResponse, PendingDebugRequest},
+        Content as MessageContent, Request, Response, ResponseDisambiguator,
+    },
+    BlockContent, BlockId, BlockNonce, InnerNode, InnerNodes, LeafNodes, MultiBlockPresence,
+    NodeState, Proof, RepositoryId, SingleBlockPresence, Summary, UntrustedProof, VersionVector,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// What kind of value a test vector encodes.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestVectorKind {
+    /// Share token in its string form.
+    ShareToken,
+    /// Proof of a snapshot as sent in the `RootNode` response.
+    Proof,
+    /// Content of a directory blob.
+    Directory,
+    /// Encrypted block as stored in the database, together with the secrets and the location
+    /// needed to decrypt it.
+    Block,
+    /// Content of a wire message, before it's split into frames and encrypted.
+    Message,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TestVector {
+    pub kind: TestVectorKind,
+    /// Short description of the vector, unique among the vectors of the same kind.
+    pub name: String,
+    /// Fields of the decoded value. Byte strings are in lowercase hex and version vectors are
+    /// comma separated `writer_id:version` pairs ordered by the writer id. The plaintext of a block
+    /// is without the trailing zeros.
+    pub decoded: BTreeMap<String, String>,
+    /// The encoded value. Share tokens are verbatim, everything else is in lowercase hex.
+    pub encoded: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("encoded value is malformed")]
+    Malformed,
+    #[error("signature is invalid")]
+    InvalidSignature,
+    #[error("decoded fields don't match the encoded value")]
+    DecodedMismatch,
+    #[error("encoded value is not in the canonical form")]
+    EncodedMismatch,
+    #[error("kind of the vector is not supported by this crate")]
+    Unsupported,
+}
+
+/// Generates the test vectors of the formats defined in this crate. The output is always the same
+/// unless some of the formats change.
+pub fn generate() -> Vec<TestVector> {
+    let mut vectors = Vec::new();
+    vectors.extend(share_tokens());
+    vectors.extend(proofs());
+    vectors.extend(messages());
+    vectors
+}
+
+/// Checks that the test vector agrees with this implementation. Fails with
+/// [`ValidationError::Unsupported`] for the kinds of vectors of the formats defined in `ouisync`.
+pub fn validate(vector: &TestVector) -> Result<(), ValidationError> {
+    let (decoded, encoded) = match vector.kind {
+        TestVectorKind::ShareToken => {
+            let token: ShareToken = vector
+                .encoded
+                .parse()
+                .map_err(|_| ValidationError::Malformed)?;
+
+            (describe_share_token(&token), token.to_string())
+        }
+        TestVectorKind::Proof => {
+            // The proof doesn't contain the repository id but it's needed to verify it.
+            let repository_id: RepositoryId = vector
+                .decoded
+                .get("repository_id")
+                .and_then(|id| id.parse().ok())
+                .ok_or(ValidationError::DecodedMismatch)?;
+
+            let proof: UntrustedProof = decode(&vector.encoded)?;
+            let proof = proof
+                .verify(&repository_id)
+                .map_err(|_| ValidationError::InvalidSignature)?;
+
+            (describe_proof(&repository_id, &proof), encode(&*proof))
+        }
+        TestVectorKind::Message => {
+            let content: MessageContent = decode(&vector.encoded)?;
+            let decoded = describe_message(&content).ok_or(ValidationError::Malformed)?;
+
+            (decoded, encode(&content))
+        }
+        TestVectorKind::Directory | TestVectorKind::Block => {
+            return Err(ValidationError::Unsupported)
+        }
+    };
+
+    check(vector, &decoded, &encoded)
+}
+
+/// Checks that the fields and the encoded form obtained by decoding the vector agree with the
+/// vector.
+#[doc(hidden)]
+pub fn check(
+    vector: &TestVector,
+    decoded: &BTreeMap<String, String>,
+    encoded: &str,
+) -> Result<(), ValidationError> {
+    if decoded != &vector.decoded {
+        return Err(ValidationError::DecodedMismatch);
+    }
+
+    if encoded != vector.encoded {
+        return Err(ValidationError::EncodedMismatch);
+    }
+
+    Ok(())
+}
+
+/// Share tokens of all the access modes.
+pub fn share_tokens() -> Vec<TestVector> {
+    let secrets = AccessSecrets::Write(WriteSecrets::from(keypair(1)));
+
+    [AccessMode::Write, AccessMode::Read, AccessMode::Blind]
+        .into_iter()
+        .map(|mode| {
+            let token = ShareToken::from(secrets.with_mode(mode)).with_name("test vectors");
+
+            TestVector {
+                kind: TestVectorKind::ShareToken,
+                name: mode.to_string(),
+                decoded: describe_share_token(&token),
+                encoded: token.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Proofs with a single and with multiple writers.
+pub fn proofs() -> Vec<TestVector> {
+    let write_keys = keypair(1);
+    let repository_id = RepositoryId::from(write_keys.public_key());
+    let writer_a = keypair(2).public_key();
+    let writer_b = keypair(3).public_key();
+
+    [
+        ("single writer", VersionVector::first(writer_a)),
+        (
+            "multiple writers",
+            [(writer_a, 3), (writer_b, 2)].into_iter().collect(),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, version_vector)| {
+        let proof = Proof::new(
+            writer_a,
+            version_vector,
+            Hash::from([0xab; Hash::SIZE]),
+            &write_keys,
+        );
+
+        TestVector {
+            kind: TestVectorKind::Proof,
+            name: name.to_owned(),
+            decoded: describe_proof(&repository_id, &proof),
+            encoded: encode(&*proof),
+        }
+    })
+    .collect()
+}
+
+/// Wire messages of the block and index synchronization.
+pub fn messages() -> Vec<TestVector> {
+    let write_keys = keypair(1);
+    let writer_id = keypair(2).public_key();
+    let block_ids = [0x33, 0x44].map(|byte| block_id([byte; BlockId::SIZE]));
+    let hashes = [0xab, 0xcd].map(|byte| Hash::from([byte; Hash::SIZE]));
+    let disambiguator = ResponseDisambiguator::new(MultiBlockPresence::Some([0x55; 16]));
+    let (block_content, block_nonce) = zero_block();
+
+    let inner_nodes: InnerNodes = [
+        (
+            0x00,
+            InnerNode::new(
+                hashes[0],
+                Summary {
+                    state: NodeState::Complete,
+                    block_presence: MultiBlockPresence::Full,
+                },
+            ),
+        ),
+        (
+            0xff,
+            InnerNode::new(
+                hashes[1],
+                Summary {
+                    state: NodeState::Incomplete,
+                    block_presence: MultiBlockPresence::None,
+                },
+            ),
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let mut leaf_nodes = LeafNodes::default();
+    leaf_nodes.insert(hashes[0], block_ids[0], SingleBlockPresence::Present);
+    leaf_nodes.insert(hashes[1], block_ids[1], SingleBlockPresence::Missing);
+
+    let proof = Proof::new(
+        writer_id,
+        VersionVector::first(writer_id),
+        Hash::from([0xab; Hash::SIZE]),
+        &write_keys,
+    );
+
+    [
+        MessageContent::Request(Request::RootNode(writer_id, debug_request())),
+        MessageContent::Request(Request::Block(block_ids[0], debug_request())),
+        MessageContent::Request(Request::Blocks(block_ids.to_vec(), debug_request())),
+        MessageContent::Request(Request::ChildNodes(
+            hashes[0],
+            disambiguator,
+            debug_request(),
+        )),
+        MessageContent::Request(Request::ChildNodesBatch(
+            hashes
+                .into_iter()
+                .map(|hash| (hash, disambiguator))
+                .collect(),
+            debug_request(),
+        )),
+        MessageContent::Response(Response::RootNode(
+            proof.into(),
+            MultiBlockPresence::Some([0x55; 16]),
+            DebugResponse::unsolicited(),
+        )),
+        MessageContent::Response(Response::RootNodeError(
+            writer_id,
+            DebugResponse::unsolicited(),
+        )),
+        MessageContent::Response(Response::InnerNodes(
+            inner_nodes,
+            disambiguator,
+            DebugResponse::unsolicited(),
+        )),
+        MessageContent::Response(Response::LeafNodes(
+            leaf_nodes,
+            disambiguator,
+            DebugResponse::unsolicited(),
+        )),
+        MessageContent::Response(Response::ChildNodesError(
+            hashes[0],
+            disambiguator,
+            DebugResponse::unsolicited(),
+        )),
+        MessageContent::Response(Response::BlockOffer(
+            block_ids[0],
+            DebugResponse::unsolicited(),
+        )),
+        MessageContent::Response(Response::Block(
+            block_content,
+            block_nonce,
+            DebugResponse::unsolicited(),
+        )),
+        MessageContent::Response(Response::BlockError(
+            block_ids[0],
+            DebugResponse::unsolicited(),
+        )),
+    ]
+    .into_iter()
+    .filter_map(|content| {
+        let decoded = describe_message(&content)?;
+
+        Some(TestVector {
+            kind: TestVectorKind::Message,
+            name: decoded["type"].clone(),
+            decoded,
+            encoded: encode(&content),
+        })
+    })
+    .collect()
+}
+
+fn describe_share_token(token: &ShareToken) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    fields.insert("access_mode".to_owned(), token.access_mode().to_string());
+    fields.insert("repository_id".to_owned(), hex::encode(token.id()));
+    fields.insert("name".to_owned(), token.suggested_name().into_owned());
+
+    if let Some(read_key) = token.secrets().read_key() {
+        fields.insert("read_key".to_owned(), hex::encode(read_key.as_ref()));
+    }
+
+    if let Some(write_secrets) = token.secrets().write_secrets() {
+        fields.insert(
+            "write_key".to_owned(),
+            hex::encode(write_secrets.write_keys.to_bytes()),
+        );
+    }
+
+    fields
+}
+
+fn describe_proof(
+    repository_id: &RepositoryId,
+    proof: &UntrustedProof,
+) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    fields.insert("repository_id".to_owned(), hex::encode(repository_id));
+    insert_proof_fields(&mut fields, "", proof);
+    fields
+}
+
+// Returns `None` for the messages not covered by the test vectors.
+fn describe_message(content: &MessageContent) -> Option<BTreeMap<String, String>> {
+    let mut fields = BTreeMap::new();
+
+    let message_type = match content {
+        MessageContent::Request(Request::RootNode(writer_id, _)) => {
+            fields.insert("writer_id".to_owned(), hex::encode(writer_id));
+            "Request::RootNode"
+        }
+        MessageContent::Request(Request::Block(block_id, _)) => {
+            fields.insert("block_id".to_owned(), hex::encode(block_id));
+            "Request::Block"
+        }
+        MessageContent::Request(Request::Blocks(block_ids, _)) => {
+            fields.insert(
+                "block_ids".to_owned(),
+                block_ids
+                    .iter()
+                    .map(hex::encode)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+            "Request::Blocks"
+        }
+        MessageContent::Request(Request::ChildNodes(hash, disambiguator, _)) => {
+            fields.insert("hash".to_owned(), hex::encode(hash));
+            fields.insert(
+                "disambiguator".to_owned(),
+                describe_block_presence(disambiguator.block_presence()),
+            );
+            "Request::ChildNodes"
+        }
+        MessageContent::Request(Request::ChildNodesBatch(nodes, _)) => {
+            fields.insert(
+                "nodes".to_owned(),
+                nodes
+                    .iter()
+                    .map(|(hash, disambiguator)| {
+                        format!(
+                            "{} {}",
+                            hex::encode(hash),
+                            describe_block_presence(disambiguator.block_presence())
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+            "Request::ChildNodesBatch"
+        }
+        MessageContent::Response(Response::RootNode(proof, block_presence, _)) => {
+            insert_proof_fields(&mut fields, "proof.", proof);
+            fields.insert(
+                "block_presence".to_owned(),
+                describe_block_presence(block_presence),
+            );
+            "Response::RootNode"
+        }
+        MessageContent::Response(Response::RootNodeError(writer_id, _)) => {
+            fields.insert("writer_id".to_owned(), hex::encode(writer_id));
+            "Response::RootNodeError"
+        }
+        MessageContent::Response(Response::InnerNodes(nodes, disambiguator, _)) => {
+            fields.insert(
+                "nodes".to_owned(),
+                nodes
+                    .iter()
+                    .map(|(bucket, node)| {
+                        format!(
+                            "{} {} {} {}",
+                            bucket,
+                            hex::encode(node.hash),
+                            match node.summary.state {
+                                NodeState::Incomplete => "incomplete",
+                                NodeState::Complete => "complete",
+                                NodeState::Approved => "approved",
+                                NodeState::Rejected => "rejected",
+                            },
+                            describe_block_presence(&node.summary.block_presence)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+            fields.insert(
+                "disambiguator".to_owned(),
+                describe_block_presence(disambiguator.block_presence()),
+            );
+            "Response::InnerNodes"
+        }
+        MessageContent::Response(Response::LeafNodes(nodes, disambiguator, _)) => {
+            fields.insert(
+                "nodes".to_owned(),
+                nodes
+                    .iter()
+                    .map(|node| {
+                        format!(
+                            "{} {} {}",
+                            hex::encode(node.locator),
+                            hex::encode(node.block_id),
+                            match node.block_presence {
+                                SingleBlockPresence::Missing => "missing",
+                                SingleBlockPresence::Present => "present",
+                                SingleBlockPresence::Expired => "expired",
+                            }
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+            fields.insert(
+                "disambiguator".to_owned(),
+                describe_block_presence(disambiguator.block_presence()),
+            );
+            "Response::LeafNodes"
+        }
+        MessageContent::Response(Response::ChildNodesError(hash, disambiguator, _)) => {
+            fields.insert("hash".to_owned(), hex::encode(hash));
+            fields.insert(
+                "disambiguator".to_owned(),
+                describe_block_presence(disambiguator.block_presence()),
+            );
+            "Response::ChildNodesError"
+        }
+        MessageContent::Response(Response::BlockOffer(block_id, _)) => {
+            fields.insert("block_id".to_owned(), hex::encode(block_id));
+            "Response::BlockOffer"
+        }
+        MessageContent::Response(Response::Block(content, nonce, _)) => {
+            fields.insert("content".to_owned(), hex::encode(&content[..]));
+            fields.insert("nonce".to_owned(), hex::encode(nonce));
+            "Response::Block"
+        }
+        MessageContent::Response(Response::BlockError(block_id, _)) => {
+            fields.insert("block_id".to_owned(), hex::encode(block_id));
+            "Response::BlockError"
+        }
+        _ => return None,
+    };
+
+    fields.insert("type".to_owned(), message_type.to_owned());
+
+    Some(fields)
+}
+
+fn insert_proof_fields(
+    fields: &mut BTreeMap<String, String>,
+    prefix: &str,
+    proof: &UntrustedProof,
+) {
+    fields.insert(format!("{prefix}writer_id"), hex::encode(proof.writer_id));
+    fields.insert(
+        format!("{prefix}version_vector"),
+        describe_version_vector(&proof.version_vector),
+    );
+    fields.insert(format!("{prefix}hash"), hex::encode(proof.hash));
+    fields.insert(
+        format!("{prefix}signature"),
+        hex::encode(proof.signature.to_bytes()),
+    );
+}
+
+fn describe_block_presence(block_presence: &MultiBlockPresence) -> String {
+    match block_presence {
+        MultiBlockPresence::None => "none".to_owned(),
+        MultiBlockPresence::Some(checksum) => format!("some {}", hex::encode(checksum)),
+        MultiBlockPresence::Full => "full".to_owned(),
+    }
+}
+
+#[doc(hidden)]
+pub fn describe_version_vector(version_vector: &VersionVector) -> String {
+    version_vector
+        .iter()
+        .map(|(writer_id, version)| format!("{}:{}", hex::encode(writer_id), version))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[doc(hidden)]
+pub fn keypair(seed: u8) -> Keypair {
+    Keypair::from(&[seed; Keypair::SECRET_KEY_SIZE])
+}
+
+fn block_id(bytes: [u8; BlockId::SIZE]) -> BlockId {
+    // The slice has the right length so the conversion can't fail.
+    BlockId::try_from(&bytes[..]).unwrap_or_else(|_| unreachable!())
+}
+
+// Head block of the blob with id `[0x11; 32]` with all zero content, encrypted with the read key of
+// `keypair(1)` the same way `ouisync` encrypts the blocks.
+fn zero_block() -> (BlockContent, BlockNonce) {
+    let read_key = WriteSecrets::from(keypair(1)).read_key;
+    let mut content = BlockContent::new();
+
+    // Hash of the read key, the locator (the blob id and the block number) and the plaintext.
+    let nonce: BlockNonce = (read_key.as_ref(), [0x11u8; 32], 0u32, &content[..])
+        .hash()
+        .into();
+
+    cipher::SecretKey::derive_from_key(read_key.as_array(), &nonce)
+        .encrypt_no_aead(&cipher::Nonce::default(), &mut content);
+
+    (content, nonce)
+}
+
+fn debug_request() -> DebugRequest {
+    PendingDebugRequest::start().send()
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    hex::encode(bincode::serialize(value).expect("failed to serialize test vector"))
+}
+
+fn decode<T: for<'de> Deserialize<'de>>(input: &str) -> Result<T, ValidationError> {
+    let input = hex::decode(input).map_err(|_| ValidationError::Malformed)?;
+    bincode::deserialize(&input).map_err(|_| ValidationError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_vectors_are_valid() {
+        for vector in generate() {
+            if let Err(error) = validate(&vector) {
+                panic!("{:?} {:?}: {}", vector.kind, vector.name, error);
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_vectors_are_invalid() {
+        let mut proof = proofs().into_iter().next().unwrap();
+        proof
+            .decoded
+            .insert("hash".to_owned(), hex::encode([0xcd; Hash::SIZE]));
+        assert!(matches!(
+            validate(&proof),
+            Err(ValidationError::DecodedMismatch)
+        ));
+
+        let mut message = messages().into_iter().next().unwrap();
+        message.encoded.truncate(message.encoded.len() - 2);
+        assert!(matches!(
+            validate(&message),
+            Err(ValidationError::Malformed)
+        ));
+    }
+}
@@ -534,7 +534,7 @@ impl Serialize for StateMonitor {
         let lock = self.shared.lock_inner();
 
         // When serializing into the messagepack format, the `serialize_struct(_, N)` is serialized
-        // into a list of size N (use `unpackList` in Dart).
+        // into a list of size N or, with named fields, into a map with N entries.
         let mut s = serializer.serialize_struct("StateMonitor", 2)?;
         s.serialize_field("values", &ValuesSerializer(&lock.values))?;
         s.serialize_field("children", &ChildrenSerializer(&lock.children))?;
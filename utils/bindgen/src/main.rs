@@ -14,6 +14,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "ffi/src/lib.rs",
         "lib/src/access_control/access_mode.rs",
         "lib/src/directory/entry_type.rs",
+        "lib/src/network/connection_stats.rs",
         "lib/src/network/peer_source.rs",
         "lib/src/network/peer_state.rs",
     ];
@@ -1,8 +1,8 @@
 pub(crate) mod multi_repo_mount;
 pub(crate) mod single_repo_mount;
 
-use camino::Utf8PathBuf;
-use deadlock::{AsyncMutex, AsyncMutexGuard};
+use camino::{Utf8Path, Utf8PathBuf};
+use deadlock::{AsyncMutex, AsyncMutexGuard, BlockingMutex};
 use dokan::{
     CreateFileInfo, DiskSpaceInfo, FileInfo, FileSystemHandler, FileTimeOperation, FillDataError,
     FillDataResult, FindData, MountFlags, OperationInfo, OperationResult, VolumeInfo,
@@ -12,7 +12,7 @@ use dokan_sys::win32::{
     FILE_CREATE, FILE_DELETE_ON_CLOSE, FILE_DIRECTORY_FILE, FILE_OPEN, FILE_OPEN_IF,
     FILE_OVERWRITE, FILE_OVERWRITE_IF, FILE_SUPERSEDE,
 };
-use ouisync_lib::{path, File, JointDirectory, JointEntryRef, Repository};
+use ouisync_lib::{path, AccessMode, EntryCursor, File, JointDirectory, JointEntryRef, Repository};
 use std::{
     collections::{hash_map, HashMap},
     fmt,
@@ -21,7 +21,7 @@ use std::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
-    time::UNIX_EPOCH,
+    time::{SystemTime, UNIX_EPOCH},
 };
 // TODO: We should have this in the `deadlock` crate.
 use tokio::sync::{RwLock as AsyncRwLock, RwLockReadGuard as AsyncRwLockReadGuard};
@@ -32,17 +32,30 @@ use winapi::{shared::ntstatus::*, um::winnt};
 // Use the same value as NTFS.
 pub const MAX_COMPONENT_LENGTH: u32 = 255;
 
+// Create option requesting no caching by the filesystem. Not exported by `dokan_sys`.
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/ntifs/nf-ntifs-ntcreatefile
+const FILE_NO_INTERMEDIATE_BUFFERING: u32 = 0x0000_0008;
+
+// Number of entries `find_files` processes while holding the cached directory. The directory is
+// released between the pages so it can be reloaded in the meantime.
+const FIND_FILES_PAGE_SIZE: usize = 64;
+
 struct VirtualFilesystem {
     rt: tokio::runtime::Handle,
     repo: Arc<Repository>,
     handles: Arc<AsyncMutex<Handles>>,
     entry_id_generator: Arc<EntryIdGenerator>,
+    // Mixed into the entry ids to keep them distinct when multiple repositories are mounted
+    // together. Zero otherwise, so the ids match (the lower 64 bits of) those exposed by the
+    // library.
+    entry_id_salt: u64,
 }
 
 impl VirtualFilesystem {
     fn new(
         rt: tokio::runtime::Handle,
         entry_id_generator: Arc<EntryIdGenerator>,
+        entry_id_salt: u64,
         repo: Arc<Repository>,
     ) -> Self {
         Self {
@@ -50,20 +63,29 @@ impl VirtualFilesystem {
             repo,
             handles: Arc::new(AsyncMutex::new(Default::default())),
             entry_id_generator,
+            entry_id_salt,
         }
     }
 
+    // Rejects the mutating operations up front with a clear error if the repository is not
+    // writable (opened in read or blind mode).
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.is_writable() {
+            Ok(())
+        } else {
+            Err(STATUS_MEDIA_WRITE_PROTECTED.into())
+        }
+    }
+
+    fn is_writable(&self) -> bool {
+        self.repo.access_mode() == AccessMode::Write
+    }
+
     async fn get_or_set_shared(
         &self,
         path: Utf8PathBuf,
         delete_on_close: bool,
-    ) -> (Arc<AsyncRwLock<Shared>>, u64) {
-        // It is not clear whether `if path1 == path2 => id1 == id2` from the documentation, but
-        // the memfs example in dokan-rust seems to always generate a new `id` in `create_file` so
-        // the above probably doesn't have to hold.
-
-        let id = self.entry_id_generator.generate_id();
-
+    ) -> Arc<AsyncRwLock<Shared>> {
         match self.handles.lock().await.entry(path.clone()) {
             hash_map::Entry::Occupied(entry) => {
                 let shared = entry.get().clone();
@@ -72,7 +94,7 @@ impl VirtualFilesystem {
                 lock.delete_on_close |= delete_on_close;
                 lock.cached_dir = None;
                 drop(lock);
-                (shared, id)
+                shared
             }
             hash_map::Entry::Vacant(entry) => {
                 let shared = Arc::new(AsyncRwLock::new(Shared {
@@ -82,7 +104,7 @@ impl VirtualFilesystem {
                     cached_dir: None,
                 }));
                 entry.insert(shared.clone());
-                (shared, id)
+                shared
             }
         }
     }
@@ -96,16 +118,17 @@ impl VirtualFilesystem {
         create_disposition: CreateDisposition,
         access_mask: AccessMask,
         shared: Arc<AsyncRwLock<Shared>>,
-    ) -> Result<(Entry, bool), Error> {
+    ) -> Result<(Entry, bool, Option<u128>), Error> {
         use ouisync_lib::Error as E;
 
         let (parent, child) = match path::decompose(path) {
             Some((parent, child)) => (parent, child),
             None => {
-                // It's the root.
+                // It's the root (whose blob id, and so also the entry id, is all zeros).
                 return Ok((
                     Entry::new_dir(self.repo.clone(), path.clone(), shared).await?,
                     false,
+                    Some(0),
                 ));
             }
         };
@@ -119,32 +142,45 @@ impl VirtualFilesystem {
                     return Err(E::EntryNotFound.into());
                 }
 
-                let entry = if access_mask.has_delete() {
+                // The entries created lazily don't exist yet so they have no id.
+                let (entry, id) = if access_mask.has_delete() {
                     if create_directory {
-                        Entry::new_dir(self.repo.clone(), path.clone(), shared).await?
+                        (
+                            Entry::new_dir(self.repo.clone(), path.clone(), shared).await?,
+                            None,
+                        )
                     } else {
-                        Entry::new_file(
-                            OpenState::Lazy {
-                                path: path.clone(),
-                                create_disposition,
-                            },
-                            shared,
+                        (
+                            Entry::new_file(
+                                OpenState::Lazy {
+                                    path: path.clone(),
+                                    create_disposition,
+                                },
+                                shared,
+                            ),
+                            None,
                         )
                     }
                 } else if create_directory {
-                    self.repo.create_directory(&path).await?;
-                    Entry::new_dir(self.repo.clone(), path.clone(), shared).await?
+                    let dir = self.repo.create_directory(&path).await?;
+                    (
+                        Entry::new_dir(self.repo.clone(), path.clone(), shared).await?,
+                        Some(dir.id()),
+                    )
                 } else {
                     let mut file = self.repo.create_file(path).await?;
                     file.flush().await?;
-                    Entry::new_file(OpenState::Open(file), shared)
+                    let id = file.id();
+                    (Entry::new_file(OpenState::Open(file), shared), Some(id))
                 };
 
-                return Ok((entry, true /* is new */));
+                return Ok((entry, true /* is new */, id));
             }
             Err(other) => return Err(other.into()),
         };
 
+        let id = existing_entry.id();
+
         let entry = if access_mask.has_delete() {
             match existing_entry {
                 JointEntryRef::File(_) => Entry::new_file(
@@ -173,7 +209,7 @@ impl VirtualFilesystem {
             }
         };
 
-        Ok((entry, false /* not new */))
+        Ok((entry, false /* not new */, Some(id)))
     }
 
     #[instrument(
@@ -193,7 +229,7 @@ impl VirtualFilesystem {
     ) -> Result<(Entry, bool, u64), Error> {
         tracing::trace!("enter");
 
-        let (shared, id) = self.get_or_set_shared(path.clone(), delete_on_close).await;
+        let shared = self.get_or_set_shared(path.clone(), delete_on_close).await;
 
         let result = self
             .create_entry_impl(
@@ -220,7 +256,16 @@ impl VirtualFilesystem {
             }
         }
 
-        result.map(|(entry, is_new)| (entry, is_new, id))
+        // Use the stable entry id when known so the apps relying on the file ids (`file_index`)
+        // see the same id for the same file across the handles, sessions and renames.
+        result.map(|(entry, is_new, id)| {
+            let id = match id {
+                Some(id) => self.entry_id_generator.map_id(id, self.entry_id_salt),
+                None => self.entry_id_generator.generate_id(),
+            };
+
+            (entry, is_new, id)
+        })
     }
 
     async fn close_shared(
@@ -264,50 +309,67 @@ impl VirtualFilesystem {
         dir_entry: &DirEntry,
         pattern: Option<&U16CStr>,
     ) -> Result<(), Error> {
-        let dir = dir_entry.cached_or_load_dir().await?;
+        // Enumerate in pages resumed from a cursor so that a reload of the directory in between
+        // (e.g., because of a concurrent modification) doesn't require starting over.
+        let mut cursor: Option<EntryCursor> = None;
 
-        for entry in dir.entries() {
-            let name = entry.unique_name();
+        loop {
+            let dir = dir_entry.cached_or_load_dir().await?;
+            let mut count = 0;
 
-            if name == "." || name == ".." {
-                continue;
-            }
+            for entry in dir
+                .entries_after(cursor.as_ref())
+                .take(FIND_FILES_PAGE_SIZE)
+            {
+                count += 1;
+                cursor = Some(entry.cursor());
 
-            // TODO: Unwrap
-            let file_name = U16CString::from_str(entry.unique_name().as_ref()).unwrap();
+                let name = entry.unique_name();
 
-            let (attributes, file_size) = match &entry {
-                JointEntryRef::File(file) => {
-                    let file_size = match file.open().await {
-                        Ok(file) => file.len(),
-                        Err(_) => 0,
-                    };
-                    (winnt::FILE_ATTRIBUTE_NORMAL, file_size)
-                }
-                JointEntryRef::Directory(_) => {
-                    // TODO: Count block sizes
-                    (winnt::FILE_ATTRIBUTE_DIRECTORY, 0)
+                if name == "." || name == ".." {
+                    continue;
                 }
-            };
 
-            if let Some(pattern) = pattern {
-                let ignore_case = true;
-                if !dokan::is_name_in_expression(pattern, &file_name, ignore_case) {
-                    continue;
+                // TODO: Unwrap
+                let file_name = U16CString::from_str(entry.unique_name().as_ref()).unwrap();
+
+                let (attributes, file_size) = match &entry {
+                    JointEntryRef::File(file) => {
+                        let file_size = match file.open().await {
+                            Ok(file) => file.len(),
+                            Err(_) => 0,
+                        };
+                        (winnt::FILE_ATTRIBUTE_NORMAL, file_size)
+                    }
+                    JointEntryRef::Directory(_) => {
+                        // TODO: Count block sizes
+                        (winnt::FILE_ATTRIBUTE_DIRECTORY, 0)
+                    }
+                };
+
+                if let Some(pattern) = pattern {
+                    let ignore_case = true;
+                    if !dokan::is_name_in_expression(pattern, &file_name, ignore_case) {
+                        continue;
+                    }
                 }
+
+                fill_find_data(&FindData {
+                    attributes,
+                    creation_time: entry.created(),
+                    last_access_time: entry.modified(),
+                    last_write_time: entry.modified(),
+                    file_size,
+                    file_name,
+                })
+                .or_else(ignore_name_too_long)?;
             }
 
-            fill_find_data(&FindData {
-                attributes,
-                // TODO
-                creation_time: UNIX_EPOCH,
-                last_access_time: UNIX_EPOCH,
-                last_write_time: UNIX_EPOCH,
-                file_size,
-                file_name,
-            })
-            .or_else(ignore_name_too_long)?;
+            if count < FIND_FILES_PAGE_SIZE {
+                break;
+            }
         }
+
         Ok(())
     }
 
@@ -325,7 +387,7 @@ impl VirtualFilesystem {
         create_disposition: u32,
         create_options: u32,
     ) -> Result<CreateFileInfo<EntryHandle>, Error> {
-        let create_disposition = create_disposition.try_into()?;
+        let create_disposition: CreateDisposition = create_disposition.try_into()?;
         let delete_on_close = create_options & FILE_DELETE_ON_CLOSE > 0;
         let create_dir = create_options & FILE_DIRECTORY_FILE > 0;
 
@@ -338,6 +400,12 @@ impl VirtualFilesystem {
             file_attributes
         );
 
+        // NOTE: `OpenIf` is commonly used to open existing files for reading so we don't reject it
+        // here. If the file doesn't exist, creating it fails later anyway.
+        if create_disposition.is_write() || delete_on_close || access_mask.has_write() {
+            self.check_writable()?;
+        }
+
         let path = to_path(file_name)?;
 
         let (entry, is_new, id) = self
@@ -350,6 +418,17 @@ impl VirtualFilesystem {
             )
             .await?;
 
+        // The app does its own caching (or reads the file only once) so bypass our block cache.
+        // Only files which are already open are affected, the lazily opened ones are being
+        // deleted or replaced anyway.
+        if create_options & FILE_NO_INTERMEDIATE_BUFFERING > 0 {
+            if let Entry::File(entry) = &entry {
+                if let Some(file) = entry.file.lock().await.as_mut_file() {
+                    file.set_cache_bypass(true);
+                }
+            }
+        }
+
         let is_dir = entry.as_directory().is_ok();
 
         Ok(CreateFileInfo {
@@ -392,6 +471,23 @@ impl VirtualFilesystem {
     ) {
         tracing::trace!("enter");
 
+        // Flush before locking `self.handles` so that flushing a big file doesn't stall opening of
+        // unrelated entries (e.g., listing directories). No more operations are issued on this
+        // handle so nothing can make the file dirty again until it's closed below.
+        if let Entry::File(entry) = &context.entry {
+            match &mut *entry.file.lock().await {
+                OpenState::Open(file) => {
+                    if let Err(error) = file.flush().await {
+                        tracing::error!("Failed to flush on file close: {error:?}");
+                    }
+                }
+                OpenState::Lazy { .. } => (),
+                OpenState::Closed => {
+                    tracing::error!("File already closed");
+                }
+            }
+        }
+
         // We need to lock `self.handles` here to prevent anything from opening the file while this
         // function runs. It is because if the file is marked for removal here and if some other
         // function opens the file, then the function `self.repo.remove_entry` will fail with
@@ -399,28 +495,10 @@ impl VirtualFilesystem {
         // Also see this issue: https://github.com/equalitie/ouisync-app/issues/414
         let mut handles = self.handles.lock().await;
 
-        match &context.entry {
-            Entry::File(entry) => {
-                let mut file_lock = entry.file.lock().await;
-
-                match &mut *file_lock {
-                    OpenState::Open(file) => {
-                        if let Err(error) = file.flush().await {
-                            tracing::error!("Failed to flush on file close: {error:?}");
-                        }
-                    }
-                    OpenState::Lazy { .. } => (),
-                    OpenState::Closed => {
-                        tracing::error!("File already closed");
-                    }
-                };
-
-                // Close the file handle.
-                *file_lock = OpenState::Closed;
-                drop(file_lock);
-            }
-            Entry::Directory(_) => (),
-        };
+        if let Entry::File(entry) = &context.entry {
+            // Close the file handle.
+            *entry.file.lock().await = OpenState::Closed;
+        }
 
         if let Some(to_delete) = self
             .close_shared(context.entry.shared(), &mut handles)
@@ -514,6 +592,8 @@ impl VirtualFilesystem {
         info: &OperationInfo<'c, 'h, Super>,
         context: &'c EntryHandle,
     ) -> Result<u32, Error> {
+        self.check_writable()?;
+
         let file_entry = context.entry.as_file()?;
 
         let mut lock = file_entry.file.lock().await;
@@ -591,33 +671,48 @@ impl VirtualFilesystem {
     ) -> Result<FileInfo, Error> {
         tracing::trace!("enter");
 
-        let (attributes, file_size) = match &context.entry {
+        let (attributes, file_size, shared) = match &context.entry {
             Entry::File(entry) => {
                 let mut lock = entry.file.lock().await;
                 let file = lock.opened_file(&self.repo).await?;
                 let len = file.len();
 
-                (winnt::FILE_ATTRIBUTE_NORMAL, len)
+                (winnt::FILE_ATTRIBUTE_NORMAL, len, &entry.shared)
             }
-            Entry::Directory(_) => (
+            Entry::Directory(entry) => (
                 winnt::FILE_ATTRIBUTE_DIRECTORY,
                 // TODO: Should we count the blocks?
                 0,
+                &entry.shared,
             ),
         };
 
+        let path = shared.read().await.path.clone();
+        let (creation_time, last_write_time) = self.entry_times(&path).await?;
+
         Ok(FileInfo {
             attributes,
-            // TODO
-            creation_time: UNIX_EPOCH,
-            last_access_time: UNIX_EPOCH,
-            last_write_time: UNIX_EPOCH,
+            creation_time,
+            last_access_time: last_write_time,
+            last_write_time,
             file_size,
             number_of_links: 1,
             file_index: context.id,
         })
     }
 
+    // Creation and modification time of the entry at `path`. `UNIX_EPOCH` for the root.
+    async fn entry_times(&self, path: &Utf8Path) -> Result<(SystemTime, SystemTime), Error> {
+        let Some((parent, name)) = path::decompose(path) else {
+            return Ok((UNIX_EPOCH, UNIX_EPOCH));
+        };
+
+        let parent = self.repo.open_directory(parent).await?;
+        let entry = parent.lookup_unique(name)?;
+
+        Ok((entry.created(), entry.modified()))
+    }
+
     fn get_file_information<'c, 'h: 'c, Super: FileSystemHandler<'c, 'h>>(
         &self,
         file_name: &U16CStr,
@@ -697,6 +792,7 @@ impl VirtualFilesystem {
         _context: &'c EntryHandle,
     ) -> Result<(), Error> {
         tracing::trace!("enter");
+        self.check_writable()?;
         Err(STATUS_NOT_IMPLEMENTED.into())
     }
 
@@ -723,6 +819,7 @@ impl VirtualFilesystem {
         _context: &'c EntryHandle,
     ) -> Result<(), Error> {
         tracing::warn!("enter - not implemented yet");
+        self.check_writable()?;
         Ok(())
     }
 
@@ -755,6 +852,7 @@ impl VirtualFilesystem {
         context: &'c EntryHandle,
     ) -> Result<(), Error> {
         tracing::trace!("enter");
+        self.check_writable()?;
         let file_entry = context.entry.as_file()?;
         file_entry.shared.write().await.delete_on_close = info.delete_on_close();
         Ok(())
@@ -779,6 +877,7 @@ impl VirtualFilesystem {
         context: &'c EntryHandle,
     ) -> Result<(), Error> {
         tracing::trace!("enter");
+        self.check_writable()?;
         let dir_entry = context.entry.as_directory()?;
         let path = to_path(file_name)?;
         let mut shared = dir_entry.shared.write().await;
@@ -816,6 +915,7 @@ impl VirtualFilesystem {
         handle: &'c EntryHandle,
     ) -> Result<(), Error> {
         tracing::trace!("enter");
+        self.check_writable()?;
 
         let src_path = to_path(file_name)?;
         let dst_path = to_path(new_file_name)?;
@@ -914,6 +1014,8 @@ impl VirtualFilesystem {
         context: &'c EntryHandle,
     ) -> Result<(), Error> {
         tracing::trace!("enter");
+        self.check_writable()?;
+
         let desired_len: u64 = alloc_size
             .try_into()
             .map_err(|_| STATUS_INVALID_PARAMETER)?;
@@ -1000,13 +1102,20 @@ impl VirtualFilesystem {
         _info: &OperationInfo<'c, 'h, Super>,
     ) -> Result<VolumeInfo, Error> {
         tracing::trace!("enter");
+
+        let mut fs_flags = winnt::FILE_CASE_PRESERVED_NAMES
+            | winnt::FILE_CASE_SENSITIVE_SEARCH
+            | winnt::FILE_UNICODE_ON_DISK;
+
+        if !self.is_writable() {
+            fs_flags |= winnt::FILE_READ_ONLY_VOLUME;
+        }
+
         Ok(VolumeInfo {
             name: U16CString::from_str("ouisync").unwrap(),
             serial_number: 0,
             max_component_length: MAX_COMPONENT_LENGTH,
-            fs_flags: winnt::FILE_CASE_PRESERVED_NAMES
-                | winnt::FILE_CASE_SENSITIVE_SEARCH
-                | winnt::FILE_UNICODE_ON_DISK,
+            fs_flags,
             // Custom names don't play well with UAC.
             fs_name: U16CString::from_str("NTFS").unwrap(),
         })
@@ -1058,20 +1167,77 @@ impl VirtualFilesystem {
     }
 }
 
+/// Assigns the 64 bit ids (`file_index`) to the entries. The entries with a stable id (see
+/// `ouisync_lib::EntryRef::id`) get the stable id truncated to 64 bits, unless it collides with the
+/// id of a different entry. The colliding entries, the entries which have no stable id yet and the
+/// virtual ones get a generated id.
 pub(crate) struct EntryIdGenerator {
     next_id: AtomicU64,
+    assigned: BlockingMutex<AssignedIds>,
 }
 
 impl EntryIdGenerator {
     fn new() -> Self {
+        // Zero is the id of the repository root.
         Self {
-            next_id: AtomicU64::new(0),
+            next_id: AtomicU64::new(1),
+            assigned: BlockingMutex::new(AssignedIds::default()),
         }
     }
 
     fn generate_id(&self) -> u64 {
         self.next_id.fetch_add(1, Ordering::Relaxed)
     }
+
+    // Maps the stable id of an entry from the repository with the given salt (see
+    // `VirtualFilesystem::entry_id_salt`) to a 64 bit id.
+    fn map_id(&self, id: u128, salt: u64) -> u64 {
+        let full_id = (salt, id);
+        let mut assigned = self.assigned.lock().unwrap();
+
+        if let Some(id) = assigned.collisions.get(&full_id) {
+            return *id;
+        }
+
+        let truncated_id = id as u64 ^ salt;
+
+        match assigned.truncated.entry(truncated_id) {
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(full_id);
+                return truncated_id;
+            }
+            hash_map::Entry::Occupied(entry) if *entry.get() == full_id => return truncated_id,
+            hash_map::Entry::Occupied(_) => (),
+        }
+
+        // The truncated id collides with that of a different entry. Generate a new one (which
+        // doesn't collide either) and remember it so the entry keeps it for the rest of the
+        // session.
+        let id = loop {
+            let id = self.generate_id();
+
+            if let hash_map::Entry::Vacant(entry) = assigned.truncated.entry(id) {
+                entry.insert(full_id);
+                break id;
+            }
+        };
+
+        tracing::debug!(?full_id, id, "entry id collision");
+
+        assigned.collisions.insert(full_id, id);
+
+        id
+    }
+}
+
+// The ids assigned by `EntryIdGenerator::map_id` so far. Grows with the number of distinct entries
+// opened during the session.
+#[derive(Default)]
+struct AssignedIds {
+    // Assigned 64 bit id -> (salt, stable id) of the entry it's been assigned to.
+    truncated: HashMap<u64, (u64, u128)>,
+    // (salt, stable id) -> generated id, for the entries whose truncated id collided.
+    collisions: HashMap<(u64, u128), u64>,
 }
 
 pub(crate) fn ignore_name_too_long(err: FillDataError) -> OperationResult<()> {
@@ -1100,6 +1266,7 @@ impl fmt::Debug for Error {
                 STATUS_LOCK_NOT_GRANTED => write!(f, "STATUS_LOCK_NOT_GRANTED"),
                 STATUS_INVALID_DEVICE_REQUEST => write!(f, "STATUS_INVALID_DEVICE_REQUEST"),
                 STATUS_FILE_CLOSED => write!(f, "STATUS_FILE_CLOSED"),
+                STATUS_MEDIA_WRITE_PROTECTED => write!(f, "STATUS_MEDIA_WRITE_PROTECTED"),
                 other => write!(f, "{:#x}", other),
             },
             Self::OuiSync(error) => {
@@ -1148,6 +1315,9 @@ impl From<Error> for i32 {
                     E::Writer(_) => STATUS_IO_DEVICE_ERROR,
                     E::StorageVersionMismatch => STATUS_IO_DEVICE_ERROR,
                     E::Locked => STATUS_LOCK_NOT_GRANTED,
+                    E::TooManyEntries | E::StorageFull => STATUS_DISK_FULL,
+                    E::PathTooDeep | E::NameTooLong => STATUS_NAME_TOO_LONG,
+                    E::Outdated => STATUS_RETRY,
                 }
             }
         }
@@ -1349,6 +1519,17 @@ impl CreateDisposition {
             Self::OverwriteIf => true,
         }
     }
+
+    fn is_write(&self) -> bool {
+        match self {
+            Self::Supersede => true,
+            Self::Create => true,
+            Self::Open => false,
+            Self::OpenIf => false,
+            Self::Overwrite => true,
+            Self::OverwriteIf => true,
+        }
+    }
 }
 
 impl TryFrom<u32> for CreateDisposition {
@@ -1379,6 +1560,15 @@ impl AccessMask {
     fn has_append(&self) -> bool {
         self.mask & winnt::FILE_APPEND_DATA > 0
     }
+    fn has_write(&self) -> bool {
+        self.mask
+            & (winnt::FILE_WRITE_DATA
+                | winnt::FILE_APPEND_DATA
+                | winnt::FILE_WRITE_ATTRIBUTES
+                | winnt::FILE_WRITE_EA
+                | winnt::DELETE)
+            > 0
+    }
 }
 
 impl From<winnt::ACCESS_MASK> for AccessMask {
@@ -175,9 +175,11 @@ impl MultiRepoMount for MultiRepoVFS {
 
         match name_to_repo.entry(name.clone()) {
             hash_map::Entry::Vacant(name_to_repo_entry) => {
+                let entry_id_salt = entry_id_salt(&repo);
                 let repo = Arc::new(VirtualFilesystem::new(
                     self.runtime_handle.clone(),
                     self.entry_id_generator.clone(),
+                    entry_id_salt,
                     repo,
                 ));
                 name_to_repo_entry.insert(repo);
@@ -227,6 +229,14 @@ impl Drop for MultiRepoVFS {
     }
 }
 
+// Derived from the repository id so the entry ids stay stable across the mounts.
+fn entry_id_salt(repo: &Repository) -> u64 {
+    let hash = repo.secrets().id().salted_hash(b"entry-id");
+    let mut salt = [0; 8];
+    salt.copy_from_slice(&hash.as_ref()[..8]);
+    u64::from_le_bytes(salt)
+}
+
 #[allow(dead_code)]
 #[derive(Eq, PartialEq)]
 enum DebugType {
@@ -1,10 +1,10 @@
 use super::{EntryHandle, EntryIdGenerator, VirtualFilesystem};
 use dokan::{
     init, shutdown, unmount, CreateFileInfo, DiskSpaceInfo, FileInfo, FileSystemHandler,
-    FileSystemMounter, FileTimeOperation, FillDataResult, FindData, MountOptions, OperationInfo,
-    OperationResult, VolumeInfo, IO_SECURITY_CONTEXT,
+    FileSystemMounter, FileTimeOperation, FillDataResult, FindData, MountFlags, MountOptions,
+    OperationInfo, OperationResult, VolumeInfo, IO_SECURITY_CONTEXT,
 };
-use ouisync_lib::Repository;
+use ouisync_lib::{AccessMode, Repository};
 use std::io;
 use std::{
     path::Path,
@@ -281,9 +281,15 @@ pub fn mount_with_span(
     mount_point: impl AsRef<Path>,
     span: Option<tracing::Span>,
 ) -> Result<MountGuard, io::Error> {
+    let mut flags = super::default_mount_flags();
+
+    if repository.access_mode() != AccessMode::Write {
+        flags |= MountFlags::WRITE_PROTECT;
+    }
+
     let options = MountOptions {
         single_thread: false,
-        flags: super::default_mount_flags(),
+        flags,
         ..Default::default()
     };
 
@@ -308,6 +314,7 @@ pub fn mount_with_span(
             vfs: VirtualFilesystem::new(
                 runtime_handle,
                 Arc::new(EntryIdGenerator::new()),
+                0,
                 repository,
             ),
             span,
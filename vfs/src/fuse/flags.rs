@@ -42,6 +42,13 @@ bitflags! {
     }
 }
 
+impl OpenFlags {
+    /// Whether the file is being opened for modification.
+    pub fn is_write(&self) -> bool {
+        self.intersects(Self::WRONLY | Self::RDWR | Self::APPEND | Self::TRUNC)
+    }
+}
+
 impl From<i32> for OpenFlags {
     fn from(raw: i32) -> Self {
         Self::from_bits_retain(raw)
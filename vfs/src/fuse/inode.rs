@@ -1,35 +1,40 @@
 use camino::Utf8PathBuf;
 use fuser::FUSE_ROOT_ID;
 use ouisync_lib::{crypto::sign::PublicKey, Error, Result};
-use slab::Slab;
 use std::{
     collections::{hash_map::Entry, HashMap},
-    convert::TryInto,
-    fmt,
+    fmt, mem,
 };
 
 /// Inode handle
 pub type Inode = u64;
 
+// The inodes are derived from the stable entry ids (see `ouisync_lib::EntryRef::id`) so they stay
+// the same across sessions and renames. The ids are 128 bits long so they are truncated to the
+// inode size. The (very unlikely) collisions of the truncated ids are detected and resolved by
+// assigning the colliding entry the next vacant inode.
 pub struct InodeMap {
-    forward: Slab<InodeData>,
+    forward: HashMap<Inode, InodeData>,
     reverse: HashMap<Key, Inode>,
 }
 
 impl InodeMap {
     pub fn new() -> Self {
         // Create inode for the root directory
-        let mut forward = Slab::with_capacity(1);
-
-        let index = forward.insert(InodeData {
-            representation: Representation::Directory,
-            parent: 0,
-            base_name: String::new(),
-            unique_name: String::new(),
-            lookups: 1,
-        });
-        let inode = index_to_inode(index);
-        assert_eq!(inode, FUSE_ROOT_ID);
+        let mut forward = HashMap::new();
+
+        forward.insert(
+            FUSE_ROOT_ID,
+            InodeData {
+                representation: Representation::Directory,
+                // The root's blob id, and so also its entry id, is all zeros.
+                id: Some(0),
+                parent: 0,
+                base_name: String::new(),
+                unique_name: String::new(),
+                lookups: 1,
+            },
+        );
 
         tracing::trace!("Create inode {} for /", FUSE_ROOT_ID);
 
@@ -39,7 +44,8 @@ impl InodeMap {
         }
     }
 
-    // Lookup the inode for an entry.
+    // Lookup the inode for an entry. `id` is the stable id of the entry or `None` for the
+    // synthetic entries which have none.
     //
     // # Panics
     //
@@ -49,6 +55,7 @@ impl InodeMap {
         parent: Inode,
         base_name: &str,
         unique_name: &str,
+        id: Option<u128>,
         representation: Representation,
     ) -> Inode {
         // TODO: consider using `Arc` to avoid the double clone of `name`.
@@ -58,67 +65,139 @@ impl InodeMap {
             unique_name: unique_name.to_owned(),
         };
 
-        match self.reverse.entry(key) {
+        if let Some(inode) = self.reverse.get(&key) {
+            let data = self.forward.get_mut(inode).expect("inode not found");
+
+            if id.is_none() || data.id == id {
+                data.lookups = data.lookups.checked_add(1).expect("too many inode lookups");
+                data.representation = representation;
+
+                return *inode;
+            }
+
+            // Otherwise the entry has been replaced with a different one (e.g., removed and
+            // recreated by another replica). The new entry gets its own inode and the old one is
+            // left to be forgotten by the kernel.
+        }
+
+        let inode = self.find_inode(id);
+
+        match self.forward.entry(inode) {
+            Entry::Occupied(entry) => {
+                // The entry has been looked up before under a different name, which means it's
+                // been moved since (e.g., by another replica). Move the inode with it.
+                let data = entry.into_mut();
+                let old_key = Key {
+                    parent: mem::replace(&mut data.parent, parent),
+                    unique_name: mem::replace(&mut data.unique_name, unique_name.to_owned()),
+                };
+
+                if self.reverse.get(&old_key) == Some(&inode) {
+                    self.reverse.remove(&old_key);
+                }
+
+                data.base_name = base_name.to_owned();
+                data.lookups = data.lookups.checked_add(1).expect("too many inode lookups");
+                data.representation = representation;
+
+                tracing::trace!(
+                    "Move inode {} to {}",
+                    inode,
+                    PathDisplay(&self.forward, inode, None)
+                );
+            }
             Entry::Vacant(entry) => {
-                let index = self.forward.insert(InodeData {
+                entry.insert(InodeData {
                     representation,
+                    id,
                     parent,
                     base_name: base_name.to_owned(),
                     unique_name: unique_name.to_owned(),
                     lookups: 1,
                 });
-                let inode = index_to_inode(index);
-
-                entry.insert(inode);
 
                 tracing::trace!(
                     "Create inode {} for {}",
                     inode,
                     PathDisplay(&self.forward, inode, None)
                 );
-
-                inode
             }
-            Entry::Occupied(entry) => {
-                let inode = *entry.get();
-                let index = inode_to_index(inode);
+        }
 
-                let data = &mut self.forward[index];
-                data.lookups = data.lookups.checked_add(1).expect("too many inode lookups");
-                data.representation = representation;
+        self.reverse.insert(key, inode);
 
-                inode
-            }
+        inode
+    }
+
+    // Returns the inode the entry has or would get if it was looked up now, without creating it.
+    // Used to report the inodes of directory entries which the kernel doesn't look up.
+    pub fn peek(&self, parent: Inode, unique_name: &str, id: u128) -> Inode {
+        let key = Key {
+            parent,
+            unique_name: unique_name.to_owned(),
+        };
+
+        match self.reverse.get(&key) {
+            Some(inode) if self.forward[inode].id == Some(id) => *inode,
+            _ => self.find_inode(Some(id)),
         }
     }
 
     // Forget the given number of lookups of the given inode. If the number of lookups drops to
     // zero, the inode is removed.
     pub fn forget(&mut self, inode: Inode, lookups: u64) {
-        let index = inode_to_index(inode);
-        let data = &mut self.forward[index];
+        let Entry::Occupied(mut entry) = self.forward.entry(inode) else {
+            panic!("inode not found");
+        };
 
-        if data.lookups <= lookups {
-            let data = self.forward.remove(index);
+        if entry.get().lookups <= lookups {
+            let data = entry.remove();
             let key = Key {
                 parent: data.parent,
                 unique_name: data.unique_name,
             };
 
-            let fwd_removed = self.reverse.remove(&key);
+            // The name might already belong to a different inode if the entry has been replaced.
+            if self.reverse.get(&key) == Some(&inode) {
+                self.reverse.remove(&key);
+            }
 
             tracing::trace!(
                 "Remove inode {} for {}",
                 inode,
                 PathDisplay(&self.forward, key.parent, Some(&key.unique_name))
             );
-
-            debug_assert!(fwd_removed.is_some());
         } else {
-            data.lookups -= lookups;
+            entry.get_mut().lookups -= lookups;
         }
     }
 
+    // Updates the inode of a moved entry (if it has one) to point to its new location. The inode
+    // of the replaced destination entry (if any) is left to be forgotten by the kernel.
+    pub fn rename(&mut self, src_parent: Inode, src_name: &str, dst_parent: Inode, dst_name: &str) {
+        let src_key = Key {
+            parent: src_parent,
+            unique_name: src_name.to_owned(),
+        };
+
+        let Some(inode) = self.reverse.remove(&src_key) else {
+            return;
+        };
+
+        let data = self.forward.get_mut(&inode).expect("inode not found");
+        data.parent = dst_parent;
+        data.base_name = dst_name.to_owned();
+        data.unique_name = dst_name.to_owned();
+
+        self.reverse.insert(
+            Key {
+                parent: dst_parent,
+                unique_name: dst_name.to_owned(),
+            },
+            inode,
+        );
+    }
+
     // Retrieve the data for the given inode.
     //
     // # Panics
@@ -126,7 +205,7 @@ impl InodeMap {
     // Panics if the inode doesn't exist.
     pub fn get(&self, inode: Inode) -> InodeView {
         self.forward
-            .get(inode_to_index(inode))
+            .get(&inode)
             .map(|data| InodeView { inodes: self, data })
             .expect("inode not found")
     }
@@ -154,6 +233,31 @@ impl InodeMap {
         self.calculate_path(self.get(inode_data.parent).data)
             .join(&inode_data.base_name)
     }
+
+    // Finds the inode for the entry with the given stable id: the one it already has if it's been
+    // looked up before, otherwise the id truncated to the inode size or, if that's taken by
+    // another entry, the next vacant inode.
+    fn find_inode(&self, id: Option<u128>) -> Inode {
+        let Some(id) = id else {
+            return self.vacant_inode(FUSE_ROOT_ID + 1);
+        };
+
+        let inode = id as Inode;
+
+        match self.forward.get(&inode) {
+            Some(data) if data.id == Some(id) => inode,
+            _ => self.vacant_inode(inode),
+        }
+    }
+
+    fn vacant_inode(&self, mut inode: Inode) -> Inode {
+        // Zero is not a valid inode and `FUSE_ROOT_ID` is reserved for the root.
+        while inode <= FUSE_ROOT_ID || self.forward.contains_key(&inode) {
+            inode = inode.wrapping_add(1);
+        }
+
+        inode
+    }
 }
 
 pub enum Representation {
@@ -164,6 +268,8 @@ pub enum Representation {
     // corresponding to the path is requested, it'll be determined dynamically.
     Directory,
     File(PublicKey),
+    // The synthetic status file (see `status::STATUS_FILE_NAME`).
+    Status,
 }
 
 impl Representation {
@@ -171,12 +277,14 @@ impl Representation {
         match self {
             Self::Directory => Err(Error::EntryIsDirectory),
             Self::File(branch_id) => Ok(branch_id),
+            Self::Status => Err(Error::PermissionDenied),
         }
     }
 }
 
 struct InodeData {
     representation: Representation,
+    id: Option<u128>,
     parent: Inode,
     base_name: String,
     unique_name: String,
@@ -208,31 +316,15 @@ struct Key {
     unique_name: String,
 }
 
-fn index_to_inode(index: usize) -> Inode {
-    // Map inode 1 to index 0 because 1 is the first valid inode (corresponding to the root
-    // directory)
-    index
-        .wrapping_add(1)
-        .try_into()
-        .unwrap_or_else(|_| unreachable!())
-}
-
-fn inode_to_index(inode: Inode) -> usize {
-    inode
-        .wrapping_sub(1)
-        .try_into()
-        .expect("inode out of bounds")
-}
-
 // Helper to display the full path of an inode. See `InodeMap::path_display` for more info.
-struct PathDisplay<'a>(&'a Slab<InodeData>, Inode, Option<&'a str>);
+struct PathDisplay<'a>(&'a HashMap<Inode, InodeData>, Inode, Option<&'a str>);
 
 impl fmt::Display for PathDisplay<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt_inode_path(f, self.0, self.1)?;
 
         if let Some(last) = self.2 {
-            if self.1 > FUSE_ROOT_ID {
+            if self.1 != FUSE_ROOT_ID {
                 write!(f, "/")?;
             }
 
@@ -243,10 +335,14 @@ impl fmt::Display for PathDisplay<'_> {
     }
 }
 
-fn fmt_inode_path(f: &mut fmt::Formatter, map: &Slab<InodeData>, inode: Inode) -> fmt::Result {
-    let data = &map[inode_to_index(inode)];
+fn fmt_inode_path(
+    f: &mut fmt::Formatter,
+    map: &HashMap<Inode, InodeData>,
+    inode: Inode,
+) -> fmt::Result {
+    let data = &map[&inode];
 
-    if data.parent > FUSE_ROOT_ID {
+    if data.parent != 0 && data.parent != FUSE_ROOT_ID {
         fmt_inode_path(f, map, data.parent)?;
     }
 
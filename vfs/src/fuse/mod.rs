@@ -2,6 +2,7 @@ mod entry_map;
 mod flags;
 mod inode;
 mod multi_repo_vfs;
+mod status;
 mod utils;
 
 pub use multi_repo_vfs::MultiRepoVFS;
@@ -10,25 +11,28 @@ use self::{
     entry_map::{EntryMap, FileHandle},
     flags::{OpenFlags, RenameFlags},
     inode::{Inode, InodeMap, InodeView, Representation},
+    status::{STATUS_FILE_NAME, STATUS_XATTR_NAME},
     utils::{FormatOptionScope, MaybeOwnedMut},
 };
+use crate::virtual_fs;
 use fuser::{
-    BackgroundSession, FileAttr, FileType, KernelConfig, MountOption, ReplyAttr, ReplyCreate,
-    ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
+    consts::FOPEN_DIRECT_IO, BackgroundSession, FileAttr, FileType, KernelConfig, MountOption,
+    ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen,
+    ReplyWrite, ReplyXattr, Request, TimeOrNow, FUSE_ROOT_ID,
 };
 use ouisync_lib::{
-    DebugPrinter, EntryType, Error, File, JointDirectory, JointEntry, JointEntryRef, Repository,
-    Result,
+    path, AccessMode, DebugPrinter, EntryType, Error, File, JointDirectory, JointEntry,
+    JointEntryRef, Repository, Result,
 };
 use std::{
     convert::TryInto,
     ffi::OsStr,
-    io::{self, SeekFrom},
+    io,
     os::raw::c_int,
     panic::{self, AssertUnwindSafe},
     path::Path,
     sync::Arc,
-    time::SystemTime,
+    time::{SystemTime, UNIX_EPOCH},
 };
 use tokio::time::Duration;
 use tracing::{instrument, Span};
@@ -42,19 +46,59 @@ const FS_NAME: &str = "ouisync";
 
 /// Mount `repository` under the given directory. Spawns the filesystem handler in a background
 /// thread and immediatelly returns. The returned `MountGuard` unmouts the repository on drop.
+///
+/// If the repository is not writable (opened in read or blind mode), it's mounted read-only.
 pub fn mount(
     runtime_handle: tokio::runtime::Handle,
     repository: Arc<Repository>,
     mount_point: impl AsRef<Path>,
 ) -> Result<MountGuard, io::Error> {
+    mount_with_options(
+        runtime_handle,
+        repository,
+        mount_point,
+        MountOptions::default(),
+    )
+}
+
+/// Like [`mount`] but with the given options.
+pub fn mount_with_options(
+    runtime_handle: tokio::runtime::Handle,
+    repository: Arc<Repository>,
+    mount_point: impl AsRef<Path>,
+    options: MountOptions,
+) -> Result<MountGuard, io::Error> {
+    let mut mount_options = vec![MountOption::FSName(FS_NAME.into())];
+
+    if repository.access_mode() != AccessMode::Write {
+        mount_options.push(MountOption::RO);
+    }
+
     let session = fuser::spawn_mount2(
-        VirtualFilesystem::new(runtime_handle, repository),
+        VirtualFilesystem::new(runtime_handle, repository, options),
         mount_point,
-        &[MountOption::FSName(FS_NAME.into())],
+        &mount_options,
     )?;
     Ok(MountGuard(Some(session)))
 }
 
+/// Per-mount options controlling how the ownership and permissions of the entries are presented.
+/// Ouisync doesn't store these, so by default the entries are owned by root and are read-only,
+/// which breaks tools (e.g., backup tools) that try to restore them.
+#[derive(Clone, Debug, Default)]
+pub struct MountOptions {
+    /// Owner to present all the entries with.
+    pub uid: Option<u32>,
+    /// Group to present all the entries with.
+    pub gid: Option<u32>,
+    /// Permission bits to present the files with.
+    pub file_mode: Option<u16>,
+    /// Permission bits to present the directories with.
+    pub dir_mode: Option<u16>,
+    /// Make `chmod` and `chown` succeed without doing anything instead of failing with `ENOTSUP`.
+    pub ignore_permission_changes: bool,
+}
+
 /// Unmounts the virtual filesystem when dropped.
 pub struct MountGuard(Option<BackgroundSession>);
 
@@ -95,6 +139,10 @@ const TTL: Duration = Duration::from_secs(0);
 // https://libfuse.github.io/doxygen/fuse__common_8h.html#a4c81f2838716f43fe493a61c87a62816
 const FUSE_CAP_ATOMIC_O_TRUNC: u32 = 8; // 0b0001
 
+// File handle of the status file. The status file isn't stored in the entry map, this value is
+// chosen so that it never refers to any entry there.
+const STATUS_FILE_HANDLE: FileHandle = FileHandle::MAX;
+
 // Convenience macro that unwraps the result or reports its error in the given reply and
 // returns.
 macro_rules! try_request {
@@ -109,6 +157,17 @@ macro_rules! try_request {
     };
 }
 
+// Convenience macro that rejects the request with `EROFS` and returns if the repository is not
+// writable. Used to fail the mutating requests up front with a clear error.
+macro_rules! check_writable {
+    ($self:expr, $reply:expr) => {
+        if !$self.inner.is_writable() {
+            $reply.error(libc::EROFS);
+            return;
+        }
+    };
+}
+
 macro_rules! record_fmt {
     ($name:expr, $($args:tt)*) => {{
         Span::current().record($name, &format_args!($($args)*));
@@ -121,11 +180,16 @@ struct VirtualFilesystem {
 }
 
 impl VirtualFilesystem {
-    fn new(runtime_handle: tokio::runtime::Handle, repository: Arc<Repository>) -> Self {
+    fn new(
+        runtime_handle: tokio::runtime::Handle,
+        repository: Arc<Repository>,
+        options: MountOptions,
+    ) -> Self {
         Self {
             rt: runtime_handle,
             inner: Inner {
                 repository,
+                options,
                 inodes: InodeMap::new(),
                 entries: EntryMap::default(),
             },
@@ -182,6 +246,8 @@ impl fuser::Filesystem for VirtualFilesystem {
         flags: Option<u32>,
         reply: ReplyAttr,
     ) {
+        check_writable!(self, reply);
+
         let attr = try_request!(
             self.rt.block_on(self.inner.setattr(
                 inode, mode, uid, gid, size, atime, mtime, ctime, fh, crtime, chgtime, bkuptime,
@@ -238,6 +304,8 @@ impl fuser::Filesystem for VirtualFilesystem {
         umask: u32,
         reply: ReplyEntry,
     ) {
+        check_writable!(self, reply);
+
         let attr = try_request!(
             self.rt
                 .block_on(self.inner.mkdir(parent, name, mode, umask)),
@@ -247,11 +315,13 @@ impl fuser::Filesystem for VirtualFilesystem {
     }
 
     fn rmdir(&mut self, _req: &Request, parent: Inode, name: &OsStr, reply: ReplyEmpty) {
+        check_writable!(self, reply);
         try_request!(self.rt.block_on(self.inner.rmdir(parent, name)), reply);
         reply.ok();
     }
 
     fn unlink(&mut self, _req: &Request, parent: Inode, name: &OsStr, reply: ReplyEmpty) {
+        check_writable!(self, reply);
         try_request!(self.rt.block_on(self.inner.unlink(parent, name)), reply);
         reply.ok();
     }
@@ -282,6 +352,8 @@ impl fuser::Filesystem for VirtualFilesystem {
         flags: i32,
         reply: ReplyCreate,
     ) {
+        check_writable!(self, reply);
+
         let (attr, handle, flags) = try_request!(
             self.rt.block_on(
                 self.inner
@@ -293,6 +365,10 @@ impl fuser::Filesystem for VirtualFilesystem {
     }
 
     fn open(&mut self, _req: &Request, inode: Inode, flags: i32, reply: ReplyOpen) {
+        if OpenFlags::from(flags).is_write() {
+            check_writable!(self, reply);
+        }
+
         let (handle, flags) = try_request!(
             self.rt.block_on(self.inner.open(inode, flags.into())),
             reply
@@ -349,6 +425,8 @@ impl fuser::Filesystem for VirtualFilesystem {
         _lock_owner: Option<u64>,
         reply: ReplyWrite,
     ) {
+        check_writable!(self, reply);
+
         // TODO: what about `write_flags` and `lock_owner`?
 
         let size = try_request!(
@@ -386,6 +464,28 @@ impl fuser::Filesystem for VirtualFilesystem {
         reply.ok();
     }
 
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        inode: Inode,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let value = try_request!(self.rt.block_on(self.inner.getxattr(inode, name)), reply);
+        let Some(value) = value else {
+            reply.error(libc::ENODATA);
+            return;
+        };
+
+        reply_xattr(reply, &value, size);
+    }
+
+    fn listxattr(&mut self, _req: &Request<'_>, inode: Inode, size: u32, reply: ReplyXattr) {
+        let names = self.inner.listxattr(inode);
+        reply_xattr(reply, &names, size);
+    }
+
     fn rename(
         &mut self,
         _req: &Request,
@@ -396,6 +496,8 @@ impl fuser::Filesystem for VirtualFilesystem {
         flags: u32,
         reply: ReplyEmpty,
     ) {
+        check_writable!(self, reply);
+
         try_request!(
             self.rt.block_on(self.inner.rename(
                 src_parent,
@@ -412,17 +514,61 @@ impl fuser::Filesystem for VirtualFilesystem {
 
 struct Inner {
     repository: Arc<Repository>,
+    options: MountOptions,
     inodes: InodeMap,
     entries: EntryMap,
 }
 
 impl Inner {
+    fn is_writable(&self) -> bool {
+        self.repository.access_mode() == AccessMode::Write
+    }
+
+    fn make_file_attr(
+        &self,
+        inode: Inode,
+        entry_type: EntryType,
+        len: u64,
+        uid: u32,
+        gid: u32,
+    ) -> FileAttr {
+        let mut attr = make_file_attr(inode, entry_type, len, uid, gid);
+
+        if let Some(uid) = self.options.uid {
+            attr.uid = uid;
+        }
+
+        if let Some(gid) = self.options.gid {
+            attr.gid = gid;
+        }
+
+        let mode = match entry_type {
+            EntryType::File => self.options.file_mode,
+            EntryType::Directory => self.options.dir_mode,
+        };
+
+        if let Some(mode) = mode {
+            attr.perm = mode & 0o7777;
+        }
+
+        attr
+    }
+
     #[instrument(skip(self, parent, name), fields(path), err(Debug))]
     async fn lookup(&mut self, parent: Inode, name: &OsStr) -> Result<FileAttr> {
         let name = name.to_str().ok_or(Error::NonUtf8FileName)?;
 
         self.record_path(parent, Some(name));
 
+        if parent == FUSE_ROOT_ID && name == STATUS_FILE_NAME {
+            let len = status::repository_status(&self.repository).await?.len() as u64;
+            let inode = self
+                .inodes
+                .lookup(parent, name, name, None, Representation::Status);
+
+            return Ok(self.make_file_attr(inode, EntryType::File, len, 0, 0));
+        }
+
         let parent_path = self.inodes.get(parent).calculate_path();
         let parent_dir = self.repository.open_directory(parent_path).await?;
 
@@ -437,10 +583,15 @@ impl Inner {
             }
         };
 
-        let inode = self.inodes.lookup(parent, entry.name(), name, repr);
+        let inode = self
+            .inodes
+            .lookup(parent, entry.name(), name, Some(entry.id()), repr);
 
         // TODO: uid, gid
-        Ok(make_file_attr(inode, entry.entry_type(), len, 0, 0))
+        let mut attr = self.make_file_attr(inode, entry.entry_type(), len, 0, 0);
+        set_times(&mut attr, entry.created(), entry.modified());
+
+        Ok(attr)
     }
 
     #[instrument(skip(self, inode), fields(path))]
@@ -453,10 +604,19 @@ impl Inner {
     async fn getattr(&mut self, inode: Inode) -> Result<FileAttr> {
         self.record_path(inode, None);
 
+        if self.is_status(inode) {
+            let len = status::repository_status(&self.repository).await?.len() as u64;
+            return Ok(self.make_file_attr(inode, EntryType::File, len, 0, 0));
+        }
+
         let entry = self.open_entry_by_inode(self.inodes.get(inode)).await?;
+        let (created, modified) = self.entry_times(self.inodes.get(inode)).await?;
 
         // TODO: uid, gid
-        Ok(make_file_attr_for_entry(&entry, inode, 0, 0).await)
+        let mut attr = self.make_file_attr(inode, entry.entry_type(), entry.len(), 0, 0);
+        set_times(&mut attr, created, modified);
+
+        Ok(attr)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -514,9 +674,14 @@ impl Inner {
             }
         }
 
-        check_unsupported(mode)?;
-        check_unsupported(uid)?;
-        check_unsupported(gid)?;
+        // Pretend the ownership and permission changes succeeded, if configured so. Otherwise
+        // reject them as they are not supported.
+        if !self.options.ignore_permission_changes {
+            check_unsupported(mode)?;
+            check_unsupported(uid)?;
+            check_unsupported(gid)?;
+        }
+
         check_unsupported(ctime)?;
         check_unsupported(crtime)?;
         check_unsupported(chgtime)?;
@@ -527,6 +692,12 @@ impl Inner {
         // check_unsupported(atime)?;
         // check_unsupported(mtime)?;
 
+        // Nothing to change (e.g., ignored `chmod`) so no need to open the file. This also makes it
+        // work on directories.
+        if size.is_none() && handle.is_none() {
+            return self.getattr(inode).await;
+        }
+
         let mut file = if let Some(handle) = handle {
             MaybeOwnedMut::Borrowed(self.entries.get_file_mut(handle)?)
         } else {
@@ -539,13 +710,9 @@ impl Inner {
             file.flush().await?;
         }
 
-        Ok(make_file_attr(
-            inode,
-            EntryType::File,
-            file.len(),
-            uid.unwrap_or(0),
-            gid.unwrap_or(0),
-        ))
+        let len = file.len();
+
+        Ok(self.make_file_attr(inode, EntryType::File, len, 0, 0))
     }
 
     #[instrument(skip(self, inode, flags), fields(path, ?flags), err(Debug))]
@@ -612,17 +779,15 @@ impl Inner {
             // NOTE: According to the libfuse documentation
             // (https://libfuse.github.io/doxygen/structfuse__lowlevel__ops.html#af1ef8e59e0cb0b02dc0e406898aeaa51)
             // "Returning a directory entry from readdir() does not affect its lookup count".
-            // This seems to imply that `forget` won't be called for inodes returned here. So for
-            // inodes that already exist, we should probably just return them here without
-            // incrementing their lookup count. It's not clear however how to handle inodes that
-            // don't exists yet because allocating them would probably leave them dangling.
-            // That said, based on some experiments, it doesn't seem the inodes returned here are
-            // even being used (there seems to be always a separate `lookup` request for each
-            // entry). To keep things simple, we currently return an invalid inode which seems to
-            // work fine. If we start seeing panics due to invalid inodes we need to come up with
-            // a different strategy.
+            // So we don't allocate the inodes here, we only report the ones the entries have or
+            // would get once looked up. They are derived from the stable entry ids so they match
+            // what `stat` returns (which matters to tools like `ls -i` or `find -inum`).
+            let entry_inode = self
+                .inodes
+                .peek(inode, entry.unique_name().as_ref(), entry.id());
+
             if reply.add(
-                u64::MAX, // invalid inode, see above.
+                entry_inode,
                 (index + first + 1) as i64,
                 to_file_type(entry.entry_type()),
                 entry.unique_name().as_ref(),
@@ -651,13 +816,17 @@ impl Inner {
         let path = self.inodes.get(parent).calculate_path().join(name);
         let dir = self.repository.create_directory(path).await?;
 
-        let inode = self
-            .inodes
-            .lookup(parent, name, name, Representation::Directory);
+        let inode = self.inodes.lookup(
+            parent,
+            name,
+            name,
+            Some(dir.id()),
+            Representation::Directory,
+        );
         let len = dir.len();
 
         // TODO: uid, gid
-        Ok(make_file_attr(inode, EntryType::Directory, len, 0, 0))
+        Ok(self.make_file_attr(inode, EntryType::Directory, len, 0, 0))
     }
 
     #[instrument(skip(self, parent, name), fields(path), err(Debug))]
@@ -699,11 +868,17 @@ impl Inner {
         file.flush().await?;
 
         let branch_id = *file.branch().id();
+        let id = file.id();
         let entry = JointEntry::File(file);
-        let inode = self
-            .inodes
-            .lookup(parent, name, name, Representation::File(branch_id));
-        let attr = make_file_attr_for_entry(&entry, inode, req.uid(), req.gid()).await;
+        let inode = self.inodes.lookup(
+            parent,
+            name,
+            name,
+            Some(id),
+            Representation::File(branch_id),
+        );
+        let attr =
+            self.make_file_attr(inode, entry.entry_type(), entry.len(), req.uid(), req.gid());
         let handle = self.entries.insert(entry);
 
         Ok((attr, handle, 0))
@@ -713,23 +888,35 @@ impl Inner {
     async fn open(&mut self, inode: Inode, flags: OpenFlags) -> Result<(FileHandle, u32)> {
         self.record_path(inode, None);
 
+        if self.is_status(inode) {
+            if flags.is_write() {
+                return Err(Error::PermissionDenied);
+            }
+
+            // The content is generated on every read, so bypass the page cache.
+            return Ok((STATUS_FILE_HANDLE, FOPEN_DIRECT_IO));
+        }
+
         let mut file = self.open_file_by_inode(inode).await?;
 
         if flags.contains(OpenFlags::TRUNC) {
-            let local_branch = self.repository.local_branch()?;
-
-            file.fork(local_branch).await?;
-            file.truncate(0)?;
-            file.flush().await?;
+            virtual_fs::truncate(&self.repository, &mut file, 0).await?;
         }
 
+        // `O_DIRECT` means the app does its own caching (or reads the file only once) so bypass
+        // both the kernel page cache and our block cache.
+        let reply_flags = if flags.contains(OpenFlags::DIRECT) {
+            file.set_cache_bypass(true);
+            FOPEN_DIRECT_IO
+        } else {
+            0
+        };
+
         // TODO: what about other flags (parameter)?
 
         let handle = self.entries.insert(JointEntry::File(file));
 
-        // TODO: what about flags (return value)?
-
-        Ok((handle, 0))
+        Ok((handle, reply_flags))
     }
 
     #[instrument(skip(self, inode, flags), fields(path, ?flags), err(Debug))]
@@ -742,6 +929,10 @@ impl Inner {
     ) -> Result<()> {
         self.record_path(inode, None);
 
+        if handle == STATUS_FILE_HANDLE {
+            return Ok(());
+        }
+
         // TODO: what about `flags`?
         let file = self.entries.get_file_mut(handle)?;
 
@@ -767,10 +958,18 @@ impl Inner {
 
         // TODO: what about flags?
 
+        if handle == STATUS_FILE_HANDLE {
+            let offset: usize = offset.try_into().map_err(|_| Error::OffsetOutOfRange)?;
+            let content = status::repository_status(&self.repository).await?;
+            let start = offset.min(content.len());
+            let end = start.saturating_add(size as usize).min(content.len());
+
+            return Ok(content[start..end].to_vec());
+        }
+
         let file = self.entries.get_file_mut(handle)?;
 
         let offset: u64 = offset.try_into().map_err(|_| Error::OffsetOutOfRange)?;
-        file.seek(SeekFrom::Start(offset));
 
         // TODO: consider reusing these buffers
         let mut buffer = vec![0; size as usize];
@@ -779,8 +978,10 @@ impl Inner {
         //
         //     Read should return exactly the number of bytes requested except on EOF or error...
         //
-        // so we need to do `read_all` not just `read`.
-        let len = file.read_all(&mut buffer).await?;
+        // so we need to do `read_all` not just `read`. The requests can be as big as the readahead
+        // size the kernel chose and for memory mapped files they are page aligned and often hit the
+        // same block repeatedly, which `read_all_at` serves from the cache.
+        let len = file.read_all_at(offset, &mut buffer).await?;
         buffer.truncate(len);
 
         Ok(buffer)
@@ -801,19 +1002,19 @@ impl Inner {
     ) -> Result<u32> {
         self.record_path(inode, None);
 
-        let offset: u64 = offset.try_into().map_err(|_| Error::OffsetOutOfRange)?;
-        let local_branch = self.repository.local_branch()?;
+        if handle == STATUS_FILE_HANDLE {
+            return Err(Error::PermissionDenied);
+        }
 
+        let offset: u64 = offset.try_into().map_err(|_| Error::OffsetOutOfRange)?;
         let file = self.entries.get_file_mut(handle)?;
-        file.seek(SeekFrom::Start(offset));
-        file.fork(local_branch).await?;
 
         // https://libfuse.github.io/doxygen/structfuse__operations.html#a897d1ece4b8b04c92d97b97b2dbf9768
         //
         //     Write should return exactly the number of bytes requested except on error.
         //
-        // so we need to do `write_all` not just `write`.
-        file.write_all(data).await?;
+        // so we need to write all of `data`, which `write_at` does.
+        virtual_fs::write_at(&self.repository, file, offset, data).await?;
 
         Ok(data.len().try_into().unwrap_or(u32::MAX))
     }
@@ -822,6 +1023,10 @@ impl Inner {
     async fn flush(&mut self, inode: Inode, handle: FileHandle) -> Result<()> {
         self.record_path(inode, None);
 
+        if handle == STATUS_FILE_HANDLE {
+            return Ok(());
+        }
+
         self.entries.get_file_mut(handle)?.flush().await
     }
 
@@ -829,10 +1034,38 @@ impl Inner {
     async fn fsync(&mut self, inode: Inode, handle: FileHandle, datasync: bool) -> Result<()> {
         self.record_path(inode, None);
 
+        if handle == STATUS_FILE_HANDLE {
+            return Ok(());
+        }
+
         // TODO: what about `datasync`?
         self.entries.get_file_mut(handle)?.flush().await
     }
 
+    #[instrument(skip(self, inode, name), fields(path, ?name), err(Debug))]
+    async fn getxattr(&mut self, inode: Inode, name: &OsStr) -> Result<Option<Vec<u8>>> {
+        self.record_path(inode, None);
+
+        if name.to_str() != Some(STATUS_XATTR_NAME) {
+            return Ok(None);
+        }
+
+        match self.inodes.get(inode).representation() {
+            Representation::File(_) => {
+                let file = self.open_file_by_inode(inode).await?;
+                Ok(Some(status::file_status(&file).await?))
+            }
+            Representation::Directory | Representation::Status => Ok(None),
+        }
+    }
+
+    fn listxattr(&self, inode: Inode) -> Vec<u8> {
+        match self.inodes.get(inode).representation() {
+            Representation::File(_) => format!("{STATUS_XATTR_NAME}\0").into_bytes(),
+            Representation::Directory | Representation::Status => Vec::new(),
+        }
+    }
+
     #[instrument(skip(self, parent, name), fields(path), err(Debug))]
     async fn unlink(&mut self, parent: Inode, name: &OsStr) -> Result<()> {
         let name = name.to_str().ok_or(Error::NonUtf8FileName)?;
@@ -886,7 +1119,13 @@ impl Inner {
 
         self.repository
             .move_entry(src_dir, src_name, dst_dir, dst_name)
-            .await
+            .await?;
+
+        // The entry keeps its id, so it keeps its inode too.
+        self.inodes
+            .rename(src_parent, src_name, dst_parent, dst_name);
+
+        Ok(())
     }
 
     async fn open_file_by_inode(&self, inode: Inode) -> Result<File> {
@@ -913,9 +1152,45 @@ impl Inner {
                 let file = self.repository.open_file_version(path, branch_id).await?;
                 Ok(JointEntry::File(file))
             }
+            Representation::Status => Err(Error::PermissionDenied),
         }
     }
 
+    // Creation and modification time of the entry. `UNIX_EPOCH` for the root and the status file.
+    async fn entry_times(&self, inode: InodeView<'_>) -> Result<(SystemTime, SystemTime)> {
+        let path = inode.calculate_path();
+        let Some((parent, name)) = path::decompose(&path) else {
+            return Ok((UNIX_EPOCH, UNIX_EPOCH));
+        };
+
+        match inode.representation() {
+            Representation::File(branch_id) => {
+                let parent = self.repository.open_directory(parent).await?;
+                let entry = parent.lookup_version(name, branch_id)?;
+                Ok((entry.created(), entry.modified()))
+            }
+            Representation::Directory => {
+                let parent = self.repository.open_directory(parent).await?;
+                let entry = parent
+                    .lookup(name)
+                    .find_map(|entry| match entry {
+                        JointEntryRef::Directory(entry) => Some(entry),
+                        JointEntryRef::File(_) => None,
+                    })
+                    .ok_or(Error::EntryNotFound)?;
+                Ok((entry.created(), entry.modified()))
+            }
+            Representation::Status => Ok((UNIX_EPOCH, UNIX_EPOCH)),
+        }
+    }
+
+    fn is_status(&self, inode: Inode) -> bool {
+        matches!(
+            self.inodes.get(inode).representation(),
+            Representation::Status
+        )
+    }
+
     // For debugging, use when needed
     #[allow(dead_code)]
     async fn debug_print(&self, print: DebugPrinter) {
@@ -929,13 +1204,11 @@ impl Inner {
     }
 }
 
-async fn make_file_attr_for_entry(
-    entry: &JointEntry,
-    inode: Inode,
-    uid: u32,
-    gid: u32,
-) -> FileAttr {
-    make_file_attr(inode, entry.entry_type(), entry.len(), uid, gid)
+fn set_times(attr: &mut FileAttr, created: SystemTime, modified: SystemTime) {
+    attr.crtime = created;
+    attr.mtime = modified;
+    attr.ctime = modified;
+    attr.atime = modified;
 }
 
 fn make_file_attr(inode: Inode, entry_type: EntryType, len: u64, uid: u32, gid: u32) -> FileAttr {
@@ -980,6 +1253,21 @@ fn to_error_code(error: &Error) -> libc::c_int {
         Error::DirectoryNotEmpty => libc::ENOTEMPTY,
         Error::OperationNotSupported => libc::ENOTSUP,
         Error::Locked => libc::EBUSY,
+        Error::TooManyEntries | Error::StorageFull => libc::ENOSPC,
+        Error::PathTooDeep | Error::NameTooLong => libc::ENAMETOOLONG,
+        Error::Outdated => libc::EAGAIN,
+    }
+}
+
+// Replies with the value of an extended attribute, or only with its size if `size` is zero (as
+// required by the xattr protocol).
+fn reply_xattr(reply: ReplyXattr, value: &[u8], size: u32) {
+    if size == 0 {
+        reply.size(value.len().try_into().unwrap_or(u32::MAX));
+    } else if value.len() <= size as usize {
+        reply.data(value);
+    } else {
+        reply.error(libc::ERANGE);
     }
 }
 
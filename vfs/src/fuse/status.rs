@@ -0,0 +1,55 @@
+//! Sync status exposed inside the mount so that shell extensions and scripts can show it without
+//! talking to the FFI.
+
+use ouisync_lib::{File, Progress, Repository, Result};
+use serde::Serialize;
+
+/// Name of the synthetic file in the root directory of the mount which contains the status of the
+/// repository as JSON. It's generated on the fly and not listed in the directory.
+pub(super) const STATUS_FILE_NAME: &str = ".ouisync-status.json";
+
+/// Name of the extended attribute which contains the status of a file as JSON.
+pub(super) const STATUS_XATTR_NAME: &str = "user.ouisync.status";
+
+#[derive(Serialize)]
+struct RepositoryStatus {
+    access_mode: String,
+    sync_progress: Progress,
+    synced: bool,
+}
+
+#[derive(Serialize)]
+struct FileStatus {
+    len: u64,
+    /// Number of bytes of the file that are present locally.
+    synced_len: u64,
+    synced: bool,
+}
+
+pub(super) async fn repository_status(repository: &Repository) -> Result<Vec<u8>> {
+    let sync_progress = repository.sync_progress().await?;
+
+    Ok(encode(&RepositoryStatus {
+        access_mode: repository.access_mode().to_string(),
+        sync_progress,
+        synced: sync_progress.value == sync_progress.total,
+    }))
+}
+
+pub(super) async fn file_status(file: &File) -> Result<Vec<u8>> {
+    let len = file.len();
+    let synced_len = file.progress().await?;
+
+    Ok(encode(&FileStatus {
+        len,
+        synced_len,
+        synced: synced_len >= len,
+    }))
+}
+
+fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    // Serializing these types into JSON can't fail.
+    let mut content = serde_json::to_vec_pretty(value).unwrap_or_else(|_| unreachable!());
+    content.push(b'\n');
+    content
+}
@@ -2,7 +2,7 @@
 mod fuse;
 
 #[cfg(target_os = "linux")]
-pub use fuse::{mount, MountGuard, MultiRepoVFS};
+pub use fuse::{mount, mount_with_options, MountGuard, MountOptions, MultiRepoVFS};
 
 #[cfg(target_os = "windows")]
 mod dokan;
@@ -19,6 +19,10 @@ mod dummy;
 #[cfg(not(any(target_os = "linux", target_os = "windows")))]
 pub use dummy::{mount, MountGuard, MultiRepoVFS};
 
+mod virtual_fs;
+
+pub use virtual_fs::{DirEntry, FileHandle, Metadata, OpenOptions, VirtualFs};
+
 #[cfg(test)]
 mod tests;
 
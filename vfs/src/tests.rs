@@ -1,5 +1,5 @@
 use super::*;
-use ouisync_lib::{Access, Repository, RepositoryParams, WriteSecrets};
+use ouisync_lib::{Access, AccessMode, Repository, RepositoryParams, WriteSecrets};
 use proptest::prelude::*;
 use rand::{self, distributions::Standard, rngs::StdRng, Rng, SeedableRng};
 use std::{
@@ -227,6 +227,136 @@ async fn move_file() {
     assert!(entries.contains_key(dst_name));
 }
 
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn stable_inodes() {
+    use std::os::unix::fs::MetadataExt;
+
+    let (base_dir, _guard, span) = setup("").await;
+    let _span_guard = span.enter();
+
+    let mount_dir = base_dir.path().join("mnt");
+    let src_path = mount_dir.join("src.txt");
+    let dst_path = mount_dir.join("dir").join("dst.txt");
+
+    fs::write(&src_path, b"blah").await.unwrap();
+    let ino = fs::metadata(&src_path).await.unwrap().ino();
+
+    // Listing the directory reports the same inode.
+    let mut entries = fs::read_dir(&mount_dir).await.unwrap();
+    let entry = entries.next_entry().await.unwrap().unwrap();
+    assert_eq!(entry.ino(), ino);
+
+    // The inode is preserved when the file is moved.
+    fs::create_dir(mount_dir.join("dir")).await.unwrap();
+    fs::rename(&src_path, &dst_path).await.unwrap();
+    assert_eq!(fs::metadata(&dst_path).await.unwrap().ino(), ino);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_only_repository() {
+    let (base_dir, _guard, span) = setup_with_access_mode("", AccessMode::Read).await;
+    let _span_guard = span.enter();
+
+    let mount_dir = base_dir.path().join("mnt");
+
+    let error = fs::write(mount_dir.join("file.txt"), b"blah")
+        .await
+        .unwrap_err();
+    #[cfg(not(target_os = "windows"))]
+    assert_eq!(error.raw_os_error(), Some(libc::EROFS));
+    #[cfg(target_os = "windows")]
+    assert!(error.raw_os_error().is_some());
+
+    fs::create_dir(mount_dir.join("dir")).await.unwrap_err();
+
+    assert!(read_dir(&mount_dir).await.is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn status_file() {
+    let (base_dir, _guard, span) = setup("").await;
+    let _span_guard = span.enter();
+
+    let mount_dir = base_dir.path().join("mnt");
+
+    let content = fs::read(mount_dir.join(".ouisync-status.json"))
+        .await
+        .unwrap();
+    let status: serde_json::Value = serde_json::from_slice(&content).unwrap();
+    assert_eq!(status["access_mode"], "write");
+    assert_eq!(status["synced"], true);
+
+    fs::write(mount_dir.join(".ouisync-status.json"), b"blah")
+        .await
+        .unwrap_err();
+
+    // The status file is hidden.
+    assert!(read_dir(&mount_dir).await.is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn masquerade_permissions() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    init_log();
+
+    let base_dir = TempDir::new().unwrap();
+    let repo = Repository::create(
+        &RepositoryParams::new(base_dir.path().join("repo.db")),
+        Access::WriteUnlocked {
+            secrets: WriteSecrets::random(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let mount_dir = base_dir.path().join("mnt");
+    fs::create_dir(&mount_dir).await.unwrap();
+
+    let _guard = super::mount_with_options(
+        tokio::runtime::Handle::current(),
+        Arc::new(repo),
+        &mount_dir,
+        MountOptions {
+            uid: Some(1234),
+            gid: Some(5678),
+            file_mode: Some(0o640),
+            dir_mode: Some(0o750),
+            ignore_permission_changes: true,
+        },
+    )
+    .unwrap();
+
+    let file_path = mount_dir.join("file.txt");
+    fs::write(&file_path, b"blah").await.unwrap();
+    fs::create_dir(mount_dir.join("dir")).await.unwrap();
+
+    let metadata = fs::metadata(&file_path).await.unwrap();
+    assert_eq!(metadata.uid(), 1234);
+    assert_eq!(metadata.gid(), 5678);
+    assert_eq!(metadata.mode() & 0o7777, 0o640);
+
+    let metadata = fs::metadata(mount_dir.join("dir")).await.unwrap();
+    assert_eq!(metadata.mode() & 0o7777, 0o750);
+
+    // Permission changes are accepted but ignored.
+    fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o600))
+        .await
+        .unwrap();
+    fs::set_permissions(
+        mount_dir.join("dir"),
+        std::fs::Permissions::from_mode(0o700),
+    )
+    .await
+    .unwrap();
+
+    let metadata = fs::metadata(&file_path).await.unwrap();
+    assert_eq!(metadata.mode() & 0o7777, 0o640);
+}
+
 // proptest doesn't work with the `#[tokio::test]` macro yet
 // (see https://github.com/AltSysrq/proptest/issues/179). As a workaround, create the runtime
 // manually.
@@ -245,6 +375,13 @@ fn run<F: Future>(future: F) -> F::Output {
 // output.
 // https://github.com/dokan-dev/dokan-rust/issues/9
 async fn setup(params: &str) -> (TempDir, MountGuard, tracing::Span) {
+    setup_with_access_mode(params, AccessMode::Write).await
+}
+
+async fn setup_with_access_mode(
+    params: &str,
+    access_mode: AccessMode,
+) -> (TempDir, MountGuard, tracing::Span) {
     use std::thread;
     use tracing::Instrument;
 
@@ -267,6 +404,7 @@ async fn setup(params: &str) -> (TempDir, MountGuard, tracing::Span) {
     .instrument(span.clone())
     .await
     .unwrap();
+    repo.set_access_mode(access_mode, None).await.unwrap();
     let repo = Arc::new(repo);
 
     let mount_dir = base_dir.path().join("mnt");
@@ -0,0 +1,377 @@
+//! Path based access to the repository content with file handle semantics, independent of any OS
+//! mount. Lets the embedders (e.g., a media server plugin) serve the content directly instead of
+//! going through a mounted filesystem.
+
+use camino::Utf8Path;
+use ouisync_lib::{path, AccessMode, EntryType, Error, File, JointEntryRef, Repository, Result};
+use slab::Slab;
+use std::{io::SeekFrom, sync::Arc, time::SystemTime};
+
+pub type FileHandle = u64;
+
+/// How to open a file in [`VirtualFs::open`].
+#[derive(Clone, Copy, Default, Debug)]
+pub struct OpenOptions {
+    /// Open the file for writing. Requires write access to the repository.
+    pub write: bool,
+    /// Create the file if it doesn't exist. Implies `write`.
+    pub create: bool,
+    /// Truncate the file to zero length. Implies `write`.
+    pub truncate: bool,
+}
+
+/// Type, length and times of an entry, see [`VirtualFs::metadata`].
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    pub entry_type: EntryType,
+    pub len: u64,
+    pub created: SystemTime,
+    pub modified: SystemTime,
+}
+
+/// Entry of a directory listing, see [`VirtualFs::read_dir`].
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    /// Name of the entry, disambiguated if there are concurrent versions of it. Can be used to
+    /// address the entry in the other operations.
+    pub name: String,
+    pub entry_type: EntryType,
+    pub created: SystemTime,
+    pub modified: SystemTime,
+}
+
+/// Virtual filesystem over a single repository. The paths are relative to the repository root and
+/// the concurrent versions of a file are addressed by their disambiguated names (as returned from
+/// [`Self::read_dir`]). Behaves like the mounted filesystem: writes fork the file into the local
+/// branch and become visible to the others once the file is flushed or released.
+///
+/// All operations take `&mut self` so concurrent users need to wrap it in a mutex.
+pub struct VirtualFs {
+    repository: Arc<Repository>,
+    files: Slab<File>,
+}
+
+impl VirtualFs {
+    pub fn new(repository: Arc<Repository>) -> Self {
+        Self {
+            repository,
+            files: Slab::new(),
+        }
+    }
+
+    pub fn repository(&self) -> &Arc<Repository> {
+        &self.repository
+    }
+
+    pub async fn metadata(&self, path: impl AsRef<Utf8Path>) -> Result<Metadata> {
+        let path = path.as_ref();
+
+        let Some((parent, name)) = path::decompose(path) else {
+            // Root
+            let dir = self.repository.open_directory(path).await?;
+
+            return Ok(Metadata {
+                entry_type: EntryType::Directory,
+                len: dir.len(),
+                created: SystemTime::UNIX_EPOCH,
+                modified: SystemTime::UNIX_EPOCH,
+            });
+        };
+
+        let parent = self.repository.open_directory(parent).await?;
+        let entry = parent.lookup_unique(name)?;
+        let len = match &entry {
+            JointEntryRef::File(entry) => entry.open().await?.len(),
+            JointEntryRef::Directory(entry) => entry.open().await?.len(),
+        };
+
+        Ok(Metadata {
+            entry_type: entry.entry_type(),
+            len,
+            created: entry.created(),
+            modified: entry.modified(),
+        })
+    }
+
+    pub async fn read_dir(&self, path: impl AsRef<Utf8Path>) -> Result<Vec<DirEntry>> {
+        let dir = self.repository.open_directory(path).await?;
+
+        Ok(dir
+            .entries()
+            .map(|entry| DirEntry {
+                name: entry.unique_name().into_owned(),
+                entry_type: entry.entry_type(),
+                created: entry.created(),
+                modified: entry.modified(),
+            })
+            .collect())
+    }
+
+    /// Opens the file at the given path and returns a handle to it, to be passed to the other
+    /// operations and eventually to [`Self::release`].
+    pub async fn open(
+        &mut self,
+        path: impl AsRef<Utf8Path>,
+        options: OpenOptions,
+    ) -> Result<FileHandle> {
+        let path = path.as_ref();
+
+        if options.write || options.create || options.truncate {
+            self.check_writable()?;
+        }
+
+        let mut file = match self.repository.open_file(path).await {
+            Ok(file) => file,
+            Err(Error::EntryNotFound) if options.create => {
+                let mut file = self.repository.create_file(path).await?;
+                file.flush().await?;
+                file
+            }
+            Err(error) => return Err(error),
+        };
+
+        if options.truncate {
+            truncate(&self.repository, &mut file, 0).await?;
+        }
+
+        Ok(self.files.insert(file) as FileHandle)
+    }
+
+    /// Reads from the file at the given offset until `buffer` is full or the end of the file is
+    /// reached. Returns the number of bytes read.
+    pub async fn read(
+        &mut self,
+        handle: FileHandle,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize> {
+        self.file_mut(handle)?.read_all_at(offset, buffer).await
+    }
+
+    /// Writes the whole `data` into the file at the given offset.
+    pub async fn write(&mut self, handle: FileHandle, offset: u64, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
+
+        let repository = self.repository.clone();
+        write_at(&repository, self.file_mut(handle)?, offset, data).await
+    }
+
+    pub async fn set_len(&mut self, handle: FileHandle, len: u64) -> Result<()> {
+        self.check_writable()?;
+
+        let repository = self.repository.clone();
+        truncate(&repository, self.file_mut(handle)?, len).await
+    }
+
+    pub fn len(&self, handle: FileHandle) -> Result<u64> {
+        Ok(self.file(handle)?.len())
+    }
+
+    pub async fn flush(&mut self, handle: FileHandle) -> Result<()> {
+        self.file_mut(handle)?.flush().await
+    }
+
+    /// Flushes and closes the file. The handle is invalid afterwards.
+    pub async fn release(&mut self, handle: FileHandle) -> Result<()> {
+        self.file_mut(handle)?.flush().await?;
+        self.files.remove(handle_to_index(handle)?);
+
+        Ok(())
+    }
+
+    pub async fn create_dir(&self, path: impl AsRef<Utf8Path>) -> Result<()> {
+        self.repository.create_directory(path).await?;
+        Ok(())
+    }
+
+    /// Removes the file or the empty directory at the given path.
+    pub async fn remove(&self, path: impl AsRef<Utf8Path>) -> Result<()> {
+        self.repository.remove_entry(path).await
+    }
+
+    pub async fn rename(&self, src: impl AsRef<Utf8Path>, dst: impl AsRef<Utf8Path>) -> Result<()> {
+        let (src_parent, src_name) =
+            path::decompose(src.as_ref()).ok_or(Error::OperationNotSupported)?;
+        let (dst_parent, dst_name) =
+            path::decompose(dst.as_ref()).ok_or(Error::OperationNotSupported)?;
+
+        self.repository
+            .move_entry(src_parent, src_name, dst_parent, dst_name)
+            .await
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.repository.access_mode() == AccessMode::Write {
+            Ok(())
+        } else {
+            Err(Error::PermissionDenied)
+        }
+    }
+
+    fn file(&self, handle: FileHandle) -> Result<&File> {
+        self.files
+            .get(handle_to_index(handle)?)
+            .ok_or(Error::EntryNotFound)
+    }
+
+    fn file_mut(&mut self, handle: FileHandle) -> Result<&mut File> {
+        self.files
+            .get_mut(handle_to_index(handle)?)
+            .ok_or(Error::EntryNotFound)
+    }
+}
+
+// The file operations shared with the OS mounts.
+
+/// Forks the file into the local branch and writes the whole `data` at the given offset.
+pub(crate) async fn write_at(
+    repository: &Repository,
+    file: &mut File,
+    offset: u64,
+    data: &[u8],
+) -> Result<()> {
+    let local_branch = repository.local_branch()?;
+
+    file.seek(SeekFrom::Start(offset));
+    file.fork(local_branch).await?;
+    file.write_all(data).await
+}
+
+/// Forks the file into the local branch and truncates it to `len`.
+pub(crate) async fn truncate(repository: &Repository, file: &mut File, len: u64) -> Result<()> {
+    let local_branch = repository.local_branch()?;
+
+    file.fork(local_branch).await?;
+    file.truncate(len)?;
+    file.flush().await
+}
+
+fn handle_to_index(handle: FileHandle) -> Result<usize> {
+    handle.try_into().map_err(|_| Error::EntryNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ouisync_lib::{Access, RepositoryParams, WriteSecrets};
+    use tempfile::TempDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_and_read() {
+        let (_base_dir, mut vfs) = setup().await;
+
+        vfs.create_dir("dir").await.unwrap();
+
+        let handle = vfs
+            .open(
+                "dir/test.txt",
+                OpenOptions {
+                    create: true,
+                    ..OpenOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        vfs.write(handle, 0, b"hello world").await.unwrap();
+        vfs.write(handle, 6, b"there").await.unwrap();
+        vfs.release(handle).await.unwrap();
+
+        let entries = vfs.read_dir("dir").await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "test.txt");
+        assert_eq!(entries[0].entry_type, EntryType::File);
+
+        let metadata = vfs.metadata("dir/test.txt").await.unwrap();
+        assert_eq!(metadata.entry_type, EntryType::File);
+        assert_eq!(metadata.len, 11);
+
+        let handle = vfs
+            .open("dir/test.txt", OpenOptions::default())
+            .await
+            .unwrap();
+        let mut buffer = [0; 16];
+        let len = vfs.read(handle, 6, &mut buffer).await.unwrap();
+        assert_eq!(&buffer[..len], b"there");
+        vfs.release(handle).await.unwrap();
+
+        // The handle is invalid after release.
+        assert!(matches!(vfs.len(handle), Err(Error::EntryNotFound)));
+
+        let handle = vfs
+            .open(
+                "dir/test.txt",
+                OpenOptions {
+                    truncate: true,
+                    ..OpenOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(vfs.len(handle).unwrap(), 0);
+        vfs.release(handle).await.unwrap();
+
+        vfs.rename("dir/test.txt", "test.txt").await.unwrap();
+        assert!(vfs.read_dir("dir").await.unwrap().is_empty());
+
+        vfs.remove("test.txt").await.unwrap();
+        assert!(matches!(
+            vfs.open("test.txt", OpenOptions::default()).await,
+            Err(Error::EntryNotFound)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_only() {
+        let (_base_dir, mut vfs) = setup().await;
+
+        let handle = vfs
+            .open(
+                "test.txt",
+                OpenOptions {
+                    create: true,
+                    ..OpenOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        vfs.release(handle).await.unwrap();
+
+        vfs.repository()
+            .set_access_mode(AccessMode::Read, None)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            vfs.open(
+                "test.txt",
+                OpenOptions {
+                    write: true,
+                    ..OpenOptions::default()
+                }
+            )
+            .await,
+            Err(Error::PermissionDenied)
+        ));
+
+        let handle = vfs.open("test.txt", OpenOptions::default()).await.unwrap();
+        assert!(matches!(
+            vfs.write(handle, 0, b"data").await,
+            Err(Error::PermissionDenied)
+        ));
+    }
+
+    async fn setup() -> (TempDir, VirtualFs) {
+        let base_dir = TempDir::new().unwrap();
+        let params = RepositoryParams::new(base_dir.path().join("repo.db"));
+        let repo = Repository::create(
+            &params,
+            Access::WriteUnlocked {
+                secrets: WriteSecrets::random(),
+            },
+        )
+        .await
+        .unwrap();
+
+        (base_dir, VirtualFs::new(Arc::new(repo)))
+    }
+}